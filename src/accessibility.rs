@@ -0,0 +1,170 @@
+//! Accessibility output channel for users who rely on screen readers or
+//! braille displays.
+//!
+//! Everything Jarvis speaks can be mirrored as text to a file, to standard
+//! output, or to the desktop's assistive technology stack. The mirror is
+//! configured through environment variables:
+//!
+//!  * `A11Y_OUTPUT` (optional): where to write the text. Either a file path
+//!    (appended to, so it can be followed with `tail -f` or a braille
+//!    display driver), `stdout` or `stderr`. When unset the file channel is
+//!    disabled.
+//!  * `A11Y_ATSPI` (optional): when set to `1`/`true`, each utterance is also
+//!    posted as a desktop notification via `notify-send`. AT-SPI screen
+//!    readers such as Orca announce notifications, so the text reaches a
+//!    refreshable braille display without any extra setup.
+//!  * `A11Y_WRAP` (optional): maximum line width in characters. Braille
+//!    displays commonly have 40 or 80 cells. Defaults to no wrapping.
+//!  * `A11Y_UPPERCASE` (optional): render the text in upper case, which
+//!    some low-vision users find easier to read in large fonts.
+//!  * `A11Y_TIMESTAMPS` (optional): prefix each entry with the local time.
+//!  * `A11Y_MUTE_AUDIO` (optional): mirror only; do not produce audio.
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::config::{env_flag, env_parse};
+
+/// Destination for mirrored text.
+enum Sink {
+    File(PathBuf),
+    Stdout,
+    Stderr,
+}
+
+/// Mirrors spoken text to a large-text friendly output.
+pub struct AccessibilityOutput {
+    sink: Option<Sink>,
+    atspi: bool,
+    wrap: Option<usize>,
+    uppercase: bool,
+    timestamps: bool,
+    mute_audio: bool,
+}
+
+impl AccessibilityOutput {
+    /// Build the output channel from environment variables. Returns `None`
+    /// when neither a text sink nor AT-SPI forwarding is configured.
+    pub fn from_env() -> Option<Self> {
+        let sink = env::var("A11Y_OUTPUT")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| match v.trim().to_lowercase().as_str() {
+                "stdout" | "-" => Sink::Stdout,
+                "stderr" => Sink::Stderr,
+                _ => Sink::File(PathBuf::from(v.trim())),
+            });
+        let atspi = env_flag("A11Y_ATSPI");
+        if sink.is_none() && !atspi {
+            return None;
+        }
+        let wrap = match env_parse::<usize>("A11Y_WRAP", 0) {
+            0 => None,
+            width => Some(width),
+        };
+        Some(Self {
+            sink,
+            atspi,
+            wrap,
+            uppercase: env_flag("A11Y_UPPERCASE"),
+            timestamps: env_flag("A11Y_TIMESTAMPS"),
+            mute_audio: env_flag("A11Y_MUTE_AUDIO"),
+        })
+    }
+
+    /// Whether audio output should be suppressed in favour of the mirror.
+    pub fn mute_audio(&self) -> bool {
+        self.mute_audio
+    }
+
+    /// Apply the configured formatting to a piece of spoken text.
+    fn format(&self, text: &str) -> String {
+        let text = if self.uppercase {
+            text.to_uppercase()
+        } else {
+            text.to_string()
+        };
+        let mut body = match self.wrap {
+            Some(width) => wrap_text(&text, width),
+            None => text,
+        };
+        if self.timestamps {
+            let now = chrono::Local::now().format("%H:%M:%S");
+            body = format!("[{now}]\n{body}");
+        }
+        body
+    }
+
+    /// Mirror a spoken utterance. Failures are logged but never interrupt
+    /// speech, since the mirror is supplementary to the audio path.
+    pub fn mirror(&self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        let formatted = self.format(text);
+        match &self.sink {
+            Some(Sink::File(path)) => {
+                let result = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut f| writeln!(f, "{formatted}\n"));
+                if let Err(e) = result {
+                    log::warn!("Failed to write accessibility output to {:?}: {e}", path);
+                }
+            }
+            Some(Sink::Stdout) => println!("{formatted}\n"),
+            Some(Sink::Stderr) => eprintln!("{formatted}\n"),
+            None => {}
+        }
+        if self.atspi {
+            // notify-send exits as soon as the notification has been
+            // handed to the notification daemon; it is reaped on a thread
+            // of its own so that speech does not wait for it.
+            match std::process::Command::new("notify-send")
+                .arg("--app-name=Jarvis")
+                .arg("Jarvis")
+                .arg(text)
+                .spawn()
+            {
+                Ok(mut child) => {
+                    std::thread::spawn(move || child.wait());
+                }
+                Err(e) => log::warn!("Failed to forward text to AT-SPI via notify-send: {e}"),
+            }
+        }
+    }
+}
+
+/// Greedily wrap `text` into lines of at most `width` characters. Words
+/// longer than the width are split so no line ever overflows the display.
+fn wrap_text(text: &str, width: usize) -> String {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        while word.len() > width {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            let rest = word.split_off(width);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.join("\n")
+}
@@ -1,24 +1,139 @@
 //! High level language model interface for Jarvis.
 //!
-//! This module wraps the [`ollama-rs`](https://crates.io/crates/ollama-rs)
-//! client and encodes a simple prompting strategy to call external
-//! functions when requested by the model. The Python version used
-//! LangChain's tool‑calling agent; here we manually instruct the LLM to
-//! return either plain text or a JSON object identifying a tool to run.
+//! This module drives an [`LlmBackend`] (by default a local Ollama server
+//! via [`ollama-rs`](https://crates.io/crates/ollama-rs)) through its chat
+//! API. The tools are offered with their argument schemas, and the model's
+//! native tool calling returns typed tool calls, much like the LangChain
+//! tool‑calling agent of the Python version.
+//!
+//! Earlier turns of the conversation are sent along with every command;
+//! see [`crate::conversation`]. Each wake word has a conversation, memory,
+//! persona and model of its own; see [`Agent::enter_scope`].
+//!
+//! With `LLM_SSML=1` the model is also told it may use a few SSML tags
+//! (pauses, emphasis, spelled-out characters) in its answers; see
+//! [`crate::tts_engine::ssml`].
 
-use anyhow::{Context, Result};
-use ollama_rs::{generation::completion::request::GenerationRequest, Ollama};
-use serde_json::Value;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
-use crate::tools;
-use std::env;
-use std::fs;
-use std::path::PathBuf;
+use crate::budget::{self, ContextBudget, TokenUsage};
+use crate::config::{env_flag, env_flag_or, env_parse};
+use crate::conversation::{estimate_tokens, Conversation};
+use crate::documents::DocumentIndex;
+use crate::environment;
+use crate::events::{EventBus, JarvisEvent};
+use crate::examples::Examples;
+use crate::intents::IntentRouter;
+use crate::jarvis_io::JarvisIO;
+use crate::jobs::{self, JobQuestion, Jobs};
+use crate::language::{self, ReplyLanguage};
+use crate::llm::{
+    self, CallOrder, GenerationOptions, LlmBackend, Message, OllamaBackend, ToolCall, ToolSpec,
+};
+use crate::memory::{self, Memory};
+use crate::persona::Personas;
+use crate::postprocess::{PostProcessor, Step};
+use crate::project::{self, ProjectCommand, Projects};
+use crate::redact::redact;
+use crate::session::{self, HistoryQuery, SessionLog, StepRecord, TurnRecord};
+use crate::stats::Stats;
+use crate::think::{self, ThinkMode};
+use crate::tools::guard::{self, Guard};
+use crate::tools::policy::{Policy, Verdict};
+use crate::tools::progress::Narrator;
+use crate::tools::{self, dry_run, schema, untrusted, Tool, ToolRegistry, ToolRun};
+use crate::tts_engine::language::set_reply_language;
+use crate::tts_engine::sentences::SentenceSplitter;
+use crate::wake::WakeScope;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
 
 /// Minimal agent that communicates with a local LLM via Ollama.
 pub struct Agent {
-    client: Ollama,
-    model: String,
+    backend: Box<dyn LlmBackend>,
+    tools: ToolRegistry,
+    events: EventBus,
+    /// Turns of the current conversation.
+    history: Mutex<Conversation>,
+    /// Tool calls per command whose output is fed back to the model.
+    max_steps: usize,
+    /// Times the model may correct a malformed tool call per command.
+    json_retries: usize,
+    /// Longest answer spoken without summing it up; `0` for no limit.
+    max_answer_chars: usize,
+    /// How answers are cleaned up before they are spoken.
+    post: Arc<PostProcessor>,
+    /// The character the system prompt starts with.
+    personas: Mutex<Personas>,
+    /// The project being worked on, see [`crate::project`].
+    projects: Mutex<Projects>,
+    /// What the first project opened changed, to be put back when it is
+    /// left.
+    before_project: Mutex<Option<BeforeProject>>,
+    /// The language the user asked to be answered in.
+    language: Mutex<ReplyLanguage>,
+    /// Example dialogues appended to the system prompt.
+    examples: Examples,
+    /// Generation settings, before the persona's overrides.
+    options: GenerationOptions,
+    /// How long to wait for the model.
+    timeout: Duration,
+    /// How often to retry a request that failed to connect.
+    retries: u32,
+    /// Model to try when the configured one fails.
+    fallback_model: Option<String>,
+    /// Commands answered without the model.
+    intents: IntentRouter,
+    /// Facts the user asked Jarvis to remember.
+    memory: Mutex<Memory>,
+    /// What happens to the model's `<think>` blocks.
+    think_mode: ThinkMode,
+    /// Whether the date, working directory, host and OS are added to the
+    /// prompt.
+    environment: bool,
+    /// Whether the model may ask the user to clarify a command.
+    clarify: bool,
+    /// The command waiting for the answer to the model's question.
+    clarification: Mutex<Option<Clarification>>,
+    /// How native tool calls of one reply are run.
+    native_order: CallOrder,
+    /// Most tool calls run at the same time.
+    tool_concurrency: usize,
+    /// How often the output of a running tool is spoken, see
+    /// [`crate::tools::progress`].
+    narrate_every: Option<Duration>,
+    /// Whether tool calls are described instead of run, see
+    /// [`crate::tools::dry_run`].
+    dry_run: bool,
+    /// Decides which tool calls need the user's confirmation.
+    guard: Guard,
+    /// Limits the shell commands the model may run, see
+    /// [`crate::tools::policy`].
+    policy: Policy,
+    /// Tool calls waiting for "yes, confirm".
+    confirmation: Mutex<Option<PendingAction>>,
+    /// Whether tool output is fenced off and checked for instructions to
+    /// the model, see [`crate::tools::untrusted`].
+    output_guard: bool,
+    /// Chunks of the user's documents, see [`crate::documents`].
+    documents: Mutex<DocumentIndex>,
+    /// The tool calls of the last command that used tools.
+    last_actions: Mutex<Vec<ToolRun>>,
+    /// Commands running in the background.
+    jobs: Jobs,
+    /// Transcript of the current session.
+    sessions: Mutex<SessionLog>,
+    /// Usage counts of the tools and models, see [`crate::stats`].
+    stats: Mutex<Stats>,
+    /// The wake word in use and what the others keep meanwhile.
+    scopes: Mutex<Scopes>,
+    /// Aborts the command being answered; replaced for every command.
+    cancel: Mutex<CancellationToken>,
 }
 
 impl Agent {
@@ -28,224 +143,1835 @@ impl Agent {
     /// the `OLLAMA_HOST` and `OLLAMA_PORT` environment variables
     /// recognised by the underlying crate.
     pub async fn new(model: &str) -> Result<Self> {
-        let client = Ollama::default();
-        Ok(Self {
-            client,
-            model: model.to_string(),
-        })
+        Ok(Self::with_backend(
+            Box::new(OllamaBackend::new(model)),
+            tools::default_tools(),
+        ))
+    }
+
+    /// Construct an agent from an arbitrary backend and tool set.
+    pub fn with_backend(backend: Box<dyn LlmBackend>, tools: ToolRegistry) -> Self {
+        let language = ReplyLanguage::from_env();
+        set_reply_language(language.current().map(|language| language.code));
+        let agent = Self {
+            backend,
+            tools,
+            events: EventBus::new(),
+            history: Mutex::new(Conversation::from_env()),
+            max_steps: env_parse("AGENT_MAX_STEPS", 3),
+            json_retries: env_parse("TOOL_JSON_RETRIES", 2),
+            max_answer_chars: env_parse("ANSWER_MAX_CHARS", 400),
+            post: Arc::new(PostProcessor::from_env()),
+            personas: Mutex::new(Personas::from_env()),
+            projects: Mutex::new(Projects::from_env()),
+            before_project: Mutex::new(None),
+            language: Mutex::new(language),
+            examples: Examples::from_env(),
+            options: GenerationOptions::from_env(),
+            timeout: Duration::from_secs(env_parse("LLM_TIMEOUT_SECS", 15)),
+            retries: env_parse("LLM_RETRIES", 2),
+            fallback_model: std::env::var("MODEL_FALLBACK")
+                .ok()
+                .filter(|model| !model.trim().is_empty()),
+            intents: IntentRouter::load(),
+            memory: Mutex::new(Memory::from_env()),
+            think_mode: ThinkMode::from_env(),
+            environment: env_flag_or("PROMPT_CONTEXT", true),
+            clarify: env_flag_or("CLARIFY", true),
+            clarification: Mutex::new(None),
+            native_order: env_parse("TOOL_ORDER", CallOrder::default()),
+            tool_concurrency: env_parse("TOOL_CONCURRENCY", 4),
+            narrate_every: tools::progress::interval_from_env(),
+            dry_run: env_flag("DRY_RUN"),
+            guard: Guard::from_env(),
+            policy: Policy::from_env(),
+            confirmation: Mutex::new(None),
+            output_guard: env_flag_or("TOOL_OUTPUT_GUARD", true),
+            documents: Mutex::new(DocumentIndex::from_env()),
+            last_actions: Mutex::new(Vec::new()),
+            jobs: Jobs::new(),
+            sessions: Mutex::new(SessionLog::from_env()),
+            stats: Mutex::new(Stats::from_env()),
+            scopes: Mutex::new(Scopes::default()),
+            cancel: Mutex::new(CancellationToken::new()),
+        };
+        if let Ok(name) = std::env::var("PROJECT") {
+            if !name.trim().is_empty() {
+                log::info!("{}", agent.open_project(name.trim()));
+            }
+        }
+        agent
+    }
+
+    /// Abort the command being answered, if any. The request to the model
+    /// is dropped at once, which ends the generation on the server, and
+    /// the command returns a response marked
+    /// [`cancelled`](AgentResponse::cancelled). A tool that is already
+    /// running is left to finish on its thread, and its output is dropped.
+    pub fn cancel(&self) {
+        self.cancel.lock().unwrap().cancel();
+    }
+
+    /// Get the model ready before the first command: check that the
+    /// server answers and has the model, download it if `pull` is set and
+    /// it is missing, and load it with a one-token request so that the
+    /// first command does not wait for a cold start. `progress` is told
+    /// about downloads, in sentences fit to speak.
+    ///
+    /// The error says what is wrong in words fit to speak, such as "the
+    /// language model server is not reachable".
+    pub async fn warm_up(&self, pull: bool, progress: &(dyn Fn(&str) + Send + Sync)) -> Result<()> {
+        let model = self.backend.model();
+        let listed = self.backend.list_models().await;
+        match (&listed, &model) {
+            (Ok(models), Some(model)) if !models.iter().any(|m| same_model(m, model)) => {
+                if !pull {
+                    bail!("the model {model} is not installed. Run ollama pull {model}, or set MODEL_PULL=1");
+                }
+                self.pull(model, progress).await?;
+            }
+            (Ok(_), _) => {}
+            // Backends that cannot list their models are checked by the
+            // request below.
+            (Err(e), _) => log::debug!("Cannot list the models: {e:#}"),
+        }
+        let name = model.as_deref().unwrap_or("language model");
+        let started = Instant::now();
+        self.backend.set_options(&GenerationOptions {
+            max_tokens: Some(1),
+            ..self.options
+        });
+        let request = tokio::time::timeout(
+            Duration::from_secs(env_parse("WARMUP_TIMEOUT_SECS", 120)),
+            self.backend.generate("Hi"),
+        )
+        .await;
+        self.backend.set_options(&self.options);
+        match request {
+            Ok(Ok(_)) => {
+                log::info!("{name} ready in {} ms", started.elapsed().as_millis());
+                Ok(())
+            }
+            Ok(Err(e)) if listed.is_err() => {
+                Err(e).context("the language model server is not reachable")
+            }
+            Ok(Err(e)) => Err(e).with_context(|| format!("the model {name} failed to load")),
+            Err(_) => bail!("the model {name} took too long to load"),
+        }
+    }
+
+    /// Download `model`, telling `progress` at every quarter.
+    async fn pull(&self, model: &str, progress: &(dyn Fn(&str) + Send + Sync)) -> Result<()> {
+        let spoken = spoken_model_name(model);
+        log::info!("Downloading {model}");
+        progress(&format!("Downloading {spoken}. This may take a while."));
+        let announced = Mutex::new(0u8);
+        let on_status = |status: &str, percent: Option<u8>| {
+            log::debug!("Pulling {model}: {status} {percent:?}");
+            let Some(percent) = percent else {
+                return;
+            };
+            let mut announced = announced.lock().unwrap();
+            let quarter = percent / 25 * 25;
+            if quarter > *announced && quarter < 100 {
+                *announced = quarter;
+                progress(&format!("{spoken} is {quarter} percent downloaded."));
+            }
+        };
+        self.backend
+            .pull_model(model, &on_status)
+            .await
+            .with_context(|| format!("the model {spoken} could not be downloaded"))?;
+        progress(&format!("{spoken} is downloaded."));
+        Ok(())
+    }
+
+    /// The commands running, or run, in the background.
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    /// Usage counts of the tools and models so far.
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Publish tool events, and what the tools say unprompted, on the
+    /// given bus.
+    pub fn set_event_bus(&mut self, events: EventBus) {
+        for tool in self.tools.iter() {
+            tool.set_event_bus(&events);
+        }
+        self.events = events;
+    }
+
+    /// Forget the current conversation, and those of the other wake
+    /// words, so that the next command starts without history.
+    pub fn clear_history(&self) {
+        self.history.lock().unwrap().clear();
+        for scope in self.scopes.lock().unwrap().stashed.values_mut() {
+            scope.history.clear();
+        }
+    }
+
+    /// The wake word whose conversation is in use; `None` for the main
+    /// `TRIGGER_WORD`.
+    pub fn scope(&self) -> Option<String> {
+        self.scopes.lock().unwrap().active.clone()
+    }
+
+    /// Continue as `scope`, or as the main wake word for `None`. The
+    /// conversation, long-term memory, persona and model of the wake word
+    /// in use are put aside and those of `scope` taken up: where it left
+    /// off, or as configured the first time.
+    pub fn enter_scope(&self, scope: Option<&WakeScope>) {
+        let key = scope.map(|scope| scope.word.clone());
+        let mut scopes = self.scopes.lock().unwrap();
+        if scopes.active == key {
+            return;
+        }
+        let mut history = self.history.lock().unwrap();
+        let mut memory = self.memory.lock().unwrap();
+        let mut personas = self.personas.lock().unwrap();
+        let leaving = ScopeState {
+            history: std::mem::take(&mut *history),
+            memory: std::mem::take(&mut *memory),
+            persona: personas.active().to_string(),
+            model: self.backend.model(),
+        };
+        let previous = std::mem::replace(&mut scopes.active, key.clone());
+        scopes.stashed.insert(previous, leaving);
+        let entering = match (scopes.stashed.remove(&key), scope) {
+            (Some(state), _) => state,
+            (None, Some(scope)) => ScopeState {
+                history: Conversation::from_env(),
+                memory: Memory::for_wake_word(&scope.word),
+                persona: scope
+                    .persona
+                    .clone()
+                    .unwrap_or_else(|| "default".to_string()),
+                model: scope.model.clone().or_else(|| {
+                    scopes
+                        .stashed
+                        .get(&None)
+                        .and_then(|state| state.model.clone())
+                }),
+            },
+            (None, None) => unreachable!("the main wake word is stashed when left"),
+        };
+        *history = entering.history;
+        *memory = entering.memory;
+        if let Err(e) = personas.switch(&entering.persona) {
+            log::warn!("Cannot use persona for wake word: {e:#}");
+        }
+        if let Some(model) = entering.model {
+            if self.backend.model().as_deref() != Some(&model) {
+                if let Err(e) = self.backend.set_model(&model) {
+                    log::warn!("Cannot switch to model {model}: {e:#}");
+                }
+            }
+        }
+        log::info!(
+            "Now answering as '{}' with persona {}",
+            key.as_deref().unwrap_or("default"),
+            personas.active()
+        );
+        drop(scopes);
+        self.end_session();
+    }
+
+    /// End the session transcript; the next command starts a new one. A
+    /// question the model asked is dropped.
+    pub fn end_session(&self) {
+        self.sessions.lock().unwrap().end();
+        self.clarification.lock().unwrap().take();
+        self.confirmation.lock().unwrap().take();
+    }
+
+    /// Switch to the named persona, see [`crate::persona`].
+    pub fn set_persona(&self, name: &str) -> Result<()> {
+        self.personas.lock().unwrap().switch(name)
+    }
+
+    /// Work on the project `name`: its directory becomes the working
+    /// directory of the tools, its variables theirs, and its persona the
+    /// active one; see [`crate::project`]. Returns the reply to speak.
+    pub fn open_project(&self, name: &str) -> String {
+        let mut projects = self.projects.lock().unwrap();
+        let Some(found) = projects.find(name) else {
+            let names = projects.names();
+            return if names.is_empty() {
+                format!("I don't have a project called {name}.")
+            } else {
+                format!(
+                    "I don't have a project called {name}. I know {}.",
+                    names.join(", ")
+                )
+            };
+        };
+        let io = JarvisIO::new();
+        let persona = self.personas.lock().unwrap().active().to_string();
+        let working_directory = io.read_working_directory();
+        let project = match projects.open(&found) {
+            Ok(project) => project,
+            Err(e) => {
+                log::warn!("Cannot open project {found}: {e:#}");
+                return format!("I can't open project {found}: {e:#}.");
+            }
+        };
+        io.write_working_directory(&project.dir.to_string_lossy());
+        if let Some(persona) = &project.persona {
+            if let Err(e) = self.set_persona(persona) {
+                log::warn!("Cannot use the persona of project {found}: {e:#}");
+            }
+        }
+        // Switching between projects goes back to before the first one.
+        self.before_project
+            .lock()
+            .unwrap()
+            .get_or_insert(BeforeProject {
+                working_directory,
+                persona,
+            });
+        // A shell kept running would still be in the old directory.
+        for tool in self.tools.iter() {
+            tool.reset();
+        }
+        log::info!("Working on project {found} in {}", project.dir.display());
+        format!("Now working on {found}.")
+    }
+
+    /// Leave the active project, going back to the working directory and
+    /// persona from before. Returns the reply to speak.
+    pub fn leave_project(&self) -> String {
+        let Some(project) = self.projects.lock().unwrap().leave() else {
+            return "There's no project open.".to_string();
+        };
+        if let Some(before) = self.before_project.lock().unwrap().take() {
+            let io = JarvisIO::new();
+            match before.working_directory {
+                Some(dir) => io.write_working_directory(&dir),
+                None => io.clear_working_directory(),
+            }
+            if let Err(e) = self.set_persona(&before.persona) {
+                log::warn!("Cannot go back to persona {}: {e:#}", before.persona);
+            }
+        }
+        for tool in self.tools.iter() {
+            tool.reset();
+        }
+        log::info!("Left project {}", project.name);
+        format!("Okay, I've left {}.", project.name)
+    }
+
+    /// The tools offered to the model: all of them, or those the active
+    /// project lists.
+    fn offered_tools(&self) -> ToolRegistry {
+        let projects = self.projects.lock().unwrap();
+        self.tools.only(|name| projects.allows(name))
+    }
+
+    /// Build the system prompt: the active persona followed by the
+    /// instructions for answering. Backends with native tool calling get
+    /// the tools separately, as [`ToolRegistry::specs`]; for others the
+    /// registry's tool section is added here.
+    fn system_prompt(&self, persona: &str) -> String {
+        let mut prompt = persona.to_string();
+        prompt.push('\n');
+        prompt.push_str(
+            "Call one of the provided tools when a request needs it. You will be given the\n\
+tool's output; call another tool if needed, then answer the question from it in a sentence or two.\n\
+Answer briefly in plain sentences. Do not use Markdown formatting,\ncode blocks, backticks or other special markup in your answers; just write the sentence(s).",
+        );
+        if env_flag("LLM_SSML") {
+            prompt.push_str(
+                "\nYour answers are read aloud. You may use these SSML tags and no others:\n\
+<break time=\"500ms\"/> for a pause, <emphasis>word</emphasis> to stress a word, and\n\
+<say-as interpret-as=\"characters\">10.0.0.1</say-as> to spell out IP addresses, codes,\n\
+version numbers and identifiers character by character.",
+            );
+        }
+        if let Some(line) = self.language.lock().unwrap().prompt_line() {
+            prompt.push('\n');
+            prompt.push_str(&line);
+        }
+        if self.clarify {
+            prompt.push_str(
+                "\nIf a request is ambiguous and you cannot make a sensible guess, reply with only\n\
+{\"ask\": \"<a short question>\"} and nothing else; the user's answer will be added to the request.",
+            );
+        }
+        if let Some(project) = self.projects.lock().unwrap().active() {
+            prompt.push_str(&format!(
+                "\nYou are working on the project {} in {}.",
+                project.name,
+                project.dir.display()
+            ));
+        }
+        let tools = self.offered_tools();
+        if !self.backend.native_tools() && !tools.is_empty() {
+            prompt.push('\n');
+            prompt.push_str(&tools.prompt_section());
+        }
+        if self.output_guard && !tools.is_empty() {
+            prompt.push('\n');
+            prompt.push_str(untrusted::POLICY);
+        }
+        prompt.push_str(&self.examples.prompt_section());
+        prompt
+    }
+
+    /// The chat sent for `user_input`: the system prompt followed by the
+    /// date and other facts about the machine (see [`crate::environment`])
+    /// and `context` (see [`Agent::recall`]), the earlier turns of the
+    /// conversation and the command itself. The history is cut to what
+    /// `budget` leaves of the context window; see [`crate::budget`].
+    fn chat_messages(
+        &self,
+        user_input: &str,
+        persona: &str,
+        context: &str,
+        budget: ContextBudget,
+    ) -> Vec<Message> {
+        let mut system = self.system_prompt(persona);
+        if self.environment {
+            system.push_str(&environment::context_block());
+        }
+        let mut usage = TokenUsage {
+            system: estimate_tokens(&system),
+            memories: estimate_tokens(context),
+            tools: if self.backend.native_tools() {
+                budget::tool_tokens(&self.offered_tools().specs())
+            } else {
+                0
+            },
+            command: estimate_tokens(user_input),
+            ..TokenUsage::default()
+        };
+        let room = budget.history_room(&usage);
+        let history = self.history.lock().unwrap();
+        let (earlier, turns) = history.within(room);
+        if history.tokens() > room {
+            log::info!(
+                "Shortening the conversation history to fit the context window of {} tokens",
+                budget.window
+            );
+        }
+        system.push_str(context);
+        system.push_str(&earlier);
+        usage.history = estimate_tokens(&earlier)
+            + turns
+                .iter()
+                .map(|m| estimate_tokens(&m.content))
+                .sum::<usize>();
+        log::debug!("Prompt size: {usage}");
+        if usage.total() + budget.reserve > budget.window {
+            log::warn!(
+                "The prompt ({usage}) does not fit the context window of {} tokens; \
+raise LLM_NUM_CTX or shorten the persona, examples or memories",
+                budget.window
+            );
+        }
+        let mut messages = vec![Message::system(&system)];
+        messages.extend(turns);
+        messages.push(Message::user(user_input));
+        messages
     }
 
     /// Send the user's spoken command to the language model and return a
-    /// textual response. The model is instructed to either answer
-    /// directly or emit a JSON object describing a tool call. When a
-    /// tool call is requested we execute the appropriate function and
-    /// return its output to the user.
+    /// textual response. The model either answers directly or calls one
+    /// of the tools. Tool output is fed back to the model, which may call
+    /// further tools before it answers, up to `AGENT_MAX_STEPS` tool calls
+    /// (default `3`). When the limit is reached, or with
+    /// `AGENT_MAX_STEPS=0`, the output of the last tool is returned to the
+    /// user as is.
+    ///
+    /// The exchange is added to the conversation history, which "forget
+    /// that" clears. "Switch to pirate mode" changes the persona and "use
+    /// model llama3" the language model; neither is sent to the model.
     pub async fn handle_command(&self, user_input: &str) -> Result<String> {
-        // System prompt describing tool usage. This keeps the prompt
-        // concise while conveying the essential semantics of each
-        // available tool. The assistant is told not to include any
-        // additional commentary when returning JSON.
-        const SYSTEM_PROMPT: &str = "You are Jarvis, a helpful AI assistant.\n\
-Use `shell_task` for raw shell commands like 'ls', 'pwd', 'cat', 'date' or 'find'.\n\
-Use `codex_cli_task` only for writing or scaffolding code via the Codex CLI, not for running system commands.\n\
-When you need to call a tool, respond with **only** a JSON object of the form:\n\
-{\"tool\": \"tool_name\", \"arguments\": {\"command\": \"...\"}}\n\
-Do not include any other text, tags or explanations around the JSON (no `<think>` tags).\n\
-If no tool is required, answer briefly in plain sentences. Do not use Markdown formatting,\ncode blocks, backticks or other special markup in your answers; just write the sentence(s).";
-
-        // Compose the combined prompt. We embed the system prompt
-        // directly into the user prompt rather than using the
-        // `system_prompt` method on `GenerationRequest` so that older
-        // versions of ollama‑rs will behave consistently.
-        let prompt = format!("{}\n\nUser: {}\nAssistant:", SYSTEM_PROMPT, user_input);
-        log::debug!("LLM prompt: {}", prompt);
-
-        let request = GenerationRequest::new(self.model.clone(), prompt);
-        use tokio::time::{timeout, Duration};
-        // Limit the time spent waiting for the language model. If the
-        // request exceeds this timeout we return a fallback response.
-        let response = match timeout(Duration::from_secs(15), self.client.generate(request)).await {
-            Ok(res) => res.context("failed to query local language model")?,
-            Err(_) => {
-                return Ok(
-                    "The request to the language model timed out. Please try again.".to_string(),
-                );
-            }
-        };
-        log::debug!("Raw LLM response: {}", response.response);
-
-        // Trim whitespace. The model might emit trailing newlines.
-        let mut answer = response.response.trim().to_string();
-        log::debug!("Trimmed answer: {}", answer);
-
-        // Check for a <think>...</think> block. If present, capture it
-        // separately and remove it from the answer. The thinking text
-        // will be stored in ~/.jarvis/jarvis.think for later
-        // inspection. We do not expose this to the end user but it
-        // can be accessed via logs or by reading the file.
-        if let Some(start) = answer.find("<think>") {
-            if let Some(end) = answer.find("</think>") {
-                let think_start = start + "<think>".len();
-                let think_end = end;
-                let think_text = answer[think_start..think_end].trim();
-                // Write the think text to ~/.jarvis/jarvis.think
-                if let Ok(home) = env::var("HOME") {
-                    let jarvis_dir = PathBuf::from(&home).join(".jarvis");
-                    // Try to create the directory; ignore errors
-                    let _ = fs::create_dir_all(&jarvis_dir);
-                    let think_file = jarvis_dir.join("jarvis.think");
-                    let _ = fs::write(&think_file, think_text);
-                }
-                log::debug!("Captured think block: {}", think_text);
-                // Remove the think block from the answer by taking
-                // everything after </think>
-                let remainder = &answer[end + "</think>".len()..];
-                answer = remainder.trim_start().to_string();
-                log::debug!("Answer after removing think block: {}", answer);
-            }
-        }
-
-        // Strip any markdown fences or backticks from the answer. The
-        // model sometimes wraps its plain responses in triple
-        // backticks or uses inline code formatting. We remove both
-        // fenced code blocks and inline backticks to ensure the
-        // spoken response is clean.
-        if answer.contains("```") {
-            let mut cleaned = String::new();
-            let mut in_code = false;
-            for line in answer.lines() {
-                let trimmed = line.trim_start();
-                if trimmed.starts_with("```") {
-                    in_code = !in_code;
-                    continue;
-                }
-                if !in_code {
-                    cleaned.push_str(line);
-                    cleaned.push('\n');
-                }
-            }
-            answer = cleaned.trim().to_string();
-            log::debug!("Answer after removing code fences: {}", answer);
-        }
-        // Remove any remaining single backtick characters used for
-        // inline code.
-        if answer.contains('`') {
-            answer = answer.replace('`', "");
-            log::debug!("Answer after removing inline backticks: {}", answer);
-        }
-        // The model sometimes prefixes the JSON tool call with explanatory
-        // markup or `<think>` blocks. Attempt to extract the tool call
-        // JSON by searching for the key "tool" and then balancing
-        // braces to obtain a complete JSON object. This is more
-        // reliable than taking the first and last braces since the
-        // assistant's reasoning may itself contain nested braces.
-        if let Some(start) = answer.find("\"tool\"") {
-            // Find the opening brace preceding the "tool" key.
-            let mut brace_start = None;
-            for (i, ch) in answer[..start].char_indices().rev() {
-                if ch == '{' {
-                    brace_start = Some(i);
-                    break;
-                }
-            }
-            if let Some(start_idx) = brace_start {
-                // Starting from start_idx, scan forward counting braces
-                let mut brace_count = 0;
-                let mut end_idx = None;
-                for (i, ch) in answer[start_idx..].char_indices() {
-                    match ch {
-                        '{' => brace_count += 1,
-                        '}' => {
-                            brace_count -= 1;
-                            if brace_count == 0 {
-                                end_idx = Some(start_idx + i);
-                                break;
-                            }
-                        }
-                        _ => {}
+        Ok(self.respond(user_input, None).await?.spoken_text)
+    }
+
+    /// Like [`handle_command`](Self::handle_command), but returns
+    /// everything that went into the reply: the model's raw output, the
+    /// tool calls and their results, and the timings.
+    pub async fn handle_command_structured(&self, user_input: &str) -> Result<AgentResponse> {
+        self.respond(user_input, None).await
+    }
+
+    /// Like [`handle_command`](Self::handle_command), but sends the answer
+    /// to `sentences` one sentence at a time while the model is still
+    /// generating it, so that speech can start before the reply is
+    /// complete.
+    ///
+    /// Tool output and fallback messages are not sent; check
+    /// [`AgentResponse::streamed`] to see whether the reply still has to be
+    /// spoken.
+    pub async fn handle_command_streaming(
+        &self,
+        user_input: &str,
+        sentences: mpsc::UnboundedSender<String>,
+    ) -> Result<AgentResponse> {
+        self.respond(user_input, Some(&sentences)).await
+    }
+
+    /// Handle a command and record the turn in the session transcript.
+    async fn respond(
+        &self,
+        user_input: &str,
+        sentences: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<AgentResponse> {
+        let started = Instant::now();
+        let mut turn = TurnRecord {
+            time: chrono::Local::now().to_rfc3339(),
+            user: user_input.to_string(),
+            ..TurnRecord::default()
+        };
+        let cancel = CancellationToken::new();
+        *self.cancel.lock().unwrap() = cancel.clone();
+        let result = tokio::select! {
+            result = self.dispatch(user_input, sentences, &mut turn.steps) => result,
+            () = cancel.cancelled() => {
+                log::info!("Command cancelled: {user_input}");
+                Ok(StreamedReply::cancelled())
+            }
+        };
+        // The model may repeat a secret it has seen.
+        let result = result.map(|mut reply| {
+            reply.text = redact(&reply.text).into_owned();
+            reply
+        });
+        match &result {
+            Ok(reply) if reply.cancelled => turn.error = Some("cancelled".to_string()),
+            Ok(reply) => turn.reply = reply.text.clone(),
+            Err(e) => turn.error = Some(format!("{e:#}")),
+        }
+        turn.total_ms = started.elapsed().as_millis() as u64;
+        turn.redact();
+        self.sessions.lock().unwrap().record(&turn);
+        self.stats.lock().unwrap().save();
+        let reply = result?;
+        Ok(AgentResponse::new(reply, &turn))
+    }
+
+    /// Answer a command locally if possible, else through the model.
+    async fn dispatch(
+        &self,
+        user_input: &str,
+        sentences: Option<&mpsc::UnboundedSender<String>>,
+        steps: &mut Vec<StepRecord>,
+    ) -> Result<StreamedReply> {
+        let pending = self.confirmation.lock().unwrap().take();
+        if let Some(pending) = pending {
+            return self.confirm(pending, user_input, sentences, steps).await;
+        }
+        // The answer to a question the model asked resumes the command it
+        // was about, unless it is a command of its own ("never mind").
+        let pending = self.clarification.lock().unwrap().take();
+        if let Some(pending) = pending {
+            if let Some(reply) = self.local_reply(user_input) {
+                return Ok(StreamedReply::unspoken(reply));
+            }
+            let mode = pending.mode;
+            let resumed = pending.resume(user_input);
+            log::info!("Resuming with the clarification: {resumed}");
+            return self.converse(&resumed, mode, sentences, steps).await;
+        }
+        if let Some(reply) = self.local_reply(user_input) {
+            return Ok(StreamedReply::unspoken(reply));
+        }
+        if let Some(reply) = self.switch_model(user_input).await {
+            return Ok(StreamedReply::unspoken(reply));
+        }
+        if let Some(reply) = self.memory_command(user_input).await {
+            return Ok(StreamedReply::unspoken(reply));
+        }
+        if let Some(reply) = self.history_command(user_input, steps).await {
+            return Ok(StreamedReply::unspoken(reply));
+        }
+        let mut mode = Mode {
+            dry_run: self.dry_run,
+            background: false,
+        };
+        let mut command = user_input.to_string();
+        if let Some(rest) = dry_run::command(&command) {
+            log::info!("Dry run: {rest}");
+            mode.dry_run = true;
+            command = rest;
+        }
+        if let Some(rest) = jobs::background_command(&command) {
+            log::info!("In the background: {rest}");
+            mode.background = true;
+            command = rest;
+        }
+        self.converse(&command, mode, sentences, steps).await
+    }
+
+    /// Handle the commands that are about the conversation itself ("forget
+    /// that", "switch to pirate mode", "answer me in Croatian") and the
+    /// trivial ones matched by the intent router ("what time is it")
+    /// without the model.
+    fn local_reply(&self, user_input: &str) -> Option<String> {
+        if Conversation::is_forget_command(user_input) {
+            self.clear_history();
+            return Some("Okay, I've forgotten our conversation.".to_string());
+        }
+        if tools::shell_session::is_reset_command(user_input) {
+            // Every tool, not just the first with something to reset.
+            let reset = self.tools.iter().filter(|tool| tool.reset()).count();
+            return Some(if reset > 0 {
+                "Okay, I've started a fresh shell.".to_string()
+            } else {
+                "There's no shell session to reset.".to_string()
+            });
+        }
+        match project::parse_command(user_input) {
+            Some(ProjectCommand::Open(name)) => return Some(self.open_project(&name)),
+            Some(ProjectCommand::Leave) => return Some(self.leave_project()),
+            None => {}
+        }
+        if let Some(reply) = self.personas.lock().unwrap().handle_command(user_input) {
+            return Some(reply);
+        }
+        if let Some(wanted) = language::language_command(user_input) {
+            self.language.lock().unwrap().set(wanted);
+            set_reply_language(Some(wanted.code));
+            log::info!("Answering in {} from now on", wanted.name);
+            return Some(format!("Okay, I'll answer in {} from now on.", wanted.name));
+        }
+        if let Some(reply) =
+            JobQuestion::parse(user_input).and_then(|question| self.jobs.answer(&question))
+        {
+            return Some(reply);
+        }
+        let last_actions = self.last_actions.lock().unwrap();
+        self.intents.handle(user_input, &last_actions)
+    }
+
+    /// Handle "use model llama3" and "switch to the big model": check that
+    /// the backend offers the model and use it from now on. Returns the
+    /// reply, or `None` if `command` is not such a request.
+    async fn switch_model(&self, command: &str) -> Option<String> {
+        let spoken = llm::model_switch_target(command)?;
+        let available = match self.backend.list_models().await {
+            Ok(models) => models,
+            Err(e) => {
+                log::warn!("Cannot switch models: {e:#}");
+                return Some("Sorry, I can't switch models right now.".to_string());
+            }
+        };
+        let reply = match llm::find_model(&spoken, &available) {
+            Ok(model) if self.backend.model().as_deref() == Some(&model) => {
+                format!("I'm already using {}.", spoken_model_name(&model))
+            }
+            Ok(model) => match self.backend.set_model(&model) {
+                Ok(()) => {
+                    log::info!("Switched language model to {model}");
+                    format!("Okay, now using {}.", spoken_model_name(&model))
+                }
+                Err(e) => {
+                    log::warn!("Cannot switch to model {model}: {e:#}");
+                    format!("Sorry, I couldn't switch to {model}.")
+                }
+            },
+            Err(candidates) if candidates.is_empty() => {
+                format!("I don't have a model called {spoken}.")
+            }
+            Err(candidates) => format!("Which one? I have {}.", candidates.join(", ")),
+        };
+        Some(reply)
+    }
+
+    /// Handle "remember that ...", "what do you remember" and "clear your
+    /// memory". Returns the reply, or `None` if `command` is not about the
+    /// long-term memory.
+    async fn memory_command(&self, command: &str) -> Option<String> {
+        if !self.memory.lock().unwrap().is_enabled() {
+            return None;
+        }
+        if memory::is_list_command(command) {
+            let memory = self.memory.lock().unwrap();
+            let facts: Vec<&str> = memory.entries().iter().map(|e| e.text.as_str()).collect();
+            return Some(if facts.is_empty() {
+                "You haven't asked me to remember anything.".to_string()
+            } else {
+                format!("You asked me to remember: {}.", facts.join("; "))
+            });
+        }
+        if memory::is_clear_command(command) {
+            return Some(match self.memory.lock().unwrap().clear() {
+                Ok(()) => "Okay, I've cleared my memory.".to_string(),
+                Err(e) => {
+                    log::warn!("Cannot clear the memory: {e:#}");
+                    "Sorry, I couldn't clear my memory.".to_string()
+                }
+            });
+        }
+        let fact = memory::remember_command(command)?;
+        let embedding = self.embed_one(&fact).await.unwrap_or_default();
+        let result = self.memory.lock().unwrap().add(&fact, embedding);
+        Some(match result {
+            Ok(()) => {
+                log::info!("Remembered: {fact}");
+                "Okay, I'll remember that.".to_string()
+            }
+            Err(e) => {
+                log::warn!("Cannot store memory: {e:#}");
+                "Sorry, I couldn't save that.".to_string()
+            }
+        })
+    }
+
+    /// What Jarvis knows that may help with `user_input`, for the system
+    /// prompt: the relevant remembered facts and passages from the indexed
+    /// documents. Empty if there are none.
+    async fn recall(&self, user_input: &str) -> String {
+        let (has_memories, memory_embeddings) = {
+            let memory = self.memory.lock().unwrap();
+            (!memory.entries().is_empty(), memory.has_embeddings())
+        };
+        let has_documents = {
+            let mut documents = self.documents.lock().unwrap();
+            documents.refresh();
+            !documents.is_empty()
+        };
+        if !has_memories && !has_documents {
+            return String::new();
+        }
+        let embedding = if memory_embeddings || has_documents {
+            self.embed_one(user_input).await
+        } else {
+            None
+        };
+
+        let mut context = String::new();
+        let memory = self.memory.lock().unwrap();
+        let facts = memory.relevant(user_input, embedding.as_deref());
+        if !facts.is_empty() {
+            context.push_str(
+                "\nEarlier, the user asked you to remember these facts; use them if they help:\n",
+            );
+            for fact in facts {
+                context.push_str(&format!("- {}\n", fact.text));
+            }
+        }
+        let documents = self.documents.lock().unwrap();
+        let passages = embedding
+            .as_deref()
+            .map(|embedding| documents.search(embedding))
+            .unwrap_or_default();
+        if !passages.is_empty() {
+            context.push_str(
+                "\nPassages from the user's documents that may answer the question. Use them if \
+they do, and say which document the answer comes from:\n",
+            );
+            for chunk in passages {
+                let name = chunk
+                    .source
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_default();
+                context.push_str(&format!("[{name}]\n{}\n\n", chunk.text));
+            }
+        }
+        context
+    }
+
+    /// The embedding of `text`, if the backend can compute one in time.
+    async fn embed_one(&self, text: &str) -> Option<Vec<f32>> {
+        match self
+            .with_timeout(self.backend.embed(&[text.to_string()]))
+            .await
+        {
+            Ok(Some(mut embeddings)) if !embeddings.is_empty() => Some(embeddings.remove(0)),
+            Ok(_) => None,
+            Err(e) => {
+                log::debug!("No embedding: {e:#}");
+                None
+            }
+        }
+    }
+
+    /// The agent loop: query the model, run the tool it calls and feed
+    /// the output back until it answers. With `sentences` the replies are
+    /// streamed; text the model says before calling a tool ("let me
+    /// check") is spoken as well. Each request to the model is added to
+    /// `steps`.
+    async fn converse(
+        &self,
+        user_input: &str,
+        mode: Mode,
+        sentences: Option<&mpsc::UnboundedSender<String>>,
+        steps: &mut Vec<StepRecord>,
+    ) -> Result<StreamedReply> {
+        let persona = self.personas.lock().unwrap().current();
+        let options = self.options.with_overrides(&persona.options);
+        self.backend.set_options(&options);
+        let context = self.recall(user_input).await;
+        let messages = self.chat_messages(
+            user_input,
+            &persona.prompt,
+            &context,
+            ContextBudget::new(&options),
+        );
+        let progress = Progress {
+            messages,
+            step: 0,
+            json_retries: 0,
+            last_output: None,
+            tainted: false,
+            mode,
+        };
+        self.run_loop(user_input, progress, sentences, steps).await
+    }
+
+    /// The agent loop from `progress` on; see [`Agent::converse`].
+    async fn run_loop(
+        &self,
+        user_input: &str,
+        mut progress: Progress,
+        sentences: Option<&mpsc::UnboundedSender<String>>,
+        steps: &mut Vec<StepRecord>,
+    ) -> Result<StreamedReply> {
+        let tools = self.offered_tools().specs();
+        loop {
+            log::debug!("LLM messages: {:?}", progress.messages);
+            let stream = sentences.map(|tx| {
+                Mutex::new(SentenceStream::new(
+                    tx.clone(),
+                    self.think_mode.status(),
+                    self.post.clone(),
+                ))
+            });
+            let asked = Instant::now();
+            let reply = self
+                .query(&progress.messages, &tools, stream.as_ref())
+                .await?;
+            steps.push(StepRecord {
+                raw: reply
+                    .as_ref()
+                    .map(|r| r.content.clone())
+                    .unwrap_or_default(),
+                model_ms: asked.elapsed().as_millis() as u64,
+                ..StepRecord::default()
+            });
+            let trace = steps.last_mut().unwrap();
+            let Some(reply) = reply else {
+                trace.error = Some("no answer in time".to_string());
+                return Ok(StreamedReply::unspoken(TIMEOUT_REPLY.to_string()));
+            };
+            self.think_mode.record(&think::split(&reply.content).1);
+
+            if self.clarify && reply.tool_calls.is_empty() {
+                if let Some(question) = llm::parse_clarification(&reply.content) {
+                    log::info!("The model asks: {question}");
+                    *self.clarification.lock().unwrap() = Some(Clarification {
+                        request: user_input.to_string(),
+                        question: question.clone(),
+                        mode: progress.mode,
+                    });
+                    return Ok(StreamedReply::unspoken(question));
+                }
+            }
+            log::debug!("Raw LLM response: {:?}", reply);
+            let streamed = stream.is_some_and(|s| s.into_inner().unwrap().finish());
+
+            // Small models sometimes write the call as JSON in their
+            // answer instead of using native tool calling.
+            let (calls, order, from_text) = if reply.tool_calls.is_empty() {
+                match llm::parse_text_tool_calls(&reply.content) {
+                    Ok((calls, order)) => (calls, order, true),
+                    Err(problem) if progress.json_retries < self.json_retries => {
+                        log::warn!("Malformed tool call: {problem}");
+                        trace.error = Some(problem.clone());
+                        progress.json_retries += 1;
+                        progress.messages.push(reply.clone());
+                        progress.messages.push(Message::user(&format!(
+                            "Your JSON was invalid because {problem}. Respond again with only \
+valid JSON for the tool call and nothing else."
+                        )));
+                        continue;
+                    }
+                    Err(problem) => {
+                        log::warn!(
+                            "Giving up after {} malformed tool calls: {problem}",
+                            progress.json_retries + 1
+                        );
+                        trace.error = Some(problem);
+                        return Ok(StreamedReply::unspoken(TOOL_CALL_REPLY.to_string()));
                     }
                 }
-                if let Some(end_idx) = end_idx {
-                    let json_slice = &answer[start_idx..=end_idx];
-                    log::debug!("Found JSON slice: {}", json_slice);
-                    if let Ok(json) = serde_json::from_str::<Value>(json_slice) {
-                        if let Some(tool_name) = json.get("tool").and_then(|v| v.as_str()) {
-                            log::debug!("Parsed tool call: {}", tool_name);
-                            match tool_name {
-                                "shell_task" => {
-                                    log::debug!(
-                                        "Executing shell_task with args: {:?}",
-                                        json.get("arguments")
-                                    );
-                                    if let Some(args) = json.get("arguments") {
-                                        if let Some(command) =
-                                            args.get("command").and_then(|v| v.as_str())
-                                        {
-                                            let result = tools::run_shell_task(command)?;
-                                            log::debug!("shell_task result: {}", result);
-                                            return Ok(result);
-                                        }
-                                    }
-                                }
-                                "codex_cli_task" => {
-                                    log::debug!(
-                                        "Executing codex_cli_task with args: {:?}",
-                                        json.get("arguments")
-                                    );
-                                    if let Some(args) = json.get("arguments") {
-                                        if let Some(command) =
-                                            args.get("command").and_then(|v| v.as_str())
-                                        {
-                                            // Intercept simple shell commands that should be run via shell_task instead
-                                            let cmd_lower = command.trim().to_lowercase();
-                                            let simple_shells =
-                                                ["date", "ls", "pwd", "cat", "find", "uptime"];
-                                            if simple_shells.iter().any(|c| {
-                                                cmd_lower == *c
-                                                    || cmd_lower.starts_with(&format!("{} ", c))
-                                            }) {
-                                                log::debug!(
-                                                    "Redirecting codex_cli_task '{}' to shell_task",
-                                                    command
-                                                );
-                                                let result = tools::run_shell_task(command)?;
-                                                log::debug!("shell_task result: {}", result);
-                                                return Ok(result);
-                                            }
-                                            let result = tools::run_codex_cli(command)?;
-                                            log::debug!("codex_cli_task result: {}", result);
-                                            return Ok(result);
-                                        }
-                                    }
-                                }
-                                _ => {
-                                    // Unknown tool; fall through to return raw answer
-                                }
-                            }
-                        }
+            } else {
+                (reply.tool_calls.clone(), self.native_order, false)
+            };
+            trace.tool_calls = calls.clone();
+
+            if !calls.is_empty() {
+                let mut asked = reply.clone();
+                if from_text {
+                    asked.content.clear();
+                    asked.tool_calls = calls.clone();
+                }
+                if progress.mode.dry_run {
+                    let text = dry_run::describe(&calls, order, |call| self.check_call(call).err());
+                    log::info!("{text}");
+                    return Ok(StreamedReply::unspoken(text));
+                }
+                let dangerous = calls.iter().find_map(|call| self.needs_confirmation(call));
+                if progress.tainted {
+                    if let Some(action) = dangerous {
+                        log::warn!("Refusing to {action}: requested after suspicious tool output");
+                        trace.error =
+                            Some(format!("refused after suspicious tool output: {action}"));
+                        return Ok(StreamedReply::unspoken(format!(
+                            "I stopped. Something in the tool output tried to make me {action}."
+                        )));
                     }
+                    let command = guard::describe(&calls[0]);
+                    log::info!("Asking for confirmation after suspicious tool output: {command}");
+                    *self.confirmation.lock().unwrap() = Some(PendingAction {
+                        request: user_input.to_string(),
+                        progress,
+                        asked,
+                        order,
+                    });
+                    return Ok(StreamedReply::unspoken(format!(
+                        "The tool output contained instructions, so I'm checking first: this will run \
+{command}. Say \"yes, confirm\" to go ahead."
+                    )));
+                }
+                if let Some(action) = dangerous {
+                    log::info!("Asking for confirmation before I {action}");
+                    *self.confirmation.lock().unwrap() = Some(PendingAction {
+                        request: user_input.to_string(),
+                        progress,
+                        asked,
+                        order,
+                    });
+                    return Ok(StreamedReply::unspoken(format!(
+                        "This will {action}. Say \"yes, confirm\" to go ahead."
+                    )));
+                }
+                match self
+                    .run_calls(user_input, &mut progress, asked, order, trace)
+                    .await?
+                {
+                    Some(reply) => return Ok(reply),
+                    None => continue,
                 }
             }
+
+            let answer = self.post.clean(&reply.content);
+            // If the answer is completely empty after stripping, fall back
+            // to the last tool output or ask the user to repeat. An empty
+            // answer can cause the TTS backend to hang.
+            if answer.trim().is_empty() {
+                let text = progress
+                    .last_output
+                    .unwrap_or_else(|| EMPTY_REPLY.to_string());
+                if text != EMPTY_REPLY {
+                    self.history.lock().unwrap().record(user_input, &text);
+                }
+                return Ok(StreamedReply::unspoken(text));
+            }
+            // Streamed answers are already being spoken and can be
+            // interrupted; others are summed up if too long to listen to.
+            let answer = if streamed || !self.post.has(Step::Length) {
+                answer
+            } else {
+                self.shorten(user_input, answer, steps).await
+            };
+            self.history.lock().unwrap().record(user_input, &answer);
+            return Ok(StreamedReply {
+                text: answer,
+                streamed,
+                cancelled: false,
+            });
+        }
+    }
+
+    /// Run the tool calls of the model's message `asked` in `order`. With
+    /// steps left, the results are added to the chat for the model to go
+    /// on, and `None` is returned. Out of steps, the tools' output is the
+    /// reply.
+    async fn run_calls(
+        &self,
+        user_input: &str,
+        progress: &mut Progress,
+        asked: Message,
+        order: CallOrder,
+        trace: &mut StepRecord,
+    ) -> Result<Option<StreamedReply>> {
+        if progress.mode.background {
+            return Ok(Some(self.start_job(user_input, &asked.tool_calls)));
+        }
+        if progress.step == 0 {
+            self.last_actions.lock().unwrap().clear();
+        }
+        let started = Instant::now();
+        let results = self.execute(&asked.tool_calls, order).await;
+        trace.tools_ms += started.elapsed().as_millis() as u64;
+        let results = results?;
+        if progress.step >= self.max_steps {
+            let mut outputs: Vec<String> = results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(output) => untrusted::sanitize(&output),
+                    Err(problem) => {
+                        log::warn!("Skipping tool call: {problem}");
+                        String::new()
+                    }
+                })
+                .collect();
+            trace.tool_results = outputs.clone();
+            outputs.retain(|output| !output.is_empty());
+            if outputs.is_empty() {
+                // No call could run: whatever else the model said, or the
+                // output of an earlier step, is the answer.
+                let answer = self.post.clean(&asked.content);
+                let text = Some(answer)
+                    .filter(|answer| !answer.trim().is_empty())
+                    .or(progress.last_output.take())
+                    .unwrap_or_else(|| TOOL_CALL_REPLY.to_string());
+                return Ok(Some(StreamedReply::unspoken(text)));
+            }
+            let output = outputs.join("\n");
+            self.history.lock().unwrap().record(user_input, &output);
+            return Ok(Some(StreamedReply::unspoken(output)));
+        }
+        progress.step += 1;
+        let names: Vec<String> = asked.tool_calls.iter().map(|c| c.name.clone()).collect();
+        progress.messages.push(asked);
+        // Every call gets a result, in order; invalid calls get the
+        // problem so that the model can correct them.
+        for (name, result) in names.iter().zip(results) {
+            let output = match result {
+                Ok(output) => {
+                    if self.output_guard && untrusted::looks_like_instructions(&output) {
+                        log::warn!("Output of {name} contains instructions to the assistant");
+                        progress.tainted = true;
+                    }
+                    progress.last_output = Some(untrusted::sanitize(&output));
+                    output
+                }
+                Err(problem) => {
+                    log::warn!("Invalid tool call: {problem}");
+                    format!("Error: {problem}")
+                }
+            };
+            let shown = tools::truncate_bytes(&output, tools::max_output_bytes());
+            let shown = if self.output_guard {
+                untrusted::wrap(name, &shown)
+            } else {
+                shown
+            };
+            progress.messages.push(Message::tool(&shown));
+            trace.tool_results.push(output);
+        }
+        Ok(None)
+    }
+
+    /// Start `calls` as a background job for `user_input` and say so.
+    /// The calls run one after the other; one that is not valid or fails
+    /// ends the job.
+    fn start_job(&self, user_input: &str, calls: &[ToolCall]) -> StreamedReply {
+        let mut work = Vec::with_capacity(calls.len());
+        for call in calls {
+            match self.check_call(call) {
+                Ok(tool) => work.push((tool.clone(), call.clone())),
+                Err(problem) => {
+                    log::warn!("Not starting job: {problem}");
+                    return StreamedReply::unspoken(format!("I couldn't start that: {problem}."));
+                }
+            }
+        }
+        let events = self.events.clone();
+        let id = self.jobs.start(user_input, self.events.clone(), move || {
+            let mut outputs = Vec::new();
+            for (tool, call) in work {
+                events.emit(JarvisEvent::ToolCall {
+                    tool: call.name.clone(),
+                    arguments: call.arguments.clone(),
+                });
+                let output = tool
+                    .run_streaming(&call.arguments, &mut |line| {
+                        events.emit(JarvisEvent::ToolOutput {
+                            tool: call.name.clone(),
+                            line: line.to_string(),
+                        })
+                    })
+                    .with_context(|| format!("the {} tool failed", call.name))?;
+                events.emit(JarvisEvent::ToolResult {
+                    tool: call.name.clone(),
+                    output: output.clone(),
+                });
+                outputs.push(untrusted::sanitize(&output));
+            }
+            Ok(outputs.join("\n"))
+        });
+        let reply = format!("Started job {id}. I'll tell you when it's done.");
+        self.history.lock().unwrap().record(user_input, &reply);
+        StreamedReply::unspoken(reply)
+    }
+
+    /// Run `calls`, one after the other or up to `TOOL_CONCURRENCY` at a
+    /// time, and return their outputs in the order of the calls. A call
+    /// that is not valid gets the problem instead; a tool that fails ends
+    /// the command. Tools run on blocking threads, so that the runtime
+    /// keeps listening for "cancel" and speaking meanwhile.
+    async fn execute(
+        &self,
+        calls: &[ToolCall],
+        order: CallOrder,
+    ) -> Result<Vec<std::result::Result<String, String>>> {
+        let checked: Vec<_> = calls
+            .iter()
+            .map(|call| (call, self.check_call(call).cloned()))
+            .collect();
+        let at_once =
+            if order == CallOrder::Sequential || calls.len() < 2 || self.tool_concurrency < 2 {
+                1
+            } else {
+                log::debug!("Running {} tool calls in parallel", calls.len());
+                self.tool_concurrency
+            };
+        let mut results = Vec::with_capacity(calls.len());
+        for batch in checked.chunks(at_once) {
+            let running: Vec<_> = batch
+                .iter()
+                .map(|(call, checked)| {
+                    checked.clone().map(|tool| {
+                        let call = (*call).clone();
+                        let events = self.events.clone();
+                        let narrate_every = self.narrate_every;
+                        tokio::task::spawn_blocking(move || {
+                            let started = Instant::now();
+                            let result = run_tool(tool.as_ref(), &call, &events, narrate_every);
+                            (result, started.elapsed().as_millis() as u64)
+                        })
+                    })
+                })
+                .collect();
+            for ((call, _), running) in batch.iter().zip(running) {
+                let (result, ms) = match running {
+                    Ok(thread) => {
+                        let (result, ms) = thread.await.unwrap_or_else(|_| {
+                            (Err(anyhow!("the {} tool crashed", call.name)), 0)
+                        });
+                        self.last_actions.lock().unwrap().push(ToolRun {
+                            call: (*call).clone(),
+                            result: result
+                                .as_ref()
+                                .map(String::clone)
+                                .map_err(|e| format!("{e:#}")),
+                        });
+                        let result =
+                            result.with_context(|| format!("the {} tool failed", call.name));
+                        (result.map(Ok), ms)
+                    }
+                    Err(problem) => (Ok(Err(problem)), 0),
+                };
+                let ok = matches!(result, Ok(Ok(_)));
+                self.stats.lock().unwrap().record_tool(&call.name, ms, ok);
+                results.push(result?);
+            }
         }
-        // At this point no tool call was detected, so we will return
-        // the cleaned answer. However, if the answer is excessively
-        // long (indicating the model is uncertain or verbose) we
-        // substitute a generic clarification request instead. This
-        // prevents long monologues from blocking the UI.
+        Ok(results)
+    }
+
+    /// Handle the answer to "say yes, confirm to go ahead": run the
+    /// pending tool calls and carry on with the command, or drop them.
+    async fn confirm(
+        &self,
+        pending: PendingAction,
+        answer: &str,
+        sentences: Option<&mpsc::UnboundedSender<String>>,
+        steps: &mut Vec<StepRecord>,
+    ) -> Result<StreamedReply> {
+        if !guard::is_confirmation(answer) {
+            log::info!("Dangerous tool call cancelled");
+            return Ok(StreamedReply::unspoken(
+                "Okay, I won't do that.".to_string(),
+            ));
+        }
+        log::info!("Dangerous tool call confirmed");
+        let PendingAction {
+            request,
+            mut progress,
+            asked,
+            order,
+        } = pending;
+        steps.push(StepRecord {
+            tool_calls: asked.tool_calls.clone(),
+            ..StepRecord::default()
+        });
+        let trace = steps.last_mut().unwrap();
+        match self
+            .run_calls(&request, &mut progress, asked, order, trace)
+            .await?
         {
-            let max_chars = 300;
-            let max_words = 50;
-            let word_count = answer.split_whitespace().count();
-            if answer.len() > max_chars || word_count > max_words {
-                return Ok("I'm sorry, I didn't quite understand. Please try again with a simpler command.".to_string());
+            Some(reply) => Ok(reply),
+            None => self.run_loop(&request, progress, sentences, steps).await,
+        }
+    }
+
+    /// Sum up an `answer` longer than `ANSWER_MAX_CHARS` in a sentence or
+    /// two by asking the model again. If that fails, the answer is
+    /// returned as it is, to be read a page at a time.
+    async fn shorten(
+        &self,
+        user_input: &str,
+        answer: String,
+        steps: &mut Vec<StepRecord>,
+    ) -> String {
+        if self.max_answer_chars == 0 || answer.chars().count() <= self.max_answer_chars {
+            return answer;
+        }
+        log::info!(
+            "Answer of {} characters is too long; asking for a summary",
+            answer.chars().count()
+        );
+        let messages = [
+            Message::system(SUMMARY_PROMPT),
+            Message::user(&format!("Question: {user_input}\n\nAnswer: {answer}")),
+        ];
+        self.summarise(&messages, steps).await.unwrap_or(answer)
+    }
+
+    /// The model's cleaned-up reply to `messages`, without tools; `None`
+    /// if it fails, takes too long or says nothing. The request is added
+    /// to `steps`.
+    async fn summarise(&self, messages: &[Message], steps: &mut Vec<StepRecord>) -> Option<String> {
+        let asked = Instant::now();
+        let result = self.query(messages, &[], None).await;
+        let mut trace = StepRecord {
+            model_ms: asked.elapsed().as_millis() as u64,
+            ..StepRecord::default()
+        };
+        let summary = match result {
+            Ok(Some(reply)) => {
+                trace.raw = reply.content.clone();
+                self.think_mode.record(&think::split(&reply.content).1);
+                Some(self.post.clean(&reply.content)).filter(|s| !s.trim().is_empty())
             }
+            Ok(None) => {
+                trace.error = Some("no summary in time".to_string());
+                None
+            }
+            Err(e) => {
+                log::warn!("Cannot get a summary: {e:#}");
+                trace.error = Some(format!("{e:#}"));
+                None
+            }
+        };
+        steps.push(trace);
+        summary
+    }
+
+    /// Answer "what did I ask you this morning?" from the session
+    /// transcripts: the matching turns are summed up by the model, or
+    /// listed if it does not answer. Returns `None` if `command` is not
+    /// about earlier sessions or transcripts are off.
+    async fn history_command(&self, command: &str, steps: &mut Vec<StepRecord>) -> Option<String> {
+        let query = HistoryQuery::parse(command)?;
+        if !self.sessions.lock().unwrap().is_enabled() {
+            return None;
         }
-        // If the answer is completely empty after stripping, return a
-        // default clarification message instead of an empty string. An
-        // empty answer can cause the TTS backend to hang.
-        if answer.trim().is_empty() {
-            return Ok("I didn't catch that. Could you repeat your command?".to_string());
+        let turns = session::search(&query, chrono::Local::now());
+        log::info!("{} earlier turns {}", turns.len(), query.describe());
+        if turns.is_empty() {
+            return Some(format!("I don't have anything {}.", query.describe()));
         }
-        Ok(answer)
+        let recent = &turns[turns.len().saturating_sub(HISTORY_TURNS)..];
+        let mut transcript = String::new();
+        for turn in recent {
+            let time = chrono::DateTime::parse_from_rfc3339(&turn.time)
+                .map(|time| time.format("%A %-I:%M %p").to_string())
+                .unwrap_or_default();
+            transcript.push_str(&format!(
+                "[{time}] User: {}\nJarvis: {}\n",
+                turn.user, turn.reply
+            ));
+        }
+        let messages = [
+            Message::system(HISTORY_PROMPT),
+            Message::user(&format!("Question: {command}\n\n{transcript}")),
+        ];
+        if let Some(summary) = self.summarise(&messages, steps).await {
+            return Some(summary);
+        }
+        let asked: Vec<&str> = recent
+            .iter()
+            .rev()
+            .take(3)
+            .rev()
+            .map(|turn| turn.user.as_str())
+            .collect();
+        Some(format!("You asked me: {}.", asked.join("; ")))
+    }
+
+    /// Ask the model for its next reply, streaming it into `stream` if
+    /// given. If the model fails or does not answer in time, the request
+    /// is repeated with `MODEL_FALLBACK`, unless part of the reply has
+    /// already been streamed. `None` means no model answered in time.
+    async fn query(
+        &self,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        stream: Option<&Mutex<SentenceStream>>,
+    ) -> Result<Option<Message>> {
+        let failed = match self.attempt(None, messages, tools, stream).await {
+            Ok(Some(reply)) => return Ok(Some(reply)),
+            failed => failed,
+        };
+        let Some(fallback) = &self.fallback_model else {
+            return failed;
+        };
+        if stream.is_some_and(|s| s.lock().unwrap().started()) {
+            return failed;
+        }
+        let Some(primary) = self.backend.model() else {
+            return failed;
+        };
+        if primary == *fallback {
+            return failed;
+        }
+        match &failed {
+            Ok(_) => log::warn!("{primary} did not answer in time; trying {fallback}"),
+            Err(e) => log::warn!("{primary} failed ({e:#}); trying {fallback}"),
+        }
+        self.attempt(Some(fallback), messages, tools, stream).await
+    }
+
+    /// One request to `model`, or the current model if `None`; other
+    /// requests meanwhile keep the current one. Connection errors are
+    /// retried up to `LLM_RETRIES` times with exponential backoff, as long
+    /// as nothing has been streamed yet.
+    async fn attempt(
+        &self,
+        model: Option<&str>,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        stream: Option<&Mutex<SentenceStream>>,
+    ) -> Result<Option<Message>> {
+        let mut delay = Duration::from_millis(500);
+        let mut retries = 0;
+        let name = model
+            .map(str::to_string)
+            .or_else(|| self.backend.model())
+            .unwrap_or_else(|| "default".to_string());
+        loop {
+            let started = Instant::now();
+            let result = match stream {
+                Some(stream) => {
+                    let heard = Mutex::new(Instant::now());
+                    let on_text = |text: &str| {
+                        *heard.lock().unwrap() = Instant::now();
+                        stream.lock().unwrap().push(text);
+                    };
+                    let request = match model {
+                        Some(model) => self
+                            .backend
+                            .chat_stream_as(model, messages, tools, &on_text),
+                        None => self.backend.chat_stream(messages, tools, &on_text),
+                    };
+                    self.with_idle_timeout(request, &heard).await
+                }
+                None => {
+                    let request = match model {
+                        Some(model) => self.backend.chat_as(model, messages, tools),
+                        None => self.backend.chat(messages, tools),
+                    };
+                    self.with_timeout(request).await
+                }
+            };
+            self.stats.lock().unwrap().record_model(
+                &name,
+                started.elapsed().as_millis() as u64,
+                matches!(result, Ok(Some(_))),
+            );
+            match result {
+                Err(e)
+                    if retries < self.retries
+                        && is_transient(&e)
+                        && !stream.is_some_and(|s| s.lock().unwrap().started()) =>
+                {
+                    log::warn!("{e:#}; retrying in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Limit the time spent waiting for the language model to
+    /// `LLM_TIMEOUT_SECS`. If the request takes longer, `None` is
+    /// returned and the caller answers with a fallback response.
+    async fn with_timeout<T>(&self, request: impl Future<Output = Result<T>>) -> Result<Option<T>> {
+        match tokio::time::timeout(self.timeout, request).await {
+            Ok(res) => Ok(Some(res.context("the language model request failed")?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Like [`with_timeout`](Self::with_timeout) for a streamed reply:
+    /// `LLM_TIMEOUT_SECS` limits the wait for the first piece of text and
+    /// each pause between pieces, `heard` being when the last one came,
+    /// so that a long answer spoken as it arrives is not cut off.
+    async fn with_idle_timeout<T>(
+        &self,
+        request: impl Future<Output = Result<T>>,
+        heard: &Mutex<Instant>,
+    ) -> Result<Option<T>> {
+        tokio::pin!(request);
+        loop {
+            let deadline = *heard.lock().unwrap() + self.timeout;
+            tokio::select! {
+                res = &mut request => {
+                    return Ok(Some(res.context("the language model request failed")?));
+                }
+                () = tokio::time::sleep_until(deadline) => {
+                    if heard.lock().unwrap().elapsed() >= self.timeout {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The tool `call` asks for, if it exists and the arguments match its
+    /// schema. Otherwise the problem is returned, to be reported back to
+    /// the model.
+    fn check_call(&self, call: &ToolCall) -> std::result::Result<&Arc<dyn Tool>, String> {
+        let allowed = self.projects.lock().unwrap().allows(&call.name);
+        let Some(tool) = self.tools.get(&call.name).filter(|_| allowed) else {
+            let offered = self.offered_tools();
+            let names = offered.names();
+            return Err(format!(
+                "there is no tool called '{}'. The available tools are: {}",
+                call.name,
+                names.join(", ")
+            ));
+        };
+        schema::validate(&tool.parameters(), &call.arguments)
+            .map_err(|problem| format!("invalid arguments for {}: {problem}", call.name))?;
+        if let Some(Verdict::Deny(reason)) = self.policy_verdict(tool.as_ref(), call) {
+            return Err(format!("the shell policy does not allow this: {reason}"));
+        }
+        Ok(tool)
+    }
+
+    /// What the shell [`Policy`] says about `call`, if `tool` runs a
+    /// command.
+    fn policy_verdict(&self, tool: &dyn Tool, call: &ToolCall) -> Option<Verdict> {
+        tool.shell_line(&call.arguments)
+            .map(|line| self.policy.check(&line))
+    }
+
+    /// What `call` will do, to be read out before it runs ("run rm -rf
+    /// build"), if it needs the user's go-ahead: tools that always ask,
+    /// calls a tool says are risky, commands the shell [`Policy`] wants
+    /// confirmed, and calls the [`Guard`] finds dangerous.
+    fn needs_confirmation(&self, call: &ToolCall) -> Option<String> {
+        if let Some(action) = self
+            .tools
+            .get(&call.name)
+            .and_then(|tool| tool.confirmation(&call.arguments))
+        {
+            return Some(action);
+        }
+        if self.tools.get(&call.name).is_some_and(|tool| {
+            tool.needs_confirmation()
+                || matches!(
+                    self.policy_verdict(tool.as_ref(), call),
+                    Some(Verdict::Confirm(_))
+                )
+        }) {
+            let what = guard::describe(call);
+            return Some(if what == call.name {
+                format!("run {what}")
+            } else {
+                format!("run {} with {what}", call.name)
+            });
+        }
+        self.guard.check(call).map(|command| format!("run {command}"))
+    }
+}
+
+/// Run `tool` for `call`, publishing its output on `events` as it comes,
+/// and return the output with its secrets masked.
+fn run_tool(
+    tool: &dyn Tool,
+    call: &ToolCall,
+    events: &EventBus,
+    narrate_every: Option<Duration>,
+) -> Result<String> {
+    log::debug!("Executing {} with args: {}", call.name, call.arguments);
+    events.emit(JarvisEvent::ToolCall {
+        tool: call.name.clone(),
+        arguments: call.arguments.clone(),
+    });
+    let mut narrator = Narrator::new(narrate_every);
+    let result = tool.run_streaming(&call.arguments, &mut |line| {
+        let line = redact(line);
+        let line = line.as_ref();
+        events.emit(JarvisEvent::ToolOutput {
+            tool: call.name.clone(),
+            line: line.to_string(),
+        });
+        if let Some(text) = narrator.line(line) {
+            log::info!("{text}");
+            events.emit(JarvisEvent::ToolProgress {
+                tool: call.name.clone(),
+                text,
+            });
+        }
+    });
+    // Secrets in the output, or in the error, go no further.
+    let result = result
+        .map(|output| redact(&output).into_owned())
+        .map_err(|e| anyhow!("{}", redact(&format!("{e:#}"))))?;
+    log::debug!("{} result: {}", call.name, result);
+    events.emit(JarvisEvent::ToolResult {
+        tool: call.name.clone(),
+        output: result.clone(),
+    });
+    Ok(result)
+}
+
+/// Whether `listed`, as the server names it, is the configured `model`,
+/// which may leave out Ollama's default tag.
+fn same_model(listed: &str, model: &str) -> bool {
+    listed == model || listed.strip_suffix(":latest") == Some(model)
+}
+
+/// A model name as it is announced, without Ollama's default tag.
+fn spoken_model_name(model: &str) -> &str {
+    model.strip_suffix(":latest").unwrap_or(model)
+}
+
+/// Instructions for summing up an answer that is too long to speak.
+const SUMMARY_PROMPT: &str = "Your answer will be read aloud and is too long to listen to. \
+Sum it up in one or two short sentences, keeping what the question asked for. Reply with the \
+summary only, in plain sentences without Markdown.";
+
+/// Instructions for summing up earlier turns from the session transcripts.
+const HISTORY_PROMPT: &str = "The user asks what they asked you earlier. Below are their \
+earlier commands and your replies, oldest first. Answer the question from them in one to three \
+short sentences, speaking to the user as \"you\". Reply in plain sentences without Markdown.";
+
+/// Most earlier turns given to the model for a question about them.
+const HISTORY_TURNS: usize = 20;
+
+/// Reply when the language model does not answer in time.
+const TIMEOUT_REPLY: &str = "The request to the language model timed out. Please try again.";
+
+/// Reply when the model keeps writing tool calls that cannot be read.
+const TOOL_CALL_REPLY: &str = "Sorry, I couldn't work out how to do that.";
+
+/// Reply when nothing is left of the model's answer after cleaning.
+const EMPTY_REPLY: &str = "I didn't catch that. Could you repeat your command?";
+
+/// How far the agent loop has got with a command.
+#[derive(Debug, Clone)]
+struct Progress {
+    /// The chat so far.
+    messages: Vec<Message>,
+    /// Tool calls made.
+    step: usize,
+    /// Malformed tool calls sent back to the model for correction.
+    json_retries: usize,
+    /// Output of the last tool that ran.
+    last_output: Option<String>,
+    /// Whether some tool output tried to instruct the model; later tool
+    /// calls then need confirmation.
+    tainted: bool,
+    /// How the tool calls are handled.
+    mode: Mode,
+}
+
+/// What happens to the tool calls of a command.
+#[derive(Debug, Clone, Copy, Default)]
+struct Mode {
+    /// They are described instead of run, see [`crate::tools::dry_run`].
+    dry_run: bool,
+    /// They run as a job, see [`crate::jobs`].
+    background: bool,
+}
+
+/// Tool calls waiting for the user's confirmation.
+#[derive(Debug, Clone)]
+struct PendingAction {
+    request: String,
+    progress: Progress,
+    /// The model's message with the calls.
+    asked: Message,
+    order: CallOrder,
+}
+
+/// What a wake word keeps while another one is in use.
+#[derive(Debug)]
+struct ScopeState {
+    history: Conversation,
+    memory: Memory,
+    /// The name of the active persona.
+    persona: String,
+    model: Option<String>,
+}
+
+/// The working directory and persona from before a project was opened.
+#[derive(Debug)]
+struct BeforeProject {
+    working_directory: Option<String>,
+    persona: String,
+}
+
+/// The wake word in use, `None` for the main one, and the state of the
+/// others.
+#[derive(Debug, Default)]
+struct Scopes {
+    active: Option<String>,
+    stashed: HashMap<Option<String>, ScopeState>,
+}
+
+/// A command the model asked a question about.
+#[derive(Debug, Clone)]
+struct Clarification {
+    request: String,
+    question: String,
+    /// How the tool calls of the command are handled.
+    mode: Mode,
+}
+
+impl Clarification {
+    /// The command with the question and the user's `answer` attached.
+    fn resume(self, answer: &str) -> String {
+        format!(
+            "{}\n(You asked: \"{}\" The user answered: \"{}\")",
+            self.request,
+            self.question,
+            answer.trim()
+        )
+    }
+}
+
+/// The reply to a command and everything that went into it, for clients
+/// that need more than the final sentence.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AgentResponse {
+    /// The reply, as spoken.
+    pub spoken_text: String,
+    /// The model's last output as it came back, `<think>` blocks and all;
+    /// empty for commands answered without the model.
+    pub raw_text: String,
+    /// The tool calls made, in order.
+    pub tool_calls: Vec<ToolCall>,
+    /// Output of each tool call, in order.
+    pub tool_results: Vec<String>,
+    pub timings: Timings,
+    /// Whether `spoken_text` was sent sentence by sentence while it was
+    /// generated (see [`Agent::handle_command_streaming`]). If not, the
+    /// caller still has to speak it.
+    pub streamed: bool,
+    /// Whether the command was aborted with [`Agent::cancel`] before it
+    /// was answered. `spoken_text` is empty then.
+    pub cancelled: bool,
+}
+
+/// Where the time of a command went, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Timings {
+    /// From receiving the command to the reply.
+    pub total_ms: u64,
+    /// Waiting for the model.
+    pub model_ms: u64,
+    /// Running tools.
+    pub tools_ms: u64,
+}
+
+impl AgentResponse {
+    fn new(reply: StreamedReply, turn: &TurnRecord) -> Self {
+        let steps = &turn.steps;
+        Self {
+            spoken_text: reply.text,
+            raw_text: steps
+                .last()
+                .map(|step| step.raw.clone())
+                .unwrap_or_default(),
+            tool_calls: steps
+                .iter()
+                .flat_map(|step| step.tool_calls.clone())
+                .collect(),
+            tool_results: steps
+                .iter()
+                .flat_map(|step| step.tool_results.clone())
+                .collect(),
+            timings: Timings {
+                total_ms: turn.total_ms,
+                model_ms: steps.iter().map(|step| step.model_ms).sum(),
+                tools_ms: steps.iter().map(|step| step.tools_ms).sum(),
+            },
+            streamed: reply.streamed,
+            cancelled: reply.cancelled,
+        }
+    }
+}
+
+/// A reply as the agent loop produces it.
+#[derive(Debug, Clone)]
+struct StreamedReply {
+    text: String,
+    /// Whether `text` was sent sentence by sentence while it was
+    /// generated.
+    streamed: bool,
+    cancelled: bool,
+}
+
+impl StreamedReply {
+    fn unspoken(text: String) -> Self {
+        Self {
+            text,
+            streamed: false,
+            cancelled: false,
+        }
+    }
+
+    fn cancelled() -> Self {
+        Self {
+            cancelled: true,
+            ..Self::unspoken(String::new())
+        }
+    }
+}
+
+/// Whether `error` looks like a passing network problem worth retrying,
+/// such as a server that is still starting.
+fn is_transient(error: &anyhow::Error) -> bool {
+    const MARKERS: &[&str] = &[
+        "connection refused",
+        "connection reset",
+        "connection closed",
+        "error sending request",
+        "broken pipe",
+        "502 bad gateway",
+        "503 service unavailable",
+    ];
+    let message = format!("{error:#}").to_lowercase();
+    MARKERS.iter().any(|marker| message.contains(marker))
+}
+
+/// Turns streamed text into sentences, holding back what should not be
+/// spoken (see [`speakable_prefix`]).
+struct SentenceStream {
+    sentences: mpsc::UnboundedSender<String>,
+    splitter: SentenceSplitter,
+    /// Raw text received so far.
+    raw: String,
+    /// Length of the speakable text already given to the splitter.
+    fed: usize,
+    /// Whether any sentence has been sent.
+    sent: bool,
+    /// Said once when the model starts reasoning; see [`ThinkMode::status`].
+    think_status: Option<String>,
+    /// Applies the replacement rules to each sentence.
+    post: Arc<PostProcessor>,
+}
+
+impl SentenceStream {
+    fn new(
+        sentences: mpsc::UnboundedSender<String>,
+        think_status: Option<String>,
+        post: Arc<PostProcessor>,
+    ) -> Self {
+        Self {
+            sentences,
+            splitter: SentenceSplitter::new(),
+            raw: String::new(),
+            fed: 0,
+            sent: false,
+            think_status,
+            post,
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.raw.push_str(text);
+        if think::is_thinking(&self.raw) {
+            if let Some(status) = self.think_status.take() {
+                self.send(status);
+            }
+        }
+        let speakable = speakable_prefix(&self.raw);
+        let Some(new) = speakable.get(self.fed..) else {
+            return;
+        };
+        self.fed = speakable.len();
+        for sentence in self.splitter.push(new) {
+            self.send_answer(&sentence);
+        }
+    }
+
+    /// Send the last sentence and return whether anything was sent.
+    fn finish(mut self) -> bool {
+        if let Some(rest) = self.splitter.finish() {
+            self.send_answer(&rest);
+        }
+        self.sent
+    }
+
+    /// Send a sentence of the answer after the replacement rules, unless
+    /// they leave nothing of it.
+    fn send_answer(&mut self, sentence: &str) {
+        let sentence = redact(&self.post.clean_sentence(sentence)).into_owned();
+        if !sentence.is_empty() {
+            self.send(sentence);
+        }
+    }
+
+    /// Whether any text has been received.
+    fn started(&self) -> bool {
+        !self.raw.is_empty()
+    }
+
+    fn send(&mut self, sentence: String) {
+        self.sent |= self.sentences.send(sentence).is_ok();
+    }
+}
+
+/// The part of a partial reply that can already be spoken: `<think>`
+/// blocks, code blocks and backticks are removed, and anything that may
+/// turn out to be the start of one is held back, as is a reply that
+/// starts with JSON and a tool call after some text.
+fn speakable_prefix(raw: &str) -> String {
+    let mut text = raw.to_string();
+    while let Some(start) = text.find("<think>") {
+        match text[start..].find("</think>") {
+            Some(end) => text.replace_range(start..start + end + "</think>".len(), ""),
+            None => text.truncate(start),
+        }
+    }
+    if let Some(pos) = text.rfind('<') {
+        if "<think>".starts_with(&text[pos..]) {
+            text.truncate(pos);
+        }
+    }
+    let pieces: Vec<&str> = text.split("```").collect();
+    // An even number of pieces means the last code block is still open.
+    let outside = if pieces.len() % 2 == 0 {
+        &pieces[..pieces.len() - 1]
+    } else {
+        &pieces[..]
+    };
+    let mut text: String = outside.iter().step_by(2).copied().collect();
+    // An answer that starts like JSON may be a tool call written as text.
+    if text.trim_start().starts_with(['{', '[']) {
+        return String::new();
+    }
+    if let Some(start) = llm::tool_call_start(&text) {
+        text.truncate(start);
     }
+    text.trim_end_matches('`').replace('`', "")
 }
@@ -0,0 +1,394 @@
+//! Audio playback through [`cpal`] on a selectable output device.
+//!
+//! Most TTS engines play audio themselves on the system default device.
+//! When that device is wrong (for example an HDMI monitor without
+//! speakers), speech can be routed elsewhere:
+//!
+//!  * `SPEAKER_INDEX` (optional): index of the device in the enumeration
+//!    of output devices.
+//!  * `SPEAKER_NAME_KEYWORD` (optional): the first output device whose
+//!    name contains the keyword (case insensitive).
+//!
+//! This mirrors `MIC_INDEX`/`MIC_NAME_KEYWORD` in [`crate::speech`]. With
+//! either variable set, the TTS engine synthesises audio into memory (see
+//! [`Speaker::synthesize`](crate::tts_engine::Speaker::synthesize)) and
+//! Jarvis plays it on the selected device.
+
+use std::collections::VecDeque;
+use std::env;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+
+/// 16-bit PCM audio held in memory.
+#[derive(Debug, Clone, Default)]
+pub struct Audio {
+    /// Interleaved samples.
+    pub samples: Vec<i16>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl Audio {
+    /// Mono audio from raw little-endian 16-bit PCM, as produced by Piper's
+    /// `--output-raw`.
+    pub fn from_raw_mono(bytes: &[u8], sample_rate: u32) -> Self {
+        Self {
+            samples: bytes
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+            sample_rate,
+            channels: 1,
+        }
+    }
+
+    /// Parse a 16-bit PCM WAV file.
+    pub fn from_wav(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            bail!("not a WAV file");
+        }
+        let mut format: Option<(u16, u32)> = None;
+        let mut pos = 12;
+        while pos + 8 <= bytes.len() {
+            let id = &bytes[pos..pos + 4];
+            let size = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            let body = pos + 8;
+            // Streaming writers may leave the size unset; take what is there.
+            let end = body.saturating_add(size).min(bytes.len());
+            match id {
+                b"fmt " if end - body >= 16 => {
+                    let tag = u16::from_le_bytes([bytes[body], bytes[body + 1]]);
+                    let bits = u16::from_le_bytes([bytes[body + 14], bytes[body + 15]]);
+                    if tag != 1 || bits != 16 {
+                        bail!("unsupported WAV encoding (format {tag}, {bits} bits); expected 16-bit PCM");
+                    }
+                    let channels = u16::from_le_bytes([bytes[body + 2], bytes[body + 3]]);
+                    let rate = u32::from_le_bytes(bytes[body + 4..body + 8].try_into().unwrap());
+                    format = Some((channels, rate));
+                }
+                b"data" => {
+                    let (channels, sample_rate) =
+                        format.ok_or_else(|| anyhow!("WAV data before format chunk"))?;
+                    let mut audio = Self::from_raw_mono(&bytes[body..end], sample_rate);
+                    audio.channels = channels;
+                    return Ok(audio);
+                }
+                _ => {}
+            }
+            // Chunks are padded to an even length.
+            pos = end + (size & 1);
+        }
+        bail!("WAV file has no audio data")
+    }
+
+    /// Encode as a 16-bit PCM WAV file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_len = (self.samples.len() * 2) as u32;
+        let block_align = self.channels * 2;
+        let mut out = Vec::with_capacity(44 + data_len as usize);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&self.channels.to_le_bytes());
+        out.extend_from_slice(&self.sample_rate.to_le_bytes());
+        out.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        out.extend_from_slice(&block_align.to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for sample in &self.samples {
+            out.extend_from_slice(&sample.to_le_bytes());
+        }
+        out
+    }
+
+    /// Write the audio to `path`. WAV files are written directly; any
+    /// other extension (`.ogg`, `.mp3`, ...) is encoded with `ffmpeg`,
+    /// which must be installed.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let is_wav = match path.extension() {
+            Some(ext) => ext.eq_ignore_ascii_case("wav"),
+            None => true,
+        };
+        if is_wav {
+            return std::fs::write(path, self.to_wav())
+                .with_context(|| format!("failed to write {}", path.display()));
+        }
+        let mut ffmpeg = std::process::Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-f", "wav", "-i", "-"])
+            .arg(path)
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .context("failed to run ffmpeg; install it or save as .wav")?;
+        if let Some(mut stdin) = ffmpeg.stdin.take() {
+            stdin
+                .write_all(&self.to_wav())
+                .context("failed to write audio to ffmpeg")?;
+        }
+        let status = ffmpeg.wait().context("failed to wait for ffmpeg")?;
+        if !status.success() {
+            bail!("ffmpeg could not encode {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Convert to interleaved `f32` samples at `rate` with `channels`
+    /// channels. The input is mixed down to mono and linearly resampled,
+    /// which is plenty for speech.
+    fn for_device(&self, rate: u32, channels: usize) -> Vec<f32> {
+        let source_channels = self.channels.max(1) as usize;
+        let mono: Vec<f32> = self
+            .samples
+            .chunks(source_channels)
+            .map(|frame| {
+                frame.iter().map(|&s| s as f32 / 32768.0).sum::<f32>() / frame.len() as f32
+            })
+            .collect();
+        if mono.is_empty() {
+            return Vec::new();
+        }
+        let step = self.sample_rate as f64 / rate as f64;
+        let frames = (mono.len() as f64 / step) as usize;
+        let mut out = Vec::with_capacity(frames * channels);
+        for i in 0..frames {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            let a = mono[index.min(mono.len() - 1)];
+            let b = mono[(index + 1).min(mono.len() - 1)];
+            let sample = a + (b - a) * fraction;
+            out.extend(std::iter::repeat(sample).take(channels));
+        }
+        out
+    }
+}
+
+/// An output device to play synthesised speech on.
+#[derive(Clone)]
+pub struct AudioOutput {
+    device: cpal::Device,
+}
+
+impl AudioOutput {
+    /// The device selected by `SPEAKER_INDEX` or `SPEAKER_NAME_KEYWORD`, or
+    /// `None` when neither is set so that engines keep using their own
+    /// playback. Fails if a device was requested but cannot be found.
+    pub fn from_env() -> Result<Option<Self>> {
+        let index = env::var("SPEAKER_INDEX")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok());
+        let keyword = env::var("SPEAKER_NAME_KEYWORD")
+            .ok()
+            .filter(|k| !k.trim().is_empty());
+        if index.is_none() && keyword.is_none() {
+            return Ok(None);
+        }
+
+        let host = cpal::default_host();
+        let devices: Vec<cpal::Device> = host
+            .output_devices()
+            .context("Failed to enumerate output audio devices")?
+            .collect();
+        let mut selected = index.and_then(|i| devices.get(i).cloned());
+        if selected.is_none() {
+            if let Some(keyword) = &keyword {
+                let keyword = keyword.to_lowercase();
+                selected = devices
+                    .iter()
+                    .find(|d| d.name().is_ok_and(|n| n.to_lowercase().contains(&keyword)))
+                    .cloned();
+            }
+        }
+        let Some(device) = selected else {
+            let names: Vec<String> = devices.iter().filter_map(|d| d.name().ok()).collect();
+            bail!(
+                "no output device matches SPEAKER_INDEX/SPEAKER_NAME_KEYWORD; available devices: {}",
+                names.join(", ")
+            );
+        };
+        if let Ok(name) = device.name() {
+            log::info!("Using speaker: {}", name);
+        }
+        Ok(Some(Self { device }))
+    }
+
+    /// The system default output device.
+    pub fn default_device() -> Result<Self> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| anyhow!("No output audio device found"))?;
+        Ok(Self { device })
+    }
+
+    /// The name the system gives the device.
+    pub fn name(&self) -> String {
+        self.device
+            .name()
+            .unwrap_or_else(|_| "unnamed device".to_string())
+    }
+
+    /// Play `audio` on a blocking thread, returning when playback has
+    /// finished or `stop` has been set.
+    pub async fn play(&self, audio: Audio, stop: Arc<AtomicBool>) -> Result<()> {
+        let device = self.device.clone();
+        tokio::task::spawn_blocking(move || play_blocking(&device, &audio, &stop))
+            .await
+            .context("failed to join audio playback task")?
+    }
+
+    /// Play mono 16-bit chunks at `sample_rate` as they arrive on `chunks`,
+    /// for engines that stream their audio. Returns once the sender has
+    /// been dropped and everything received has been played, or when
+    /// `stop` has been set.
+    pub async fn play_stream(
+        &self,
+        sample_rate: u32,
+        chunks: Receiver<Vec<i16>>,
+        stop: Arc<AtomicBool>,
+    ) -> Result<()> {
+        let device = self.device.clone();
+        tokio::task::spawn_blocking(move || {
+            play_stream_blocking(&device, sample_rate, &chunks, &stop)
+        })
+        .await
+        .context("failed to join audio playback task")?
+    }
+}
+
+fn play_blocking(device: &cpal::Device, audio: &Audio, stop: &AtomicBool) -> Result<()> {
+    let config = device
+        .default_output_config()
+        .context("Failed to get default output configuration")?;
+    let samples = Arc::new(audio.for_device(config.sample_rate().0, config.channels() as usize));
+    let position = Arc::new(AtomicUsize::new(0));
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(device, &config.into(), &samples, &position)?,
+        SampleFormat::I16 => build_stream::<i16>(device, &config.into(), &samples, &position)?,
+        SampleFormat::U16 => build_stream::<u16>(device, &config.into(), &samples, &position)?,
+        other => bail!("Unsupported sample format: {:?}", other),
+    };
+    stream
+        .play()
+        .context("Failed to start audio output stream")?;
+    while position.load(Ordering::SeqCst) < samples.len() {
+        if stop.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // Give the device time to play out its last buffer.
+    std::thread::sleep(Duration::from_millis(150));
+    Ok(())
+}
+
+fn play_stream_blocking(
+    device: &cpal::Device,
+    sample_rate: u32,
+    chunks: &Receiver<Vec<i16>>,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let config = device
+        .default_output_config()
+        .context("Failed to get default output configuration")?;
+    let (rate, channels) = (config.sample_rate().0, config.channels() as usize);
+    let buffer: Arc<Mutex<VecDeque<f32>>> = Arc::default();
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => build_queue_stream::<f32>(device, &config.into(), &buffer)?,
+        SampleFormat::I16 => build_queue_stream::<i16>(device, &config.into(), &buffer)?,
+        SampleFormat::U16 => build_queue_stream::<u16>(device, &config.into(), &buffer)?,
+        other => bail!("Unsupported sample format: {:?}", other),
+    };
+    let mut started = false;
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        match chunks.recv_timeout(Duration::from_millis(20)) {
+            Ok(samples) => {
+                let audio = Audio {
+                    samples,
+                    sample_rate,
+                    channels: 1,
+                };
+                buffer
+                    .lock()
+                    .unwrap()
+                    .extend(audio.for_device(rate, channels));
+                if !started {
+                    stream
+                        .play()
+                        .context("Failed to start audio output stream")?;
+                    started = true;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    while !buffer.lock().unwrap().is_empty() {
+        if stop.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    if started {
+        // Give the device time to play out its last buffer.
+        std::thread::sleep(Duration::from_millis(150));
+    }
+    Ok(())
+}
+
+/// An output stream that plays samples from `buffer`, and silence whenever
+/// it runs dry.
+fn build_queue_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    buffer: &Arc<Mutex<VecDeque<f32>>>,
+) -> Result<cpal::Stream> {
+    let buffer = buffer.clone();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let mut buffer = buffer.lock().unwrap();
+            for out in data.iter_mut() {
+                *out = T::from_sample(buffer.pop_front().unwrap_or(0.0));
+            }
+        },
+        |err| log::error!("An error occurred on the output audio stream: {}", err),
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples: &Arc<Vec<f32>>,
+    position: &Arc<AtomicUsize>,
+) -> Result<cpal::Stream> {
+    let samples = samples.clone();
+    let position = position.clone();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let start = position.fetch_add(data.len(), Ordering::SeqCst);
+            for (i, out) in data.iter_mut().enumerate() {
+                let sample = samples.get(start + i).copied().unwrap_or(0.0);
+                *out = T::from_sample(sample);
+            }
+        },
+        |err| log::error!("An error occurred on the output audio stream: {}", err),
+        None,
+    )?;
+    Ok(stream)
+}
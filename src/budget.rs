@@ -0,0 +1,99 @@
+//! Keeping the prompt within the model's context window.
+//!
+//! Ollama silently cuts a prompt that is longer than the context window
+//! from the front, so an overlong conversation loses the system prompt
+//! first and the answers quietly get worse. Before every command the
+//! system prompt, the remembered facts and document passages, the tool
+//! schemas and the command are estimated at about four characters per
+//! token; the history gets what is left of the window after room for the
+//! reply, and older turns are reduced to notes or left out to fit (see
+//! [`Conversation::within`](crate::conversation::Conversation::within)).
+//!
+//! The window is `LLM_NUM_CTX` (or the persona's `num_ctx`), default
+//! `4096`; the room kept for the reply is `LLM_MAX_TOKENS`, default `512`.
+
+use std::fmt;
+
+use crate::conversation::estimate_tokens;
+use crate::llm::{GenerationOptions, ToolSpec};
+
+/// Context window assumed when `num_ctx` is not set.
+const DEFAULT_WINDOW: usize = 4096;
+
+/// Tokens kept free for the reply when `max_tokens` is not set.
+const DEFAULT_RESERVE: usize = 512;
+
+/// The context window and the part of it kept for the reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextBudget {
+    pub window: usize,
+    pub reserve: usize,
+}
+
+impl ContextBudget {
+    /// The budget for requests made with `options`.
+    pub fn new(options: &GenerationOptions) -> Self {
+        Self {
+            window: options.num_ctx.map_or(DEFAULT_WINDOW, |n| n as usize),
+            reserve: options.max_tokens.map_or(DEFAULT_RESERVE, |n| n as usize),
+        }
+    }
+
+    /// Tokens left for the history once `usage` is in the prompt.
+    pub fn history_room(&self, usage: &TokenUsage) -> usize {
+        self.window
+            .saturating_sub(self.reserve)
+            .saturating_sub(usage.fixed())
+    }
+}
+
+/// Estimated tokens of each part of a prompt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    /// The persona, instructions and environment.
+    pub system: usize,
+    /// Remembered facts and document passages.
+    pub memories: usize,
+    /// The tool schemas sent through the API.
+    pub tools: usize,
+    pub history: usize,
+    pub command: usize,
+}
+
+impl TokenUsage {
+    /// Everything but the history.
+    fn fixed(&self) -> usize {
+        self.system + self.memories + self.tools + self.command
+    }
+
+    pub fn total(&self) -> usize {
+        self.fixed() + self.history
+    }
+}
+
+impl fmt::Display for TokenUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "~{} tokens (system {}, memories {}, tools {}, history {}, command {})",
+            self.total(),
+            self.system,
+            self.memories,
+            self.tools,
+            self.history,
+            self.command
+        )
+    }
+}
+
+/// Estimated tokens of the tool schemas as the backend sends them.
+pub fn tool_tokens(tools: &[ToolSpec]) -> usize {
+    tools
+        .iter()
+        .map(|tool| {
+            estimate_tokens(&tool.name)
+                + estimate_tokens(&tool.description)
+                + estimate_tokens(&tool.parameters.to_string())
+        })
+        .sum()
+}
@@ -0,0 +1,161 @@
+//! Command line parsing for the `jarvis` binary.
+//!
+//! Running the binary without arguments starts the voice assistant. A few
+//! subcommands provide diagnostics and utilities that do not need the
+//! microphone or the language model.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+pub const USAGE: &str = "\
+Usage: jarvis [COMMAND]
+
+Commands:
+  (none)       Start the voice assistant
+  --text       Read commands from stdin instead of the microphone
+               (add --speak to also speak the replies, --json to print each
+               reply as a JSON line with its tool calls and timings)
+  tune-wake    Summarise wake word detection accuracy from ~/.jarvis/wake_log.jsonl
+  voices       List the voices of the configured TTS engine (VOICE_ENGINE)
+  say TEXT     Speak TEXT with the configured voice
+               (add --out FILE to save it as .wav, or .ogg/.mp3 via ffmpeg)
+  speak-test   Speak a sample phrase with the configured TTS engine and report
+               whether it works (add --all to try every engine)
+  index PATH   Index the text files under PATH so that Jarvis can answer
+               questions about them (several paths may be given)
+  sessions list
+               List the recorded conversation transcripts
+  sessions export ID [--format md|jsonl]
+               Print a transcript (ID `last` for the latest) for review
+  stats        Show how often each tool and model was used, failed and how
+               long it took (add --json to print them as JSON)
+  help         Show this message";
+
+/// The action selected on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Start the voice assistant.
+    Run,
+    /// Headless REPL reading commands from stdin.
+    Text { speak: bool, json: bool },
+    /// Print the wake word tuning report.
+    TuneWake,
+    /// List the voices of the configured TTS engine.
+    Voices,
+    /// Speak some text, or save it to a file.
+    Say { text: String, out: Option<PathBuf> },
+    /// Check that the TTS engines can speak.
+    SpeakTest { all: bool },
+    /// Index documents for retrieval.
+    Index { paths: Vec<PathBuf> },
+    /// List the session transcripts.
+    Sessions,
+    /// Print a session transcript.
+    ExportSession { id: String, format: ExportFormat },
+    /// Print the tool and model usage report.
+    Stats { json: bool },
+    /// Print usage information.
+    Help,
+}
+
+/// How `sessions export` prints a transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Jsonl,
+}
+
+impl Command {
+    /// Parse the process arguments (excluding the program name).
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let args: Vec<String> = args.into_iter().collect();
+        match args.first().map(String::as_str) {
+            None => Ok(Command::Run),
+            Some("--text") => {
+                let (mut speak, mut json) = (false, false);
+                for arg in &args[1..] {
+                    match arg.as_str() {
+                        "--speak" => speak = true,
+                        "--json" => json = true,
+                        other => bail!("unknown option '{other}' for --text\n\n{USAGE}"),
+                    }
+                }
+                Ok(Command::Text { speak, json })
+            }
+            Some("tune-wake") => Ok(Command::TuneWake),
+            Some("voices") => Ok(Command::Voices),
+            Some("say") => {
+                let mut out = None;
+                let mut words = Vec::new();
+                let mut rest = args[1..].iter();
+                while let Some(arg) = rest.next() {
+                    match arg.as_str() {
+                        "--out" | "-o" => match rest.next() {
+                            Some(path) => out = Some(PathBuf::from(path)),
+                            None => bail!("--out needs a file name\n\n{USAGE}"),
+                        },
+                        _ => words.push(arg.as_str()),
+                    }
+                }
+                if words.is_empty() {
+                    bail!("nothing to say\n\n{USAGE}");
+                }
+                Ok(Command::Say {
+                    text: words.join(" "),
+                    out,
+                })
+            }
+            Some("speak-test") => {
+                let all = match args.get(1).map(String::as_str) {
+                    None => false,
+                    Some("--all") => true,
+                    Some(other) => bail!("unknown option '{other}' for speak-test\n\n{USAGE}"),
+                };
+                Ok(Command::SpeakTest { all })
+            }
+            Some("index") => {
+                if args.len() < 2 {
+                    bail!("index needs a file or folder\n\n{USAGE}");
+                }
+                Ok(Command::Index {
+                    paths: args[1..].iter().map(PathBuf::from).collect(),
+                })
+            }
+            Some("sessions") => match args.get(1).map(String::as_str) {
+                None | Some("list") => Ok(Command::Sessions),
+                Some("export") => {
+                    let mut id = None;
+                    let mut format = ExportFormat::Markdown;
+                    let mut rest = args[2..].iter();
+                    while let Some(arg) = rest.next() {
+                        match arg.as_str() {
+                            "--format" | "-f" => {
+                                format = match rest.next().map(String::as_str) {
+                                    Some("md" | "markdown") => ExportFormat::Markdown,
+                                    Some("jsonl" | "json") => ExportFormat::Jsonl,
+                                    Some(other) => bail!("unknown format '{other}'\n\n{USAGE}"),
+                                    None => bail!("--format needs md or jsonl\n\n{USAGE}"),
+                                }
+                            }
+                            _ if id.is_none() => id = Some(arg.clone()),
+                            other => bail!("unexpected argument '{other}'\n\n{USAGE}"),
+                        }
+                    }
+                    match id {
+                        Some(id) => Ok(Command::ExportSession { id, format }),
+                        None => bail!("sessions export needs a session id\n\n{USAGE}"),
+                    }
+                }
+                Some(other) => bail!("unknown sessions command '{other}'\n\n{USAGE}"),
+            },
+            Some("stats") => match args.get(1).map(String::as_str) {
+                None => Ok(Command::Stats { json: false }),
+                Some("--json") => Ok(Command::Stats { json: true }),
+                Some(other) => bail!("unknown option '{other}' for stats\n\n{USAGE}"),
+            },
+            Some("help" | "-h" | "--help") => Ok(Command::Help),
+            Some(other) => bail!("unknown command '{other}'\n\n{USAGE}"),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Helpers for reading Jarvis configuration from the environment.
+//!
+//! Jarvis is configured entirely through environment variables (optionally
+//! loaded from a `.env` file at startup). These helpers keep the parsing
+//! rules consistent across modules: flags accept `1`/`true`/`yes`/`on`,
+//! lists are comma separated, and unparsable values fall back to the
+//! supplied default.
+
+use std::env;
+use std::str::FromStr;
+
+/// Read a boolean flag. Missing or unrecognised values are `false`.
+pub fn env_flag(name: &str) -> bool {
+    env::var(name)
+        .map(|v| {
+            matches!(
+                v.trim().to_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// Read a boolean flag that defaults to `default` when unset. Set values
+/// follow the same rules as [`env_flag`].
+pub fn env_flag_or(name: &str, default: bool) -> bool {
+    if env::var_os(name).is_none() {
+        return default;
+    }
+    env_flag(name)
+}
+
+/// Parse a value of type `T`, falling back to `default` when the variable
+/// is missing or cannot be parsed.
+pub fn env_parse<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse::<T>().ok())
+        .unwrap_or(default)
+}
+
+/// Read a comma separated list. Entries are trimmed and empty entries are
+/// dropped. When the variable is unset `default` is returned; setting it
+/// to an empty string yields an empty list.
+pub fn env_list(name: &str, default: &[&str]) -> Vec<String> {
+    match env::var(name) {
+        Ok(v) => v
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        Err(_) => default.iter().map(|s| s.to_string()).collect(),
+    }
+}
@@ -0,0 +1,214 @@
+//! Short-term memory of the current conversation.
+//!
+//! Every exchange is kept in a rolling window so that follow-up questions
+//! ("what about yesterday?") reach the model with the turns they refer to.
+//! The oldest turns are dropped once the window exceeds
+//! `HISTORY_MAX_TOKENS` (default `1000`, `0` disables the history). The
+//! history is cleared when Jarvis returns to idle or when the user says
+//! "forget that".
+//!
+//! Dropped turns are not forgotten outright: each leaves a one-line note
+//! ("User: … / Jarvis: …"), and the notes, up to a quarter of the window,
+//! are sent in place of the turns. When the prompt would not fit the
+//! model's context window, [`Conversation::within`] sends fewer turns
+//! and notes; see [`crate::budget`].
+
+use std::collections::VecDeque;
+
+use crate::config::env_parse;
+use crate::llm::Message;
+
+/// Phrases that clear the conversation.
+const FORGET_PHRASES: &[&str] = &[
+    "forget that",
+    "forget it",
+    "forget everything",
+    "forget our conversation",
+    "start over",
+    "new conversation",
+];
+
+/// One exchange between the user and Jarvis.
+#[derive(Debug, Clone)]
+struct Turn {
+    user: String,
+    assistant: String,
+}
+
+impl Turn {
+    fn tokens(&self) -> usize {
+        estimate_tokens(&self.user) + estimate_tokens(&self.assistant)
+    }
+
+    /// A one-line reminder of the exchange.
+    fn note(&self) -> String {
+        format!(
+            "User: {} / Jarvis: {}",
+            shorten(&self.user, NOTE_CHARS),
+            shorten(&self.assistant, NOTE_CHARS)
+        )
+    }
+}
+
+/// Characters kept of each side of a turn in its note.
+const NOTE_CHARS: usize = 100;
+
+/// `text` on one line, cut to `max` characters.
+fn shorten(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max {
+        return text;
+    }
+    let cut: String = text.chars().take(max).collect();
+    format!("{}…", cut.trim_end())
+}
+
+fn notes_tokens<'a>(notes: impl IntoIterator<Item = &'a String>) -> usize {
+    notes
+        .into_iter()
+        .map(|note| estimate_tokens(note) + 1)
+        .sum()
+}
+
+/// Rough token count of `text`; about four characters per token for
+/// English.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// The turns of the current conversation, oldest first.
+#[derive(Debug, Default)]
+pub struct Conversation {
+    turns: VecDeque<Turn>,
+    /// Notes on the turns that no longer fit, oldest first.
+    earlier: VecDeque<String>,
+    max_tokens: usize,
+}
+
+impl Conversation {
+    /// A history of at most `max_tokens` estimated tokens; `0` keeps no
+    /// history.
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            turns: VecDeque::new(),
+            earlier: VecDeque::new(),
+            max_tokens,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(env_parse("HISTORY_MAX_TOKENS", 1000))
+    }
+
+    /// Remember an exchange. The oldest turns that no longer fit are
+    /// reduced to notes.
+    pub fn record(&mut self, user: &str, assistant: &str) {
+        if self.max_tokens == 0 {
+            return;
+        }
+        self.turns.push_back(Turn {
+            user: user.trim().to_string(),
+            assistant: assistant.trim().to_string(),
+        });
+        let mut tokens: usize = self.turns.iter().map(Turn::tokens).sum();
+        while tokens > self.max_tokens {
+            let Some(oldest) = self.turns.pop_front() else {
+                break;
+            };
+            tokens -= oldest.tokens();
+            self.earlier.push_back(oldest.note());
+        }
+        while notes_tokens(&self.earlier) > self.max_tokens / 4 {
+            self.earlier.pop_front();
+        }
+    }
+
+    /// Forget the whole conversation.
+    pub fn clear(&mut self) {
+        self.turns.clear();
+        self.earlier.clear();
+    }
+
+    /// Estimated tokens of the turns and notes.
+    pub fn tokens(&self) -> usize {
+        self.turns.iter().map(Turn::tokens).sum::<usize>() + notes_tokens(&self.earlier)
+    }
+
+    /// The history as chat messages, a user and an assistant message per
+    /// turn.
+    pub fn messages(&self) -> Vec<Message> {
+        self.turns
+            .iter()
+            .flat_map(|turn| {
+                [
+                    Message::user(&turn.user),
+                    Message::assistant(&turn.assistant),
+                ]
+            })
+            .collect()
+    }
+
+    /// The history in at most `budget` estimated tokens: the notes on
+    /// earlier turns as a prompt section (empty if there are none), and
+    /// the latest turns as chat messages. Turns that do not fit are sent
+    /// as notes instead; the oldest notes are left out first.
+    pub fn within(&self, budget: usize) -> (String, Vec<Message>) {
+        let mut used = 0;
+        let kept = self
+            .turns
+            .iter()
+            .rev()
+            .take_while(|turn| {
+                used += turn.tokens();
+                used <= budget
+            })
+            .count();
+        let skip = self.turns.len() - kept;
+        let room = budget
+            - self
+                .turns
+                .iter()
+                .skip(skip)
+                .map(Turn::tokens)
+                .sum::<usize>();
+        let notes: Vec<String> = self
+            .earlier
+            .iter()
+            .cloned()
+            .chain(self.turns.iter().take(skip).map(Turn::note))
+            .collect();
+        let mut first = 0;
+        while first < notes.len() && notes_tokens(&notes[first..]) > room {
+            first += 1;
+        }
+        let mut section = String::new();
+        if first < notes.len() {
+            section.push_str("\nEarlier in this conversation:\n");
+            for note in &notes[first..] {
+                section.push_str(&format!("- {note}\n"));
+            }
+        }
+        let messages = self
+            .turns
+            .iter()
+            .skip(skip)
+            .flat_map(|turn| {
+                [
+                    Message::user(&turn.user),
+                    Message::assistant(&turn.assistant),
+                ]
+            })
+            .collect();
+        (section, messages)
+    }
+
+    /// Whether `command` asks to forget the conversation.
+    pub fn is_forget_command(command: &str) -> bool {
+        let command = command
+            .trim()
+            .trim_end_matches(|c: char| c.is_ascii_punctuation())
+            .to_lowercase();
+        let command = command.strip_prefix("please ").unwrap_or(&command);
+        FORGET_PHRASES.contains(&command)
+    }
+}
@@ -0,0 +1,319 @@
+//! Questions about local documents.
+//!
+//! `jarvis index ~/Documents/notes` reads the text files under a folder,
+//! cuts them into chunks of about `DOCS_CHUNK_CHARS` characters (default
+//! `1000`) along paragraph breaks and stores each chunk with its embedding
+//! in `~/.jarvis/documents.jsonl` (or the file named by `DOCS_INDEX`).
+//! Running it again only embeds the files that changed and drops those
+//! that are gone.
+//!
+//! For every command the agent embeds the question and adds the
+//! `DOCS_TOP_K` (default `3`) most similar chunks, with their file names,
+//! to the system prompt, so that "what did my meeting notes say about the
+//! deadline?" is answered from the notes. Chunks less similar than
+//! `DOCS_MIN_SCORE` (default `0.5`) are left out. The index is re-read
+//! when it changes, so a running assistant picks up a new `jarvis index`.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::env_parse;
+use crate::llm::LlmBackend;
+use crate::memory::cosine_similarity;
+
+/// File extensions read as text.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "md", "markdown", "txt", "text", "org", "rst", "adoc", "tex", "csv", "json", "toml", "yaml",
+    "yml", "html", "htm",
+];
+
+/// Files larger than this are skipped.
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Chunks embedded per request.
+const EMBED_BATCH: usize = 16;
+
+/// A piece of an indexed file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    /// The file the chunk comes from.
+    pub source: PathBuf,
+    /// Modification time of the file when it was indexed, in seconds since
+    /// the Unix epoch.
+    pub modified: u64,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// What `jarvis index` did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IndexReport {
+    /// Files embedded because they were new or changed.
+    pub indexed: usize,
+    /// Files whose chunks were kept as they were.
+    pub unchanged: usize,
+    /// Files no longer there whose chunks were dropped.
+    pub removed: usize,
+}
+
+/// The indexed chunks of the user's documents.
+#[derive(Debug, Default)]
+pub struct DocumentIndex {
+    path: Option<PathBuf>,
+    chunks: Vec<Chunk>,
+    /// Modification time of the index file when it was read.
+    loaded: Option<SystemTime>,
+    top_k: usize,
+    min_score: f32,
+}
+
+impl DocumentIndex {
+    /// The index stored in `path`; empty if there is none yet.
+    pub fn open(path: PathBuf) -> Self {
+        let mut index = Self {
+            path: Some(path),
+            top_k: env_parse("DOCS_TOP_K", 3),
+            min_score: env_parse("DOCS_MIN_SCORE", 0.5),
+            ..Self::default()
+        };
+        index.reload();
+        index
+    }
+
+    /// The index in `DOCS_INDEX`, default `~/.jarvis/documents.jsonl`.
+    pub fn from_env() -> Self {
+        let path = std::env::var("DOCS_INDEX")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("documents.jsonl")));
+        path.map(Self::open).unwrap_or_default()
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Read the index file again if it changed since it was last read.
+    pub fn refresh(&mut self) {
+        let modified = self
+            .path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).ok())
+            .and_then(|meta| meta.modified().ok());
+        if modified != self.loaded {
+            self.reload();
+        }
+    }
+
+    fn reload(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        self.loaded = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        self.chunks = match fs::read_to_string(path) {
+            Ok(data) => data
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(|line| match serde_json::from_str(line) {
+                    Ok(chunk) => Some(chunk),
+                    Err(e) => {
+                        log::warn!("Ignoring invalid chunk in {}: {e}", path.display());
+                        None
+                    }
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+    }
+
+    /// Index the text files under `root` (or the file `root` itself),
+    /// embedding them with `backend`, and save the index.
+    pub async fn index(&mut self, root: &Path, backend: &dyn LlmBackend) -> Result<IndexReport> {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("cannot read {}", root.display()))?;
+        let files = text_files(&root);
+        let chunk_chars = env_parse("DOCS_CHUNK_CHARS", 1000).max(100);
+        let mut report = IndexReport::default();
+
+        let mut gone: Vec<PathBuf> = self
+            .chunks
+            .iter()
+            .filter(|chunk| chunk.source.starts_with(&root) && !files.contains(&chunk.source))
+            .map(|chunk| chunk.source.clone())
+            .collect();
+        gone.dedup();
+        self.chunks.retain(|chunk| !gone.contains(&chunk.source));
+        report.removed = gone.len();
+
+        for file in files {
+            let modified = modified_secs(&file);
+            let known = self.chunks.iter().find(|chunk| chunk.source == file);
+            if known.is_some_and(|chunk| chunk.modified == modified) {
+                report.unchanged += 1;
+                continue;
+            }
+            let text = match fs::read_to_string(&file) {
+                Ok(text) => text,
+                Err(e) => {
+                    log::warn!("Skipping {}: {e}", file.display());
+                    continue;
+                }
+            };
+            let pieces = chunk_text(&text, chunk_chars);
+            let mut embeddings = Vec::with_capacity(pieces.len());
+            for batch in pieces.chunks(EMBED_BATCH) {
+                let vectors = backend
+                    .embed(batch)
+                    .await
+                    .with_context(|| format!("cannot embed {}", file.display()))?;
+                if vectors.len() != batch.len() {
+                    bail!(
+                        "the embedding model returned {} vectors for {} texts",
+                        vectors.len(),
+                        batch.len()
+                    );
+                }
+                embeddings.extend(vectors);
+            }
+            self.chunks.retain(|chunk| chunk.source != file);
+            self.chunks.extend(
+                pieces
+                    .into_iter()
+                    .zip(embeddings)
+                    .map(|(text, embedding)| Chunk {
+                        source: file.clone(),
+                        modified,
+                        text,
+                        embedding,
+                    }),
+            );
+            report.indexed += 1;
+        }
+        self.save()?;
+        Ok(report)
+    }
+
+    /// Write the chunks to the index file.
+    fn save(&mut self) -> Result<()> {
+        let Some(path) = &self.path else {
+            bail!("no index file: set DOCS_INDEX");
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        let mut file = BufWriter::new(
+            File::create(path).with_context(|| format!("cannot write {}", path.display()))?,
+        );
+        for chunk in &self.chunks {
+            writeln!(file, "{}", serde_json::to_string(chunk)?)?;
+        }
+        file.flush()?;
+        self.loaded = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        Ok(())
+    }
+
+    /// The chunks most similar to the question with embedding `query`,
+    /// best first.
+    pub fn search(&self, query: &[f32]) -> Vec<&Chunk> {
+        let mut scored: Vec<(f32, &Chunk)> = self
+            .chunks
+            .iter()
+            .filter_map(|chunk| {
+                let score = cosine_similarity(query, &chunk.embedding)?;
+                (score >= self.min_score).then_some((score, chunk))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored
+            .into_iter()
+            .take(self.top_k)
+            .map(|(_, chunk)| chunk)
+            .collect()
+    }
+}
+
+/// The text files under `root`, skipping hidden files and folders and
+/// not following links to folders, which may lead back up the tree.
+pub fn text_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        if path.is_dir() {
+            let Ok(entries) = fs::read_dir(&path) else {
+                log::warn!("Cannot read {}", path.display());
+                continue;
+            };
+            for entry in entries.flatten() {
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                let linked_folder =
+                    entry.file_type().is_ok_and(|kind| kind.is_symlink()) && entry.path().is_dir();
+                if !hidden && !linked_folder {
+                    pending.push(entry.path());
+                }
+            }
+        } else if is_text_file(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+fn is_text_file(path: &Path) -> bool {
+    let known = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+    known && fs::metadata(path).is_ok_and(|meta| meta.len() <= MAX_FILE_BYTES)
+}
+
+fn modified_secs(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |age| age.as_secs())
+}
+
+/// Cut `text` into chunks of at most about `max_chars` characters, along
+/// paragraph breaks where possible. Paragraphs longer than that are cut
+/// between words.
+pub fn chunk_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.len() + paragraph.len() + 2 > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if paragraph.len() <= max_chars {
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(paragraph);
+            continue;
+        }
+        for word in paragraph.split_whitespace() {
+            if !current.is_empty() && current.len() + word.len() + 1 > max_chars {
+                chunks.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
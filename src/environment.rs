@@ -0,0 +1,66 @@
+//! Facts about the machine added to every prompt.
+//!
+//! The model cannot know the date or where the shell tools run, so a short
+//! block with the local date and time, the working directory of
+//! `shell_task`, the host name and the operating system is added to the
+//! system prompt of every command. "What's today's date?" and "where am
+//! I?" are then answered without a tool call. `PROMPT_CONTEXT=0` leaves
+//! the block out.
+
+use std::fs;
+
+use crate::jarvis_io::JarvisIO;
+
+/// The context block, starting with a newline.
+pub fn context_block() -> String {
+    let now = chrono::Local::now();
+    let mut block = format!(
+        "\nCurrent context (use it to answer questions about it directly):\n\
+- Date and time: {}\n",
+        now.format("%A, %-d %B %Y, %H:%M (UTC%:z)")
+    );
+    if let Some(dir) = working_directory() {
+        block.push_str(&format!("- Working directory of shell commands: {dir}\n"));
+    }
+    if let Some(host) = hostname() {
+        block.push_str(&format!("- Host name: {host}\n"));
+    }
+    block.push_str(&format!("- Operating system: {}\n", operating_system()));
+    block
+}
+
+/// The directory `shell_task` runs in: the one `cd` last moved to, else
+/// the directory Jarvis was started in.
+fn working_directory() -> Option<String> {
+    dirs::home_dir()
+        .and_then(|_| JarvisIO::new().read_working_directory())
+        .filter(|dir| !dir.is_empty())
+        .or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .map(|dir| dir.display().to_string())
+        })
+}
+
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// The distribution's name on Linux (e.g. "Ubuntu 24.04 LTS"), else the
+/// operating system family.
+fn operating_system() -> String {
+    fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|release| {
+            release.lines().find_map(|line| {
+                let name = line.strip_prefix("PRETTY_NAME=")?;
+                Some(name.trim_matches('"').to_string())
+            })
+        })
+        .unwrap_or_else(|| std::env::consts::OS.to_string())
+}
@@ -0,0 +1,101 @@
+//! Typed pipeline events for embedders and user interfaces.
+//!
+//! The pipeline publishes a [`JarvisEvent`] at every significant step:
+//! wake word activations, recognised commands, tool invocations, replies
+//! and errors. Subscribe with [`Jarvis::events`](crate::Jarvis::events)
+//! instead of polling the files in `~/.jarvis`.
+//!
+//! Events are delivered over a broadcast channel. A subscriber that falls
+//! too far behind skips the oldest events rather than blocking the
+//! pipeline.
+
+use futures_util::stream::{self, Stream};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::agent::AgentResponse;
+use crate::jobs::Job;
+
+/// Number of events buffered per subscriber before old ones are dropped.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Something that happened in the pipeline.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JarvisEvent {
+    /// The wake word was detected in `transcript`.
+    Wake { transcript: String },
+    /// Jarvis returned to idle mode.
+    Idle,
+    /// A command was recognised in conversation mode.
+    Transcript {
+        text: String,
+        confidence: Option<f32>,
+    },
+    /// The model requested a tool.
+    ToolCall { tool: String, arguments: Value },
+    /// A running tool printed `line`.
+    ToolOutput { tool: String, line: String },
+    /// A tool has been running for a while; `text` says how it is
+    /// getting on and is spoken. See [`crate::tools::progress`].
+    ToolProgress { tool: String, text: String },
+    /// A tool finished and produced `output`.
+    ToolResult { tool: String, output: String },
+    /// The reply that is about to be spoken.
+    Response { text: String },
+    /// The full result of a command: the reply, the tool calls made for
+    /// it and how long it took.
+    AgentResponse(AgentResponse),
+    /// The speech engine started playing `text`.
+    SpeakingStarted { text: String },
+    /// Playback of `text` ended, either completely or because it was
+    /// interrupted.
+    SpeakingFinished { text: String },
+    /// A background job ended; see [`crate::jobs`].
+    JobFinished(Job),
+    /// A Pomodoro session or break began; `text` says which. See
+    /// [`crate::tools::timers`].
+    Timer { text: String },
+    /// Something went wrong; the pipeline keeps running.
+    Error { message: String },
+}
+
+/// Cheaply cloneable publisher shared by the pipeline components.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<JarvisEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish an event. Having no subscribers is not an error.
+    pub fn emit(&self, event: JarvisEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribe to all events published from now on.
+    pub fn subscribe(&self) -> impl Stream<Item = JarvisEvent> {
+        stream::unfold(self.sender.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => return Some((event, rx)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("Event subscriber lagged; skipped {skipped} events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,104 @@
+//! Example dialogues shown to the model.
+//!
+//! Small models get tool calls right far more often after seeing a few
+//! worked examples. Every `*.md` file in `~/.jarvis/examples` (or the
+//! folder named by `EXAMPLES_DIR`) is one example, written however reads
+//! best, for instance:
+//!
+//! ```text
+//! User: how much disk space is left?
+//! Jarvis: {"tool": "shell_task", "arguments": {"command": "df -h /"}}
+//! Tool: /dev/nvme0n1p2  468G  341G  104G  77% /
+//! Jarvis: About 104 gigabytes are free.
+//! ```
+//!
+//! The files are added to the end of the system prompt in name order and
+//! read for every command, so edits apply at once. Once the examples reach
+//! `EXAMPLES_MAX_CHARS` characters (default `4000`) the remaining files are
+//! left out; `EXAMPLES=0` leaves them all out.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::{env_flag_or, env_parse};
+
+/// The folder of example dialogues.
+#[derive(Debug, Clone, Default)]
+pub struct Examples {
+    dir: Option<PathBuf>,
+    max_chars: usize,
+}
+
+impl Examples {
+    /// The examples in `dir`.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir: Some(dir),
+            max_chars: env_parse("EXAMPLES_MAX_CHARS", 4000),
+        }
+    }
+
+    /// The examples configured by `EXAMPLES` and `EXAMPLES_DIR`.
+    pub fn from_env() -> Self {
+        if !env_flag_or("EXAMPLES", true) {
+            return Self::default();
+        }
+        std::env::var("EXAMPLES_DIR")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("examples")))
+            .map(Self::new)
+            .unwrap_or_default()
+    }
+
+    /// The texts of the example files, in name order, within the size
+    /// limit.
+    pub fn load(&self) -> Vec<String> {
+        let Some(entries) = self.dir.as_ref().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "md"))
+            .collect();
+        paths.sort();
+        let mut examples = Vec::new();
+        let mut total = 0;
+        for path in paths {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text.trim().to_string(),
+                Err(e) => {
+                    log::warn!("Cannot read example {}: {e}", path.display());
+                    continue;
+                }
+            };
+            if text.is_empty() {
+                continue;
+            }
+            total += text.chars().count();
+            if total > self.max_chars {
+                log::debug!("Leaving out {} and later examples", path.display());
+                break;
+            }
+            examples.push(text);
+        }
+        examples
+    }
+
+    /// The system prompt section with the examples, or an empty string if
+    /// there are none. It starts with a newline.
+    pub fn prompt_section(&self) -> String {
+        let examples = self.load();
+        if examples.is_empty() {
+            return String::new();
+        }
+        let mut section = String::from(
+            "\nExamples of requests and how to handle them. Follow their format, not their facts:\n",
+        );
+        for example in examples {
+            section.push_str(&format!("\n<example>\n{example}\n</example>\n"));
+        }
+        section
+    }
+}
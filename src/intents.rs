@@ -0,0 +1,258 @@
+//! Fast path for trivial commands.
+//!
+//! Commands such as "what time is it" or "stop" are answered locally,
+//! without the round trip to the language model. Intents are regular
+//! expressions matched against the lower-cased command; the built-in ones
+//! (`time`, `date`, `stop` and `last action`) can be replaced and more added in
+//! `~/.jarvis/intents.toml` (or the file named by `INTENTS_FILE`):
+//!
+//! ```toml
+//! [[intent]]
+//! name = "volume up"
+//! patterns = ['^(turn )?(the )?volume up$']
+//! shell = "pactl set-sink-volume @DEFAULT_SINK@ +10%"
+//! reply = "Volume up."
+//!
+//! [[intent]]
+//! name = "thanks"
+//! patterns = ['^thank(s| you)']
+//! reply = "You're welcome."
+//! ```
+//!
+//! The built-in `last action` intent answers "what did you just run?"
+//! with the exact tool calls of the last command that used tools and the
+//! start of their output, rather than the model's recollection of them.
+//!
+//! An intent either runs a built-in `action` (`time`, `date`, `stop`,
+//! `last_action`),
+//! runs a `shell` command and speaks its `reply` (or the command's output
+//! if there is none), or just speaks its `reply`. Everything that matches
+//! no intent goes to the model. `INTENTS=0` turns the fast path off.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::config::env_flag_or;
+use crate::tools::{run_shell_task, ToolRun};
+
+/// The built-in intents: name, patterns and action.
+const BUILT_IN: &[(&str, &[&str], &str)] = &[
+    (
+        "time",
+        &[
+            r"^what(?:'s| is) the time(?: now)?$",
+            r"^what time is it(?: now)?$",
+            r"^(?:tell me )?the time$",
+        ],
+        "time",
+    ),
+    (
+        "date",
+        &[
+            r"^what(?:'s| is) (?:the )?date(?: today)?$",
+            r"^what(?:'s| is) today's date$",
+            r"^what day is (?:it|today)$",
+        ],
+        "date",
+    ),
+    (
+        "stop",
+        &[r"^(?:stop|cancel|never ?mind|be quiet|quiet|shut up)$"],
+        "stop",
+    ),
+    (
+        "last action",
+        &[
+            r"^what (?:did you|have you) just (?:run|do|execute|done)$",
+            r"^what (?:command )?did you (?:just )?(?:run|execute)$",
+            r"^(?:explain|tell me) what you (?:just )?(?:did|ran)$",
+            r"^what was (?:that|the last) command$",
+        ],
+        "last_action",
+    ),
+];
+
+/// What a matched intent does.
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    /// Say the current time.
+    Time,
+    /// Say today's date.
+    Date,
+    /// Acknowledge and do nothing.
+    Stop,
+    /// Say which tools the last command ran and what came of it.
+    Recap,
+    /// Speak a fixed reply.
+    Reply(String),
+    /// Run a shell command, then speak `reply` or the command's output.
+    Shell {
+        command: String,
+        reply: Option<String>,
+    },
+}
+
+/// One intent from the configuration.
+#[derive(Debug, Clone)]
+struct Intent {
+    name: String,
+    patterns: Vec<Regex>,
+    action: Action,
+}
+
+/// Intent definitions as written in `intents.toml`.
+#[derive(Debug, Default, Deserialize)]
+struct IntentFile {
+    #[serde(default, rename = "intent")]
+    intents: Vec<IntentEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IntentEntry {
+    name: String,
+    patterns: Vec<String>,
+    action: Option<String>,
+    shell: Option<String>,
+    reply: Option<String>,
+}
+
+/// Matches commands against the configured intents.
+#[derive(Debug, Clone, Default)]
+pub struct IntentRouter {
+    intents: Vec<Intent>,
+}
+
+impl IntentRouter {
+    /// The built-in intents and those in the user's file, or none if
+    /// `INTENTS=0`. Invalid entries are logged and skipped.
+    pub fn load() -> Self {
+        if !env_flag_or("INTENTS", true) {
+            return Self::default();
+        }
+        let mut router = Self::default();
+        for (name, patterns, action) in BUILT_IN {
+            router.add(IntentEntry {
+                name: name.to_string(),
+                patterns: patterns.iter().map(|p| p.to_string()).collect(),
+                action: Some(action.to_string()),
+                shell: None,
+                reply: None,
+            });
+        }
+        let path = std::env::var("INTENTS_FILE")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("intents.toml")));
+        if let Some(Ok(data)) = path.as_ref().map(std::fs::read_to_string) {
+            match toml::from_str::<IntentFile>(&data) {
+                Ok(file) => file.intents.into_iter().for_each(|entry| router.add(entry)),
+                Err(e) => log::warn!("Ignoring invalid intents file {:?}: {e}", path),
+            }
+        }
+        router
+    }
+
+    /// Add an intent, replacing one of the same name.
+    fn add(&mut self, entry: IntentEntry) {
+        let name = entry.name.clone();
+        match Intent::from_entry(entry) {
+            Ok(intent) => {
+                self.intents.retain(|i| i.name != intent.name);
+                self.intents.push(intent);
+            }
+            Err(e) => log::warn!("Ignoring intent '{name}': {e:#}"),
+        }
+    }
+
+    /// Answer `command` if it matches an intent. `None` means the command
+    /// is for the model. `last_actions` are the tool calls of the last
+    /// command that used tools.
+    pub fn handle(&self, command: &str, last_actions: &[ToolRun]) -> Option<String> {
+        let command = normalize(command);
+        let intent = self
+            .intents
+            .iter()
+            .find(|intent| intent.patterns.iter().any(|p| p.is_match(&command)))?;
+        log::info!("Command '{command}' matched intent '{}'", intent.name);
+        Some(intent.action.run(last_actions))
+    }
+}
+
+impl Intent {
+    fn from_entry(entry: IntentEntry) -> Result<Self> {
+        let patterns = entry
+            .patterns
+            .iter()
+            .map(|p| Regex::new(&format!("(?i){p}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        if patterns.is_empty() {
+            bail!("no patterns");
+        }
+        let action = match (entry.action.as_deref(), entry.shell, entry.reply) {
+            (Some("time"), _, _) => Action::Time,
+            (Some("date"), _, _) => Action::Date,
+            (Some("stop"), _, _) => Action::Stop,
+            (Some("last_action"), _, _) => Action::Recap,
+            (Some(other), _, _) => {
+                bail!("unknown action '{other}' (expected time, date, stop or last_action)")
+            }
+            (None, Some(command), reply) => Action::Shell { command, reply },
+            (None, None, Some(reply)) => Action::Reply(reply),
+            (None, None, None) => bail!("needs an action, a shell command or a reply"),
+        };
+        Ok(Self {
+            name: entry.name,
+            patterns,
+            action,
+        })
+    }
+}
+
+impl Action {
+    /// Carry out the action and return the reply.
+    fn run(&self, last_actions: &[ToolRun]) -> String {
+        let now = chrono::Local::now();
+        match self {
+            Action::Time => format!("It's {}.", now.format("%-I:%M %p")),
+            Action::Date => format!("It's {}.", now.format("%A, %B %-d")),
+            Action::Stop => "Okay.".to_string(),
+            Action::Recap => match last_actions {
+                [] => "I haven't run anything yet.".to_string(),
+                [run] => format!("I ran {}.", run.summary()),
+                runs => format!(
+                    "I ran {} tools: {}.",
+                    runs.len(),
+                    runs.iter()
+                        .map(ToolRun::summary)
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ),
+            },
+            Action::Reply(reply) => reply.clone(),
+            Action::Shell { command, reply } => match run_shell_task(command) {
+                Ok(output) => reply.clone().unwrap_or(output),
+                Err(e) => {
+                    log::warn!("Intent command '{command}' failed: {e:#}");
+                    "Sorry, that didn't work.".to_string()
+                }
+            },
+        }
+    }
+}
+
+/// The command as matched: lower case, without a leading "please" or
+/// trailing punctuation.
+fn normalize(command: &str) -> String {
+    let command = command
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_lowercase();
+    command
+        .strip_prefix("please ")
+        .unwrap_or(&command)
+        .trim()
+        .to_string()
+}
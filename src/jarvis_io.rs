@@ -1,9 +1,32 @@
+//! The files in `~/.jarvis` through which other programs follow Jarvis.
+//!
+//! * `jarvis`: the process id.
+//! * `jarvis.status`: starting, idle, listening, speaking or canceled.
+//! * `jarvis.heard`: the last phrase recognised.
+//! * `jarvis.spoken`: the text of the last reply.
+//! * `jarvis.response.json`: the last reply as an
+//!   [`AgentResponse`](crate::agent::AgentResponse), with the model's raw
+//!   answer, the tool calls, their results and the timings.
+//! * `jarvis.speaking`: the utterance being spoken, empty when silent.
+//! * `jarvis.working_directory`: the directory shell tasks run in.
+//!
+//! Secrets are masked in what is written of replies; see
+//! [`crate::redact`].
+
 use std::path::PathBuf;
 
+use crate::agent::AgentResponse;
+
 pub struct JarvisIO {
     base: PathBuf,
 }
 
+impl Default for JarvisIO {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl JarvisIO {
     pub fn new() -> Self {
         let base = dirs::home_dir().unwrap().join(".jarvis");
@@ -15,8 +38,26 @@ impl JarvisIO {
         let _ = std::fs::write(self.base.join("jarvis.status"), status);
     }
 
+    /// Record the text last spoken, with its secrets masked; see
+    /// [`crate::redact`].
     pub fn write_spoken(&self, text: &str) {
-        let _ = std::fs::write(self.base.join("jarvis.spoken"), text);
+        let text = crate::redact::redact(text);
+        let _ = std::fs::write(self.base.join("jarvis.spoken"), text.as_ref());
+    }
+
+    /// Record the last reply as JSON in `jarvis.response.json`, with its
+    /// secrets masked.
+    pub fn write_response(&self, response: &AgentResponse) {
+        let Ok(json) = serde_json::to_string_pretty(response) else {
+            return;
+        };
+        let json = crate::redact::redact(&json);
+        let _ = std::fs::write(self.base.join("jarvis.response.json"), json.as_ref());
+    }
+
+    /// Record the utterance being spoken; empty once speech has finished.
+    pub fn write_speaking(&self, text: &str) {
+        let _ = std::fs::write(self.base.join("jarvis.speaking"), text);
     }
 
     pub fn write_heard(&self, text: &str) {
@@ -28,6 +69,11 @@ impl JarvisIO {
         let _ = std::fs::write(self.base.join("jarvis.working_directory"), path);
     }
 
+    /// Forget the persisted working directory.
+    pub fn clear_working_directory(&self) {
+        let _ = std::fs::remove_file(self.base.join("jarvis.working_directory"));
+    }
+
     pub fn current_status(&self) -> Option<String> {
         std::fs::read_to_string(self.base.join("jarvis.status")).ok()
     }
@@ -39,13 +85,6 @@ impl JarvisIO {
             .map(|s| s.trim().to_string())
     }
 
-    pub fn cancel_tts(&self) {
-        let _ = std::process::Command::new("bash")
-            .arg("-c")
-            .arg("~/.jarvis/scripts/cancel_tts.sh")
-            .spawn();
-    }
-
     pub fn set_pid(&self) {
         let pid = std::process::id().to_string();
         let _ = std::fs::write(self.base.join("jarvis"), pid);
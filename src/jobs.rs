@@ -0,0 +1,308 @@
+//! Long-running tool calls in the background.
+//!
+//! "Run the backup in the background" goes to the model as usual, but the
+//! tool calls it makes are started as a job instead of holding up the
+//! conversation: Jarvis says the job's number and is ready for the next
+//! command. When the job ends its result is announced, and "is the backup
+//! done?", "is job 2 finished?" or "what's running?" ask about it in the
+//! meantime.
+//!
+//! Jobs are kept in memory only; the last [`KEEP_FINISHED`] finished
+//! ones can be asked about.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::events::{EventBus, JarvisEvent};
+
+/// Finished jobs kept for questions about them.
+const KEEP_FINISHED: usize = 20;
+
+/// Characters of a job's output quoted when it is announced.
+const OUTPUT_CHARS: usize = 200;
+
+/// The phrases that send a command to the background.
+const BACKGROUND_PHRASES: &[&str] = &["in the background", "as a background job", "as a job"];
+
+/// How a job is doing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", content = "output", rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    /// Finished, with the output of its tools.
+    Done(String),
+    /// Failed with this error.
+    Failed(String),
+}
+
+/// A command running, or run, in the background.
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: usize,
+    /// The command that started the job, e.g. "run the backup".
+    pub label: String,
+    /// When the job started, in RFC 3339.
+    pub started: String,
+    pub status: JobStatus,
+}
+
+impl Job {
+    /// The job's end in a sentence fit to speak.
+    pub fn announcement(&self) -> String {
+        match &self.status {
+            JobStatus::Running => format!("Job {}, {}, is still running.", self.id, self.label),
+            JobStatus::Done(output) if output.trim().is_empty() => {
+                format!("Job {}, {}, is done.", self.id, self.label)
+            }
+            JobStatus::Done(output) => format!(
+                "Job {}, {}, is done: {}",
+                self.id,
+                self.label,
+                first_chars(output)
+            ),
+            JobStatus::Failed(error) => format!(
+                "Job {}, {}, failed: {}",
+                self.id,
+                self.label,
+                first_chars(error)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: usize,
+    jobs: Vec<Job>,
+    /// Ids of finished jobs not announced yet.
+    unannounced: Vec<usize>,
+}
+
+/// The background jobs. Clones share them.
+#[derive(Debug, Clone, Default)]
+pub struct Jobs {
+    state: Arc<Mutex<State>>,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `work` on a thread of its own as a job called `label` and
+    /// return its id. `events` is told when it ends.
+    pub fn start(
+        &self,
+        label: &str,
+        events: EventBus,
+        work: impl FnOnce() -> Result<String> + Send + 'static,
+    ) -> usize {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            state.next_id += 1;
+            let id = state.next_id;
+            state.jobs.push(Job {
+                id,
+                label: label.to_string(),
+                started: chrono::Local::now().to_rfc3339(),
+                status: JobStatus::Running,
+            });
+            id
+        };
+        log::info!("Started job {id}: {label}");
+        let jobs = self.clone();
+        std::thread::spawn(move || {
+            let status = match work() {
+                Ok(output) => JobStatus::Done(output),
+                Err(e) => JobStatus::Failed(format!("{e:#}")),
+            };
+            if let Some(job) = jobs.finish(id, status) {
+                log::info!("Job {id} ended: {:?}", job.status);
+                events.emit(JarvisEvent::JobFinished(job));
+            }
+        });
+        id
+    }
+
+    /// Record the end of job `id`.
+    fn finish(&self, id: usize, status: JobStatus) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        let job = state.jobs.iter_mut().find(|job| job.id == id)?;
+        job.status = status;
+        let job = job.clone();
+        state.unannounced.push(id);
+        // Forget the oldest finished jobs, keeping the running ones and
+        // those still to be announced.
+        let State {
+            jobs, unannounced, ..
+        } = &mut *state;
+        let forgettable =
+            |job: &Job| job.status != JobStatus::Running && !unannounced.contains(&job.id);
+        let mut excess = jobs
+            .iter()
+            .filter(|job| forgettable(job))
+            .count()
+            .saturating_sub(KEEP_FINISHED);
+        jobs.retain(|job| {
+            if excess > 0 && forgettable(job) {
+                excess -= 1;
+                return false;
+            }
+            true
+        });
+        Some(job)
+    }
+
+    /// All known jobs, oldest first.
+    pub fn list(&self) -> Vec<Job> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+
+    /// The jobs that ended since the last call, to be announced.
+    pub fn take_finished(&self) -> Vec<Job> {
+        let mut state = self.state.lock().unwrap();
+        let ids = std::mem::take(&mut state.unannounced);
+        state
+            .jobs
+            .iter()
+            .filter(|job| ids.contains(&job.id))
+            .cloned()
+            .collect()
+    }
+
+    /// The answer to `question`, in sentences fit to speak, or `None` if
+    /// it is not about a job after all: there are no jobs, or "is the
+    /// pasta ready?" matches none of them.
+    pub fn answer(&self, question: &JobQuestion) -> Option<String> {
+        let jobs = self.list();
+        if jobs.is_empty() {
+            return None;
+        }
+        Some(match question {
+            JobQuestion::All => {
+                let running: Vec<&Job> = jobs
+                    .iter()
+                    .filter(|job| job.status == JobStatus::Running)
+                    .collect();
+                match running.as_slice() {
+                    [] => "Nothing is running in the background.".to_string(),
+                    [job] => format!("Job {}, {}, is running.", job.id, job.label),
+                    many => format!(
+                        "{} jobs are running: {}.",
+                        many.len(),
+                        many.iter()
+                            .map(|job| format!("job {}, {}", job.id, job.label))
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    ),
+                }
+            }
+            JobQuestion::Id(id) => jobs
+                .iter()
+                .find(|job| job.id == *id)
+                .map(Job::announcement)
+                .unwrap_or_else(|| format!("There is no job {id}.")),
+            JobQuestion::About(words) => jobs
+                .iter()
+                .rev()
+                .find(|job| {
+                    let label = job.label.to_lowercase();
+                    words.iter().all(|word| label.contains(word.as_str()))
+                })
+                .map(Job::announcement)?,
+        })
+    }
+}
+
+/// A question about the background jobs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobQuestion {
+    /// "What's running?"
+    All,
+    /// "Is job 2 done?"
+    Id(usize),
+    /// "Is the backup done?": the job whose command has these words.
+    About(Vec<String>),
+}
+
+impl JobQuestion {
+    /// The question in `command`, if it is about the background jobs.
+    pub fn parse(command: &str) -> Option<Self> {
+        let lower = command
+            .trim()
+            .trim_end_matches(['?', '.', '!'])
+            .to_lowercase();
+        if matches!(
+            lower.as_str(),
+            "what's running"
+                | "what is running"
+                | "what's running in the background"
+                | "what is running in the background"
+                | "list jobs"
+                | "list the jobs"
+                | "job status"
+                | "what jobs are running"
+                | "are any jobs running"
+        ) {
+            return Some(JobQuestion::All);
+        }
+        let subject = ["is ", "has "]
+            .iter()
+            .find_map(|start| lower.strip_prefix(start))?;
+        let subject = [
+            " done",
+            " finished",
+            " ready",
+            " still running",
+            " complete",
+            " over",
+        ]
+        .iter()
+        .find_map(|end| subject.strip_suffix(end))?
+        .trim();
+        let subject = subject.strip_prefix("the ").unwrap_or(subject);
+        if let Some(id) = subject
+            .strip_prefix("job ")
+            .and_then(|id| id.trim().parse().ok())
+        {
+            return Some(JobQuestion::Id(id));
+        }
+        if subject == "job" || subject == "it" {
+            return Some(JobQuestion::All);
+        }
+        let words: Vec<String> = subject
+            .split_whitespace()
+            .filter(|word| !matches!(*word, "job" | "task" | "my"))
+            .map(str::to_string)
+            .collect();
+        (!words.is_empty()).then_some(JobQuestion::About(words))
+    }
+}
+
+/// The command without "in the background", if it asks for a job.
+pub fn background_command(command: &str) -> Option<String> {
+    let trimmed = command.trim().trim_end_matches(['.', '!']);
+    BACKGROUND_PHRASES.iter().find_map(|phrase| {
+        let start = trimmed.char_indices().map(|(i, _)| i).find(|&i| {
+            trimmed
+                .get(i..i + phrase.len())
+                .is_some_and(|found| found.eq_ignore_ascii_case(phrase))
+        })?;
+        let end = start + phrase.len();
+        let rest = format!("{} {}", &trimmed[..start], &trimmed[end..]);
+        let rest = rest.split_whitespace().collect::<Vec<_>>().join(" ");
+        (!rest.is_empty()).then_some(rest)
+    })
+}
+
+/// `text` on one line, cut to [`OUTPUT_CHARS`] characters.
+fn first_chars(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= OUTPUT_CHARS {
+        return text;
+    }
+    let cut: String = text.chars().take(OUTPUT_CHARS).collect();
+    format!("{}…", cut.trim_end())
+}
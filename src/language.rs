@@ -0,0 +1,130 @@
+//! The language Jarvis answers in.
+//!
+//! By default the model answers in whatever language it likes, usually the
+//! one it was addressed in. "Answer me in Croatian", "speak German" or
+//! "switch to French" makes it answer in that language from then on,
+//! until another language is asked for; `REPLY_LANGUAGE` (a name such as
+//! `croatian` or a code such as `hr`) sets the language at start-up.
+//!
+//! The chosen language is added to the system prompt and handed to the
+//! speech queue, which picks its voice from `VOICE_LANGUAGES` for replies
+//! whose language it cannot recognise by itself (see
+//! [`crate::tts_engine::language`]).
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// A language the user can ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Language {
+    /// ISO 639-1 code, as used in `VOICE_LANGUAGES`.
+    pub code: &'static str,
+    /// English name, for the prompt and spoken replies.
+    pub name: &'static str,
+}
+
+/// Code, English name and other names (in the language itself) of the
+/// languages that can be asked for.
+const LANGUAGES: &[(&str, &str, &[&str])] = &[
+    ("en", "English", &[]),
+    ("hr", "Croatian", &["hrvatski"]),
+    ("sr", "Serbian", &["srpski"]),
+    ("bs", "Bosnian", &["bosanski"]),
+    ("sl", "Slovenian", &["slovene", "slovenščina"]),
+    ("de", "German", &["deutsch"]),
+    ("fr", "French", &["français", "francais"]),
+    ("es", "Spanish", &["español", "espanol"]),
+    ("it", "Italian", &["italiano"]),
+    ("pt", "Portuguese", &["português", "portugues"]),
+    ("nl", "Dutch", &["nederlands"]),
+    ("pl", "Polish", &["polski"]),
+    ("cs", "Czech", &["čeština", "cestina"]),
+    ("sk", "Slovak", &["slovenčina"]),
+    ("hu", "Hungarian", &["magyar"]),
+    ("ro", "Romanian", &["română", "romana"]),
+    ("sv", "Swedish", &["svenska"]),
+    ("da", "Danish", &["dansk"]),
+    ("nb", "Norwegian", &["norsk"]),
+    ("fi", "Finnish", &["suomi"]),
+    ("tr", "Turkish", &["türkçe", "turkce"]),
+    ("el", "Greek", &[]),
+    ("ru", "Russian", &[]),
+    ("uk", "Ukrainian", &[]),
+    ("ar", "Arabic", &[]),
+    ("he", "Hebrew", &[]),
+    ("ja", "Japanese", &[]),
+    ("zh", "Chinese", &["mandarin"]),
+    ("ko", "Korean", &[]),
+];
+
+static COMMAND: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?:please )?(?:(?:answer|reply|respond|talk|speak)(?: to)?(?: me)?(?: only)? in|(?:switch|change) (?:your )?(?:language )?to|speak) (\w+)(?: from now on| please)?$",
+    )
+    .unwrap()
+});
+
+/// The language named `name`: its English name, its own name or its code.
+pub fn lookup(name: &str) -> Option<Language> {
+    let name = name.trim().to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(code, english, others)| {
+            *code == name || english.to_lowercase() == name || others.contains(&name.as_str())
+        })
+        .map(|&(code, name, _)| Language { code, name })
+}
+
+/// The language asked for by a command such as "answer me in Croatian",
+/// or `None` if `command` is not one.
+pub fn language_command(command: &str) -> Option<Language> {
+    let command: String = command
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect();
+    let command = command.split_whitespace().collect::<Vec<_>>().join(" ");
+    lookup(&COMMAND.captures(&command)?[1])
+}
+
+/// The language replies are given in.
+#[derive(Debug, Clone, Default)]
+pub struct ReplyLanguage {
+    current: Option<Language>,
+}
+
+impl ReplyLanguage {
+    /// The language in `REPLY_LANGUAGE`, if any.
+    pub fn from_env() -> Self {
+        let current = std::env::var("REPLY_LANGUAGE")
+            .ok()
+            .filter(|name| !name.trim().is_empty())
+            .and_then(|name| {
+                let language = lookup(&name);
+                if language.is_none() {
+                    log::warn!("Ignoring unknown REPLY_LANGUAGE={name}");
+                }
+                language
+            });
+        Self { current }
+    }
+
+    /// The language asked for, `None` if the model may choose.
+    pub fn current(&self) -> Option<Language> {
+        self.current
+    }
+
+    pub fn set(&mut self, language: Language) {
+        self.current = Some(language);
+    }
+
+    /// The system prompt line asking for the language, if one was chosen.
+    pub fn prompt_line(&self) -> Option<String> {
+        self.current.map(|language| {
+            format!(
+                "Always answer in {}, whatever language the user speaks; keep commands, file names and code unchanged.",
+                language.name
+            )
+        })
+    }
+}
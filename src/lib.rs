@@ -0,0 +1,47 @@
+//! Jarvis: an offline, voice‑activated personal assistant.
+//!
+//! The crate is usable both as the `jarvis` binary and as a library. The
+//! [`Jarvis`] pipeline connects speech recognition, a language model agent
+//! with tools, and text‑to‑speech; each stage can be swapped for a custom
+//! implementation through [`JarvisBuilder`]:
+//!
+//!  * [`speech::Listener`] for speech recognition (default: Vosk),
+//!  * [`llm::LlmBackend`] for the language model (default: Ollama),
+//!  * [`tools::Tool`] for capabilities the model can invoke,
+//!  * [`tts_engine::Speaker`] for speech output (default: OS voices or RHVoice).
+
+pub mod accessibility;
+pub mod agent;
+pub mod audio_output;
+pub mod budget;
+pub mod config;
+pub mod conversation;
+pub mod documents;
+pub mod environment;
+pub mod events;
+pub mod examples;
+pub mod intents;
+pub mod jarvis_io;
+pub mod jobs;
+pub mod language;
+pub mod llm;
+pub mod memory;
+pub mod persona;
+pub mod pipeline;
+pub mod postprocess;
+pub mod project;
+pub mod redact;
+pub mod session;
+pub mod speech;
+pub mod stats;
+pub mod think;
+pub mod tools;
+pub mod transcript;
+pub mod tts_engine;
+pub mod verbosity;
+pub mod wake;
+
+pub use agent::{AgentResponse, Timings};
+pub use events::JarvisEvent;
+pub use pipeline::{Jarvis, JarvisBuilder};
+pub use verbosity::ErrorVerbosity;
@@ -0,0 +1,733 @@
+//! Language model backends.
+//!
+//! [`LlmBackend`] is the seam between the [`Agent`](crate::agent::Agent)
+//! and whatever serves the model. The agent talks to it in chat
+//! [`Message`]s and offers the tools as [`ToolSpec`]s; tool calls come back
+//! as typed [`ToolCall`]s rather than JSON embedded in the answer.
+//!
+//! `LLM_BACKEND` selects the implementation used by [`from_env`]:
+//!
+//!  * `ollama` (default): a local Ollama server, through its chat API.
+//!  * `openai`: any server with an OpenAI-compatible chat completions API,
+//!    such as llama.cpp server, LM Studio or vLLM. Needs the `openai`
+//!    feature; see `openai`.
+//!
+//! Embedders can supply their own backend through
+//! [`JarvisBuilder::with_llm`](crate::pipeline::JarvisBuilder::with_llm).
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use ollama_rs::generation::chat::request::ChatMessageRequest;
+use ollama_rs::generation::chat::{ChatMessage, MessageRole};
+use ollama_rs::generation::embeddings::request::GenerateEmbeddingsRequest;
+use ollama_rs::generation::tools::{ToolCall as OllamaToolCall, ToolCallFunction, ToolInfo};
+use ollama_rs::models::ModelOptions;
+use ollama_rs::{generation::completion::request::GenerationRequest, Ollama};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::config::env_list;
+
+pub mod mock;
+#[cfg(feature = "openai")]
+pub mod openai;
+
+/// Who a chat [`Message`] is from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    /// The output of a tool call.
+    Tool,
+}
+
+/// One message of a chat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+    /// Tools the assistant asked to run; only set on assistant messages.
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl Message {
+    pub fn new(role: Role, content: &str) -> Self {
+        Self {
+            role,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+        }
+    }
+
+    pub fn system(content: &str) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: &str) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub fn tool(content: &str) -> Self {
+        Self::new(Role::Tool, content)
+    }
+}
+
+/// A request from the model to run a tool.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub name: String,
+    /// Arguments matching the tool's [`parameters`](ToolSpec::parameters).
+    pub arguments: Value,
+}
+
+/// A tool call written as JSON in the text of a reply, as small models
+/// sometimes do instead of using native tool calling:
+/// `{"tool": "shell_task", "arguments": {"command": "df -h"}}`.
+#[derive(Debug, Deserialize)]
+struct TextToolCall {
+    #[serde(alias = "name", alias = "function")]
+    tool: String,
+    #[serde(default, alias = "parameters", alias = "args")]
+    arguments: Value,
+}
+
+/// One call, a list of them, or a list with its order:
+/// `{"order": "parallel", "calls": [...]}`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TextToolCalls {
+    One(TextToolCall),
+    Many(Vec<TextToolCall>),
+    Ordered {
+        #[serde(default)]
+        order: CallOrder,
+        calls: Vec<TextToolCall>,
+    },
+}
+
+/// How the tool calls of one reply are run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallOrder {
+    /// One after the other, in the order given.
+    #[default]
+    Sequential,
+    /// At the same time; the calls do not depend on each other.
+    Parallel,
+}
+
+impl FromStr for CallOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "sequential" => Ok(CallOrder::Sequential),
+            "parallel" => Ok(CallOrder::Parallel),
+            other => bail!("unknown call order '{other}' (expected sequential or parallel)"),
+        }
+    }
+}
+
+/// The start of a tool call written as JSON after some other text, as in
+/// `Sure, let me check. {"tool": "shell_task", ...}`.
+static EMBEDDED_CALL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?:\[\s*)?\{\s*"(?:tool|name|function|order)"\s*:"#).unwrap());
+
+/// Where a tool call written as JSON starts in `text`, if it has one.
+pub fn tool_call_start(text: &str) -> Option<usize> {
+    EMBEDDED_CALL.find(text).map(|m| m.start())
+}
+
+/// Tool calls written as JSON in the text of a reply, alone or after some
+/// other text; anything after the JSON is ignored. A reply without JSON
+/// gives no calls; JSON that is meant as a tool call but cannot be read
+/// gives an error describing the problem, to be sent back to the model.
+pub fn parse_text_tool_calls(content: &str) -> Result<(Vec<ToolCall>, CallOrder), String> {
+    let mut text = reply_json(content);
+    if !text.starts_with(['{', '[']) {
+        let Some(start) = tool_call_start(text) else {
+            return Ok((Vec::new(), CallOrder::default()));
+        };
+        text = text[start..].trim_end().trim_end_matches("```");
+    }
+    let looks_like_call = ["\"tool\"", "\"name\"", "\"function\""]
+        .iter()
+        .any(|key| text.contains(key));
+    let value = match serde_json::Deserializer::from_str(text)
+        .into_iter::<Value>()
+        .next()
+    {
+        Some(Ok(value)) => value,
+        Some(Err(e)) if looks_like_call => {
+            return Err(format!("the tool call is not valid JSON: {e}"))
+        }
+        _ => return Ok((Vec::new(), CallOrder::default())),
+    };
+    let (calls, order) = match serde_json::from_value::<TextToolCalls>(value) {
+        Ok(TextToolCalls::One(call)) => (vec![call], CallOrder::default()),
+        Ok(TextToolCalls::Many(calls)) => (calls, CallOrder::default()),
+        Ok(TextToolCalls::Ordered { order, calls }) => (calls, order),
+        Err(_) if !looks_like_call => return Ok((Vec::new(), CallOrder::default())),
+        Err(_) => return Err(
+            "the tool call has the wrong shape: write {\"tool\": \"<name>\", \"arguments\": {...}}"
+                .to_string(),
+        ),
+    };
+    let calls = calls
+        .into_iter()
+        .map(|call| ToolCall {
+            name: call.tool,
+            arguments: match call.arguments {
+                Value::Null => json!({}),
+                // Arguments given as a JSON string.
+                Value::String(text) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+                arguments => arguments,
+            },
+        })
+        .collect();
+    Ok((calls, order))
+}
+
+/// The question in a reply of the form `{"ask": "which directory?"}`,
+/// with which the model asks the user to clarify a command.
+pub fn parse_clarification(content: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Ask {
+        ask: String,
+    }
+    let ask: Ask = serde_json::from_str(reply_json(content)).ok()?;
+    Some(ask.ask.trim().to_string()).filter(|question| !question.is_empty())
+}
+
+/// The text of a reply that may be JSON, without a `<think>` block or
+/// Markdown code fences around it.
+fn reply_json(content: &str) -> &str {
+    let text = content
+        .rsplit_once("</think>")
+        .map_or(content, |(_, rest)| rest)
+        .trim();
+    text.strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(text)
+        .trim()
+}
+
+/// A tool offered to the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the arguments object.
+    pub parameters: Value,
+}
+
+/// Sampling settings for a request. Unset fields keep the model's own
+/// defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// Context window in tokens (Ollama only).
+    pub num_ctx: Option<u64>,
+    /// Longest reply in tokens, including any `<think>` block.
+    pub max_tokens: Option<u32>,
+}
+
+impl GenerationOptions {
+    /// The settings from `LLM_TEMPERATURE`, `LLM_TOP_P`, `LLM_NUM_CTX` and
+    /// `LLM_MAX_TOKENS`.
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok()?.trim().parse().ok()
+        }
+        Self {
+            temperature: var("LLM_TEMPERATURE"),
+            top_p: var("LLM_TOP_P"),
+            num_ctx: var("LLM_NUM_CTX"),
+            max_tokens: var("LLM_MAX_TOKENS"),
+        }
+    }
+
+    /// These settings with the fields set in `overrides` replaced.
+    pub fn with_overrides(self, overrides: &GenerationOptions) -> Self {
+        Self {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            num_ctx: overrides.num_ctx.or(self.num_ctx),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+        }
+    }
+}
+
+/// A language model backend.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    /// Generate a completion for the given prompt and return the raw text.
+    async fn generate(&self, prompt: &str) -> Result<String>;
+
+    /// Whether [`chat`](Self::chat) passes the tools to the model natively.
+    /// If not, the agent describes them in the system prompt and reads
+    /// tool calls written as JSON from the reply.
+    fn native_tools(&self) -> bool {
+        false
+    }
+
+    /// Continue the chat in `messages`, offering `tools`, and return the
+    /// assistant's reply.
+    ///
+    /// The default flattens the chat into a single prompt for
+    /// [`generate`](Self::generate); tool calls then come back as JSON in
+    /// the text.
+    async fn chat(&self, messages: &[Message], _tools: &[ToolSpec]) -> Result<Message> {
+        let mut prompt = String::new();
+        for message in messages {
+            let speaker = match message.role {
+                Role::System => {
+                    prompt.push_str(&message.content);
+                    prompt.push_str("\n\n");
+                    continue;
+                }
+                Role::User => "User",
+                Role::Assistant => "Assistant",
+                Role::Tool => "Tool",
+            };
+            prompt.push_str(&format!("{speaker}: {}", message.content));
+            for call in &message.tool_calls {
+                let call = json!({ "tool": call.name, "arguments": call.arguments });
+                prompt.push_str(&call.to_string());
+            }
+            prompt.push('\n');
+        }
+        prompt.push_str("Assistant:");
+        Ok(Message::assistant(&self.generate(&prompt).await?))
+    }
+
+    /// Like [`chat`](Self::chat), but passes the text of the reply to
+    /// `on_text` piece by piece as it is generated. The default calls
+    /// `chat` and passes the whole text at once.
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        let reply = self.chat(messages, tools).await?;
+        on_text(&reply.content);
+        Ok(reply)
+    }
+
+    /// Like [`chat`](Self::chat), but with `model` for this request only,
+    /// leaving the model in use alone. The default cannot switch models.
+    async fn chat_as(
+        &self,
+        model: &str,
+        _messages: &[Message],
+        _tools: &[ToolSpec],
+    ) -> Result<Message> {
+        bail!("this language model backend cannot switch to {model}")
+    }
+
+    /// Like [`chat_stream`](Self::chat_stream), but with `model` for this
+    /// request only. The default cannot switch models.
+    async fn chat_stream_as(
+        &self,
+        model: &str,
+        _messages: &[Message],
+        _tools: &[ToolSpec],
+        _on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        bail!("this language model backend cannot switch to {model}")
+    }
+
+    /// Name of the model in use, if the backend knows it.
+    fn model(&self) -> Option<String> {
+        None
+    }
+
+    /// Models the server offers. The default offers none, so the model
+    /// cannot be switched at runtime.
+    async fn list_models(&self) -> Result<Vec<String>> {
+        bail!("this language model backend cannot switch models")
+    }
+
+    /// Use `model`, one of [`list_models`](Self::list_models), from now on.
+    fn set_model(&self, _model: &str) -> Result<()> {
+        bail!("this language model backend cannot switch models")
+    }
+
+    /// Download `model` to the server. `progress` gets each status
+    /// message and, while layers are downloading, the percentage done.
+    /// The default cannot download.
+    async fn pull_model(
+        &self,
+        _model: &str,
+        _progress: &(dyn for<'a> Fn(&'a str, Option<u8>) + Send + Sync),
+    ) -> Result<()> {
+        bail!("this language model backend cannot download models")
+    }
+
+    /// Use `options` for the following requests. The default ignores
+    /// them.
+    fn set_options(&self, _options: &GenerationOptions) {}
+
+    /// Embedding vectors for `texts`, one per text, from the model named in
+    /// `EMBED_MODEL`. Used to find memories relevant to a command.
+    async fn embed(&self, _texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        bail!("this language model backend cannot compute embeddings")
+    }
+}
+
+/// The embedding model from `EMBED_MODEL`.
+pub fn embed_model() -> String {
+    std::env::var("EMBED_MODEL").unwrap_or_else(|_| "nomic-embed-text".to_string())
+}
+
+/// The backend selected by `LLM_BACKEND`, serving the model named in
+/// `MODEL_NAME` (default `qwen3:1.7b`).
+pub fn from_env() -> Result<Box<dyn LlmBackend>> {
+    let model = std::env::var("MODEL_NAME").unwrap_or_else(|_| "qwen3:1.7b".to_string());
+    let backend = std::env::var("LLM_BACKEND").unwrap_or_else(|_| "ollama".to_string());
+    match backend.trim().to_lowercase().as_str() {
+        "ollama" => Ok(Box::new(OllamaBackend::new(&model))),
+        #[cfg(feature = "openai")]
+        "openai" => Ok(Box::new(openai::OpenAiBackend::from_env(&model)?)),
+        #[cfg(not(feature = "openai"))]
+        "openai" => {
+            bail!("LLM_BACKEND=openai requires Jarvis to be built with `--features openai`")
+        }
+        other => bail!("unknown LLM_BACKEND '{other}' (expected ollama or openai)"),
+    }
+}
+
+/// Backend for a model served by a local Ollama instance.
+pub struct OllamaBackend {
+    client: Ollama,
+    model: Mutex<String>,
+    options: Mutex<GenerationOptions>,
+}
+
+impl OllamaBackend {
+    /// Create a backend for the given model name. The Ollama client will
+    /// connect to the default endpoint at `http://localhost:11434`.
+    pub fn new(model: &str) -> Self {
+        Self {
+            client: Ollama::default(),
+            model: Mutex::new(model.to_string()),
+            options: Mutex::new(GenerationOptions::default()),
+        }
+    }
+
+    /// A chat request to `model` offering `tools` in the form Ollama
+    /// expects.
+    fn chat_request(
+        &self,
+        model: &str,
+        messages: &[Message],
+        tools: &[ToolSpec],
+    ) -> Result<ChatMessageRequest> {
+        let messages = messages.iter().map(to_ollama_message).collect();
+        let tools = tools
+            .iter()
+            .map(|tool| {
+                serde_json::from_value::<ToolInfo>(json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters,
+                    },
+                }))
+                .with_context(|| format!("invalid parameter schema for tool '{}'", tool.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ChatMessageRequest::new(model.to_string(), messages)
+            .tools(tools)
+            .options(self.model_options()))
+    }
+
+    /// The generation options in the form Ollama expects.
+    fn model_options(&self) -> ModelOptions {
+        let options = *self.options.lock().unwrap();
+        let mut converted = ModelOptions::default();
+        if let Some(temperature) = options.temperature {
+            converted = converted.temperature(temperature);
+        }
+        if let Some(top_p) = options.top_p {
+            converted = converted.top_p(top_p);
+        }
+        if let Some(num_ctx) = options.num_ctx {
+            converted = converted.num_ctx(num_ctx);
+        }
+        if let Some(max_tokens) = options.max_tokens {
+            converted = converted.num_predict(max_tokens.min(i32::MAX as u32) as i32);
+        }
+        converted
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OllamaBackend {
+    fn native_tools(&self) -> bool {
+        true
+    }
+
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        let request =
+            GenerationRequest::new(self.model.lock().unwrap().clone(), prompt.to_string())
+                .options(self.model_options());
+        let response = self
+            .client
+            .generate(request)
+            .await
+            .context("failed to query local language model")?;
+        Ok(response.response)
+    }
+
+    async fn chat(&self, messages: &[Message], tools: &[ToolSpec]) -> Result<Message> {
+        let model = self.model.lock().unwrap().clone();
+        self.chat_as(&model, messages, tools).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        let model = self.model.lock().unwrap().clone();
+        self.chat_stream_as(&model, messages, tools, on_text).await
+    }
+
+    async fn chat_as(
+        &self,
+        model: &str,
+        messages: &[Message],
+        tools: &[ToolSpec],
+    ) -> Result<Message> {
+        let request = self.chat_request(model, messages, tools)?;
+        let response = self
+            .client
+            .send_chat_messages(request)
+            .await
+            .context("failed to query local language model")?;
+        let message = response.message;
+        Ok(Message {
+            role: Role::Assistant,
+            content: message.content,
+            tool_calls: message
+                .tool_calls
+                .into_iter()
+                .map(from_ollama_call)
+                .collect(),
+        })
+    }
+
+    async fn chat_stream_as(
+        &self,
+        model: &str,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        let request = self.chat_request(model, messages, tools)?;
+        let mut stream = self
+            .client
+            .send_chat_messages_stream(request)
+            .await
+            .context("failed to query local language model")?;
+        let mut reply = Message::assistant("");
+        while let Some(chunk) = stream.next().await {
+            let chunk =
+                chunk.map_err(|()| anyhow!("the language model stream ended unexpectedly"))?;
+            if !chunk.message.content.is_empty() {
+                on_text(&chunk.message.content);
+                reply.content.push_str(&chunk.message.content);
+            }
+            reply
+                .tool_calls
+                .extend(chunk.message.tool_calls.into_iter().map(from_ollama_call));
+            if chunk.done {
+                break;
+            }
+        }
+        Ok(reply)
+    }
+
+    fn model(&self) -> Option<String> {
+        Some(self.model.lock().unwrap().clone())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let models = self
+            .client
+            .list_local_models()
+            .await
+            .context("failed to list the Ollama models")?;
+        Ok(models.into_iter().map(|model| model.name).collect())
+    }
+
+    fn set_model(&self, model: &str) -> Result<()> {
+        *self.model.lock().unwrap() = model.to_string();
+        Ok(())
+    }
+
+    async fn pull_model(
+        &self,
+        model: &str,
+        progress: &(dyn for<'a> Fn(&'a str, Option<u8>) + Send + Sync),
+    ) -> Result<()> {
+        let mut stream = self
+            .client
+            .pull_model_stream(model.to_string(), false)
+            .await
+            .with_context(|| format!("failed to download {model}"))?;
+        while let Some(status) = stream.next().await {
+            let status = status.with_context(|| format!("failed to download {model}"))?;
+            let percent = match (status.completed, status.total) {
+                (Some(done), Some(total)) if total > 0 => Some((done * 100 / total).min(100) as u8),
+                _ => None,
+            };
+            progress(&status.message, percent);
+        }
+        Ok(())
+    }
+
+    fn set_options(&self, options: &GenerationOptions) {
+        *self.options.lock().unwrap() = *options;
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = GenerateEmbeddingsRequest::new(embed_model(), texts.to_vec().into());
+        let response = self
+            .client
+            .generate_embeddings(request)
+            .await
+            .context("failed to compute embeddings")?;
+        Ok(response.embeddings)
+    }
+}
+
+/// Model names by what the user calls them, from `MODEL_ALIASES`, e.g.
+/// `big=qwen3:14b,small=qwen3:1.7b`.
+fn model_aliases() -> Vec<(String, String)> {
+    env_list("MODEL_ALIASES", &[])
+        .iter()
+        .filter_map(|entry| {
+            let (alias, model) = entry.split_once('=')?;
+            Some((alias.trim().to_lowercase(), model.trim().to_string()))
+        })
+        .collect()
+}
+
+static MODEL_COMMAND: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?:please )?(?:use|switch to|change to|load) (?:the )?(?:(?:model|llm) (.+)|(.+?) (?:model|llm))$",
+    )
+    .unwrap()
+});
+
+/// The model `command` asks to switch to, as spoken: "use model llama3",
+/// "switch to the big model".
+pub fn model_switch_target(command: &str) -> Option<String> {
+    let command = command
+        .trim()
+        .trim_end_matches(['.', '!', '?'])
+        .to_lowercase();
+    let captures = MODEL_COMMAND.captures(&command)?;
+    let name = captures.get(1).or_else(|| captures.get(2))?;
+    Some(name.as_str().trim().to_string())
+}
+
+/// Find the model the user means by `spoken` among `available`: an alias
+/// from `MODEL_ALIASES`, an exact name (tag `:latest` optional), or the
+/// start of exactly one name (so "mistral" finds `mistral:7b`). Spoken
+/// numbers are accepted for digits. On failure the candidates are
+/// returned, none if nothing matched.
+pub fn find_model(spoken: &str, available: &[String]) -> Result<String, Vec<String>> {
+    let spoken = spoken.to_lowercase();
+    let wanted = model_aliases()
+        .into_iter()
+        .find(|(alias, _)| *alias == spoken)
+        .map(|(_, model)| model)
+        .unwrap_or(spoken);
+    let wanted = normalize_model_name(&wanted);
+    if wanted.is_empty() {
+        return Err(Vec::new());
+    }
+    // Ollama names untagged models `<name>:latest`.
+    let latest = format!("{wanted}latest");
+    if let Some(exact) = available.iter().find(|name| {
+        let name = normalize_model_name(name);
+        name == wanted || name == latest
+    }) {
+        return Ok(exact.clone());
+    }
+    let matches: Vec<String> = available
+        .iter()
+        .filter(|name| normalize_model_name(name).starts_with(&wanted))
+        .cloned()
+        .collect();
+    match matches.as_slice() {
+        [only] => Ok(only.clone()),
+        _ => Err(matches),
+    }
+}
+
+/// A model name reduced to letters and digits, with spoken numbers
+/// turned into digits: "qwen three one point seven b" and `qwen3:1.7b`
+/// both become `qwen317b`.
+fn normalize_model_name(name: &str) -> String {
+    const NUMBERS: &[&str] = &[
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+    ];
+    name.to_lowercase()
+        .split_whitespace()
+        .map(|word| match NUMBERS.iter().position(|n| *n == word) {
+            Some(digit) => digit.to_string(),
+            None if word == "point" || word == "dot" => String::new(),
+            None => word.to_string(),
+        })
+        .collect::<String>()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+fn from_ollama_call(call: OllamaToolCall) -> ToolCall {
+    ToolCall {
+        name: call.function.name,
+        arguments: call.function.arguments,
+    }
+}
+
+fn to_ollama_message(message: &Message) -> ChatMessage {
+    let role = match message.role {
+        Role::System => MessageRole::System,
+        Role::User => MessageRole::User,
+        Role::Assistant => MessageRole::Assistant,
+        Role::Tool => MessageRole::Tool,
+    };
+    let mut converted = ChatMessage::new(role, message.content.clone());
+    converted.tool_calls = message
+        .tool_calls
+        .iter()
+        .map(|call| OllamaToolCall {
+            function: ToolCallFunction {
+                name: call.name.clone(),
+                arguments: call.arguments.clone(),
+            },
+        })
+        .collect();
+    converted
+}
@@ -0,0 +1,196 @@
+//! A scripted backend for testing the agent without a model server.
+//!
+//! [`MockLlm`] answers every request with the next of its canned replies,
+//! whatever it was asked, and keeps the chats it was sent so that a test
+//! can check what the agent told the model:
+//!
+//! ```no_run
+//! use jarvis_rust::agent::Agent;
+//! use jarvis_rust::llm::mock::{MockLlm, MockReply};
+//! use jarvis_rust::tools::ToolRegistry;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let llm = MockLlm::new([
+//!     MockReply::text(r#"{"tool": "shell_task", "arguments": {"command": "ls"}}"#),
+//!     MockReply::text("There are three files."),
+//! ]);
+//! let agent = Agent::with_backend(Box::new(llm.clone()), ToolRegistry::new());
+//! agent.handle_command("what's here?").await?;
+//! assert_eq!(llm.requests().len(), 2);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Clones share the script, so keep one to inspect after handing the other
+//! to the agent. Once the script runs out every request fails.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use super::{LlmBackend, Message, ToolCall, ToolSpec};
+
+/// One canned answer of a [`MockLlm`].
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    /// An assistant message, with native tool calls if any.
+    Message(Message),
+    /// The request fails with this error.
+    Error(String),
+    /// The request never finishes, so that the agent times out.
+    Hang,
+    /// This text, generated a word at a time with the pause between
+    /// words, like a long answer from a slow model.
+    Slow(String, Duration),
+}
+
+impl MockReply {
+    /// A reply with this text: an answer, a JSON tool call, a `<think>`
+    /// block or anything else a model might write.
+    pub fn text(content: &str) -> Self {
+        Self::Message(Message::assistant(content))
+    }
+
+    /// A reply with native tool calls and no text.
+    pub fn tool_calls(calls: Vec<ToolCall>) -> Self {
+        let mut message = Message::assistant("");
+        message.tool_calls = calls;
+        Self::Message(message)
+    }
+
+    /// A failed request.
+    pub fn error(message: &str) -> Self {
+        Self::Error(message.to_string())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    replies: VecDeque<MockReply>,
+    requests: Vec<Vec<Message>>,
+}
+
+/// A backend that replays canned replies; see the [module
+/// docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct MockLlm {
+    script: Arc<Mutex<Script>>,
+    native_tools: bool,
+}
+
+impl MockLlm {
+    /// A backend giving `replies` in order. Tool calls are expected as
+    /// JSON in the text, as from a model without native tool calling.
+    pub fn new(replies: impl IntoIterator<Item = MockReply>) -> Self {
+        Self {
+            script: Arc::new(Mutex::new(Script {
+                replies: replies.into_iter().collect(),
+                requests: Vec::new(),
+            })),
+            native_tools: false,
+        }
+    }
+
+    /// Claim native tool calling, so that the agent offers the tools
+    /// through the API rather than in the system prompt.
+    pub fn with_native_tools(mut self) -> Self {
+        self.native_tools = true;
+        self
+    }
+
+    /// Add `reply` to the end of the script.
+    pub fn push(&self, reply: MockReply) {
+        self.script.lock().unwrap().replies.push_back(reply);
+    }
+
+    /// The chats sent so far, one per request.
+    pub fn requests(&self) -> Vec<Vec<Message>> {
+        self.script.lock().unwrap().requests.clone()
+    }
+
+    /// The number of replies not yet given.
+    pub fn remaining(&self) -> usize {
+        self.script.lock().unwrap().replies.len()
+    }
+
+    fn next(&self, messages: &[Message]) -> Option<MockReply> {
+        let mut script = self.script.lock().unwrap();
+        script.requests.push(messages.to_vec());
+        script.replies.pop_front()
+    }
+
+    /// The next reply, its text passed to `on_text` a word at a time.
+    async fn reply(
+        &self,
+        messages: &[Message],
+        on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        let (message, pause) = match self.next(messages) {
+            Some(MockReply::Message(message)) => (message, None),
+            Some(MockReply::Slow(text, pause)) => (Message::assistant(&text), Some(pause)),
+            Some(MockReply::Error(message)) => return Err(anyhow!(message)),
+            Some(MockReply::Hang) => std::future::pending().await,
+            None => return Err(anyhow!("the mock backend has no replies left")),
+        };
+        for piece in message.content.split_inclusive(' ') {
+            if let Some(pause) = pause {
+                tokio::time::sleep(pause).await;
+            }
+            on_text(piece);
+        }
+        Ok(message)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for MockLlm {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        let reply = self.chat(&[Message::user(prompt)], &[]).await?;
+        Ok(reply.content)
+    }
+
+    fn native_tools(&self) -> bool {
+        self.native_tools
+    }
+
+    async fn chat(&self, messages: &[Message], _tools: &[ToolSpec]) -> Result<Message> {
+        self.reply(messages, &|_| {}).await
+    }
+
+    /// Passes the text on a word at a time, as a real model would.
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        _tools: &[ToolSpec],
+        on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        self.reply(messages, on_text).await
+    }
+
+    /// Any model answers from the same script.
+    async fn chat_as(
+        &self,
+        _model: &str,
+        messages: &[Message],
+        tools: &[ToolSpec],
+    ) -> Result<Message> {
+        self.chat(messages, tools).await
+    }
+
+    async fn chat_stream_as(
+        &self,
+        _model: &str,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        self.chat_stream(messages, tools, on_text).await
+    }
+
+    fn model(&self) -> Option<String> {
+        Some("mock".to_string())
+    }
+}
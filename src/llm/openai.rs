@@ -0,0 +1,356 @@
+//! Backend for servers speaking the OpenAI chat completions API: llama.cpp
+//! server, LM Studio, vLLM, OpenAI itself and other cloud services with a
+//! compatible endpoint. Only built with the `openai` feature:
+//!
+//! ```bash
+//! cargo build --release --features openai
+//! ```
+//!
+//! Selected with `LLM_BACKEND=openai`:
+//!
+//!  * `OPENAI_BASE_URL`: the API root, default `http://localhost:8080/v1`
+//!    (llama.cpp server). LM Studio uses `http://localhost:1234/v1`, vLLM
+//!    `http://localhost:8000/v1` and OpenAI `https://api.openai.com/v1`.
+//!  * `OPENAI_API_KEY`: sent as a bearer token if set. Local servers
+//!    usually do not need one.
+//!  * `MODEL_NAME`: the model to ask for.
+//!
+//! Embeddings use the server's `/embeddings` endpoint with `EMBED_MODEL`.
+//! `LLM_NUM_CTX` has no equivalent in this API and is ignored.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+
+use super::{GenerationOptions, LlmBackend, Message, Role, ToolCall, ToolSpec};
+
+/// Backend for an OpenAI-compatible chat completions endpoint.
+pub struct OpenAiBackend {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: Mutex<String>,
+    options: Mutex<GenerationOptions>,
+}
+
+impl OpenAiBackend {
+    /// A backend for `model` served at `base_url`, e.g.
+    /// `http://localhost:8080/v1`.
+    pub fn new(base_url: &str, api_key: Option<String>, model: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(5))
+            .build()
+            .context("failed to create HTTP client")?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model: Mutex::new(model.to_string()),
+            options: Mutex::new(GenerationOptions::default()),
+        })
+    }
+
+    /// A backend configured by `OPENAI_BASE_URL` and `OPENAI_API_KEY`.
+    pub fn from_env(model: &str) -> Result<Self> {
+        let base_url = std::env::var("OPENAI_BASE_URL")
+            .unwrap_or_else(|_| "http://localhost:8080/v1".to_string());
+        let api_key = std::env::var("OPENAI_API_KEY")
+            .ok()
+            .filter(|key| !key.is_empty());
+        Self::new(&base_url, api_key, model)
+    }
+
+    /// Post a chat completion request to `model`.
+    async fn request(
+        &self,
+        model: &str,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        stream: bool,
+    ) -> Result<reqwest::Response> {
+        let mut body = json!({
+            "model": model,
+            "messages": to_openai_messages(messages),
+            "stream": stream,
+        });
+        let options = *self.options.lock().unwrap();
+        if let Some(temperature) = options.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = options.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(max_tokens) = options.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if !tools.is_empty() {
+            body["tools"] = tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.parameters,
+                        },
+                    })
+                })
+                .collect();
+        }
+        let request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+        self.send(request).await
+    }
+
+    /// Send `request` with the API key. A response with an error status is
+    /// turned into an error carrying the server's message.
+    async fn send(&self, mut request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("cannot reach {}", self.base_url))?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            bail!("request failed with {status}: {}", message.trim());
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiBackend {
+    fn native_tools(&self) -> bool {
+        true
+    }
+
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        Ok(self.chat(&[Message::user(prompt)], &[]).await?.content)
+    }
+
+    async fn chat(&self, messages: &[Message], tools: &[ToolSpec]) -> Result<Message> {
+        let model = self.model.lock().unwrap().clone();
+        self.chat_as(&model, messages, tools).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        let model = self.model.lock().unwrap().clone();
+        self.chat_stream_as(&model, messages, tools, on_text).await
+    }
+
+    async fn chat_as(
+        &self,
+        model: &str,
+        messages: &[Message],
+        tools: &[ToolSpec],
+    ) -> Result<Message> {
+        let response: Value = self
+            .request(model, messages, tools, false)
+            .await?
+            .json()
+            .await
+            .context("invalid chat completion response")?;
+        let message = &response["choices"][0]["message"];
+        if message.is_null() {
+            bail!("chat completion response has no choices: {response}");
+        }
+        let mut reply = Message::assistant(message["content"].as_str().unwrap_or_default());
+        for call in message["tool_calls"].as_array().into_iter().flatten() {
+            let function = &call["function"];
+            reply.tool_calls.push(parse_tool_call(
+                function["name"].as_str().unwrap_or_default(),
+                function["arguments"].as_str().unwrap_or("{}"),
+            )?);
+        }
+        Ok(reply)
+    }
+
+    async fn chat_stream_as(
+        &self,
+        model: &str,
+        messages: &[Message],
+        tools: &[ToolSpec],
+        on_text: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Message> {
+        let mut body = self
+            .request(model, messages, tools, true)
+            .await?
+            .bytes_stream();
+        let mut reply = Message::assistant("");
+        // Tool calls arrive in pieces: the name first, then the arguments
+        // a few characters at a time.
+        let mut calls: Vec<(String, String)> = Vec::new();
+        // A server-sent event may be split between two network chunks.
+        let mut pending: Vec<u8> = Vec::new();
+        'events: while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| anyhow!("the chat completion stream failed: {e}"))?;
+            pending.extend_from_slice(&chunk);
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let Some(data) = line.trim().strip_prefix("data:") else {
+                    continue;
+                };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    break 'events;
+                }
+                let event: Value = serde_json::from_str(data)
+                    .with_context(|| format!("invalid chat completion event: {data}"))?;
+                let delta = &event["choices"][0]["delta"];
+                if let Some(text) = delta["content"].as_str().filter(|t| !t.is_empty()) {
+                    on_text(text);
+                    reply.content.push_str(text);
+                }
+                for call in delta["tool_calls"].as_array().into_iter().flatten() {
+                    let index = call["index"].as_u64().unwrap_or(0) as usize;
+                    if calls.len() <= index {
+                        calls.resize(index + 1, Default::default());
+                    }
+                    let function = &call["function"];
+                    if let Some(name) = function["name"].as_str() {
+                        calls[index].0.push_str(name);
+                    }
+                    if let Some(arguments) = function["arguments"].as_str() {
+                        calls[index].1.push_str(arguments);
+                    }
+                }
+            }
+        }
+        for (name, arguments) in calls {
+            reply.tool_calls.push(parse_tool_call(&name, &arguments)?);
+        }
+        Ok(reply)
+    }
+
+    fn model(&self) -> Option<String> {
+        Some(self.model.lock().unwrap().clone())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let request = self.client.get(format!("{}/models", self.base_url));
+        let response: Value = self
+            .send(request)
+            .await?
+            .json()
+            .await
+            .context("invalid model list")?;
+        Ok(response["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|model| model["id"].as_str().map(str::to_string))
+            .collect())
+    }
+
+    fn set_model(&self, model: &str) -> Result<()> {
+        *self.model.lock().unwrap() = model.to_string();
+        Ok(())
+    }
+
+    fn set_options(&self, options: &GenerationOptions) {
+        *self.options.lock().unwrap() = *options;
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = self
+            .client
+            .post(format!("{}/embeddings", self.base_url))
+            .json(&json!({ "model": super::embed_model(), "input": texts }));
+        let response: Value = self
+            .send(request)
+            .await?
+            .json()
+            .await
+            .context("invalid embeddings response")?;
+        response["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|item| {
+                item["embedding"]
+                    .as_array()
+                    .map(|values| {
+                        values
+                            .iter()
+                            .filter_map(Value::as_f64)
+                            .map(|v| v as f32)
+                            .collect()
+                    })
+                    .ok_or_else(|| anyhow!("embeddings response without vectors"))
+            })
+            .collect()
+    }
+}
+
+/// A tool call whose arguments came as a JSON string.
+fn parse_tool_call(name: &str, arguments: &str) -> Result<ToolCall> {
+    let arguments = if arguments.trim().is_empty() {
+        json!({})
+    } else {
+        serde_json::from_str(arguments)
+            .with_context(|| format!("invalid arguments for tool '{name}': {arguments}"))?
+    };
+    Ok(ToolCall {
+        name: name.to_string(),
+        arguments,
+    })
+}
+
+/// The chat in the form the API expects. Tool calls need ids that the
+/// following tool messages refer to; they are numbered here.
+fn to_openai_messages(messages: &[Message]) -> Vec<Value> {
+    let mut next_id = 0;
+    let mut unanswered: VecDeque<String> = VecDeque::new();
+    messages
+        .iter()
+        .map(|message| match message.role {
+            Role::System => json!({ "role": "system", "content": message.content }),
+            Role::User => json!({ "role": "user", "content": message.content }),
+            Role::Assistant if message.tool_calls.is_empty() => {
+                json!({ "role": "assistant", "content": message.content })
+            }
+            Role::Assistant => {
+                let calls: Vec<Value> = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| {
+                        let id = format!("call_{next_id}");
+                        next_id += 1;
+                        unanswered.push_back(id.clone());
+                        json!({
+                            "id": id,
+                            "type": "function",
+                            "function": {
+                                "name": call.name,
+                                "arguments": call.arguments.to_string(),
+                            },
+                        })
+                    })
+                    .collect();
+                json!({ "role": "assistant", "content": message.content, "tool_calls": calls })
+            }
+            Role::Tool => json!({
+                "role": "tool",
+                "tool_call_id": unanswered.pop_front().unwrap_or_default(),
+                "content": message.content,
+            }),
+        })
+        .collect()
+}
@@ -1,6 +1,8 @@
 //! Entry point for the Jarvis Rust implementation.
 //!
-//! This binary replicates the core functionality of the Python prototype:
+//! The assistant itself lives in the `jarvis_rust` library (see
+//! `pipeline.rs`); this binary parses the command line and runs it. It
+//! replicates the core functionality of the Python prototype:
 //!
 //!  * Listens for a wake word (default "Jarvis") using offline speech
 //!    recognition via the Vosk library.
@@ -8,8 +10,8 @@
 //!    commands, consults a local language model via Ollama and speaks
 //!    the response out loud.
 //!  * Supports two tools—`shell_task` and `codex_cli_task`—which the
-//!    language model can invoke by returning a JSON object. When the
-//!    user says "shadow" the assistant goes back to sleep.
+//!    language model invokes through Ollama's native tool calling. When
+//!    the user says "shadow" the assistant goes back to sleep.
 //!
 //! The program is highly configurable via environment variables:
 //!
@@ -21,122 +23,382 @@
 //!    returning to idle.
 //!  * `MIC_INDEX`/`MIC_NAME_KEYWORD` (optional): control which input
 //!    device the recogniser uses (see `speech.rs` for details).
+//!  * `NOISE_WORDS`/`NOISE_PHRASES` (optional): spurious recogniser output
+//!    to ignore (see `transcript.rs` for details).
+//!
+//! `jarvis --text` skips audio entirely and reads commands from stdin,
+//! which is useful for exercising the agent and tools on machines without
+//! sound hardware.
 
 use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 
-mod agent;
-mod jarvis_io;
-mod speech;
-mod tools;
-mod tts_engine;
+mod cli;
 
-use agent::Agent;
-use jarvis_io::JarvisIO;
-use speech::SpeechRecognizer;
+use cli::{Command, ExportFormat};
+use futures_util::StreamExt;
+use jarvis_rust::audio_output::AudioOutput;
+use jarvis_rust::documents::DocumentIndex;
+use jarvis_rust::events::JarvisEvent;
+use jarvis_rust::llm;
+use jarvis_rust::redact;
+use jarvis_rust::session;
+use jarvis_rust::stats::{self, Stats};
+use jarvis_rust::tts_engine::{ssml, Prosody, Speaker, TtsEngine, ENGINES};
+use jarvis_rust::wake::WakeLog;
+use jarvis_rust::Jarvis;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::signal;
-use tokio::time::sleep;
-use tts_engine::TtsEngine;
 
-// Note: we used to filter out common filler words ("the", "uh", "um", etc.)
-// from the beginning and end of recognised phrases to reduce false
-// activations. However, some users found this overly aggressive and
-// confusing when legitimate words were removed. The current
-// implementation processes the transcript as‑is without trimming
-// specific tokens.
+/// Headless REPL: read one command per line from stdin, print the reply
+/// and optionally speak it. Ends on EOF, `exit` or `quit`. With `json`,
+/// each reply is printed as one JSON object for programs driving Jarvis.
+async fn run_text_repl(speak: bool, json: bool) -> Result<()> {
+    let mut builder = Jarvis::builder().without_asr();
+    if !speak {
+        builder = builder.without_tts();
+    }
+    let jarvis = builder.build()?;
+    // Show what running tools print while the reply is awaited.
+    if !json {
+        let events = jarvis.events();
+        tokio::spawn(async move {
+            tokio::pin!(events);
+            while let Some(event) = events.next().await {
+                if let JarvisEvent::ToolOutput { line, .. } = event {
+                    eprintln!("  {line}");
+                }
+            }
+        });
+    }
 
-/// Single tokens that are likely to be false positives from the speech
-/// recogniser. When the entire recognised text matches one of these
-/// strings exactly (case insensitive), Jarvis will treat the result as
-/// silence and continue listening. This helps filter out spurious
-/// words like "the" that Vosk sometimes produces when the microphone
-/// is quiet. These tokens are ignored only when they constitute the
-/// entire transcript; they are not removed from legitimate commands.
-const NOISE_WORDS: &[&str] = &["the", "uh", "um", "a"];
+    // Pomodoro sessions and breaks are announced while waiting for input.
+    let (timer_tx, mut timers) = tokio::sync::mpsc::unbounded_channel();
+    let events = jarvis.events();
+    tokio::spawn(async move {
+        tokio::pin!(events);
+        while let Some(event) = events.next().await {
+            if let JarvisEvent::Timer { text } = event {
+                if timer_tx.send(text).is_err() {
+                    break;
+                }
+            }
+        }
+    });
 
-/// Trim leading/trailing single-token noise words (e.g. "the", "uh")
-fn strip_noise_words(text: &str) -> String {
-    let mut tokens: Vec<&str> = text.split_whitespace().collect();
-    // Drop noise words from the start
-    while tokens
-        .first()
-        .map_or(false, |t| NOISE_WORDS.contains(&t.to_lowercase().as_str()))
-    {
-        tokens.remove(0);
+    let mut stdout = tokio::io::stdout();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        if !json {
+            stdout.write_all(b"> ").await?;
+            stdout.flush().await?;
+        }
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            Some(text) = timers.recv() => {
+                if json {
+                    let event = JarvisEvent::Timer { text: text.clone() };
+                    println!("{}", serde_json::to_string(&event)?);
+                } else {
+                    println!("\n{text}");
+                }
+                if speak {
+                    if let Err(e) = jarvis.say(&text).await {
+                        log::warn!("Failed to speak announcement: {e}");
+                    }
+                }
+                continue;
+            }
+        };
+        let Some(line) = line else {
+            break;
+        };
+        let command = line.trim();
+        if command.is_empty() {
+            continue;
+        }
+        if matches!(command, "exit" | "quit") {
+            break;
+        }
+        match jarvis.ask_structured(command).await {
+            Ok(response) => {
+                let reply = &response.spoken_text;
+                if json {
+                    println!("{}", serde_json::to_string(&response)?);
+                } else {
+                    println!("{}", ssml::strip(reply));
+                }
+                if speak {
+                    if let Err(e) = jarvis.say(reply).await {
+                        log::warn!("Failed to speak reply: {e}");
+                    }
+                }
+            }
+            Err(e) if json => {
+                println!("{}", serde_json::json!({ "error": format!("{e:#}") }))
+            }
+            Err(e) => eprintln!("error: {e:#}"),
+        }
+        for job in jarvis.jobs().take_finished() {
+            if json {
+                println!("{}", serde_json::to_string(&job)?);
+            } else {
+                println!("{}", job.announcement());
+            }
+        }
     }
-    // Drop noise words from the end
-    while tokens
-        .last()
-        .map_or(false, |t| NOISE_WORDS.contains(&t.to_lowercase().as_str()))
-    {
-        tokens.pop();
+    Ok(())
+}
+
+/// Print the voices offered by the engine selected with `VOICE_ENGINE`,
+/// one per line, in a form that can be pasted into `VOICE_NAME`.
+fn list_voices() -> Result<()> {
+    let engine = env::var("VOICE_ENGINE").unwrap_or_else(|_| "system".to_string());
+    let voices = TtsEngine::new()?.list_voices()?;
+    if voices.is_empty() {
+        println!("No voices found for VOICE_ENGINE={engine}.");
+        return Ok(());
+    }
+    println!("{:<32} {:<12} GENDER", "NAME", "LANGUAGE");
+    for voice in voices {
+        println!(
+            "{:<32} {:<12} {}",
+            voice.name,
+            voice.language.as_deref().unwrap_or("-"),
+            voice.gender.as_deref().unwrap_or("-")
+        );
     }
-    tokens.join(" ")
+    println!("\nSet VOICE_NAME to one of the names above (partial matches work).");
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Load environment variables from `.env` if present.
-    dotenvy::dotenv().ok();
-    env_logger::init();
+/// Speak `text` once, or save it to `out`.
+async fn say(text: &str, out: Option<&Path>) -> Result<()> {
+    let mut tts = TtsEngine::from_env()?;
+    match out {
+        Some(path) => {
+            tts.speak_to_file(text, path).await?;
+            println!("Saved {}", path.display());
+            Ok(())
+        }
+        None => tts.speak(text).await,
+    }
+}
 
-    // Retrieve required and optional configuration.
-    let model_path = env::var("VOSK_MODEL_PATH")
-        .context("VOSK_MODEL_PATH environment variable must point to a Vosk model directory")?;
-    let model_name = env::var("MODEL_NAME").unwrap_or_else(|_| "qwen3:1.7b".to_string());
-    let trigger_word = env::var("TRIGGER_WORD").unwrap_or_else(|_| "jarvis".to_string());
-    let timeout_secs = env::var("CONVERSATION_TIMEOUT")
+/// Longest time a sample phrase may take before the engine is reported as
+/// hanging.
+const SPEAK_TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Initialise the configured TTS engine (or every engine with `all`),
+/// speak a sample phrase with each and report which ones work.
+async fn speak_test(all: bool) -> Result<()> {
+    let configured = env::var("VOICE_ENGINE")
+        .map(|v| v.to_lowercase())
         .ok()
-        .and_then(|v| v.parse::<u64>().ok())
-        .unwrap_or(30);
-    let voice_name = env::var("VOICE_NAME").ok();
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "system".to_string());
+    let mut engines = vec![configured.clone()];
+    if all {
+        engines.extend(
+            ENGINES
+                .iter()
+                .filter(|e| **e != configured)
+                .map(|e| e.to_string()),
+        );
+    } else if matches!(configured.as_str(), "azure" | "google" | "elevenlabs") {
+        // The local engine used while the service is unreachable.
+        engines.push(
+            env::var("VOICE_FALLBACK_ENGINE")
+                .map(|e| e.to_lowercase())
+                .unwrap_or_else(|_| "system".to_string()),
+        );
+    }
 
-    // Initialise audio input and speech recognition.
-    let recogniser = SpeechRecognizer::new(&model_path)?;
+    match AudioOutput::from_env() {
+        Ok(Some(output)) => println!("Output device: {}", output.name()),
+        Ok(None) => match AudioOutput::default_device() {
+            Ok(output) => println!("Output device: system default ({})", output.name()),
+            Err(e) => println!("Output device: {e:#}"),
+        },
+        Err(e) => println!("Output device: {e:#}"),
+    }
 
-    // Initialise TTS. If a voice is specified attempt to select it.
-    let mut tts = TtsEngine::new()?;
-    if let Some(name) = voice_name {
-        match tts.set_voice_by_name(&name) {
-            Ok(_) => log::info!("Using voice: {}", name),
-            Err(e) => log::warn!(
-                "Failed to set voice '{}': {e}. Falling back to default.",
-                name
-            ),
+    let mut working = Vec::new();
+    for engine in &engines {
+        println!("\n{engine}:");
+        let mut tts = match TtsEngine::for_engine(engine) {
+            Ok(tts) => tts,
+            Err(e) => {
+                println!("  not available: {e:#}");
+                continue;
+            }
+        };
+        match tts.list_voices() {
+            Ok(voices) => println!("  {} voices installed", voices.len()),
+            Err(e) => println!("  cannot list voices: {e:#}"),
+        }
+        if let Ok(name) = env::var("VOICE_NAME") {
+            match tts.set_voice(&name) {
+                Ok(()) => println!("  voice '{name}' selected"),
+                Err(e) => println!("  voice '{name}' not found, using the default: {e:#}"),
+            }
+        }
+        Prosody::from_env().apply(&mut tts);
+        let sample = format!("This is the {engine} voice. If you can hear me, speech works.");
+        let started = Instant::now();
+        match tokio::time::timeout(SPEAK_TEST_TIMEOUT, tts.speak(&sample)).await {
+            Ok(Ok(())) => {
+                println!("  spoke in {:.1}s", started.elapsed().as_secs_f32());
+                working.push(engine.as_str());
+            }
+            Ok(Err(e)) => println!("  failed to speak: {e:#}"),
+            Err(_) => {
+                tts.stop().await.ok();
+                println!(
+                    "  no result after {}s; the engine is hanging",
+                    SPEAK_TEST_TIMEOUT.as_secs()
+                );
+            }
         }
     }
 
-    // Initialise the language model client and agent.
-    let agent = Agent::new(&model_name).await?;
+    println!();
+    if working.is_empty() {
+        println!("No engine could speak. Check that the engine is installed and see the README.");
+    } else {
+        println!("Working engines: {}", working.join(", "));
+        println!("If you heard nothing, check the output device and its volume.");
+    }
+    Ok(())
+}
 
-    // Audio capture durations for wake word detection and user commands.
-    // These can be tuned via environment variables for faster responsiveness.
-    let idle_listen_secs: u64 = env::var("IDLE_LISTEN_SECS")
-        .ok()
-        .and_then(|v| v.parse::<u64>().ok())
-        .unwrap_or(2);
-    let convo_listen_secs: u64 = env::var("CONVO_LISTEN_SECS")
-        .ok()
-        .and_then(|v| v.parse::<u64>().ok())
-        .unwrap_or(8);
+/// Add the text files under `paths` to the document index.
+async fn index_documents(paths: &[PathBuf]) -> Result<()> {
+    let backend = llm::from_env()?;
+    let mut index = DocumentIndex::from_env();
+    for path in paths {
+        let report = index.index(path, backend.as_ref()).await?;
+        println!(
+            "{}: {} files indexed, {} unchanged, {} removed",
+            path.display(),
+            report.indexed,
+            report.unchanged,
+            report.removed
+        );
+    }
+    println!("{} chunks in the index.", index.chunks().len());
+    Ok(())
+}
 
-    // Conversation state.
-    let mut conversation_mode = false;
-    let mut last_interaction = Instant::now();
-    let timeout = Duration::from_secs(timeout_secs);
+/// Print how often each tool and model was used, failed and how long it
+/// took, as a table or as JSON.
+fn print_stats(json: bool) -> Result<()> {
+    let Some(path) = stats::stats_file() else {
+        anyhow::bail!("no home directory; set STATS_FILE");
+    };
+    let stats = Stats::load(path);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats.summary())?);
+    } else {
+        println!("{}", stats.report().trim_end());
+    }
+    Ok(())
+}
 
-    let jarvis_io = JarvisIO::new();
-    jarvis_io.set_pid();
-    jarvis_io.write_status("idle");
+/// Print the recorded sessions with their number of turns.
+fn list_sessions() -> Result<()> {
+    let ids = session::list();
+    if ids.is_empty() {
+        println!("No sessions recorded yet.");
+        return Ok(());
+    }
+    for id in ids {
+        match session::load(&id) {
+            Ok((_, turns)) => {
+                let first = turns.first().map(|t| t.user.as_str()).unwrap_or_default();
+                println!("{id}  {:>3} turns  {first}", turns.len());
+            }
+            Err(e) => println!("{id}  {e:#}"),
+        }
+    }
+    Ok(())
+}
 
-    log::info!(
-        "Jarvis initialised. Waiting for wake word '{}' (idle listen: {}s, convo listen: {}s).",
-        trigger_word,
-        idle_listen_secs,
-        convo_listen_secs
-    );
+/// Print a session transcript.
+fn export_session(id: &str, format: ExportFormat) -> Result<()> {
+    let (id, turns) = session::load(id)?;
+    match format {
+        ExportFormat::Markdown => print!("{}", session::to_markdown(&id, &turns)),
+        ExportFormat::Jsonl => {
+            for turn in &turns {
+                println!("{}", serde_json::to_string(turn)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Load environment variables from `.env` if present.
+    dotenvy::dotenv().ok();
+    // Secrets in log messages are masked; see `redact.rs`.
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            let style = buf.default_level_style(record.level());
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {}",
+                buf.timestamp(),
+                style.value(record.level()),
+                record.target(),
+                redact::redact_log(&record.args().to_string())
+            )
+        })
+        .init();
+    redact::load();
+
+    match Command::parse(env::args().skip(1))? {
+        Command::Run => {}
+        Command::Text { speak, json } => return run_text_repl(speak, json).await,
+        Command::TuneWake => {
+            println!("{}", WakeLog::new().tuning_report()?);
+            return Ok(());
+        }
+        Command::Voices => return list_voices(),
+        Command::Say { text, out } => return say(&text, out.as_deref()).await,
+        Command::SpeakTest { all } => return speak_test(all).await,
+        Command::Index { paths } => return index_documents(&paths).await,
+        Command::Sessions => return list_sessions(),
+        Command::Stats { json } => return print_stats(json),
+        Command::ExportSession { id, format } => return export_session(&id, format),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+    }
+
+    // Assemble the pipeline from environment configuration.
+    let jarvis = Arc::new(Jarvis::builder().build()?);
+
+    // SIGUSR1 interrupts the reply being spoken or generated, e.g. from a hotkey:
+    // `kill -USR1 $(cat ~/.jarvis/jarvis)`.
+    #[cfg(unix)]
+    {
+        use signal::unix::{signal, SignalKind};
+        let jarvis = jarvis.clone();
+        let mut cancel = signal(SignalKind::user_defined1())?;
+        tokio::spawn(async move {
+            while cancel.recv().await.is_some() {
+                jarvis.cancel_speech();
+            }
+        });
+    }
 
     // Handle Ctrl-C (SIGINT) to allow graceful shutdown
     let _shutdown = tokio::spawn(async move {
@@ -147,146 +409,5 @@ async fn main() -> Result<()> {
         std::process::exit(0);
     });
 
-    loop {
-        if !conversation_mode {
-            // In idle mode we periodically listen for a short phrase and
-            // check if it contains the trigger word. Using a short
-            // duration reduces latency while keeping CPU usage low.
-            // Listen for up to `idle_listen_secs` seconds of audio while idle. This captures
-            // most wake‑word utterances without clipping.
-            match recogniser.listen_for_phrase(Duration::from_secs(idle_listen_secs)) {
-                Ok(transcript) => {
-                    log::debug!("Idle recognised transcript: {}", transcript);
-                    let trimmed = transcript.trim();
-                    if !trimmed.is_empty() {
-                        let cleaned = strip_noise_words(trimmed);
-                        if !cleaned.is_empty() {
-                            let lower = cleaned.to_lowercase();
-                            // Check whether the wake word appears in the cleaned transcript.
-                            if lower.contains(&trigger_word.to_lowercase()) {
-                                log::info!("Wake word detected: {}", cleaned);
-                                jarvis_io.write_heard(&cleaned);
-                                tts.speak("Yes sir?").await.ok();
-                                jarvis_io.write_status("listening");
-                                conversation_mode = true;
-                                last_interaction = Instant::now();
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    log::warn!("Speech recognition error in idle mode: {e}");
-                }
-            }
-            continue;
-        } else {
-            // Conversation mode: listen for a command. If no speech is
-            // recognised within the timeout window we drop back to idle.
-            // In conversation mode record up to `convo_listen_secs` seconds of audio to ensure
-            // full commands are captured. Adjust this value to balance responsiveness and completeness.
-            match recogniser.listen_for_phrase(Duration::from_secs(convo_listen_secs)) {
-                Ok(command) => {
-                    log::debug!("Raw recognised transcript: {}", command);
-                    let trimmed = command.trim();
-                    if trimmed.is_empty() {
-                        // No speech captured this round. If we've been idle longer than the
-                        // configured timeout then exit conversation mode.
-                        if last_interaction.elapsed() > timeout {
-                            log::info!("Conversation timeout. Returning to idle mode.");
-                            jarvis_io.write_status("idle");
-                            conversation_mode = false;
-                        }
-                    } else {
-                        last_interaction = Instant::now();
-                        // Strip spurious noise tokens from the ends.
-                        let cleaned = strip_noise_words(trimmed);
-                        if cleaned.is_empty() {
-                            continue;
-                        }
-                        let lower = cleaned.to_lowercase();
-                        // "shadow" tells Jarvis to go back to sleep immediately.
-                        if lower.contains("shadow") {
-                            tts.speak("Going silent.").await.ok();
-                            jarvis_io.write_status("idle");
-                            conversation_mode = false;
-                            continue;
-                        }
-                        log::info!("User command: {}", cleaned);
-                        jarvis_io.write_heard(&cleaned);
-                        // // Delegate to the language model for all commands. We no longer filter
-                        // // based on specific keywords; instead we rely on the language model's
-                        // // built‑in reasoning and our existing timeout mechanism to avoid
-                        // // pathological hangs. The `Agent` implementation ensures that
-                        // // "think" blocks and Markdown are stripped before speaking, and
-                        // // imposes a timeout on long running requests.
-                        // let mut reply = agent
-                        //     .handle_command(trimmed)
-                        //     .await
-                        //     .context("failed to handle command via agent")?;
-                        // // Provide a fallback if the model returns an empty string.
-                        // if reply.trim().is_empty() {
-                        //     reply = "I'm sorry, I didn't understand. Please try again.".to_string();
-                        // }
-                        // log::info!("Assistant response: {}", reply);
-                        // tts.speak(&reply).await.ok();
-                        match agent.handle_command(trimmed).await {
-                            Ok(reply) => {
-                                let reply = if reply.trim().is_empty() {
-                                    "I'm sorry, I didn't understand. Please try again.".to_string()
-                                } else {
-                                    reply
-                                };
-                                log::info!("Assistant response: {}", reply);
-                                jarvis_io.write_spoken(&reply);
-                                jarvis_io.write_status("speaking");
-                                // Speak and allow cancellation via status file
-                                let mut was_canceled = false;
-                                {
-                                    let speak_fut = tts.speak(&reply);
-                                    tokio::pin!(speak_fut);
-                                    // Poll for cancel status periodically
-                                    let mut cancel_check =
-                                        tokio::time::interval(Duration::from_millis(200));
-                                    loop {
-                                        tokio::select! {
-                                            res = &mut speak_fut => {
-                                                let _ = res;
-                                                break;
-                                            }
-                                            _ = cancel_check.tick() => {
-                                                if jarvis_io.current_status()
-                                                    .map(|s| s.trim().eq_ignore_ascii_case("canceled"))
-                                                    .unwrap_or(false)
-                                                {
-                                                    was_canceled = true;
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                if was_canceled {
-                                    tts.stop().await.ok();
-                                    jarvis_io.write_status("canceled");
-                                    jarvis_io.cancel_tts();
-                                    sleep(Duration::from_millis(500)).await;
-                                }
-                                jarvis_io.write_status("listening");
-                            }
-                            Err(e) => log::error!("Agent error: {e}"),
-                        }
-                    }
-                }
-                Err(e) => {
-                    log::warn!("Speech recognition error in conversation mode: {e}");
-                    // If recognition fails repeatedly we still respect the
-                    // timeout to avoid getting stuck.
-                    if last_interaction.elapsed() > timeout {
-                        jarvis_io.write_status("idle");
-                        conversation_mode = false;
-                    }
-                }
-            }
-        }
-    }
+    jarvis.run().await
 }
@@ -0,0 +1,251 @@
+//! Long-term memory: facts the user asks Jarvis to keep.
+//!
+//! "Remember that my WiFi password is on the fridge" stores the fact in
+//! `~/.jarvis/memory.jsonl` (or the file named by `MEMORY_FILE`), one JSON
+//! object per line, together with its embedding from the language model
+//! backend (see `EMBED_MODEL`). For every later command the most relevant
+//! facts, by cosine similarity of the embeddings, are added to the system
+//! prompt; without embeddings, facts sharing words with the command are
+//! used instead. "What do you remember?" lists the facts and "clear your
+//! memory" deletes them. `MEMORY=0` turns the memory off. Each wake word
+//! in `WAKE_PERSONAS` has a memory of its own, `memory-<word>.jsonl` next
+//! to the main one.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{env_flag_or, env_parse};
+
+/// Words too common to make two texts related.
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "are", "was", "what", "where", "when", "which", "who", "how", "you",
+    "your", "with", "that", "this", "there", "have", "has", "did", "does", "about", "from", "into",
+    "is", "my", "me", "it", "its", "a", "an", "of", "to", "in", "on", "at", "do",
+];
+
+/// One remembered fact.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    /// The fact in the user's words.
+    pub text: String,
+    /// When it was stored, in RFC 3339.
+    pub created: String,
+    /// Embedding of `text`; empty if none could be computed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embedding: Vec<f32>,
+}
+
+/// The stored facts.
+#[derive(Debug, Default)]
+pub struct Memory {
+    /// The JSONL file; `None` when the memory is off.
+    path: Option<PathBuf>,
+    entries: Vec<MemoryEntry>,
+    /// Most facts added to a prompt.
+    top_k: usize,
+    /// Least cosine similarity for a fact to count as relevant.
+    min_score: f32,
+}
+
+impl Memory {
+    /// The memory in `path`. Unreadable lines are logged and skipped.
+    pub fn open(path: PathBuf) -> Self {
+        let entries = match fs::read_to_string(&path) {
+            Ok(data) => data
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(|line| match serde_json::from_str(line) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        log::warn!("Ignoring invalid memory in {}: {e}", path.display());
+                        None
+                    }
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path: Some(path),
+            entries,
+            top_k: env_parse("MEMORY_TOP_K", 3),
+            min_score: env_parse("MEMORY_MIN_SCORE", 0.5),
+        }
+    }
+
+    /// The memory configured by `MEMORY` and `MEMORY_FILE`.
+    pub fn from_env() -> Self {
+        if !env_flag_or("MEMORY", true) {
+            return Self::default();
+        }
+        memory_file().map(Self::open).unwrap_or_default()
+    }
+
+    /// The memory of the wake word `word`, see [`crate::wake::WakeScope`].
+    pub fn for_wake_word(word: &str) -> Self {
+        if !env_flag_or("MEMORY", true) {
+            return Self::default();
+        }
+        memory_file()
+            .map(|path| path.with_file_name(format!("memory-{}.jsonl", word.replace(' ', "-"))))
+            .map(Self::open)
+            .unwrap_or_default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn entries(&self) -> &[MemoryEntry] {
+        &self.entries
+    }
+
+    /// Whether any fact has an embedding, so that commands are worth
+    /// embedding too.
+    pub fn has_embeddings(&self) -> bool {
+        self.entries.iter().any(|e| !e.embedding.is_empty())
+    }
+
+    /// Store a fact.
+    pub fn add(&mut self, text: &str, embedding: Vec<f32>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let entry = MemoryEntry {
+            text: text.trim().to_string(),
+            created: chrono::Local::now().to_rfc3339(),
+            embedding,
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("cannot open {}", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)
+            .with_context(|| format!("cannot write {}", path.display()))?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Delete every fact.
+    pub fn clear(&mut self) -> Result<()> {
+        self.entries.clear();
+        if let Some(path) = &self.path {
+            if path.exists() {
+                fs::remove_file(path)
+                    .with_context(|| format!("cannot delete {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The facts most relevant to `query`, best first. `embedding` is the
+    /// query's embedding, if one could be computed.
+    pub fn relevant(&self, query: &str, embedding: Option<&[f32]>) -> Vec<&MemoryEntry> {
+        let mut scored: Vec<(f32, &MemoryEntry)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let score = match embedding.and_then(|e| cosine_similarity(e, &entry.embedding)) {
+                    Some(score) if score >= self.min_score => score,
+                    Some(_) => return None,
+                    None => keyword_score(query, &entry.text).filter(|s| *s > 0.0)?,
+                };
+                Some((score, entry))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored
+            .into_iter()
+            .take(self.top_k)
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+}
+
+/// Cosine similarity of two vectors, or `None` if they cannot be compared
+/// (different lengths, e.g. from another embedding model).
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> Option<f32> {
+    if a.len() != b.len() || a.is_empty() {
+        return None;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    (norms > 0.0).then(|| dot / norms)
+}
+
+/// Share of the significant words of `query` that also appear in `text`,
+/// or `None` if `query` has no significant words.
+pub fn keyword_score(query: &str, text: &str) -> Option<f32> {
+    let words = |s: &str| -> Vec<String> {
+        s.to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.len() > 2 && !STOP_WORDS.contains(w))
+            .map(str::to_string)
+            .collect()
+    };
+    let query = words(query);
+    if query.is_empty() {
+        return None;
+    }
+    let text = words(text);
+    let shared = query.iter().filter(|w| text.contains(w)).count();
+    Some(shared as f32 / query.len() as f32)
+}
+
+/// The fact in "remember that ...", "remember ..." or "don't forget ...".
+pub fn remember_command(command: &str) -> Option<String> {
+    let lower = command.trim().to_lowercase();
+    let lower = lower.strip_prefix("please ").unwrap_or(&lower);
+    let fact = [
+        "remember that ",
+        "remember ",
+        "don't forget that ",
+        "don't forget ",
+    ]
+    .iter()
+    .find_map(|prefix| lower.strip_prefix(prefix))?;
+    let fact = fact.trim().trim_end_matches(['.', '!']);
+    // "Remember what I said about ..." is a question, and "remember this"
+    // and the like carry no fact.
+    let question = ["what ", "when ", "where ", "who ", "how ", "why ", "if "]
+        .iter()
+        .any(|word| fact.starts_with(word));
+    (!question && fact.split_whitespace().count() > 2).then(|| fact.to_string())
+}
+
+/// Whether `command` asks what Jarvis remembers.
+pub fn is_list_command(command: &str) -> bool {
+    let lower = command.trim().trim_end_matches('?').to_lowercase();
+    matches!(
+        lower.as_str(),
+        "what do you remember" | "what have i asked you to remember" | "what do you know about me"
+    )
+}
+
+/// Whether `command` asks to delete the memory.
+pub fn is_clear_command(command: &str) -> bool {
+    let lower = command.trim().trim_end_matches('.').to_lowercase();
+    matches!(
+        lower.as_str(),
+        "clear your memory"
+            | "wipe your memory"
+            | "delete your memory"
+            | "forget everything i told you"
+    )
+}
+
+/// `MEMORY_FILE`, default `~/.jarvis/memory.jsonl`.
+fn memory_file() -> Option<PathBuf> {
+    std::env::var("MEMORY_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("memory.jsonl")))
+}
@@ -0,0 +1,230 @@
+//! Who Jarvis is: the opening of the system prompt.
+//!
+//! The default persona comes from `SYSTEM_PROMPT`, else from
+//! `~/.jarvis/persona.md`, else the built-in "You are Jarvis, a helpful AI
+//! assistant." Named personas live in `~/.jarvis/personas/<name>.md`;
+//! `PERSONA=<name>` starts with one, and "switch to pirate mode" changes
+//! persona by voice ("switch to normal mode" goes back to the default).
+//! The files are read for every command, so edits apply without a
+//! restart.
+//!
+//! The persona only sets the character. The instructions about tools and
+//! plain spoken answers are always added after it. A persona file may
+//! start with TOML front matter that overrides the `LLM_*` generation
+//! settings, e.g. a livelier pirate:
+//!
+//! ```text
+//! +++
+//! temperature = 1.1
+//! max_tokens = 120
+//! +++
+//! You are Jarvis, a pirate. Speak like one.
+//! ```
+
+use std::path::PathBuf;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::llm::GenerationOptions;
+
+/// The persona used when no other is configured.
+pub const DEFAULT_PERSONA: &str = "You are Jarvis, a helpful AI assistant.";
+
+/// Names that select the default persona.
+const DEFAULT_NAMES: &[&str] = &["default", "normal", "jarvis", "standard"];
+
+static SWITCH_COMMAND: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?:please )?(?:(?:switch|change|go) (?:back )?to (?:the )?(.+?) (?:mode|persona|personality)|(?:use|be) (?:the )?(.+?) (?:persona|personality))$",
+    )
+    .unwrap()
+});
+
+/// A persona: the opening of the system prompt and the generation
+/// settings that go with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Persona {
+    pub prompt: String,
+    /// Overrides of the `LLM_*` settings.
+    pub options: GenerationOptions,
+}
+
+impl Persona {
+    /// Read a persona file. It may start with TOML front matter between
+    /// `+++` lines that sets `temperature`, `top_p`, `num_ctx` or
+    /// `max_tokens`.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        let front_matter = text
+            .strip_prefix("+++")
+            .and_then(|rest| rest.split_once("\n+++"));
+        let Some((settings, prompt)) = front_matter else {
+            return Self {
+                prompt: text.to_string(),
+                options: GenerationOptions::default(),
+            };
+        };
+        let options = toml::from_str(settings).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid persona settings: {e}");
+            GenerationOptions::default()
+        });
+        Self {
+            prompt: prompt.trim().to_string(),
+            options,
+        }
+    }
+}
+
+/// The available personas and the one in use.
+#[derive(Debug, Clone)]
+pub struct Personas {
+    /// `~/.jarvis`, if there is a home directory.
+    dir: Option<PathBuf>,
+    /// Name of the active persona; `None` for the default.
+    active: Option<String>,
+}
+
+impl Personas {
+    /// The personas in `dir`, starting with the default.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir, active: None }
+    }
+
+    /// The personas in `~/.jarvis`, starting with the one named in
+    /// `PERSONA`.
+    pub fn from_env() -> Self {
+        let mut personas = Self::new(dirs::home_dir().map(|h| h.join(".jarvis")));
+        if let Ok(name) = std::env::var("PERSONA") {
+            if let Err(e) = personas.switch(&name) {
+                log::warn!("Ignoring PERSONA={name}: {e}");
+            }
+        }
+        personas
+    }
+
+    /// Name of the active persona, `default` for the default one.
+    pub fn active(&self) -> &str {
+        self.active.as_deref().unwrap_or("default")
+    }
+
+    /// The active persona. A persona file that has become unreadable
+    /// falls back to the default.
+    pub fn current(&self) -> Persona {
+        if let Some(path) = self.active.as_ref().and_then(|name| self.path(name)) {
+            match std::fs::read_to_string(&path) {
+                Ok(text) if !text.trim().is_empty() => return Persona::parse(&text),
+                Ok(_) => log::warn!("Persona file {} is empty", path.display()),
+                Err(e) => log::warn!("Cannot read persona file {}: {e}", path.display()),
+            }
+        }
+        if let Ok(prompt) = std::env::var("SYSTEM_PROMPT") {
+            if !prompt.trim().is_empty() {
+                return Persona::parse(&prompt);
+            }
+        }
+        self.dir
+            .as_ref()
+            .and_then(|dir| std::fs::read_to_string(dir.join("persona.md")).ok())
+            .filter(|text| !text.trim().is_empty())
+            .map(|text| Persona::parse(&text))
+            .unwrap_or_else(|| Persona {
+                prompt: DEFAULT_PERSONA.to_string(),
+                options: GenerationOptions::default(),
+            })
+    }
+
+    /// The file of the named persona.
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        Some(
+            self.dir
+                .as_ref()?
+                .join("personas")
+                .join(format!("{name}.md")),
+        )
+    }
+
+    /// Names of the personas in `~/.jarvis/personas`, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let Some(entries) = self
+            .dir
+            .as_ref()
+            .and_then(|dir| std::fs::read_dir(dir.join("personas")).ok())
+        else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "md" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_string_lossy().into_owned())
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Make `name` the active persona. Spoken names match file names
+    /// regardless of case, spaces, dashes and underscores.
+    pub fn switch(&mut self, name: &str) -> anyhow::Result<()> {
+        let wanted = normalize(name);
+        if DEFAULT_NAMES.contains(&wanted.as_str()) {
+            self.active = None;
+            return Ok(());
+        }
+        let found = self
+            .names()
+            .into_iter()
+            .find(|candidate| normalize(candidate) == wanted);
+        match found {
+            Some(name) => {
+                self.active = Some(name);
+                Ok(())
+            }
+            None => anyhow::bail!("there is no persona called '{name}'"),
+        }
+    }
+
+    /// Handle "switch to pirate mode". Returns the reply to speak, or
+    /// `None` if `command` is not such a request.
+    pub fn handle_command(&mut self, command: &str) -> Option<String> {
+        let name = switch_target(command)?;
+        let reply = match self.switch(&name) {
+            Ok(()) if self.active.is_none() => "Back to normal.".to_string(),
+            Ok(()) => format!("Switching to {name} mode."),
+            Err(_) => {
+                let names = self.names();
+                if names.is_empty() {
+                    format!("I don't have a persona called {name}.")
+                } else {
+                    format!(
+                        "I don't have a persona called {name}. I know {}.",
+                        names.join(", ")
+                    )
+                }
+            }
+        };
+        log::info!("Persona command '{command}': now {}", self.active());
+        Some(reply)
+    }
+}
+
+/// The persona `command` asks to switch to, if it is such a request.
+fn switch_target(command: &str) -> Option<String> {
+    let command = command
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_lowercase();
+    let captures = SWITCH_COMMAND.captures(&command)?;
+    let name = captures.get(1).or_else(|| captures.get(2))?;
+    Some(name.as_str().to_string())
+}
+
+fn normalize(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
@@ -0,0 +1,956 @@
+//! The Jarvis voice pipeline as an embeddable component.
+//!
+//! [`Jarvis`] ties together a [`Listener`] (speech recognition), an
+//! [`Agent`] driving an [`LlmBackend`] with a set of [`Tool`]s, and a
+//! [`Speaker`] (text‑to‑speech). Every stage is replaceable through
+//! [`JarvisBuilder`]; anything left unset falls back to the same defaults
+//! the `jarvis` binary uses, configured from environment variables.
+//!
+//! ```no_run
+//! # async fn demo() -> anyhow::Result<()> {
+//! use jarvis_rust::Jarvis;
+//!
+//! let jarvis = Jarvis::builder().with_trigger_word("computer").build()?;
+//! jarvis.start()?;
+//! let reply = jarvis.ask("what is the capital of France?").await?;
+//! jarvis.say(&reply).await?;
+//! jarvis.stop().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use futures_util::stream::{Stream, StreamExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::agent::{Agent, AgentResponse};
+use crate::config::{env_flag, env_flag_or, env_parse};
+use crate::events::{EventBus, JarvisEvent};
+use crate::jarvis_io::JarvisIO;
+use crate::jobs::Jobs;
+use crate::llm::{self, LlmBackend};
+use crate::speech::{Listener, SpeechRecognizer, Transcript};
+use crate::stats::Stats;
+use crate::tools::{self, Tool, ToolRegistry};
+use crate::transcript::TranscriptFilter;
+use crate::tts_engine::paging::Pager;
+use crate::tts_engine::{Prosody, SilentSpeaker, Speaker, TtsEngine};
+use crate::verbosity::ErrorVerbosity;
+use crate::wake::{self, WakeLog, WakeScope};
+
+/// Timing and wake word settings for the conversation loop.
+struct Settings {
+    trigger_word: String,
+    /// More wake words, each with its own persona, model and history.
+    wake_scopes: Vec<WakeScope>,
+    idle_listen: Duration,
+    convo_listen: Duration,
+    timeout: Duration,
+    /// Spoken once when the conversation is about to time out; empty
+    /// disables the warning.
+    timeout_prompt: String,
+    /// Extra time granted after the timeout prompt.
+    timeout_grace: Duration,
+    /// How much to say when a command fails.
+    error_verbosity: ErrorVerbosity,
+    /// Listen for "cancel" while the model is generating; the length of
+    /// each listen, or `None` not to.
+    cancel_listen: Option<Duration>,
+    /// Check and load the model before listening.
+    startup_check: bool,
+    /// Download the model at startup if the server does not have it.
+    model_pull: bool,
+}
+
+/// Mutable state of the conversation loop.
+struct ConversationState {
+    /// Whether Jarvis is awake and listening for commands.
+    active: bool,
+    last_interaction: Instant,
+    /// When the "still there?" prompt was spoken for the current silence.
+    timeout_warned: Option<Instant>,
+    /// Consecutive "say that again" prompts for the current command.
+    asr_retries: u32,
+    /// Whether the current conversation has produced any command; used to
+    /// spot wake word false positives.
+    had_command: bool,
+}
+
+impl ConversationState {
+    fn new() -> Self {
+        Self {
+            active: false,
+            last_interaction: Instant::now(),
+            timeout_warned: None,
+            asr_retries: 0,
+            had_command: false,
+        }
+    }
+
+    /// Record user activity, resetting the timeout window.
+    fn touch(&mut self) {
+        self.last_interaction = Instant::now();
+        self.timeout_warned = None;
+    }
+}
+
+/// Builder for [`Jarvis`]. Every component is optional.
+#[derive(Default)]
+pub struct JarvisBuilder {
+    asr: Option<Box<dyn Listener>>,
+    llm: Option<Box<dyn LlmBackend>>,
+    tts: Option<Box<dyn Speaker>>,
+    tools: Option<ToolRegistry>,
+    trigger_word: Option<String>,
+    wake_scopes: Option<Vec<WakeScope>>,
+    conversation_timeout: Option<Duration>,
+    error_verbosity: Option<ErrorVerbosity>,
+    without_asr: bool,
+    without_tts: bool,
+}
+
+impl JarvisBuilder {
+    /// Use a custom speech recogniser instead of Vosk.
+    pub fn with_asr(mut self, asr: impl Listener + 'static) -> Self {
+        self.asr = Some(Box::new(asr));
+        self
+    }
+
+    /// Use a custom language model backend instead of Ollama.
+    pub fn with_llm(mut self, llm: impl LlmBackend + 'static) -> Self {
+        self.llm = Some(Box::new(llm));
+        self
+    }
+
+    /// Use a custom text‑to‑speech engine.
+    pub fn with_tts(mut self, tts: impl Speaker + 'static) -> Self {
+        self.tts = Some(Box::new(tts));
+        self
+    }
+
+    /// Replace the default tool set (`shell_task` and `codex_cli_task`).
+    pub fn with_tools(mut self, tools: impl Into<ToolRegistry>) -> Self {
+        self.tools = Some(tools.into());
+        self
+    }
+
+    /// Add `tool` to the tool set, replacing a tool of the same name.
+    pub fn with_tool(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools
+            .get_or_insert_with(tools::default_tools)
+            .register(tool);
+        self
+    }
+
+    /// Do not initialise speech recognition. The resulting pipeline can
+    /// only be driven through [`Jarvis::ask`]; [`Jarvis::start`] and
+    /// [`Jarvis::run`] will fail.
+    pub fn without_asr(mut self) -> Self {
+        self.without_asr = true;
+        self
+    }
+
+    /// Do not initialise text‑to‑speech; spoken output is discarded.
+    pub fn without_tts(mut self) -> Self {
+        self.without_tts = true;
+        self
+    }
+
+    /// Override the `TRIGGER_WORD` environment variable.
+    pub fn with_trigger_word(mut self, word: &str) -> Self {
+        self.trigger_word = Some(word.to_string());
+        self
+    }
+
+    /// Override the `WAKE_PERSONAS` environment variable.
+    pub fn with_wake_scopes(mut self, scopes: Vec<WakeScope>) -> Self {
+        self.wake_scopes = Some(scopes);
+        self
+    }
+
+    /// Override the `CONVERSATION_TIMEOUT` environment variable.
+    pub fn with_conversation_timeout(mut self, timeout: Duration) -> Self {
+        self.conversation_timeout = Some(timeout);
+        self
+    }
+
+    /// Override the `ERROR_VERBOSITY` environment variable.
+    pub fn with_error_verbosity(mut self, verbosity: ErrorVerbosity) -> Self {
+        self.error_verbosity = Some(verbosity);
+        self
+    }
+
+    /// Assemble the pipeline. Components that were not supplied are
+    /// created from environment variables; this fails if, for example,
+    /// no custom recogniser was given and `VOSK_MODEL_PATH` is unset.
+    pub fn build(self) -> Result<Jarvis> {
+        // Initialise audio input and speech recognition.
+        let listener: Option<Arc<dyn Listener>> = match self.asr {
+            Some(asr) => Some(Arc::from(asr)),
+            None if self.without_asr => None,
+            None => {
+                let model_path = env::var("VOSK_MODEL_PATH").context(
+                    "VOSK_MODEL_PATH environment variable must point to a Vosk model directory",
+                )?;
+                Some(Arc::new(SpeechRecognizer::new(&model_path)?))
+            }
+        };
+
+        // Initialise TTS with the configured voice.
+        let events = EventBus::new();
+        let prosody = Prosody::from_env();
+        let speaker: Box<dyn Speaker> = match self.tts {
+            Some(tts) => tts,
+            None if self.without_tts => Box::new(SilentSpeaker),
+            None => {
+                let tts = TtsEngine::from_env()?;
+                tts.set_event_bus(events.clone());
+                Box::new(tts)
+            }
+        };
+
+        // Initialise the language model client and agent.
+        let backend = match self.llm {
+            Some(llm) => llm,
+            None => llm::from_env()?,
+        };
+        let mut agent =
+            Agent::with_backend(backend, self.tools.unwrap_or_else(tools::default_tools));
+        agent.set_event_bus(events.clone());
+
+        // Audio capture durations for wake word detection and user commands.
+        // These can be tuned via environment variables for faster responsiveness.
+        let settings = Settings {
+            trigger_word: self.trigger_word.unwrap_or_else(|| {
+                env::var("TRIGGER_WORD").unwrap_or_else(|_| "jarvis".to_string())
+            }),
+            wake_scopes: self.wake_scopes.unwrap_or_else(wake::wake_scopes),
+            idle_listen: Duration::from_secs(env_parse("IDLE_LISTEN_SECS", 2)),
+            convo_listen: Duration::from_secs(env_parse("CONVO_LISTEN_SECS", 8)),
+            timeout: self
+                .conversation_timeout
+                .unwrap_or_else(|| Duration::from_secs(env_parse("CONVERSATION_TIMEOUT", 30))),
+            timeout_prompt: env::var("TIMEOUT_PROMPT")
+                .unwrap_or_else(|_| "Still there?".to_string()),
+            timeout_grace: Duration::from_secs(env_parse("TIMEOUT_GRACE_SECS", 10)),
+            error_verbosity: self
+                .error_verbosity
+                .unwrap_or_else(ErrorVerbosity::from_env),
+            cancel_listen: env_flag_or("CANCEL_BY_VOICE", true)
+                .then(|| Duration::from_secs(env_parse("CANCEL_LISTEN_SECS", 1))),
+            startup_check: env_flag_or("STARTUP_CHECK", true),
+            model_pull: env_flag("MODEL_PULL"),
+        };
+
+        Ok(Jarvis {
+            inner: Arc::new(Inner {
+                listener,
+                agent,
+                speaker: tokio::sync::Mutex::new(speaker),
+                prosody: Mutex::new(prosody),
+                pager: Mutex::new(Pager::from_env()),
+                speech_cancel: Mutex::new(CancellationToken::new()),
+                settings,
+                // Noise-word and phrase blacklist applied to every transcript.
+                filter: TranscriptFilter::from_env(),
+                jarvis_io: JarvisIO::new(),
+                wake_log: WakeLog::new(),
+                events,
+                announcements: Mutex::new(Vec::new()),
+                running: AtomicBool::new(false),
+            }),
+            task: Mutex::new(None),
+        })
+    }
+}
+
+/// A runnable voice assistant pipeline.
+pub struct Jarvis {
+    inner: Arc<Inner>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct Inner {
+    listener: Option<Arc<dyn Listener>>,
+    agent: Agent,
+    speaker: tokio::sync::Mutex<Box<dyn Speaker>>,
+    /// Current rate, pitch and volume, adjusted by voice commands.
+    prosody: Mutex<Prosody>,
+    /// Pages of the last reply that have not been read out yet.
+    pager: Mutex<Pager>,
+    /// Interrupts the reply being spoken; replaced for every reply.
+    speech_cancel: Mutex<CancellationToken>,
+    settings: Settings,
+    filter: TranscriptFilter,
+    jarvis_io: JarvisIO,
+    wake_log: WakeLog,
+    events: EventBus,
+    /// What the tools said unprompted, to be spoken between commands.
+    announcements: Mutex<Vec<String>>,
+    running: AtomicBool,
+}
+
+impl Jarvis {
+    /// Start configuring a pipeline.
+    pub fn builder() -> JarvisBuilder {
+        JarvisBuilder::default()
+    }
+
+    /// Start the listen/respond loop in a background task. Calling this
+    /// while the loop is already running has no effect. Fails if the
+    /// pipeline was built without speech recognition.
+    pub fn start(&self) -> Result<()> {
+        self.inner.ensure_listener()?;
+        if self.inner.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let inner = self.inner.clone();
+        let handle = tokio::spawn(async move { inner.run_loop().await });
+        *self.task.lock().unwrap() = Some(handle);
+        Ok(())
+    }
+
+    /// Run the listen/respond loop on the current task until [`stop`]
+    /// is called from elsewhere.
+    ///
+    /// [`stop`]: Self::stop
+    pub async fn run(&self) -> Result<()> {
+        self.inner.ensure_listener()?;
+        if self.inner.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.inner.clone().run_loop().await;
+        Ok(())
+    }
+
+    /// Stop the loop and any ongoing speech. The loop finishes the current
+    /// listen window before exiting.
+    pub async fn stop(&self) {
+        self.inner.running.store(false, Ordering::SeqCst);
+        let _ = self.inner.speaker.lock().await.stop().await;
+        let handle = self.task.lock().unwrap().take();
+        if let Some(handle) = handle {
+            let _ = handle.await;
+        }
+        self.inner.jarvis_io.write_status("idle");
+    }
+
+    /// Subscribe to pipeline events (wake, transcript, tool, response,
+    /// speech and error). Each call returns an independent stream that receives all
+    /// events published after subscribing.
+    pub fn events(&self) -> impl Stream<Item = JarvisEvent> {
+        self.inner.events.subscribe()
+    }
+
+    /// Send a text command straight to the agent and return its reply,
+    /// bypassing speech recognition and synthesis.
+    pub async fn ask(&self, text: &str) -> Result<String> {
+        self.inner.agent.handle_command(text).await
+    }
+
+    /// Like [`ask`](Self::ask), but return the reply together with the
+    /// model's raw answer, the tool calls and their results and timings.
+    pub async fn ask_structured(&self, text: &str) -> Result<AgentResponse> {
+        self.inner.agent.handle_command_structured(text).await
+    }
+
+    /// How often each tool and model has been used, failed and how long
+    /// it took; see [`crate::stats`].
+    pub fn stats(&self) -> Stats {
+        self.inner.agent.stats()
+    }
+
+    /// The commands running, or run, in the background; see
+    /// [`crate::jobs`].
+    pub fn jobs(&self) -> &Jobs {
+        self.inner.agent.jobs()
+    }
+
+    /// Speak the given text with the configured speaker.
+    pub async fn say(&self, text: &str) -> Result<()> {
+        self.inner.speaker.lock().await.speak(text).await
+    }
+
+    /// Interrupt the reply being spoken, if any, and abort the request to
+    /// the model if it is still generating. Unlike [`stop`], the loop
+    /// keeps listening.
+    ///
+    /// [`stop`]: Self::stop
+    pub fn cancel_speech(&self) {
+        self.inner.agent.cancel();
+        self.inner.speech_cancel.lock().unwrap().cancel();
+    }
+}
+
+impl Inner {
+    fn ensure_listener(&self) -> Result<()> {
+        if self.listener.is_none() {
+            return Err(anyhow!(
+                "this Jarvis pipeline was built without speech recognition"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Capture one phrase on a blocking thread so that the recogniser does
+    /// not stall the async runtime.
+    async fn listen(&self, duration: Duration) -> Result<Transcript> {
+        let listener = self
+            .listener
+            .clone()
+            .context("no speech recogniser configured")?;
+        tokio::task::spawn_blocking(move || listener.listen(duration))
+            .await
+            .context("failed to join speech recognition task")?
+    }
+
+    fn emit_error(&self, error: &anyhow::Error) {
+        self.events.emit(JarvisEvent::Error {
+            message: format!("{error:#}"),
+        });
+    }
+
+    /// Speak one of the pipeline's fixed phrases. These go through the
+    /// audio cache since they are repeated often.
+    async fn speak(&self, text: &str) {
+        self.speaker.lock().await.speak_cached(text).await.ok();
+    }
+
+    async fn run_loop(self: Arc<Self>) {
+        let settings = &self.settings;
+        let jarvis_io = &self.jarvis_io;
+        let filter = &self.filter;
+
+        let mut state = ConversationState::new();
+
+        jarvis_io.set_pid();
+        jarvis_io.write_status("idle");
+        let mirror = tokio::spawn(mirror_speaking(self.events.subscribe()));
+        let narration = tokio::spawn(self.clone().narrate_progress());
+        let timers = tokio::spawn(self.clone().queue_announcements());
+        if settings.startup_check {
+            self.warm_up().await;
+        }
+
+        log::info!(
+            "Jarvis initialised. Waiting for wake word '{}' (idle listen: {}s, convo listen: {}s).",
+            settings.trigger_word,
+            settings.idle_listen.as_secs(),
+            settings.convo_listen.as_secs()
+        );
+        for scope in &settings.wake_scopes {
+            log::info!(
+                "Also waking on '{}' (persona: {}, model: {}).",
+                scope.word,
+                scope.persona.as_deref().unwrap_or("default"),
+                scope.model.as_deref().unwrap_or("default")
+            );
+        }
+
+        while self.running.load(Ordering::SeqCst) {
+            self.announce_jobs(state.active).await;
+            if !state.active {
+                // In idle mode we periodically listen for a short phrase and
+                // check if it contains the trigger word. Using a short
+                // duration reduces latency while keeping CPU usage low.
+                // Listen for up to `idle_listen` of audio while idle. This captures
+                // most wake‑word utterances without clipping.
+                match self.listen(settings.idle_listen).await {
+                    Ok(transcript) => {
+                        log::debug!("Idle recognised transcript: {}", transcript.text);
+                        let trimmed = transcript.text.trim();
+                        if !trimmed.is_empty() {
+                            let cleaned = filter.clean(trimmed);
+                            if !cleaned.is_empty() {
+                                // Check whether a wake word appears in the cleaned transcript.
+                                if let Some(scope) =
+                                    self.match_wake_words(&cleaned, transcript.confidence)
+                                {
+                                    self.agent.enter_scope(scope);
+                                    log::info!("Wake word detected: {}", cleaned);
+                                    self.events.emit(JarvisEvent::Wake {
+                                        transcript: cleaned.clone(),
+                                    });
+                                    jarvis_io.write_heard(&cleaned);
+                                    self.speak("Yes sir?").await;
+                                    jarvis_io.write_status("listening");
+                                    state.active = true;
+                                    state.had_command = false;
+                                    state.touch();
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("Speech recognition error in idle mode: {e}");
+                        self.emit_error(&e);
+                    }
+                }
+                continue;
+            }
+
+            // Conversation mode: listen for a command. If no speech is
+            // recognised within the timeout window we drop back to idle.
+            // In conversation mode record up to `convo_listen` of audio to ensure
+            // full commands are captured. Adjust this value to balance responsiveness and completeness.
+            match self.listen(settings.convo_listen).await {
+                Ok(transcript) => {
+                    log::debug!(
+                        "Raw recognised transcript: {} (confidence: {:?})",
+                        transcript.text,
+                        transcript.confidence
+                    );
+                    let trimmed = transcript.text.trim();
+                    if trimmed.is_empty() {
+                        // No speech captured this round. If we've been idle longer than the
+                        // configured timeout then exit conversation mode.
+                        self.check_timeout(&mut state).await;
+                        continue;
+                    }
+                    state.touch();
+                    // Strip spurious noise tokens and blacklisted phrases.
+                    let cleaned = filter.clean(trimmed);
+                    if cleaned.is_empty() {
+                        continue;
+                    }
+                    // "Codex, ..." hands the conversation to another wake word.
+                    let cleaned = match self.switch_scope(&cleaned) {
+                        Some(word) => wake::strip_wake_word(&cleaned, word)
+                            .unwrap_or(&cleaned)
+                            .to_string(),
+                        None => cleaned,
+                    };
+                    if cleaned.is_empty() {
+                        self.speak("Yes sir?").await;
+                        continue;
+                    }
+                    let lower = cleaned.to_lowercase();
+                    // "shadow" tells Jarvis to go back to sleep immediately.
+                    if lower.contains("shadow") {
+                        self.speak("Going silent.").await;
+                        jarvis_io.write_status("idle");
+                        self.wake_log.record_outcome(state.had_command);
+                        self.events.emit(JarvisEvent::Idle);
+                        self.agent.clear_history();
+                        self.agent.end_session();
+                        state.active = false;
+                        continue;
+                    }
+                    // Ask the user to repeat gibberish rather than letting
+                    // the model invent a response (or a tool call) for it.
+                    if !filter.is_plausible(&cleaned, transcript.confidence) {
+                        if state.asr_retries < filter.retry_limit {
+                            state.asr_retries += 1;
+                            log::info!(
+                                "Implausible transcript '{}', asking to repeat ({}/{})",
+                                cleaned,
+                                state.asr_retries,
+                                filter.retry_limit
+                            );
+                            self.speak(&filter.retry_prompt).await;
+                        } else {
+                            log::info!("Giving up on implausible transcript '{}'", cleaned);
+                            state.asr_retries = 0;
+                            self.speak("I still couldn't understand that.").await;
+                        }
+                        continue;
+                    }
+                    state.asr_retries = 0;
+                    state.had_command = true;
+                    if self.adjust_voice(&cleaned).await {
+                        continue;
+                    }
+                    if self.continue_reading(&cleaned).await {
+                        continue;
+                    }
+                    log::info!("User command: {}", cleaned);
+                    jarvis_io.write_heard(&cleaned);
+                    self.events.emit(JarvisEvent::Transcript {
+                        text: cleaned.clone(),
+                        confidence: transcript.confidence,
+                    });
+                    // Speak the answer sentence by sentence while the model
+                    // is still generating the rest, and listen for "cancel"
+                    // until it starts speaking.
+                    let (sentences, stream) = mpsc::unbounded_channel();
+                    let speaking = CancellationToken::new();
+                    let ((result, ()), ()) = tokio::join!(
+                        async {
+                            let done = tokio::join!(
+                                self.agent.handle_command_streaming(&cleaned, sentences),
+                                self.speak_stream(stream, &speaking),
+                            );
+                            speaking.cancel();
+                            done
+                        },
+                        self.listen_for_cancel(&speaking),
+                    );
+                    match result {
+                        Ok(reply) if reply.cancelled => {
+                            log::info!("Command cancelled; listening again");
+                            jarvis_io.write_status("listening");
+                        }
+                        Ok(reply) => {
+                            let text = if reply.spoken_text.trim().is_empty() {
+                                "I'm sorry, I didn't understand. Please try again.".to_string()
+                            } else {
+                                reply.spoken_text.clone()
+                            };
+                            log::info!("Assistant response: {}", text);
+                            jarvis_io.write_spoken(&text);
+                            self.events
+                                .emit(JarvisEvent::Response { text: text.clone() });
+                            jarvis_io.write_response(&reply);
+                            let streamed = reply.streamed;
+                            self.events.emit(JarvisEvent::AgentResponse(reply));
+                            if !streamed {
+                                jarvis_io.write_status("speaking");
+                                let page = self.pager.lock().unwrap().first_page(&text);
+                                self.speak_cancellable(&page).await;
+                            }
+                            jarvis_io.write_status("listening");
+                        }
+                        Err(e) => {
+                            log::error!("Agent error: {e:#}");
+                            self.emit_error(&e);
+                            self.report_error(&e).await;
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Speech recognition error in conversation mode: {e}");
+                    self.emit_error(&e);
+                    // If recognition fails repeatedly we still respect the
+                    // timeout to avoid getting stuck.
+                    self.check_timeout(&mut state).await;
+                }
+            }
+        }
+        mirror.abort();
+        narration.abort();
+        timers.abort();
+        jarvis_io.write_speaking("");
+        log::info!("Jarvis pipeline stopped.");
+    }
+
+    /// Say which background jobs have ended since the last time, the
+    /// Pomodoro sessions and breaks that began (see
+    /// [`queue_announcements`](Self::queue_announcements)) and the MQTT
+    /// messages that arrived (see [`crate::tools::mqtt`]), then go back to
+    /// listening, or to idle unless `active`.
+    async fn announce_jobs(&self, active: bool) {
+        #[allow(unused_mut)]
+        let mut texts: Vec<String> = self
+            .agent
+            .jobs()
+            .take_finished()
+            .iter()
+            .map(|job| job.announcement())
+            .collect();
+        texts.append(&mut self.announcements.lock().unwrap());
+        #[cfg(feature = "mqtt")]
+        texts.extend(crate::tools::mqtt::take_announcements());
+        if texts.is_empty() {
+            return;
+        }
+        for text in texts {
+            log::info!("{text}");
+            self.jarvis_io.write_spoken(&text);
+            self.jarvis_io.write_status("speaking");
+            self.speak_cancellable(&text).await;
+        }
+        self.jarvis_io
+            .write_status(if active { "listening" } else { "idle" });
+    }
+
+    /// Keep the Pomodoro phases published on the event bus for
+    /// [`announce_jobs`](Self::announce_jobs), so that they are spoken
+    /// between commands rather than over a reply; see
+    /// [`crate::tools::timers`].
+    async fn queue_announcements(self: Arc<Self>) {
+        let events = self.events.subscribe();
+        tokio::pin!(events);
+        while let Some(event) = events.next().await {
+            if let JarvisEvent::Timer { text } = event {
+                self.announcements.lock().unwrap().push(text);
+            }
+        }
+    }
+
+    /// Speak the milestones of long-running tools while the command
+    /// waits for them; see [`crate::tools::progress`].
+    async fn narrate_progress(self: Arc<Self>) {
+        let events = self.events.subscribe();
+        tokio::pin!(events);
+        while let Some(event) = events.next().await {
+            let JarvisEvent::ToolProgress { text, .. } = event else {
+                continue;
+            };
+            // The reply's token, so that cancelling the reply stops this
+            // too.
+            let cancel = self.speech_cancel.lock().unwrap().clone();
+            if cancel.is_cancelled() {
+                continue;
+            }
+            let mut speaker = self.speaker.lock().await;
+            if let Err(e) = speaker.speak_cancellable(&text, &cancel).await {
+                log::warn!("Failed to speak progress: {e:#}");
+            }
+        }
+    }
+
+    /// Tell the user that a command failed, in as much detail as
+    /// `ERROR_VERBOSITY` asks for, rather than going silent.
+    async fn report_error(&self, error: &anyhow::Error) {
+        let message = self.settings.error_verbosity.describe(error);
+        self.jarvis_io.write_spoken(&message);
+        self.jarvis_io.write_status("speaking");
+        self.speak_cancellable(&message).await;
+        self.jarvis_io.write_status("listening");
+    }
+
+    /// Handle "speak slower" style requests locally instead of sending
+    /// them to the agent. Returns whether `command` was such a request.
+    async fn adjust_voice(&self, command: &str) -> bool {
+        let current = *self.prosody.lock().unwrap();
+        let Some((next, reply)) = current.adjust_for_command(command) else {
+            return false;
+        };
+        log::info!("Adjusting voice: {:?} -> {:?}", current, next);
+        let mut speaker = self.speaker.lock().await;
+        next.apply(speaker.as_mut());
+        *self.prosody.lock().unwrap() = next;
+        speaker.speak_cached(reply).await.ok();
+        true
+    }
+
+    /// Read the next page of a long reply if `command` asks for it.
+    /// Without a pending page the command goes to the agent as usual.
+    async fn continue_reading(&self, command: &str) -> bool {
+        if !Pager::is_continue_command(command) {
+            return false;
+        }
+        let Some(page) = self.pager.lock().unwrap().next_page() else {
+            return false;
+        };
+        log::info!("Reading the next page of the last reply");
+        self.jarvis_io.write_status("speaking");
+        self.speak_cancellable(&page).await;
+        self.jarvis_io.write_status("listening");
+        true
+    }
+
+    /// Match an idle transcript against the main wake word and those of
+    /// `WAKE_PERSONAS`, logging it for `jarvis tune-wake`. Returns the
+    /// scope to answer in, `Some(None)` for the main wake word, or `None`
+    /// if no wake word was said.
+    fn match_wake_words(
+        &self,
+        transcript: &str,
+        confidence: Option<f32>,
+    ) -> Option<Option<&WakeScope>> {
+        let settings = &self.settings;
+        let main = wake::match_trigger(transcript, &settings.trigger_word);
+        self.wake_log
+            .record(transcript, confidence, &settings.trigger_word, &main);
+        if main.activated {
+            return Some(None);
+        }
+        settings.wake_scopes.iter().find_map(|scope| {
+            let m = wake::match_trigger(transcript, &scope.word);
+            self.wake_log
+                .record(transcript, confidence, &scope.word, &m);
+            m.activated.then_some(Some(scope))
+        })
+    }
+
+    /// If a conversation command starts with a wake word other than the
+    /// one in use, switch to it and return the word.
+    fn switch_scope(&self, command: &str) -> Option<&str> {
+        let settings = &self.settings;
+        let active = self.agent.scope();
+        let (word, scope) = std::iter::once((settings.trigger_word.as_str(), None))
+            .chain(
+                settings
+                    .wake_scopes
+                    .iter()
+                    .map(|scope| (scope.word.as_str(), Some(scope))),
+            )
+            .find(|(word, _)| wake::strip_wake_word(command, word).is_some())?;
+        if scope.map(|scope| &scope.word) == active.as_ref() {
+            return None;
+        }
+        log::info!("Switching conversation to wake word '{word}'");
+        self.agent.enter_scope(scope);
+        Some(word)
+    }
+
+    /// Handle a listen window without a command. Once the conversation
+    /// timeout expires Jarvis asks whether the user is still there and
+    /// extends the window once by the grace period; if that also passes in
+    /// silence it returns to idle mode.
+    async fn check_timeout(&self, state: &mut ConversationState) {
+        let settings = &self.settings;
+        if state.last_interaction.elapsed() <= settings.timeout {
+            return;
+        }
+        let prompt = settings.timeout_prompt.trim();
+        match state.timeout_warned {
+            None if !prompt.is_empty() => {
+                log::info!("Conversation about to time out; prompting the user.");
+                self.speak(prompt).await;
+                // The grace period counts from the end of the prompt.
+                state.timeout_warned = Some(Instant::now());
+                return;
+            }
+            Some(warned) if warned.elapsed() <= settings.timeout_grace => return,
+            _ => {}
+        }
+        log::info!("Conversation timeout. Returning to idle mode.");
+        self.jarvis_io.write_status("idle");
+        self.wake_log.record_outcome(state.had_command);
+        self.events.emit(JarvisEvent::Idle);
+        self.agent.clear_history();
+        self.agent.end_session();
+        state.active = false;
+        state.timeout_warned = None;
+    }
+
+    /// A token for the next reply, which [`Jarvis::cancel_speech`]
+    /// cancels.
+    fn new_speech(&self) -> CancellationToken {
+        let cancel = CancellationToken::new();
+        *self.speech_cancel.lock().unwrap() = cancel.clone();
+        cancel
+    }
+
+    /// Speak a reply; [`Jarvis::cancel_speech`] interrupts it.
+    async fn speak_cancellable(&self, reply: &str) {
+        let cancel = self.new_speech();
+        let mut speaker = self.speaker.lock().await;
+        if let Err(e) = speaker.speak_cancellable(reply, &cancel).await {
+            log::warn!("Failed to speak reply: {e:#}");
+        }
+        if cancel.is_cancelled() {
+            log::info!("Speech cancelled");
+            self.jarvis_io.write_status("canceled");
+        }
+    }
+
+    /// Check that the language model is there and load it, announcing
+    /// downloads; if it is not usable, say why. Jarvis keeps listening
+    /// either way, since the server may still come up.
+    async fn warm_up(&self) {
+        self.jarvis_io.write_status("starting");
+        let (announcements, spoken) = mpsc::unbounded_channel();
+        let speaking = CancellationToken::new();
+        let (result, ()) = tokio::join!(
+            async move {
+                let progress = move |text: &str| {
+                    let _ = announcements.send(text.to_string());
+                };
+                self.agent
+                    .warm_up(self.settings.model_pull, &progress)
+                    .await
+            },
+            self.speak_stream(spoken, &speaking),
+        );
+        self.jarvis_io.write_status("idle");
+        if let Err(e) = result {
+            log::error!("The language model is not ready: {e:#}");
+            self.emit_error(&e);
+            self.report_error(&e).await;
+            self.jarvis_io.write_status("idle");
+        }
+    }
+
+    /// While the model is thinking, listen in short windows for "cancel",
+    /// "stop", "never mind" or "shadow", and abort the command if one is
+    /// heard. Stops once `speaking` is cancelled; the current window is
+    /// finished first.
+    async fn listen_for_cancel(&self, speaking: &CancellationToken) {
+        let Some(window) = self.settings.cancel_listen else {
+            return;
+        };
+        while !speaking.is_cancelled() {
+            let heard = match self.listen(window).await {
+                Ok(transcript) => self.filter.clean(transcript.text.trim()),
+                Err(e) => {
+                    log::debug!("Not listening for cancel: {e:#}");
+                    return;
+                }
+            };
+            if !speaking.is_cancelled() && is_cancel_command(&heard) {
+                log::info!("Cancelling the command: heard '{heard}'");
+                self.agent.cancel();
+                self.speech_cancel.lock().unwrap().cancel();
+                return;
+            }
+        }
+    }
+
+    /// Speak the sentences of a streamed reply as they arrive, until the
+    /// sender is dropped. Once [`Jarvis::cancel_speech`] is called the
+    /// rest of the reply is discarded. `speaking` is cancelled when the
+    /// first sentence is spoken.
+    async fn speak_stream(
+        &self,
+        mut sentences: mpsc::UnboundedReceiver<String>,
+        speaking: &CancellationToken,
+    ) {
+        let cancel = self.new_speech();
+        while let Some(sentence) = sentences.recv().await {
+            if cancel.is_cancelled() {
+                continue;
+            }
+            speaking.cancel();
+            self.jarvis_io.write_status("speaking");
+            let mut speaker = self.speaker.lock().await;
+            if let Err(e) = speaker.speak_cancellable(&sentence, &cancel).await {
+                log::warn!("Failed to speak reply: {e:#}");
+            }
+        }
+        if cancel.is_cancelled() {
+            log::info!("Speech cancelled");
+            self.jarvis_io.write_status("canceled");
+        }
+    }
+}
+
+/// Keep `~/.jarvis/jarvis.speaking` up to date with the utterance being
+/// played, for scripts and status bars.
+async fn mirror_speaking(events: impl Stream<Item = JarvisEvent>) {
+    let jarvis_io = JarvisIO::new();
+    tokio::pin!(events);
+    while let Some(event) = events.next().await {
+        match event {
+            JarvisEvent::SpeakingStarted { text } => jarvis_io.write_speaking(&text),
+            JarvisEvent::SpeakingFinished { .. } => jarvis_io.write_speaking(""),
+            _ => {}
+        }
+    }
+}
+
+/// Whether `text`, heard while a command is being answered, asks to drop
+/// it.
+fn is_cancel_command(text: &str) -> bool {
+    const WORDS: &[&str] = &["cancel", "stop", "shadow", "never mind", "nevermind"];
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let text = words.join(" ");
+    WORDS.iter().any(|word| {
+        text == *word
+            || text.starts_with(&format!("{word} "))
+            || text.ends_with(&format!(" {word}"))
+    })
+}
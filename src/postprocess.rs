@@ -0,0 +1,240 @@
+//! Cleaning up the model's answer before it is spoken.
+//!
+//! The answer goes through a list of steps, in the order given by
+//! `POSTPROCESS` (default `think,fences,backticks,rules,length`):
+//!
+//!  * `think`: drop `<think>` blocks (see [`crate::think`]).
+//!  * `fences`: drop Markdown code blocks.
+//!  * `backticks`: drop the backticks of inline code.
+//!  * `rules`: apply the replacement rules of `~/.jarvis/postprocess.toml`
+//!    (or the file named by `POSTPROCESS_FILE`).
+//!  * `length`: sum up answers longer than `ANSWER_MAX_CHARS` by asking
+//!    the model again. The summary goes through the other steps too, so
+//!    this step always comes last.
+//!
+//! A step left out of the list is not done. Rules are regular expressions
+//! with their replacement, which may refer to groups as `$1`, applied in
+//! file order:
+//!
+//! ```toml
+//! [[rule]]
+//! name = "no emoji"
+//! pattern = '\p{Extended_Pictographic}\x{FE0F}?'
+//! replace = ""
+//!
+//! [[rule]]
+//! name = "no boilerplate"
+//! pattern = '(?i)\bas an ai(?: language model)?,?\s*'
+//! replace = ""
+//! ```
+//!
+//! Streamed answers are spoken a sentence at a time while they are
+//! generated: the rules are applied to each sentence, the other text
+//! steps are done while streaming, and `length` does not apply since a
+//! streamed answer can be interrupted instead.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::config::env_list;
+use crate::think;
+
+/// The steps done when `POSTPROCESS` is not set.
+const DEFAULT_STEPS: &[&str] = &["think", "fences", "backticks", "rules", "length"];
+
+/// One step of the post-processing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Think,
+    Fences,
+    Backticks,
+    Rules,
+    Length,
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "think" => Ok(Step::Think),
+            "fences" => Ok(Step::Fences),
+            "backticks" => Ok(Step::Backticks),
+            "rules" => Ok(Step::Rules),
+            "length" => Ok(Step::Length),
+            other => Err(anyhow!(
+                "unknown post-processing step '{other}' (expected think, fences, backticks, rules or length)"
+            )),
+        }
+    }
+}
+
+/// A replacement rule from the rules file.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub pattern: Regex,
+    pub replace: String,
+}
+
+/// Rules as written in `postprocess.toml`.
+#[derive(Debug, Default, Deserialize)]
+struct RuleFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleEntry {
+    name: Option<String>,
+    pattern: String,
+    #[serde(default)]
+    replace: String,
+}
+
+/// The configured steps and rules.
+#[derive(Debug, Clone)]
+pub struct PostProcessor {
+    steps: Vec<Step>,
+    rules: Vec<Rule>,
+}
+
+impl Default for PostProcessor {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_STEPS.iter().map(|s| s.parse().unwrap()).collect(),
+            Vec::new(),
+        )
+    }
+}
+
+impl PostProcessor {
+    pub fn new(steps: Vec<Step>, rules: Vec<Rule>) -> Self {
+        Self { steps, rules }
+    }
+
+    /// The steps from `POSTPROCESS` and the rules from `POSTPROCESS_FILE`.
+    /// Unknown steps and invalid rules are logged and skipped.
+    pub fn from_env() -> Self {
+        let steps = env_list("POSTPROCESS", DEFAULT_STEPS)
+            .iter()
+            .filter_map(|name| match name.parse() {
+                Ok(step) => Some(step),
+                Err(e) => {
+                    log::warn!("Ignoring POSTPROCESS entry: {e}");
+                    None
+                }
+            })
+            .collect();
+        let rules = rules_file()
+            .map(|path| load_rules(&path))
+            .unwrap_or_default();
+        Self::new(steps, rules)
+    }
+
+    /// Whether `step` is done.
+    pub fn has(&self, step: Step) -> bool {
+        self.steps.contains(&step)
+    }
+
+    /// `answer` after every step but `length`.
+    pub fn clean(&self, answer: &str) -> String {
+        let mut answer = answer.trim().to_string();
+        for step in &self.steps {
+            answer = self.apply(*step, &answer);
+        }
+        answer
+    }
+
+    /// A sentence of a streamed answer after the rules.
+    pub fn clean_sentence(&self, sentence: &str) -> String {
+        if self.has(Step::Rules) {
+            self.apply(Step::Rules, sentence)
+        } else {
+            sentence.to_string()
+        }
+    }
+
+    /// `text` after `step`; `length` leaves it as it is.
+    fn apply(&self, step: Step, text: &str) -> String {
+        let result = match step {
+            Step::Think => think::split(text).0,
+            Step::Fences => strip_fences(text),
+            Step::Backticks => text.replace('`', ""),
+            Step::Rules => self.rules.iter().fold(text.to_string(), |text, rule| {
+                rule.pattern
+                    .replace_all(&text, rule.replace.as_str())
+                    .into_owned()
+            }),
+            Step::Length => return text.to_string(),
+        };
+        if result != text {
+            log::debug!("Answer after {step:?}: {result}");
+        }
+        result.trim().to_string()
+    }
+}
+
+/// `text` without Markdown code blocks.
+fn strip_fences(text: &str) -> String {
+    if !text.contains("```") {
+        return text.to_string();
+    }
+    let mut cleaned = String::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if !in_code {
+            cleaned.push_str(line);
+            cleaned.push('\n');
+        }
+    }
+    cleaned
+}
+
+/// `POSTPROCESS_FILE`, default `~/.jarvis/postprocess.toml`.
+fn rules_file() -> Option<PathBuf> {
+    std::env::var("POSTPROCESS_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("postprocess.toml")))
+}
+
+/// The rules in `path`; none if it does not exist.
+fn load_rules(path: &Path) -> Vec<Rule> {
+    let Ok(data) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let file: RuleFile = match toml::from_str(&data) {
+        Ok(file) => file,
+        Err(e) => {
+            log::warn!("Ignoring invalid rules file {}: {e}", path.display());
+            return Vec::new();
+        }
+    };
+    file.rules
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let name = entry.name.unwrap_or_else(|| format!("rule {}", i + 1));
+            match Regex::new(&entry.pattern).with_context(|| format!("invalid pattern in {name}")) {
+                Ok(pattern) => Some(Rule {
+                    name,
+                    pattern,
+                    replace: entry.replace,
+                }),
+                Err(e) => {
+                    log::warn!("Ignoring post-processing rule: {e:#}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
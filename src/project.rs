@@ -0,0 +1,236 @@
+//! Project profiles: where the tools work, and with what.
+//!
+//! A project is a TOML file in `~/.jarvis/projects/` (or `PROJECTS_DIR`),
+//! named after the project, such as `blog.toml`:
+//!
+//! ```toml
+//! dir = "~/src/blog"
+//! persona = "writer"          # optional, see crate::persona
+//! tools = ["shell_task", "codex_cli_task", "git_task", "file_task"]
+//!
+//! [env]
+//! HUGO_ENV = "development"
+//! ```
+//!
+//! "Switch to project blog" makes `dir` the working directory of the
+//! tools, adds `env` to the environment of the commands they run (see
+//! [`tool_env`]), offers the model only the `tools` listed (all of them
+//! without a list) and switches to the persona. "Leave the project" goes
+//! back to the working directory and persona from before. `PROJECT=blog`
+//! starts in a project.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+
+static OPEN_COMMAND: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?:please )?(?:(?:switch|change|go) to|open|work on|use) (?:the )?(?:project (.+?)|(.+?) project)$",
+    )
+    .unwrap()
+});
+
+static LEAVE_COMMAND: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:please )?(?:leave|close|exit|quit) (?:the |this )?project$").unwrap()
+});
+
+/// The variables of the active project, for the commands the tools run.
+static ENV: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// A project as written in its file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProjectFile {
+    dir: String,
+    persona: Option<String>,
+    tools: Option<Vec<String>>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+/// A project profile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub name: String,
+    /// The working directory of the tools.
+    pub dir: PathBuf,
+    /// The persona to switch to, if any.
+    pub persona: Option<String>,
+    /// The tools the model is offered; `None` for all of them.
+    pub tools: Option<Vec<String>>,
+    /// Variables added to the environment of tool commands.
+    pub env: Vec<(String, String)>,
+}
+
+impl Project {
+    /// Read the project `name` from `path`.
+    pub fn load_file(name: &str, path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).context("cannot read it")?;
+        let file: ProjectFile = toml::from_str(&data)?;
+        Ok(Self {
+            name: name.to_string(),
+            dir: expand_home(file.dir.trim()),
+            persona: file
+                .persona
+                .map(|persona| persona.trim().to_string())
+                .filter(|persona| !persona.is_empty()),
+            tools: file.tools,
+            env: file.env.into_iter().collect(),
+        })
+    }
+
+    /// Whether the model may use the tool called `name` here.
+    pub fn allows(&self, tool: &str) -> bool {
+        match &self.tools {
+            Some(tools) => tools.iter().any(|allowed| allowed == tool),
+            None => true,
+        }
+    }
+}
+
+/// What a command asks of the projects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectCommand {
+    /// "Switch to project blog".
+    Open(String),
+    /// "Leave the project".
+    Leave,
+}
+
+/// The project command in `command`, if it is one.
+pub fn parse_command(command: &str) -> Option<ProjectCommand> {
+    let command = command
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_lowercase();
+    if LEAVE_COMMAND.is_match(&command) {
+        return Some(ProjectCommand::Leave);
+    }
+    let captures = OPEN_COMMAND.captures(&command)?;
+    let name = captures.get(1).or_else(|| captures.get(2))?;
+    Some(ProjectCommand::Open(name.as_str().to_string()))
+}
+
+/// The available projects and the one in use.
+#[derive(Debug, Clone)]
+pub struct Projects {
+    /// `~/.jarvis/projects`, if there is a home directory.
+    dir: Option<PathBuf>,
+    active: Option<Project>,
+}
+
+impl Projects {
+    /// The projects in `dir`, with none active.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir, active: None }
+    }
+
+    /// The projects in `PROJECTS_DIR`, default `~/.jarvis/projects`.
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("PROJECTS_DIR")
+                .map(|dir| expand_home(dir.trim()))
+                .ok()
+                .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("projects"))),
+        )
+    }
+
+    /// The project in use, if any.
+    pub fn active(&self) -> Option<&Project> {
+        self.active.as_ref()
+    }
+
+    /// Whether the model may use the tool called `name` now.
+    pub fn allows(&self, tool: &str) -> bool {
+        match &self.active {
+            Some(project) => project.allows(tool),
+            None => true,
+        }
+    }
+
+    /// Names of the projects, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let Some(entries) = self
+            .dir
+            .as_ref()
+            .and_then(|dir| std::fs::read_dir(dir).ok())
+        else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "toml" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_string_lossy().into_owned())
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// The name of the project file `name` stands for. Spoken names match
+    /// file names regardless of case, spaces, dashes and underscores.
+    pub fn find(&self, name: &str) -> Option<String> {
+        let wanted = normalize(name);
+        self.names()
+            .into_iter()
+            .find(|candidate| normalize(candidate) == wanted)
+    }
+
+    /// Make `name` the active project and its variables those of the tool
+    /// commands.
+    pub fn open(&mut self, name: &str) -> Result<&Project> {
+        let Some(found) = self.find(name) else {
+            bail!("there is no project called '{name}'");
+        };
+        let path = self
+            .dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{found}.toml")))
+            .context("no projects directory")?;
+        let project = Project::load_file(&found, &path)
+            .with_context(|| format!("invalid project file {}", path.display()))?;
+        if !project.dir.is_dir() {
+            bail!(
+                "the folder of project {found}, {}, does not exist",
+                project.dir.display()
+            );
+        }
+        *ENV.lock().unwrap() = project.env.clone();
+        Ok(self.active.insert(project))
+    }
+
+    /// Leave the active project; its variables are dropped.
+    pub fn leave(&mut self) -> Option<Project> {
+        ENV.lock().unwrap().clear();
+        self.active.take()
+    }
+}
+
+/// The variables of the active project, added to those of `TOOL_ENV` for
+/// the commands the tools run.
+pub fn tool_env() -> Vec<(String, String)> {
+    ENV.lock().unwrap().clone()
+}
+
+/// `path` with a leading `~` replaced by the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
@@ -0,0 +1,191 @@
+//! Masking secrets before they are spoken or written down.
+//!
+//! Tool output can hold secrets: `cat .env`, a private key, a token in a
+//! config file. Everything a tool prints, every answer, the session
+//! transcripts, `jarvis.spoken` and the log go through [`redact`] first,
+//! which replaces what looks like a secret with `[redacted]`. Built in are
+//! private key blocks, the API keys and tokens of AWS, GitHub, GitLab,
+//! Slack, Google, OpenAI and Anthropic, JSON web tokens, `Bearer` headers
+//! and the values of `.env`, JSON and YAML settings named like a password,
+//! secret, token or API key.
+//!
+//! Patterns of your own go in `~/.jarvis/redact.toml` (or `REDACT_FILE`)
+//! as regular expressions. When one has a group named `secret`, only that
+//! group is masked:
+//!
+//! ```toml
+//! patterns = [
+//!     'hunter2',
+//!     '(?i)wifi password is (?P<secret>\w+)',
+//! ]
+//! builtin = true   # keep the built-in patterns (default)
+//! ```
+//!
+//! `REDACT=0` turns masking off.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use crate::config::env_flag_or;
+
+/// What a secret is replaced with.
+pub const MASK: &str = "[redacted]";
+
+/// The built-in secret patterns.
+const BUILTIN: &[&str] = &[
+    r"(?s)-----BEGIN [A-Z0-9 ]*PRIVATE KEY-----.*?(?:-----END [A-Z0-9 ]*PRIVATE KEY-----|$)",
+    r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+    r"\bgithub_pat_[A-Za-z0-9_]{22,}\b",
+    r"\bglpat-[A-Za-z0-9_-]{20,}\b",
+    r"\bxox[abprs]-[A-Za-z0-9-]{10,}",
+    r"\bAIza[0-9A-Za-z_-]{35}\b",
+    r"\bsk-(?:ant-|proj-)?[A-Za-z0-9_-]{20,}",
+    r"\beyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+    r"(?i)\bbearer\s+(?P<secret>[A-Za-z0-9._~+/=-]{16,})",
+    r#"(?i)[A-Za-z0-9_]*(?:api[_-]?key|secret[_-]?key|access[_-]?key|private[_-]?key|secret|token|password|passwd)["']?\s*[=:]\s*["']?(?P<secret>[^\s"']{4,})"#,
+];
+
+static REDACTOR: Lazy<Redactor> = Lazy::new(Redactor::from_env);
+
+/// The user's patterns as written in `redact.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RedactFile {
+    #[serde(default)]
+    patterns: Vec<String>,
+    #[serde(default = "default_builtin")]
+    builtin: bool,
+}
+
+fn default_builtin() -> bool {
+    true
+}
+
+/// Finds secrets in text and masks them.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    /// The built-in patterns followed by `extra`.
+    pub fn new(extra: &[String]) -> Result<Self> {
+        let mut redactor = Self::builtin();
+        redactor.add(extra)?;
+        Ok(redactor)
+    }
+
+    /// Only the built-in patterns.
+    pub fn builtin() -> Self {
+        Self {
+            patterns: BUILTIN
+                .iter()
+                .map(|pattern| Regex::new(pattern).expect("built-in pattern"))
+                .collect(),
+        }
+    }
+
+    /// The redactor configured by `REDACT` and `REDACT_FILE` (default
+    /// `~/.jarvis/redact.toml`). A file with mistakes in it is ignored,
+    /// leaving the built-in patterns.
+    pub fn from_env() -> Self {
+        if !env_flag_or("REDACT", true) {
+            return Self::default();
+        }
+        let Some(path) = redact_file().filter(|path| path.exists()) else {
+            return Self::builtin();
+        };
+        Self::load_file(&path).unwrap_or_else(|e| {
+            log::warn!("Ignoring redaction file {}: {e:#}", path.display());
+            Self::builtin()
+        })
+    }
+
+    /// The patterns in `path`.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).context("cannot read it")?;
+        let file: RedactFile = toml::from_str(&data)?;
+        let mut redactor = if file.builtin {
+            Self::builtin()
+        } else {
+            Self::default()
+        };
+        redactor.add(&file.patterns)?;
+        Ok(redactor)
+    }
+
+    fn add(&mut self, patterns: &[String]) -> Result<()> {
+        for pattern in patterns {
+            self.patterns
+                .push(Regex::new(pattern).with_context(|| format!("invalid pattern '{pattern}'"))?);
+        }
+        Ok(())
+    }
+
+    /// `text` with every secret replaced by [`MASK`].
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            let masked = match pattern.replace_all(&text, mask) {
+                Cow::Owned(masked) => Some(masked),
+                Cow::Borrowed(_) => None,
+            };
+            if let Some(masked) = masked {
+                text = Cow::Owned(masked);
+            }
+        }
+        text
+    }
+}
+
+/// The masked form of the match in `captures`: only its `secret` group,
+/// if it has one.
+fn mask(captures: &Captures) -> String {
+    let whole = captures.get(0).expect("the whole match");
+    match captures.name("secret") {
+        Some(secret) => {
+            let start = secret.start() - whole.start();
+            let end = secret.end() - whole.start();
+            format!(
+                "{}{MASK}{}",
+                &whole.as_str()[..start],
+                &whole.as_str()[end..]
+            )
+        }
+        None => MASK.to_string(),
+    }
+}
+
+/// `text` with the secrets masked, as configured by `REDACT` and
+/// `REDACT_FILE`.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    REDACTOR.redact(text)
+}
+
+/// Like [`redact`], for the log: `text` is passed on as it is until the
+/// patterns have been read, because reading them may log a warning.
+pub fn redact_log(text: &str) -> Cow<'_, str> {
+    match Lazy::get(&REDACTOR) {
+        Some(redactor) => redactor.redact(text),
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// Read the patterns now, so that the log is masked from the start.
+pub fn load() {
+    Lazy::force(&REDACTOR);
+}
+
+/// `REDACT_FILE`, default `~/.jarvis/redact.toml`.
+fn redact_file() -> Option<PathBuf> {
+    std::env::var("REDACT_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("redact.toml")))
+}
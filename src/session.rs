@@ -0,0 +1,446 @@
+//! Transcripts of conversations, for reviewing what Jarvis did.
+//!
+//! Every turn is appended as one JSON object to
+//! `~/.jarvis/sessions/<id>.jsonl` (or under `SESSIONS_DIR`): the command,
+//! the model's raw output at each step, the tool calls it made and their
+//! results, the final reply and how long it all took. A session starts
+//! with the first command after waking up and ends when Jarvis goes back
+//! to idle; its id is the local time it started, e.g. `20250101-093000`.
+//! `SESSIONS=0` turns the transcripts off.
+//!
+//! `jarvis sessions list` shows the recorded sessions and
+//! `jarvis sessions export <id> --format md` prints one as Markdown.
+//!
+//! Questions such as "what did I ask you this morning?" or "what did we
+//! talk about docker yesterday?" are answered from the transcripts; see
+//! [`HistoryQuery`] and [`search`].
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::env_flag_or;
+use crate::llm::ToolCall;
+use crate::redact::redact;
+
+/// One request to the model within a turn.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StepRecord {
+    /// The model's answer as it came back.
+    pub raw: String,
+    /// The tool calls in the answer, native or parsed from the text.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Output of each tool call, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_results: Vec<String>,
+    /// What went wrong with the answer, e.g. a malformed tool call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Time the model took to answer.
+    pub model_ms: u64,
+    /// Time the tool calls took.
+    #[serde(default)]
+    pub tools_ms: u64,
+}
+
+/// One command and what came of it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TurnRecord {
+    /// When the command was handled, in RFC 3339.
+    pub time: String,
+    pub user: String,
+    /// The requests to the model; empty for commands answered locally.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepRecord>,
+    /// The reply given to the user.
+    pub reply: String,
+    /// The error the command failed with, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Time from receiving the command to the reply.
+    pub total_ms: u64,
+}
+
+impl TurnRecord {
+    /// Mask the secrets in everything the turn holds; see
+    /// [`crate::redact`].
+    pub fn redact(&mut self) {
+        let mask = |text: &mut String| *text = redact(text).into_owned();
+        mask(&mut self.user);
+        mask(&mut self.reply);
+        if let Some(error) = &mut self.error {
+            mask(error);
+        }
+        for step in &mut self.steps {
+            mask(&mut step.raw);
+            step.tool_results.iter_mut().for_each(mask);
+            if let Some(error) = &mut step.error {
+                mask(error);
+            }
+            for call in &mut step.tool_calls {
+                let arguments = call.arguments.to_string();
+                let masked = redact(&arguments);
+                if masked != arguments {
+                    // A pattern of the user's may not leave valid JSON.
+                    call.arguments = serde_json::from_str(&masked)
+                        .unwrap_or_else(|_| Value::String(masked.into_owned()));
+                }
+            }
+        }
+    }
+}
+
+/// Writes the transcript of the current session.
+#[derive(Debug, Default)]
+pub struct SessionLog {
+    /// Folder of the transcripts; `None` when they are off.
+    dir: Option<PathBuf>,
+    /// Id of the current session, once it has a turn.
+    current: Option<String>,
+}
+
+impl SessionLog {
+    /// Transcripts written to `dir`.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir: Some(dir),
+            current: None,
+        }
+    }
+
+    /// Transcripts configured by `SESSIONS` and `SESSIONS_DIR`.
+    pub fn from_env() -> Self {
+        if !env_flag_or("SESSIONS", true) {
+            return Self::default();
+        }
+        sessions_dir().map(Self::new).unwrap_or_default()
+    }
+
+    /// Whether transcripts are written.
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// Id of the current session, if it has started.
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// End the current session; the next turn starts a new one.
+    pub fn end(&mut self) {
+        self.current = None;
+    }
+
+    /// Append `turn` to the current session's transcript. Failures are
+    /// logged, not returned: a full disk should not silence Jarvis.
+    pub fn record(&mut self, turn: &TurnRecord) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let id = self
+            .current
+            .get_or_insert_with(|| chrono::Local::now().format("%Y%m%d-%H%M%S").to_string());
+        let path = dir.join(format!("{id}.jsonl"));
+        let result = fs::create_dir_all(dir)
+            .and_then(|()| OpenOptions::new().create(true).append(true).open(&path))
+            .and_then(|mut file| {
+                let line = serde_json::to_string(turn).map_err(std::io::Error::other)?;
+                writeln!(file, "{line}")
+            });
+        if let Err(e) = result {
+            log::warn!("Cannot write session log {}: {e}", path.display());
+        }
+    }
+}
+
+/// `SESSIONS_DIR`, default `~/.jarvis/sessions`.
+pub fn sessions_dir() -> Option<PathBuf> {
+    std::env::var("SESSIONS_DIR")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("sessions")))
+}
+
+/// Ids of the recorded sessions, oldest first.
+pub fn list() -> Vec<String> {
+    let Some(entries) = sessions_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut ids: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "jsonl" {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().into_owned())
+        })
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// The turns of session `id`; `last` is the most recent session.
+pub fn load(id: &str) -> Result<(String, Vec<TurnRecord>)> {
+    let id = if id == "last" {
+        match list().pop() {
+            Some(id) => id,
+            None => bail!("no sessions recorded yet"),
+        }
+    } else {
+        id.to_string()
+    };
+    let Some(dir) = sessions_dir() else {
+        bail!("no home directory; set SESSIONS_DIR");
+    };
+    let path = dir.join(format!("{id}.jsonl"));
+    let data = fs::read_to_string(&path)
+        .with_context(|| format!("no session '{id}' (see `jarvis sessions list`)"))?;
+    let turns = data
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(n, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}: invalid line {}", path.display(), n + 1))
+        })
+        .collect::<Result<_>>()?;
+    Ok((id, turns))
+}
+
+/// Session `id` as a Markdown document for review.
+pub fn to_markdown(id: &str, turns: &[TurnRecord]) -> String {
+    let mut out = format!("# Session {id}\n");
+    for (n, turn) in turns.iter().enumerate() {
+        out.push_str(&format!(
+            "\n## {}. {}\n\n*{} · {} ms*\n",
+            n + 1,
+            turn.user,
+            turn.time,
+            turn.total_ms
+        ));
+        for (s, step) in turn.steps.iter().enumerate() {
+            out.push_str(&format!(
+                "\n**Step {}** (model {} ms, tools {} ms)\n",
+                s + 1,
+                step.model_ms,
+                step.tools_ms
+            ));
+            if !step.raw.trim().is_empty() {
+                out.push_str(&format!("\n```text\n{}\n```\n", step.raw.trim()));
+            }
+            for (i, call) in step.tool_calls.iter().enumerate() {
+                out.push_str(&format!("\n- Tool `{}` `{}`\n", call.name, call.arguments));
+                if let Some(result) = step.tool_results.get(i) {
+                    out.push_str(&format!(
+                        "\n  ```text\n  {}\n  ```\n",
+                        result.trim().replace('\n', "\n  ")
+                    ));
+                }
+            }
+            if let Some(error) = &step.error {
+                out.push_str(&format!("\n- Error: {error}\n"));
+            }
+        }
+        if let Some(error) = &turn.error {
+            out.push_str(&format!("\n**Error:** {error}\n"));
+        }
+        out.push_str(&format!("\n**Jarvis:** {}\n", turn.reply));
+    }
+    out
+}
+
+/// The beginnings of a question about earlier sessions, longest first.
+const HISTORY_QUESTIONS: &[&str] = &[
+    "what did i ask you about",
+    "what did i ask you",
+    "what did i ask about",
+    "what did i ask",
+    "what have i asked you about",
+    "what have i asked you",
+    "what did we talk about",
+    "what did we discuss",
+    "what did i say about",
+    "what did i say",
+];
+
+/// Words too common to search for.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "the", "my", "your", "you", "me", "i", "to", "do", "of", "for", "with", "and", "or",
+];
+
+/// A part of the day or week asked about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Today,
+    Morning,
+    Afternoon,
+    Evening,
+    Yesterday,
+    LastNight,
+    ThisWeek,
+    LastWeek,
+}
+
+impl Period {
+    /// The spoken names of each period, longest first.
+    const NAMES: &'static [(&'static str, Period)] = &[
+        ("this morning", Period::Morning),
+        ("this afternoon", Period::Afternoon),
+        ("this evening", Period::Evening),
+        ("tonight", Period::Evening),
+        ("today", Period::Today),
+        ("yesterday", Period::Yesterday),
+        ("last night", Period::LastNight),
+        ("this week", Period::ThisWeek),
+        ("last week", Period::LastWeek),
+    ];
+
+    /// The period as it is spoken, e.g. "this morning".
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, period)| *period == self)
+            .map(|(name, _)| *name)
+            .unwrap_or("today")
+    }
+
+    /// Start and end of the period as seen at `now`.
+    pub fn range(self, now: DateTime<Local>) -> (DateTime<Local>, DateTime<Local>) {
+        let today = now.date_naive();
+        let at = |days_ago: i64, hour: u32| {
+            let day = today - Duration::days(days_ago);
+            let time = NaiveTime::from_hms_opt(hour, 0, 0).unwrap_or_default();
+            Local
+                .from_local_datetime(&day.and_time(time))
+                .earliest()
+                .unwrap_or(now)
+        };
+        match self {
+            Period::Today => (at(0, 0), now),
+            Period::Morning => (at(0, 0), at(0, 12).min(now)),
+            Period::Afternoon => (at(0, 12), at(0, 18).min(now)),
+            Period::Evening => (at(0, 18), now),
+            Period::Yesterday => (at(1, 0), at(0, 0)),
+            Period::LastNight => (at(1, 18), at(0, 6).min(now)),
+            Period::ThisWeek | Period::LastWeek => {
+                let monday = now.weekday().num_days_from_monday() as i64;
+                if self == Period::ThisWeek {
+                    (at(monday, 0), now)
+                } else {
+                    (at(monday + 7, 0), at(monday, 0))
+                }
+            }
+        }
+    }
+}
+
+/// A question about earlier sessions: when, and what about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryQuery {
+    /// The part of the day or week asked about; any time if `None`.
+    pub period: Option<Period>,
+    /// Words every matching turn mentions.
+    pub keywords: Vec<String>,
+}
+
+impl HistoryQuery {
+    /// The question in `command`, if it asks about earlier sessions. A
+    /// bare "what did I ask you?" is left to the conversation history,
+    /// so the question needs a time or a topic.
+    pub fn parse(command: &str) -> Option<Self> {
+        let lower = command
+            .trim()
+            .trim_end_matches(['?', '.', '!'])
+            .to_lowercase();
+        let lower = lower.strip_prefix("please ").unwrap_or(&lower);
+        let rest = HISTORY_QUESTIONS
+            .iter()
+            .find_map(|start| lower.strip_prefix(start))
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '))?;
+        let mut rest = format!(" {} ", rest.trim());
+        let mut period = None;
+        for (name, candidate) in Period::NAMES {
+            let spoken = format!(" {name} ");
+            if rest.contains(&spoken) {
+                rest = rest.replacen(&spoken, " ", 1);
+                period = Some(*candidate);
+                break;
+            }
+        }
+        let rest = rest.trim();
+        let rest = rest.strip_prefix("about ").unwrap_or(rest);
+        let keywords: Vec<String> = rest
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty() && !STOP_WORDS.contains(word))
+            .map(str::to_string)
+            .collect();
+        (period.is_some() || !keywords.is_empty()).then_some(Self { period, keywords })
+    }
+
+    /// Whether `turn` is one the question is about, as seen at `now`.
+    /// Earlier questions about the history are left out.
+    pub fn matches(&self, turn: &TurnRecord, now: DateTime<Local>) -> bool {
+        if Self::parse(&turn.user).is_some() {
+            return false;
+        }
+        if let Some(period) = self.period {
+            let Ok(time) = DateTime::parse_from_rfc3339(&turn.time) else {
+                return false;
+            };
+            let (start, end) = period.range(now);
+            if time < start || time >= end {
+                return false;
+            }
+        }
+        let text = format!("{} {}", turn.user, turn.reply).to_lowercase();
+        self.keywords
+            .iter()
+            .all(|word| text.contains(word.as_str()))
+    }
+
+    /// The question in words, e.g. "about docker this morning".
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.keywords.is_empty() {
+            parts.push(format!("about {}", self.keywords.join(" ")));
+        }
+        if let Some(period) = self.period {
+            parts.push(period.name().to_string());
+        }
+        parts.join(" ")
+    }
+}
+
+/// The recorded turns `query` is about, oldest first. Unreadable sessions
+/// are logged and skipped.
+pub fn search(query: &HistoryQuery, now: DateTime<Local>) -> Vec<TurnRecord> {
+    let since = query
+        .period
+        .map(|period| period.range(now).0.format("%Y%m%d").to_string());
+    let mut ids = list();
+    // A session is named after its start, so one that started the day
+    // before may still have turns in the period.
+    if let Some(since) = &since {
+        let first = ids.iter().rposition(|id| id.as_str() < since.as_str());
+        if let Some(first) = first {
+            ids.drain(..first);
+        }
+    }
+    let mut turns = Vec::new();
+    for id in ids {
+        match load(&id) {
+            Ok((_, session)) => {
+                turns.extend(session.into_iter().filter(|turn| query.matches(turn, now)))
+            }
+            Err(e) => log::warn!("Cannot search session {id}: {e:#}"),
+        }
+    }
+    turns
+}
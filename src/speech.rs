@@ -26,6 +26,27 @@ use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::SampleFormat;
 use vosk::{Model, Recognizer, DecodingState};
 
+/// A recognised utterance together with the recogniser's confidence.
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    /// The recognised text. Empty when no speech was detected.
+    pub text: String,
+    /// Mean per-word confidence in the range `0.0..=1.0`, if the
+    /// recogniser reported word-level results.
+    pub confidence: Option<f32>,
+}
+
+/// A source of transcribed speech.
+///
+/// [`SpeechRecognizer`] is the default implementation. Embedders can plug in
+/// other recognisers (Whisper, a network service, a test fixture) through
+/// [`JarvisBuilder::with_asr`](crate::pipeline::JarvisBuilder::with_asr).
+pub trait Listener: Send + Sync {
+    /// Capture up to `duration` of audio and return what was said. An empty
+    /// transcript means no speech was detected.
+    fn listen(&self, duration: Duration) -> Result<Transcript>;
+}
+
 /// A simple wrapper around Vosk for capturing a short phrase from the microphone
 /// and converting it to text.
 pub struct SpeechRecognizer {
@@ -96,12 +117,14 @@ impl SpeechRecognizer {
 
         Ok(Self { model, device })
     }
+}
 
+impl Listener for SpeechRecognizer {
     /// Listen to the microphone for a fixed duration and return the recognised
-    /// transcript. If no speech is detected an empty string is returned. Any
-    /// errors encountered during recording or recognition will be returned to
-    /// the caller.
-    pub fn listen_for_phrase(&self, duration: Duration) -> Result<String> {
+    /// transcript along with the recogniser's confidence. If no speech is
+    /// detected the transcript text is empty. Any errors encountered during
+    /// recording or recognition will be returned to the caller.
+    fn listen(&self, duration: Duration) -> Result<Transcript> {
         // Obtain the default input configuration. This contains the sample rate,
         // number of channels and sample format supported by the device. If the
         // device does not support input we return an error.
@@ -117,9 +140,9 @@ impl SpeechRecognizer {
         let mut recogniser = Recognizer::new(&self.model, sample_rate)
             .with_context(|| "Failed to create Vosk recogniser")?;
 
-        // We do not need word-level timing or alternatives for the simple
-        // phrase recognition use case.
-        recogniser.set_words(false);
+        // Word-level results carry the per-word confidence used for the
+        // plausibility check. Alternatives are not needed.
+        recogniser.set_words(true);
         recogniser.set_max_alternatives(0);
 
         // Create a channel to transfer audio samples from the CPAL callback to
@@ -269,16 +292,25 @@ impl SpeechRecognizer {
         drop(stream);
         drop(tx_err);
 
-        // If no audio captured, return an empty string
+        // If no audio captured, return an empty transcript
         if samples.is_empty() {
-            return Ok(String::new());
+            return Ok(Transcript::default());
         }
         // Fetch the final recognition result from Vosk
         let final_result = recogniser.final_result();
         // `single()` returns `Option<CompleteResultSingle>`; extract the final transcript
         if let Some(single) = final_result.single() {
-            return Ok(single.text.to_string());
+            let confidence = if single.result.is_empty() {
+                None
+            } else {
+                let total: f32 = single.result.iter().map(|w| w.conf).sum();
+                Some(total / single.result.len() as f32)
+            };
+            return Ok(Transcript {
+                text: single.text.to_string(),
+                confidence,
+            });
         }
-        Ok(String::new())
+        Ok(Transcript::default())
     }
 }
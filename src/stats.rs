@@ -0,0 +1,245 @@
+//! How often each tool and model is used, how often it fails and how long
+//! it takes.
+//!
+//! Every tool call and every request to the model is counted in
+//! `~/.jarvis/stats.json` (or the file named by `STATS_FILE`), with the
+//! latest `500` latencies of each for percentiles. `jarvis stats` prints
+//! the report, which shows, for instance, whether the model keeps sending
+//! work to `codex_cli_task` that `shell_task` would do. A tool call counts
+//! as failed when it is invalid or the tool returns an error; a model
+//! request when it errors or times out. `STATS=0` turns the counting off.
+//! `jarvis stats --json` prints the same numbers for scripts and
+//! monitoring. Several Jarvis processes may count into the same file:
+//! each adds its new calls to what is on disk when it saves.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::config::env_flag_or;
+
+/// Latencies kept per tool or model.
+const MAX_SAMPLES: usize = 500;
+
+/// The counts of one tool or model.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub calls: u64,
+    pub failures: u64,
+    /// The latest latencies, oldest first, in milliseconds.
+    #[serde(default)]
+    pub latencies_ms: Vec<u64>,
+}
+
+impl Usage {
+    fn record(&mut self, ms: u64, ok: bool) {
+        self.calls += 1;
+        if !ok {
+            self.failures += 1;
+        }
+        self.latencies_ms.push(ms);
+        if self.latencies_ms.len() > MAX_SAMPLES {
+            let excess = self.latencies_ms.len() - MAX_SAMPLES;
+            self.latencies_ms.drain(..excess);
+        }
+    }
+
+    /// The latency below which `p` percent of the recent calls finished.
+    pub fn percentile(&self, p: f64) -> Option<u64> {
+        let mut sorted = self.latencies_ms.clone();
+        sorted.sort_unstable();
+        let last = sorted.len().checked_sub(1)?;
+        let rank = ((p / 100.0) * last as f64).round() as usize;
+        sorted.get(rank.min(last)).copied()
+    }
+
+    /// Share of the calls that failed, in percent.
+    pub fn failure_rate(&self) -> f64 {
+        if self.calls == 0 {
+            return 0.0;
+        }
+        self.failures as f64 * 100.0 / self.calls as f64
+    }
+}
+
+/// Usage of the tools and models.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stats {
+    #[serde(default)]
+    pub tools: BTreeMap<String, Usage>,
+    #[serde(default)]
+    pub models: BTreeMap<String, Usage>,
+    /// The file the counts are kept in; `None` when counting is off.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// The calls recorded since the counts were last saved.
+    #[serde(skip)]
+    unsaved: Vec<Call>,
+}
+
+/// One tool call or model request not yet written to the file.
+#[derive(Debug, Clone)]
+struct Call {
+    model: bool,
+    name: String,
+    ms: u64,
+    ok: bool,
+}
+
+impl Stats {
+    /// The counts in `path`, empty if there are none yet.
+    pub fn load(path: PathBuf) -> Self {
+        let mut stats: Stats = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| match serde_json::from_str(&data) {
+                Ok(stats) => Some(stats),
+                Err(e) => {
+                    log::warn!("Ignoring invalid {}: {e}", path.display());
+                    None
+                }
+            })
+            .unwrap_or_default();
+        stats.path = Some(path);
+        stats
+    }
+
+    /// The counts configured by `STATS` and `STATS_FILE`.
+    pub fn from_env() -> Self {
+        if !env_flag_or("STATS", true) {
+            return Self::default();
+        }
+        stats_file().map(Self::load).unwrap_or_default()
+    }
+
+    pub fn record_tool(&mut self, tool: &str, ms: u64, ok: bool) {
+        self.record(false, tool, ms, ok);
+    }
+
+    pub fn record_model(&mut self, model: &str, ms: u64, ok: bool) {
+        self.record(true, model, ms, ok);
+    }
+
+    fn record(&mut self, model: bool, name: &str, ms: u64, ok: bool) {
+        if self.path.is_none() {
+            return;
+        }
+        let call = Call {
+            model,
+            name: name.to_string(),
+            ms,
+            ok,
+        };
+        self.apply(&call);
+        self.unsaved.push(call);
+    }
+
+    fn apply(&mut self, call: &Call) {
+        let usages = if call.model {
+            &mut self.models
+        } else {
+            &mut self.tools
+        };
+        usages
+            .entry(call.name.clone())
+            .or_default()
+            .record(call.ms, call.ok);
+    }
+
+    /// Add the calls recorded since the last save to the counts in the
+    /// file, which another process may have updated meanwhile, and write
+    /// them back. Failures are logged only.
+    pub fn save(&mut self) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let mut saved = Stats::load(path.clone());
+        for call in &self.unsaved {
+            saved.apply(call);
+        }
+        self.unsaved.clear();
+        self.tools = saved.tools;
+        self.models = saved.models;
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let result = serde_json::to_string(self)
+            .map_err(std::io::Error::other)
+            .and_then(|data| fs::write(&path, data));
+        if let Err(e) = result {
+            log::warn!("Cannot write {}: {e}", path.display());
+        }
+    }
+
+    /// The counts with their failure rates and percentiles, for
+    /// `jarvis stats --json`.
+    pub fn summary(&self) -> Value {
+        let summarise = |usages: &BTreeMap<String, Usage>| -> Value {
+            usages
+                .iter()
+                .map(|(name, usage)| {
+                    let summary = json!({
+                        "calls": usage.calls,
+                        "failures": usage.failures,
+                        "failure_rate": usage.failure_rate(),
+                        "p50_ms": usage.percentile(50.0),
+                        "p90_ms": usage.percentile(90.0),
+                        "p99_ms": usage.percentile(99.0),
+                    });
+                    (name.clone(), summary)
+                })
+                .collect::<serde_json::Map<_, _>>()
+                .into()
+        };
+        json!({
+            "tools": summarise(&self.tools),
+            "models": summarise(&self.models),
+        })
+    }
+
+    /// The counts as a table for `jarvis stats`.
+    pub fn report(&self) -> String {
+        if self.tools.is_empty() && self.models.is_empty() {
+            return "No tool calls or model requests recorded yet.".to_string();
+        }
+        let mut out = String::new();
+        for (title, usages) in [("TOOL", &self.tools), ("MODEL", &self.models)] {
+            if usages.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!(
+                "{title:<24} {:>7} {:>8} {:>8} {:>8} {:>8}\n",
+                "CALLS", "FAILED", "P50 MS", "P90 MS", "P99 MS"
+            ));
+            for (name, usage) in usages {
+                let p = |p| {
+                    usage
+                        .percentile(p)
+                        .map_or("-".to_string(), |ms| ms.to_string())
+                };
+                out.push_str(&format!(
+                    "{name:<24} {:>7} {:>7.1}% {:>8} {:>8} {:>8}\n",
+                    usage.calls,
+                    usage.failure_rate(),
+                    p(50.0),
+                    p(90.0),
+                    p(99.0)
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// `STATS_FILE`, default `~/.jarvis/stats.json`.
+pub fn stats_file() -> Option<PathBuf> {
+    std::env::var("STATS_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("stats.json")))
+}
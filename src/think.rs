@@ -0,0 +1,154 @@
+//! What to do with the `<think>` blocks of reasoning models.
+//!
+//! Models such as Qwen 3 and DeepSeek R1 reason inside `<think>...</think>`
+//! before they answer, sometimes in several blocks. The blocks are never
+//! spoken; `THINK_MODE` selects what happens to them:
+//!
+//!  * `strip` (default): the latest reasoning is kept in
+//!    `~/.jarvis/jarvis.think`, replacing the previous one.
+//!  * `log`: every block is appended to `~/.jarvis/jarvis.think` with a
+//!    timestamp. Once the file grows past `THINK_LOG_MAX_BYTES` (default
+//!    256 KiB) its older half is dropped.
+//!  * `speak`: as `strip`, and while the model is reasoning Jarvis says
+//!    `THINK_STATUS` (default "Let me think.") so that the silence is not
+//!    mistaken for a hang. Only streamed replies can do this.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+use crate::config::env_parse;
+
+const OPEN: &str = "<think>";
+const CLOSE: &str = "</think>";
+
+/// Handling of `<think>` blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThinkMode {
+    #[default]
+    Strip,
+    Log,
+    Speak,
+}
+
+impl ThinkMode {
+    /// The mode from `THINK_MODE`.
+    pub fn from_env() -> Self {
+        env_parse("THINK_MODE", Self::default())
+    }
+
+    /// Store the reasoning of one reply as this mode asks.
+    pub fn record(self, thoughts: &[String]) {
+        if thoughts.is_empty() {
+            return;
+        }
+        for thought in thoughts {
+            log::debug!("Captured think block: {thought}");
+        }
+        let Some(path) = think_file() else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let result = match self {
+            ThinkMode::Strip | ThinkMode::Speak => fs::write(&path, thoughts.join("\n\n")),
+            ThinkMode::Log => append(&path, thoughts),
+        };
+        if let Err(e) = result {
+            log::debug!("Cannot write {}: {e}", path.display());
+        }
+    }
+
+    /// What to say while the model is reasoning, in `speak` mode.
+    pub fn status(self) -> Option<String> {
+        (self == ThinkMode::Speak)
+            .then(|| std::env::var("THINK_STATUS").unwrap_or_else(|_| "Let me think.".to_string()))
+    }
+}
+
+impl FromStr for ThinkMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "strip" => Ok(ThinkMode::Strip),
+            "log" => Ok(ThinkMode::Log),
+            "speak" => Ok(ThinkMode::Speak),
+            other => Err(anyhow!(
+                "unknown think mode '{other}' (expected strip, log or speak)"
+            )),
+        }
+    }
+}
+
+/// Split `raw` into the answer and the contents of its think blocks. A
+/// block that is never closed (the model ran out of tokens) takes the
+/// rest of the text. A lone `</think>` ends a block whose opening tag was
+/// left out, as some chat templates do.
+pub fn split(raw: &str) -> (String, Vec<String>) {
+    let mut thoughts = Vec::new();
+    let mut rest = raw;
+    if let Some(end) = rest.find(CLOSE) {
+        if !rest[..end].contains(OPEN) {
+            thoughts.push(rest[..end].trim().to_string());
+            rest = &rest[end + CLOSE.len()..];
+        }
+    }
+    let mut answer = String::new();
+    while let Some(start) = rest.find(OPEN) {
+        answer.push_str(&rest[..start]);
+        let inner = &rest[start + OPEN.len()..];
+        match inner.find(CLOSE) {
+            Some(end) => {
+                thoughts.push(inner[..end].trim().to_string());
+                rest = &inner[end + CLOSE.len()..];
+            }
+            None => {
+                thoughts.push(inner.trim().to_string());
+                rest = "";
+            }
+        }
+    }
+    answer.push_str(rest);
+    thoughts.retain(|thought| !thought.is_empty());
+    (answer.trim().to_string(), thoughts)
+}
+
+/// Whether a partial reply is inside a think block that is still open.
+pub fn is_thinking(partial: &str) -> bool {
+    partial
+        .rfind(OPEN)
+        .is_some_and(|start| !partial[start..].contains(CLOSE))
+}
+
+fn think_file() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".jarvis").join("jarvis.think"))
+}
+
+/// Append `thoughts` to the log at `path`, halving it first if it has
+/// grown too big.
+fn append(path: &Path, thoughts: &[String]) -> std::io::Result<()> {
+    let max_bytes: u64 = env_parse("THINK_LOG_MAX_BYTES", 256 * 1024);
+    if fs::metadata(path).is_ok_and(|meta| meta.len() > max_bytes) {
+        let data = fs::read_to_string(path).unwrap_or_default();
+        let mut cut = data.len() / 2;
+        while !data.is_char_boundary(cut) {
+            cut += 1;
+        }
+        // Keep whole entries: start at the next entry's timestamp.
+        let kept = data[cut..]
+            .find("\n[")
+            .map_or("", |pos| &data[cut + pos + 1..]);
+        fs::write(path, kept)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+    for thought in thoughts {
+        writeln!(file, "[{time}]\n{thought}\n")?;
+    }
+    Ok(())
+}
@@ -7,16 +7,818 @@
 //! executed program and attempt to provide useful error messages on
 //! failure.
 
-use anyhow::{Context, Result};
-use std::process::Command;
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 use wait_timeout::ChildExt;
 
+use crate::config::env_parse;
+use crate::events::EventBus;
+use crate::llm::{ToolCall, ToolSpec};
+use shell::Shell;
+
+pub mod apps;
+pub mod audio;
+pub mod calculator;
+pub mod codex_sessions;
+pub mod containers;
+pub mod dry_run;
+#[cfg(feature = "email")]
+pub mod email;
+pub mod files;
+pub mod git;
+pub mod guard;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod kubernetes;
+pub mod lists;
+pub mod manifest;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(unix)]
+pub mod music;
+#[cfg(feature = "news")]
+pub mod news;
+pub mod notes;
+pub mod notify;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod policy;
+pub mod progress;
+pub mod remote;
+pub mod sandbox;
+pub mod schema;
+pub mod screen;
+pub mod shell;
+pub mod shell_session;
+pub mod status;
+pub mod system;
+pub mod timers;
+pub mod untrusted;
+#[cfg(feature = "weather")]
+pub mod weather;
+#[cfg(feature = "wiki")]
+pub mod wiki;
+
+/// A capability the language model can invoke by name.
+///
+/// Tools are registered in a [`ToolRegistry`]. Every tool is offered to
+/// the model with its [`name`](Tool::name),
+/// [`description`](Tool::description) and [`parameters`](Tool::parameters)
+/// schema. When the model calls it, the agent looks the tool up by name and
+/// passes the arguments object to [`run`](Tool::run).
+pub trait Tool: Send + Sync {
+    /// Identifier the model uses to request this tool.
+    fn name(&self) -> &str;
+    /// One-line instruction telling the model when to use the tool.
+    fn description(&self) -> &str;
+    /// JSON Schema of the arguments object. The default is the single
+    /// `command` string the built-in tools take.
+    fn parameters(&self) -> Value {
+        command_schema("The command to run.")
+    }
+    /// Whether every call waits for the user's "yes, confirm", whatever
+    /// its arguments. The default leaves it to the
+    /// [`guard`](guard::Guard).
+    fn needs_confirmation(&self) -> bool {
+        false
+    }
+    /// What a call with `arguments` will do, to be read out while it
+    /// waits for "yes, confirm": "send an email to Bob". For tools where
+    /// only some calls are risky. The default is none.
+    fn confirmation(&self, arguments: &Value) -> Option<String> {
+        let _ = arguments;
+        None
+    }
+    /// The shell command a call runs, checked against the
+    /// [`policy`](policy::Policy) first. The default is none: the tool
+    /// runs no commands the model wrote.
+    fn shell_line(&self, arguments: &Value) -> Option<String> {
+        let _ = arguments;
+        None
+    }
+    /// Forget whatever the tool keeps between calls, such as a running
+    /// shell, for "reset shell". Returns whether there was anything.
+    fn reset(&self) -> bool {
+        false
+    }
+    /// Publish what the tool has to say unprompted, such as the start of
+    /// a Pomodoro break, on `events` from now on. The default says
+    /// nothing.
+    fn set_event_bus(&self, events: &EventBus) {
+        let _ = events;
+    }
+    /// Execute the tool with the model-supplied arguments.
+    fn run(&self, arguments: &Value) -> Result<String>;
+    /// Like [`run`](Tool::run), passing each line of output to `on_line`
+    /// while the tool is still running. The default runs the tool and
+    /// passes on nothing.
+    fn run_streaming(&self, arguments: &Value, on_line: &mut dyn FnMut(&str)) -> Result<String> {
+        let _ = on_line;
+        self.run(arguments)
+    }
+}
+
+/// Schema of an arguments object with one required `command` string.
+fn command_schema(description: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "command": { "type": "string", "description": description },
+        },
+        "required": ["command"],
+    })
+}
+
+/// Extract the `command` string argument shared by the built-in tools.
+fn command_argument(arguments: &Value) -> Option<&str> {
+    arguments.get("command").and_then(|v| v.as_str())
+}
+
+/// Runs raw shell commands via [`run_shell_task`].
+pub struct ShellTool;
+
+impl Tool for ShellTool {
+    fn name(&self) -> &str {
+        "shell_task"
+    }
+
+    fn description(&self) -> &str {
+        "Use `shell_task` for raw shell commands like 'ls', 'pwd', 'cat', 'date' or 'find'."
+    }
+
+    fn shell_line(&self, arguments: &Value) -> Option<String> {
+        command_argument(arguments).map(str::to_string)
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        self.run_streaming(arguments, &mut |_| {})
+    }
+
+    fn run_streaming(&self, arguments: &Value, on_line: &mut dyn FnMut(&str)) -> Result<String> {
+        match command_argument(arguments) {
+            Some(command) => shell_task(command, on_line),
+            None => Ok("No command provided.".to_string()),
+        }
+    }
+}
+
+/// Delegates coding tasks to the Codex CLI via [`run_codex_cli`].
+pub struct CodexTool;
+
+impl Tool for CodexTool {
+    fn name(&self) -> &str {
+        "codex_cli_task"
+    }
+
+    fn description(&self) -> &str {
+        "Use `codex_cli_task` only for writing or scaffolding code via the Codex CLI, not for running system commands."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "command": {
+                    "type": "string",
+                    "description": "What the Codex CLI should write, in plain words.",
+                },
+                "continue": {
+                    "type": "boolean",
+                    "description": "Continue the last Codex session in this project, for follow-ups such as \"continue where you left off\" or \"apply that change\".",
+                },
+            },
+            "required": ["command"],
+        })
+    }
+
+    /// Simple shell commands are run as they are; anything else runs
+    /// the Codex program.
+    fn shell_line(&self, arguments: &Value) -> Option<String> {
+        let command = command_argument(arguments)?;
+        Some(if is_simple_shell_command(command) {
+            command.to_string()
+        } else {
+            codex_program()
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        self.run_streaming(arguments, &mut |_| {})
+    }
+
+    fn run_streaming(&self, arguments: &Value, on_line: &mut dyn FnMut(&str)) -> Result<String> {
+        let Some(command) = command_argument(arguments) else {
+            return Ok("No Codex instruction provided.".to_string());
+        };
+        if is_simple_shell_command(command) {
+            log::debug!("Redirecting codex_cli_task '{}' to shell_task", command);
+            return shell_task(command, on_line);
+        }
+        let resume = arguments
+            .get("continue")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let Some(project) = working_directory().or_else(|| std::env::current_dir().ok()) else {
+            return codex_cli(command, None, on_line);
+        };
+        let sessions = codex_sessions::CodexSessions::from_env();
+        let branch = codex_sessions::current_branch(&project);
+        let last = if resume { sessions.get(&project) } else { None };
+        let note = match &last {
+            Some(session) => codex_sessions::resume_note(session, branch.as_deref()),
+            None if resume => Some(
+                "There was no earlier Codex session in this project, so this one starts fresh."
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let mut id = None;
+        let result = codex_cli(
+            command,
+            last.as_ref().map(|session| session.id.as_str()),
+            &mut |line| {
+                if id.is_none() {
+                    id = codex_sessions::session_id(line);
+                }
+                on_line(line)
+            },
+        )?;
+        let id = id
+            .or_else(|| result.lines().find_map(codex_sessions::session_id))
+            .or_else(|| last.map(|session| session.id));
+        if let Some(id) = id {
+            let session = codex_sessions::CodexSession {
+                id,
+                instruction: command.trim().to_string(),
+                time: chrono::Local::now().to_rfc3339(),
+                branch,
+            };
+            if let Err(e) = sessions.save(&project, session) {
+                log::warn!("Could not remember the Codex session: {e:#}");
+            }
+        }
+        Ok(match note {
+            Some(note) => format!("{note}\n{result}"),
+            None => result,
+        })
+    }
+}
+
+/// Whether `command`, given to `codex_cli_task`, is a simple shell
+/// command that should be run via `shell_task` instead.
+fn is_simple_shell_command(command: &str) -> bool {
+    let cmd_lower = command.trim().to_lowercase();
+    let simple_shells = ["date", "ls", "pwd", "cat", "find", "uptime"];
+    simple_shells
+        .iter()
+        .any(|c| cmd_lower == *c || cmd_lower.starts_with(&format!("{} ", c)))
+}
+
+/// The tools the agent can use. Everything the agent needs to know about
+/// a tool comes from its registration: the specs offered to the model,
+/// the tool section of the prompt for backends without native tool
+/// calling, and the lookup when the model calls a tool.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `tool`, replacing a registered tool of the same name.
+    pub fn register(&mut self, tool: impl Tool + 'static) -> &mut Self {
+        self.register_arc(Arc::new(tool))
+    }
+
+    /// Like [`register`](Self::register), for a tool that is already
+    /// shared.
+    pub fn register_arc(&mut self, tool: Arc<dyn Tool>) -> &mut Self {
+        self.tools.retain(|t| t.name() != tool.name());
+        self.tools.push(tool);
+        self
+    }
+
+    /// The tool called `name`.
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn Tool>> {
+        self.tools.iter().find(|t| t.name() == name)
+    }
+
+    /// Names of the registered tools, in registration order.
+    pub fn names(&self) -> Vec<&str> {
+        self.tools.iter().map(|t| t.name()).collect()
+    }
+
+    /// The registered tools, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Tool>> {
+        self.tools.iter()
+    }
+
+    /// Whether no tool is registered.
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// The registered tools whose name `keep` accepts.
+    pub fn only(&self, keep: impl Fn(&str) -> bool) -> Self {
+        Self {
+            tools: self
+                .tools
+                .iter()
+                .filter(|tool| keep(tool.name()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The tools as offered to the model.
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        self.tools
+            .iter()
+            .map(|tool| ToolSpec {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                parameters: tool.parameters(),
+            })
+            .collect()
+    }
+
+    /// The tools described in the system prompt, for backends that cannot
+    /// be given them natively. The model is asked to answer with a JSON
+    /// tool call, which the agent recognises in the text.
+    pub fn prompt_section(&self) -> String {
+        if self.tools.is_empty() {
+            return String::new();
+        }
+        let mut section = String::from(
+            "To use a tool, answer with only a JSON object like\n\
+{\"tool\": \"shell_task\", \"arguments\": {\"command\": \"df -h\"}}\n\
+and nothing else. To make several calls that do not depend on each other, answer with\n\
+{\"order\": \"parallel\", \"calls\": [...]}; use \"sequential\" when each call needs the one before.\n\
+The tools and the JSON Schema of their arguments are:\n",
+        );
+        for tool in &self.tools {
+            section.push_str(&format!(
+                "- {}: {} Arguments: {}\n",
+                tool.name(),
+                tool.description(),
+                tool.parameters()
+            ));
+        }
+        section
+    }
+}
+
+impl From<Vec<Arc<dyn Tool>>> for ToolRegistry {
+    fn from(tools: Vec<Arc<dyn Tool>>) -> Self {
+        let mut registry = Self::new();
+        for tool in tools {
+            registry.register_arc(tool);
+        }
+        registry
+    }
+}
+
+/// A tool call that ran, kept for "what did you just run?".
+#[derive(Debug, Clone)]
+pub struct ToolRun {
+    pub call: ToolCall,
+    /// The output, or the error if the tool failed.
+    pub result: std::result::Result<String, String>,
+}
+
+impl ToolRun {
+    /// The call and its result in a sentence fragment: "shell_task with
+    /// df -h, which printed: …".
+    pub fn summary(&self) -> String {
+        let what = guard::describe(&self.call);
+        let what = if what == self.call.name {
+            what
+        } else {
+            format!("{} with {what}", self.call.name)
+        };
+        match &self.result {
+            Ok(output) if output.trim().is_empty() => {
+                format!("{what}, which finished without output")
+            }
+            Ok(output) => format!("{what}, which printed: {}", first_words(output)),
+            Err(e) => format!("{what}, which failed: {}", first_words(e)),
+        }
+    }
+}
+
+/// Characters of output quoted in a [`ToolRun::summary`].
+const SUMMARY_CHARS: usize = 160;
+
+/// `text` on one line, cut to [`SUMMARY_CHARS`] characters.
+fn first_words(text: &str) -> String {
+    let text = untrusted::sanitize(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.chars().count() <= SUMMARY_CHARS {
+        return text;
+    }
+    let cut: String = text.chars().take(SUMMARY_CHARS).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// The tools available to the agent by default: the shell, Codex,
+/// calculator, file, notes, list, system control and status, audio device,
+/// notification, music, screen reading, app launcher, stopwatch and
+/// Pomodoro, git, container, SSH and Kubernetes tools, those declared in
+/// the user's manifest (see [`manifest`]) and, with the `http`,
+/// `weather`, `wiki`, `news`, `email`, `mqtt` and `plugins` features, the
+/// HTTP, weather, Wikipedia, news, email and MQTT tools and the
+/// WebAssembly plugins.
+pub fn default_tools() -> ToolRegistry {
+    let mut tools = ToolRegistry::new();
+    tools
+        .register(ShellTool)
+        .register(CodexTool)
+        .register(calculator::CalculatorTool);
+    if crate::config::env_flag_or("FILE_TOOL", true) {
+        tools.register(files::FileTool::from_env());
+    }
+    if crate::config::env_flag_or("NOTES_TOOL", true) {
+        tools.register(notes::NotesTool::from_env());
+    }
+    if crate::config::env_flag_or("LIST_TOOL", true) {
+        tools.register(lists::ListsTool::from_env());
+    }
+    #[cfg(unix)]
+    if crate::config::env_flag_or("SHELL_SESSION", true) {
+        tools.register(shell_session::SessionShellTool::new());
+    }
+    #[cfg(target_os = "linux")]
+    if crate::config::env_flag_or("SYSTEM_TOOLS", true) {
+        tools
+            .register(system::VolumeTool)
+            .register(system::BrightnessTool)
+            .register(system::MediaTool)
+            .register(audio::AudioDevicesTool::from_env());
+    }
+    if crate::config::env_flag_or("SYSTEM_TOOLS", true) {
+        tools.register(status::SystemStatusTool);
+    }
+    #[cfg(unix)]
+    if crate::config::env_flag_or("NOTIFY_TOOL", true) {
+        tools.register(notify::NotifyTool::from_env());
+    }
+    #[cfg(unix)]
+    if crate::config::env_flag_or("MUSIC_TOOL", true) {
+        tools.register(music::MusicTool::from_env());
+    }
+    #[cfg(target_os = "linux")]
+    if crate::config::env_flag_or("SCREEN_READ", true) {
+        tools.register(screen::ScreenReadTool);
+    }
+    #[cfg(target_os = "linux")]
+    if crate::config::env_flag_or("OPEN_APP", true) {
+        tools.register(apps::OpenAppTool);
+    }
+    if crate::config::env_flag_or("TIMER_TOOLS", true) {
+        tools
+            .register(timers::StopwatchTool::default())
+            .register(timers::PomodoroTool::default());
+    }
+    if crate::config::env_flag_or("GIT_TOOL", true) {
+        tools.register(git::GitTool::default());
+    }
+    if crate::config::env_flag_or("CONTAINERS", true) {
+        if let Some(tool) = containers::ContainersTool::from_env() {
+            tools.register(tool);
+        }
+    }
+    if let Some(tool) = remote::RemoteTool::from_env() {
+        tools.register(tool);
+    }
+    if let Some(tool) = kubernetes::KubectlTool::from_env() {
+        tools.register(tool);
+    }
+    #[cfg(feature = "http")]
+    if let Some(tool) = http::HttpTool::from_env() {
+        tools.register(tool);
+    }
+    #[cfg(feature = "weather")]
+    tools.register(weather::WeatherTool::from_env());
+    #[cfg(feature = "wiki")]
+    tools.register(wiki::WikiTool::from_env());
+    #[cfg(feature = "news")]
+    if let Some(tool) = news::NewsTool::from_env() {
+        tools.register(tool);
+    }
+    #[cfg(feature = "email")]
+    if let Some(tool) = email::EmailTool::from_env() {
+        tools.register(tool);
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(tool) = mqtt::MqttTool::from_env() {
+        tools.register(tool);
+    }
+    for tool in manifest::load() {
+        tools.register(tool);
+    }
+    #[cfg(feature = "plugins")]
+    for tool in plugin::load_all() {
+        tools.register(tool);
+    }
+    tools
+}
+
+/// `line` run by the configured shell (`sh -c`, or `cmd /C` on Windows,
+/// by default; see [`shell`]) in the persistent working directory, if one
+/// is set.
+fn shell_command(line: &str) -> Command {
+    let shell = Shell::from_env();
+    let argv = shell.argv(line);
+    let mut cmd = Command::new(&argv[0]);
+    cmd.args(&argv[1..]).envs(shell.env().iter().cloned());
+    if let Some(cwd) = working_directory() {
+        cmd.current_dir(cwd);
+    }
+    cmd
+}
+
+/// The persistent working directory of the shell tools, if one is set.
+fn working_directory() -> Option<PathBuf> {
+    crate::jarvis_io::JarvisIO::new()
+        .read_working_directory()
+        .map(|cwd| PathBuf::from(cwd.trim()))
+}
+
+/// `line` run like [`shell_command`], inside the sandbox chosen with
+/// `SHELL_SANDBOX` if there is one; see [`sandbox`].
+fn sandboxed_command(line: &str) -> Result<Command> {
+    let Some(sandbox) = sandbox::Sandbox::from_env()? else {
+        return Ok(shell_command(line));
+    };
+    let shell = Shell::from_env();
+    let cwd = working_directory().or_else(|| std::env::current_dir().ok());
+    Ok(sandbox.command(&shell.argv(line), shell.env(), cwd.as_deref()))
+}
+
+/// `argv` run directly, without a shell, like [`sandboxed_command`]: in
+/// the persistent working directory, with the shell's variables, inside
+/// the sandbox if there is one.
+fn program_command(argv: &[String]) -> Result<Command> {
+    let shell = Shell::from_env();
+    let cwd = working_directory();
+    if let Some(sandbox) = sandbox::Sandbox::from_env()? {
+        let cwd = cwd.or_else(|| std::env::current_dir().ok());
+        return Ok(sandbox.command(argv, shell.env(), cwd.as_deref()));
+    }
+    let mut cmd = Command::new(&argv[0]);
+    cmd.args(&argv[1..]).envs(shell.env().iter().cloned());
+    if let Some(cwd) = cwd {
+        cmd.current_dir(cwd);
+    }
+    Ok(cmd)
+}
+
+/// `path` with a leading `~` replaced by the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// `path` with links and `..` resolved. The file itself need not exist
+/// yet, its folder does.
+fn resolve_path(path: &Path) -> Option<PathBuf> {
+    if let Ok(resolved) = path.canonicalize() {
+        return Some(resolved);
+    }
+    let name = path.file_name()?;
+    Some(path.parent()?.canonicalize().ok()?.join(name))
+}
+
+/// Run `cmd` and capture its output, killing it if it runs longer than
+/// `timeout`. Each line the command prints, on stdout or stderr, is
+/// passed to `on_line` as it comes. `None` means it timed out.
+fn output_with_timeout(
+    cmd: Command,
+    timeout: Duration,
+    on_line: &mut dyn FnMut(&str),
+) -> Result<Option<Output>> {
+    let (output, finished) = output_until_timeout(cmd, timeout, on_line)?;
+    Ok(finished.then_some(output))
+}
+
+/// [`output_with_timeout`], keeping what a command that ran out of time
+/// printed before it was killed: the output, and whether the command
+/// finished.
+fn output_until_timeout(
+    mut cmd: Command,
+    timeout: Duration,
+    on_line: &mut dyn FnMut(&str),
+) -> Result<(Output, bool)> {
+    // In a process group of its own, so that a timeout also stops the
+    // commands the shell started.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    let deadline = Instant::now() + timeout;
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to execute command")?;
+    // Read the pipes while waiting, so that a command with a lot of
+    // output does not block on a full pipe.
+    let mut stdout = child.stdout.take();
+    let mut stderr = child.stderr.take();
+    let limit = max_output_bytes();
+    let (lines_tx, lines) = mpsc::channel();
+    let stderr_lines = lines_tx.clone();
+    let stdout = std::thread::spawn(move || read_all(stdout.as_mut(), limit, Some(lines_tx)));
+    let stderr = std::thread::spawn(move || read_all(stderr.as_mut(), limit, Some(stderr_lines)));
+    // Pass on the lines until the command closes its output or runs out
+    // of time.
+    while let Ok(line) = lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        on_line(&line);
+    }
+    let (status, finished) = match child
+        .wait_timeout(deadline.saturating_duration_since(Instant::now()))
+        .context("failed to wait for command")?
+    {
+        Some(status) => (status, true),
+        None => {
+            #[cfg(unix)]
+            let _ = Command::new("kill")
+                .args(["-KILL", "--", &format!("-{}", child.id())])
+                .status();
+            let _ = child.kill();
+            (child.wait().context("failed to wait for command")?, false)
+        }
+    };
+    // After a timeout, something the command left running outside its
+    // process group may still hold the pipes open; don't wait for it.
+    let collect = |reader: std::thread::JoinHandle<Vec<u8>>| {
+        let give_up = Instant::now() + Duration::from_secs(1);
+        while !finished && !reader.is_finished() && Instant::now() < give_up {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        if finished || reader.is_finished() {
+            reader.join().unwrap_or_default()
+        } else {
+            Vec::new()
+        }
+    };
+    let output = Output {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    };
+    Ok((output, finished))
+}
+
+/// Longest line passed on from a command that prints without line
+/// breaks.
+const MAX_LINE_BYTES: usize = 4096;
+
+/// Everything left in `pipe`, of which at most `limit` bytes and one
+/// more are kept, so that [`output_text`] can tell it was cut. The rest
+/// is read and dropped, so that the command does not block on a full
+/// pipe. With `lines`, every line read is sent there as well.
+fn read_all(
+    pipe: Option<&mut impl Read>,
+    limit: usize,
+    lines: Option<mpsc::Sender<String>>,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    let Some(pipe) = pipe else {
+        return data;
+    };
+    let mut buf = [0u8; 8192];
+    let mut line = Vec::new();
+    let send = |line: &mut Vec<u8>| {
+        if let Some(lines) = &lines {
+            let text = String::from_utf8_lossy(line);
+            let _ = lines.send(text.trim_end().to_string());
+        }
+        line.clear();
+    };
+    loop {
+        match pipe.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let room = limit.saturating_add(1).saturating_sub(data.len());
+                data.extend_from_slice(&buf[..n.min(room)]);
+                if lines.is_none() {
+                    continue;
+                }
+                for &byte in &buf[..n] {
+                    if byte == b'\n' {
+                        send(&mut line);
+                    } else if line.len() < MAX_LINE_BYTES {
+                        line.push(byte);
+                    } else {
+                        send(&mut line);
+                        line.push(byte);
+                    }
+                }
+            }
+        }
+    }
+    if !line.is_empty() {
+        send(&mut line);
+    }
+    data
+}
+
+/// How long `shell_task` may run when `SHELL_TIMEOUT_SECS` is not set.
+const DEFAULT_SHELL_TIMEOUT_SECS: u64 = 120;
+
+/// How long `codex_cli_task` may run when `CODEX_TIMEOUT_SECS` is not
+/// set. Codex works through a task in many steps.
+const DEFAULT_CODEX_TIMEOUT_SECS: u64 = 600;
+
+/// The flags `codex` runs with when `CODEX_ARGS` is not set.
+const DEFAULT_CODEX_ARGS: &str = "--dangerously-bypass-approvals-and-sandbox";
+
+/// Errors that are worth trying Codex again for: rate limits, overloaded
+/// servers and dropped connections.
+static TRANSIENT_ERROR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)rate.?limit|too many requests|\b(?:429|502|503|504)\b|overloaded|service unavailable|connection (?:reset|refused|closed)|network error|stream disconnected",
+    )
+    .unwrap()
+});
+
+/// Marker put after output that was cut to [`max_output_bytes`].
+const TRUNCATED: &str = "\n... (output truncated)";
+
+/// Most bytes of stdout and of stderr a tool keeps, and of a tool's
+/// output fed back to the model: `TOOL_MAX_OUTPUT_BYTES` (default 64 KiB,
+/// 0 = no limit).
+pub fn max_output_bytes() -> usize {
+    match env_parse("TOOL_MAX_OUTPUT_BYTES", 64 * 1024) {
+        0 => usize::MAX,
+        limit => limit,
+    }
+}
+
+/// Captured output as trimmed text, cut to [`max_output_bytes`] with a
+/// "(output truncated)" marker.
+fn output_text(bytes: &[u8]) -> String {
+    truncate_bytes(String::from_utf8_lossy(bytes).trim(), max_output_bytes())
+}
+
+/// `work` run on a thread of its own and waited for. The blocking HTTP
+/// client of the web tools must not run on the async runtime's threads,
+/// so they make their requests through this. `what` names the work in
+/// the error if it panics.
+pub fn off_runtime<T: Send>(what: &str, work: impl FnOnce() -> Result<T> + Send) -> Result<T> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(work)
+            .join()
+            .unwrap_or_else(|_| Err(anyhow!("{what} panicked")))
+    })
+}
+
+/// `text` cut to at most `limit` bytes, on a character boundary, and
+/// marked as truncated if anything was cut. Text already cut to `limit`
+/// is left as it is.
+pub fn truncate_bytes(text: &str, limit: usize) -> String {
+    let cut = text
+        .strip_suffix(TRUNCATED)
+        .is_some_and(|kept| kept.len() <= limit);
+    if text.len() <= limit || cut {
+        return text.to_string();
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{TRUNCATED}", text[..end].trim_end())
+}
+
 /// Execute a raw shell command and return its output. The command is
 /// executed using the default system shell (`sh` on Unix and `cmd.exe`
 /// on Windows). Stdout and stderr are captured and concatenated. If
 /// the process exits with a non‑zero status the exit code and stderr
-/// are returned instead of stdout.
+/// are returned instead of stdout. Commands running longer than
+/// `SHELL_TIMEOUT_SECS` are killed.
 pub fn run_shell_task(command: &str) -> Result<String> {
+    shell_task(command, &mut |_| {})
+}
+
+/// [`run_shell_task`], passing each line of output to `on_line` as the
+/// command prints it.
+fn shell_task(command: &str, on_line: &mut dyn FnMut(&str)) -> Result<String> {
     let trimmed = command.trim();
     if trimmed.is_empty() {
         return Ok("No command provided.".to_string());
@@ -41,22 +843,16 @@ pub fn run_shell_task(command: &str) -> Result<String> {
             return Ok(format!("Directory not found: {}", new_dir.display()));
         }
     }
-    // On Windows use `cmd /C`, on other platforms use `sh -c` and set current_dir if configured.
-    #[cfg(target_os = "windows")]
-    let mut cmd = Command::new("cmd");
-    #[cfg(not(target_os = "windows"))]
-    let mut cmd = Command::new("sh");
-    #[cfg(target_os = "windows")]
-    cmd.args(["/C", trimmed]);
-    #[cfg(not(target_os = "windows"))]
-    cmd.args(["-c", trimmed]);
-    if let Some(cwd) = jarvis_io.read_working_directory() {
-        cmd.current_dir(cwd.trim());
-    }
-    let output = cmd.output().context("failed to execute shell command")?;
+    let timeout = Duration::from_secs(env_parse("SHELL_TIMEOUT_SECS", DEFAULT_SHELL_TIMEOUT_SECS));
+    let Some(output) = output_with_timeout(sandboxed_command(trimmed)?, timeout, on_line)? else {
+        return Ok(format!(
+            "Command timed out after {} seconds and was stopped.",
+            timeout.as_secs()
+        ));
+    };
 
-    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let stdout = output_text(&output.stdout);
+    let stderr = output_text(&output.stderr);
     if !output.status.success() {
         let code = output.status.code().unwrap_or(-1);
         if !stderr.is_empty() {
@@ -76,95 +872,132 @@ pub fn run_shell_task(command: &str) -> Result<String> {
     }
 }
 
-/// Run the `codex` CLI in `--full-auto` mode with the provided natural
-/// language instruction. This function assumes that the `codex` binary
-/// is available on the system `PATH`. Execution is limited to a
-/// reasonable duration; if the process times out an error message is
-/// returned. As with [`run_shell_task`], stdout and stderr are
+/// Give the `codex` CLI the provided natural language instruction. The
+/// program is `CODEX_BIN` (default `codex`, found on the `PATH`) with the
+/// flags in `CODEX_ARGS`, run without a shell, so nothing in the
+/// instruction is ever interpreted as shell syntax. It is stopped after
+/// `CODEX_TIMEOUT_SECS`, keeping what it printed until then, and tried
+/// again up to `CODEX_RETRIES` times (default 1) after errors such as
+/// rate limits. As with [`run_shell_task`], stdout and stderr are
 /// captured and formatted into a single string.
 pub fn run_codex_cli(instruction: &str) -> Result<String> {
+    codex_cli(instruction, None, &mut |_| {})
+}
+
+/// The program and arguments that give `instruction` to `program` with
+/// `flags`: the instruction is one argument of its own, after `--` if it
+/// could be taken for a flag.
+pub fn codex_argv(program: &str, flags: &str, instruction: &str) -> Vec<String> {
+    let mut argv = vec![program.to_string()];
+    argv.extend(flags.split_whitespace().map(str::to_string));
+    if instruction.starts_with('-') {
+        argv.push("--".to_string());
+    }
+    argv.push(instruction.to_string());
+    argv
+}
+
+/// `CODEX_BIN`, default `codex`.
+fn codex_program() -> String {
+    std::env::var("CODEX_BIN")
+        .ok()
+        .map(|program| program.trim().to_string())
+        .filter(|program| !program.is_empty())
+        .unwrap_or_else(|| "codex".to_string())
+}
+
+/// Whether a failed Codex run said something worth trying again for.
+pub fn is_transient_error(output: &str) -> bool {
+    TRANSIENT_ERROR.is_match(output)
+}
+
+/// [`run_codex_cli`], in the session `resume` if given (see
+/// [`codex_sessions`]), passing each line of output to `on_line` as Codex
+/// prints it.
+fn codex_cli(
+    instruction: &str,
+    resume: Option<&str>,
+    on_line: &mut dyn FnMut(&str),
+) -> Result<String> {
     let trimmed = instruction.trim();
     if trimmed.is_empty() {
         return Ok("No Codex instruction provided.".to_string());
     }
-    // Quote the instruction so that spaces and special characters are
-    // passed correctly to the codex binary. We rely on the shell to
-    // perform argument parsing so we wrap the entire instruction in
-    // double quotes and escape any existing quotes.
-    let escaped = trimmed.replace('"', "\\\"");
-    let full_cmd = format!(
-        "codex --dangerously-bypass-approvals-and-sandbox \"{}\"",
-        escaped
-    );
-
-    // Use the system shell to execute the command. This allows users to
-    // set up aliases or wrappers for codex as desired. To prevent the
-    // assistant from hanging indefinitely when Codex runs a long task or
-    // encounters an unknown instruction, we spawn the process and
-    // enforce a timeout.
-    use std::time::Duration;
-    // Spawn the Codex CLI process with piped stdout/stderr
-    // Spawn the Codex CLI process, using persistent working directory if set.
-    let jarvis_io = crate::jarvis_io::JarvisIO::new();
-    #[cfg(target_os = "windows")]
-    let mut child = {
-        let mut c = Command::new("cmd");
-        c.args(["/C", &full_cmd])
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped());
-        if let Some(cwd) = jarvis_io.read_working_directory() {
-            c.current_dir(cwd.trim());
-        }
-        c.spawn().context("failed to spawn codex CLI")?
-    };
-    #[cfg(not(target_os = "windows"))]
-    let mut child = {
-        let mut c = Command::new("sh");
-        c.args(["-c", &full_cmd])
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped());
-        if let Some(cwd) = jarvis_io.read_working_directory() {
-            c.current_dir(cwd.trim());
-        }
-        c.spawn().context("failed to spawn codex CLI")?
+    let program = codex_program();
+    let flags = std::env::var("CODEX_ARGS").unwrap_or_else(|_| DEFAULT_CODEX_ARGS.to_string());
+    let argv = match resume {
+        Some(id) => codex_sessions::resume_argv(&program, &flags, id, trimmed),
+        None => codex_argv(&program, &flags, trimmed),
     };
-    // Use wait_timeout to wait for the process with a timeout
-    let timeout = Duration::from_secs(60);
-    match child
-        .wait_timeout(timeout)
-        .context("failed to wait on codex process")?
-    {
-        Some(status) => {
-            // Process exited within timeout; capture output
-            let output = child
-                .wait_with_output()
-                .context("failed to capture codex output")?;
-            let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-            if !status.success() {
-                let code = status.code().unwrap_or(-1);
-                if !stderr.is_empty() {
-                    return Ok(format!("Codex CLI exited with {code}: {stderr}"));
-                } else {
-                    return Ok(format!(
-                        "Codex CLI exited with {code} and produced no output."
-                    ));
-                }
+    let timeout = Duration::from_secs(env_parse("CODEX_TIMEOUT_SECS", DEFAULT_CODEX_TIMEOUT_SECS));
+    let retries: u32 = env_parse("CODEX_RETRIES", 1);
+    let mut attempt = 0;
+    loop {
+        let cmd = program_command(&argv)?;
+        // The sandbox program, if there is one, is what runs Codex.
+        let spawned = cmd.get_program().to_string_lossy().into_owned();
+        let (output, finished) = match output_until_timeout(cmd, timeout, on_line) {
+            Ok(result) => result,
+            Err(e)
+                if spawned == program
+                    && e.root_cause()
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) =>
+            {
+                return Ok(codex_missing(&program));
             }
-            if !stdout.is_empty() {
-                Ok(stdout)
+            Err(e) => return Err(e),
+        };
+        let stdout = output_text(&output.stdout);
+        let stderr = output_text(&output.stderr);
+        if !finished {
+            let printed = if stdout.is_empty() { &stderr } else { &stdout };
+            return Ok(if printed.is_empty() {
+                format!(
+                    "Codex CLI was stopped after {} seconds without printing anything. \
+Try a smaller instruction, or raise CODEX_TIMEOUT_SECS.",
+                    timeout.as_secs()
+                )
+            } else {
+                format!(
+                    "Codex CLI was stopped after {} seconds and may have left its work \
+half done. What it printed until then:\n{printed}",
+                    timeout.as_secs()
+                )
+            });
+        }
+        if output.status.success() {
+            return Ok(if !stdout.is_empty() {
+                stdout
             } else if !stderr.is_empty() {
-                Ok(stderr)
+                stderr
             } else {
-                Ok("Codex ran successfully with no output.".to_string())
-            }
+                "Codex ran successfully with no output.".to_string()
+            });
         }
-        None => {
-            // Timeout expired; kill the process and return message
-            let _ = child.kill();
-            // Wait for the process to exit and clean up resources
-            let _ = child.wait();
-            Ok("Codex CLI timed out. Please try again with a simpler or more specific instruction.".to_string())
+        let code = output.status.code().unwrap_or(-1);
+        // 127: the shell of a sandbox could not find the program.
+        if code == 127 {
+            return Ok(codex_missing(&program));
+        }
+        if attempt < retries && is_transient_error(&format!("{stderr}\n{stdout}")) {
+            attempt += 1;
+            log::warn!("Codex CLI failed with a temporary error, trying again: {stderr}");
+            on_line("Codex hit a temporary error; trying again.");
+            std::thread::sleep(Duration::from_secs(5 * u64::from(attempt)));
+            continue;
         }
+        return Ok(match (stderr.is_empty(), stdout.is_empty()) {
+            (false, true) => format!("Codex CLI exited with {code}: {stderr}"),
+            (false, false) => {
+                format!("Codex CLI exited with {code}: {stderr}\nIts output:\n{stdout}")
+            }
+            (true, false) => format!("Codex CLI exited with {code}. Its output:\n{stdout}"),
+            (true, true) => format!("Codex CLI exited with {code} and produced no output."),
+        });
     }
 }
+
+fn codex_missing(program: &str) -> String {
+    format!("The Codex CLI ({program}) is not installed or not on the PATH; set CODEX_BIN to where it is.")
+}
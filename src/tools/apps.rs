@@ -0,0 +1,350 @@
+//! Opening desktop applications by name.
+//!
+//! `open_app` finds the application the user named among the `.desktop`
+//! entries in the XDG data directories (`~/.local/share/applications`,
+//! `/usr/share/applications`, Flatpak exports, ...), by its name, its
+//! generic name ("web browser"), its keywords or the file name, so "open
+//! firefox" does not depend on the model guessing the program. It is
+//! started with `gtk-launch`, or from the entry's `Exec` line without it.
+//!
+//! If the application already has a window, that window is brought to
+//! the front instead, through `hyprctl` under Hyprland and `wmctrl`
+//! elsewhere on X11, unless the model asks for a new one. `OPEN_APP=0`
+//! leaves the tool out.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use super::{output_with_timeout, Tool};
+
+/// How long `gtk-launch`, `hyprctl` and `wmctrl` may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An application from a `.desktop` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// The file name without `.desktop`, as `gtk-launch` wants it.
+    pub id: String,
+    pub name: String,
+    pub generic_name: Option<String>,
+    pub keywords: Vec<String>,
+    pub exec: String,
+    /// The window class of its windows, if the entry says.
+    pub wm_class: Option<String>,
+}
+
+impl DesktopEntry {
+    /// The application described by `text`, the contents of the file
+    /// `id.desktop`; `None` for hidden entries and ones that are not
+    /// applications.
+    pub fn parse(id: &str, text: &str) -> Option<Self> {
+        let mut in_entry = false;
+        let mut fields = HashMap::new();
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_entry = line == "[Desktop Entry]";
+                continue;
+            }
+            if !in_entry || line.starts_with('#') {
+                continue;
+            }
+            // Translated keys such as Name[de] are left out.
+            if let Some((key, value)) = line.split_once('=') {
+                fields
+                    .entry(key.trim().to_string())
+                    .or_insert_with(|| value.trim().to_string());
+            }
+        }
+        let field = |key: &str| fields.get(key).filter(|value| !value.is_empty()).cloned();
+        let hidden = |key: &str| field(key).is_some_and(|value| value == "true");
+        if field("Type").as_deref() != Some("Application")
+            || hidden("NoDisplay")
+            || hidden("Hidden")
+        {
+            return None;
+        }
+        Some(Self {
+            id: id.to_string(),
+            name: field("Name")?,
+            generic_name: field("GenericName"),
+            keywords: field("Keywords")
+                .map(|keywords| {
+                    keywords
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|keyword| !keyword.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            exec: field("Exec")?,
+            wm_class: field("StartupWMClass"),
+        })
+    }
+
+    /// The window class to look for: the entry's, or its file name.
+    fn window_class(&self) -> &str {
+        self.wm_class
+            .as_deref()
+            .unwrap_or_else(|| self.id.rsplit('.').next().unwrap_or(&self.id))
+    }
+}
+
+/// The program and arguments of an `Exec` line, without the `%f`, `%U`
+/// and similar codes for files to open.
+pub fn exec_argv(exec: &str) -> Vec<String> {
+    let mut argv = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            '\\' if quoted => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    argv.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        argv.push(word);
+    }
+    argv.into_iter()
+        .filter(|arg| !(arg.len() == 2 && arg.starts_with('%')))
+        .map(|arg| arg.replace("%%", "%"))
+        .collect()
+}
+
+/// The best match for `wanted` among `entries`: the same name or file
+/// name first, then a name that contains it, then the generic name and
+/// keywords.
+pub fn find<'a>(entries: &'a [DesktopEntry], wanted: &str) -> Option<&'a DesktopEntry> {
+    let wanted = wanted.trim().to_lowercase();
+    let wanted = wanted.strip_prefix("the ").unwrap_or(&wanted);
+    let wanted = wanted.strip_suffix(" app").unwrap_or(wanted);
+    if wanted.is_empty() {
+        return None;
+    }
+    let id_name = |entry: &DesktopEntry| {
+        entry
+            .id
+            .rsplit('.')
+            .next()
+            .unwrap_or(&entry.id)
+            .to_lowercase()
+    };
+    let tests: [&dyn Fn(&DesktopEntry) -> bool; 4] = [
+        &|entry| entry.name.to_lowercase() == wanted || id_name(entry) == wanted,
+        &|entry| entry.name.to_lowercase().contains(wanted),
+        &|entry| {
+            entry
+                .generic_name
+                .as_ref()
+                .is_some_and(|generic| generic.to_lowercase().contains(wanted))
+        },
+        &|entry| {
+            entry
+                .keywords
+                .iter()
+                .any(|keyword| keyword.to_lowercase() == wanted)
+        },
+    ];
+    tests
+        .iter()
+        .find_map(|test| entries.iter().find(|entry| test(entry)))
+}
+
+/// The applications in the XDG data directories. An entry in an earlier
+/// directory hides one with the same file name in a later one.
+pub fn installed() -> Vec<DesktopEntry> {
+    let mut entries: Vec<DesktopEntry> = Vec::new();
+    let mut seen = HashSet::new();
+    for dir in application_dirs() {
+        let Ok(files) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = files.flatten().map(|file| file.path()).collect();
+        paths.sort();
+        for path in paths {
+            if !path.extension().is_some_and(|ext| ext == "desktop") {
+                continue;
+            }
+            let Some(id) = path.file_stem().map(|id| id.to_string_lossy().into_owned()) else {
+                continue;
+            };
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            if let Some(entry) = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|text| DesktopEntry::parse(&id, &text))
+            {
+                entries.push(entry);
+            }
+        }
+    }
+    entries
+}
+
+/// `applications` in `XDG_DATA_HOME` and `XDG_DATA_DIRS`, with their
+/// defaults, and the Flatpak exports.
+fn application_dirs() -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let home = dirs::home_dir().unwrap_or_default();
+    match std::env::var("XDG_DATA_HOME") {
+        Ok(dir) if !dir.is_empty() => found.push(PathBuf::from(dir)),
+        _ => found.push(home.join(".local/share")),
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    found.extend(data_dirs.split(':').map(PathBuf::from));
+    for flatpak in [
+        home.join(".local/share/flatpak/exports/share"),
+        PathBuf::from("/var/lib/flatpak/exports/share"),
+    ] {
+        if !found.contains(&flatpak) {
+            found.push(flatpak);
+        }
+    }
+    found
+        .into_iter()
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// Bring a window of `entry` to the front; whether there was one.
+fn focus(entry: &DesktopEntry) -> bool {
+    let class = entry.window_class().to_lowercase();
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let Some(clients) = output_of(&["hyprctl", "clients", "-j"]) else {
+            return false;
+        };
+        let clients: Vec<Value> = serde_json::from_str(&clients).unwrap_or_default();
+        let Some(found) = clients
+            .iter()
+            .filter_map(|client| client.get("class").and_then(Value::as_str))
+            .find(|found| found.to_lowercase() == class)
+        else {
+            return false;
+        };
+        let target = format!("class:^({})$", regex::escape(found));
+        return output_of(&["hyprctl", "dispatch", "focuswindow", &target]).is_some();
+    }
+    // `wmctrl -lx` shows the class as `instance.Class` in the third
+    // column.
+    let Some(windows) = output_of(&["wmctrl", "-lx"]) else {
+        return false;
+    };
+    let open = windows.lines().any(|line| {
+        line.split_whitespace()
+            .nth(2)
+            .is_some_and(|column| column.to_lowercase().split('.').any(|part| part == class))
+    });
+    open && output_of(&["wmctrl", "-x", "-a", &class]).is_some()
+}
+
+/// What `argv` prints, if it runs and succeeds.
+fn output_of(argv: &[&str]) -> Option<String> {
+    let mut cmd = Command::new(argv[0]);
+    cmd.args(&argv[1..]);
+    match output_with_timeout(cmd, TIMEOUT, &mut |_| {}) {
+        Ok(Some(output)) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        _ => None,
+    }
+}
+
+/// Start `entry` with `gtk-launch`, or from its `Exec` line.
+fn launch(entry: &DesktopEntry) -> Result<()> {
+    if output_of(&["gtk-launch", &entry.id]).is_some() {
+        return Ok(());
+    }
+    let argv = exec_argv(&entry.exec);
+    let Some((program, args)) = argv.split_first() else {
+        bail!("{} has no command to run", entry.name);
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(dirs::home_dir().as_deref().unwrap_or(Path::new("/")))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("cannot start {program}"))?;
+    // Reap it when it exits, so that it does not linger as a zombie.
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Opens applications, or switches to their windows.
+#[derive(Debug, Default)]
+pub struct OpenAppTool;
+
+impl Tool for OpenAppTool {
+    fn name(&self) -> &str {
+        "open_app"
+    }
+
+    fn description(&self) -> &str {
+        "Use `open_app` to open a desktop application by name, such as 'firefox', 'files' or 'text editor', or switch to it if it is open."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "app": {
+                    "type": "string",
+                    "description": "The application's name as the user said it.",
+                },
+                "new_window": {
+                    "type": "boolean",
+                    "description": "Start it even if it already has a window.",
+                },
+            },
+            "required": ["app"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let Some(wanted) = arguments.get("app").and_then(Value::as_str) else {
+            return Ok("No application provided.".to_string());
+        };
+        let new_window = arguments
+            .get("new_window")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let entries = installed();
+        let Some(entry) = find(&entries, wanted) else {
+            return Ok(format!("I couldn't find an application called {wanted}."));
+        };
+        if !new_window && focus(entry) {
+            return Ok(format!("Switched to {}.", entry.name));
+        }
+        launch(entry)?;
+        Ok(format!("Opening {}.", entry.name))
+    }
+}
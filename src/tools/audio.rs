@@ -0,0 +1,415 @@
+//! Bluetooth devices and where the sound goes.
+//!
+//! `audio_devices` connects and disconnects paired Bluetooth devices with
+//! `bluetoothctl`, and chooses the speakers and microphone with `pactl`,
+//! which PipeWire (through `pipewire-pulse`) and PulseAudio both
+//! understand. Once "connect my headphones" has connected them, they
+//! become the default output, and their microphone, if they have one,
+//! the default input, so the sound and Jarvis' own microphone follow
+//! them. Once they are disconnected, the sound falls back to the devices
+//! that are left.
+//!
+//! Devices are found by the words of their Bluetooth name, or by a name
+//! of your own from `BLUETOOTH_ALIASES`, such as
+//! `headphones=WH-1000XM4,speaker=JBL Flip 5`. `SYSTEM_TOOLS=0` leaves
+//! the tool out.
+
+use std::io::ErrorKind;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{output_text, output_with_timeout, Tool};
+use crate::config::env_list;
+
+/// How long `bluetoothctl` may take to connect a device.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How long the other commands may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a connected device has to show up as speakers.
+const SINK_WAIT: Duration = Duration::from_secs(10);
+
+/// A paired Bluetooth device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BluetoothDevice {
+    /// Such as `AA:BB:CC:DD:EE:FF`.
+    pub address: String,
+    pub name: String,
+}
+
+/// An audio output (sink) or input (source).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioDevice {
+    /// The name `pactl` knows it by, such as
+    /// `bluez_output.AA_BB_CC_DD_EE_FF.1`.
+    pub name: String,
+    /// The name people know it by, such as "WH-1000XM4".
+    pub description: String,
+}
+
+/// The devices `bluetoothctl devices` printed: `Device <address> <name>`
+/// lines.
+pub fn parse_bluetooth_devices(text: &str) -> Vec<BluetoothDevice> {
+    text.lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("Device ")?;
+            let (address, name) = rest.split_once(' ').unwrap_or((rest, rest));
+            Some(BluetoothDevice {
+                address: address.to_string(),
+                name: name.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The sinks or sources in the output of `pactl list sinks` or `pactl
+/// list sources`, without the monitors of the sinks.
+pub fn parse_pactl_list(text: &str) -> Vec<AudioDevice> {
+    let mut devices = Vec::new();
+    let mut name: Option<String> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("Name: ") {
+            name = Some(value.to_string());
+        } else if let Some(value) = line.strip_prefix("Description: ") {
+            if let Some(name) = name.take() {
+                if !name.ends_with(".monitor") {
+                    devices.push(AudioDevice {
+                        name,
+                        description: value.to_string(),
+                    });
+                }
+            }
+        }
+    }
+    devices
+}
+
+/// The paired device `wanted` stands for: the one an alias from
+/// `aliases` (`alias=name` pairs) points to, else the one whose name
+/// holds all the words asked for.
+pub fn find_bluetooth_device<'a>(
+    devices: &'a [BluetoothDevice],
+    wanted: &str,
+    aliases: &[String],
+) -> Option<&'a BluetoothDevice> {
+    let wanted = words(wanted);
+    let target = aliases
+        .iter()
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(alias, _)| words(alias) == wanted)
+        .map(|(_, name)| words(name))
+        .unwrap_or(wanted);
+    devices
+        .iter()
+        .find(|device| words(&device.name) == target)
+        .or_else(|| {
+            devices
+                .iter()
+                .find(|device| contains_all(&words(&device.name), &target))
+        })
+}
+
+/// The output or input `wanted` stands for, by the words of its
+/// description or its name.
+pub fn find_audio_device<'a>(devices: &'a [AudioDevice], wanted: &str) -> Option<&'a AudioDevice> {
+    let wanted = words(wanted);
+    devices.iter().find(|device| {
+        contains_all(&words(&device.description), &wanted) || words(&device.name) == wanted
+    })
+}
+
+/// The outputs or inputs of the Bluetooth device at `address`: PipeWire
+/// and PulseAudio name them after the address, with underscores.
+pub fn bluetooth_audio<'a>(devices: &'a [AudioDevice], address: &str) -> Option<&'a AudioDevice> {
+    let node = address.replace(':', "_").to_uppercase();
+    devices
+        .iter()
+        .find(|device| device.name.starts_with("bluez") && device.name.contains(&node))
+}
+
+/// `text` in lower case words of letters and digits, without "my" and
+/// "the".
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| !word.is_empty() && word != "my" && word != "the")
+        .collect()
+}
+
+fn contains_all(have: &[String], wanted: &[String]) -> bool {
+    !wanted.is_empty() && wanted.iter().all(|word| have.contains(word))
+}
+
+/// Run `argv`: its output, or the problem in words.
+fn run(argv: &[&str], timeout: Duration) -> Result<Result<String, String>> {
+    let mut cmd = Command::new(argv[0]);
+    cmd.args(&argv[1..]);
+    match output_with_timeout(cmd, timeout, &mut |_| {}) {
+        Ok(Some(output)) if output.status.success() => Ok(Ok(output_text(&output.stdout))),
+        Ok(Some(output)) => {
+            let stderr = output_text(&output.stderr);
+            let stdout = output_text(&output.stdout);
+            let said = if stderr.is_empty() { stdout } else { stderr };
+            Ok(Err(format!("{} failed: {said}", argv[0])))
+        }
+        Ok(None) => Ok(Err(format!("{} did not answer in time.", argv[0]))),
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == ErrorKind::NotFound) =>
+        {
+            Ok(Err(format!("{} is not installed.", argv[0])))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Connects Bluetooth devices and chooses the speakers and microphone.
+#[derive(Debug, Clone, Default)]
+pub struct AudioDevicesTool {
+    /// `alias=name` pairs from `BLUETOOTH_ALIASES`.
+    aliases: Vec<String>,
+}
+
+impl AudioDevicesTool {
+    /// The tool with the aliases in `BLUETOOTH_ALIASES`.
+    pub fn from_env() -> Self {
+        Self {
+            aliases: env_list("BLUETOOTH_ALIASES", &[]),
+        }
+    }
+
+    /// The paired Bluetooth devices, and those of them connected.
+    fn bluetooth_devices(&self, which: &str) -> Result<Result<Vec<BluetoothDevice>, String>> {
+        // Older bluetoothctl only knows `paired-devices`.
+        let listed = match run(&["bluetoothctl", "devices", which], TIMEOUT)? {
+            Ok(text) if which == "Paired" && text.trim().is_empty() => {
+                run(&["bluetoothctl", "paired-devices"], TIMEOUT)?
+            }
+            other => other,
+        };
+        Ok(listed.map(|text| parse_bluetooth_devices(&text)))
+    }
+
+    fn audio_devices(&self, kind: &str) -> Result<Result<Vec<AudioDevice>, String>> {
+        Ok(run(&["pactl", "list", kind], TIMEOUT)?.map(|text| parse_pactl_list(&text)))
+    }
+
+    /// The paired device `wanted` stands for, or what to say instead.
+    fn paired(&self, wanted: &str) -> Result<Result<BluetoothDevice, String>> {
+        let devices = match self.bluetooth_devices("Paired")? {
+            Ok(devices) => devices,
+            Err(problem) => return Ok(Err(problem)),
+        };
+        if let Some(device) = find_bluetooth_device(&devices, wanted, &self.aliases) {
+            return Ok(Ok(device.clone()));
+        }
+        let names: Vec<&str> = devices.iter().map(|device| device.name.as_str()).collect();
+        Ok(Err(if names.is_empty() {
+            "No Bluetooth devices are paired.".to_string()
+        } else {
+            format!(
+                "I don't know a Bluetooth device called {wanted}; paired are {}.",
+                names.join(", ")
+            )
+        }))
+    }
+
+    fn connect(&self, wanted: &str) -> Result<String> {
+        let device = match self.paired(wanted)? {
+            Ok(device) => device,
+            Err(problem) => return Ok(problem),
+        };
+        if let Err(problem) = run(
+            &["bluetoothctl", "connect", &device.address],
+            CONNECT_TIMEOUT,
+        )? {
+            return Ok(format!("I couldn't connect {}. {problem}", device.name));
+        }
+        // The speakers show up a moment after the connection.
+        let started = Instant::now();
+        let sink = loop {
+            if let Ok(sinks) = self.audio_devices("sinks")? {
+                if let Some(sink) = bluetooth_audio(&sinks, &device.address) {
+                    break Some(sink.clone());
+                }
+            }
+            if started.elapsed() > SINK_WAIT {
+                break None;
+            }
+            std::thread::sleep(Duration::from_millis(500));
+        };
+        let Some(sink) = sink else {
+            return Ok(format!(
+                "{} is connected, but it doesn't play sound here.",
+                device.name
+            ));
+        };
+        if let Err(problem) = run(&["pactl", "set-default-sink", &sink.name], TIMEOUT)? {
+            return Ok(format!(
+                "{} is connected, but I couldn't play sound through it. {problem}",
+                device.name
+            ));
+        }
+        let microphone = match self.audio_devices("sources")? {
+            Ok(sources) => bluetooth_audio(&sources, &device.address).cloned(),
+            Err(_) => None,
+        };
+        let listening = match &microphone {
+            Some(source) => run(&["pactl", "set-default-source", &source.name], TIMEOUT)?.is_ok(),
+            None => false,
+        };
+        Ok(if listening {
+            format!(
+                "{} is connected, for sound and as the microphone.",
+                device.name
+            )
+        } else {
+            format!("{} is connected and playing the sound.", device.name)
+        })
+    }
+
+    fn disconnect(&self, wanted: &str) -> Result<String> {
+        let device = match self.paired(wanted)? {
+            Ok(device) => device,
+            Err(problem) => return Ok(problem),
+        };
+        Ok(
+            match run(&["bluetoothctl", "disconnect", &device.address], TIMEOUT)? {
+                Ok(_) => format!("{} is disconnected.", device.name),
+                Err(problem) => format!("I couldn't disconnect {}. {problem}", device.name),
+            },
+        )
+    }
+
+    /// Make `wanted` the default output (`sinks`) or input (`sources`).
+    fn choose(&self, kind: &str, wanted: &str) -> Result<String> {
+        let (what, command) = match kind {
+            "sinks" => ("output", "set-default-sink"),
+            _ => ("input", "set-default-source"),
+        };
+        let devices = match self.audio_devices(kind)? {
+            Ok(devices) => devices,
+            Err(problem) => return Ok(problem),
+        };
+        let Some(device) = find_audio_device(&devices, wanted) else {
+            let names: Vec<&str> = devices.iter().map(|d| d.description.as_str()).collect();
+            return Ok(format!(
+                "There is no audio {what} called {wanted}; there are {}.",
+                names.join(", ")
+            ));
+        };
+        Ok(match run(&["pactl", command, &device.name], TIMEOUT)? {
+            Ok(_) => format!("The {what} is now {}.", device.description),
+            Err(problem) => problem,
+        })
+    }
+
+    /// The paired devices, which are connected, and the outputs and inputs
+    /// in use.
+    fn list(&self) -> Result<String> {
+        let mut lines: Vec<String> = Vec::new();
+        match self.bluetooth_devices("Paired")? {
+            Ok(paired) if paired.is_empty() => {
+                lines.push("No Bluetooth devices are paired.".into())
+            }
+            Ok(paired) => {
+                let connected = self.bluetooth_devices("Connected")?.unwrap_or_default();
+                let names: Vec<String> = paired
+                    .iter()
+                    .map(|device| {
+                        if connected.contains(device) {
+                            format!("{} (connected)", device.name)
+                        } else {
+                            device.name.clone()
+                        }
+                    })
+                    .collect();
+                lines.push(format!("Paired Bluetooth devices: {}.", names.join(", ")));
+            }
+            Err(problem) => lines.push(problem),
+        }
+        for (kind, what, default) in [
+            ("sinks", "Outputs", "get-default-sink"),
+            ("sources", "Inputs", "get-default-source"),
+        ] {
+            match self.audio_devices(kind)? {
+                Ok(devices) => {
+                    let current = run(&["pactl", default], TIMEOUT)?.unwrap_or_default();
+                    let names: Vec<String> = devices
+                        .iter()
+                        .map(|device| {
+                            if device.name == current.trim() {
+                                format!("{} (in use)", device.description)
+                            } else {
+                                device.description.clone()
+                            }
+                        })
+                        .collect();
+                    lines.push(format!("{what}: {}.", names.join(", ")));
+                }
+                Err(problem) => {
+                    lines.push(problem);
+                    break;
+                }
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+impl Tool for AudioDevicesTool {
+    fn name(&self) -> &str {
+        "audio_devices"
+    }
+
+    fn description(&self) -> &str {
+        "Use `audio_devices` to connect or disconnect Bluetooth headphones and speakers, to choose the speakers or microphone, or to list them."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["list", "connect", "disconnect", "output", "input"],
+                    "description": "output and input choose the speakers and the microphone.",
+                },
+                "device": {
+                    "type": "string",
+                    "description": "The device, in the user's words, such as headphones.",
+                },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let action = arguments
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_lowercase();
+        let device = arguments
+            .get("device")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .unwrap_or_default();
+        let needs_device = ["connect", "disconnect", "output", "input"].contains(&action.as_str());
+        if needs_device && device.is_empty() {
+            return Ok("Which device?".to_string());
+        }
+        match action.as_str() {
+            "list" => self.list(),
+            "connect" => self.connect(device),
+            "disconnect" => self.disconnect(device),
+            "output" => self.choose("sinks", device),
+            "input" => self.choose("sources", device),
+            _ => Ok(format!("Unknown audio device action '{action}'.")),
+        }
+    }
+}
@@ -0,0 +1,802 @@
+//! Arithmetic and unit conversions worked out locally.
+//!
+//! Small models are unreliable at arithmetic, so `calculate` answers
+//! "what is 18% of 245" and "how many ounces in 2 liters" itself, the
+//! same way every time. It evaluates expressions with `+ - * / ^`,
+//! percentages ("18% of 245"), parentheses, `sqrt`, `ln`, `log`, `sin`
+//! and friends, `pi` and `e`, also written out ("5 times 3", "2 to the
+//! power of 10"). "2 liters in ounces" converts between units of length,
+//! mass, volume, time, speed, temperature, area and data.
+//!
+//! "100 USD in EUR" converts currencies with the exchange rates in
+//! `~/.jarvis/rates.json` (or `CURRENCY_RATES_FILE`), in the format of
+//! the [Frankfurter](https://frankfurter.dev) API: `{"base": "EUR",
+//! "date": "2024-05-31", "rates": {"USD": 1.08, ...}}`. Built with
+//! `--features currency`, Jarvis downloads the European Central Bank's
+//! rates from there once a day into that file.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::Tool;
+
+/// Words for operators, replaced before an expression is read.
+static WORDS: Lazy<Vec<(Regex, &str)>> = Lazy::new(|| {
+    [
+        (r"\bto the power of\b", "^"),
+        (r"\bsquared\b", "^2"),
+        (r"\bcubed\b", "^3"),
+        (r"\bsquare root of\b", "sqrt"),
+        (r"\b(?:multiplied by|times)\b", "*"),
+        (r"\b(?:divided by|over)\b", "/"),
+        (r"\bplus\b", "+"),
+        (r"\bminus\b", "-"),
+        (r"\bpercent\b", "%"),
+        (r"\bx\b", "*"),
+        (r"×", "*"),
+        (r"÷", "/"),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+    .collect()
+});
+
+/// Thousands separators: the comma in `1,000`.
+static THOUSANDS: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\d),(\d{3})\b").unwrap());
+
+/// "2 liters in ounces": an amount, a unit, and the unit wanted.
+static CONVERSION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?P<amount>.*?[\d)])\s*(?P<from>[^\d\s()].*?)\s+(?:in|to|into|as)\s+(?P<to>\S.*)$",
+    )
+    .unwrap()
+});
+
+/// What a unit measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Volume,
+    Time,
+    Speed,
+    Temperature,
+    Area,
+    Data,
+}
+
+/// A unit, as a `factor` and `offset` from the base unit of its
+/// dimension: `base = value * factor + offset`.
+struct Unit {
+    names: &'static [&'static str],
+    singular: &'static str,
+    plural: &'static str,
+    dimension: Dimension,
+    factor: f64,
+    offset: f64,
+}
+
+const fn unit(
+    names: &'static [&'static str],
+    singular: &'static str,
+    plural: &'static str,
+    dimension: Dimension,
+    factor: f64,
+) -> Unit {
+    Unit {
+        names,
+        singular,
+        plural,
+        dimension,
+        factor,
+        offset: 0.0,
+    }
+}
+
+use Dimension::*;
+
+/// The known units. A name may stand for several, such as ounces of
+/// mass and of volume; the conversion picks the one that fits.
+static UNITS: &[Unit] = &[
+    unit(&["m", "meter", "metre"], "meter", "meters", Length, 1.0),
+    unit(
+        &["km", "kilometer", "kilometre"],
+        "kilometer",
+        "kilometers",
+        Length,
+        1000.0,
+    ),
+    unit(
+        &["cm", "centimeter", "centimetre"],
+        "centimeter",
+        "centimeters",
+        Length,
+        0.01,
+    ),
+    unit(
+        &["mm", "millimeter", "millimetre"],
+        "millimeter",
+        "millimeters",
+        Length,
+        0.001,
+    ),
+    unit(&["mi", "mile"], "mile", "miles", Length, 1609.344),
+    unit(&["yd", "yard"], "yard", "yards", Length, 0.9144),
+    unit(&["ft", "foot", "feet"], "foot", "feet", Length, 0.3048),
+    unit(&["in", "inch"], "inch", "inches", Length, 0.0254),
+    unit(
+        &["nmi", "nautical mile"],
+        "nautical mile",
+        "nautical miles",
+        Length,
+        1852.0,
+    ),
+    unit(&["g", "gram", "gramme"], "gram", "grams", Mass, 1.0),
+    unit(
+        &["kg", "kilogram", "kilo"],
+        "kilogram",
+        "kilograms",
+        Mass,
+        1000.0,
+    ),
+    unit(&["mg", "milligram"], "milligram", "milligrams", Mass, 0.001),
+    unit(
+        &["t", "tonne", "metric ton"],
+        "tonne",
+        "tonnes",
+        Mass,
+        1_000_000.0,
+    ),
+    unit(&["lb", "lbs", "pound"], "pound", "pounds", Mass, 453.59237),
+    unit(&["oz", "ounce"], "ounce", "ounces", Mass, 28.349523125),
+    unit(&["st", "stone"], "stone", "stone", Mass, 6350.29318),
+    unit(&["l", "liter", "litre"], "liter", "liters", Volume, 1.0),
+    unit(
+        &["ml", "milliliter", "millilitre"],
+        "milliliter",
+        "milliliters",
+        Volume,
+        0.001,
+    ),
+    unit(
+        &["cl", "centiliter", "centilitre"],
+        "centiliter",
+        "centiliters",
+        Volume,
+        0.01,
+    ),
+    unit(
+        &["dl", "deciliter", "decilitre"],
+        "deciliter",
+        "deciliters",
+        Volume,
+        0.1,
+    ),
+    unit(
+        &["m3", "m³", "cubic meter", "cubic metre"],
+        "cubic meter",
+        "cubic meters",
+        Volume,
+        1000.0,
+    ),
+    unit(
+        &["gal", "gallon"],
+        "US gallon",
+        "US gallons",
+        Volume,
+        3.785411784,
+    ),
+    unit(
+        &["qt", "quart"],
+        "US quart",
+        "US quarts",
+        Volume,
+        0.946352946,
+    ),
+    unit(&["pt", "pint"], "US pint", "US pints", Volume, 0.473176473),
+    unit(&["cup"], "cup", "cups", Volume, 0.2365882365),
+    unit(
+        &["fl oz", "floz", "fluid ounce", "oz", "ounce"],
+        "US fluid ounce",
+        "US fluid ounces",
+        Volume,
+        0.0295735295625,
+    ),
+    unit(
+        &["tbsp", "tablespoon"],
+        "tablespoon",
+        "tablespoons",
+        Volume,
+        0.01478676478125,
+    ),
+    unit(
+        &["tsp", "teaspoon"],
+        "teaspoon",
+        "teaspoons",
+        Volume,
+        0.00492892159375,
+    ),
+    unit(&["s", "sec", "second"], "second", "seconds", Time, 1.0),
+    unit(&["min", "minute"], "minute", "minutes", Time, 60.0),
+    unit(&["h", "hr", "hour"], "hour", "hours", Time, 3600.0),
+    unit(&["day"], "day", "days", Time, 86_400.0),
+    unit(&["week"], "week", "weeks", Time, 604_800.0),
+    unit(&["year"], "year", "years", Time, 31_557_600.0),
+    unit(
+        &["m/s", "meter per second", "meters per second"],
+        "meter per second",
+        "meters per second",
+        Speed,
+        1.0,
+    ),
+    unit(
+        &[
+            "km/h",
+            "kph",
+            "kmh",
+            "kilometer per hour",
+            "kilometers per hour",
+        ],
+        "kilometer per hour",
+        "kilometers per hour",
+        Speed,
+        1.0 / 3.6,
+    ),
+    unit(
+        &["mph", "mile per hour", "miles per hour"],
+        "mile per hour",
+        "miles per hour",
+        Speed,
+        0.44704,
+    ),
+    unit(&["kn", "knot"], "knot", "knots", Speed, 1852.0 / 3600.0),
+    Unit {
+        names: &["c", "°c", "celsius", "centigrade"],
+        singular: "degree Celsius",
+        plural: "degrees Celsius",
+        dimension: Temperature,
+        factor: 1.0,
+        offset: 273.15,
+    },
+    Unit {
+        names: &["f", "°f", "fahrenheit"],
+        singular: "degree Fahrenheit",
+        plural: "degrees Fahrenheit",
+        dimension: Temperature,
+        factor: 5.0 / 9.0,
+        offset: 273.15 - 32.0 * 5.0 / 9.0,
+    },
+    unit(&["k", "kelvin"], "kelvin", "kelvin", Temperature, 1.0),
+    unit(
+        &["m2", "m²", "square meter", "square metre"],
+        "square meter",
+        "square meters",
+        Area,
+        1.0,
+    ),
+    unit(
+        &["km2", "km²", "square kilometer", "square kilometre"],
+        "square kilometer",
+        "square kilometers",
+        Area,
+        1_000_000.0,
+    ),
+    unit(
+        &["ft2", "ft²", "sq ft", "square foot", "square feet"],
+        "square foot",
+        "square feet",
+        Area,
+        0.09290304,
+    ),
+    unit(&["ha", "hectare"], "hectare", "hectares", Area, 10_000.0),
+    unit(&["acre"], "acre", "acres", Area, 4046.8564224),
+    unit(&["b", "byte"], "byte", "bytes", Data, 1.0),
+    unit(&["kb", "kilobyte"], "kilobyte", "kilobytes", Data, 1e3),
+    unit(&["mb", "megabyte"], "megabyte", "megabytes", Data, 1e6),
+    unit(&["gb", "gigabyte"], "gigabyte", "gigabytes", Data, 1e9),
+    unit(&["tb", "terabyte"], "terabyte", "terabytes", Data, 1e12),
+    unit(&["kib", "kibibyte"], "kibibyte", "kibibytes", Data, 1024.0),
+    unit(
+        &["mib", "mebibyte"],
+        "mebibyte",
+        "mebibytes",
+        Data,
+        1_048_576.0,
+    ),
+    unit(
+        &["gib", "gibibyte"],
+        "gibibyte",
+        "gibibytes",
+        Data,
+        1_073_741_824.0,
+    ),
+];
+
+/// Currency names that are not ISO codes.
+const CURRENCY_NAMES: &[(&str, &str)] = &[
+    ("$", "USD"),
+    ("dollar", "USD"),
+    ("€", "EUR"),
+    ("euro", "EUR"),
+    ("£", "GBP"),
+    ("pound", "GBP"),
+    ("pound sterling", "GBP"),
+    ("quid", "GBP"),
+    ("yen", "JPY"),
+    ("franc", "CHF"),
+];
+
+/// Exchange rates: how much of each currency one unit of `base` buys.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rates {
+    pub base: String,
+    #[serde(default)]
+    pub date: String,
+    pub rates: HashMap<String, f64>,
+}
+
+impl Rates {
+    /// How much one unit of `code` is worth in the base currency.
+    fn value_of(&self, code: &str) -> Option<f64> {
+        if code == self.base {
+            return Some(1.0);
+        }
+        self.rates
+            .get(code)
+            .filter(|rate| **rate > 0.0)
+            .map(|rate| 1.0 / rate)
+    }
+}
+
+/// Works out sums and conversions.
+#[derive(Debug, Default)]
+pub struct CalculatorTool;
+
+impl Tool for CalculatorTool {
+    fn name(&self) -> &str {
+        "calculate"
+    }
+
+    fn description(&self) -> &str {
+        "Use `calculate` for any arithmetic, percentage, unit or currency conversion instead of working it out yourself."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": {
+                    "type": "string",
+                    "description": "Such as '18% of 245', '(3 + 4) * 2', '2 liters in ounces' or '100 USD in EUR'.",
+                },
+            },
+            "required": ["expression"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let Some(expression) = arguments.get("expression").and_then(Value::as_str) else {
+            return Ok("No expression provided.".to_string());
+        };
+        // Mistakes go back to the model, which may write it differently.
+        Ok(answer(expression, &load_rates)
+            .unwrap_or_else(|e| format!("I can't work out '{expression}': {e:#}.")))
+    }
+}
+
+/// The answer to `text`, a sum or a conversion, as a sentence: "18% of
+/// 245 is 44.1." Exchange rates come from `rates` when needed.
+pub fn answer(text: &str, rates: &dyn Fn() -> Result<Rates>) -> Result<String> {
+    let mut shown = text.trim().trim_end_matches(['?', '.', '=']).trim();
+    for prefix in ["what is ", "what's ", "calculate "] {
+        if shown
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        {
+            shown = shown[prefix.len()..].trim_start();
+        }
+    }
+    let normal = normalize(shown);
+    if let Some(caps) = CONVERSION.captures(&normal) {
+        let amount = evaluate(&caps["amount"]);
+        if let Ok(amount) = amount {
+            return convert(amount, caps["from"].trim(), caps["to"].trim(), rates);
+        }
+    }
+    let value = evaluate(&normal)?;
+    Ok(format!("{shown} is {}.", format_number(value)))
+}
+
+/// `text` in lower case with operators written out replaced.
+fn normalize(text: &str) -> String {
+    let mut text = text.to_lowercase();
+    for (pattern, replacement) in WORDS.iter() {
+        text = pattern.replace_all(&text, *replacement).into_owned();
+    }
+    THOUSANDS.replace_all(&text, "$1$2").into_owned()
+}
+
+/// The value of the arithmetic expression `text`.
+pub fn evaluate(text: &str) -> Result<f64> {
+    let mut parser = Parser {
+        tokens: tokenize(&normalize(text))?,
+        pos: 0,
+    };
+    let value = parser.expression()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        bail!("unexpected {token}");
+    }
+    if !value.is_finite() {
+        bail!("the result is not a number");
+    }
+    Ok(value)
+}
+
+/// `amount` of the unit or currency `from` in `to`, as a sentence.
+fn convert(amount: f64, from: &str, to: &str, rates: &dyn Fn() -> Result<Rates>) -> Result<String> {
+    let (from_units, to_units) = (units(from), units(to));
+    let pair = from_units.iter().find_map(|from| {
+        to_units
+            .iter()
+            .find(|to| to.dimension == from.dimension)
+            .map(|to| (*from, *to))
+    });
+    if let Some((from, to)) = pair {
+        let base = amount * from.factor + from.offset;
+        let result = (base - to.offset) / to.factor;
+        return Ok(format!(
+            "{} {} is {} {}.",
+            format_number(amount),
+            if amount == 1.0 {
+                from.singular
+            } else {
+                from.plural
+            },
+            format_number(result),
+            if result == 1.0 {
+                to.singular
+            } else {
+                to.plural
+            },
+        ));
+    }
+    if let (Some(from), Some(to)) = (currency(from), currency(to)) {
+        let rates = rates()?;
+        let value = |code: &str| {
+            rates
+                .value_of(code)
+                .ok_or_else(|| anyhow!("there is no exchange rate for {code}"))
+        };
+        let result = amount * value(&from)? / value(&to)?;
+        let date = if rates.date.is_empty() {
+            String::new()
+        } else {
+            format!(" at the rate of {}", rates.date)
+        };
+        return Ok(format!(
+            "{} {from} is {result:.2} {to}{date}.",
+            format_number(amount)
+        ));
+    }
+    match (from_units.is_empty(), to_units.is_empty()) {
+        (true, _) => bail!("I don't know the unit '{from}'"),
+        (_, true) => bail!("I don't know the unit '{to}'"),
+        _ => bail!("{from} cannot be converted to {to}"),
+    }
+}
+
+/// The units `name` may stand for, singular or plural.
+fn units(name: &str) -> Vec<&'static Unit> {
+    let name = name.trim().trim_end_matches('.');
+    let name = name
+        .strip_prefix("degrees ")
+        .or_else(|| name.strip_prefix("degree "))
+        .unwrap_or(name);
+    let name = name.strip_prefix("us ").unwrap_or(name);
+    let mut candidates = vec![name.to_string()];
+    for suffix in ["es", "s"] {
+        if let Some(stem) = name.strip_suffix(suffix) {
+            candidates.push(stem.to_string());
+        }
+    }
+    // "meters per second" and "square feet" are listed as they are.
+    UNITS
+        .iter()
+        .filter(|unit| {
+            candidates
+                .iter()
+                .any(|candidate| unit.names.contains(&candidate.as_str()))
+        })
+        .collect()
+}
+
+/// The ISO code of the currency `name`: "usd", "dollars", "€".
+fn currency(name: &str) -> Option<String> {
+    let name = name.trim();
+    let singular = name.strip_suffix('s').unwrap_or(name);
+    if let Some((_, code)) = CURRENCY_NAMES
+        .iter()
+        .find(|(known, _)| *known == name || *known == singular)
+    {
+        return Some(code.to_string());
+    }
+    (name.len() == 3 && name.chars().all(|c| c.is_ascii_alphabetic())).then(|| name.to_uppercase())
+}
+
+/// `value` as it is read out: whole numbers without decimals, others with
+/// up to four.
+pub fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return (value as i64).to_string();
+    }
+    if value.abs() < 1e-4 {
+        return format!("{value:e}");
+    }
+    let text = format!("{value:.4}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// A piece of an expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{n}"),
+            Token::Name(name) => write!(f, "'{name}'"),
+            Token::Op(op) => write!(f, "'{op}'"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            let value = number
+                .parse()
+                .with_context(|| format!("'{number}' is not a number"))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_alphabetic() {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !c.is_alphanumeric() {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else if "+-*/^%()!".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else {
+            bail!("unexpected '{c}'");
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads an expression by recursive descent, lowest precedence first.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Sums and differences.
+    fn expression(&mut self) -> Result<f64> {
+        let mut value = self.term()?;
+        loop {
+            match self.peek() {
+                Some(Token::Op('+')) => {
+                    self.pos += 1;
+                    value += self.term()?;
+                }
+                Some(Token::Op('-')) => {
+                    self.pos += 1;
+                    value -= self.term()?;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    /// Products and quotients; "of" multiplies, as in "18% of 245".
+    fn term(&mut self) -> Result<f64> {
+        let mut value = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Op('*')) => {
+                    self.pos += 1;
+                    value *= self.unary()?;
+                }
+                Some(Token::Name(name)) if name == "of" => {
+                    self.pos += 1;
+                    value *= self.unary()?;
+                }
+                Some(Token::Op('/')) => {
+                    self.pos += 1;
+                    let divisor = self.unary()?;
+                    if divisor == 0.0 {
+                        bail!("division by zero");
+                    }
+                    value /= divisor;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64> {
+        match self.peek() {
+            Some(Token::Op('-')) => {
+                self.pos += 1;
+                Ok(-self.unary()?)
+            }
+            Some(Token::Op('+')) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    /// Powers, which bind to the right: `2^3^2` is `2^9`.
+    fn power(&mut self) -> Result<f64> {
+        let base = self.postfix()?;
+        if self.peek() == Some(&Token::Op('^')) {
+            self.pos += 1;
+            return Ok(base.powf(self.unary()?));
+        }
+        Ok(base)
+    }
+
+    /// Percentages and factorials.
+    fn postfix(&mut self) -> Result<f64> {
+        let mut value = self.primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Op('%')) => {
+                    self.pos += 1;
+                    value /= 100.0;
+                }
+                Some(Token::Op('!')) => {
+                    self.pos += 1;
+                    if value < 0.0 || value.fract() != 0.0 || value > 170.0 {
+                        bail!("only whole numbers up to 170 have a factorial");
+                    }
+                    value = (1..=value as u32).map(f64::from).product();
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Op('(')) => {
+                let value = self.expression()?;
+                match self.next() {
+                    Some(Token::Op(')')) => Ok(value),
+                    _ => bail!("a ')' is missing"),
+                }
+            }
+            Some(Token::Name(name)) => match name.as_str() {
+                "pi" => Ok(std::f64::consts::PI),
+                "e" => Ok(std::f64::consts::E),
+                _ => {
+                    let argument = self.postfix()?;
+                    let value = match name.as_str() {
+                        "sqrt" => argument.sqrt(),
+                        "abs" => argument.abs(),
+                        "round" => argument.round(),
+                        "floor" => argument.floor(),
+                        "ceil" => argument.ceil(),
+                        "ln" => argument.ln(),
+                        "log" => argument.log10(),
+                        "exp" => argument.exp(),
+                        "sin" => argument.sin(),
+                        "cos" => argument.cos(),
+                        "tan" => argument.tan(),
+                        _ => bail!("unknown function '{name}'"),
+                    };
+                    Ok(value)
+                }
+            },
+            Some(token) => bail!("unexpected {token}"),
+            None => bail!("the expression ends too soon"),
+        }
+    }
+}
+
+/// `CURRENCY_RATES_FILE`, default `~/.jarvis/rates.json`.
+fn rates_file() -> Option<PathBuf> {
+    std::env::var("CURRENCY_RATES_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("rates.json")))
+}
+
+/// The exchange rates in the rates file, downloaded first if it is
+/// missing or a day old and Jarvis has the `currency` feature.
+fn load_rates() -> Result<Rates> {
+    let path = rates_file().context("no home directory for the exchange rates")?;
+    #[cfg(feature = "currency")]
+    {
+        let fresh = std::fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age <= std::time::Duration::from_secs(24 * 3600));
+        if !fresh {
+            let fetched = super::off_runtime("the exchange rate request", fetch_rates);
+            match fetched {
+                Ok(rates) => {
+                    if let Err(e) = std::fs::write(&path, serde_json::to_string(&rates)?) {
+                        log::warn!("Cannot save exchange rates to {}: {e}", path.display());
+                    }
+                    return Ok(rates);
+                }
+                // Old rates are better than none.
+                Err(e) => log::warn!("Cannot download exchange rates: {e:#}"),
+            }
+        }
+    }
+    let data = std::fs::read_to_string(&path)
+        .with_context(|| format!("there are no exchange rates in {}", path.display()))?;
+    serde_json::from_str(&data)
+        .with_context(|| format!("invalid exchange rates in {}", path.display()))
+}
+
+/// Today's rates of the European Central Bank.
+#[cfg(feature = "currency")]
+fn fetch_rates() -> Result<Rates> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .context("failed to create HTTP client")?;
+    Ok(client
+        .get("https://api.frankfurter.dev/v1/latest")
+        .send()?
+        .error_for_status()?
+        .json()?)
+}
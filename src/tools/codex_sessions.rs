@@ -0,0 +1,142 @@
+//! Codex sessions kept per project.
+//!
+//! Every `codex_cli_task` run prints the id of the Codex session it
+//! worked in. The id is saved for the project directory it ran in, with
+//! the instruction and the git branch at the time, in
+//! `~/.jarvis/codex_sessions.json` (or the file named by
+//! `CODEX_SESSIONS_FILE`). A later call with `continue` set, for "continue
+//! where you left off" or "apply that change", resumes that session with
+//! `resume <id>` instead of starting from scratch, and mentions it when
+//! the branch has changed since.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{codex_argv, output_text, output_with_timeout};
+
+/// The session id Codex prints as it starts: `session id: <uuid>`, or
+/// `"thread_id":"<uuid>"` with `--json`.
+static SESSION_ID: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)(?:session[ _]id|"thread_id"|"session_id")\s*[:=]\s*"?([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})"#)
+        .unwrap()
+});
+
+/// The last Codex session in a project.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodexSession {
+    pub id: String,
+    /// The instruction that started or last continued it.
+    pub instruction: String,
+    /// When it was last used, in RFC 3339.
+    pub time: String,
+    /// The git branch checked out then, if the project is a repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+}
+
+/// The saved sessions, by project directory.
+#[derive(Debug, Clone)]
+pub struct CodexSessions {
+    path: Option<PathBuf>,
+}
+
+impl CodexSessions {
+    /// Sessions saved in `path`.
+    pub fn open(path: PathBuf) -> Self {
+        Self { path: Some(path) }
+    }
+
+    /// Sessions saved in `CODEX_SESSIONS_FILE`, default
+    /// `~/.jarvis/codex_sessions.json`.
+    pub fn from_env() -> Self {
+        Self {
+            path: sessions_file(),
+        }
+    }
+
+    fn load(&self) -> BTreeMap<String, CodexSession> {
+        let Some(path) = &self.path else {
+            return BTreeMap::new();
+        };
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                log::warn!("Ignoring Codex sessions file {}: {e}", path.display());
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        }
+    }
+
+    /// The last session in `project`.
+    pub fn get(&self, project: &Path) -> Option<CodexSession> {
+        self.load().remove(&key(project))
+    }
+
+    /// Remember `session` as the last one in `project`.
+    pub fn save(&self, project: &Path, session: CodexSession) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut sessions = self.load();
+        sessions.insert(key(project), session);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).ok();
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&sessions)?)
+            .with_context(|| format!("cannot write {}", path.display()))
+    }
+}
+
+fn key(project: &Path) -> String {
+    project.display().to_string()
+}
+
+/// The session id in a line Codex printed, if there is one.
+pub fn session_id(line: &str) -> Option<String> {
+    SESSION_ID
+        .captures(line)
+        .map(|captures| captures[1].to_lowercase())
+}
+
+/// The program and arguments that give `instruction` to the session `id`
+/// of `program`, like [`codex_argv`]: `codex <flags> resume <id> <instruction>`.
+pub fn resume_argv(program: &str, flags: &str, id: &str, instruction: &str) -> Vec<String> {
+    codex_argv(program, &format!("{flags} resume {id}"), instruction)
+}
+
+/// The branch checked out in `project`, if it is a git repository.
+pub fn current_branch(project: &Path) -> Option<String> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C")
+        .arg(project)
+        .args(["rev-parse", "--abbrev-ref", "HEAD"]);
+    let output = output_with_timeout(cmd, Duration::from_secs(5), &mut |_| {}).ok()??;
+    let branch = output_text(&output.stdout);
+    (output.status.success() && !branch.is_empty()).then_some(branch)
+}
+
+/// What to say before the output of a resumed `session`, when the
+/// project is now on `branch`.
+pub fn resume_note(session: &CodexSession, branch: Option<&str>) -> Option<String> {
+    match (session.branch.as_deref(), branch) {
+        (Some(then), Some(now)) if then != now => Some(format!(
+            "The last Codex session here was on {then}; you're on {now} now."
+        )),
+        _ => None,
+    }
+}
+
+/// `CODEX_SESSIONS_FILE`, default `~/.jarvis/codex_sessions.json`.
+fn sessions_file() -> Option<PathBuf> {
+    std::env::var("CODEX_SESSIONS_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("codex_sessions.json")))
+}
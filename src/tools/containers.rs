@@ -0,0 +1,241 @@
+//! Docker and Podman containers.
+//!
+//! `containers` lists the running containers, starts, stops and restarts
+//! them by name and reads the end of their logs, for "is Nextcloud
+//! running?" or "restart the media server" on a home server. It runs
+//! `docker` or `podman`, whichever is installed, or `CONTAINER_CLI`,
+//! with fixed arguments. The name the user says is matched against the
+//! existing containers ("next cloud" finds `nextcloud-app-1`), and stopping
+//! or restarting one waits for "yes, confirm". `CONTAINERS=0` leaves the
+//! tool out.
+
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{max_output_bytes, output_text, output_with_timeout, truncate_bytes, Tool};
+
+/// How long a container command may take; stopping waits for the
+/// container to shut down.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Log lines read out by default.
+const DEFAULT_LOG_LINES: u64 = 20;
+
+/// Starts, stops and inspects containers.
+#[derive(Debug, Clone)]
+pub struct ContainersTool {
+    /// `docker` or `podman`.
+    cli: String,
+}
+
+impl ContainersTool {
+    /// A tool running `cli`.
+    pub fn new(cli: impl Into<String>) -> Self {
+        Self { cli: cli.into() }
+    }
+
+    /// The tool for `CONTAINER_CLI`, or `docker` or `podman` if one is
+    /// installed; `None` otherwise.
+    pub fn from_env() -> Option<Self> {
+        if let Ok(cli) = std::env::var("CONTAINER_CLI") {
+            if !cli.trim().is_empty() {
+                return Some(Self::new(cli.trim()));
+            }
+        }
+        ["docker", "podman"]
+            .into_iter()
+            .find(|program| installed(program))
+            .map(Self::new)
+    }
+
+    /// Run the CLI with `args`: its output, or the problem in words.
+    fn run_cli(&self, args: &[String]) -> Result<Result<String, String>> {
+        let mut cmd = Command::new(&self.cli);
+        cmd.args(args);
+        Ok(match output_with_timeout(cmd, TIMEOUT, &mut |_| {})? {
+            Some(output) if output.status.success() => {
+                // `docker logs` passes on the container's stderr as its own.
+                let mut text = output_text(&output.stdout);
+                let stderr = output_text(&output.stderr);
+                if args.first().is_some_and(|arg| arg == "logs") && !stderr.is_empty() {
+                    text.push('\n');
+                    text.push_str(&stderr);
+                }
+                Ok(text)
+            }
+            Some(output) => Err(format!(
+                "{} failed: {}",
+                self.cli,
+                output_text(&output.stderr)
+            )),
+            None => Err(format!("{} did not answer in time.", self.cli)),
+        })
+    }
+
+    /// The names of all containers, running or not.
+    fn names(&self) -> Result<Result<Vec<String>, String>> {
+        let args = argv(&["ps", "--all", "--format", "{{.Names}}"]);
+        Ok(self.run_cli(&args)?.map(|names| {
+            names
+                .lines()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect()
+        }))
+    }
+}
+
+impl Tool for ContainersTool {
+    fn name(&self) -> &str {
+        "containers"
+    }
+
+    fn description(&self) -> &str {
+        "Use `containers` to list the running Docker or Podman containers, start, stop or restart one, or read its latest logs."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["list", "start", "stop", "restart", "logs"],
+                },
+                "name": { "type": "string", "description": "The container, as the user said it." },
+                "lines": { "type": "integer", "description": "How many log lines to read." },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn confirmation(&self, arguments: &Value) -> Option<String> {
+        let action = arguments.get("action").and_then(Value::as_str)?;
+        let name = arguments.get("name").and_then(Value::as_str)?;
+        matches!(action, "stop" | "restart").then(|| format!("{action} the container {name}"))
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let action = arguments
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if action == "list" {
+            let args = argv(&["ps", "--format", "{{.Names}}\t{{.Status}}"]);
+            return Ok(match self.run_cli(&args)? {
+                Ok(output) => spoken_list(&output),
+                Err(problem) => problem,
+            });
+        }
+        if !matches!(action, "start" | "stop" | "restart" | "logs") {
+            return Ok(format!("Unknown action '{action}'."));
+        }
+        let Some(wanted) = arguments.get("name").and_then(Value::as_str) else {
+            return Ok("No container name provided.".to_string());
+        };
+        let names = match self.names()? {
+            Ok(names) => names,
+            Err(problem) => return Ok(problem),
+        };
+        let Some(name) = find(&names, wanted) else {
+            return Ok(format!("There is no container called {wanted}."));
+        };
+        let args = match action {
+            "logs" => {
+                let lines = arguments
+                    .get("lines")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(DEFAULT_LOG_LINES)
+                    .clamp(1, 200);
+                argv(&["logs", "--tail", &lines.to_string(), name])
+            }
+            action => argv(&[action, name]),
+        };
+        Ok(match self.run_cli(&args)? {
+            Ok(output) if action == "logs" => {
+                if output.trim().is_empty() {
+                    format!("{name} has logged nothing.")
+                } else {
+                    truncate_bytes(output.trim(), max_output_bytes())
+                }
+            }
+            Ok(_) => match action {
+                "start" => format!("Started {name}."),
+                "stop" => format!("Stopped {name}."),
+                _ => format!("Restarted {name}."),
+            },
+            Err(problem) => problem,
+        })
+    }
+}
+
+fn argv(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+/// The output of `ps --format "{{.Names}}\t{{.Status}}"` read out: "2
+/// containers are running: nextcloud, up 2 days; jellyfin, up 3 hours."
+pub fn spoken_list(output: &str) -> String {
+    let running: Vec<String> = output
+        .lines()
+        .filter_map(|line| {
+            let (name, status) = line.split_once('\t').unwrap_or((line, ""));
+            let name = name.trim();
+            let status = status.trim().to_lowercase();
+            (!name.is_empty()).then(|| {
+                if status.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{name}, {status}")
+                }
+            })
+        })
+        .collect();
+    match running.as_slice() {
+        [] => "No containers are running.".to_string(),
+        [one] => format!("1 container is running: {one}."),
+        many => format!(
+            "{} containers are running: {}.",
+            many.len(),
+            many.join("; ")
+        ),
+    }
+}
+
+/// The container among `names` that the user meant by `wanted`: the same
+/// name ignoring case, spaces, dashes and underscores, or failing that
+/// the only one containing it.
+pub fn find<'a>(names: &'a [String], wanted: &str) -> Option<&'a str> {
+    let simple = |text: &str| -> String {
+        text.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let wanted = wanted.trim().to_lowercase();
+    let wanted = simple(wanted.strip_prefix("the ").unwrap_or(&wanted));
+    if wanted.is_empty() {
+        return None;
+    }
+    if let Some(name) = names.iter().find(|name| simple(name) == wanted) {
+        return Some(name);
+    }
+    let containing: Vec<&String> = names
+        .iter()
+        .filter(|name| simple(name).contains(&wanted))
+        .collect();
+    match containing.as_slice() {
+        [name] => Some(name),
+        _ => None,
+    }
+}
+
+/// Whether `program` is on the `PATH`.
+fn installed(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
@@ -0,0 +1,64 @@
+//! Saying which tools would run instead of running them.
+//!
+//! A command starting with "dry run" ("dry run: clean up my downloads")
+//! is answered as usual up to the first tool call, and then Jarvis says
+//! which tools it would call and with which arguments, and stops. Nothing
+//! is run and nothing needs confirmation. `DRY_RUN=1` does this for every
+//! command, which is handy for trying a new prompt or model.
+
+use crate::llm::{CallOrder, ToolCall};
+
+/// The phrases that start a dry run.
+const PHRASES: &[&str] = &["dry run", "dry-run", "dryrun"];
+
+/// The command after a leading "dry run", if there is one.
+pub fn command(command: &str) -> Option<String> {
+    let command = command.trim();
+    let command = strip_phrase(command, "please ").unwrap_or(command);
+    let rest = PHRASES
+        .iter()
+        .find_map(|phrase| strip_phrase(command, phrase))?;
+    if !rest.is_empty() && !rest.starts_with([' ', ',', ':', '.']) {
+        return None;
+    }
+    let rest = rest.trim_start_matches([' ', ',', ':', '.']).trim();
+    (!rest.is_empty()).then(|| rest.to_string())
+}
+
+/// `text` after `phrase`, ignoring case.
+fn strip_phrase<'a>(text: &'a str, phrase: &str) -> Option<&'a str> {
+    text.get(..phrase.len())
+        .filter(|start| start.eq_ignore_ascii_case(phrase))
+        .map(|_| &text[phrase.len()..])
+}
+
+/// What would be done for `calls`, in words fit to speak. `check` tells
+/// why a call could not run, if it could not.
+pub fn describe(
+    calls: &[ToolCall],
+    order: CallOrder,
+    check: impl Fn(&ToolCall) -> Option<String>,
+) -> String {
+    let described: Vec<String> = calls
+        .iter()
+        .map(|call| {
+            let mut text = format!("{} with {}", call.name, call.arguments);
+            if let Some(problem) = check(call) {
+                text.push_str(&format!(", which would fail: {problem}"));
+            }
+            text
+        })
+        .collect();
+    match described.as_slice() {
+        [one] => format!("Dry run: I would call {one}."),
+        many => format!(
+            "Dry run: I would make {} calls, {}: {}.",
+            many.len(),
+            match order {
+                CallOrder::Parallel => "at the same time",
+                CallOrder::Sequential => "one after the other",
+            },
+            many.join("; ")
+        ),
+    }
+}
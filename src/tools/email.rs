@@ -0,0 +1,425 @@
+//! Reading and sending email.
+//!
+//! `email` answers "any new mail?" with the senders and subjects of the
+//! unread messages in the inbox, over IMAP, and sends short messages over
+//! SMTP: "tell Bob I'll be late". Sending always waits for "yes,
+//! confirm", after the recipient and the text have been read out.
+//! Reading leaves the messages unread. The accounts are in
+//! `~/.jarvis/email.toml` (or the file named by `EMAIL_FILE`):
+//!
+//! ```toml
+//! address = "me@example.com"
+//! name = "Ana"                      # shown as the sender
+//!
+//! [imap]
+//! host = "imap.example.com"         # port 993, TLS
+//! user = "me@example.com"           # default the address
+//!
+//! [smtp]
+//! host = "smtp.example.com"         # port 465 is TLS, others STARTTLS
+//! port = 587
+//!
+//! [contacts]
+//! bob = "bob@example.com"
+//! ```
+//!
+//! The password is read from `EMAIL_PASSWORD`, so it stays out of the file.
+//! Without a file there is no tool. Needs Jarvis built with
+//! `--features email`.
+
+use std::collections::BTreeMap;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::Tool;
+
+/// How long connecting to a mail server may take.
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A MIME encoded word in a header: `=?UTF-8?Q?Caf=C3=A9?=`.
+static ENCODED_WORD: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"=\?([^?\s]+)\?([bBqQ])\?([^?\s]*)\?=").unwrap());
+
+/// The accounts as written in `email.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmailFile {
+    address: String,
+    name: Option<String>,
+    imap: Option<Server>,
+    smtp: Option<Server>,
+    #[serde(default)]
+    contacts: BTreeMap<String, String>,
+    #[serde(default = "default_max_messages")]
+    max_messages: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Server {
+    host: String,
+    port: Option<u16>,
+    user: Option<String>,
+}
+
+fn default_max_messages() -> usize {
+    10
+}
+
+/// The sender and subject of a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub from: String,
+    pub subject: String,
+}
+
+/// Reads the inbox and sends mail.
+#[derive(Debug, Clone)]
+pub struct EmailTool {
+    account: EmailFile,
+}
+
+impl EmailTool {
+    /// The tool configured in `EMAIL_FILE`, default
+    /// `~/.jarvis/email.toml`; `None` without one.
+    pub fn from_env() -> Option<Self> {
+        let path = email_file()?;
+        if !path.exists() {
+            return None;
+        }
+        match Self::load_file(&path) {
+            Ok(tool) => Some(tool),
+            Err(e) => {
+                log::warn!("Ignoring email file {}: {e:#}", path.display());
+                None
+            }
+        }
+    }
+
+    /// The tool configured in `path`.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).context("cannot read it")?;
+        let account: EmailFile = toml::from_str(&data)?;
+        if account.imap.is_none() && account.smtp.is_none() {
+            bail!("it names neither an IMAP nor an SMTP server");
+        }
+        Ok(Self { account })
+    }
+
+    /// The address for `who`: a contact's name, ignoring case, or an
+    /// address.
+    pub fn recipient(&self, who: &str) -> Option<String> {
+        let who = who.trim();
+        if who.contains('@') {
+            return Some(who.to_string());
+        }
+        self.account
+            .contacts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(who))
+            .map(|(_, address)| address.clone())
+    }
+
+    /// The unread messages in the inbox, newest first.
+    fn unread(&self) -> Result<Vec<Summary>> {
+        let Some(server) = &self.account.imap else {
+            bail!("no IMAP server is set up");
+        };
+        let user = server.user.as_deref().unwrap_or(&self.account.address);
+        let port = server.port.unwrap_or(993);
+        let tls = native_tls::TlsConnector::new()?;
+        let address = std::net::ToSocketAddrs::to_socket_addrs(&(server.host.as_str(), port))?
+            .next()
+            .ok_or_else(|| anyhow!("cannot find {}", server.host))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)
+            .with_context(|| format!("cannot reach {}", server.host))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let stream = tls
+            .connect(&server.host, stream)
+            .map_err(|e| anyhow!("TLS with {} failed: {e}", server.host))?;
+        let mut client = imap::Client::new(stream);
+        client.read_greeting()?;
+        let mut session = client
+            .login(user, password()?)
+            .map_err(|(e, _)| anyhow!("cannot log in: {e}"))?;
+        // EXAMINE opens the inbox read-only, so nothing is marked as read.
+        session.examine("INBOX")?;
+        let mut unseen: Vec<u32> = session.search("UNSEEN")?.into_iter().collect();
+        unseen.sort_unstable_by(|a, b| b.cmp(a));
+        unseen.truncate(self.account.max_messages);
+        let mut found = Vec::new();
+        if !unseen.is_empty() {
+            let set: Vec<String> = unseen.iter().map(u32::to_string).collect();
+            let fetches = session.fetch(set.join(","), "ENVELOPE")?;
+            for fetch in fetches.iter() {
+                let Some(envelope) = fetch.envelope() else {
+                    continue;
+                };
+                let from = envelope
+                    .from
+                    .as_ref()
+                    .and_then(|from| from.first())
+                    .map(|from| {
+                        let text = |bytes: Option<&[u8]>| {
+                            bytes.map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+                        };
+                        text(from.name)
+                            .map(|name| decode_header(&name))
+                            .filter(|name| !name.trim().is_empty())
+                            .unwrap_or_else(|| {
+                                format!(
+                                    "{}@{}",
+                                    text(from.mailbox).unwrap_or_default(),
+                                    text(from.host).unwrap_or_default()
+                                )
+                            })
+                    })
+                    .unwrap_or_else(|| "someone".to_string());
+                let subject = envelope
+                    .subject
+                    .map(|subject| decode_header(&String::from_utf8_lossy(subject)))
+                    .unwrap_or_default();
+                found.push((fetch.message, Summary { from, subject }));
+            }
+        }
+        session.logout().ok();
+        found.sort_by(|a, b| b.0.cmp(&a.0));
+        Ok(found.into_iter().map(|(_, summary)| summary).collect())
+    }
+
+    /// Send `body` to `to` with `subject`.
+    fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        let Some(server) = &self.account.smtp else {
+            bail!("no SMTP server is set up");
+        };
+        let from = Mailbox::new(
+            self.account.name.clone(),
+            self.account
+                .address
+                .parse()
+                .context("the address in the email file is not valid")?,
+        );
+        let message = Message::builder()
+            .from(from)
+            .to(to
+                .parse()
+                .with_context(|| format!("{to} is not an address"))?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.to_string())?;
+        let port = server.port.unwrap_or(465);
+        let relay = if port == 465 {
+            SmtpTransport::relay(&server.host)?
+        } else {
+            SmtpTransport::starttls_relay(&server.host)?
+        };
+        let user = server
+            .user
+            .clone()
+            .unwrap_or_else(|| self.account.address.clone());
+        relay
+            .port(port)
+            .timeout(Some(TIMEOUT))
+            .credentials(Credentials::new(user, password()?))
+            .build()
+            .send(&message)?;
+        Ok(())
+    }
+}
+
+impl Tool for EmailTool {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn description(&self) -> &str {
+        "Use `email` to check for unread email, or to send a short email to one of the user's contacts."
+    }
+
+    fn parameters(&self) -> Value {
+        let contacts: Vec<&str> = self.account.contacts.keys().map(String::as_str).collect();
+        let who = if contacts.is_empty() {
+            "An email address.".to_string()
+        } else {
+            format!("One of {} or an email address.", contacts.join(", "))
+        };
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["unread", "send"] },
+                "to": { "type": "string", "description": who },
+                "subject": { "type": "string" },
+                "body": { "type": "string", "description": "The message, in the user's words." },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn confirmation(&self, arguments: &Value) -> Option<String> {
+        if arguments.get("action").and_then(Value::as_str) != Some("send") {
+            return None;
+        }
+        let to = arguments.get("to").and_then(Value::as_str)?;
+        let address = self.recipient(to)?;
+        let body = arguments
+            .get("body")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        Some(format!(
+            "send an email to {to}, {address}, saying: {}",
+            body.trim()
+        ))
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let argument = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        match argument("action").unwrap_or_default() {
+            "unread" => Ok(match self.unread() {
+                Ok(messages) => spoken_unread(&messages),
+                Err(e) => format!("I couldn't check your email: {e:#}."),
+            }),
+            "send" => {
+                let (Some(to), Some(body)) = (argument("to"), argument("body")) else {
+                    return Ok("No recipient or message provided.".to_string());
+                };
+                let Some(address) = self.recipient(to) else {
+                    return Ok(format!("I don't have an email address for {to}."));
+                };
+                let subject = argument("subject")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| default_subject(body));
+                Ok(match self.send(&address, &subject, body) {
+                    Ok(()) => format!("Sent to {to}."),
+                    Err(e) => format!("I couldn't send it: {e:#}."),
+                })
+            }
+            other => Ok(format!("Unknown action '{other}'.")),
+        }
+    }
+}
+
+/// The unread messages read out: "2 unread emails. From Alice: Lunch?
+/// From GitHub: New issue."
+pub fn spoken_unread(messages: &[Summary]) -> String {
+    if messages.is_empty() {
+        return "No new email.".to_string();
+    }
+    let mut sentences = vec![if messages.len() == 1 {
+        "1 unread email.".to_string()
+    } else {
+        format!("{} unread emails.", messages.len())
+    }];
+    for message in messages {
+        let subject = message.subject.trim();
+        sentences.push(if subject.is_empty() {
+            format!("From {}, with no subject.", message.from)
+        } else if subject.ends_with(['.', '?', '!']) {
+            format!("From {}: {subject}", message.from)
+        } else {
+            format!("From {}: {subject}.", message.from)
+        });
+    }
+    sentences.join(" ")
+}
+
+/// A subject for a message sent without one: its first few words.
+fn default_subject(body: &str) -> String {
+    let words: Vec<&str> = body.split_whitespace().collect();
+    let mut subject = words.iter().take(8).copied().collect::<Vec<_>>().join(" ");
+    if words.len() > 8 {
+        subject.push('…');
+    }
+    subject
+}
+
+/// `text`, a header, with its MIME encoded words (`=?UTF-8?B?...?=`)
+/// decoded. Encoded words that cannot be decoded are left as they are.
+pub fn decode_header(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut last = 0;
+    let mut after_word = false;
+    for caps in ENCODED_WORD.captures_iter(text) {
+        let whole = caps.get(0).unwrap();
+        let gap = &text[last..whole.start()];
+        // Space between two encoded words is not part of the text.
+        if !(after_word && gap.trim().is_empty()) {
+            decoded.push_str(gap);
+        }
+        match decode_word(&caps[1], &caps[2], &caps[3]) {
+            Some(word) => {
+                decoded.push_str(&word);
+                after_word = true;
+            }
+            None => {
+                decoded.push_str(whole.as_str());
+                after_word = false;
+            }
+        }
+        last = whole.end();
+    }
+    decoded.push_str(&text[last..]);
+    decoded
+}
+
+fn decode_word(charset: &str, encoding: &str, text: &str) -> Option<String> {
+    let bytes = if encoding.eq_ignore_ascii_case("b") {
+        base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .ok()?
+    } else {
+        let mut bytes = Vec::new();
+        let mut rest = text.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            match byte {
+                b'_' => bytes.push(b' '),
+                b'=' if tail.len() >= 2 => {
+                    let hex = std::str::from_utf8(&tail[..2]).ok()?;
+                    bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                    rest = &tail[2..];
+                    continue;
+                }
+                byte => bytes.push(byte),
+            }
+            rest = tail;
+        }
+        bytes
+    };
+    let charset = charset.to_lowercase();
+    Some(
+        if charset.starts_with("iso-8859-1") || charset == "latin1" {
+            bytes.iter().map(|&byte| byte as char).collect()
+        } else {
+            String::from_utf8_lossy(&bytes).into_owned()
+        },
+    )
+}
+
+/// `EMAIL_PASSWORD`.
+fn password() -> Result<String> {
+    std::env::var("EMAIL_PASSWORD").map_err(|_| anyhow!("EMAIL_PASSWORD is not set"))
+}
+
+/// `EMAIL_FILE`, default `~/.jarvis/email.toml`.
+fn email_file() -> Option<PathBuf> {
+    std::env::var("EMAIL_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("email.toml")))
+}
@@ -0,0 +1,317 @@
+//! Reading and writing the user's files without the shell.
+//!
+//! `file_task` reads, summarises, searches, appends to and creates text
+//! files, so "read me my TODO file" or "summarise ~/notes/meeting.md" does
+//! not need `cat` in `shell_task`. It only touches files under the folders
+//! in `FILE_TOOL_PATHS` (comma separated, `~` for the home directory;
+//! default the home directory), after following `..` and symbolic links,
+//! and never overwrites a file. Relative paths start from the working
+//! directory of `shell_task`. `FILE_TOOL=0` leaves the tool out.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+
+use super::{expand_home, max_output_bytes, resolve_path, truncate_bytes, working_directory, Tool};
+use crate::config::env_list;
+use crate::documents;
+
+/// Files larger than this are not read.
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Matching lines a search reports.
+const MAX_MATCHES: usize = 20;
+
+/// Headings and to-dos named in a summary.
+const SUMMARY_ITEMS: usize = 5;
+
+/// Lines of text quoted at the start of a summary.
+const OPENING_LINES: usize = 3;
+
+/// Reads and writes text files inside the allowed folders.
+#[derive(Debug, Clone)]
+pub struct FileTool {
+    roots: Vec<PathBuf>,
+}
+
+impl FileTool {
+    /// A tool for the files under `roots`.
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self { roots }
+    }
+
+    /// The folders in `FILE_TOOL_PATHS`, default the home directory.
+    pub fn from_env() -> Self {
+        Self::new(
+            env_list("FILE_TOOL_PATHS", &["~"])
+                .iter()
+                .map(|path| expand_home(path))
+                .collect(),
+        )
+    }
+
+    /// `path` as an absolute path with links and `..` resolved, if it is
+    /// inside one of the allowed folders. The file itself need not exist
+    /// yet, its folder does.
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let mut full = expand_home(path.trim());
+        if full.is_relative() {
+            let base = working_directory()
+                .or_else(|| std::env::current_dir().ok())
+                .unwrap_or_default();
+            full = base.join(full);
+        }
+        let Some(resolved) = resolve_path(&full) else {
+            return Err(if full.file_name().is_none() {
+                format!("{path} is not a file.")
+            } else {
+                format!("There is no folder for {path}.")
+            });
+        };
+        if self.allows(&resolved) {
+            Ok(resolved)
+        } else {
+            Err(format!("{path} is outside the folders I may use."))
+        }
+    }
+
+    /// Whether the resolved path `path` is inside one of the allowed
+    /// folders.
+    fn allows(&self, path: &Path) -> bool {
+        self.roots
+            .iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .any(|root| path.starts_with(root))
+    }
+
+    /// The lines mentioning `query`, ignoring case, in the file at `path`
+    /// or the text files in the folder at `path`. Files linked from
+    /// outside the allowed folders are skipped.
+    fn search(&self, path: &Path, name: &str, query: &str) -> String {
+        let files: Vec<PathBuf> = if path.is_dir() {
+            documents::text_files(path)
+                .into_iter()
+                .filter(|file| file.canonicalize().is_ok_and(|file| self.allows(&file)))
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        let query = query.to_lowercase();
+        let mut matches = Vec::new();
+        let mut more = 0;
+        for file in &files {
+            let Ok(text) = read(file, name) else {
+                continue;
+            };
+            let shown = file.strip_prefix(path).unwrap_or(file);
+            let shown = if shown.as_os_str().is_empty() {
+                name.to_string()
+            } else {
+                shown.display().to_string()
+            };
+            for (number, line) in text.lines().enumerate() {
+                if !line.to_lowercase().contains(&query) {
+                    continue;
+                }
+                if matches.len() < MAX_MATCHES {
+                    matches.push(format!("{shown}:{}: {}", number + 1, line.trim()));
+                } else {
+                    more += 1;
+                }
+            }
+        }
+        if matches.is_empty() {
+            return format!("Nothing in {name} mentions '{query}'.");
+        }
+        if more > 0 {
+            matches.push(format!("… and {more} more matching lines."));
+        }
+        matches.join("\n")
+    }
+}
+
+impl Tool for FileTool {
+    fn name(&self) -> &str {
+        "file_task"
+    }
+
+    fn description(&self) -> &str {
+        "Use `file_task` to read, summarise, search, append to or create the user's text files, such as notes and to-do lists."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["read", "summarise", "search", "append", "create"],
+                    "description": "What to do with the file.",
+                },
+                "path": {
+                    "type": "string",
+                    "description": "The file, such as ~/notes/todo.md; for search also a folder.",
+                },
+                "text": {
+                    "type": "string",
+                    "description": "The text to append or to create the file with.",
+                },
+                "query": {
+                    "type": "string",
+                    "description": "The words to search for.",
+                },
+            },
+            "required": ["action", "path"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let argument = |name: &str| arguments.get(name).and_then(Value::as_str);
+        let (Some(action), Some(path)) = (argument("action"), argument("path")) else {
+            return Ok("No action or path provided.".to_string());
+        };
+        let full = match self.resolve(path) {
+            Ok(full) => full,
+            Err(problem) => return Ok(problem),
+        };
+        let name = full.file_name().map_or_else(
+            || path.to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        match action {
+            // Problems with the file go back to the model, which may try
+            // another path.
+            "read" => Ok(match read(&full, &name) {
+                Ok(text) if text.trim().is_empty() => format!("{name} is empty."),
+                Ok(text) => truncate_bytes(&text, max_output_bytes()),
+                Err(e) => format!("{e:#}."),
+            }),
+            "summarise" | "summarize" => Ok(match read(&full, &name) {
+                Ok(text) => summary(&name, &text),
+                Err(e) => format!("{e:#}."),
+            }),
+            "search" => match argument("query").map(str::trim) {
+                Some(query) if !query.is_empty() => Ok(self.search(&full, &name, query)),
+                _ => Ok("No query provided.".to_string()),
+            },
+            "append" | "create" => {
+                let Some(text) = argument("text") else {
+                    return Ok("No text provided.".to_string());
+                };
+                if action == "create" && full.exists() {
+                    return Ok(format!("{name} already exists; append to it instead."));
+                }
+                append(&full, text)?;
+                Ok(if action == "create" {
+                    format!("Created {name}.")
+                } else {
+                    format!("Added to {name}.")
+                })
+            }
+            other => Ok(format!("Unknown action '{other}'.")),
+        }
+    }
+}
+
+/// The text of the file at `path`, called `name` in errors: "cannot open
+/// todo.md: No such file or directory".
+fn read(path: &Path, name: &str) -> Result<String> {
+    let meta = fs::metadata(path).with_context(|| format!("cannot open {name}"))?;
+    if meta.is_dir() {
+        bail!("{name} is a folder, not a file");
+    }
+    if meta.len() > MAX_FILE_BYTES {
+        bail!("{name} is too large to read");
+    }
+    let bytes = fs::read(path).with_context(|| format!("cannot read {name}"))?;
+    String::from_utf8(bytes).map_err(|_| anyhow!("{name} is not a text file"))
+}
+
+/// Add `text` as a line at the end of the file at `path`, creating it if
+/// needed.
+fn append(path: &Path, text: &str) -> Result<()> {
+    let starts_line = match fs::read(path) {
+        Ok(old) => old.is_empty() || old.ends_with(b"\n"),
+        Err(_) => true,
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("cannot write to {}", path.display()))?;
+    if !starts_line {
+        file.write_all(b"\n")?;
+    }
+    file.write_all(text.trim_end_matches('\n').as_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(())
+}
+
+/// A few sentences about `text`, the contents of `name`, to be read out:
+/// its size, headings, open to-dos and how it begins.
+pub fn summary(name: &str, text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let words = text.split_whitespace().count();
+    if words == 0 {
+        return format!("{name} is empty.");
+    }
+    let mut sentences = vec![format!(
+        "{name} has {} lines and {words} words.",
+        lines.len()
+    )];
+    let headings: Vec<&str> = lines
+        .iter()
+        .filter_map(|line| line.strip_prefix('#'))
+        .map(|heading| heading.trim_start_matches('#').trim())
+        .filter(|heading| !heading.is_empty())
+        .collect();
+    if !headings.is_empty() {
+        sentences.push(format!("Headings: {}.", listed(&headings)));
+    }
+    let todos: Vec<&str> = lines
+        .iter()
+        .filter_map(|line| {
+            line.strip_prefix("- [ ]")
+                .or_else(|| line.strip_prefix("* [ ]"))
+                .or_else(|| line.strip_prefix("TODO"))
+        })
+        .map(|todo| todo.trim_start_matches(':').trim())
+        .filter(|todo| !todo.is_empty())
+        .collect();
+    if !todos.is_empty() {
+        sentences.push(format!(
+            "{} open to-do{}: {}.",
+            todos.len(),
+            if todos.len() == 1 { "" } else { "s" },
+            listed(&todos)
+        ));
+    }
+    let opening: Vec<&str> = lines
+        .iter()
+        .copied()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .take(OPENING_LINES)
+        .collect();
+    if !opening.is_empty() {
+        sentences.push(format!("It begins: {}", opening.join(" ")));
+    }
+    sentences.join(" ")
+}
+
+/// The first few of `items`, comma separated, saying how many more there
+/// are.
+fn listed(items: &[&str]) -> String {
+    let mut text = items
+        .iter()
+        .take(SUMMARY_ITEMS)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if items.len() > SUMMARY_ITEMS {
+        text.push_str(&format!(" and {} more", items.len() - SUMMARY_ITEMS));
+    }
+    text
+}
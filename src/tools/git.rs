@@ -0,0 +1,312 @@
+//! Git without the free-form shell.
+//!
+//! `git_task` answers "what's the status of the repo?", "which branch am
+//! I on?", "what was the last commit?" and "how big is the diff?" in
+//! sentences, and commits and pushes after "yes, confirm", for the coding
+//! workflow. It works in the persistent working directory of the shell
+//! tools ("cd ~/projects/blog" first), runs `git` with fixed arguments,
+//! and never force-pushes. `GIT_TOOL=0` leaves the tool out.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{output_text, output_with_timeout, working_directory, Tool};
+
+/// How long a git command may take; pushing goes over the network.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Answers questions about the repository and commits and pushes.
+#[derive(Debug, Clone, Default)]
+pub struct GitTool {
+    /// The repository; the persistent working directory if `None`.
+    dir: Option<PathBuf>,
+}
+
+impl GitTool {
+    /// A tool for the repository at `dir`, or the working directory.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.dir
+            .clone()
+            .or_else(working_directory)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default()
+    }
+
+    /// Run `git` with `args`: its output, or the problem in words.
+    fn git(&self, args: &[&str]) -> Result<Result<String, String>> {
+        let mut cmd = Command::new("git");
+        cmd.arg("-C").arg(self.dir()).args(args);
+        // Never wait for a password prompt nobody can answer.
+        cmd.env("GIT_TERMINAL_PROMPT", "0");
+        Ok(match output_with_timeout(cmd, TIMEOUT, &mut |_| {})? {
+            Some(output) if output.status.success() => Ok(output_text(&output.stdout)),
+            Some(output) => {
+                // `git commit` says why it did nothing on stdout.
+                let mut detail = output_text(&output.stderr);
+                if detail.is_empty() {
+                    detail = output_text(&output.stdout);
+                }
+                Err(if detail.contains("not a git repository") {
+                    format!("{} is not a git repository.", self.dir().display())
+                } else {
+                    format!("git {} failed: {detail}", args[0])
+                })
+            }
+            None => Err(format!("git {} did not finish in time.", args[0])),
+        })
+    }
+}
+
+impl Tool for GitTool {
+    fn name(&self) -> &str {
+        "git_task"
+    }
+
+    fn description(&self) -> &str {
+        "Use `git_task` for the status, current branch, last commit and diff size of the git repository in the working directory, and to commit or push."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["status", "branch", "last_commit", "diff_stat", "commit", "push"],
+                },
+                "message": { "type": "string", "description": "The commit message." },
+                "all": {
+                    "type": "boolean",
+                    "description": "Also commit changes that are not staged; new files still need staging.",
+                },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn confirmation(&self, arguments: &Value) -> Option<String> {
+        let repository = self
+            .dir()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "the repository".to_string());
+        match arguments.get("action").and_then(Value::as_str)? {
+            "commit" => {
+                let message = arguments.get("message").and_then(Value::as_str)?;
+                let which = if all(arguments) {
+                    "all changes"
+                } else {
+                    "the staged changes"
+                };
+                Some(format!(
+                    "commit {which} in {repository} with the message: {}",
+                    message.trim()
+                ))
+            }
+            "push" => Some(format!("push {repository}")),
+            _ => None,
+        }
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let action = arguments
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let answer = match action {
+            "status" => self
+                .git(&["status", "--porcelain=v1", "--branch"])?
+                .map(|output| spoken_status(&output)),
+            "branch" => {
+                self.git(&["branch", "--show-current"])?
+                    .map(|branch| match branch.trim() {
+                        "" => "You're not on a branch; HEAD is detached.".to_string(),
+                        branch => format!("You're on {branch}."),
+                    })
+            }
+            "last_commit" => self
+                .git(&["log", "-1", "--format=%h%x09%an%x09%ar%x09%s"])?
+                .map(|line| spoken_commit(&line)),
+            "diff_stat" => {
+                let unstaged = self.git(&["diff", "--shortstat"])?;
+                let staged = self.git(&["diff", "--cached", "--shortstat"])?;
+                unstaged.and_then(|unstaged| {
+                    staged.map(|staged| {
+                        match (spoken_shortstat(&unstaged), spoken_shortstat(&staged)) {
+                            (None, None) => "There are no changes.".to_string(),
+                            (Some(unstaged), None) => format!("Unstaged: {unstaged}."),
+                            (None, Some(staged)) => format!("Staged: {staged}."),
+                            (Some(unstaged), Some(staged)) => {
+                                format!("Staged: {staged}. Unstaged: {unstaged}.")
+                            }
+                        }
+                    })
+                })
+            }
+            "commit" => {
+                let message = arguments
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .map(str::trim)
+                    .unwrap_or_default();
+                if message.is_empty() {
+                    return Ok("No commit message provided.".to_string());
+                }
+                let mut args = vec!["commit", "-m", message];
+                if all(arguments) {
+                    args.insert(1, "--all");
+                }
+                match self.git(&args)? {
+                    Ok(output) => Ok(output.lines().next().map_or_else(
+                        || "Committed.".to_string(),
+                        |line| format!("Committed: {line}"),
+                    )),
+                    Err(problem) if problem.contains("nothing to commit") => {
+                        Ok("There was nothing to commit.".to_string())
+                    }
+                    Err(problem) => Err(problem),
+                }
+            }
+            "push" => self.git(&["push"])?.map(|_| "Pushed.".to_string()),
+            other => Ok(format!("Unknown action '{other}'.")),
+        };
+        Ok(answer.unwrap_or_else(|problem| problem))
+    }
+}
+
+/// The `all` argument.
+fn all(arguments: &Value) -> bool {
+    arguments
+        .get("all")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// `git status --porcelain=v1 --branch` read out: "On main, 2 commits
+/// ahead of origin/main. 1 staged, 2 modified and 1 untracked files."
+pub fn spoken_status(output: &str) -> String {
+    let mut sentences = Vec::new();
+    let (mut staged, mut modified, mut untracked, mut conflicts) = (0, 0, 0, 0);
+    for line in output.lines() {
+        if let Some(branch) = line.strip_prefix("## ") {
+            sentences.push(spoken_branch(branch));
+            continue;
+        }
+        let mut codes = line.chars();
+        let (Some(x), Some(y)) = (codes.next(), codes.next()) else {
+            continue;
+        };
+        match (x, y) {
+            ('?', '?') => untracked += 1,
+            ('U', _) | (_, 'U') | ('A', 'A') | ('D', 'D') => conflicts += 1,
+            (x, y) => {
+                if x != ' ' {
+                    staged += 1;
+                }
+                if y != ' ' {
+                    modified += 1;
+                }
+            }
+        }
+    }
+    match conflicts {
+        0 => {}
+        1 => sentences.push("1 file has conflicts.".to_string()),
+        many => sentences.push(format!("{many} files have conflicts.")),
+    }
+    let mut counts = Vec::new();
+    for (count, what) in [
+        (staged, "staged"),
+        (modified, "modified"),
+        (untracked, "untracked"),
+    ] {
+        if count > 0 {
+            counts.push(format!("{count} {what}"));
+        }
+    }
+    let total = staged + modified + untracked;
+    match counts.split_last() {
+        None if conflicts > 0 => {}
+        None => sentences.push("Nothing to commit.".to_string()),
+        Some((last, [])) => sentences.push(format!("{last} {}.", files(total))),
+        Some((last, rest)) => {
+            sentences.push(format!("{} and {last} {}.", rest.join(", "), files(total)))
+        }
+    }
+    sentences.join(" ")
+}
+
+fn files(count: usize) -> &'static str {
+    if count == 1 {
+        "file"
+    } else {
+        "files"
+    }
+}
+
+/// The branch line of `git status --branch`, without `## `, read out.
+fn spoken_branch(line: &str) -> String {
+    if let Some(branch) = line.strip_prefix("No commits yet on ") {
+        return format!("On {branch}, with no commits yet.");
+    }
+    if line.starts_with("HEAD (no branch)") {
+        return "HEAD is detached.".to_string();
+    }
+    let (names, tracking) = match line.split_once(" [") {
+        Some((names, tracking)) => (names, tracking.trim_end_matches(']')),
+        None => (line, ""),
+    };
+    let (branch, upstream) = match names.split_once("...") {
+        Some((branch, upstream)) => (branch, Some(upstream)),
+        None => (names, None),
+    };
+    let Some(upstream) = upstream else {
+        return format!("On {branch}.");
+    };
+    if tracking == "gone" {
+        return format!("On {branch}, whose upstream {upstream} is gone.");
+    }
+    let mut parts = Vec::new();
+    for part in tracking.split(", ").filter(|part| !part.is_empty()) {
+        if let Some((direction, count)) = part.split_once(' ') {
+            let commits = if count == "1" { "commit" } else { "commits" };
+            parts.push(format!("{count} {commits} {direction}"));
+        }
+    }
+    match parts.last() {
+        None => format!("On {branch}, up to date with {upstream}."),
+        // "2 commits ahead of origin/main", "1 commit behind origin/main".
+        Some(last) if last.ends_with("ahead") => {
+            format!("On {branch}, {} of {upstream}.", parts.join(" and "))
+        }
+        Some(_) => format!("On {branch}, {} {upstream}.", parts.join(" and ")),
+    }
+}
+
+/// `git log -1 --format=%h%x09%an%x09%ar%x09%s` read out: "The last
+/// commit, abc1234 by Ana 2 hours ago: Fix the parser."
+pub fn spoken_commit(line: &str) -> String {
+    let fields: Vec<&str> = line.trim().splitn(4, '\t').collect();
+    match fields.as_slice() {
+        [hash, author, when, subject] => {
+            let subject = subject.trim().trim_end_matches('.');
+            format!("The last commit, {hash} by {author} {when}: {subject}.")
+        }
+        _ => "There are no commits yet.".to_string(),
+    }
+}
+
+/// `git diff --shortstat` without the `(+)` and `(-)`: "3 files
+/// changed, 10 insertions, 2 deletions"; `None` for no changes.
+pub fn spoken_shortstat(output: &str) -> Option<String> {
+    let text = output.trim().replace("(+)", "").replace("(-)", "");
+    (!text.is_empty()).then_some(text)
+}
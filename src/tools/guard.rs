@@ -0,0 +1,169 @@
+//! Spotting tool calls that need the user's go-ahead.
+//!
+//! Before a tool runs, its string arguments are matched against a list of
+//! dangerous patterns: `rm -rf`, `dd`, `mkfs`, `shutdown` and the like,
+//! and commands that write to absolute paths outside the home directory
+//! (`/tmp` excepted), after following `..` and symbolic links. A match is
+//! spoken back to the user, and the call only runs after "yes, confirm".
+//!
+//! `CONFIRM_PATTERNS` replaces the built-in patterns with a comma
+//! separated list of regular expressions; `CONFIRM=0` turns the check off.
+
+use std::path::{Component, Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+use super::resolve_path;
+use crate::config::{env_flag_or, env_list};
+use crate::llm::ToolCall;
+
+/// Commands that need confirmation.
+const DEFAULT_PATTERNS: &[&str] = &[
+    r"\brm\s+(?:-\S+\s+)*-[a-zA-Z]*[rRf]",
+    r"\brm\s+(?:-\S+\s+)*--(?:recursive|force)\b",
+    r"\bdd\s",
+    r"\bmkfs(?:\.\w+)?\b",
+    r"\b(?:shutdown|poweroff|reboot|halt)\b",
+    r"\b(?:wipefs|shred|fdisk|parted)\b",
+    r"\b(?:chmod|chown)\s+(?:-\S+\s+)*-[a-zA-Z]*R",
+    r"\bsudo\b",
+];
+
+/// Absolute paths a shell command writes to: redirections, `tee`, and the
+/// target of `cp`, `mv`, `touch`, `mkdir` and `rm`.
+static WRITTEN_PATH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?:>>?\s*|\btee\s+(?:-\S+\s+)*|\b(?:cp|mv|install|ln|touch|mkdir|rm|rmdir)\b[^;|&]*\s)["']?(/[^\s;|&"']*)"#,
+    )
+    .unwrap()
+});
+
+/// Places outside the home directory that may be written freely.
+const SAFE_PREFIXES: &[&str] = &["/tmp/", "/dev/null", "/dev/stdout", "/dev/stderr"];
+
+/// Decides which tool calls need confirmation.
+#[derive(Debug, Clone, Default)]
+pub struct Guard {
+    patterns: Vec<Regex>,
+    home: Option<PathBuf>,
+    enabled: bool,
+}
+
+impl Guard {
+    /// The guard configured by `CONFIRM` and `CONFIRM_PATTERNS`. Invalid
+    /// patterns are logged and skipped.
+    pub fn from_env() -> Self {
+        if !env_flag_or("CONFIRM", true) {
+            return Self::default();
+        }
+        let patterns = env_list("CONFIRM_PATTERNS", DEFAULT_PATTERNS)
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    log::warn!("Ignoring confirmation pattern '{pattern}': {e}");
+                    None
+                }
+            })
+            .collect();
+        Self {
+            patterns,
+            home: dirs::home_dir(),
+            enabled: true,
+        }
+    }
+
+    /// Why `call` needs confirmation: the command to read out to the
+    /// user. `None` if it may run straight away.
+    pub fn check(&self, call: &ToolCall) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        let mut texts = Vec::new();
+        strings(&call.arguments, &mut texts);
+        texts
+            .into_iter()
+            .find(|text| {
+                self.patterns.iter().any(|p| p.is_match(text)) || self.writes_outside_home(text)
+            })
+            .map(str::to_string)
+    }
+
+    /// Whether `command` writes to a path that, once links and `..` are
+    /// resolved, is neither in the home directory nor a safe place.
+    fn writes_outside_home(&self, command: &str) -> bool {
+        let home = self.home.as_deref().and_then(resolved);
+        let safe = |path: &Path| SAFE_PREFIXES.iter().any(|safe| path.starts_with(safe));
+        WRITTEN_PATH.captures_iter(command).any(|captures| {
+            let path = Path::new(&captures[1]);
+            let climbs = path.components().any(|part| part == Component::ParentDir);
+            if !climbs && safe(path) {
+                return false;
+            }
+            let Some(path) = resolved(path) else {
+                return true;
+            };
+            !home.as_ref().is_some_and(|home| path.starts_with(home)) && !safe(&path)
+        })
+    }
+}
+
+/// `path` with links and `..` resolved in the part of it that exists, as
+/// [`resolve_path`] does, and the folders a command may create after it.
+/// `None` if `..` climbs out of a folder that does not exist.
+fn resolved(path: &Path) -> Option<PathBuf> {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    let mut resolved = loop {
+        if let Some(found) = resolve_path(existing) {
+            break found;
+        }
+        missing.push(existing.file_name()?);
+        existing = existing.parent()?;
+    };
+    for name in missing.into_iter().rev() {
+        resolved.push(name);
+    }
+    Some(resolved)
+}
+
+/// How `call` is read out when asking for confirmation: its first string
+/// argument (the command, for the shell tools), else the tool's name.
+pub fn describe(call: &ToolCall) -> String {
+    let mut texts = Vec::new();
+    strings(&call.arguments, &mut texts);
+    texts
+        .first()
+        .map_or_else(|| call.name.clone(), |text| text.to_string())
+}
+
+/// Whether `answer` confirms a dangerous action: "yes, confirm" or
+/// "confirm".
+pub fn is_confirmation(answer: &str) -> bool {
+    let answer: String = answer
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect();
+    let words: Vec<&str> = answer.split_whitespace().collect();
+    matches!(
+        words.as_slice(),
+        ["confirm"]
+            | ["yes", "confirm"]
+            | ["confirmed"]
+            | ["yes", "confirmed"]
+            | ["yes", "i", "confirm"]
+    )
+}
+
+/// The string values in `value`, however deeply nested.
+fn strings<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+    match value {
+        Value::String(text) => out.push(text),
+        Value::Array(items) => items.iter().for_each(|item| strings(item, out)),
+        Value::Object(fields) => fields.values().for_each(|field| strings(field, out)),
+        _ => {}
+    }
+}
@@ -0,0 +1,258 @@
+//! HTTP requests to the user's own services.
+//!
+//! `http_request` lets the model GET or POST JSON to the hosts listed in
+//! `~/.jarvis/http.toml` (or the file named by `HTTP_TOOL_FILE`), such as
+//! Home Assistant or an internal wiki, without a tool written in Rust for
+//! each service:
+//!
+//! ```toml
+//! max_response_bytes = 65536        # longer responses are cut
+//! timeout_secs = 10
+//!
+//! [hosts."homeassistant.local:8123"]
+//! headers = { Authorization = "Bearer {env:HA_TOKEN}" }
+//!
+//! [hosts."wiki.example.com"]
+//! ```
+//!
+//! A host is allowed with any port unless its entry names one. `{env:NAME}`
+//! in a header value is replaced by the environment variable `NAME`, so
+//! tokens stay out of the file and out of the model's sight. Redirects
+//! are not followed, since they could lead to a host not on the list.
+//! Without hosts there is no tool. Needs Jarvis built with
+//! `--features http`.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::blocking::Client;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{off_runtime, truncate_bytes, Tool};
+
+/// `{env:NAME}` in a header value.
+static ENV_TEMPLATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{env:(\w+)\}").unwrap());
+
+/// The tool as written in `http.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HttpFile {
+    #[serde(default = "default_max_response_bytes")]
+    max_response_bytes: usize,
+    #[serde(default = "default_timeout_secs")]
+    timeout_secs: u64,
+    #[serde(default)]
+    hosts: BTreeMap<String, HostEntry>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostEntry {
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+fn default_max_response_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// Sends requests to the allowed hosts.
+#[derive(Debug, Clone)]
+pub struct HttpTool {
+    /// Allowed hosts, as `name` or `name:port`.
+    hosts: BTreeMap<String, HostEntry>,
+    max_response_bytes: usize,
+    timeout: Duration,
+}
+
+impl HttpTool {
+    /// The tool configured in `HTTP_TOOL_FILE`, default
+    /// `~/.jarvis/http.toml`; `None` without allowed hosts.
+    pub fn from_env() -> Option<Self> {
+        let path = http_file()?;
+        match Self::load_file(&path) {
+            Ok(tool) if !tool.hosts.is_empty() => {
+                log::info!(
+                    "HTTP requests allowed to {} hosts from {}",
+                    tool.hosts.len(),
+                    path.display()
+                );
+                Some(tool)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                log::warn!("Ignoring HTTP tool file {}: {e:#}", path.display());
+                None
+            }
+        }
+    }
+
+    /// The tool configured in `path`; one without hosts if it does not
+    /// exist.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).context("cannot read it"),
+        };
+        let file: HttpFile = toml::from_str(&data)?;
+        Ok(Self {
+            hosts: file
+                .hosts
+                .into_iter()
+                .map(|(host, entry)| (host.to_lowercase(), entry))
+                .collect(),
+            max_response_bytes: file.max_response_bytes,
+            timeout: Duration::from_secs(file.timeout_secs),
+        })
+    }
+
+    /// The headers to send with a request to `url`, with their templates
+    /// filled in; an error saying why if its host is not allowed.
+    pub fn headers(&self, url: &Url) -> Result<Vec<(String, String)>> {
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("only http and https URLs are allowed");
+        }
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+        let with_port = url
+            .port_or_known_default()
+            .map(|port| format!("{host}:{port}"));
+        let Some(entry) = with_port
+            .and_then(|key| self.hosts.get(&key))
+            .or_else(|| self.hosts.get(&host))
+        else {
+            bail!("{host} is not one of the allowed hosts");
+        };
+        entry
+            .headers
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), fill_template(value)?)))
+            .collect()
+    }
+
+    /// Send the request and describe the response.
+    fn send(&self, method: &str, url: Url, body: Option<&Value>) -> Result<String> {
+        let headers = self.headers(&url)?;
+        let client = Client::builder()
+            .timeout(self.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("failed to create HTTP client")?;
+        let mut request = match method {
+            "POST" => client.post(url),
+            _ => client.get(url),
+        };
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send()?;
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        // One byte more than the limit tells whether there was more.
+        let limit = self.max_response_bytes;
+        let mut bytes = Vec::new();
+        response
+            .take(limit.saturating_add(1) as u64)
+            .read_to_end(&mut bytes)?;
+        let text = truncate_bytes(&String::from_utf8_lossy(&bytes), limit);
+        Ok(if status.is_success() {
+            if text.trim().is_empty() {
+                format!("The request succeeded with status {status} and no content.")
+            } else {
+                text
+            }
+        } else if status.is_redirection() {
+            format!(
+                "The server answered {status}, a redirect to '{location}', which is not followed."
+            )
+        } else {
+            format!("The server answered {status}: {text}")
+        })
+    }
+}
+
+impl Tool for HttpTool {
+    fn name(&self) -> &str {
+        "http_request"
+    }
+
+    fn description(&self) -> &str {
+        "Use `http_request` to GET or POST JSON to the user's own web services, such as Home Assistant."
+    }
+
+    fn parameters(&self) -> Value {
+        let hosts: Vec<&str> = self.hosts.keys().map(String::as_str).collect();
+        json!({
+            "type": "object",
+            "properties": {
+                "method": { "type": "string", "enum": ["GET", "POST"] },
+                "url": {
+                    "type": "string",
+                    "description": format!("The full URL, on one of these hosts: {}.", hosts.join(", ")),
+                },
+                "body": { "description": "The JSON body of a POST request." },
+            },
+            "required": ["method", "url"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let argument = |name: &str| arguments.get(name).and_then(Value::as_str);
+        let (Some(method), Some(url)) = (argument("method"), argument("url")) else {
+            return Ok("No method or URL provided.".to_string());
+        };
+        let method = method.to_uppercase();
+        let url = match Url::parse(url.trim()) {
+            Ok(url) => url,
+            Err(e) => return Ok(format!("'{url}' is not a valid URL: {e}.")),
+        };
+        if let Err(e) = self.headers(&url) {
+            return Ok(format!("Not sent: {e}."));
+        }
+        let body = arguments.get("body").filter(|_| method == "POST");
+        off_runtime("the HTTP request", || self.send(&method, url, body))
+            .or_else(|e| Ok(format!("The request failed: {e:#}.")))
+    }
+}
+
+/// `value` with every `{env:NAME}` replaced by the variable `NAME`.
+fn fill_template(value: &str) -> Result<String> {
+    let mut missing = None;
+    let filled = ENV_TEMPLATE.replace_all(value, |caps: &regex::Captures| {
+        std::env::var(&caps[1]).unwrap_or_else(|_| {
+            missing = Some(caps[1].to_string());
+            String::new()
+        })
+    });
+    match missing {
+        Some(name) => bail!("the environment variable {name} for a header is not set"),
+        None => Ok(filled.into_owned()),
+    }
+}
+
+/// `HTTP_TOOL_FILE`, default `~/.jarvis/http.toml`.
+fn http_file() -> Option<PathBuf> {
+    std::env::var("HTTP_TOOL_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("http.toml")))
+}
@@ -0,0 +1,420 @@
+//! Kubernetes clusters, for on-call questions.
+//!
+//! `kubectl_task` answers "are any pods crashlooping in prod?" by running
+//! `kubectl get pods` in the namespaces of a context listed in
+//! `~/.jarvis/kubernetes.toml` (or the file named by `KUBE_TOOL_FILE`) and
+//! reading out the pods that are not well. It also describes resources
+//! and reads the end of a pod's logs. Restarting a deployment, scaling it
+//! and deleting a pod are only offered for contexts that allow changes,
+//! and wait for "yes, confirm":
+//!
+//! ```toml
+//! [contexts.prod]
+//! context = "gke_acme_prod"          # the kubectl context, default the name
+//! namespaces = ["web", "payments"]   # default ["default"]
+//! allow_changes = true
+//!
+//! [contexts.staging]
+//! namespaces = ["web"]
+//! ```
+//!
+//! Nothing outside the listed contexts and namespaces is touched. Without
+//! contexts there is no tool.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{max_output_bytes, output_text, output_with_timeout, truncate_bytes, Tool};
+
+/// How long a kubectl command may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Restarts after which a running pod is worth mentioning.
+const MANY_RESTARTS: u64 = 5;
+
+/// Log lines read out by default.
+const DEFAULT_LOG_LINES: u64 = 20;
+
+/// Actions that change the cluster.
+const CHANGES: &[&str] = &["restart", "scale", "delete_pod"];
+
+/// Kinds of resource that may be described.
+const KINDS: &[&str] = &[
+    "pod",
+    "deployment",
+    "statefulset",
+    "daemonset",
+    "service",
+    "ingress",
+    "job",
+    "cronjob",
+    "node",
+];
+
+/// The contexts as written in `kubernetes.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KubeFile {
+    #[serde(default)]
+    contexts: BTreeMap<String, Scope>,
+}
+
+/// A context and what may be done in it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scope {
+    context: Option<String>,
+    #[serde(default = "default_namespaces")]
+    namespaces: Vec<String>,
+    #[serde(default)]
+    allow_changes: bool,
+}
+
+fn default_namespaces() -> Vec<String> {
+    vec!["default".to_string()]
+}
+
+/// Runs kubectl in the configured contexts and namespaces.
+#[derive(Debug, Clone)]
+pub struct KubectlTool {
+    contexts: BTreeMap<String, Scope>,
+}
+
+impl KubectlTool {
+    /// The contexts in `KUBE_TOOL_FILE`, default
+    /// `~/.jarvis/kubernetes.toml`; `None` without any.
+    pub fn from_env() -> Option<Self> {
+        let path = kube_file()?;
+        if !path.exists() {
+            return None;
+        }
+        match Self::load_file(&path) {
+            Ok(tool) if !tool.contexts.is_empty() => Some(tool),
+            Ok(_) => None,
+            Err(e) => {
+                log::warn!("Ignoring Kubernetes file {}: {e:#}", path.display());
+                None
+            }
+        }
+    }
+
+    /// The contexts in `path`.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).context("cannot read it")?;
+        let file: KubeFile = toml::from_str(&data)?;
+        Ok(Self {
+            contexts: file
+                .contexts
+                .into_iter()
+                .map(|(name, scope)| (name.to_lowercase(), scope))
+                .collect(),
+        })
+    }
+
+    /// The `kubectl` command lines for a call, one for each namespace it
+    /// covers, or why it may not run.
+    pub fn commands(&self, arguments: &Value) -> Result<Vec<Vec<String>>, String> {
+        let argument = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let action = argument("action").unwrap_or_default();
+        let (name, scope) = match argument("context") {
+            Some(wanted) => {
+                let wanted = wanted.to_lowercase();
+                match self.contexts.get_key_value(&wanted) {
+                    Some(found) => found,
+                    None => return Err(format!("There is no context called {wanted}.")),
+                }
+            }
+            None if self.contexts.len() == 1 => self.contexts.iter().next().unwrap(),
+            None => return Err(format!("Which context: {}?", self.context_names())),
+        };
+        if CHANGES.contains(&action) && !scope.allow_changes {
+            return Err(format!("Changes are not allowed in {name}."));
+        }
+        let namespaces: Vec<&String> = match argument("namespace") {
+            Some(wanted) => match scope.namespaces.iter().find(|known| *known == wanted) {
+                Some(namespace) => vec![namespace],
+                None => {
+                    return Err(format!(
+                        "{wanted} is not one of the namespaces of {name}: {}.",
+                        scope.namespaces.join(", ")
+                    ))
+                }
+            },
+            // Only listing pods looks at every namespace at once.
+            None if action == "pods" => scope.namespaces.iter().collect(),
+            None if scope.namespaces.len() == 1 => vec![&scope.namespaces[0]],
+            None => {
+                return Err(format!(
+                    "Which namespace of {name}: {}?",
+                    scope.namespaces.join(", ")
+                ))
+            }
+        };
+        let resource = argument("name");
+        if resource.is_some_and(|resource| resource.starts_with('-')) {
+            return Err("That is not the name of a resource.".to_string());
+        }
+        let needs_name = |what: &str| format!("No {what} name provided.");
+        let mut commands = Vec::new();
+        for namespace in namespaces {
+            let mut argv: Vec<String> = vec![
+                "kubectl".to_string(),
+                "--context".to_string(),
+                scope.context.clone().unwrap_or_else(|| name.clone()),
+                "--namespace".to_string(),
+                namespace.clone(),
+            ];
+            let rest: Vec<String> = match action {
+                "pods" => words(&["get", "pods", "--output", "json"]),
+                "describe" => {
+                    let kind = argument("kind").unwrap_or("pod").to_lowercase();
+                    if !KINDS.contains(&kind.as_str()) {
+                        return Err(format!("I can't describe a {kind}."));
+                    }
+                    let resource = resource.ok_or_else(|| needs_name(&kind))?;
+                    words(&["describe", &kind, resource])
+                }
+                "logs" => {
+                    let lines = arguments
+                        .get("lines")
+                        .and_then(Value::as_u64)
+                        .unwrap_or(DEFAULT_LOG_LINES)
+                        .clamp(1, 200);
+                    let pod = resource.ok_or_else(|| needs_name("pod"))?;
+                    words(&["logs", "--tail", &lines.to_string(), pod])
+                }
+                "restart" => {
+                    let deployment = resource.ok_or_else(|| needs_name("deployment"))?;
+                    words(&["rollout", "restart", &format!("deployment/{deployment}")])
+                }
+                "scale" => {
+                    let deployment = resource.ok_or_else(|| needs_name("deployment"))?;
+                    let Some(replicas) = arguments.get("replicas").and_then(Value::as_u64) else {
+                        return Err("No number of replicas provided.".to_string());
+                    };
+                    words(&[
+                        "scale",
+                        &format!("deployment/{deployment}"),
+                        &format!("--replicas={replicas}"),
+                    ])
+                }
+                "delete_pod" => {
+                    let pod = resource.ok_or_else(|| needs_name("pod"))?;
+                    words(&["delete", "pod", pod])
+                }
+                other => return Err(format!("Unknown action '{other}'.")),
+            };
+            argv.extend(rest);
+            commands.push(argv);
+        }
+        Ok(commands)
+    }
+
+    fn context_names(&self) -> String {
+        self.contexts
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Tool for KubectlTool {
+    fn name(&self) -> &str {
+        "kubectl_task"
+    }
+
+    fn description(&self) -> &str {
+        "Use `kubectl_task` to check the pods of a Kubernetes cluster, describe a resource or read a pod's logs, and where allowed restart or scale a deployment or delete a pod."
+    }
+
+    fn parameters(&self) -> Value {
+        let contexts: Vec<String> = self
+            .contexts
+            .iter()
+            .map(|(name, scope)| {
+                let changes = if scope.allow_changes {
+                    ", changes allowed"
+                } else {
+                    ""
+                };
+                format!(
+                    "{name} (namespaces {}{changes})",
+                    scope.namespaces.join(", ")
+                )
+            })
+            .collect();
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["pods", "describe", "logs", "restart", "scale", "delete_pod"],
+                },
+                "context": {
+                    "type": "string",
+                    "description": format!("One of: {}.", contexts.join("; ")),
+                },
+                "namespace": { "type": "string", "description": "Leave out for all of the context's namespaces." },
+                "kind": { "type": "string", "enum": KINDS, "description": "What to describe; default pod." },
+                "name": { "type": "string", "description": "The pod, deployment or other resource." },
+                "lines": { "type": "integer", "description": "How many log lines to read." },
+                "replicas": { "type": "integer" },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn confirmation(&self, arguments: &Value) -> Option<String> {
+        let action = arguments.get("action").and_then(Value::as_str)?;
+        if !CHANGES.contains(&action) {
+            return None;
+        }
+        // Calls that may not run are refused without asking.
+        let commands = self.commands(arguments).ok()?;
+        let argv = commands.first()?;
+        let (context, namespace) = (&argv[2], &argv[4]);
+        let name = arguments.get("name").and_then(Value::as_str)?;
+        Some(match action {
+            "restart" => format!("restart the deployment {name} in {namespace} on {context}"),
+            "scale" => format!(
+                "scale the deployment {name} in {namespace} on {context} to {} replicas",
+                arguments.get("replicas").and_then(Value::as_u64)?
+            ),
+            _ => format!("delete the pod {name} in {namespace} on {context}"),
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let commands = match self.commands(arguments) {
+            Ok(commands) => commands,
+            Err(problem) => return Ok(problem),
+        };
+        let action = arguments
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let several = commands.len() > 1;
+        let mut answers = Vec::new();
+        for argv in commands {
+            let namespace = argv[4].clone();
+            let mut cmd = Command::new(&argv[0]);
+            cmd.args(&argv[1..]);
+            let answer = match output_with_timeout(cmd, TIMEOUT, &mut |_| {})? {
+                Some(output) if output.status.success() => {
+                    let text = output_text(&output.stdout);
+                    match action {
+                        "pods" => match serde_json::from_str(&text) {
+                            Ok(pods) => spoken_pods(&pods),
+                            Err(_) => "kubectl sent pods I could not read.".to_string(),
+                        },
+                        _ if text.is_empty() => "Done.".to_string(),
+                        _ => text,
+                    }
+                }
+                Some(output) => format!("kubectl failed: {}", output_text(&output.stderr)),
+                None => "kubectl did not answer in time.".to_string(),
+            };
+            answers.push(if several {
+                format!("In {namespace}: {answer}")
+            } else {
+                answer
+            });
+        }
+        Ok(truncate_bytes(&answers.join("\n"), max_output_bytes()))
+    }
+}
+
+fn words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+/// The output of `kubectl get pods --output json` read out: the number
+/// of pods and the ones that are crashlooping, failing to pull their
+/// image, pending, not ready or restarting often.
+pub fn spoken_pods(pods: &Value) -> String {
+    let items = pods
+        .get("items")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    if items.is_empty() {
+        return "There are no pods.".to_string();
+    }
+    let mut problems = Vec::new();
+    for pod in &items {
+        let name = pod["metadata"]["name"].as_str().unwrap_or("a pod");
+        let phase = pod["status"]["phase"].as_str().unwrap_or("Unknown");
+        if phase == "Succeeded" {
+            continue;
+        }
+        let statuses = pod["status"]["containerStatuses"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let restarts: u64 = statuses
+            .iter()
+            .filter_map(|status| status["restartCount"].as_u64())
+            .sum();
+        let waiting = statuses
+            .iter()
+            .find_map(|status| status["state"]["waiting"]["reason"].as_str());
+        let ready = !statuses.is_empty()
+            && statuses
+                .iter()
+                .all(|status| status["ready"].as_bool() == Some(true));
+        let times = |count: u64| {
+            if count == 1 {
+                "1 restart".to_string()
+            } else {
+                format!("{count} restarts")
+            }
+        };
+        let problem = match waiting {
+            Some(reason) if restarts > 0 => {
+                Some(format!("{name} is in {reason} after {}", times(restarts)))
+            }
+            Some(reason) => Some(format!("{name} is in {reason}")),
+            None if phase != "Running" => Some(format!("{name} is {}", phase.to_lowercase())),
+            None if !ready => Some(format!("{name} is not ready")),
+            None if restarts >= MANY_RESTARTS => {
+                Some(format!("{name} is running but has had {}", times(restarts)))
+            }
+            None => None,
+        };
+        problems.extend(problem);
+    }
+    let total = if items.len() == 1 {
+        "1 pod".to_string()
+    } else {
+        format!("{} pods", items.len())
+    };
+    match problems.len() {
+        0 => format!("{total}, all healthy."),
+        count => format!(
+            "{count} of {total} {} problems: {}.",
+            if count == 1 { "has" } else { "have" },
+            problems.join("; ")
+        ),
+    }
+}
+
+/// `KUBE_TOOL_FILE`, default `~/.jarvis/kubernetes.toml`.
+fn kube_file() -> Option<PathBuf> {
+    std::env::var("KUBE_TOOL_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("kubernetes.toml")))
+}
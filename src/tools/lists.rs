@@ -0,0 +1,366 @@
+//! Shopping, to-do and other lists, one Markdown file each.
+//!
+//! `list` adds items to, removes them from and reads out the lists in
+//! `LISTS_DIR` (default `~/.jarvis/lists`): "add milk to the shopping
+//! list" adds `- milk` to `shopping.md`, and "what's on my todo list"
+//! reads `todo.md`. List names are found regardless of case, spaces and
+//! dashes, so "to-do" and "todo" are the same list, and an item to remove
+//! may be named by some of its words ("take the eggs off the list"
+//! removes "a dozen eggs"). The files are plain bullet lists, so they can
+//! be edited by hand. Clearing a list waits for "yes, confirm".
+//! `LIST_TOOL=0` leaves the tool out.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use super::{expand_home, Tool};
+
+/// Words that say a list is meant rather than which one.
+const FILLER_WORDS: &[&str] = &["a", "my", "our", "the", "list"];
+
+/// Keeps lists in a folder of Markdown files.
+#[derive(Debug, Clone)]
+pub struct ListsTool {
+    dir: PathBuf,
+}
+
+impl ListsTool {
+    /// A tool for the lists in `dir`, which is created with the first
+    /// item.
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The lists in `LISTS_DIR`, default `~/.jarvis/lists`.
+    pub fn from_env() -> Self {
+        let dir = std::env::var("LISTS_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .unwrap_or_else(|| "~/.jarvis/lists".to_string());
+        Self::new(expand_home(dir.trim()))
+    }
+
+    /// The names of the lists, from their files.
+    pub fn names(&self) -> Vec<String> {
+        self.files()
+            .iter()
+            .filter_map(|path| path.file_stem())
+            .map(|stem| stem.to_string_lossy().replace('-', " "))
+            .collect()
+    }
+
+    /// The items on `list`, in order; none if there is no such list.
+    pub fn items(&self, list: &str) -> Vec<String> {
+        items(&fs::read_to_string(self.path(list)).unwrap_or_default())
+    }
+
+    /// Add the `wanted` items that are not on `list` yet.
+    pub fn add(&self, list: &str, wanted: &[String]) -> Result<String> {
+        let mut items = self.items(list);
+        let (mut added, mut already) = (Vec::new(), Vec::new());
+        for item in wanted {
+            if items.iter().any(|have| have.eq_ignore_ascii_case(item)) {
+                already.push(item.clone());
+            } else {
+                items.push(item.clone());
+                added.push(item.clone());
+            }
+        }
+        if !added.is_empty() {
+            self.write(list, &items)?;
+        }
+        let name = spoken_name(list);
+        Ok(match (added.is_empty(), already.is_empty()) {
+            (true, _) => capitalized(&format!(
+                "{} already on the {name} list.",
+                is_or_are(&already)
+            )),
+            (false, true) => format!("Added {} to the {name} list.", and_list(&added)),
+            (false, false) => format!(
+                "Added {} to the {name} list; {} already on it.",
+                and_list(&added),
+                is_or_are(&already)
+            ),
+        })
+    }
+
+    /// Take the `wanted` items off `list`, each found by its words.
+    pub fn remove(&self, list: &str, wanted: &[String]) -> Result<String> {
+        let mut items = self.items(list);
+        let (mut removed, mut missing) = (Vec::new(), Vec::new());
+        for item in wanted {
+            match find_item(&items, item) {
+                Some(index) => removed.push(items.remove(index)),
+                None => missing.push(item.clone()),
+            }
+        }
+        if !removed.is_empty() {
+            self.write(list, &items)?;
+        }
+        let name = spoken_name(list);
+        let not_there = match missing.as_slice() {
+            [] => String::new(),
+            [item] => format!("{item} isn't"),
+            _ => format!("{} aren't", and_list(&missing)),
+        };
+        Ok(if removed.is_empty() {
+            capitalized(&format!("{not_there} on the {name} list."))
+        } else if missing.is_empty() {
+            format!("Removed {} from the {name} list.", and_list(&removed))
+        } else {
+            format!(
+                "Removed {} from the {name} list; {not_there} on it.",
+                and_list(&removed)
+            )
+        })
+    }
+
+    /// What is on `list`, to be read out.
+    pub fn read(&self, list: &str) -> String {
+        let items = self.items(list);
+        let name = spoken_name(list);
+        match items.len() {
+            0 => format!("There's nothing on the {name} list."),
+            1 => format!("The {name} list has one item: {}.", items[0]),
+            count => format!("The {name} list has {count} items: {}.", and_list(&items)),
+        }
+    }
+
+    /// Take everything off `list`.
+    pub fn clear(&self, list: &str) -> Result<String> {
+        let name = spoken_name(list);
+        if self.items(list).is_empty() {
+            return Ok(format!("There's nothing on the {name} list."));
+        }
+        self.write(list, &[])?;
+        Ok(format!("Cleared the {name} list."))
+    }
+
+    /// The file of `list`: the existing one it stands for, or a new one.
+    fn path(&self, list: &str) -> PathBuf {
+        let name = file_name(list);
+        self.files()
+            .into_iter()
+            .find(|path| {
+                path.file_stem()
+                    .is_some_and(|stem| same_name(&stem.to_string_lossy(), &name))
+            })
+            .unwrap_or_else(|| self.dir.join(format!("{name}.md")))
+    }
+
+    fn write(&self, list: &str, items: &[String]) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("cannot create {}", self.dir.display()))?;
+        let path = self.path(list);
+        let old = fs::read_to_string(&path).unwrap_or_default();
+        let title = old
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(str::trim)
+            .map(str::to_string)
+            .unwrap_or_else(|| spoken_name(list));
+        let mut text = format!("# {title}\n\n");
+        for item in items {
+            text.push_str(&format!("- {item}\n"));
+        }
+        fs::write(&path, text).with_context(|| format!("cannot write to {}", path.display()))
+    }
+
+    /// The list files, by name.
+    fn files(&self) -> Vec<PathBuf> {
+        let Ok(found) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut files: Vec<PathBuf> = found
+            .flatten()
+            .map(|file| file.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+            .collect();
+        files.sort();
+        files
+    }
+}
+
+impl Tool for ListsTool {
+    fn name(&self) -> &str {
+        "list"
+    }
+
+    fn description(&self) -> &str {
+        "Use `list` to add items to or remove them from the user's shopping, to-do and other lists, to read a list out, to clear one, or to say which lists there are."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["add", "remove", "read", "clear", "lists"],
+                },
+                "list": {
+                    "type": "string",
+                    "description": "Which list, such as 'shopping' or 'todo'.",
+                },
+                "items": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "For add and remove: the items, one per entry.",
+                },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn confirmation(&self, arguments: &Value) -> Option<String> {
+        let list = arguments.get("list").and_then(Value::as_str)?;
+        (arguments.get("action").and_then(Value::as_str)? == "clear")
+            .then(|| format!("clear the {} list", spoken_name(list)))
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let action = arguments
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if action == "lists" {
+            let names = self.names();
+            return Ok(if names.is_empty() {
+                "You don't have any lists yet.".to_string()
+            } else {
+                format!("You have the {} lists.", and_list(&names))
+            });
+        }
+        let Some(list) = arguments
+            .get("list")
+            .and_then(Value::as_str)
+            .filter(|list| !file_name(list).is_empty())
+        else {
+            return Ok("Which list?".to_string());
+        };
+        let items: Vec<String> = match arguments.get("items") {
+            Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect::<Vec<_>>(),
+            Some(Value::String(item)) => vec![item.as_str()],
+            _ => Vec::new(),
+        }
+        .into_iter()
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect();
+        match action {
+            "add" | "remove" if items.is_empty() => Ok("Which items?".to_string()),
+            "add" => self.add(list, &items),
+            "remove" => self.remove(list, &items),
+            "read" => Ok(self.read(list)),
+            "clear" => self.clear(list),
+            other => Ok(format!("Unknown action '{other}'.")),
+        }
+    }
+}
+
+/// The file name, without `.md`, for `list`: "My Shopping List" is
+/// `shopping`.
+pub fn file_name(list: &str) -> String {
+    let lower = list.to_lowercase();
+    let all: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let named: Vec<&str> = all
+        .iter()
+        .copied()
+        .filter(|word| !FILLER_WORDS.contains(word))
+        .collect();
+    if named.is_empty() {
+        all.join("-")
+    } else {
+        named.join("-")
+    }
+}
+
+/// The items in the text of a list file: its `-` and `*` bullets.
+pub fn items(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            line.strip_prefix("- ").or_else(|| line.strip_prefix("* "))
+        })
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// The index of the item `wanted` names: the same text, or else the
+/// first item with all its words.
+fn find_item(items: &[String], wanted: &str) -> Option<usize> {
+    if let Some(index) = items
+        .iter()
+        .position(|item| item.eq_ignore_ascii_case(wanted))
+    {
+        return Some(index);
+    }
+    let wanted = words(wanted);
+    if wanted.is_empty() {
+        return None;
+    }
+    items.iter().position(|item| {
+        let have = words(item);
+        wanted.iter().all(|word| have.contains(word))
+    })
+}
+
+/// The words of `text` in lower case, without "a" and "the".
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !["a", "an", "the", "some"].contains(word))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether two list names are the same regardless of case, spaces and
+/// dashes.
+fn same_name(a: &str, b: &str) -> bool {
+    let letters = |name: &str| -> String {
+        name.to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect()
+    };
+    letters(a) == letters(b)
+}
+
+/// The list's name as it is said: "shopping", "to do".
+fn spoken_name(list: &str) -> String {
+    file_name(list).replace('-', " ")
+}
+
+/// "milk", "milk and eggs", "milk, eggs and bread".
+fn and_list(items: &[String]) -> String {
+    match items.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {last}", rest.join(", ")),
+        None => String::new(),
+    }
+}
+
+/// `text` with its first letter in upper case.
+fn capitalized(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// "milk is", "milk and eggs are".
+fn is_or_are(items: &[String]) -> String {
+    match items.len() {
+        1 => format!("{} is", items[0]),
+        _ => format!("{} are", and_list(items)),
+    }
+}
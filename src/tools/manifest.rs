@@ -0,0 +1,296 @@
+//! Tools declared in a manifest file instead of in Rust.
+//!
+//! Each `[[tool]]` in `~/.jarvis/tools.toml` (or the file named by
+//! `TOOLS_FILE`) becomes a tool the model can call. Its `command` is run
+//! in the shell, with every `{name}` replaced by the argument of that
+//! name, quoted for the shell:
+//!
+//! ```toml
+//! [[tool]]
+//! name = "backup_home"
+//! description = "Back up the home directory to the external drive."
+//! command = "rsync -a --delete ~/ /media/backup/{folder}/"
+//! timeout_secs = 600
+//! confirm = true
+//!
+//! [tool.args.folder]
+//! type = "string"
+//! description = "Folder on the backup drive."
+//! default = "home"
+//! ```
+//!
+//! Arguments are strings unless `type` says otherwise (`integer`,
+//! `number`, `boolean`), may be limited to an `enum` of values, and are
+//! required unless they have a `default` or `required = false`.
+//! `timeout_secs` (default 60) is how long the command may run before it
+//! is killed, and `confirm = true` makes every call wait for "yes,
+//! confirm". Invalid entries are logged and skipped; a tool named like a
+//! built-in one replaces it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use super::{output_text, output_with_timeout, shell_command, Tool};
+
+/// How long a manifest tool may run when `timeout_secs` is not given.
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// Tools as written in `tools.toml`.
+#[derive(Debug, Default, Deserialize)]
+struct ManifestFile {
+    #[serde(default, rename = "tool")]
+    tools: Vec<ToolEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolEntry {
+    name: String,
+    description: String,
+    command: String,
+    #[serde(default)]
+    args: BTreeMap<String, ArgEntry>,
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    confirm: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArgEntry {
+    #[serde(default = "string_type", rename = "type")]
+    kind: String,
+    #[serde(default)]
+    description: String,
+    #[serde(rename = "enum")]
+    allowed: Option<Vec<Value>>,
+    default: Option<Value>,
+    required: Option<bool>,
+}
+
+fn string_type() -> String {
+    "string".to_string()
+}
+
+/// A tool that runs a command template from the manifest.
+#[derive(Debug, Clone)]
+pub struct CommandTool {
+    name: String,
+    description: String,
+    command: String,
+    parameters: Value,
+    /// Values of the arguments the model may leave out.
+    defaults: Map<String, Value>,
+    timeout: Duration,
+    confirm: bool,
+}
+
+impl CommandTool {
+    fn from_entry(entry: ToolEntry) -> Result<Self> {
+        let name = entry.name.trim().to_string();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            bail!("the name must be letters, digits and underscores");
+        }
+        if entry.command.trim().is_empty() {
+            bail!("no command");
+        }
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        let mut defaults = Map::new();
+        for (arg, spec) in entry.args {
+            if !matches!(
+                spec.kind.as_str(),
+                "string" | "integer" | "number" | "boolean"
+            ) {
+                bail!("argument '{arg}' has unknown type '{}'", spec.kind);
+            }
+            let mut schema = json!({ "type": spec.kind, "description": spec.description });
+            if let Some(allowed) = spec.allowed {
+                schema["enum"] = Value::Array(allowed);
+            }
+            properties.insert(arg.clone(), schema);
+            match spec.default {
+                Some(default) => {
+                    defaults.insert(arg, default);
+                }
+                None if spec.required.unwrap_or(true) => required.push(arg),
+                None => {}
+            }
+        }
+        for placeholder in placeholders(&entry.command) {
+            if !properties.contains_key(placeholder) {
+                bail!("the command uses {{{placeholder}}}, which is not an argument");
+            }
+        }
+        Ok(Self {
+            name,
+            description: entry.description,
+            command: entry.command,
+            parameters: json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false,
+            }),
+            defaults,
+            timeout: Duration::from_secs(entry.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+            confirm: entry.confirm,
+        })
+    }
+
+    /// The command with the placeholders replaced by `arguments`. The
+    /// command is read once, so values that look like placeholders are
+    /// left alone.
+    pub fn command_line(&self, arguments: &Value) -> String {
+        let mut line = String::new();
+        let mut rest = self.command.as_str();
+        while let Some((before, name, after)) = next_placeholder(rest) {
+            let value = arguments.get(name).or_else(|| self.defaults.get(name));
+            let text = match value {
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            };
+            line.push_str(before);
+            line.push_str(&shell_quote(&text));
+            rest = after;
+        }
+        line.push_str(rest);
+        line
+    }
+}
+
+impl Tool for CommandTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters(&self) -> Value {
+        self.parameters.clone()
+    }
+
+    fn needs_confirmation(&self) -> bool {
+        self.confirm
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        self.run_streaming(arguments, &mut |_| {})
+    }
+
+    fn run_streaming(&self, arguments: &Value, on_line: &mut dyn FnMut(&str)) -> Result<String> {
+        let line = self.command_line(arguments);
+        log::debug!("Running {}: {line}", self.name);
+        let Some(output) = output_with_timeout(shell_command(&line), self.timeout, on_line)? else {
+            return Ok(format!(
+                "{} timed out after {} seconds.",
+                self.name,
+                self.timeout.as_secs()
+            ));
+        };
+        let stdout = output_text(&output.stdout);
+        let stderr = output_text(&output.stderr);
+        if !output.status.success() {
+            let code = output.status.code().unwrap_or(-1);
+            let detail = if stderr.is_empty() { &stdout } else { &stderr };
+            return Ok(if detail.is_empty() {
+                format!("{} exited with {code} and produced no output.", self.name)
+            } else {
+                format!("{} exited with {code}: {detail}", self.name)
+            });
+        }
+        Ok(if !stdout.is_empty() {
+            stdout
+        } else if !stderr.is_empty() {
+            stderr
+        } else {
+            format!("{} ran successfully with no output.", self.name)
+        })
+    }
+}
+
+/// The names between braces in `command`, in order.
+fn placeholders(command: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = command;
+    while let Some((_, name, after)) = next_placeholder(rest) {
+        names.push(name);
+        rest = after;
+    }
+    names
+}
+
+/// The text before the first `{name}` in `text`, the name and the text
+/// after it. Braces around anything but a name are not placeholders.
+fn next_placeholder(text: &str) -> Option<(&str, &str, &str)> {
+    let mut from = 0;
+    loop {
+        let start = from + text[from..].find('{')?;
+        let end = start + 1 + text[start + 1..].find('}')?;
+        let name = &text[start + 1..end];
+        if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Some((&text[..start], name, &text[end + 1..]));
+        }
+        from = start + 1;
+    }
+}
+
+/// `text` as a single shell word.
+fn shell_quote(text: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+/// `TOOLS_FILE`, default `~/.jarvis/tools.toml`.
+fn manifest_file() -> Option<PathBuf> {
+    std::env::var("TOOLS_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("tools.toml")))
+}
+
+/// The tools in the user's manifest; none if there is no manifest.
+pub fn load() -> Vec<CommandTool> {
+    manifest_file()
+        .map(|path| load_file(&path))
+        .unwrap_or_default()
+}
+
+/// The tools declared in `path`; none if it does not exist.
+pub fn load_file(path: &Path) -> Vec<CommandTool> {
+    let Ok(data) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let file: ManifestFile = match toml::from_str(&data) {
+        Ok(file) => file,
+        Err(e) => {
+            log::warn!("Ignoring invalid tools file {}: {e}", path.display());
+            return Vec::new();
+        }
+    };
+    file.tools
+        .into_iter()
+        .filter_map(|entry| {
+            let name = entry.name.clone();
+            match CommandTool::from_entry(entry) {
+                Ok(tool) => {
+                    log::info!("Loaded tool '{name}' from {}", path.display());
+                    Some(tool)
+                }
+                Err(e) => {
+                    log::warn!("Ignoring tool '{name}': {e:#}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
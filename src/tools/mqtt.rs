@@ -0,0 +1,316 @@
+//! MQTT devices: commands out, announcements in.
+//!
+//! `mqtt` publishes the commands listed for each device in
+//! `~/.jarvis/mqtt.toml` (or the file named by `MQTT_FILE`), so "tell the
+//! vacuum to start" sends `START` to the vacuum's topic without the model
+//! making up topics or payloads. The same connection subscribes to the
+//! topics under `[[announce]]` and turns their messages into sentences
+//! that are spoken between commands, such as "The washing machine is
+//! done.":
+//!
+//! ```toml
+//! host = "homeassistant.local"      # port 1883
+//! username = "jarvis"               # password from MQTT_PASSWORD
+//!
+//! [devices.vacuum]
+//! topic = "valetudo/robot/BasicControlCapability/operation/set"
+//! commands = { start = "START", stop = "STOP", dock = "HOME" }
+//!
+//! [[announce]]
+//! topic = "home/washer/state"       # + and # wildcards work
+//! say = "The washing machine is {payload}."
+//! values = { done = "The washing machine is done." }
+//! only = ["done", "error"]          # other payloads are not spoken
+//! ```
+//!
+//! Without devices or announcements there is no tool. Needs Jarvis built
+//! with `--features mqtt`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::Tool;
+
+/// Messages waiting to be spoken; see [`take_announcements`].
+static ANNOUNCEMENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Announcements kept while nobody takes them; older ones are dropped.
+const MAX_WAITING: usize = 20;
+
+/// The bridge as written in `mqtt.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MqttFile {
+    host: String,
+    #[serde(default = "default_port")]
+    port: u16,
+    username: Option<String>,
+    #[serde(default = "default_client_id")]
+    client_id: String,
+    #[serde(default)]
+    devices: BTreeMap<String, Device>,
+    #[serde(default)]
+    announce: Vec<Announce>,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    "jarvis".to_string()
+}
+
+/// A device and the payloads it understands.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Device {
+    topic: String,
+    /// What the user says, and the payload it sends.
+    commands: BTreeMap<String, String>,
+    #[serde(default)]
+    retain: bool,
+}
+
+/// Messages on a topic that are spoken.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Announce {
+    /// The topic filter, with `+` and `#` wildcards.
+    pub topic: String,
+    /// What to say; `{payload}` and `{topic}` are filled in.
+    #[serde(default = "default_say")]
+    pub say: String,
+    /// Sentences for particular payloads, instead of `say`.
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+    /// If not empty, the only payloads that are spoken.
+    #[serde(default)]
+    pub only: Vec<String>,
+}
+
+fn default_say() -> String {
+    "{payload}".to_string()
+}
+
+impl Announce {
+    /// What to say about `payload` arriving on `topic`, if anything.
+    pub fn sentence(&self, topic: &str, payload: &str) -> Option<String> {
+        if !topic_matches(&self.topic, topic) {
+            return None;
+        }
+        let payload = payload.trim();
+        let wanted = self.only.is_empty() || self.only.iter().any(|only| only == payload);
+        if payload.is_empty() || !wanted {
+            return None;
+        }
+        Some(match self.values.get(payload) {
+            Some(sentence) => sentence.clone(),
+            None => self
+                .say
+                .replace("{payload}", payload)
+                .replace("{topic}", topic),
+        })
+    }
+}
+
+/// Whether `topic` is one of those `filter` stands for: `+` matches one
+/// level and a final `#` any number.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut levels = topic.split('/');
+    for part in filter.split('/') {
+        match (part, levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (part, Some(level)) if part == level => {}
+            _ => return false,
+        }
+    }
+    levels.next().is_none()
+}
+
+/// The announcements that arrived since the last call, oldest first.
+pub fn take_announcements() -> Vec<String> {
+    std::mem::take(&mut *ANNOUNCEMENTS.lock().unwrap())
+}
+
+fn push_announcement(text: String) {
+    let mut waiting = ANNOUNCEMENTS.lock().unwrap();
+    if waiting.len() >= MAX_WAITING {
+        waiting.remove(0);
+    }
+    waiting.push(text);
+}
+
+/// Publishes device commands over the bridge's connection.
+#[derive(Clone)]
+pub struct MqttTool {
+    client: Client,
+    devices: BTreeMap<String, Device>,
+}
+
+impl MqttTool {
+    /// The bridge configured in `MQTT_FILE`, default
+    /// `~/.jarvis/mqtt.toml`, connected and listening; `None` without
+    /// one.
+    pub fn from_env() -> Option<Self> {
+        let path = mqtt_file()?;
+        if !path.exists() {
+            return None;
+        }
+        match Self::connect(&path) {
+            Ok(tool) => Some(tool),
+            Err(e) => {
+                log::warn!("Ignoring MQTT file {}: {e:#}", path.display());
+                None
+            }
+        }
+    }
+
+    /// Connect to the broker in `path` and start listening for the
+    /// announced topics in the background.
+    fn connect(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).context("cannot read it")?;
+        let file: MqttFile = toml::from_str(&data)?;
+        if file.devices.is_empty() && file.announce.is_empty() {
+            bail!("it lists no devices and no announcements");
+        }
+        let mut options = MqttOptions::new(&file.client_id, &file.host, file.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &file.username {
+            let password = std::env::var("MQTT_PASSWORD").unwrap_or_default();
+            options.set_credentials(username, password);
+        }
+        let (client, mut connection) = Client::new(options, 16);
+        let subscriber = client.clone();
+        let announce = file.announce;
+        log::info!(
+            "MQTT: {} devices and {} announced topics on {}:{}",
+            file.devices.len(),
+            announce.len(),
+            file.host,
+            file.port
+        );
+        std::thread::spawn(move || {
+            for event in connection.iter() {
+                match event {
+                    // Subscribe again after every reconnect.
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        for rule in &announce {
+                            if let Err(e) = subscriber.try_subscribe(&rule.topic, QoS::AtMostOnce) {
+                                log::warn!("MQTT: cannot subscribe to {}: {e}", rule.topic);
+                            }
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(message))) => {
+                        let payload = String::from_utf8_lossy(&message.payload);
+                        for rule in &announce {
+                            if let Some(text) = rule.sentence(&message.topic, &payload) {
+                                log::info!("MQTT {}: {text}", message.topic);
+                                push_announcement(text);
+                                break;
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::warn!("MQTT connection: {e}");
+                        std::thread::sleep(Duration::from_secs(5));
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            client,
+            devices: file.devices,
+        })
+    }
+}
+
+impl Tool for MqttTool {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    fn description(&self) -> &str {
+        "Use `mqtt` to send a command to one of the user's smart home devices, such as telling the vacuum to start."
+    }
+
+    fn parameters(&self) -> Value {
+        let devices: Vec<String> = self
+            .devices
+            .iter()
+            .map(|(name, device)| {
+                let commands: Vec<&str> = device.commands.keys().map(String::as_str).collect();
+                format!("{name} ({})", commands.join(", "))
+            })
+            .collect();
+        json!({
+            "type": "object",
+            "properties": {
+                "device": {
+                    "type": "string",
+                    "description": format!("One of: {}.", devices.join("; ")),
+                },
+                "command": { "type": "string", "description": "One of the device's commands." },
+            },
+            "required": ["device", "command"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let argument = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(Value::as_str)
+                .map(|value| value.trim().to_lowercase())
+        };
+        let (Some(name), Some(command)) = (argument("device"), argument("command")) else {
+            return Ok("No device or command provided.".to_string());
+        };
+        let Some(device) = self
+            .devices
+            .iter()
+            .find(|(known, _)| known.to_lowercase() == name)
+            .map(|(_, device)| device)
+        else {
+            return Ok(format!("There is no device called {name}."));
+        };
+        let Some(payload) = device
+            .commands
+            .iter()
+            .find(|(known, _)| known.to_lowercase() == command)
+            .map(|(_, payload)| payload)
+        else {
+            let commands: Vec<&str> = device.commands.keys().map(String::as_str).collect();
+            return Ok(format!("The {name} only knows: {}.", commands.join(", ")));
+        };
+        // Queued for the connection thread, which sends it.
+        Ok(
+            match self.client.try_publish(
+                &device.topic,
+                QoS::AtLeastOnce,
+                device.retain,
+                payload.as_bytes(),
+            ) {
+                Ok(()) => format!("Sent {command} to the {name}."),
+                Err(e) => format!("I couldn't reach the {name}: {e}."),
+            },
+        )
+    }
+}
+
+/// `MQTT_FILE`, default `~/.jarvis/mqtt.toml`.
+fn mqtt_file() -> Option<PathBuf> {
+    std::env::var("MQTT_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("mqtt.toml")))
+}
@@ -0,0 +1,439 @@
+//! Music from the user's own library.
+//!
+//! `music` finds tracks for "play some Miles Davis" and plays them, either
+//! on an MPD server through `mpc` (`MUSIC_BACKEND=mpd`, with `MPD_HOST`
+//! and `MPD_PORT` as `mpc` reads them) or from the folder in `MUSIC_DIR`
+//! (default `~/Music`) with `mpv`. Every word asked for, apart from
+//! fillers such as "some" and "songs", must be in the track's artist,
+//! album or title for MPD, or in its path for the folder, so
+//! `~/Music/Miles Davis/Kind of Blue/01 So What.flac` is found by "miles
+//! davis", "kind of blue" or "so what".
+//!
+//! While music started here is playing, the `media` tool's play, pause,
+//! next, previous and stop go to it rather than to `playerctl` (see
+//! [`control`]), so "pause" and "next song" work even without an MPRIS
+//! bridge. `MUSIC_TOOL=0` leaves the tool out.
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use super::{expand_home, output_text, output_with_timeout, Tool};
+
+/// How long an `mpc` command may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Files looked at in the music folder; the rest are not searched.
+const MAX_FILES: usize = 100_000;
+
+/// Tracks queued at once.
+const MAX_TRACKS: usize = 500;
+
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "opus", "m4a", "aac", "wav", "wma"];
+
+/// Words that say how much to play rather than what.
+const FILLER_WORDS: &[&str] = &[
+    "some", "any", "a", "the", "by", "music", "songs", "song", "tracks", "track", "album", "me",
+    "play", "little", "bit", "of",
+];
+
+/// The player started by the tool, which the `media` tool controls too.
+static PLAYING: Mutex<Option<Playing>> = Mutex::new(None);
+
+enum Playing {
+    Mpd,
+    /// `mpv` and the socket it listens for commands on.
+    Mpv(Child, PathBuf),
+}
+
+/// Where the music comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Library {
+    /// An MPD server, through `mpc`.
+    Mpd,
+    /// A folder of audio files, played with `mpv`.
+    Folder(PathBuf),
+}
+
+/// Searches the library and plays what it finds.
+#[derive(Debug, Clone)]
+pub struct MusicTool {
+    library: Library,
+}
+
+impl MusicTool {
+    pub fn new(library: Library) -> Self {
+        Self { library }
+    }
+
+    /// The library of `MUSIC_BACKEND` and `MUSIC_DIR`.
+    pub fn from_env() -> Self {
+        let backend = std::env::var("MUSIC_BACKEND").unwrap_or_default();
+        if backend.trim().eq_ignore_ascii_case("mpd") {
+            return Self::new(Library::Mpd);
+        }
+        let dir = std::env::var("MUSIC_DIR").unwrap_or_else(|_| "~/Music".to_string());
+        Self::new(Library::Folder(expand_home(dir.trim())))
+    }
+
+    /// Queue and start the tracks matching `words`.
+    fn play(&self, query: &str, words: &[String]) -> Result<String> {
+        stop_playing();
+        match &self.library {
+            Library::Mpd => {
+                for argv in mpc_play_commands(words) {
+                    if let Err(problem) = mpc(&argv[1..])? {
+                        return Ok(problem);
+                    }
+                }
+                let queued = mpc(&["playlist".to_string()])?.unwrap_or_default();
+                if queued.trim().is_empty() {
+                    return Ok(format!("I found no music matching {query}."));
+                }
+                *PLAYING.lock().unwrap() = Some(Playing::Mpd);
+                let current = mpc(&["current".to_string()])?.unwrap_or_default();
+                Ok(format!(
+                    "Playing {} matching {query}, starting with {}.",
+                    tracks(queued.lines().count()),
+                    current.trim()
+                ))
+            }
+            Library::Folder(dir) => {
+                let found: Vec<PathBuf> = library(dir)
+                    .into_iter()
+                    .filter(|path| matches(path.strip_prefix(dir).unwrap_or(path), words))
+                    .take(MAX_TRACKS)
+                    .collect();
+                let Some(first) = found.first() else {
+                    return Ok(format!("I found no music matching {query}."));
+                };
+                let first = track_name(first);
+                let count = found.len();
+                let id = std::process::id();
+                let playlist = std::env::temp_dir().join(format!("jarvis-music-{id}.m3u"));
+                let socket = std::env::temp_dir().join(format!("jarvis-music-{id}.sock"));
+                let lines: Vec<String> = found
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                std::fs::write(&playlist, lines.join("\n") + "\n")
+                    .context("cannot write the playlist")?;
+                let child = match Command::new("mpv")
+                    .args(mpv_arguments(&playlist, &socket))
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                {
+                    Ok(child) => child,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        return Ok("I can't play music: mpv is not installed.".to_string())
+                    }
+                    Err(e) => return Err(e).context("cannot start mpv"),
+                };
+                *PLAYING.lock().unwrap() = Some(Playing::Mpv(child, socket));
+                Ok(format!(
+                    "Playing {} matching {query}, starting with {first}.",
+                    tracks(count)
+                ))
+            }
+        }
+    }
+}
+
+impl Tool for MusicTool {
+    fn name(&self) -> &str {
+        "music"
+    }
+
+    fn description(&self) -> &str {
+        "Use `music` to play music from the user's library by artist, album or song, and to pause, skip, stop or set the volume of it."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["play", "pause", "resume", "next", "previous", "stop", "volume"],
+                },
+                "query": {
+                    "type": "string",
+                    "description": "For play: the artist, album or song, as the user said it.",
+                },
+                "percent": { "type": "integer", "description": "For volume: the level to set." },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let action = arguments
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_lowercase();
+        let query = arguments
+            .get("query")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .unwrap_or_default();
+        let words = query_words(query);
+        if action == "play" && !words.is_empty() {
+            return self.play(query, &words);
+        }
+        let action = match action.as_str() {
+            "play" | "resume" => "play",
+            "volume" => {
+                let Some(percent) = arguments.get("percent").and_then(Value::as_u64) else {
+                    return Ok("What volume should the music be?".to_string());
+                };
+                return control(&format!("volume {}", percent.min(100)))
+                    .unwrap_or_else(|| Ok(nothing_playing()));
+            }
+            other => other,
+        };
+        if !matches!(action, "play" | "pause" | "next" | "previous" | "stop") {
+            return Ok(format!("Unknown music action '{action}'."));
+        }
+        if let Some(answer) = control(action) {
+            return answer;
+        }
+        // Nothing started here is playing, but MPD may be paused.
+        if self.library == Library::Mpd && action == "play" {
+            *PLAYING.lock().unwrap() = Some(Playing::Mpd);
+            return control(action).unwrap_or_else(|| Ok(nothing_playing()));
+        }
+        Ok(nothing_playing())
+    }
+}
+
+fn nothing_playing() -> String {
+    "No music is playing; tell me what to play.".to_string()
+}
+
+fn tracks(count: usize) -> String {
+    if count == 1 {
+        "1 track".to_string()
+    } else {
+        format!("{count} tracks")
+    }
+}
+
+/// Do `action` ("play", "pause", "toggle", "next", "previous", "stop",
+/// or "volume N") to the music this tool started: `None` if none is
+/// playing.
+pub fn control(action: &str) -> Option<Result<String>> {
+    let mut playing = PLAYING.lock().unwrap();
+    // mpv quits at the end of the playlist, or when its window is closed.
+    if let Some(Playing::Mpv(child, _)) = playing.as_mut() {
+        if !matches!(child.try_wait(), Ok(None)) {
+            *playing = None;
+        }
+    }
+    let answer = match playing.as_ref()? {
+        Playing::Mpd => {
+            let args = mpc_control(action)?;
+            mpc(&args).map(|outcome| outcome.err())
+        }
+        Playing::Mpv(_, socket) => {
+            let command = mpv_control(action)?;
+            Ok(mpv_send(socket, &command).err())
+        }
+    };
+    // After "stop" the media tool goes back to other players.
+    if action == "stop" {
+        if let Some(Playing::Mpv(mut child, _)) = playing.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+    Some(answer.map(|problem| {
+        problem.unwrap_or_else(|| match action {
+            "play" => "Playing.".to_string(),
+            "pause" => "Paused.".to_string(),
+            "next" => "Next track.".to_string(),
+            "previous" => "Previous track.".to_string(),
+            "stop" => "Stopped.".to_string(),
+            volume if volume.starts_with("volume ") => {
+                format!("Music volume set to {} percent.", &volume[7..])
+            }
+            _ => "Done.".to_string(),
+        })
+    }))
+}
+
+/// Stop whatever this tool started before playing something else.
+fn stop_playing() {
+    if let Some(Playing::Mpv(mut child, _)) = PLAYING.lock().unwrap().take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// The words of `query` that say what to play: "play some Miles Davis"
+/// gives `miles`, `davis`.
+pub fn query_words(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(str::to_lowercase)
+        .filter(|word| !word.is_empty() && !FILLER_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Whether every one of `words` is in `path`, ignoring case.
+pub fn matches(path: &Path, words: &[String]) -> bool {
+    let text = path.to_string_lossy().to_lowercase();
+    words.iter().all(|word| text.contains(word.as_str()))
+}
+
+/// The `mpc` commands that replace the queue with the tracks whose tags
+/// contain all of `words` and start playing.
+pub fn mpc_play_commands(words: &[String]) -> Vec<Vec<String>> {
+    let mut searchadd = vec!["mpc".to_string(), "searchadd".to_string()];
+    for word in words {
+        searchadd.push("any".to_string());
+        searchadd.push(word.clone());
+    }
+    vec![
+        vec!["mpc".to_string(), "clear".to_string()],
+        searchadd,
+        vec!["mpc".to_string(), "play".to_string()],
+    ]
+}
+
+/// The `mpc` arguments for a [`control`] action.
+fn mpc_control(action: &str) -> Option<Vec<String>> {
+    if let Some(level) = action.strip_prefix("volume ") {
+        return Some(vec!["volume".to_string(), level.to_string()]);
+    }
+    let command = match action {
+        "play" => "play",
+        "pause" => "pause",
+        "toggle" => "toggle",
+        "next" => "next",
+        "previous" => "prev",
+        "stop" => "stop",
+        _ => return None,
+    };
+    Some(vec![command.to_string()])
+}
+
+/// The `mpv` arguments that play `playlist` without a window and listen
+/// for commands on `socket`.
+pub fn mpv_arguments(playlist: &Path, socket: &Path) -> Vec<String> {
+    vec![
+        "--no-video".to_string(),
+        "--really-quiet".to_string(),
+        format!("--input-ipc-server={}", socket.display()),
+        format!("--playlist={}", playlist.display()),
+    ]
+}
+
+/// The JSON IPC command for a [`control`] action.
+pub fn mpv_control(action: &str) -> Option<Value> {
+    if let Some(level) = action.strip_prefix("volume ") {
+        let level: u64 = level.parse().ok()?;
+        return Some(json!({ "command": ["set_property", "volume", level] }));
+    }
+    let command = match action {
+        "play" => json!(["set_property", "pause", false]),
+        "pause" => json!(["set_property", "pause", true]),
+        "toggle" => json!(["cycle", "pause"]),
+        "next" => json!(["playlist-next"]),
+        "previous" => json!(["playlist-prev"]),
+        "stop" => json!(["quit"]),
+        _ => return None,
+    };
+    Some(json!({ "command": command }))
+}
+
+/// Send `command` to the `mpv` listening on `socket`.
+#[cfg(unix)]
+fn mpv_send(socket: &Path, command: &Value) -> Result<(), String> {
+    use std::io::Write;
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)
+        .map_err(|e| format!("I couldn't reach the music player: {e}."))?;
+    writeln!(stream, "{command}").map_err(|e| format!("I couldn't reach the music player: {e}."))
+}
+
+#[cfg(not(unix))]
+fn mpv_send(_socket: &Path, _command: &Value) -> Result<(), String> {
+    Err("I can't control the music player on this system.".to_string())
+}
+
+/// Run `mpc` with `args`: its output, or the problem in words.
+fn mpc(args: &[String]) -> Result<Result<String, String>> {
+    let mut cmd = Command::new("mpc");
+    cmd.args(args);
+    Ok(match output_with_timeout(cmd, TIMEOUT, &mut |_| {}) {
+        Ok(Some(output)) if output.status.success() => Ok(output_text(&output.stdout)),
+        Ok(Some(output)) => Err(format!("mpc failed: {}", output_text(&output.stderr))),
+        Ok(None) => Err("MPD did not answer in time.".to_string()),
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) =>
+        {
+            Err("I can't reach MPD: mpc is not installed.".to_string())
+        }
+        Err(e) => return Err(e),
+    })
+}
+
+/// The audio files under `dir`, in path order, skipping hidden folders.
+fn library(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut folders = vec![dir.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let Ok(entries) = std::fs::read_dir(&folder) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if path.is_dir() {
+                if !hidden {
+                    folders.push(path);
+                }
+            } else if is_audio(&path) {
+                files.push(path);
+                if files.len() >= MAX_FILES {
+                    break;
+                }
+            }
+        }
+        if files.len() >= MAX_FILES {
+            break;
+        }
+    }
+    files.sort();
+    files
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// A track's file name read out: "01 So What.flac" is "So What".
+pub fn track_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = stem
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim_start_matches([' ', '-', '.', '_'])
+        .replace('_', " ");
+    if name.is_empty() {
+        stem
+    } else {
+        name
+    }
+}
@@ -0,0 +1,355 @@
+//! A spoken news briefing from RSS and Atom feeds.
+//!
+//! `news` reads out the latest headlines of the feeds in
+//! `~/.jarvis/news.toml` (or the file named by `NEWS_FILE`) for "give me
+//! the news", or of one feed for "what's new on Hacker News?":
+//!
+//! ```toml
+//! headlines = 8          # read out in all (default 8)
+//! per_feed = 3           # at most from one feed (default 3)
+//! max_age_hours = 24     # older items are left out (default 24)
+//!
+//! [[feed]]
+//! name = "BBC"
+//! url = "https://feeds.bbci.co.uk/news/rss.xml"
+//!
+//! [[feed]]
+//! name = "Hacker News"
+//! url = "https://hnrss.org/frontpage"
+//! enabled = false        # only when asked for by name
+//! ```
+//!
+//! The feeds are fetched at the same time, and a story carried by several
+//! of them is read out once. Without feeds there is no tool. Needs Jarvis
+//! built with `--features news`.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{off_runtime, Tool};
+
+/// The briefing as written in `news.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewsFile {
+    #[serde(default = "default_headlines")]
+    headlines: usize,
+    #[serde(default = "default_per_feed")]
+    per_feed: usize,
+    #[serde(default = "default_max_age_hours")]
+    max_age_hours: i64,
+    #[serde(default)]
+    feed: Vec<Feed>,
+}
+
+fn default_headlines() -> usize {
+    8
+}
+
+fn default_per_feed() -> usize {
+    3
+}
+
+fn default_max_age_hours() -> i64 {
+    24
+}
+
+/// One RSS or Atom feed.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Feed {
+    name: String,
+    url: String,
+    /// Whether it is part of the briefing; a disabled feed is still read
+    /// when asked for by name.
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A story in a feed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Headline {
+    /// The name of the feed it came from.
+    pub feed: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+}
+
+/// Reads out the headlines of the configured feeds.
+#[derive(Debug, Clone)]
+pub struct NewsTool {
+    feeds: Vec<Feed>,
+    headlines: usize,
+    per_feed: usize,
+    max_age: chrono::Duration,
+}
+
+impl NewsTool {
+    /// The feeds in `NEWS_FILE`, default `~/.jarvis/news.toml`; `None`
+    /// without any.
+    pub fn from_env() -> Option<Self> {
+        let path = news_file()?;
+        if !path.exists() {
+            return None;
+        }
+        match Self::load_file(&path) {
+            Ok(tool) if !tool.feeds.is_empty() => Some(tool),
+            Ok(_) => None,
+            Err(e) => {
+                log::warn!("Ignoring news file {}: {e:#}", path.display());
+                None
+            }
+        }
+    }
+
+    /// The feeds in `path`.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).context("cannot read it")?;
+        let file: NewsFile = toml::from_str(&data)?;
+        Ok(Self {
+            feeds: file.feed,
+            headlines: file.headlines.max(1),
+            per_feed: file.per_feed.max(1),
+            max_age: chrono::Duration::hours(file.max_age_hours.max(1)),
+        })
+    }
+
+    /// The feeds to read: the one called `name`, or the enabled ones.
+    fn chosen(&self, name: Option<&str>) -> Result<Vec<&Feed>, String> {
+        let Some(name) = name else {
+            return Ok(self.feeds.iter().filter(|feed| feed.enabled).collect());
+        };
+        let wanted = simple(name);
+        self.feeds
+            .iter()
+            .find(|feed| simple(&feed.name) == wanted)
+            .map(|feed| vec![feed])
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.feeds.iter().map(|feed| feed.name.as_str()).collect();
+                format!(
+                    "There is no feed called {name}; I know {}.",
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// Fetch `feeds` at the same time: their headlines, and the names of
+    /// those that could not be read.
+    fn fetch(&self, feeds: &[&Feed]) -> Result<(Vec<Headline>, Vec<String>)> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("jarvis_rust/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("failed to create HTTP client")?;
+        let results: Vec<(String, Result<Vec<Headline>>)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = feeds
+                .iter()
+                .map(|feed| {
+                    let client = &client;
+                    scope.spawn(move || {
+                        let headlines = client
+                            .get(&feed.url)
+                            .send()
+                            .and_then(|response| response.error_for_status())
+                            .map_err(anyhow::Error::from)
+                            .and_then(|response| Ok(response.bytes()?))
+                            .and_then(|body| parse_feed(&feed.name, &body));
+                        (feed.name.clone(), headlines)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| (String::new(), Err(anyhow!("panicked"))))
+                })
+                .collect()
+        });
+        let mut headlines = Vec::new();
+        let mut failed = Vec::new();
+        for (name, result) in results {
+            match result {
+                Ok(found) => headlines.extend(found),
+                Err(e) => {
+                    log::warn!("News feed {name}: {e:#}");
+                    failed.push(name);
+                }
+            }
+        }
+        Ok((headlines, failed))
+    }
+}
+
+impl Tool for NewsTool {
+    fn name(&self) -> &str {
+        "news"
+    }
+
+    fn description(&self) -> &str {
+        "Use `news` for a briefing of the latest headlines from the user's news feeds, or from one of them."
+    }
+
+    fn parameters(&self) -> Value {
+        let names: Vec<&str> = self.feeds.iter().map(|feed| feed.name.as_str()).collect();
+        json!({
+            "type": "object",
+            "properties": {
+                "feed": {
+                    "type": "string",
+                    "description": format!("Only this feed, one of: {}.", names.join(", ")),
+                },
+            },
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let name = arguments
+            .get("feed")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|name| !name.is_empty());
+        let feeds = match self.chosen(name) {
+            Ok(feeds) if feeds.is_empty() => {
+                return Ok("All news feeds are turned off.".to_string())
+            }
+            Ok(feeds) => feeds,
+            Err(problem) => return Ok(problem),
+        };
+        let fetched = off_runtime("the news request", || self.fetch(&feeds));
+        let (headlines, failed) = match fetched {
+            Ok(fetched) => fetched,
+            Err(e) => return Ok(format!("I couldn't get the news: {e:#}.")),
+        };
+        let cutoff = Utc::now() - self.max_age;
+        let recent: Vec<Headline> = headlines
+            .into_iter()
+            .filter(|headline| match headline.published {
+                Some(time) => time >= cutoff,
+                None => true,
+            })
+            .collect();
+        let chosen = select(dedupe(recent), self.per_feed, self.headlines);
+        let mut text = briefing(&chosen);
+        match failed.as_slice() {
+            [] => {}
+            [one] => text.push_str(&format!(" I couldn't reach {one}.")),
+            many => text.push_str(&format!(" I couldn't reach {}.", many.join(", "))),
+        }
+        Ok(text)
+    }
+}
+
+/// The headlines in `body`, an RSS or Atom feed called `feed`, newest
+/// first.
+pub fn parse_feed(feed: &str, body: &[u8]) -> Result<Vec<Headline>> {
+    let parsed = feed_rs::parser::parse(body).context("not an RSS or Atom feed")?;
+    let mut headlines: Vec<Headline> = parsed
+        .entries
+        .into_iter()
+        .filter_map(|entry| {
+            let title = entry.title?.content;
+            let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!title.is_empty()).then(|| Headline {
+                feed: feed.to_string(),
+                title,
+                link: entry.links.into_iter().next().map(|link| link.href),
+                published: entry.published.or(entry.updated),
+            })
+        })
+        .collect();
+    // Items without a date keep their place after the dated ones.
+    headlines.sort_by(|a, b| b.published.cmp(&a.published));
+    Ok(headlines)
+}
+
+/// `headlines` without the stories already seen, by link or by the words
+/// of their title.
+pub fn dedupe(headlines: Vec<Headline>) -> Vec<Headline> {
+    let mut titles = std::collections::HashSet::new();
+    let mut links = std::collections::HashSet::new();
+    headlines
+        .into_iter()
+        .filter(|headline| {
+            let new_title = titles.insert(simple(&headline.title));
+            let new_link = match &headline.link {
+                Some(link) => links.insert(link.trim_end_matches('/').to_string()),
+                None => true,
+            };
+            new_title && new_link
+        })
+        .collect()
+}
+
+/// At most `per_feed` headlines from each feed and `total` in all, in
+/// their order.
+pub fn select(headlines: Vec<Headline>, per_feed: usize, total: usize) -> Vec<Headline> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let mut chosen: Vec<Headline> = Vec::new();
+    for headline in headlines {
+        let index = match counts.iter().position(|(feed, _)| *feed == headline.feed) {
+            Some(index) => index,
+            None => {
+                counts.push((headline.feed.clone(), 0));
+                counts.len() - 1
+            }
+        };
+        if counts[index].1 < per_feed && chosen.len() < total {
+            counts[index].1 += 1;
+            chosen.push(headline);
+        }
+    }
+    chosen
+}
+
+/// `headlines` read out, feed by feed: "Here's the news. From BBC: Storm
+/// hits the coast. Elections called. From Reuters: Markets rally."
+pub fn briefing(headlines: &[Headline]) -> String {
+    if headlines.is_empty() {
+        return "There's no news right now.".to_string();
+    }
+    let mut text = "Here's the news.".to_string();
+    let mut current: Option<&str> = None;
+    for headline in headlines {
+        if current != Some(headline.feed.as_str()) {
+            text.push_str(&format!(" From {}:", headline.feed));
+            current = Some(&headline.feed);
+        }
+        let title = headline.title.trim_end_matches(['.', ' ']);
+        if title.ends_with(['?', '!']) {
+            text.push_str(&format!(" {title}"));
+        } else {
+            text.push_str(&format!(" {title}."));
+        }
+    }
+    text
+}
+
+/// `text` in lower case with only its letters and digits, for comparing.
+fn simple(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `NEWS_FILE`, default `~/.jarvis/news.toml`.
+fn news_file() -> Option<PathBuf> {
+    std::env::var("NEWS_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("news.toml")))
+}
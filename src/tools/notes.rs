@@ -0,0 +1,297 @@
+//! Notes and a journal, one Markdown file per topic.
+//!
+//! `notes` adds dated entries to `<topic>.md` in `NOTES_DIR` (default
+//! `~/notes`), "journal" being a topic like any other, and reads them
+//! back: "note that the boiler was serviced" or "read my notes about the
+//! garden". A topic the user names need not match a file name exactly;
+//! failing that, the entries are ranked by the words they share with the
+//! request, so finding notes works without an index or embeddings (see
+//! [`crate::documents`] for those). Each entry is a `## YYYY-MM-DD HH:MM`
+//! heading followed by its text, so the files stay readable by hand.
+//! `NOTES_TOOL=0` leaves the tool out.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use super::{expand_home, max_output_bytes, truncate_bytes, Tool};
+
+/// Entries read out for a topic, the latest ones.
+const MAX_READ: usize = 10;
+
+/// Entries read out for a search.
+const MAX_FOUND: usize = 5;
+
+/// Words too common to say what a note is about.
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "all", "an", "and", "any", "are", "at", "for", "from", "i", "in", "is", "it",
+    "me", "my", "notes", "note", "of", "on", "or", "the", "to", "was", "what", "with",
+];
+
+/// One entry of a notes file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// When it was written, as in its heading: "2026-10-16 14:05".
+    pub time: String,
+    pub text: String,
+}
+
+/// Keeps notes in a folder of Markdown files.
+#[derive(Debug, Clone)]
+pub struct NotesTool {
+    dir: PathBuf,
+}
+
+impl NotesTool {
+    /// A tool for the notes in `dir`, which is created with the first
+    /// note.
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The notes in `NOTES_DIR`, default `~/notes`.
+    pub fn from_env() -> Self {
+        let dir = std::env::var("NOTES_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .unwrap_or_else(|| "~/notes".to_string());
+        Self::new(expand_home(dir.trim()))
+    }
+
+    /// Add `text` to `topic` at `time`, creating its file if needed.
+    pub fn add(&self, topic: &str, text: &str, time: &str) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("cannot create {}", self.dir.display()))?;
+        let path = self.dir.join(format!("{}.md", file_name(topic)));
+        let old = fs::read_to_string(&path).unwrap_or_default();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("cannot write to {}", path.display()))?;
+        let mut added = String::new();
+        if old.is_empty() {
+            added.push_str(&format!("# {}\n", topic.trim()));
+        } else if !old.ends_with('\n') {
+            added.push('\n');
+        }
+        added.push_str(&format!("\n## {time}\n\n{}\n", text.trim()));
+        file.write_all(added.as_bytes())?;
+        Ok(path)
+    }
+
+    /// The topics there are notes on, from their files.
+    pub fn topics(&self) -> Vec<String> {
+        self.files()
+            .iter()
+            .filter_map(|path| path.file_stem())
+            .map(|stem| stem.to_string_lossy().replace('-', " "))
+            .collect()
+    }
+
+    /// The latest entries on `topic`, or the entries elsewhere that share
+    /// the most words with it; what was found, to be read out.
+    pub fn read(&self, topic: &str) -> String {
+        let wanted = file_name(topic);
+        let files = self.files();
+        let exact = files.iter().find(|path| {
+            path.file_stem()
+                .is_some_and(|stem| stem.to_string_lossy() == wanted)
+        });
+        if let Some(path) = exact {
+            let entries = entries(&fs::read_to_string(path).unwrap_or_default());
+            if entries.is_empty() {
+                return format!("There's nothing in your notes about {}.", topic.trim());
+            }
+            let skipped = entries.len().saturating_sub(MAX_READ);
+            let mut text = String::new();
+            if skipped > 0 {
+                text.push_str(&format!(
+                    "The latest {MAX_READ} of {} notes.\n",
+                    entries.len()
+                ));
+            }
+            for entry in &entries[skipped..] {
+                text.push_str(&format!("{}: {}\n", entry.time, entry.text));
+            }
+            return truncate_bytes(text.trim_end(), max_output_bytes());
+        }
+        self.search(topic)
+    }
+
+    /// The entries in all topics that share the most words with `query`.
+    pub fn search(&self, query: &str) -> String {
+        let wanted = words(query);
+        if wanted.is_empty() {
+            return "No topic or words to look for.".to_string();
+        }
+        let mut found: Vec<(usize, String, Entry)> = Vec::new();
+        for path in self.files() {
+            let topic = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().replace('-', " "))
+                .unwrap_or_default();
+            let topic_words = words(&topic);
+            for entry in entries(&fs::read_to_string(&path).unwrap_or_default()) {
+                let entry_words = words(&entry.text);
+                let score = wanted
+                    .iter()
+                    .filter(|word| entry_words.contains(*word) || topic_words.contains(*word))
+                    .count();
+                if score > 0 {
+                    found.push((score, topic.clone(), entry));
+                }
+            }
+        }
+        if found.is_empty() {
+            return format!("There's nothing in your notes about {}.", query.trim());
+        }
+        // Best first, and the latest of equally good ones.
+        found.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.2.time.cmp(&a.2.time)));
+        let lines: Vec<String> = found
+            .iter()
+            .take(MAX_FOUND)
+            .map(|(_, topic, entry)| format!("{topic}, {}: {}", entry.time, entry.text))
+            .collect();
+        truncate_bytes(&lines.join("\n"), max_output_bytes())
+    }
+
+    /// The notes files, by name.
+    fn files(&self) -> Vec<PathBuf> {
+        let Ok(found) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut files: Vec<PathBuf> = found
+            .flatten()
+            .map(|file| file.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+            .collect();
+        files.sort();
+        files
+    }
+}
+
+impl Tool for NotesTool {
+    fn name(&self) -> &str {
+        "notes"
+    }
+
+    fn description(&self) -> &str {
+        "Use `notes` to write down a note or journal entry under a topic, read the notes on a topic, or find notes about something."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["add", "read", "search", "topics"] },
+                "topic": {
+                    "type": "string",
+                    "description": "What the notes are about, such as 'garden' or 'journal'.",
+                },
+                "text": { "type": "string", "description": "The note to add." },
+                "query": { "type": "string", "description": "The words to look for." },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let argument = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        match argument("action").unwrap_or_default() {
+            "add" => {
+                let Some(text) = argument("text") else {
+                    return Ok("No text provided.".to_string());
+                };
+                let topic = argument("topic").unwrap_or("journal");
+                let time = chrono::Local::now().format("%Y-%m-%d %H:%M").to_string();
+                self.add(topic, text, &time)?;
+                Ok(format!("Noted under {topic}."))
+            }
+            "read" => Ok(match argument("topic").or_else(|| argument("query")) {
+                Some(topic) => self.read(topic),
+                None => "No topic provided.".to_string(),
+            }),
+            "search" => Ok(match argument("query").or_else(|| argument("topic")) {
+                Some(query) => self.search(query),
+                None => "No query provided.".to_string(),
+            }),
+            "topics" => {
+                let topics = self.topics();
+                Ok(if topics.is_empty() {
+                    "There are no notes yet.".to_string()
+                } else {
+                    format!("You have notes on {}.", topics.join(", "))
+                })
+            }
+            other => Ok(format!("Unknown action '{other}'.")),
+        }
+    }
+}
+
+/// The file name, without `.md`, for `topic`: "Garden Plans" is
+/// `garden-plans`.
+pub fn file_name(topic: &str) -> String {
+    let mut name = String::new();
+    for word in topic
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if !name.is_empty() {
+            name.push('-');
+        }
+        name.push_str(word);
+    }
+    if name.is_empty() {
+        "journal".to_string()
+    } else {
+        name
+    }
+}
+
+/// The entries in the text of a notes file, oldest first.
+pub fn entries(text: &str) -> Vec<Entry> {
+    let mut found: Vec<Entry> = Vec::new();
+    for line in text.lines() {
+        if let Some(time) = line.strip_prefix("## ") {
+            found.push(Entry {
+                time: time.trim().to_string(),
+                text: String::new(),
+            });
+        } else if let Some(entry) = found.last_mut() {
+            let line = line.trim();
+            if !line.is_empty() {
+                if !entry.text.is_empty() {
+                    entry.text.push(' ');
+                }
+                entry.text.push_str(line);
+            }
+        }
+    }
+    found.retain(|entry| !entry.text.is_empty());
+    found
+}
+
+/// The words of `text` that say what it is about, in lower case and
+/// without a plural `s`.
+fn words(text: &str) -> HashSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !STOP_WORDS.contains(word))
+        .map(|word| match word.strip_suffix('s') {
+            Some(stem) if stem.len() > 2 => stem.to_string(),
+            _ => word.to_string(),
+        })
+        .collect()
+}
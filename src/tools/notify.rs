@@ -0,0 +1,379 @@
+//! Desktop notifications, out and in.
+//!
+//! `notify` shows a desktop notification with `notify-send`, for "remind
+//! me on screen" or a result worth keeping in view. With
+//! `NOTIFY_LISTEN=1` it also listens to the notifications other programs
+//! send on the session bus (through `dbus-monitor`) and keeps the latest,
+//! so "any notifications?" reads out those that came in since the last
+//! time. `NOTIFY_APPS=Slack,Thunderbird` keeps only the notifications of
+//! those apps and `NOTIFY_IGNORE_APPS=Spotify` leaves out those of others;
+//! Jarvis' own are never read back. `NOTIFY_TOOL=0` leaves the tool out.
+
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Value};
+
+use super::{output_text, output_with_timeout, Tool};
+use crate::config::{env_flag, env_list};
+
+/// The app name Jarvis' notifications are sent under.
+pub const APP_NAME: &str = "Jarvis";
+
+/// How long `notify-send` may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Notifications kept while nobody asks for them; older ones are dropped.
+const MAX_WAITING: usize = 50;
+
+static MARKUP: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// Notifications waiting to be read; see [`take_notifications`].
+static NOTIFICATIONS: Mutex<Vec<Notification>> = Mutex::new(Vec::new());
+
+static LISTENER: Once = Once::new();
+
+/// A notification another program sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// The app that sent it, such as "Slack".
+    pub app: String,
+    pub summary: String,
+    /// The text under the summary, without markup.
+    pub body: String,
+}
+
+impl Notification {
+    /// The notification as it is read out: "Slack: Anna. Lunch?"
+    pub fn sentence(&self) -> String {
+        let mut parts: Vec<String> = [self.summary.as_str(), self.body.as_str()]
+            .iter()
+            .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                if part.ends_with(|c: char| c.is_ascii_punctuation()) {
+                    part
+                } else {
+                    format!("{part}.")
+                }
+            })
+            .collect();
+        if !self.app.is_empty() {
+            parts.insert(0, format!("{}:", self.app));
+        }
+        parts.join(" ")
+    }
+}
+
+/// Which apps' notifications are kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppFilter {
+    /// Only these apps, or all of them when empty.
+    pub only: Vec<String>,
+    /// Never these apps.
+    pub ignore: Vec<String>,
+}
+
+impl AppFilter {
+    /// The filter in `NOTIFY_APPS` and `NOTIFY_IGNORE_APPS`; Jarvis' own
+    /// notifications are always ignored.
+    pub fn from_env() -> Self {
+        let mut ignore = env_list("NOTIFY_IGNORE_APPS", &[]);
+        ignore.push(APP_NAME.to_string());
+        Self {
+            only: env_list("NOTIFY_APPS", &[]),
+            ignore,
+        }
+    }
+
+    /// Whether notifications of `app` are kept. App names match
+    /// regardless of case.
+    pub fn allows(&self, app: &str) -> bool {
+        let named = |apps: &[String]| apps.iter().any(|name| name.eq_ignore_ascii_case(app));
+        !named(&self.ignore) && (self.only.is_empty() || named(&self.only))
+    }
+}
+
+/// Reads the notifications out of what `dbus-monitor` prints, a line at
+/// a time.
+///
+/// A notification is a call of `Notify`, whose first arguments are the
+/// app name, the id it replaces, the icon, the summary and the body:
+///
+/// ```text
+/// method call time=1718000000.1 sender=:1.87 -> destination=:1.21 serial=9 path=/org/freedesktop/Notifications; interface=org.freedesktop.Notifications; member=Notify
+///    string "Slack"
+///    uint32 0
+///    string "slack"
+///    string "Anna"
+///    string "Lunch?"
+/// ```
+#[derive(Debug, Default)]
+pub struct NotificationParser {
+    /// The arguments read so far, while in a `Notify` call.
+    arguments: Option<Vec<String>>,
+    /// A string that goes on over more lines.
+    partial: Option<String>,
+}
+
+impl NotificationParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read `line`: the notification, once its body has been read.
+    pub fn feed(&mut self, line: &str) -> Option<Notification> {
+        if ["method call", "method return", "signal", "error"]
+            .iter()
+            .any(|kind| line.starts_with(kind))
+        {
+            self.partial = None;
+            self.arguments = (line.contains("interface=org.freedesktop.Notifications;")
+                && line.trim_end().ends_with("member=Notify"))
+            .then(Vec::new);
+            return None;
+        }
+        let arguments = self.arguments.as_mut()?;
+        if let Some(mut partial) = self.partial.take() {
+            partial.push('\n');
+            match line.strip_suffix('"') {
+                Some(end) => {
+                    partial.push_str(end);
+                    arguments.push(partial);
+                }
+                None => {
+                    partial.push_str(line);
+                    self.partial = Some(partial);
+                }
+            }
+        } else {
+            let line = line.trim_start();
+            if let Some(value) = line.strip_prefix("string \"") {
+                match value.strip_suffix('"') {
+                    Some(value) => arguments.push(value.to_string()),
+                    None => self.partial = Some(value.to_string()),
+                }
+            } else if let Some(value) = line.strip_prefix("uint32 ") {
+                arguments.push(value.to_string());
+            }
+        }
+        if arguments.len() < 5 {
+            return None;
+        }
+        let arguments = self.arguments.take()?;
+        Some(Notification {
+            app: arguments[0].clone(),
+            summary: plain_text(&arguments[3]),
+            body: plain_text(&arguments[4]),
+        })
+    }
+}
+
+/// The notifications in `text`, printed by `dbus-monitor`.
+pub fn parse_dbus_monitor(text: &str) -> Vec<Notification> {
+    let mut parser = NotificationParser::new();
+    text.lines().filter_map(|line| parser.feed(line)).collect()
+}
+
+/// `text` without the markup notifications may have.
+fn plain_text(text: &str) -> String {
+    MARKUP
+        .replace_all(text, "")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// The notifications that arrived since the last call, oldest first.
+pub fn take_notifications() -> Vec<Notification> {
+    std::mem::take(&mut *NOTIFICATIONS.lock().unwrap())
+}
+
+fn push_notification(notification: Notification) {
+    let mut waiting = NOTIFICATIONS.lock().unwrap();
+    if waiting.len() >= MAX_WAITING {
+        waiting.remove(0);
+    }
+    waiting.push(notification);
+}
+
+/// Listen for notifications in the background, keeping those `filter`
+/// allows. Only the first call starts a listener.
+pub fn start_listener(filter: AppFilter) {
+    LISTENER.call_once(|| {
+        std::thread::spawn(move || loop {
+            match listen(&filter) {
+                Ok(()) => log::warn!("dbus-monitor stopped; listening again in a moment"),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    log::warn!("Not listening for notifications: dbus-monitor is not installed");
+                    return;
+                }
+                Err(e) => log::warn!("Cannot listen for notifications: {e}"),
+            }
+            std::thread::sleep(Duration::from_secs(5));
+        });
+    });
+}
+
+/// Run `dbus-monitor` until it stops, keeping the notifications `filter`
+/// allows.
+fn listen(filter: &AppFilter) -> std::io::Result<()> {
+    let mut child = Command::new("dbus-monitor")
+        .args([
+            "--session",
+            "type='method_call',interface='org.freedesktop.Notifications',member='Notify'",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut parser = NotificationParser::new();
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            let Some(notification) = parser.feed(&line?) else {
+                continue;
+            };
+            if filter.allows(&notification.app) {
+                log::debug!("Notification from {}", notification.app);
+                push_notification(notification);
+            }
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Shows desktop notifications and reads out those that came in.
+#[derive(Debug, Clone, Default)]
+pub struct NotifyTool {
+    /// Whether notifications are being listened for.
+    listening: bool,
+}
+
+impl NotifyTool {
+    /// The tool, listening for notifications when `NOTIFY_LISTEN` is set.
+    pub fn from_env() -> Self {
+        let listening = env_flag("NOTIFY_LISTEN");
+        if listening {
+            start_listener(AppFilter::from_env());
+        }
+        Self { listening }
+    }
+
+    fn send(&self, title: &str, body: &str, urgent: bool) -> Result<String> {
+        let mut cmd = Command::new("notify-send");
+        cmd.arg(format!("--app-name={APP_NAME}"));
+        if urgent {
+            cmd.arg("--urgency=critical");
+        }
+        cmd.arg("--").arg(title);
+        if !body.is_empty() {
+            cmd.arg(body);
+        }
+        Ok(match output_with_timeout(cmd, TIMEOUT, &mut |_| {}) {
+            Ok(Some(output)) if output.status.success() => "The notification is up.".to_string(),
+            Ok(Some(output)) => format!("notify-send failed: {}", output_text(&output.stderr)),
+            Ok(None) => "notify-send did not answer in time.".to_string(),
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == ErrorKind::NotFound) =>
+            {
+                "notify-send is not installed.".to_string()
+            }
+            Err(e) => return Err(e),
+        })
+    }
+
+    /// Read out the notifications that came in, those of `app` only if
+    /// given; the others wait for the next time.
+    fn read(&self, app: Option<&str>) -> String {
+        if !self.listening {
+            return "I'm not listening for notifications; NOTIFY_LISTEN=1 turns that on."
+                .to_string();
+        }
+        let read: Vec<Notification> = {
+            let mut waiting = NOTIFICATIONS.lock().unwrap();
+            let (read, kept) =
+                std::mem::take(&mut *waiting)
+                    .into_iter()
+                    .partition(|notification| match app {
+                        Some(app) => notification
+                            .app
+                            .to_lowercase()
+                            .contains(&app.to_lowercase()),
+                        None => true,
+                    });
+            *waiting = kept;
+            read
+        };
+        let from = app.map(|app| format!(" from {app}")).unwrap_or_default();
+        match read.len() {
+            0 => format!("There are no new notifications{from}."),
+            1 => format!("One new notification{from}. {}", read[0].sentence()),
+            count => {
+                let sentences: Vec<String> = read.iter().map(Notification::sentence).collect();
+                format!("{count} new notifications{from}.\n{}", sentences.join("\n"))
+            }
+        }
+    }
+}
+
+impl Tool for NotifyTool {
+    fn name(&self) -> &str {
+        "notify"
+    }
+
+    fn description(&self) -> &str {
+        "Use `notify` to show the user a desktop notification, or to read out the notifications that came in, such as for \"any notifications?\"."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["send", "read"] },
+                "title": { "type": "string", "description": "For send: the notification's title." },
+                "body": { "type": "string", "description": "For send: the text under the title." },
+                "urgent": { "type": "boolean", "description": "For send: keep it up until it is dismissed." },
+                "app": { "type": "string", "description": "For read: only the notifications of this app." },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let text = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        match text("action").unwrap_or_default() {
+            "send" => {
+                let Some(title) = text("title").or_else(|| text("body")) else {
+                    return Ok("What should the notification say?".to_string());
+                };
+                let body = text("body")
+                    .filter(|body| *body != title)
+                    .unwrap_or_default();
+                let urgent = arguments
+                    .get("urgent")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                self.send(title, body, urgent)
+            }
+            "read" => Ok(self.read(text("app"))),
+            action => Ok(format!("Unknown notify action '{action}'.")),
+        }
+    }
+}
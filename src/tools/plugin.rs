@@ -0,0 +1,203 @@
+//! Tools loaded from WebAssembly plugins.
+//!
+//! Every `.wasm` file in `~/.jarvis/plugins/` (or `PLUGINS_DIR`) is a tool.
+//! Plugins run in [wasmtime](https://wasmtime.dev) without any imports: no
+//! files, no network and no shell, only the arguments they are given. A
+//! call gets a fresh instance, a memory limit of `PLUGIN_MEMORY_MB`
+//! (default 64) and a fuel budget of `PLUGIN_FUEL` instructions (default
+//! one billion), so a plugin can neither keep state between calls nor
+//! hang Jarvis.
+//!
+//! A plugin exports its `memory` and three functions, exchanging JSON
+//! encoded as UTF-8. Strings returned by the plugin are packed into an
+//! `i64` as `pointer << 32 | length`:
+//!
+//! * `jarvis_alloc(len: i32) -> i32`: room for `len` bytes, where Jarvis
+//!   writes the arguments.
+//! * `jarvis_manifest() -> i64`: the tool as
+//!   `{"name": ..., "description": ..., "parameters": <JSON Schema>}`.
+//! * `jarvis_invoke(ptr: i32, len: i32) -> i64`: run the tool with the
+//!   arguments object at `ptr` and return `{"output": "..."}` or
+//!   `{"error": "..."}`.
+//!
+//! Plugins need Jarvis built with `--features plugins`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use super::Tool;
+use crate::config::env_parse;
+
+/// What a plugin says about itself.
+#[derive(Debug, Clone, Deserialize)]
+struct Manifest {
+    name: String,
+    description: String,
+    #[serde(default = "empty_object")]
+    parameters: Value,
+}
+
+fn empty_object() -> Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
+/// The answer of `jarvis_invoke`.
+#[derive(Debug, Deserialize)]
+struct Outcome {
+    output: Option<String>,
+    error: Option<String>,
+}
+
+/// A tool implemented by a WebAssembly plugin.
+pub struct PluginTool {
+    manifest: Manifest,
+    engine: Engine,
+    module: Module,
+    memory_bytes: usize,
+    fuel: u64,
+    path: PathBuf,
+}
+
+impl PluginTool {
+    /// Compile the plugin at `path` and ask it what it is.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, path)
+            .with_context(|| format!("cannot compile {}", path.display()))?;
+        let mut tool = Self {
+            manifest: Manifest {
+                name: String::new(),
+                description: String::new(),
+                parameters: empty_object(),
+            },
+            engine,
+            module,
+            memory_bytes: env_parse("PLUGIN_MEMORY_MB", 64usize) * 1024 * 1024,
+            fuel: env_parse("PLUGIN_FUEL", 1_000_000_000),
+            path: path.to_path_buf(),
+        };
+        let (mut store, instance) = tool.instantiate()?;
+        let manifest = instance
+            .get_typed_func::<(), i64>(&mut store, "jarvis_manifest")?
+            .call(&mut store, ())
+            .context("jarvis_manifest failed")?;
+        let manifest = read_string(&mut store, &instance, manifest)?;
+        tool.manifest = serde_json::from_str(&manifest).context("invalid manifest")?;
+        if tool.manifest.name.trim().is_empty() {
+            bail!("the plugin has no name");
+        }
+        Ok(tool)
+    }
+
+    /// A fresh instance of the plugin with its limits.
+    fn instantiate(&self) -> Result<(Store<StoreLimits>, Instance)> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.memory_bytes)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel)?;
+        // No imports: a plugin that needs any cannot be instantiated.
+        let instance = Instance::new(&mut store, &self.module, &[])
+            .context("plugins may not import anything")?;
+        Ok((store, instance))
+    }
+}
+
+impl Tool for PluginTool {
+    fn name(&self) -> &str {
+        &self.manifest.name
+    }
+
+    fn description(&self) -> &str {
+        &self.manifest.description
+    }
+
+    fn parameters(&self) -> Value {
+        self.manifest.parameters.clone()
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let (mut store, instance) = self.instantiate()?;
+        let input = arguments.to_string();
+        let len = i32::try_from(input.len()).context("arguments too long")?;
+        let ptr = instance
+            .get_typed_func::<i32, i32>(&mut store, "jarvis_alloc")?
+            .call(&mut store, len)
+            .context("jarvis_alloc failed")?;
+        memory(&mut store, &instance)?
+            .write(&mut store, ptr as u32 as usize, input.as_bytes())
+            .context("jarvis_alloc returned memory out of bounds")?;
+        let packed = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, "jarvis_invoke")?
+            .call(&mut store, (ptr, len))
+            .with_context(|| format!("plugin {} failed or ran out of fuel", self.path.display()))?;
+        let outcome: Outcome = serde_json::from_str(&read_string(&mut store, &instance, packed)?)
+            .context("invalid answer from jarvis_invoke")?;
+        match (outcome.error, outcome.output) {
+            (Some(error), _) => Err(anyhow!(error)),
+            (None, Some(output)) => Ok(output),
+            (None, None) => Ok(format!("{} finished without output.", self.manifest.name)),
+        }
+    }
+}
+
+/// The plugin's exported memory.
+fn memory(store: &mut Store<StoreLimits>, instance: &Instance) -> Result<wasmtime::Memory> {
+    instance
+        .get_memory(&mut *store, "memory")
+        .ok_or_else(|| anyhow!("the plugin does not export its memory"))
+}
+
+/// The string at the packed pointer and length `packed`.
+fn read_string(store: &mut Store<StoreLimits>, instance: &Instance, packed: i64) -> Result<String> {
+    let ptr = (packed as u64 >> 32) as usize;
+    let len = (packed as u64 & 0xffff_ffff) as usize;
+    let memory = memory(store, instance)?;
+    let bytes = memory
+        .data(&*store)
+        .get(ptr..ptr + len)
+        .ok_or_else(|| anyhow!("the plugin returned memory out of bounds"))?;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// `PLUGINS_DIR`, default `~/.jarvis/plugins`.
+pub fn plugins_dir() -> Option<PathBuf> {
+    std::env::var("PLUGINS_DIR")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("plugins")))
+}
+
+/// The plugins in [`plugins_dir`]. Plugins that cannot be loaded are
+/// logged and skipped.
+pub fn load_all() -> Vec<PluginTool> {
+    let Some(entries) = plugins_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|path| match PluginTool::load(&path) {
+            Ok(tool) => {
+                log::info!("Loaded plugin '{}' from {}", tool.name(), path.display());
+                Some(tool)
+            }
+            Err(e) => {
+                log::warn!("Ignoring plugin {}: {e:#}", path.display());
+                None
+            }
+        })
+        .collect()
+}
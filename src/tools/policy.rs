@@ -0,0 +1,241 @@
+//! Limits on the shell commands the model may run.
+//!
+//! `~/.jarvis/policy.toml` (or the file named by `POLICY_FILE`) says which
+//! programs, paths and patterns the commands of `shell_task` and
+//! `codex_cli_task` may use:
+//!
+//! ```toml
+//! mode = "confirm"                  # open, confirm or strict
+//! allow = ["ls", "df", "cat", "git", "grep"]
+//! deny = ["curl", "wget", "nc"]
+//! deny_patterns = ['\bsudo\b', '>\s*/etc/']
+//! paths = ["~/projects", "/tmp"]
+//! ```
+//!
+//! A command using a program in `deny` or matching one of the
+//! `deny_patterns` never runs. What happens to the rest depends on the
+//! mode: `open` runs them, `confirm` waits for "yes, confirm" before
+//! running a program that is not in `allow` or a path outside `paths`,
+//! and `strict` refuses those commands. An empty `allow` allows no
+//! program, an empty `paths` any path.
+//!
+//! Without a policy file every command is open, as before. A policy file
+//! that cannot be read is logged and treated as `mode = "confirm"` with
+//! nothing allowed, rather than as no policy at all.
+
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::expand_home;
+
+/// Where one command ends and the next starts: `;`, `&&`, `||`, pipes,
+/// line breaks, subshells and command substitutions.
+static SEPARATOR: Lazy<Regex> = Lazy::new(|| Regex::new(r"[;&|\n()`]|\$\(").unwrap());
+
+/// Redirections that use `&` without ending a command: `2>&1`, `&>`.
+static REDIRECT: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d*[<>]&\d*-?|&>>?").unwrap());
+
+/// Programs that run the program named after them; a shell runs the one
+/// in the command after its `-c`.
+const WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nohup", "time", "nice", "exec", "command", "xargs", "sh", "bash",
+    "dash", "zsh",
+];
+
+/// Shell keywords and braces that may come before the program of a
+/// command.
+const KEYWORDS: &[&str] = &[
+    "{", "}", "!", "if", "then", "else", "elif", "fi", "while", "until", "do", "done",
+];
+
+/// How commands outside the allowed programs and paths are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// They run.
+    #[default]
+    Open,
+    /// They wait for "yes, confirm".
+    Confirm,
+    /// They are refused.
+    Strict,
+}
+
+/// The policy as written in `policy.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    mode: Mode,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    #[serde(default)]
+    deny_patterns: Vec<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+/// What the policy says about a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// It may run after "yes, confirm", for this reason.
+    Confirm(String),
+    /// It may not run, for this reason.
+    Deny(String),
+}
+
+/// Decides which shell commands may run.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    mode: Mode,
+    allow: Vec<String>,
+    deny: Vec<String>,
+    deny_patterns: Vec<Regex>,
+    paths: Vec<PathBuf>,
+}
+
+impl Policy {
+    /// The policy in `POLICY_FILE`, default `~/.jarvis/policy.toml`.
+    pub fn from_env() -> Self {
+        policy_file()
+            .map(|path| Self::load_file(&path))
+            .unwrap_or_default()
+    }
+
+    /// The policy in `path`; an open one if it does not exist.
+    pub fn load_file(path: &Path) -> Self {
+        let Ok(data) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        let file: PolicyFile = match toml::from_str(&data) {
+            Ok(file) => file,
+            Err(e) => {
+                log::warn!(
+                    "Invalid shell policy {}: {e}; asking before every command",
+                    path.display()
+                );
+                return Self {
+                    mode: Mode::Confirm,
+                    ..Self::default()
+                };
+            }
+        };
+        let deny_patterns = file
+            .deny_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    log::warn!("Ignoring denied pattern '{pattern}': {e}");
+                    None
+                }
+            })
+            .collect();
+        log::info!(
+            "Shell policy from {}: {:?}, {} allowed and {} denied programs",
+            path.display(),
+            file.mode,
+            file.allow.len(),
+            file.deny.len()
+        );
+        Self {
+            mode: file.mode,
+            allow: file.allow,
+            deny: file.deny,
+            deny_patterns,
+            paths: file.paths.iter().map(|path| expand_home(path)).collect(),
+        }
+    }
+
+    /// What may be done with `command`.
+    pub fn check(&self, command: &str) -> Verdict {
+        if let Some(pattern) = self.deny_patterns.iter().find(|p| p.is_match(command)) {
+            return Verdict::Deny(format!("it matches the denied pattern '{pattern}'"));
+        }
+        let programs = programs(command);
+        if let Some(program) = programs.iter().find(|program| self.deny.contains(*program)) {
+            return Verdict::Deny(format!("{program} is not allowed"));
+        }
+        if self.mode == Mode::Open {
+            return Verdict::Allow;
+        }
+        let problem = programs
+            .iter()
+            .find(|program| !self.allow.contains(*program))
+            .map(|program| format!("{program} is not on the allow list"))
+            .or_else(|| {
+                self.outside_paths(command)
+                    .map(|path| format!("{path} is outside the allowed paths"))
+            });
+        match (problem, self.mode) {
+            (None, _) => Verdict::Allow,
+            (Some(problem), Mode::Strict) => Verdict::Deny(problem),
+            (Some(problem), _) => Verdict::Confirm(problem),
+        }
+    }
+
+    /// The first absolute or home path in `command` outside the allowed
+    /// paths.
+    fn outside_paths<'a>(&self, command: &'a str) -> Option<&'a str> {
+        if self.paths.is_empty() {
+            return None;
+        }
+        command
+            .split(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '<' | ';' | '|'))
+            .map(|word| word.trim_matches(['"', '\'']))
+            .filter(|word| word.starts_with('/') || word.starts_with('~'))
+            .filter(|word| *word != "/dev/null")
+            .find(|word| {
+                let path = expand_home(word);
+                // `..` could climb out of an allowed directory.
+                let climbs = path.components().any(|c| c.as_os_str() == "..");
+                climbs || !self.paths.iter().any(|allowed| path.starts_with(allowed))
+            })
+    }
+}
+
+/// The programs `command` runs, by their file name: the first word of
+/// every command in it after keywords such as `if` and `{`, and the
+/// program run by `sudo`, `env`, `bash -c` and the like.
+pub fn programs(command: &str) -> Vec<String> {
+    let mut programs = Vec::new();
+    let command = REDIRECT.replace_all(command, " ");
+    for part in SEPARATOR.split(&command) {
+        let words = part
+            .split_whitespace()
+            .map(|word| word.trim_matches(['"', '\'']))
+            .filter(|word| !word.is_empty());
+        let mut wrapped = false;
+        for word in words {
+            // Keywords, variable assignments and a wrapper's options.
+            if KEYWORDS.contains(&word)
+                || (word.contains('=') && !word.starts_with('='))
+                || (wrapped && word.starts_with('-'))
+            {
+                continue;
+            }
+            let name = word.rsplit('/').next().unwrap_or(word).to_string();
+            let wrapper = WRAPPERS.contains(&name.as_str());
+            programs.push(name);
+            if !wrapper {
+                break;
+            }
+            wrapped = true;
+        }
+    }
+    programs
+}
+
+/// `POLICY_FILE`, default `~/.jarvis/policy.toml`.
+fn policy_file() -> Option<PathBuf> {
+    std::env::var("POLICY_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("policy.toml")))
+}
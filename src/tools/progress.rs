@@ -0,0 +1,61 @@
+//! Telling the user how a long-running tool is getting on.
+//!
+//! `shell_task`, `codex_cli_task` and the manifest tools pass on each
+//! line they print while they run, and every line is published as a
+//! [`JarvisEvent::ToolOutput`](crate::events::JarvisEvent::ToolOutput)
+//! for user interfaces. With `TOOL_PROGRESS_SECS` set, the latest line is
+//! also spoken at most that often ("Still working: copying photos"), so
+//! that a long command does not leave the user in silence.
+
+use std::time::{Duration, Instant};
+
+use super::untrusted;
+use crate::config::env_parse;
+
+/// Characters of a line quoted in a milestone.
+const LINE_CHARS: usize = 80;
+
+/// `TOOL_PROGRESS_SECS` as an interval; `None` (the default, or 0)
+/// keeps running tools quiet.
+pub fn interval_from_env() -> Option<Duration> {
+    match env_parse("TOOL_PROGRESS_SECS", 0u64) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Picks the lines of one tool run worth saying.
+#[derive(Debug)]
+pub struct Narrator {
+    every: Option<Duration>,
+    last: Instant,
+}
+
+impl Narrator {
+    /// A narrator for a tool starting now, speaking at most once
+    /// `every` interval; never without one.
+    pub fn new(every: Option<Duration>) -> Self {
+        Self {
+            every,
+            last: Instant::now(),
+        }
+    }
+
+    /// The milestone to say for `line`, if one is due.
+    pub fn line(&mut self, line: &str) -> Option<String> {
+        let every = self.every?;
+        let line = untrusted::sanitize(line);
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() || self.last.elapsed() < every {
+            return None;
+        }
+        self.last = Instant::now();
+        let quoted = if line.chars().count() > LINE_CHARS {
+            let cut: String = line.chars().take(LINE_CHARS).collect();
+            format!("{}…", cut.trim_end())
+        } else {
+            line
+        };
+        Some(format!("Still working: {quoted}"))
+    }
+}
@@ -0,0 +1,250 @@
+//! Commands on the user's other machines over SSH.
+//!
+//! `remote_task` runs a command on one of the hosts in
+//! `~/.jarvis/remote.toml` (or the file named by `REMOTE_HOSTS_FILE`), so
+//! "check disk space on the NAS" runs `df -h` there. Only the commands
+//! listed for every host or for that host are run:
+//!
+//! ```toml
+//! allow = ["df -h", "uptime", "free -h"]   # on every host
+//! timeout_secs = 30
+//!
+//! [hosts.nas]
+//! address = "192.168.1.10"
+//! user = "admin"
+//! key = "~/.ssh/id_nas"                    # default ssh's own choice
+//! port = 22
+//! allow = ["zpool status", "systemctl status *"]
+//! ```
+//!
+//! A pattern ending in `*` allows any arguments after it, but not the
+//! characters the remote shell would use to run more than one command
+//! (`;`, `|`, `&`, `$`, backquotes, redirections, newlines); other
+//! patterns must match the whole command. `ssh` runs in batch mode, so a
+//! host that asks for a password fails instead of waiting. Without hosts
+//! there is no tool.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    expand_home, max_output_bytes, output_text, output_with_timeout, truncate_bytes, Tool,
+};
+
+/// Characters that would let one allowed command run another.
+const SHELL_CHARACTERS: &[char] = &[';', '|', '&', '$', '`', '>', '<', '\n', '\r', '(', ')'];
+
+/// The hosts as written in `remote.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemoteFile {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default = "default_timeout_secs")]
+    timeout_secs: u64,
+    #[serde(default)]
+    hosts: BTreeMap<String, Host>,
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+/// A machine and how to log in to it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Host {
+    address: String,
+    user: Option<String>,
+    key: Option<String>,
+    port: Option<u16>,
+    #[serde(default)]
+    allow: Vec<String>,
+}
+
+/// Runs allowed commands on the configured hosts.
+#[derive(Debug, Clone)]
+pub struct RemoteTool {
+    allow: Vec<String>,
+    hosts: BTreeMap<String, Host>,
+    timeout: Duration,
+}
+
+impl RemoteTool {
+    /// The hosts in `REMOTE_HOSTS_FILE`, default `~/.jarvis/remote.toml`;
+    /// `None` without any.
+    pub fn from_env() -> Option<Self> {
+        let path = remote_file()?;
+        if !path.exists() {
+            return None;
+        }
+        match Self::load_file(&path) {
+            Ok(tool) if !tool.hosts.is_empty() => Some(tool),
+            Ok(_) => None,
+            Err(e) => {
+                log::warn!("Ignoring remote hosts file {}: {e:#}", path.display());
+                None
+            }
+        }
+    }
+
+    /// The hosts in `path`.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).context("cannot read it")?;
+        let file: RemoteFile = toml::from_str(&data)?;
+        Ok(Self {
+            allow: file.allow,
+            hosts: file
+                .hosts
+                .into_iter()
+                .map(|(name, host)| (name.to_lowercase(), host))
+                .collect(),
+            timeout: Duration::from_secs(file.timeout_secs),
+        })
+    }
+
+    /// The `ssh` command line that runs `command` on the host called
+    /// `name`, or why it may not.
+    pub fn ssh_argv(&self, name: &str, command: &str) -> Result<Vec<String>, String> {
+        let name = name.trim().to_lowercase();
+        let name = name.strip_prefix("the ").unwrap_or(&name);
+        let Some(host) = self.hosts.get(name) else {
+            let known: Vec<&str> = self.hosts.keys().map(String::as_str).collect();
+            return Err(format!(
+                "There is no host called {name}; I know {}.",
+                known.join(", ")
+            ));
+        };
+        let command = command.trim();
+        let patterns = self.allow.iter().chain(&host.allow);
+        if !patterns.clone().any(|pattern| allows(pattern, command)) {
+            let listed: Vec<&str> = patterns.map(String::as_str).collect();
+            return Err(format!(
+                "'{command}' is not allowed on {name}. Allowed: {}.",
+                listed.join(", ")
+            ));
+        }
+        let mut argv = vec![
+            "ssh".to_string(),
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+            "-o".to_string(),
+            "ConnectTimeout=10".to_string(),
+        ];
+        if let Some(key) = &host.key {
+            argv.push("-i".to_string());
+            argv.push(expand_home(key).display().to_string());
+        }
+        if let Some(port) = host.port {
+            argv.push("-p".to_string());
+            argv.push(port.to_string());
+        }
+        argv.push(match &host.user {
+            Some(user) => format!("{user}@{}", host.address),
+            None => host.address.clone(),
+        });
+        argv.push("--".to_string());
+        argv.push(command.to_string());
+        Ok(argv)
+    }
+}
+
+impl Tool for RemoteTool {
+    fn name(&self) -> &str {
+        "remote_task"
+    }
+
+    fn description(&self) -> &str {
+        "Use `remote_task` to run an allowed command, such as checking disk space, on one of the user's other machines over SSH."
+    }
+
+    fn parameters(&self) -> Value {
+        let hosts: Vec<String> = self
+            .hosts
+            .iter()
+            .map(|(name, host)| {
+                let allowed: Vec<&str> = self
+                    .allow
+                    .iter()
+                    .chain(&host.allow)
+                    .map(String::as_str)
+                    .collect();
+                format!("{name} ({})", allowed.join(", "))
+            })
+            .collect();
+        json!({
+            "type": "object",
+            "properties": {
+                "host": {
+                    "type": "string",
+                    "description": format!("One of these hosts, with its allowed commands: {}.", hosts.join("; ")),
+                },
+                "command": { "type": "string", "description": "One of the host's allowed commands." },
+            },
+            "required": ["host", "command"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let argument = |name: &str| arguments.get(name).and_then(Value::as_str);
+        let (Some(host), Some(command)) = (argument("host"), argument("command")) else {
+            return Ok("No host or command provided.".to_string());
+        };
+        let argv = match self.ssh_argv(host, command) {
+            Ok(argv) => argv,
+            Err(problem) => return Ok(problem),
+        };
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]);
+        Ok(match output_with_timeout(cmd, self.timeout, &mut |_| {})? {
+            Some(output) if output.status.success() => {
+                let text = output_text(&output.stdout);
+                if text.is_empty() {
+                    format!("'{command}' ran on {host} with no output.")
+                } else {
+                    truncate_bytes(&text, max_output_bytes())
+                }
+            }
+            // ssh exits with 255 when it could not connect at all.
+            Some(output) if output.status.code() == Some(255) => format!(
+                "I couldn't connect to {host}: {}",
+                output_text(&output.stderr)
+            ),
+            Some(output) => format!(
+                "'{command}' failed on {host}: {}",
+                output_text(&output.stderr)
+            ),
+            None => format!("'{command}' on {host} did not finish in time."),
+        })
+    }
+}
+
+/// Whether `pattern` allows `command`: the same words, or, for a
+/// pattern ending in `*`, those words followed by any arguments without
+/// shell characters.
+pub fn allows(pattern: &str, command: &str) -> bool {
+    let words = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let command = words(command);
+    match pattern.trim().strip_suffix('*') {
+        Some(prefix) => {
+            let prefix = words(prefix);
+            let matches = command == prefix || command.starts_with(&format!("{prefix} "));
+            matches && !command.contains(SHELL_CHARACTERS)
+        }
+        None => command == words(pattern),
+    }
+}
+
+/// `REMOTE_HOSTS_FILE`, default `~/.jarvis/remote.toml`.
+fn remote_file() -> Option<PathBuf> {
+    std::env::var("REMOTE_HOSTS_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("remote.toml")))
+}
@@ -0,0 +1,186 @@
+//! Running the model's shell commands in a sandbox.
+//!
+//! With `SHELL_SANDBOX` set to `bwrap`, `firejail` or `systemd-run`, the
+//! commands of `shell_task` and `codex_cli_task` run under bubblewrap,
+//! firejail or `systemd-run --user`. Inside, the whole file system is
+//! read-only except for the working directory, `/tmp` and the paths in
+//! `SANDBOX_WRITABLE` (comma separated, `~` for the home directory), so a
+//! command cannot change anything outside the workspace.
+//! `SANDBOX_NETWORK=0` cuts it off from the network as well.
+//!
+//! An unknown `SHELL_SANDBOX` makes the commands fail rather than run
+//! without a sandbox. Tools from `tools.toml` and intents are yours and
+//! run as they are.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+use super::expand_home;
+use crate::config::{env_flag_or, env_list};
+
+/// The program a sandbox is made with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Bubblewrap,
+    Firejail,
+    SystemdRun,
+}
+
+impl FromStr for Kind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "bwrap" | "bubblewrap" => Ok(Kind::Bubblewrap),
+            "firejail" => Ok(Kind::Firejail),
+            "systemd-run" | "systemd" => Ok(Kind::SystemdRun),
+            other => bail!("unknown SHELL_SANDBOX '{other}' (use bwrap, firejail or systemd-run)"),
+        }
+    }
+}
+
+/// Where and how commands are confined.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    kind: Kind,
+    /// Paths commands may change besides the working directory.
+    writable: Vec<PathBuf>,
+    network: bool,
+}
+
+impl Sandbox {
+    pub fn new(kind: Kind, writable: Vec<PathBuf>, network: bool) -> Self {
+        Self {
+            kind,
+            writable,
+            network,
+        }
+    }
+
+    /// The sandbox configured by `SHELL_SANDBOX`, `SANDBOX_WRITABLE` and
+    /// `SANDBOX_NETWORK`; `None` if commands run as they are.
+    pub fn from_env() -> Result<Option<Self>> {
+        let kind = std::env::var("SHELL_SANDBOX").unwrap_or_default();
+        if matches!(kind.trim(), "" | "0" | "none" | "off") {
+            return Ok(None);
+        }
+        let mut writable = vec![PathBuf::from("/tmp")];
+        writable.extend(
+            env_list("SANDBOX_WRITABLE", &[])
+                .iter()
+                .map(|path| expand_home(path)),
+        );
+        Ok(Some(Self::new(
+            kind.parse()?,
+            writable,
+            env_flag_or("SANDBOX_NETWORK", true),
+        )))
+    }
+
+    /// The program and arguments that run `command`, a shell and its
+    /// arguments, in the sandbox with the variables `env`, from `cwd`,
+    /// which is writable too. Writable paths that do not exist are left
+    /// out.
+    pub fn argv(
+        &self,
+        command: &[String],
+        env: &[(String, String)],
+        cwd: Option<&Path>,
+    ) -> Vec<String> {
+        let mut writable: Vec<&Path> = cwd.into_iter().collect();
+        for path in &self.writable {
+            if !writable.contains(&path.as_path()) {
+                writable.push(path);
+            }
+        }
+        let writable = writable
+            .into_iter()
+            .filter(|path| path.exists())
+            .map(|path| path.display().to_string());
+        let mut argv: Vec<String> = Vec::new();
+        match self.kind {
+            Kind::Bubblewrap => {
+                argv.extend(
+                    [
+                        "bwrap",
+                        "--ro-bind",
+                        "/",
+                        "/",
+                        "--dev",
+                        "/dev",
+                        "--proc",
+                        "/proc",
+                        "--die-with-parent",
+                    ]
+                    .map(String::from),
+                );
+                if !self.network {
+                    argv.push("--unshare-net".to_string());
+                }
+                for path in writable {
+                    argv.extend(["--bind".to_string(), path.clone(), path]);
+                }
+                if let Some(cwd) = cwd {
+                    argv.extend(["--chdir".to_string(), cwd.display().to_string()]);
+                }
+            }
+            Kind::Firejail => {
+                argv.extend(["firejail", "--quiet", "--read-only=/"].map(String::from));
+                if !self.network {
+                    argv.push("--net=none".to_string());
+                }
+                argv.extend(writable.map(|path| format!("--read-write={path}")));
+            }
+            Kind::SystemdRun => {
+                argv.extend(
+                    [
+                        "systemd-run",
+                        "--user",
+                        "--pipe",
+                        "--wait",
+                        "--quiet",
+                        "--collect",
+                        "--same-dir",
+                        "-p",
+                        "ProtectSystem=strict",
+                        "-p",
+                        "ProtectHome=read-only",
+                    ]
+                    .map(String::from),
+                );
+                if !self.network {
+                    argv.extend(["-p".to_string(), "PrivateNetwork=yes".to_string()]);
+                }
+                for path in writable {
+                    argv.extend(["-p".to_string(), format!("ReadWritePaths={path}")]);
+                }
+                // The unit does not inherit the environment.
+                argv.extend(
+                    env.iter()
+                        .map(|(name, value)| format!("--setenv={name}={value}")),
+                );
+            }
+        }
+        argv.extend(command.iter().cloned());
+        argv
+    }
+
+    /// `command` run in the sandbox with `env` from `cwd`.
+    pub fn command(
+        &self,
+        command: &[String],
+        env: &[(String, String)],
+        cwd: Option<&Path>,
+    ) -> Command {
+        let argv = self.argv(command, env, cwd);
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]).envs(env.iter().cloned());
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        cmd
+    }
+}
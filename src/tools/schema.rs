@@ -0,0 +1,117 @@
+//! Checking tool arguments against a tool's
+//! [`parameters`](super::Tool::parameters) schema before the tool runs.
+//!
+//! Only the parts of JSON Schema that tool definitions use are checked:
+//! `type`, `properties`, `required`, `additionalProperties: false`, `enum`
+//! and array `items`. The problems found are reported to the model so that
+//! it can correct its call.
+
+use serde_json::Value;
+
+/// Check `arguments` against `schema`. On failure the problems are
+/// returned in one sentence, e.g. "missing required field 'command'".
+pub fn validate(schema: &Value, arguments: &Value) -> Result<(), String> {
+    let mut problems = Vec::new();
+    check(schema, arguments, "arguments", &mut problems);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+fn check(schema: &Value, value: &Value, path: &str, problems: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            problems.push(format!(
+                "{path} should be {} but is {}",
+                types
+                    .iter()
+                    .map(|t| with_article(t))
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            problems.push(format!("{path} must be one of {}", allowed.join(", ")));
+        }
+    }
+    match value {
+        Value::Object(fields) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for required in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !fields.contains_key(required) {
+                    problems.push(format!("missing required field '{required}'"));
+                }
+            }
+            for (name, field) in fields {
+                match properties.and_then(|p| p.get(name)) {
+                    Some(field_schema) => {
+                        check(field_schema, field, &format!("'{name}'"), problems)
+                    }
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        problems.push(format!("unknown field '{name}'"));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{path}[{i}]"), problems);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        // Types this checker does not know are accepted.
+        _ => true,
+    }
+}
+
+fn with_article(type_name: &str) -> String {
+    match type_name {
+        "object" | "array" | "integer" => format!("an {type_name}"),
+        "null" => type_name.to_string(),
+        _ => format!("a {type_name}"),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
@@ -0,0 +1,170 @@
+//! Reading the screen.
+//!
+//! `screen_read` takes a screenshot and reads the text on it with
+//! `tesseract`, so "what does the error dialog on my screen say?" can be
+//! answered from what is actually there. The screenshot is taken with
+//! `grim` (wlroots Wayland compositors), `spectacle` (KDE) or `scrot`
+//! (X11), whichever is installed, `grim` first under Wayland, or with the
+//! one named by `SCREENSHOT_TOOL`. `OCR_LANGUAGES` (default `eng`) are
+//! the tesseract languages, such as `eng+hrv`. The screenshot is deleted
+//! as soon as it has been read. `SCREEN_READ=0` leaves the tool out.
+
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{max_output_bytes, output_text, output_with_timeout, truncate_bytes, Tool};
+
+/// How long taking the screenshot or reading it may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The programs that can take a screenshot.
+const SCREENSHOT_TOOLS: [&str; 3] = ["grim", "spectacle", "scrot"];
+
+fn argv(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+/// The commands that save a screenshot of the whole screen to `path`, in
+/// the order they are tried: `preferred` alone if it is one of them, else
+/// `grim` first under Wayland and last otherwise.
+pub fn capture_commands(path: &Path, wayland: bool, preferred: Option<&str>) -> Vec<Vec<String>> {
+    let file = path.display().to_string();
+    let command = |program: &str| match program {
+        "grim" => argv(&["grim", &file]),
+        "spectacle" => argv(&[
+            "spectacle",
+            "--background",
+            "--nonotify",
+            "--fullscreen",
+            "--output",
+            &file,
+        ]),
+        _ => argv(&["scrot", "--overwrite", &file]),
+    };
+    if let Some(program) = preferred.filter(|program| SCREENSHOT_TOOLS.contains(program)) {
+        return vec![command(program)];
+    }
+    let order = if wayland {
+        ["grim", "spectacle", "scrot"]
+    } else {
+        ["scrot", "spectacle", "grim"]
+    };
+    order.into_iter().map(command).collect()
+}
+
+/// The command that prints the text in the image at `path`.
+pub fn ocr_command(path: &Path, languages: &str) -> Vec<String> {
+    let file = path.display().to_string();
+    argv(&["tesseract", &file, "stdout", "-l", languages])
+}
+
+/// Tesseract's output without the lines that are only specks and
+/// borders read as punctuation, and with runs of blank lines collapsed.
+pub fn clean_ocr(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        let readable = line.chars().filter(|c| c.is_alphanumeric()).count() >= 2;
+        if readable {
+            lines.push(line);
+        } else if lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push("");
+        }
+    }
+    lines.join("\n").trim().to_string()
+}
+
+/// Run `argv`: its output, or the problem in words; an error of kind
+/// `NotFound` if it is not installed.
+fn run(argv: &[String]) -> Result<Result<String, String>> {
+    let mut cmd = Command::new(&argv[0]);
+    cmd.args(&argv[1..]);
+    Ok(match output_with_timeout(cmd, TIMEOUT, &mut |_| {})? {
+        Some(output) if output.status.success() => Ok(output_text(&output.stdout)),
+        Some(output) => Err(format!(
+            "{} failed: {}",
+            argv[0],
+            output_text(&output.stderr)
+        )),
+        None => Err(format!("{} did not finish in time.", argv[0])),
+    })
+}
+
+fn not_installed(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::NotFound)
+}
+
+/// Reads the text on the screen.
+pub struct ScreenReadTool;
+
+impl ScreenReadTool {
+    /// Save a screenshot to `path` with the first installed program.
+    fn capture(&self, path: &Path) -> Result<Result<(), String>> {
+        let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+        let preferred = std::env::var("SCREENSHOT_TOOL").ok();
+        let commands = capture_commands(path, wayland, preferred.as_deref().map(str::trim));
+        let programs: Vec<&str> = commands.iter().map(|argv| argv[0].as_str()).collect();
+        for argv in &commands {
+            match run(argv) {
+                Ok(outcome) => return Ok(outcome.map(|_| ())),
+                Err(e) if not_installed(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Err(format!(
+            "I can't take a screenshot: none of {} is installed.",
+            programs.join(", ")
+        )))
+    }
+}
+
+impl Tool for ScreenReadTool {
+    fn name(&self) -> &str {
+        "screen_read"
+    }
+
+    fn description(&self) -> &str {
+        "Use `screen_read` to read the text currently shown on the user's screen, such as an error dialog."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({ "type": "object", "properties": {} })
+    }
+
+    fn run(&self, _arguments: &Value) -> Result<String> {
+        let path = std::env::temp_dir().join(format!("jarvis-screen-{}.png", std::process::id()));
+        let captured = self.capture(&path);
+        let read = match captured {
+            Ok(Ok(())) => {
+                let languages =
+                    std::env::var("OCR_LANGUAGES").unwrap_or_else(|_| "eng".to_string());
+                match run(&ocr_command(&path, languages.trim())) {
+                    Err(e) if not_installed(&e) => Ok(Err(
+                        "I can't read the screen: tesseract is not installed.".to_string(),
+                    )),
+                    other => other,
+                }
+            }
+            other => other.map(|outcome| outcome.map(|_| String::new())),
+        };
+        // The screenshot may show anything; don't leave it lying around.
+        let _ = std::fs::remove_file(&path);
+        Ok(match read? {
+            Ok(text) => {
+                let text = clean_ocr(&text);
+                if text.is_empty() {
+                    "I couldn't make out any text on the screen.".to_string()
+                } else {
+                    truncate_bytes(&text, max_output_bytes())
+                }
+            }
+            Err(problem) => problem,
+        })
+    }
+}
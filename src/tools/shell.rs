@@ -0,0 +1,110 @@
+//! The shell that runs the commands of the shell tools.
+//!
+//! Commands run with `sh -c` (`cmd /C` on Windows) unless `TOOL_SHELL`
+//! names another shell, such as `bash`, `zsh` or `fish`.
+//! `TOOL_SHELL_LOGIN=1` starts it as a login shell, which reads
+//! `~/.profile`, `~/.zprofile` and the like, and `TOOL_SHELL_INTERACTIVE=1`
+//! as an interactive one, which reads `~/.bashrc` or `~/.zshrc`, so that
+//! the aliases and `PATH` entries set up there work. `TOOL_ENV` adds
+//! environment variables as comma separated `NAME=value` pairs.
+
+use std::path::Path;
+
+use crate::config::{env_flag, env_list};
+
+/// A shell and how it is started.
+#[derive(Debug, Clone)]
+pub struct Shell {
+    program: String,
+    login: bool,
+    interactive: bool,
+    env: Vec<(String, String)>,
+}
+
+impl Shell {
+    pub fn new(program: &str, login: bool, interactive: bool, env: Vec<(String, String)>) -> Self {
+        Self {
+            program: program.to_string(),
+            login,
+            interactive,
+            env,
+        }
+    }
+
+    /// The shell configured by `TOOL_SHELL`, `TOOL_SHELL_LOGIN`,
+    /// `TOOL_SHELL_INTERACTIVE` and `TOOL_ENV`, with the variables of the
+    /// active project (see [`crate::project`]).
+    pub fn from_env() -> Self {
+        let default = if cfg!(target_os = "windows") {
+            "cmd"
+        } else {
+            "sh"
+        };
+        let program = std::env::var("TOOL_SHELL")
+            .ok()
+            .filter(|shell| !shell.trim().is_empty())
+            .unwrap_or_else(|| default.to_string());
+        let mut env: Vec<(String, String)> = env_list("TOOL_ENV", &[])
+            .into_iter()
+            .filter_map(|pair| match pair.split_once('=') {
+                Some((name, value)) if !name.trim().is_empty() => {
+                    Some((name.trim().to_string(), value.to_string()))
+                }
+                _ => {
+                    log::warn!("Ignoring TOOL_ENV entry '{pair}': expected NAME=value");
+                    None
+                }
+            })
+            .collect();
+        env.extend(crate::project::tool_env());
+        Self::new(
+            program.trim(),
+            env_flag("TOOL_SHELL_LOGIN"),
+            env_flag("TOOL_SHELL_INTERACTIVE"),
+            env,
+        )
+    }
+
+    /// The program and arguments that run `line`.
+    pub fn argv(&self, line: &str) -> Vec<String> {
+        let name = Path::new(&self.program)
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name == "cmd" {
+            return vec![self.program.clone(), "/C".to_string(), line.to_string()];
+        }
+        let mut argv = vec![self.program.clone()];
+        if self.login {
+            argv.push("-l".to_string());
+        }
+        if self.interactive {
+            argv.push("-i".to_string());
+        }
+        argv.extend(["-c".to_string(), line.to_string()]);
+        argv
+    }
+
+    /// The program and arguments that start the shell to read commands
+    /// from a terminal, as for a [`shell_session`](super::shell_session).
+    pub fn session_argv(&self) -> Vec<String> {
+        let mut argv = vec![self.program.clone()];
+        if self.login {
+            argv.push("-l".to_string());
+        }
+        argv
+    }
+
+    /// Whether this is the fish shell, whose syntax differs from the
+    /// POSIX shells.
+    pub fn is_fish(&self) -> bool {
+        Path::new(&self.program)
+            .file_stem()
+            .is_some_and(|name| name == "fish")
+    }
+
+    /// The environment variables added for commands.
+    pub fn env(&self) -> &[(String, String)] {
+        &self.env
+    }
+}
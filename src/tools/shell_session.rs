@@ -0,0 +1,322 @@
+//! A shell that lives from one command to the next.
+//!
+//! `shell_task` starts a new shell for every command, so only `cd` is
+//! carried over. `persistent_shell_task` keeps one shell running on a
+//! pseudo-terminal instead: variables exported, virtualenvs activated and
+//! directories changed by one command are still there for the next. The
+//! shell is the one configured for the shell tools (see [`super::shell`]),
+//! started in the working directory, and runs in the sandbox if there is
+//! one. Saying "reset shell" ends it; the next command starts a fresh one.
+//!
+//! After each command the shell prints a marker line with the exit
+//! status, which is how Jarvis knows the command is done. A command still
+//! running after `SHELL_TIMEOUT_SECS` is interrupted with Ctrl-C; if the
+//! shell does not come back, it is killed and replaced. `SHELL_SESSION=0`
+//! leaves the tool out.
+
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use portable_pty::{native_pty_system, Child, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use serde_json::Value;
+
+use super::shell::Shell;
+use super::{
+    command_argument, max_output_bytes, sandbox, truncate_bytes, working_directory, Tool,
+    DEFAULT_SHELL_TIMEOUT_SECS,
+};
+use crate::config::env_parse;
+
+/// How long a shell may take to start, or to come back after Ctrl-C.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start of the line printed after every command.
+const MARKER: &str = "__JARVIS_DONE_";
+
+/// Tells the markers of consecutive commands apart.
+static NEXT_MARKER: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether `command` asks to start over with a fresh shell: "reset
+/// shell", "restart the shell".
+pub fn is_reset_command(command: &str) -> bool {
+    let command = command
+        .trim()
+        .trim_end_matches(['.', '!'])
+        .to_lowercase()
+        .replace("the ", "")
+        .replace("your ", "");
+    matches!(
+        command.trim(),
+        "reset shell" | "restart shell" | "new shell" | "reset shell session"
+    )
+}
+
+/// A running shell on a pseudo-terminal.
+struct Session {
+    writer: Box<dyn Write + Send>,
+    /// Lines the shell prints, without line endings.
+    lines: mpsc::Receiver<String>,
+    child: Box<dyn Child + Send + Sync>,
+    /// Keeps the terminal open.
+    _master: Box<dyn MasterPty + Send>,
+    fish: bool,
+}
+
+/// How running a command in the session went.
+enum Outcome {
+    Done {
+        output: String,
+        status: i32,
+    },
+    TimedOut,
+    /// The shell itself ended, e.g. after `exit`.
+    Ended(String),
+}
+
+impl Session {
+    fn start() -> Result<Self> {
+        let shell = Shell::from_env();
+        let cwd = working_directory().or_else(|| std::env::current_dir().ok());
+        let argv = match sandbox::Sandbox::from_env()? {
+            Some(sandbox) => sandbox.argv(&shell.session_argv(), shell.env(), cwd.as_deref()),
+            None => shell.session_argv(),
+        };
+        let pair = native_pty_system()
+            .openpty(PtySize {
+                rows: 24,
+                cols: 200,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .context("cannot open a terminal for the shell")?;
+        let mut cmd = CommandBuilder::new(&argv[0]);
+        cmd.args(&argv[1..]);
+        // No colours or fancy prompts in the output.
+        cmd.env("TERM", "dumb");
+        for (name, value) in shell.env() {
+            cmd.env(name, value);
+        }
+        if let Some(cwd) = &cwd {
+            cmd.cwd(cwd);
+        }
+        let child = pair
+            .slave
+            .spawn_command(cmd)
+            .with_context(|| format!("cannot start {}", argv[0]))?;
+        drop(pair.slave);
+        let reader = pair.master.try_clone_reader()?;
+        let writer = pair.master.take_writer()?;
+        let (lines_tx, lines) = mpsc::channel();
+        std::thread::spawn(move || read_lines(reader, lines_tx));
+        let mut session = Self {
+            writer,
+            lines,
+            child,
+            _master: pair.master,
+            fish: shell.is_fish(),
+        };
+        // Quiet the terminal and the prompts, then wait for the shell to
+        // be ready.
+        let setup = if session.fish {
+            "stty -echo; function fish_prompt; end; function fish_right_prompt; end"
+        } else {
+            "stty -echo; PS1=''; PS2=''; PROMPT_COMMAND=''; RPROMPT=''"
+        };
+        match session.run(setup, SETTLE_TIMEOUT, &mut |_| {})? {
+            Outcome::Done { .. } => Ok(session),
+            Outcome::TimedOut => Err(anyhow!("the shell did not start in time")),
+            Outcome::Ended(output) => Err(anyhow!("the shell ended at once: {output}")),
+        }
+    }
+
+    /// Run `command` and wait for it up to `timeout`, passing each line
+    /// of output to `on_line`.
+    fn run(
+        &mut self,
+        command: &str,
+        timeout: Duration,
+        on_line: &mut dyn FnMut(&str),
+    ) -> Result<Outcome> {
+        // Forget anything printed since the last command.
+        while self.lines.try_recv().is_ok() {}
+        let marker = self.send_marker_after(command)?;
+        let deadline = Instant::now() + timeout;
+        let mut output = Vec::new();
+        loop {
+            match self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(line) => {
+                    if let Some(status) = line.trim().strip_prefix(&marker) {
+                        let status = status.trim().parse().unwrap_or(-1);
+                        let output = output.join("\n").trim().to_string();
+                        return Ok(Outcome::Done { output, status });
+                    }
+                    on_line(&line);
+                    output.push(line);
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(Outcome::TimedOut),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Ok(Outcome::Ended(output.join("\n").trim().to_string()))
+                }
+            }
+        }
+    }
+
+    /// Type `command`, followed by the command that prints a new marker
+    /// with its exit status, and return the marker.
+    fn send_marker_after(&mut self, command: &str) -> Result<String> {
+        let marker = format!("{MARKER}{}__", NEXT_MARKER.fetch_add(1, Ordering::Relaxed));
+        let status = if self.fish { "$status" } else { "$?" };
+        write!(
+            self.writer,
+            "{command}\nprintf '\\n%s %s\\n' '{marker}' \"{status}\"\n"
+        )?;
+        self.writer.flush()?;
+        Ok(marker)
+    }
+
+    /// Interrupt the running command with Ctrl-C and return whether the
+    /// shell came back.
+    fn interrupt(&mut self) -> bool {
+        if self.writer.write_all(b"\x03").is_err() || self.writer.flush().is_err() {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(200));
+        matches!(
+            self.run("", SETTLE_TIMEOUT, &mut |_| {}),
+            Ok(Outcome::Done { .. })
+        )
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+/// Send the lines read from the terminal to `lines` until it closes.
+fn read_lines(mut reader: Box<dyn Read + Send>, lines: mpsc::Sender<String>) {
+    let mut buf = [0u8; 4096];
+    let mut line = Vec::new();
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                for &byte in &buf[..n] {
+                    match byte {
+                        b'\n' => {
+                            let text = String::from_utf8_lossy(&line).into_owned();
+                            if lines.send(text).is_err() {
+                                return;
+                            }
+                            line.clear();
+                        }
+                        b'\r' => {}
+                        _ => line.push(byte),
+                    }
+                }
+            }
+        }
+    }
+    if !line.is_empty() {
+        let _ = lines.send(String::from_utf8_lossy(&line).into_owned());
+    }
+}
+
+/// Runs shell commands in a shell that persists between calls.
+#[derive(Default)]
+pub struct SessionShellTool {
+    session: Mutex<Option<Session>>,
+}
+
+impl SessionShellTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Tool for SessionShellTool {
+    fn name(&self) -> &str {
+        "persistent_shell_task"
+    }
+
+    fn description(&self) -> &str {
+        "Use `persistent_shell_task` for shell commands that build on earlier ones, such as \
+exporting variables, activating a virtualenv or changing directory for the commands that follow."
+    }
+
+    fn shell_line(&self, arguments: &Value) -> Option<String> {
+        command_argument(arguments).map(str::to_string)
+    }
+
+    fn reset(&self) -> bool {
+        self.session.lock().unwrap().take().is_some()
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        self.run_streaming(arguments, &mut |_| {})
+    }
+
+    fn run_streaming(&self, arguments: &Value, on_line: &mut dyn FnMut(&str)) -> Result<String> {
+        let Some(command) = command_argument(arguments).map(str::trim) else {
+            return Ok("No command provided.".to_string());
+        };
+        if command.is_empty() {
+            return Ok("No command provided.".to_string());
+        }
+        let timeout =
+            Duration::from_secs(env_parse("SHELL_TIMEOUT_SECS", DEFAULT_SHELL_TIMEOUT_SECS));
+        let mut slot = self.session.lock().unwrap();
+        if slot.is_none() {
+            *slot = Some(Session::start()?);
+        }
+        let session = slot.as_mut().unwrap();
+        let limit = max_output_bytes();
+        Ok(match session.run(command, timeout, on_line)? {
+            Outcome::Done { output, status: 0 } if output.is_empty() => {
+                "Command ran successfully with no output.".to_string()
+            }
+            Outcome::Done { output, status: 0 } => truncate_bytes(&output, limit),
+            Outcome::Done { output, status } if output.is_empty() => {
+                format!("Command exited with {status} and produced no output.")
+            }
+            Outcome::Done { output, status } => {
+                format!(
+                    "Command exited with {status}: {}",
+                    truncate_bytes(&output, limit)
+                )
+            }
+            Outcome::TimedOut => {
+                let kept = session.interrupt();
+                if !kept {
+                    *slot = None;
+                }
+                format!(
+                    "Command timed out after {} seconds and was stopped{}.",
+                    timeout.as_secs(),
+                    if kept {
+                        ""
+                    } else {
+                        "; the shell was restarted"
+                    }
+                )
+            }
+            Outcome::Ended(output) => {
+                *slot = None;
+                let output = truncate_bytes(&output, limit);
+                if output.is_empty() {
+                    "The shell session ended; the next command starts a new one.".to_string()
+                } else {
+                    format!("The shell session ended; the next command starts a new one. {output}")
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,360 @@
+//! How the machine is doing, in sentences.
+//!
+//! `system_status` answers "how's the computer doing?" with the CPU load,
+//! free memory and disk space, the battery, the temperature and the
+//! uptime, read through `sysinfo` and the kernel's battery files instead
+//! of a shell pipeline whose output the model has to make sense of: "CPU
+//! is at 12 percent. 9 gigabytes of memory free out of 16 gigabytes."
+//! Asking about one of them reads out only that one. `SYSTEM_TOOLS=0`
+//! leaves the tool out.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use sysinfo::{Components, Disks, System, MINIMUM_CPU_UPDATE_INTERVAL};
+
+use super::Tool;
+
+/// CPU load, in percent, read out as high.
+const BUSY_PERCENT: f32 = 90.0;
+
+/// How full a disk is, in percent, before it is mentioned.
+const FULL_PERCENT: u64 = 90;
+
+/// Free memory, in percent of all, read out as low.
+const LOW_MEMORY_PERCENT: u64 = 10;
+
+/// Battery level, in percent, read out as low while discharging.
+const LOW_BATTERY_PERCENT: u8 = 15;
+
+/// Degrees Celsius read out as hot.
+const HOT_CELSIUS: f32 = 85.0;
+
+/// What the tool can report on.
+const TOPICS: [&str; 7] = [
+    "all",
+    "cpu",
+    "memory",
+    "disk",
+    "battery",
+    "temperature",
+    "uptime",
+];
+
+/// The machine's state at one moment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// CPU load over all cores, in percent.
+    pub cpu_percent: f32,
+    pub memory_available: u64,
+    pub memory_total: u64,
+    /// Mounted disks, the main one first.
+    pub disks: Vec<DiskSpace>,
+    pub battery: Option<Battery>,
+    /// The hottest sensor, in degrees Celsius.
+    pub temperature: Option<f32>,
+    pub uptime: Duration,
+}
+
+/// Space on one mounted disk, in bytes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiskSpace {
+    pub mount: String,
+    pub available: u64,
+    pub total: u64,
+}
+
+impl DiskSpace {
+    /// How full it is, in percent.
+    pub fn used_percent(&self) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        (self.total.saturating_sub(self.available) as f64 * 100.0 / self.total as f64).round()
+            as u64
+    }
+}
+
+/// The battery's charge.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Battery {
+    pub percent: u8,
+    /// `Charging`, `Discharging`, `Full` or `Not charging`, as the kernel
+    /// says it.
+    pub status: String,
+}
+
+impl Snapshot {
+    /// Read the parts of the machine's state that `about` needs; the CPU
+    /// load takes a moment to measure.
+    pub fn take(about: &str) -> Self {
+        let all = about == "all";
+        let mut snapshot = Snapshot::default();
+        let mut system = System::new();
+        if all || about == "cpu" {
+            system.refresh_cpu_usage();
+            std::thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+            system.refresh_cpu_usage();
+            snapshot.cpu_percent = system.global_cpu_usage();
+        }
+        if all || about == "memory" {
+            system.refresh_memory();
+            snapshot.memory_available = system.available_memory();
+            snapshot.memory_total = system.total_memory();
+        }
+        if all || about == "disk" {
+            let disks = Disks::new_with_refreshed_list();
+            let mut disks: Vec<DiskSpace> = disks
+                .iter()
+                .filter(|disk| disk.total_space() > 0)
+                .map(|disk| DiskSpace {
+                    mount: disk.mount_point().display().to_string(),
+                    available: disk.available_space(),
+                    total: disk.total_space(),
+                })
+                .collect();
+            // The root (or system drive) first, then the biggest.
+            disks.sort_by_key(|disk| (!is_main(&disk.mount), std::cmp::Reverse(disk.total)));
+            disks.dedup_by(|a, b| a.mount == b.mount);
+            snapshot.disks = disks;
+        }
+        if all || about == "battery" {
+            snapshot.battery = battery(Path::new("/sys/class/power_supply"));
+        }
+        if all || about == "temperature" {
+            let components = Components::new_with_refreshed_list();
+            snapshot.temperature = components
+                .iter()
+                .map(|component| component.temperature())
+                .filter(|celsius| celsius.is_finite() && *celsius > 0.0)
+                .reduce(f32::max);
+        }
+        snapshot.uptime = Duration::from_secs(System::uptime());
+        snapshot
+    }
+
+    /// What `about` ("all", "cpu", "memory", "disk", "battery",
+    /// "temperature", "uptime") is, read out.
+    pub fn spoken(&self, about: &str) -> String {
+        match about {
+            "cpu" => self.spoken_cpu(),
+            "memory" => self.spoken_memory(),
+            "disk" => self.spoken_disks(),
+            "battery" => self.spoken_battery(),
+            "temperature" => self.spoken_temperature(),
+            "uptime" => self.spoken_uptime(),
+            _ => {
+                let mut sentences = vec![if self.healthy() {
+                    "Everything looks healthy.".to_string()
+                } else {
+                    "Something needs a look.".to_string()
+                }];
+                sentences.push(self.spoken_cpu());
+                sentences.push(self.spoken_memory());
+                sentences.push(self.spoken_disks());
+                if self.battery.is_some() {
+                    sentences.push(self.spoken_battery());
+                }
+                if self.temperature.is_some() {
+                    sentences.push(self.spoken_temperature());
+                }
+                sentences.push(self.spoken_uptime());
+                sentences.join(" ")
+            }
+        }
+    }
+
+    /// Whether nothing is busy, low, full or hot.
+    pub fn healthy(&self) -> bool {
+        let memory_low = self.memory_total > 0
+            && self.memory_available * 100 / self.memory_total < LOW_MEMORY_PERCENT;
+        let disk_full = self
+            .disks
+            .iter()
+            .any(|disk| disk.used_percent() >= FULL_PERCENT);
+        let battery_low = self.battery.as_ref().is_some_and(|battery| {
+            battery.percent <= LOW_BATTERY_PERCENT && battery.status == "Discharging"
+        });
+        let hot = self
+            .temperature
+            .is_some_and(|celsius| celsius >= HOT_CELSIUS);
+        self.cpu_percent < BUSY_PERCENT && !memory_low && !disk_full && !battery_low && !hot
+    }
+
+    fn spoken_cpu(&self) -> String {
+        let percent = self.cpu_percent.round() as u32;
+        if self.cpu_percent >= BUSY_PERCENT {
+            format!("CPU is busy, at {percent} percent.")
+        } else {
+            format!("CPU is at {percent} percent.")
+        }
+    }
+
+    fn spoken_memory(&self) -> String {
+        if self.memory_total == 0 {
+            return "I can't tell how much memory is free.".to_string();
+        }
+        format!(
+            "{} of memory free out of {}.",
+            bytes(self.memory_available),
+            bytes(self.memory_total)
+        )
+    }
+
+    fn spoken_disks(&self) -> String {
+        let Some((main, others)) = self.disks.split_first() else {
+            return "I can't see any disks.".to_string();
+        };
+        let mut sentences = vec![format!(
+            "The main disk is {} percent full, with {} free.",
+            main.used_percent(),
+            bytes(main.available)
+        )];
+        for disk in others {
+            if disk.used_percent() >= FULL_PERCENT {
+                sentences.push(format!(
+                    "{} is {} percent full.",
+                    disk.mount,
+                    disk.used_percent()
+                ));
+            }
+        }
+        sentences.join(" ")
+    }
+
+    fn spoken_battery(&self) -> String {
+        let Some(battery) = &self.battery else {
+            return "There is no battery.".to_string();
+        };
+        let percent = battery.percent;
+        match battery.status.as_str() {
+            "Full" => "The battery is full.".to_string(),
+            "Charging" => format!("The battery is at {percent} percent and charging."),
+            "Discharging" if percent <= LOW_BATTERY_PERCENT => {
+                format!("The battery is low, at {percent} percent. Plug in soon.")
+            }
+            _ => format!("The battery is at {percent} percent."),
+        }
+    }
+
+    fn spoken_temperature(&self) -> String {
+        match self.temperature {
+            None => "I can't read any temperature sensors.".to_string(),
+            Some(celsius) if celsius >= HOT_CELSIUS => {
+                format!("It's running hot, at {} degrees.", celsius.round())
+            }
+            Some(celsius) => format!("The temperature is {} degrees.", celsius.round()),
+        }
+    }
+
+    fn spoken_uptime(&self) -> String {
+        format!("Up for {}.", spoken_duration(self.uptime))
+    }
+}
+
+/// Whether `mount` is the root or the Windows system drive.
+fn is_main(mount: &str) -> bool {
+    mount == "/" || mount.eq_ignore_ascii_case("C:\\")
+}
+
+/// `bytes` in words: "9 gigabytes", "1.5 gigabytes", "512 megabytes".
+pub fn bytes(bytes: u64) -> String {
+    const GIGABYTE: f64 = 1024.0 * 1024.0 * 1024.0;
+    let gigabytes = bytes as f64 / GIGABYTE;
+    if gigabytes >= 1000.0 {
+        format!("{:.1} terabytes", gigabytes / 1024.0).replace(".0 ", " ")
+    } else if gigabytes >= 10.0 {
+        format!("{} gigabytes", gigabytes.round())
+    } else if gigabytes >= 1.0 {
+        format!("{gigabytes:.1} gigabytes").replace(".0 ", " ")
+    } else {
+        format!("{} megabytes", (gigabytes * 1024.0).round())
+    }
+}
+
+/// `duration` to the minute: "3 days and 4 hours", "2 hours and 5
+/// minutes", "12 minutes".
+pub fn spoken_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    let unit = |count: u64, one: &str| {
+        if count == 1 {
+            format!("1 {one}")
+        } else {
+            format!("{count} {one}s")
+        }
+    };
+    match (days, hours, minutes) {
+        (0, 0, minutes) => unit(minutes, "minute"),
+        (0, hours, 0) => unit(hours, "hour"),
+        (0, hours, minutes) => format!("{} and {}", unit(hours, "hour"), unit(minutes, "minute")),
+        (days, 0, _) => unit(days, "day"),
+        (days, hours, _) => format!("{} and {}", unit(days, "day"), unit(hours, "hour")),
+    }
+}
+
+/// The first battery under `power_supply` (Linux's
+/// `/sys/class/power_supply`); `None` on machines without one.
+fn battery(power_supply: &Path) -> Option<Battery> {
+    let read = |dir: &Path, name: &str| {
+        std::fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|text| text.trim().to_string())
+    };
+    let mut entries: Vec<_> = std::fs::read_dir(power_supply)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+    entries.sort();
+    entries.iter().find_map(|dir| {
+        if read(dir, "type").as_deref() != Some("Battery") {
+            return None;
+        }
+        Some(Battery {
+            percent: read(dir, "capacity")?.parse().ok()?,
+            status: read(dir, "status").unwrap_or_default(),
+        })
+    })
+}
+
+/// Reads out the CPU load, memory, disks, battery, temperature and
+/// uptime.
+pub struct SystemStatusTool;
+
+impl Tool for SystemStatusTool {
+    fn name(&self) -> &str {
+        "system_status"
+    }
+
+    fn description(&self) -> &str {
+        "Use `system_status` for how this computer is doing: CPU load, free memory and disk space, battery, temperature and uptime."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "about": {
+                    "type": "string",
+                    "enum": TOPICS,
+                    "description": "What to report on; all by default.",
+                },
+            },
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let about = arguments
+            .get("about")
+            .and_then(Value::as_str)
+            .unwrap_or("all")
+            .to_lowercase();
+        if !TOPICS.contains(&about.as_str()) {
+            return Ok(format!("I can't report on '{about}'."));
+        }
+        Ok(Snapshot::take(&about).spoken(&about))
+    }
+}
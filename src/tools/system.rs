@@ -0,0 +1,276 @@
+//! Volume, screen brightness and media players.
+//!
+//! `volume`, `brightness` and `media` let "turn it up", "dim the screen"
+//! and "pause the music" work without the model writing shell commands.
+//! Each runs a fixed program with fixed arguments: `wpctl` (PipeWire) or
+//! `pactl` (PulseAudio) for the volume, `brightnessctl` or `light` for
+//! the brightness and `playerctl` for MPRIS media players, whichever is
+//! installed. `VOLUME_STEP` and `BRIGHTNESS_STEP` (default 10) are the
+//! percentages "up" and "down" change by. `SYSTEM_TOOLS=0` leaves the
+//! tools out.
+
+use std::io::ErrorKind;
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{output_text, output_with_timeout, Tool};
+use crate::config::env_parse;
+
+/// How long a control program may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ways of running one change, in order of preference: a program and its
+/// arguments.
+type Commands = Vec<Vec<String>>;
+
+fn argv(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+/// The commands that change the volume by `action` ("up", "down", "set",
+/// "mute", "unmute"), `percent` being the step or the level to set.
+pub fn volume_commands(action: &str, percent: u32) -> Option<Commands> {
+    let (wpctl, pactl) = match action {
+        "up" => (format!("{percent}%+"), format!("+{percent}%")),
+        "down" => (format!("{percent}%-"), format!("-{percent}%")),
+        "set" => (format!("{percent}%"), format!("{percent}%")),
+        "mute" | "unmute" => {
+            let flag = if action == "mute" { "1" } else { "0" };
+            return Some(vec![
+                argv(&["wpctl", "set-mute", "@DEFAULT_AUDIO_SINK@", flag]),
+                argv(&["pactl", "set-sink-mute", "@DEFAULT_SINK@", flag]),
+            ]);
+        }
+        _ => return None,
+    };
+    Some(vec![
+        // -l 1.0 keeps "up" from going past full volume.
+        argv(&[
+            "wpctl",
+            "set-volume",
+            "-l",
+            "1.0",
+            "@DEFAULT_AUDIO_SINK@",
+            &wpctl,
+        ]),
+        argv(&["pactl", "set-sink-volume", "@DEFAULT_SINK@", &pactl]),
+    ])
+}
+
+/// The commands that change the brightness by `action` ("up", "down",
+/// "set"), `percent` being the step or the level to set.
+pub fn brightness_commands(action: &str, percent: u32) -> Option<Commands> {
+    // Never quite dark: a black screen is hard to undo by voice.
+    let level = percent.max(5);
+    let (brightnessctl, flag, amount) = match action {
+        "up" => (format!("{percent}%+"), "-A", percent),
+        "down" => (format!("{percent}%-"), "-U", percent),
+        "set" => (format!("{level}%"), "-S", level),
+        _ => return None,
+    };
+    Some(vec![
+        argv(&["brightnessctl", "--quiet", "set", &brightnessctl]),
+        argv(&["light", flag, &amount.to_string()]),
+    ])
+}
+
+/// The commands that do `action` ("play", "pause", "toggle", "next",
+/// "previous", "stop") to the current media player.
+pub fn media_commands(action: &str) -> Option<Commands> {
+    let command = match action {
+        "play" | "pause" | "next" | "previous" | "stop" => action,
+        "toggle" => "play-pause",
+        _ => return None,
+    };
+    Some(vec![argv(&["playerctl", command])])
+}
+
+/// Run the first of `commands` whose program is installed. Returns the
+/// problem, if any, in words for the model.
+fn run_first(what: &str, commands: Commands) -> Result<Option<String>> {
+    let programs: Vec<&str> = commands.iter().map(|argv| argv[0].as_str()).collect();
+    for argv in &commands {
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]);
+        match output_with_timeout(cmd, TIMEOUT, &mut |_| {}) {
+            Ok(Some(output)) if output.status.success() => return Ok(None),
+            Ok(Some(output)) => {
+                let stderr = output_text(&output.stderr);
+                return Ok(Some(if stderr.is_empty() {
+                    format!("{} failed to change the {what}.", argv[0])
+                } else {
+                    format!("{} failed to change the {what}: {stderr}", argv[0])
+                }));
+            }
+            Ok(None) => return Ok(Some(format!("{} did not answer in time.", argv[0]))),
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == ErrorKind::NotFound) =>
+            {
+                continue
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(format!(
+        "I can't change the {what}: none of {} is installed.",
+        programs.join(", ")
+    )))
+}
+
+/// The `action` argument, in lower case.
+fn action(arguments: &Value) -> String {
+    arguments
+        .get("action")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// The `action` and `percent` arguments, the step from `step_variable`
+/// standing in for a missing percentage.
+fn action_and_percent(arguments: &Value, step_variable: &str) -> (String, u32) {
+    let action = action(arguments);
+    let percent = arguments
+        .get("percent")
+        .and_then(Value::as_u64)
+        .map_or_else(
+            || env_parse(step_variable, 10),
+            |percent| percent.min(100) as u32,
+        );
+    (action, percent)
+}
+
+/// Turns the volume up or down, or mutes it.
+pub struct VolumeTool;
+
+impl Tool for VolumeTool {
+    fn name(&self) -> &str {
+        "volume"
+    }
+
+    fn description(&self) -> &str {
+        "Use `volume` to turn the sound up or down, set it to a level or mute it."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["up", "down", "set", "mute", "unmute"] },
+                "percent": {
+                    "type": "integer",
+                    "description": "How much to turn it up or down, or the level to set, in percent.",
+                },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let (action, percent) = action_and_percent(arguments, "VOLUME_STEP");
+        let Some(commands) = volume_commands(&action, percent) else {
+            return Ok(format!("Unknown volume action '{action}'."));
+        };
+        Ok(match run_first("volume", commands)? {
+            Some(problem) => problem,
+            None => match action.as_str() {
+                "set" => format!("Volume set to {percent} percent."),
+                "mute" => "Muted.".to_string(),
+                "unmute" => "Unmuted.".to_string(),
+                _ => format!("Volume {action}."),
+            },
+        })
+    }
+}
+
+/// Makes the screen brighter or darker.
+pub struct BrightnessTool;
+
+impl Tool for BrightnessTool {
+    fn name(&self) -> &str {
+        "brightness"
+    }
+
+    fn description(&self) -> &str {
+        "Use `brightness` to make the screen brighter or dimmer, or set its brightness."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["up", "down", "set"] },
+                "percent": {
+                    "type": "integer",
+                    "description": "How much brighter or dimmer, or the level to set, in percent.",
+                },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let (action, percent) = action_and_percent(arguments, "BRIGHTNESS_STEP");
+        let Some(commands) = brightness_commands(&action, percent) else {
+            return Ok(format!("Unknown brightness action '{action}'."));
+        };
+        Ok(match run_first("brightness", commands)? {
+            Some(problem) => problem,
+            None if action == "set" => format!("Brightness set to {} percent.", percent.max(5)),
+            None => format!("Brightness {action}."),
+        })
+    }
+}
+
+/// Plays, pauses and skips in the current media player.
+pub struct MediaTool;
+
+impl Tool for MediaTool {
+    fn name(&self) -> &str {
+        "media"
+    }
+
+    fn description(&self) -> &str {
+        "Use `media` to play, pause, skip or stop the music or video that is playing."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["play", "pause", "toggle", "next", "previous", "stop"],
+                },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let action = action(arguments);
+        let Some(commands) = media_commands(&action) else {
+            return Ok(format!("Unknown media action '{action}'."));
+        };
+        // Music the `music` tool started is controlled directly.
+        #[cfg(unix)]
+        if let Some(answer) = super::music::control(&action) {
+            return answer;
+        }
+        Ok(match run_first("media player", commands)? {
+            Some(problem) => problem,
+            None => match action.as_str() {
+                "play" => "Playing.".to_string(),
+                "pause" => "Paused.".to_string(),
+                "next" => "Next track.".to_string(),
+                "previous" => "Previous track.".to_string(),
+                "stop" => "Stopped.".to_string(),
+                _ => "Done.".to_string(),
+            },
+        })
+    }
+}
@@ -0,0 +1,471 @@
+//! A stopwatch and a Pomodoro timer.
+//!
+//! `stopwatch` starts, laps, stops and reads a stopwatch, to the second.
+//! `pomodoro` runs focus sessions with breaks in between: four sessions
+//! of 25 minutes, each followed by a 5 minute break, and a 15 minute break
+//! after the fourth, over again until it is stopped
+//! (`POMODORO_WORK_MINUTES`, `POMODORO_BREAK_MINUTES`,
+//! `POMODORO_LONG_BREAK_MINUTES`, `POMODORO_ROUNDS`). A timer task
+//! publishes the start of every session and break as a
+//! [`JarvisEvent::Timer`], which the voice loop speaks between commands
+//! and `--text` prints. Both tools keep their state in memory only.
+//! `TIMER_TOOLS=0` leaves the tools out.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use tokio_util::sync::CancellationToken;
+
+use super::status::spoken_duration;
+use super::Tool;
+use crate::config::env_parse;
+use crate::events::{EventBus, JarvisEvent};
+
+/// `duration` to the second: "1 hour, 2 minutes and 5 seconds", "45
+/// seconds".
+pub fn stopwatch_time(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let unit = |count: u64, one: &str| {
+        if count == 1 {
+            format!("1 {one}")
+        } else {
+            format!("{count} {one}s")
+        }
+    };
+    let mut parts = Vec::new();
+    if seconds >= 3600 {
+        parts.push(unit(seconds / 3600, "hour"));
+    }
+    if seconds % 3600 >= 60 {
+        parts.push(unit(seconds / 60 % 60, "minute"));
+    }
+    if seconds % 60 > 0 || parts.is_empty() {
+        parts.push(unit(seconds % 60, "second"));
+    }
+    match parts.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {last}", rest.join(", ")),
+        None => String::new(),
+    }
+}
+
+/// A stopwatch with laps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stopwatch {
+    /// When it was started, while it runs.
+    started: Option<Instant>,
+    /// The time it showed when it was stopped.
+    stopped_at: Option<Duration>,
+    /// The time it showed at each lap.
+    laps: Vec<Duration>,
+}
+
+impl Stopwatch {
+    pub const fn new() -> Self {
+        Self {
+            started: None,
+            stopped_at: None,
+            laps: Vec::new(),
+        }
+    }
+
+    /// The time it shows at `now`.
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        match (self.started, self.stopped_at) {
+            (Some(started), _) => now.saturating_duration_since(started),
+            (None, Some(stopped_at)) => stopped_at,
+            (None, None) => Duration::ZERO,
+        }
+    }
+
+    /// Start from zero, unless it is running already.
+    pub fn start(&mut self, now: Instant) -> String {
+        if self.started.is_some() {
+            return format!(
+                "The stopwatch is already running, at {}.",
+                stopwatch_time(self.elapsed(now))
+            );
+        }
+        *self = Self {
+            started: Some(now),
+            ..Self::new()
+        };
+        "Stopwatch started.".to_string()
+    }
+
+    /// Take a lap: its time and the time so far.
+    pub fn lap(&mut self, now: Instant) -> String {
+        if self.started.is_none() {
+            return "The stopwatch isn't running.".to_string();
+        }
+        let total = self.elapsed(now);
+        let previous = self.laps.last().copied().unwrap_or_default();
+        self.laps.push(total);
+        format!(
+            "Lap {}: {}, {} in all.",
+            self.laps.len(),
+            stopwatch_time(total.saturating_sub(previous)),
+            stopwatch_time(total)
+        )
+    }
+
+    /// Stop it and say the time.
+    pub fn stop(&mut self, now: Instant) -> String {
+        if self.started.is_none() {
+            return "The stopwatch isn't running.".to_string();
+        }
+        let total = self.elapsed(now);
+        self.started = None;
+        self.stopped_at = Some(total);
+        format!("Stopped at {}.", stopwatch_time(total))
+    }
+
+    /// The time it shows.
+    pub fn status(&self, now: Instant) -> String {
+        match (self.started, self.stopped_at) {
+            (Some(_), _) => format!("The stopwatch is at {}.", stopwatch_time(self.elapsed(now))),
+            (None, Some(stopped_at)) => {
+                format!("The stopwatch stopped at {}.", stopwatch_time(stopped_at))
+            }
+            (None, None) => "The stopwatch isn't running.".to_string(),
+        }
+    }
+}
+
+/// The lengths of the Pomodoro sessions and breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PomodoroSettings {
+    pub work: Duration,
+    pub short_break: Duration,
+    /// The break after the last session of a round.
+    pub long_break: Duration,
+    /// Focus sessions before a long break.
+    pub rounds: u32,
+}
+
+impl Default for PomodoroSettings {
+    fn default() -> Self {
+        Self {
+            work: minutes(25),
+            short_break: minutes(5),
+            long_break: minutes(15),
+            rounds: 4,
+        }
+    }
+}
+
+impl PomodoroSettings {
+    /// The settings in `POMODORO_WORK_MINUTES`, `POMODORO_BREAK_MINUTES`,
+    /// `POMODORO_LONG_BREAK_MINUTES` and `POMODORO_ROUNDS`.
+    pub fn from_env() -> Self {
+        Self {
+            work: minutes(env_parse("POMODORO_WORK_MINUTES", 25)),
+            short_break: minutes(env_parse("POMODORO_BREAK_MINUTES", 5)),
+            long_break: minutes(env_parse("POMODORO_LONG_BREAK_MINUTES", 15)),
+            rounds: env_parse("POMODORO_ROUNDS", 4u32).max(1),
+        }
+    }
+
+    /// The session or break `elapsed` after the start.
+    pub fn phase_at(&self, elapsed: Duration) -> Phase {
+        let rounds = self.rounds.max(1);
+        let phases: Vec<(PhaseKind, Duration)> = (1..=rounds)
+            .flat_map(|round| {
+                let rest = if round == rounds {
+                    (PhaseKind::LongBreak, self.long_break)
+                } else {
+                    (PhaseKind::Break, self.short_break)
+                };
+                [(PhaseKind::Focus(round), self.work), rest]
+            })
+            .collect();
+        let cycle: Duration = phases.iter().map(|(_, length)| *length).sum();
+        let cycles = (elapsed.as_secs() / cycle.as_secs().max(1)) as usize;
+        let mut into = Duration::from_secs(elapsed.as_secs() % cycle.as_secs().max(1));
+        for (number, (kind, length)) in phases.iter().enumerate() {
+            if into < *length {
+                return Phase {
+                    kind: *kind,
+                    index: cycles * phases.len() + number,
+                    remaining: *length - into,
+                };
+            }
+            into -= *length;
+        }
+        unreachable!("the cycle is the sum of its phases")
+    }
+
+    fn length(&self, kind: PhaseKind) -> Duration {
+        match kind {
+            PhaseKind::Focus(_) => self.work,
+            PhaseKind::Break => self.short_break,
+            PhaseKind::LongBreak => self.long_break,
+        }
+    }
+}
+
+/// `count` minutes, at least one.
+fn minutes(count: u64) -> Duration {
+    Duration::from_secs(count.max(1) * 60)
+}
+
+/// What a Pomodoro phase is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseKind {
+    /// The focus session with this number in its round, from 1.
+    Focus(u32),
+    Break,
+    LongBreak,
+}
+
+/// A Pomodoro session or break.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phase {
+    pub kind: PhaseKind,
+    /// Phases since the start, from 0.
+    pub index: usize,
+    pub remaining: Duration,
+}
+
+impl Phase {
+    /// What is said as the phase begins.
+    pub fn announcement(&self, settings: &PomodoroSettings) -> String {
+        let length = spoken_duration(settings.length(self.kind));
+        match self.kind {
+            PhaseKind::Focus(session) if self.index == 0 => {
+                format!("Focus session {session} of {}: {length}.", settings.rounds)
+            }
+            PhaseKind::Focus(session) => format!(
+                "Break's over. Focus session {session} of {}: {length}.",
+                settings.rounds
+            ),
+            PhaseKind::Break => format!("Time for a break: {length}."),
+            PhaseKind::LongBreak => format!(
+                "That's {} focus sessions. Time for a long break: {length}.",
+                settings.rounds
+            ),
+        }
+    }
+
+    /// Where the timer is: "Focus session 2 of 4, 12 minutes left."
+    pub fn status(&self, settings: &PomodoroSettings) -> String {
+        let left = if self.remaining < Duration::from_secs(60) {
+            "less than a minute".to_string()
+        } else {
+            spoken_duration(Duration::from_secs(
+                self.remaining.as_secs().div_ceil(60) * 60,
+            ))
+        };
+        match self.kind {
+            PhaseKind::Focus(session) => format!(
+                "Focus session {session} of {}, {left} left.",
+                settings.rounds
+            ),
+            PhaseKind::Break => format!("On a break, {left} left."),
+            PhaseKind::LongBreak => format!("On a long break, {left} left."),
+        }
+    }
+
+    /// Focus sessions finished before this phase.
+    pub fn sessions_done(&self) -> usize {
+        (self.index + 1) / 2
+    }
+}
+
+/// A running Pomodoro timer.
+#[derive(Debug)]
+struct Pomodoro {
+    settings: PomodoroSettings,
+    started: Instant,
+    /// Ends the task announcing its phases.
+    announcer: CancellationToken,
+}
+
+/// Publish the start of each phase of the timer started at `started` on
+/// `events` as it comes, until `cancel`.
+async fn announce_phases(
+    settings: PomodoroSettings,
+    started: Instant,
+    events: EventBus,
+    cancel: CancellationToken,
+) {
+    let mut phase = settings.phase_at(started.elapsed());
+    loop {
+        tokio::select! {
+            () = cancel.cancelled() => return,
+            // `remaining` is rounded up to the second, so this wakes in
+            // the next phase.
+            () = tokio::time::sleep(phase.remaining) => {}
+        }
+        let next = settings.phase_at(started.elapsed());
+        if next.index > phase.index {
+            events.emit(JarvisEvent::Timer {
+                text: next.announcement(&settings),
+            });
+        }
+        phase = next;
+    }
+}
+
+/// Starts, laps, stops and reads the stopwatch.
+#[derive(Debug, Clone, Default)]
+pub struct StopwatchTool {
+    stopwatch: Arc<Mutex<Stopwatch>>,
+}
+
+impl Tool for StopwatchTool {
+    fn name(&self) -> &str {
+        "stopwatch"
+    }
+
+    fn description(&self) -> &str {
+        "Use `stopwatch` to start, lap, stop or read a stopwatch."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["start", "lap", "stop", "status"] },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let action = arguments
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or("status")
+            .to_lowercase();
+        let mut stopwatch = self.stopwatch.lock().unwrap();
+        let now = Instant::now();
+        Ok(match action.as_str() {
+            "start" => stopwatch.start(now),
+            "lap" => stopwatch.lap(now),
+            "stop" => stopwatch.stop(now),
+            "status" => stopwatch.status(now),
+            _ => format!("Unknown stopwatch action '{action}'."),
+        })
+    }
+}
+
+/// Starts, stops and reads the Pomodoro timer.
+#[derive(Clone, Default)]
+pub struct PomodoroTool {
+    running: Arc<Mutex<Option<Pomodoro>>>,
+    /// Where the phases are announced.
+    events: Arc<Mutex<EventBus>>,
+}
+
+impl PomodoroTool {
+    /// Start the timer with `settings` from its first focus session,
+    /// restarting it if it runs, and say so. The phases are announced
+    /// from a task on the current Tokio runtime, if there is one.
+    pub fn start(&self, settings: PomodoroSettings) -> String {
+        let mut running = self.running.lock().unwrap();
+        let restarted = running.take().map(|old| old.announcer.cancel()).is_some();
+        let started = Instant::now();
+        let announcer = CancellationToken::new();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let events = self.events.lock().unwrap().clone();
+                runtime.spawn(announce_phases(
+                    settings,
+                    started,
+                    events,
+                    announcer.clone(),
+                ));
+            }
+            Err(_) => log::warn!("No async runtime; the Pomodoro phases will not be announced"),
+        }
+        *running = Some(Pomodoro {
+            settings,
+            started,
+            announcer,
+        });
+        format!(
+            "Pomodoro {}. {}",
+            if restarted { "restarted" } else { "started" },
+            settings.phase_at(Duration::ZERO).announcement(&settings)
+        )
+    }
+
+    /// Stop the timer and say how many focus sessions it ran.
+    pub fn stop(&self) -> String {
+        let Some(running) = self.running.lock().unwrap().take() else {
+            return "The Pomodoro timer isn't running.".to_string();
+        };
+        running.announcer.cancel();
+        let done = running
+            .settings
+            .phase_at(running.started.elapsed())
+            .sessions_done();
+        match done {
+            1 => "Pomodoro stopped after 1 focus session.".to_string(),
+            done => format!("Pomodoro stopped after {done} focus sessions."),
+        }
+    }
+
+    /// Where the timer is.
+    pub fn status(&self) -> String {
+        match self.running.lock().unwrap().as_ref() {
+            Some(running) => running
+                .settings
+                .phase_at(running.started.elapsed())
+                .status(&running.settings),
+            None => "The Pomodoro timer isn't running.".to_string(),
+        }
+    }
+}
+
+impl Tool for PomodoroTool {
+    fn name(&self) -> &str {
+        "pomodoro"
+    }
+
+    fn description(&self) -> &str {
+        "Use `pomodoro` to start or stop Pomodoro focus sessions with breaks in between, or to say how long the current one has left."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["start", "stop", "status"] },
+                "work_minutes": { "type": "integer", "description": "For start, if the user asks for sessions of another length." },
+                "break_minutes": { "type": "integer", "description": "For start, if the user asks for breaks of another length." },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn set_event_bus(&self, events: &EventBus) {
+        *self.events.lock().unwrap() = events.clone();
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let action = arguments
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or("status")
+            .to_lowercase();
+        Ok(match action.as_str() {
+            "start" => {
+                let mut settings = PomodoroSettings::from_env();
+                if let Some(work) = arguments.get("work_minutes").and_then(Value::as_u64) {
+                    settings.work = minutes(work);
+                }
+                if let Some(rest) = arguments.get("break_minutes").and_then(Value::as_u64) {
+                    settings.short_break = minutes(rest);
+                }
+                self.start(settings)
+            }
+            "stop" => self.stop(),
+            "status" => self.status(),
+            _ => format!("Unknown pomodoro action '{action}'."),
+        })
+    }
+}
@@ -0,0 +1,372 @@
+//! The weather, from [Open-Meteo](https://open-meteo.com).
+//!
+//! `weather` answers "what's the weather?" for the home location in
+//! `WEATHER_LOCATION`, a place name ("Zagreb") or `latitude,longitude`,
+//! or for a place the user names. Open-Meteo needs no API key. The answer
+//! is a few sentences meant to be read out: the temperature now, the sky,
+//! the day's low and high and the chance of rain, today or tomorrow.
+//! `WEATHER_UNITS=imperial` gives Fahrenheit, inches and miles per hour.
+//! Needs Jarvis built with `--features weather`.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{off_runtime, Tool};
+
+const FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+const GEOCODING_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+
+/// Wind worth mentioning, in kilometres per hour.
+const WINDY_KMH: f64 = 30.0;
+
+/// Units of the forecast.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl Units {
+    /// `WEATHER_UNITS`, `metric` unless it is `imperial`.
+    pub fn from_env() -> Self {
+        match std::env::var("WEATHER_UNITS") {
+            Ok(units) if units.trim().eq_ignore_ascii_case("imperial") => Units::Imperial,
+            _ => Units::Metric,
+        }
+    }
+
+    /// Open-Meteo query parameters for these units.
+    fn query(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Units::Metric => &[],
+            Units::Imperial => &[
+                ("temperature_unit", "fahrenheit"),
+                ("precipitation_unit", "inch"),
+                ("wind_speed_unit", "mph"),
+            ],
+        }
+    }
+}
+
+/// The day a forecast is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Day {
+    Today,
+    Tomorrow,
+}
+
+/// A place on the map, named as it is read out: "in Zagreb".
+#[derive(Debug, Clone, PartialEq)]
+struct Place {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// What Open-Meteo says about a place, as far as it is read out.
+#[derive(Debug, Deserialize)]
+pub struct Report {
+    current: Current,
+    daily: Daily,
+}
+
+#[derive(Debug, Deserialize)]
+struct Current {
+    temperature_2m: f64,
+    weather_code: u8,
+    wind_speed_10m: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Daily {
+    weather_code: Vec<u8>,
+    temperature_2m_max: Vec<f64>,
+    temperature_2m_min: Vec<f64>,
+    precipitation_sum: Vec<f64>,
+    precipitation_probability_max: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Geocoding {
+    #[serde(default)]
+    results: Vec<GeocodingResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodingResult {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Tells the weather at home or elsewhere.
+#[derive(Debug, Clone)]
+pub struct WeatherTool {
+    /// `WEATHER_LOCATION`, looked up when it is first needed.
+    home: Option<String>,
+    units: Units,
+}
+
+impl WeatherTool {
+    pub fn new(home: Option<String>, units: Units) -> Self {
+        Self { home, units }
+    }
+
+    /// The tool for `WEATHER_LOCATION` and `WEATHER_UNITS`.
+    pub fn from_env() -> Self {
+        let home = std::env::var("WEATHER_LOCATION")
+            .ok()
+            .filter(|home| !home.trim().is_empty());
+        Self::new(home, Units::from_env())
+    }
+
+    /// The forecast for `place` (the home location if `None`) on `day`,
+    /// ready to be spoken.
+    fn forecast(&self, place: Option<&str>, day: Day) -> Result<String> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("failed to create HTTP client")?;
+        let place = match place {
+            Some(place) => locate(&client, place)?,
+            None => {
+                let home = self.home.as_deref().unwrap_or_default();
+                let mut place = locate(&client, home)?;
+                if parse_coordinates(home).is_some() {
+                    place.name = "at home".to_string();
+                }
+                place
+            }
+        };
+        let report: Report = client
+            .get(FORECAST_URL)
+            .query(&[
+                ("latitude", place.latitude.to_string()),
+                ("longitude", place.longitude.to_string()),
+                (
+                    "current",
+                    "temperature_2m,weather_code,wind_speed_10m".to_string(),
+                ),
+                (
+                    "daily",
+                    "weather_code,temperature_2m_max,temperature_2m_min,precipitation_sum,\
+precipitation_probability_max"
+                        .to_string(),
+                ),
+                ("timezone", "auto".to_string()),
+                ("forecast_days", "2".to_string()),
+            ])
+            .query(self.units.query())
+            .send()?
+            .error_for_status()?
+            .json()
+            .context("unexpected answer from Open-Meteo")?;
+        spoken_forecast(&report, &place.name, day, self.units)
+    }
+}
+
+impl Tool for WeatherTool {
+    fn name(&self) -> &str {
+        "weather"
+    }
+
+    fn description(&self) -> &str {
+        "Use `weather` for the current weather and today's or tomorrow's forecast, at home or in a named place."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "place": {
+                    "type": "string",
+                    "description": "A city or town; leave out for the user's home.",
+                },
+                "day": { "type": "string", "enum": ["today", "tomorrow"] },
+            },
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let place = arguments
+            .get("place")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|place| !place.is_empty());
+        if place.is_none() && self.home.is_none() {
+            return Ok(
+                "I don't know where home is; set WEATHER_LOCATION or name a place.".to_string(),
+            );
+        }
+        let day = match arguments.get("day").and_then(Value::as_str) {
+            Some("tomorrow") => Day::Tomorrow,
+            _ => Day::Today,
+        };
+        off_runtime("the weather request", || self.forecast(place, day))
+            .or_else(|e| Ok(format!("I couldn't get the weather: {e:#}.")))
+    }
+}
+
+/// `text` as `latitude,longitude`, if it is.
+fn parse_coordinates(text: &str) -> Option<(f64, f64)> {
+    let (latitude, longitude) = text.split_once(',')?;
+    Some((
+        latitude.trim().parse().ok()?,
+        longitude.trim().parse().ok()?,
+    ))
+}
+
+/// Where `place`, a name or coordinates, is.
+fn locate(client: &Client, place: &str) -> Result<Place> {
+    if let Some((latitude, longitude)) = parse_coordinates(place) {
+        return Ok(Place {
+            name: format!("at {place}"),
+            latitude,
+            longitude,
+        });
+    }
+    let found: Geocoding = client
+        .get(GEOCODING_URL)
+        .query(&[("name", place), ("count", "1")])
+        .send()?
+        .error_for_status()?
+        .json()
+        .context("unexpected answer from Open-Meteo")?;
+    let result = found
+        .results
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("there is no place called {place}"))?;
+    Ok(Place {
+        name: format!("in {}", result.name),
+        latitude: result.latitude,
+        longitude: result.longitude,
+    })
+}
+
+/// A few sentences about `report` for `place` ("in Zagreb", "at home")
+/// on `day`: "In Zagreb it's 14 degrees and partly cloudy. Today: light
+/// rain, from 9 to 16 degrees, with a 70 percent chance of rain and about
+/// 3 millimetres."
+pub fn spoken_forecast(report: &Report, place: &str, day: Day, units: Units) -> Result<String> {
+    let index = match day {
+        Day::Today => 0,
+        Day::Tomorrow => 1,
+    };
+    let daily = &report.daily;
+    let (Some(&code), Some(&high), Some(&low), Some(&rain)) = (
+        daily.weather_code.get(index),
+        daily.temperature_2m_max.get(index),
+        daily.temperature_2m_min.get(index),
+        daily.precipitation_sum.get(index),
+    ) else {
+        return Err(anyhow!("Open-Meteo sent no forecast for that day"));
+    };
+    let chance = daily
+        .precipitation_probability_max
+        .get(index)
+        .copied()
+        .flatten();
+    let mut sentences = Vec::new();
+    if day == Day::Today {
+        let mut place = place.to_string();
+        if let Some(first) = place.get(..1) {
+            place.replace_range(..1, &first.to_uppercase());
+        }
+        let now = &report.current;
+        sentences.push(format!(
+            "{place} it's {} and {}.",
+            degrees(now.temperature_2m),
+            condition(now.weather_code)
+        ));
+        let windy = match units {
+            Units::Metric => now.wind_speed_10m >= WINDY_KMH,
+            Units::Imperial => now.wind_speed_10m * 1.609 >= WINDY_KMH,
+        };
+        if windy {
+            let speed = match units {
+                Units::Metric => "kilometres per hour",
+                Units::Imperial => "miles per hour",
+            };
+            sentences.push(format!(
+                "It's windy, {} {speed}.",
+                now.wind_speed_10m.round()
+            ));
+        }
+    }
+    let which = match day {
+        Day::Today => "Today".to_string(),
+        Day::Tomorrow => format!("Tomorrow {place}"),
+    };
+    let mut outlook = format!(
+        "{which}: {}, from {} to {}",
+        condition(code),
+        number(low),
+        degrees(high)
+    );
+    match chance {
+        Some(chance) if chance >= 10.0 => {
+            outlook.push_str(&format!(
+                ", with a {} percent chance of rain",
+                chance.round()
+            ));
+            if rain >= 0.1 {
+                let amount = match units {
+                    Units::Metric => format!("{} millimetres", rain.round().max(1.0)),
+                    Units::Imperial => format!("{rain:.1} inches"),
+                };
+                outlook.push_str(&format!(" and about {amount}"));
+            }
+        }
+        _ if rain >= 0.1 => outlook.push_str(", with some rain"),
+        _ => outlook.push_str(", and no rain expected"),
+    }
+    sentences.push(format!("{outlook}."));
+    Ok(sentences.join(" "))
+}
+
+/// A temperature read out: "minus 3 degrees".
+fn degrees(value: f64) -> String {
+    format!("{} degrees", number(value))
+}
+
+fn number(value: f64) -> String {
+    let rounded = value.round() as i64;
+    if rounded < 0 {
+        format!("minus {}", -rounded)
+    } else {
+        rounded.to_string()
+    }
+}
+
+/// The sky described by a WMO weather code, as used by Open-Meteo.
+pub fn condition(code: u8) -> &'static str {
+    match code {
+        0 => "clear",
+        1 => "mostly clear",
+        2 => "partly cloudy",
+        3 => "overcast",
+        45 | 48 => "foggy",
+        51 | 53 | 55 => "drizzle",
+        56 | 57 => "freezing drizzle",
+        61 => "light rain",
+        63 => "rain",
+        65 => "heavy rain",
+        66 | 67 => "freezing rain",
+        71 => "light snow",
+        73 => "snow",
+        75 => "heavy snow",
+        77 => "snow grains",
+        80 | 81 => "rain showers",
+        82 => "violent rain showers",
+        85 | 86 => "snow showers",
+        95 => "thunderstorms",
+        96 | 99 => "thunderstorms with hail",
+        _ => "changeable weather",
+    }
+}
@@ -0,0 +1,267 @@
+//! Facts from Wikipedia.
+//!
+//! `wiki_lookup` answers "who was Nikola Tesla?" or "how tall is Mount
+//! Everest?" from the introduction of the matching Wikipedia article
+//! instead of from what a small local model half remembers. The article
+//! is found with Wikipedia's search, and its first few sentences
+//! (`WIKI_SENTENCES`, default 3), without the parentheses that hold
+//! pronunciations and alternative names, are what the model gets.
+//! `WIKI_LANGUAGE` (default `en`) is the edition to search, and the model
+//! may ask for another one, such as `hr`. Needs Jarvis built with
+//! `--features wiki`.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{off_runtime, Tool};
+use crate::config::env_parse;
+
+/// Abbreviations whose full stop does not end a sentence.
+const ABBREVIATIONS: &[&str] = &[
+    "Mr.", "Mrs.", "Ms.", "Dr.", "St.", "Jr.", "Sr.", "Prof.", "vs.", "ca.", "approx.", "etc.",
+    "No.",
+];
+
+#[derive(Debug, Deserialize)]
+struct Search {
+    query: SearchQuery,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    search: Vec<SearchResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    title: String,
+}
+
+/// The page summary of the REST API, as far as it is used.
+#[derive(Debug, Deserialize)]
+struct Summary {
+    title: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    extract: String,
+}
+
+/// Looks up the introductions of Wikipedia articles.
+#[derive(Debug, Clone)]
+pub struct WikiTool {
+    /// The edition searched by default, such as `en`.
+    language: String,
+    sentences: usize,
+}
+
+impl WikiTool {
+    pub fn new(language: impl Into<String>, sentences: usize) -> Self {
+        Self {
+            language: language.into(),
+            sentences: sentences.max(1),
+        }
+    }
+
+    /// The tool for `WIKI_LANGUAGE` and `WIKI_SENTENCES`.
+    pub fn from_env() -> Self {
+        let language = std::env::var("WIKI_LANGUAGE")
+            .ok()
+            .map(|language| language.trim().to_lowercase())
+            .filter(|language| !language.is_empty())
+            .unwrap_or_else(|| "en".to_string());
+        Self::new(language, env_parse("WIKI_SENTENCES", 3))
+    }
+
+    /// The introduction of the article that best matches `query` in the
+    /// `language` edition, ready to be spoken.
+    fn lookup(&self, query: &str, language: &str) -> Result<String> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            // Wikimedia asks clients to say who they are.
+            .user_agent(concat!("jarvis_rust/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("failed to create HTTP client")?;
+        let base = format!("https://{language}.wikipedia.org");
+        let found: Search = client
+            .get(format!("{base}/w/api.php"))
+            .query(&[
+                ("action", "query"),
+                ("list", "search"),
+                ("srsearch", query),
+                ("srlimit", "1"),
+                ("format", "json"),
+            ])
+            .send()?
+            .error_for_status()?
+            .json()
+            .context("unexpected answer from Wikipedia")?;
+        let Some(result) = found.query.search.into_iter().next() else {
+            return Ok(format!("Wikipedia has no article about {query}."));
+        };
+        let title = result.title.replace(' ', "_");
+        let summary: Summary = client
+            .get(format!(
+                "{base}/api/rest_v1/page/summary/{}",
+                path_segment(&title)
+            ))
+            .send()?
+            .error_for_status()?
+            .json()
+            .context("unexpected answer from Wikipedia")?;
+        if summary.kind == "disambiguation" {
+            return Ok(format!(
+                "{} can mean several things on Wikipedia; ask about one of them.",
+                summary.title
+            ));
+        }
+        Ok(spoken_intro(
+            &summary.title,
+            &summary.extract,
+            self.sentences,
+        ))
+    }
+}
+
+impl Tool for WikiTool {
+    fn name(&self) -> &str {
+        "wiki_lookup"
+    }
+
+    fn description(&self) -> &str {
+        "Use `wiki_lookup` for facts about people, places, things and events, from the introduction of the Wikipedia article about them."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "What to look up, such as a name or a topic.",
+                },
+                "language": {
+                    "type": "string",
+                    "description": format!(
+                        "The Wikipedia language code, such as en or de; {} by default.",
+                        self.language
+                    ),
+                },
+            },
+            "required": ["query"],
+        })
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        let Some(query) = arguments
+            .get("query")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|query| !query.is_empty())
+        else {
+            return Ok("Nothing to look up.".to_string());
+        };
+        let language = arguments
+            .get("language")
+            .and_then(Value::as_str)
+            .map(|language| language.trim().to_lowercase())
+            .filter(|language| is_language_code(language))
+            .unwrap_or_else(|| self.language.clone());
+        off_runtime("the Wikipedia request", || self.lookup(query, &language))
+            .or_else(|e| Ok(format!("I couldn't look that up: {e:#}.")))
+    }
+}
+
+/// Whether `code` looks like a Wikipedia edition, such as `en` or
+/// `zh-yue`, and not like a way to reach another host.
+fn is_language_code(code: &str) -> bool {
+    (2..=12).contains(&code.len()) && code.chars().all(|c| c.is_ascii_lowercase() || c == '-')
+}
+
+/// `title` with the characters a URL path cannot hold escaped.
+fn path_segment(title: &str) -> String {
+    let mut escaped = String::new();
+    for byte in title.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'~' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    escaped
+}
+
+/// The first `sentences` sentences of `extract`, the introduction of the
+/// article `title`, without parentheses: "Nikola Tesla was a
+/// Serbian-American engineer…".
+pub fn spoken_intro(title: &str, extract: &str, sentences: usize) -> String {
+    let text = without_parentheses(extract);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let intro = first_sentences(&text, sentences);
+    if intro.is_empty() {
+        format!("The Wikipedia article {title} has no introduction.")
+    } else {
+        intro
+    }
+}
+
+/// `text` without anything in parentheses, and without the space before
+/// them.
+fn without_parentheses(text: &str) -> String {
+    let mut kept = String::new();
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '(' => {
+                if depth == 0 {
+                    let trimmed = kept.trim_end().len();
+                    kept.truncate(trimmed);
+                }
+                depth += 1;
+            }
+            ')' if depth > 0 => depth -= 1,
+            c if depth == 0 => kept.push(c),
+            _ => {}
+        }
+    }
+    kept
+}
+
+/// The first `count` sentences of `text`. A full stop after an initial
+/// ("J. R. R. Tolkien"), in a dotted abbreviation ("U.S.") or after a
+/// common one ("Dr.") does not end one.
+pub fn first_sentences(text: &str, count: usize) -> String {
+    let mut found = 0;
+    let mut end = text.len();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+        let at_end = match chars.peek() {
+            None => true,
+            Some((_, next)) => next.is_whitespace(),
+        };
+        if !at_end {
+            continue;
+        }
+        let word = text[..=i].rsplit(' ').next().unwrap_or_default();
+        let initial = word.chars().filter(|c| c.is_alphabetic()).count() == 1;
+        let dotted = word[..word.len() - 1].contains('.');
+        if c == '.' && (initial || dotted || ABBREVIATIONS.contains(&word)) {
+            continue;
+        }
+        found += 1;
+        if found == count {
+            end = i + c.len_utf8();
+            break;
+        }
+    }
+    text[..end].trim().to_string()
+}
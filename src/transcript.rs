@@ -0,0 +1,118 @@
+//! Filtering of raw speech recogniser transcripts.
+//!
+//! Vosk occasionally produces spurious output when the microphone is quiet:
+//! single tokens such as "the" or "uh", and on some models whole phrases
+//! such as "thank you". [`TranscriptFilter`] removes these before the text
+//! reaches wake word detection or the agent. Both lists are configurable:
+//!
+//!  * `NOISE_WORDS` (optional): comma separated tokens trimmed from the
+//!    start and end of a transcript. Defaults to `the,uh,um,a`.
+//!  * `NOISE_PHRASES` (optional): comma separated phrases. A transcript
+//!    consisting only of one of these phrases is treated as silence.
+//!    Defaults to `thank you`.
+//!
+//! Noise words are never removed from the middle of a command, and
+//! blacklisted phrases only match when they make up the entire transcript,
+//! so legitimate commands that contain them are unaffected.
+//!
+//! Commands are additionally checked for plausibility before they are sent
+//! to the language model (see [`TranscriptFilter::is_plausible`]):
+//!
+//!  * `ASR_MIN_CONFIDENCE` (optional): mean word confidence below which a
+//!    command is considered gibberish. Defaults to `0.4`.
+//!  * `ASR_RETRY_LIMIT` (optional): how many times Jarvis asks the user to
+//!    repeat an implausible command before giving up. Defaults to `2`.
+//!  * `ASR_RETRY_PROMPT` (optional): what Jarvis says when asking the user
+//!    to repeat. Defaults to "Sorry, say that again?".
+
+use crate::config::{env_list, env_parse};
+
+const DEFAULT_NOISE_WORDS: &[&str] = &["the", "uh", "um", "a"];
+const DEFAULT_NOISE_PHRASES: &[&str] = &["thank you"];
+
+/// Configurable noise-word and phrase blacklist.
+pub struct TranscriptFilter {
+    noise_words: Vec<String>,
+    noise_phrases: Vec<String>,
+    min_confidence: f32,
+    /// Maximum number of "say that again" prompts for a single command.
+    pub retry_limit: u32,
+    /// Phrase spoken when asking the user to repeat themselves.
+    pub retry_prompt: String,
+}
+
+impl TranscriptFilter {
+    /// Build a filter from the `NOISE_WORDS` and `NOISE_PHRASES`
+    /// environment variables.
+    pub fn from_env() -> Self {
+        Self {
+            noise_words: env_list("NOISE_WORDS", DEFAULT_NOISE_WORDS)
+                .into_iter()
+                .map(|w| w.to_lowercase())
+                .collect(),
+            noise_phrases: env_list("NOISE_PHRASES", DEFAULT_NOISE_PHRASES)
+                .into_iter()
+                .map(|p| normalise(&p))
+                .collect(),
+            min_confidence: env_parse("ASR_MIN_CONFIDENCE", 0.4),
+            retry_limit: env_parse("ASR_RETRY_LIMIT", 2),
+            retry_prompt: std::env::var("ASR_RETRY_PROMPT")
+                .unwrap_or_else(|_| "Sorry, say that again?".to_string()),
+        }
+    }
+
+    fn is_noise_word(&self, token: &str) -> bool {
+        let token = token.to_lowercase();
+        self.noise_words.contains(&token)
+    }
+
+    /// Clean a transcript. Leading and trailing noise words are trimmed and
+    /// the result is emptied if it matches a blacklisted phrase. An empty
+    /// return value should be treated as silence.
+    pub fn clean(&self, text: &str) -> String {
+        let mut tokens: Vec<&str> = text.split_whitespace().collect();
+        while tokens.first().is_some_and(|t| self.is_noise_word(t)) {
+            tokens.remove(0);
+        }
+        while tokens.last().is_some_and(|t| self.is_noise_word(t)) {
+            tokens.pop();
+        }
+        let cleaned = tokens.join(" ");
+        let normalised = normalise(&cleaned);
+        if self.noise_phrases.contains(&normalised) {
+            log::debug!("Dropping blacklisted phrase: {}", cleaned);
+            return String::new();
+        }
+        cleaned
+    }
+
+    /// Basic plausibility check for a cleaned command. A transcript is
+    /// rejected when the recogniser's mean confidence is below
+    /// `ASR_MIN_CONFIDENCE`, or when it contains no word-like token (at
+    /// least two letters and not a noise word). Forwarding such text to
+    /// the language model tends to produce invented tool calls.
+    pub fn is_plausible(&self, text: &str, confidence: Option<f32>) -> bool {
+        if let Some(conf) = confidence {
+            if conf < self.min_confidence {
+                log::debug!("Transcript '{}' rejected: confidence {:.2}", text, conf);
+                return false;
+            }
+        }
+        let has_word = text.split_whitespace().any(|t| {
+            t.chars().filter(|c| c.is_alphabetic()).count() >= 2 && !self.is_noise_word(t)
+        });
+        if !has_word {
+            log::debug!("Transcript '{}' rejected: no dictionary words", text);
+        }
+        has_word
+    }
+}
+
+/// Lower-case and collapse whitespace so phrase comparison ignores
+/// formatting differences.
+fn normalise(text: &str) -> String {
+    text.split_whitespace()
+        .map(|t| t.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
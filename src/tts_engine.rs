@@ -1,149 +1,484 @@
-//! Text‑to‑speech abstraction built on top of the [`tts`] crate.
+//! Text‑to‑speech abstraction.
 //!
-//! The Python implementation of Jarvis relied on RHVoice for TTS. In Rust we
-//! take advantage of the [`tts`] crate, which delegates synthesis to the
-//! underlying operating system (Speech Dispatcher on Linux, SAPI on
-//! Windows, AVFoundation on macOS). This module exposes a simple
-//! [`TtsEngine`] type that can speak arbitrary strings and optionally
-//! select a voice by name.
+//! Speech output goes through the [`Speaker`] trait. The backend is chosen
+//! with the `VOICE_ENGINE` environment variable:
+//!
+//!  * `system` (default): the operating system's voices via the [`tts`]
+//!    crate (Speech Dispatcher on Linux, SAPI on Windows, AVFoundation on
+//!    macOS). See [`system`].
+//!  * `rhvoice`: the RHVoice command line program used by the original
+//!    Python prototype. See [`rhvoice`].
+//!  * `piper`: the Piper neural TTS. See [`piper`].
+//!  * `espeak`: eSpeak NG, for small devices. See [`espeak`].
+//!  * `command`: any external program configured with `TTS_COMMAND`. See
+//!    [`command`].
+//!  * `azure`, `google`, `elevenlabs`: network services, only available
+//!    with the `cloud-tts` feature. See `cloud`.
+//!
+//! [`TtsEngine`] picks the backend and adds the accessibility text mirror,
+//! a pronunciation dictionary (see [`pronunciation`]) and an utterance
+//! queue on top of it. Dates, times and units are spelled out in words (see
+//! [`normalize`]), and other applications can be turned down while Jarvis
+//! speaks (see [`ducking`]). Long replies are spoken one sentence at a time
+//! (see [`sentences`]) so that audio starts as soon as the first sentence
+//! has been synthesised. Replies may contain SSML markup, which is
+//! converted to plain text for engines that do not support it (see
+//! [`ssml`]). Each reply can be spoken with a voice matching its language
+//! (see [`language`]).
+//!
+//! Speaking rate, pitch and volume are given relative to the engine's
+//! defaults, where `1.0` is normal (see [`Prosody`]):
+//!
+//!  * `TTS_RATE` (optional): `0.5` is half speed, `2.0` double speed.
+//!  * `TTS_PITCH` (optional): `0.5` to `2.0`.
+//!  * `TTS_VOLUME` (optional): `0.0` (silent) to `2.0`; most engines cannot
+//!    go louder than their default.
+//!
+//! They can also be changed at runtime by saying, for example, "speak
+//! slower", "louder" or "normal voice".
 
-use anyhow::{anyhow, Context, Result};
-use tts::Tts;
+pub mod cache;
+#[cfg(feature = "cloud-tts")]
+pub mod cloud;
+pub mod command;
+pub mod ducking;
+pub mod espeak;
+pub mod language;
+pub mod normalize;
+pub mod paging;
+pub mod piper;
+pub mod pronunciation;
+pub mod queue;
+pub mod rhvoice;
+pub mod sentences;
+pub mod ssml;
+pub mod system;
 
-/// Wrapper around the [`tts`] crate providing convenience methods for
-/// speaking text and selecting a specific voice.
-use tokio::process::{Child, Command};
+use std::path::Path;
 
-pub struct TtsEngine {
-    tts: Tts,
-    /// Whether to use the external RHVoice CLI instead of the built‑in
-    /// `tts` crate. This is configured via the VOICE_ENGINE
-    /// environment variable. If set to "rhvoice" (case insensitive),
-    /// Jarvis will spawn the `rhvoice-test` command for speech
-    /// synthesis. This can reduce latency on some systems and
-    /// matches the behaviour of the original Python prototype.
-    use_rhvoice: bool,
-    /// Handle to the currently running RHVoice process, if any. When
-    /// speaking a new utterance we terminate the previous process.
-    rhvoice_process: Option<Child>,
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::accessibility::AccessibilityOutput;
+use crate::audio_output::{Audio, AudioOutput};
+use crate::config::env_parse;
+use crate::events::EventBus;
+
+use command::CommandSpeaker;
+use espeak::EspeakSpeaker;
+use piper::PiperSpeaker;
+use queue::{SpeechHandle, SpeechQueue};
+use rhvoice::RhVoiceSpeaker;
+use system::SystemSpeaker;
+
+/// A voice offered by a [`Speaker`].
+#[derive(Debug, Clone)]
+pub struct VoiceInfo {
+    /// Name accepted by [`Speaker::set_voice`].
+    pub name: String,
+    pub language: Option<String>,
+    pub gender: Option<String>,
 }
 
-impl TtsEngine {
-    /// Create a new TTS engine. Internally this initialises the system
-    /// speech synthesis backend. If no backend is available on the host
-    /// platform this will return an error.
-    pub fn new() -> Result<Self> {
-        // Check the VOICE_ENGINE environment variable. If set to
-        // "rhvoice" (case insensitive), we'll use the rhvoice-test
-        // command for synthesis. Otherwise fall back to the `tts` crate.
-        let use_rhvoice = std::env::var("VOICE_ENGINE")
-            .map(|v| v.to_lowercase() == "rhvoice")
-            .unwrap_or(false);
-        let tts = Tts::default().context("failed to initialise text‑to‑speech engine")?;
-        Ok(Self {
-            tts,
-            use_rhvoice,
-            rhvoice_process: None,
-        })
-    }
-
-    /// Stop any ongoing speech, either internal TTS or external RHVoice process.
-    pub async fn stop(&mut self) -> Result<()> {
-        if self.use_rhvoice {
-            if let Some(child) = self.rhvoice_process.as_mut() {
-                let _ = child.kill().await;
-            }
-            self.rhvoice_process = None;
-        } else {
-            // Stop any ongoing utterances.
-            self.tts
-                .stop()
-                .map_err(|e| anyhow!(format!("Failed to stop TTS: {:?}", e)))?;
+/// Something that can say text out loud.
+///
+/// [`TtsEngine`] is the default implementation. Embedders can provide their
+/// own through [`JarvisBuilder::with_tts`](crate::pipeline::JarvisBuilder::with_tts).
+#[async_trait]
+pub trait Speaker: Send {
+    /// Speak the provided text, returning once playback has finished.
+    /// Existing speech is interrupted.
+    async fn speak(&mut self, text: &str) -> Result<()>;
+    /// Stop any ongoing speech.
+    async fn stop(&mut self) -> Result<()>;
+    /// Synthesise `text` into memory instead of playing it. Used to route
+    /// speech to a specific output device (see [`crate::audio_output`]).
+    async fn synthesize(&mut self, _text: &str) -> Result<Audio> {
+        bail!("this TTS engine cannot synthesise audio into memory")
+    }
+    /// Synthesise `text` and save it to `path` (see [`Audio::save`] for
+    /// the supported formats) instead of playing it.
+    async fn speak_to_file(&mut self, text: &str, path: &Path) -> Result<()> {
+        self.synthesize(text).await?.save(path)
+    }
+    /// Speak a phrase that is said often, such as an acknowledgement.
+    /// Implementations may cache its audio; the default just speaks it.
+    async fn speak_cached(&mut self, text: &str) -> Result<()> {
+        self.speak(text).await
+    }
+    /// Interrupt whatever is being spoken and speak `text` instead.
+    async fn speak_now(&mut self, text: &str) -> Result<()> {
+        self.stop().await?;
+        self.speak(text).await
+    }
+    /// Speak `text`, stopping as soon as `cancel` is cancelled.
+    async fn speak_cancellable(&mut self, text: &str, cancel: &CancellationToken) -> Result<()> {
+        tokio::select! {
+            result = self.speak(text) => result,
+            _ = cancel.cancelled() => self.stop().await,
         }
-        Ok(())
     }
+    /// Choose a voice by name. If a matching voice cannot be found the
+    /// previous voice remains active and an error is returned.
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        Err(anyhow!(format!(
+            "this speaker cannot select voice '{name}'"
+        )))
+    }
+    /// The voices this speaker can switch to.
+    fn list_voices(&self) -> Result<Vec<VoiceInfo>> {
+        Ok(Vec::new())
+    }
+    /// Set the speaking rate relative to normal (`1.0`).
+    fn set_rate(&mut self, _rate: f32) -> Result<()> {
+        Err(anyhow!("this speaker cannot change its rate"))
+    }
+    /// Set the pitch relative to normal (`1.0`).
+    fn set_pitch(&mut self, _pitch: f32) -> Result<()> {
+        Err(anyhow!("this speaker cannot change its pitch"))
+    }
+    /// Set the volume relative to normal (`1.0`).
+    fn set_volume(&mut self, _volume: f32) -> Result<()> {
+        Err(anyhow!("this speaker cannot change its volume"))
+    }
+    /// Whether [`speak`](Self::speak) accepts SSML markup (see [`ssml`]).
+    /// Speakers that do not are given plain text instead.
+    fn supports_ssml(&self) -> bool {
+        false
+    }
+}
+
+/// Speaking rate, pitch and volume as multiples of the engine's normal
+/// values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prosody {
+    pub rate: f32,
+    pub pitch: f32,
+    pub volume: f32,
+}
 
-    /// Choose a voice by name. The supplied name is matched case
-    /// insensitively against the available voices. If a matching voice
-    /// cannot be found the previous voice remains active and an error is
-    /// returned.
-    pub fn set_voice_by_name(&mut self, name: &str) -> Result<()> {
-        // If using RHVoice CLI we cannot programmatically select a
-        // voice through the `tts` crate. The CLI will use its default
-        // voice or the voice specified via command line arguments. In
-        // this case we ignore the requested voice and return Ok.
-        if self.use_rhvoice {
-            return Ok(());
+impl Default for Prosody {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            pitch: 1.0,
+            volume: 1.0,
         }
-        let available = self.tts.voices().context("failed to enumerate voices")?;
-        let target = name.to_lowercase();
-        for voice in available {
-            // Voice names are accessed via the `name()` method. Compare
-            // case‑insensitively against the requested string.
-            if voice.name().to_lowercase().contains(&target) {
-                // `set_voice` expects a reference to the desired voice.
-                self.tts
-                    .set_voice(&voice)
-                    .context("failed to set TTS voice")?;
-                return Ok(());
-            }
+    }
+}
+
+impl Prosody {
+    /// Read `TTS_RATE`, `TTS_PITCH` and `TTS_VOLUME`.
+    pub fn from_env() -> Self {
+        Self {
+            rate: env_parse("TTS_RATE", 1.0),
+            pitch: env_parse("TTS_PITCH", 1.0),
+            volume: env_parse("TTS_VOLUME", 1.0),
         }
-        Err(anyhow!(format!("no voice matching '{name}' found")))
-    }
-
-    /// Speak the provided text. Existing speech will be interrupted if it
-    /// is still playing. This method is asynchronous because the call to
-    /// [`tts::Tts::speak`] blocks until the underlying OS has queued the
-    /// utterance. We use `spawn_blocking` so as not to stall the Tokio
-    /// executor while synthesis takes place.
-    pub async fn speak(&mut self, text: &str) -> Result<()> {
-        // If using RHVoice CLI, spawn an external process to speak.
-        if self.use_rhvoice {
-            // Terminate any existing process if it is still running.
-            if let Some(child) = self.rhvoice_process.as_mut() {
-                let _ = child.kill().await;
+        .clamped()
+    }
+
+    fn clamped(self) -> Self {
+        Self {
+            rate: self.rate.clamp(0.5, 2.0),
+            pitch: self.pitch.clamp(0.5, 2.0),
+            volume: self.volume.clamp(0.0, 2.0),
+        }
+    }
+
+    /// Apply all three settings to `speaker`. Settings the speaker does
+    /// not support are logged and skipped, unless they are at their
+    /// normal value anyway.
+    pub fn apply(&self, speaker: &mut dyn Speaker) {
+        let results = [
+            ("rate", self.rate, speaker.set_rate(self.rate)),
+            ("pitch", self.pitch, speaker.set_pitch(self.pitch)),
+            ("volume", self.volume, speaker.set_volume(self.volume)),
+        ];
+        for (what, value, result) in results {
+            if let Err(e) = result {
+                if value != 1.0 {
+                    log::warn!("Failed to set speech {what} to {value}: {e}");
+                }
             }
-            // Spawn the rhvoice-test process. We pass the "slt" voice by
-            // default to approximate the Python implementation. You can
-            // customise this by changing the argument or by setting
-            // environment variables in the future.
-            let mut cmd = Command::new("/snap/bin/rhvoice.test");
-            cmd.arg("-p").arg("slt").stdin(std::process::Stdio::piped());
-            let mut child = cmd.spawn().context("failed to spawn RHVoice process")?;
-            if let Some(mut stdin) = child.stdin.take() {
-                use tokio::io::AsyncWriteExt;
-                stdin
-                    .write_all(text.as_bytes())
-                    .await
-                    .context("failed to write to RHVoice stdin")?;
-                // Close stdin to let rhvoice know the input is complete.
-                stdin.shutdown().await.ok();
+        }
+    }
+
+    /// Interpret short spoken requests such as "speak slower" or "a bit
+    /// louder". Returns the adjusted settings and a confirmation to speak,
+    /// or `None` if `text` is not such a request.
+    pub fn adjust_for_command(&self, text: &str) -> Option<(Prosody, &'static str)> {
+        let lower = text.to_lowercase();
+        // Longer sentences are real commands that merely mention speech,
+        // e.g. "write a script that makes the fan spin faster".
+        if lower.split_whitespace().count() > 6 {
+            return None;
+        }
+        let has = |phrases: &[&str]| phrases.iter().any(|p| lower.contains(p));
+        let mut next = *self;
+        let reply = if has(&["normal voice", "reset voice", "normal speed"]) {
+            next = Prosody::default();
+            "Back to my normal voice."
+        } else if has(&["higher pitch", "pitch up", "raise your pitch"]) {
+            next.pitch *= 1.15;
+            "Raising my pitch."
+        } else if has(&["lower pitch", "pitch down", "deeper"]) {
+            next.pitch /= 1.15;
+            "Lowering my pitch."
+        } else if has(&["slower", "slow down"]) {
+            next.rate /= 1.25;
+            "Okay, I'll speak slower."
+        } else if has(&["faster", "speed up"]) {
+            next.rate *= 1.25;
+            "Okay, I'll speak faster."
+        } else if has(&["louder", "speak up", "volume up"]) {
+            next.volume *= 1.25;
+            "Okay, louder."
+        } else if has(&["quieter", "softer", "volume down"]) {
+            next.volume /= 1.25;
+            "Okay, quieter."
+        } else {
+            return None;
+        };
+        Some((next.clamped(), reply))
+    }
+}
+
+/// A speaker that discards all text. Used when running without audio
+/// output, e.g. in the headless text REPL.
+pub struct SilentSpeaker;
+
+#[async_trait]
+impl Speaker for SilentSpeaker {
+    async fn speak(&mut self, _text: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_voice(&mut self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_rate(&mut self, _rate: f32) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, _pitch: f32) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_volume(&mut self, _volume: f32) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Every value `VOICE_ENGINE` accepts.
+pub const ENGINES: &[&str] = &[
+    "system",
+    "rhvoice",
+    "piper",
+    "espeak",
+    "command",
+    "azure",
+    "google",
+    "elevenlabs",
+];
+
+/// The offline backend called `engine`; see the list at the top of this
+/// module.
+fn local_backend(engine: &str) -> Result<Box<dyn Speaker>> {
+    Ok(match engine {
+        "" | "system" | "tts" => Box::new(SystemSpeaker::new()?),
+        "rhvoice" => Box::new(RhVoiceSpeaker::from_env()),
+        "piper" => Box::new(PiperSpeaker::from_env()?),
+        "espeak" | "espeak-ng" => Box::new(EspeakSpeaker::from_env()),
+        "command" => Box::new(CommandSpeaker::from_env()?),
+        other => bail!(
+            "unknown VOICE_ENGINE '{other}' (expected system, rhvoice, piper, espeak, command, azure, google or elevenlabs)"
+        ),
+    })
+}
+
+/// The configured speech backend, the accessibility mirror and a queue of
+/// utterances (see [`queue`]).
+///
+/// Clones share the same queue, so other parts of an application can hold
+/// a clone to [`speak`](Self::speak) notifications without waiting for the
+/// current reply to finish.
+#[derive(Clone)]
+pub struct TtsEngine {
+    queue: SpeechQueue,
+}
+
+impl TtsEngine {
+    /// Create the backend selected by `VOICE_ENGINE`.
+    pub fn new() -> Result<Self> {
+        let engine = std::env::var("VOICE_ENGINE")
+            .map(|v| v.to_lowercase())
+            .unwrap_or_default();
+        Self::for_engine(if engine.is_empty() { "system" } else { &engine })
+    }
+
+    /// Create the backend named `engine`, one of [`ENGINES`], as if it were
+    /// selected by `VOICE_ENGINE`.
+    pub fn for_engine(engine: &str) -> Result<Self> {
+        let backend: Box<dyn Speaker> = match engine {
+            #[cfg(feature = "cloud-tts")]
+            "azure" | "google" | "elevenlabs" => Box::new(cloud::CloudSpeaker::from_env(engine)?),
+            #[cfg(not(feature = "cloud-tts"))]
+            "azure" | "google" | "elevenlabs" => {
+                bail!(
+                    "VOICE_ENGINE={engine} requires Jarvis to be built with `--features cloud-tts`"
+                )
             }
-            // Store the handle so that cancellation can stop the process,
-            // then await completion of the speech process.
-            self.rhvoice_process = Some(child);
-            if let Some(child) = self.rhvoice_process.as_mut() {
-                let _ = child.wait().await;
+            local => local_backend(local)?,
+        };
+        Ok(Self::assemble(engine, backend, AudioOutput::from_env()?))
+    }
+
+    /// Create the engine and apply `VOICE_NAME` and the `TTS_RATE`,
+    /// `TTS_PITCH` and `TTS_VOLUME` settings. A voice that cannot be found
+    /// is logged and the engine's default voice is kept.
+    pub fn from_env() -> Result<Self> {
+        let mut tts = Self::new()?;
+        if let Ok(name) = std::env::var("VOICE_NAME") {
+            match tts.set_voice(&name) {
+                Ok(_) => log::info!("Using voice: {}", name),
+                Err(e) => log::warn!(
+                    "Failed to set voice '{}': {e}. Falling back to default.",
+                    name
+                ),
             }
-            self.rhvoice_process = None;
-            return Ok(());
         }
+        Prosody::from_env().apply(&mut tts);
+        Ok(tts)
+    }
+
+    /// Wrap an existing backend, adding the accessibility mirror and
+    /// output device selection.
+    pub fn with_backend(backend: Box<dyn Speaker>) -> Self {
+        let output = AudioOutput::from_env().unwrap_or_else(|e| {
+            log::warn!("Ignoring speaker selection: {e:#}");
+            None
+        });
+        Self::assemble("custom", backend, output)
+    }
+
+    fn assemble(engine: &str, backend: Box<dyn Speaker>, output: Option<AudioOutput>) -> Self {
+        Self {
+            queue: SpeechQueue::new(engine, backend, AccessibilityOutput::from_env(), output),
+        }
+    }
+
+    /// Queue `text` after everything already waiting and return without
+    /// waiting for playback. Await the handle to wait until it has been
+    /// spoken, interrupted or dropped.
+    pub fn speak(&self, text: &str) -> SpeechHandle {
+        self.queue.enqueue(text, false)
+    }
+
+    /// Like [`speak`](Self::speak), but the utterance is also stopped, or
+    /// skipped if it has not started yet, once `cancel` is cancelled.
+    pub fn speak_cancellable(&self, text: &str, cancel: &CancellationToken) -> SpeechHandle {
+        self.queue.enqueue_cancellable(text, cancel)
+    }
+
+    /// Interrupt the current utterance and speak `text` straight away.
+    /// Queued utterances follow afterwards.
+    pub fn speak_now(&self, text: &str) -> SpeechHandle {
+        self.queue.speak_now(text)
+    }
+
+    /// Publish `SpeakingStarted` and `SpeakingFinished` events for every
+    /// utterance on `events`.
+    pub fn set_event_bus(&self, events: EventBus) {
+        self.queue.set_event_bus(events);
+    }
+
+    /// Drop all queued utterances and stop the one being spoken.
+    pub fn clear_queue(&self) {
+        self.queue.clear();
+    }
+
+    /// Run `f` on the backend. Voice settings cannot change in the middle
+    /// of an utterance, so this fails while something is being spoken.
+    fn configure<T>(&self, f: impl FnOnce(&mut dyn Speaker) -> Result<T>) -> Result<T> {
+        let mut backend = self
+            .queue
+            .try_backend()
+            .ok_or_else(|| anyhow!("cannot change voice settings while speaking"))?;
+        f(backend.as_mut())
+    }
+}
+
+#[async_trait]
+impl Speaker for TtsEngine {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        TtsEngine::speak(self, text).await
+    }
+
+    async fn speak_cached(&mut self, text: &str) -> Result<()> {
+        self.queue.enqueue(text, true).await
+    }
+
+    async fn speak_now(&mut self, text: &str) -> Result<()> {
+        TtsEngine::speak_now(self, text).await
+    }
+
+    async fn speak_cancellable(&mut self, text: &str, cancel: &CancellationToken) -> Result<()> {
+        TtsEngine::speak_cancellable(self, text, cancel).await
+    }
+
+    /// Waits for the current utterance to finish, so that the file and the
+    /// speakers never compete for the engine.
+    async fn speak_to_file(&mut self, text: &str, path: &Path) -> Result<()> {
+        let text = self.queue.prepare(text);
+        self.queue.backend().await.speak_to_file(&text, path).await
+    }
+
+    /// Stopping also clears the queue, so cancelling a reply does not
+    /// leave older messages to be spoken afterwards.
+    async fn stop(&mut self) -> Result<()> {
+        self.clear_queue();
+        Ok(())
+    }
+
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        self.configure(|backend| backend.set_voice(name))?;
+        self.queue.set_voice_setting("voice", name.to_string());
+        Ok(())
+    }
+
+    fn list_voices(&self) -> Result<Vec<VoiceInfo>> {
+        self.configure(|backend| backend.list_voices())
+    }
 
-        // Default path: use the built‑in TTS engine via the tts crate. We
-        // clone the engine and speak on a blocking thread to avoid
-        // stalling the async runtime.
-        let text_owned = text.to_owned();
-        let tts = self.tts.clone();
-        tokio::task::spawn_blocking(move || {
-            let mut tts = tts;
-            // Stop any existing utterances. Ignore errors here since we
-            // immediately follow with a new speak call.
-            let _ = tts.stop();
-            tts.speak(&text_owned, true)
-                .map_err(|e| anyhow!(format!("TTS speak failed: {e:?}")))
-        })
-        .await
-        .context("failed to join blocking TTS task")??;
+    fn set_rate(&mut self, rate: f32) -> Result<()> {
+        self.configure(|backend| backend.set_rate(rate))?;
+        self.queue.set_voice_setting("rate", format!("{rate:.2}"));
         Ok(())
     }
+
+    fn set_pitch(&mut self, pitch: f32) -> Result<()> {
+        self.configure(|backend| backend.set_pitch(pitch))?;
+        self.queue.set_voice_setting("pitch", format!("{pitch:.2}"));
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<()> {
+        self.configure(|backend| backend.set_volume(volume))?;
+        self.queue
+            .set_voice_setting("volume", format!("{volume:.2}"));
+        Ok(())
+    }
+
+    /// SSML is always accepted; it is stripped for backends that cannot
+    /// handle it.
+    fn supports_ssml(&self) -> bool {
+        true
+    }
 }
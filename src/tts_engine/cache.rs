@@ -0,0 +1,94 @@
+//! On-disk cache of synthesised audio for fixed phrases.
+//!
+//! Phrases such as "Yes sir?", "Going silent." or the timeout prompt are
+//! spoken over and over. Slower engines like Piper and RHVoice take a
+//! noticeable moment to synthesise even these, so the audio is stored in
+//! `~/.jarvis/tts_cache/` the first time and played straight from disk
+//! afterwards. Only text spoken through
+//! [`Speaker::speak_cached`](super::Speaker::speak_cached) is cached, and
+//! only with engines that support
+//! [`Speaker::synthesize`](super::Speaker::synthesize).
+//!
+//! The cache key covers the text and the voice settings (engine, voice,
+//! rate, pitch and volume), so changing the voice never plays stale audio.
+//! Set `TTS_CACHE=0` to disable the cache; delete the directory to clear
+//! it.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::Speaker;
+use crate::audio_output::{Audio, AudioOutput};
+use crate::config::env_flag_or;
+
+pub struct AudioCache {
+    dir: PathBuf,
+}
+
+impl AudioCache {
+    /// The cache under `~/.jarvis/tts_cache`, unless disabled with
+    /// `TTS_CACHE=0`.
+    pub fn from_env() -> Option<Self> {
+        if !env_flag_or("TTS_CACHE", true) {
+            return None;
+        }
+        let dir = dirs::home_dir()?.join(".jarvis").join("tts_cache");
+        Some(Self { dir })
+    }
+
+    /// File holding the audio for `text` spoken with the voice settings
+    /// summarised by `voice`.
+    fn path(&self, voice: &str, text: &str) -> PathBuf {
+        // FNV-1a: stable across Rust releases, unlike `DefaultHasher`.
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in voice.bytes().chain([0]).chain(text.trim().bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        self.dir.join(format!("{hash:016x}.wav"))
+    }
+
+    /// Play `text` from the cache, synthesising and storing it first if
+    /// needed. Returns `false` without playing anything if the engine
+    /// cannot synthesise into memory.
+    pub async fn speak(
+        &self,
+        backend: &mut dyn Speaker,
+        output: Option<&AudioOutput>,
+        voice: &str,
+        text: &str,
+        stop: &Arc<AtomicBool>,
+    ) -> Result<bool> {
+        let path = self.path(voice, text);
+        let cached = fs::read(&path)
+            .ok()
+            .and_then(|bytes| Audio::from_wav(&bytes).ok());
+        let audio = match cached {
+            Some(audio) => audio,
+            None => match backend.synthesize(text).await {
+                Ok(audio) => {
+                    let stored = fs::create_dir_all(&self.dir)
+                        .and_then(|_| fs::write(&path, audio.to_wav()));
+                    if let Err(e) = stored {
+                        log::warn!("Failed to write TTS cache file {}: {e}", path.display());
+                    }
+                    audio
+                }
+                Err(e) => {
+                    log::debug!("Not caching '{text}': {e}");
+                    return Ok(false);
+                }
+            },
+        };
+        let device = match output {
+            Some(device) => device.clone(),
+            None => AudioOutput::default_device()?,
+        };
+        device.play(audio, stop.clone()).await?;
+        Ok(true)
+    }
+}
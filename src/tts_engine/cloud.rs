@@ -0,0 +1,388 @@
+//! Network TTS services, for users who prefer voice quality over offline
+//! operation. Only built with the `cloud-tts` feature:
+//!
+//! ```bash
+//! cargo build --release --features cloud-tts
+//! ```
+//!
+//! `VOICE_ENGINE` selects the service; `VOICE_NAME` picks one of its voices:
+//!
+//!  * `azure`: Azure AI Speech. Needs `AZURE_SPEECH_KEY` and
+//!    `AZURE_SPEECH_REGION` (e.g. `westeurope`). Voices such as
+//!    `en-GB-RyanNeural`; defaults to `en-US-JennyNeural`.
+//!  * `google`: Google Cloud Text-to-Speech. Needs `GOOGLE_TTS_API_KEY`.
+//!    Voices such as `en-GB-Neural2-B`; defaults to `en-US-Neural2-F`.
+//!  * `elevenlabs`: ElevenLabs. Needs `ELEVENLABS_API_KEY`; `VOICE_NAME`
+//!    is a voice id. `ELEVENLABS_MODEL` defaults to
+//!    `eleven_multilingual_v2`.
+//!
+//! Azure and ElevenLabs audio is played while it is still downloading.
+//! If the service cannot be reached, the utterance is spoken by the local
+//! engine named in `VOICE_FALLBACK_ENGINE` (default `system`) instead.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use base64::Engine as _;
+use futures_util::StreamExt;
+use serde_json::json;
+
+use super::{ssml, Prosody, Speaker};
+use crate::audio_output::{Audio, AudioOutput};
+
+/// A supported service and its credentials.
+enum Service {
+    Azure { key: String, region: String },
+    Google { key: String },
+    ElevenLabs { key: String, model: String },
+}
+
+impl Service {
+    fn name(&self) -> &'static str {
+        match self {
+            Service::Azure { .. } => "Azure",
+            Service::Google { .. } => "Google",
+            Service::ElevenLabs { .. } => "ElevenLabs",
+        }
+    }
+
+    /// Sample rate of the mono PCM audio requested from the service.
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Service::Azure { .. } | Service::Google { .. } => 24000,
+            Service::ElevenLabs { .. } => 22050,
+        }
+    }
+}
+
+fn required(name: &str, engine: &str) -> Result<String> {
+    std::env::var(name).with_context(|| format!("{name} must be set when VOICE_ENGINE={engine}"))
+}
+
+/// Speaks through a network TTS service.
+pub struct CloudSpeaker {
+    service: Service,
+    client: reqwest::Client,
+    voice: String,
+    prosody: Prosody,
+    /// Stops the audio currently playing.
+    playing: Option<Arc<AtomicBool>>,
+    /// Local engine used while the service is unreachable, created on
+    /// first use.
+    fallback: Option<Box<dyn Speaker>>,
+    fallback_engine: String,
+}
+
+impl CloudSpeaker {
+    /// Build the speaker for `engine` (`azure`, `google` or `elevenlabs`).
+    pub fn from_env(engine: &str) -> Result<Self> {
+        let (service, voice) = match engine {
+            "azure" => (
+                Service::Azure {
+                    key: required("AZURE_SPEECH_KEY", engine)?,
+                    region: required("AZURE_SPEECH_REGION", engine)?,
+                },
+                "en-US-JennyNeural",
+            ),
+            "google" => (
+                Service::Google {
+                    key: required("GOOGLE_TTS_API_KEY", engine)?,
+                },
+                "en-US-Neural2-F",
+            ),
+            "elevenlabs" => (
+                Service::ElevenLabs {
+                    key: required("ELEVENLABS_API_KEY", engine)?,
+                    model: std::env::var("ELEVENLABS_MODEL")
+                        .unwrap_or_else(|_| "eleven_multilingual_v2".to_string()),
+                },
+                // "Rachel", one of the default voices.
+                "21m00Tcm4TlvDq8ikWAM",
+            ),
+            other => bail!("unknown cloud TTS service '{other}'"),
+        };
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(5))
+            .read_timeout(Duration::from_secs(15))
+            .build()
+            .context("failed to create HTTP client")?;
+        Ok(Self {
+            service,
+            client,
+            voice: voice.to_string(),
+            prosody: Prosody::default(),
+            playing: None,
+            fallback: None,
+            fallback_engine: std::env::var("VOICE_FALLBACK_ENGINE")
+                .map(|e| e.to_lowercase())
+                .unwrap_or_else(|_| "system".to_string()),
+        })
+    }
+
+    /// Send `text` to the service. A response with an error status is
+    /// turned into an error carrying the service's message.
+    ///
+    /// The returned future does not borrow `self`, which is not `Sync`.
+    fn request(&self, text: &str) -> impl Future<Output = Result<reqwest::Response>> + Send {
+        let request = match &self.service {
+            Service::Azure { key, region } => self
+                .client
+                .post(format!(
+                    "https://{region}.tts.speech.microsoft.com/cognitiveservices/v1"
+                ))
+                .header("Ocp-Apim-Subscription-Key", key)
+                .header("Content-Type", "application/ssml+xml")
+                .header("X-Microsoft-OutputFormat", "raw-24khz-16bit-mono-pcm")
+                .header("User-Agent", "jarvis")
+                .body(self.azure_ssml(text)),
+            Service::Google { key } => {
+                let input = if ssml::is_ssml(text) {
+                    json!({ "ssml": ssml::wrap(text) })
+                } else {
+                    json!({ "text": text })
+                };
+                // The language code is the start of the voice name.
+                let language: Vec<&str> = self.voice.splitn(3, '-').take(2).collect();
+                self.client
+                    .post("https://texttospeech.googleapis.com/v1/text:synthesize")
+                    .query(&[("key", key)])
+                    .json(&json!({
+                        "input": input,
+                        "voice": { "languageCode": language.join("-"), "name": self.voice },
+                        "audioConfig": {
+                            "audioEncoding": "LINEAR16",
+                            "sampleRateHertz": self.service.sample_rate(),
+                            "speakingRate": self.prosody.rate.clamp(0.25, 4.0),
+                            "pitch": (12.0 * self.prosody.pitch.log2()).clamp(-20.0, 20.0),
+                            "volumeGainDb": (20.0 * self.prosody.volume.max(0.01).log10()).clamp(-96.0, 16.0),
+                        },
+                    }))
+            }
+            Service::ElevenLabs { key, model } => self
+                .client
+                .post(format!(
+                    "https://api.elevenlabs.io/v1/text-to-speech/{}/stream",
+                    self.voice
+                ))
+                .query(&[("output_format", "pcm_22050")])
+                .header("xi-api-key", key)
+                .json(&json!({ "text": text, "model_id": model })),
+        };
+        let name = self.service.name();
+        async move {
+            let response = request
+                .send()
+                .await
+                .with_context(|| format!("cannot reach {name}"))?;
+            let status = response.status();
+            if !status.is_success() {
+                let message = response.text().await.unwrap_or_default();
+                bail!(
+                    "{name} TTS request failed with {status}: {}",
+                    message.trim()
+                );
+            }
+            Ok(response)
+        }
+    }
+
+    /// The SSML document Azure expects, with the voice and prosody applied.
+    fn azure_ssml(&self, text: &str) -> String {
+        let body = if ssml::is_ssml(text) {
+            // Keep the caller's markup but drop its <speak> root, which
+            // must carry Azure's own attributes.
+            let document = ssml::wrap(text);
+            let start = document.find('>').map_or(0, |i| i + 1);
+            let end = document.rfind("</speak>").unwrap_or(document.len());
+            document[start..end].to_string()
+        } else {
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        };
+        let percent = |v: f32| format!("{:+.0}%", (v - 1.0) * 100.0);
+        let language: Vec<&str> = self.voice.splitn(3, '-').take(2).collect();
+        format!(
+            "<speak version='1.0' xmlns='http://www.w3.org/2001/10/synthesis' xml:lang='{}'>\
+<voice name='{}'><prosody rate='{}' pitch='{}' volume='{}'>{body}</prosody></voice></speak>",
+            language.join("-"),
+            self.voice,
+            percent(self.prosody.rate),
+            percent(self.prosody.pitch),
+            percent(self.prosody.volume),
+        )
+    }
+
+    /// Decode a complete response into audio.
+    fn decode(&self, response: reqwest::Response) -> impl Future<Output = Result<Audio>> + Send {
+        let google = matches!(self.service, Service::Google { .. });
+        let name = self.service.name();
+        let sample_rate = self.service.sample_rate();
+        async move {
+            if google {
+                let body: serde_json::Value = response
+                    .json()
+                    .await
+                    .context("invalid response from Google TTS")?;
+                let content = body["audioContent"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Google TTS returned no audio"))?;
+                let wav = base64::engine::general_purpose::STANDARD
+                    .decode(content)
+                    .context("invalid audio from Google TTS")?;
+                return Audio::from_wav(&wav);
+            }
+            let bytes = response
+                .bytes()
+                .await
+                .with_context(|| format!("failed to download {name} audio"))?;
+            Ok(Audio::from_raw_mono(&bytes, sample_rate))
+        }
+    }
+
+    /// Play the PCM stream of `response` while it downloads.
+    async fn play_streaming(&mut self, response: reqwest::Response) -> Result<()> {
+        let stop = Arc::new(AtomicBool::new(false));
+        self.playing = Some(stop.clone());
+        let (tx, rx) = std::sync::mpsc::channel();
+        let output = AudioOutput::default_device()?;
+        let playback = output.play_stream(self.service.sample_rate(), rx, stop);
+        let name = self.service.name();
+        let download = async move {
+            let mut body = response.bytes_stream();
+            // A sample may be split between two network chunks.
+            let mut odd_byte: Option<u8> = None;
+            while let Some(chunk) = body.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        log::warn!("{name} audio stream ended early: {e}");
+                        break;
+                    }
+                };
+                let mut bytes: Vec<u8> = odd_byte.take().into_iter().collect();
+                bytes.extend_from_slice(&chunk);
+                if bytes.len() % 2 == 1 {
+                    odd_byte = bytes.pop();
+                }
+                let samples = bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect();
+                // Playback has been stopped once the receiver is gone.
+                if tx.send(samples).is_err() {
+                    break;
+                }
+            }
+        };
+        let (result, ()) = tokio::join!(playback, download);
+        self.playing = None;
+        result
+    }
+
+    /// The local engine, created on first use.
+    fn fallback(&mut self) -> Result<&mut Box<dyn Speaker>> {
+        if self.fallback.is_none() {
+            self.fallback = Some(super::local_backend(&self.fallback_engine)?);
+        }
+        Ok(self.fallback.as_mut().expect("fallback was just created"))
+    }
+
+    /// Speak `text` with the local engine after the service failed with
+    /// `error`.
+    async fn speak_locally(&mut self, text: &str, error: anyhow::Error) -> Result<()> {
+        log::warn!(
+            "{} TTS unavailable ({error:#}); using the {} engine instead.",
+            self.service.name(),
+            self.fallback_engine
+        );
+        let fallback = self.fallback()?;
+        let text = if fallback.supports_ssml() {
+            text.to_string()
+        } else {
+            ssml::strip(text)
+        };
+        fallback.speak(&text).await
+    }
+}
+
+#[async_trait]
+impl Speaker for CloudSpeaker {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        self.stop().await?;
+        let response = match self.request(text).await {
+            Ok(response) => response,
+            Err(e) => return self.speak_locally(text, e).await,
+        };
+        if let Service::Google { .. } = self.service {
+            let audio = self.decode(response).await?;
+            let stop = Arc::new(AtomicBool::new(false));
+            self.playing = Some(stop.clone());
+            let result = AudioOutput::default_device()?.play(audio, stop).await;
+            self.playing = None;
+            return result;
+        }
+        self.play_streaming(response).await
+    }
+
+    async fn synthesize(&mut self, text: &str) -> Result<Audio> {
+        match self.request(text).await {
+            Ok(response) => self.decode(response).await,
+            Err(e) => {
+                log::warn!(
+                    "{} TTS unavailable ({e:#}); using the {} engine instead.",
+                    self.service.name(),
+                    self.fallback_engine
+                );
+                self.fallback()?.synthesize(&ssml::strip(text)).await
+            }
+        }
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(playing) = self.playing.take() {
+            playing.store(true, Ordering::SeqCst);
+        }
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.stop().await?;
+        }
+        Ok(())
+    }
+
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        self.voice = name.to_string();
+        Ok(())
+    }
+
+    fn set_rate(&mut self, rate: f32) -> Result<()> {
+        if let Service::ElevenLabs { .. } = self.service {
+            bail!("ElevenLabs voices cannot change their rate");
+        }
+        self.prosody.rate = rate;
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, pitch: f32) -> Result<()> {
+        if let Service::ElevenLabs { .. } = self.service {
+            bail!("ElevenLabs voices cannot change their pitch");
+        }
+        self.prosody.pitch = pitch;
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<()> {
+        if let Service::ElevenLabs { .. } = self.service {
+            bail!("ElevenLabs voices cannot change their volume");
+        }
+        self.prosody.volume = volume;
+        Ok(())
+    }
+
+    fn supports_ssml(&self) -> bool {
+        !matches!(self.service, Service::ElevenLabs { .. })
+    }
+}
@@ -0,0 +1,224 @@
+//! Speech through an arbitrary external command.
+//!
+//! `VOICE_ENGINE=command` runs the program given in `TTS_COMMAND` for every
+//! utterance. The command line is split on whitespace; the placeholder
+//! `{text}` is replaced by the text to speak, and `{voice}` by the voice
+//! selected with `VOICE_NAME`. `{rate}`, `{pitch}` and `{volume}` become
+//! percentages of normal (`100`). Without a `{text}` placeholder the text
+//! is written to the program's stdin instead.
+//!
+//! If the command contains `{out}`, it is expected to write a 16-bit PCM
+//! WAV file to that path instead of playing the audio itself; Jarvis then
+//! plays the file, on the device selected by `SPEAKER_NAME_KEYWORD` if
+//! set. Examples:
+//!
+//! ```env
+//! TTS_COMMAND=espeak-ng -v {voice} -s {rate} {text}
+//! TTS_COMMAND=festival --tts
+//! TTS_COMMAND=espeak-ng -w {out} {text}
+//! ```
+//!
+//! Set `TTS_COMMAND_SSML=1` if the program understands SSML markup (for
+//! example `espeak-ng -m`); otherwise markup is converted to plain text
+//! before the command runs.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+
+use super::{Prosody, Speaker};
+use crate::audio_output::{Audio, AudioOutput};
+use crate::config::env_flag;
+
+/// Distinguishes temporary output files of concurrent syntheses.
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// Spawns one process per utterance and kills it on [`stop`](Speaker::stop).
+pub struct CommandSpeaker {
+    program: String,
+    args: Vec<String>,
+    voice: Option<String>,
+    prosody: Prosody,
+    process: Option<Child>,
+    /// Stops playback of a file written by an `{out}` command.
+    playing: Option<Arc<AtomicBool>>,
+    /// The program reads SSML markup.
+    ssml: bool,
+}
+
+impl CommandSpeaker {
+    /// Create a speaker for `program` with argument templates `args`.
+    pub fn new(program: &str, args: &[&str], voice: Option<String>) -> Self {
+        Self {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            voice,
+            prosody: Prosody::default(),
+            process: None,
+            playing: None,
+            ssml: false,
+        }
+    }
+
+    /// Build a speaker from the `TTS_COMMAND` environment variable.
+    pub fn from_env() -> Result<Self> {
+        let template = std::env::var("TTS_COMMAND")
+            .context("TTS_COMMAND must be set when VOICE_ENGINE=command")?;
+        let mut parts = template.split_whitespace();
+        let program = parts.next().context("TTS_COMMAND is empty")?;
+        let args: Vec<&str> = parts.collect();
+        let mut speaker = Self::new(program, &args, None);
+        speaker.ssml = env_flag("TTS_COMMAND_SSML");
+        Ok(speaker)
+    }
+
+    /// Settings can only be changed if the command line uses them.
+    fn supports(&self, placeholder: &str) -> Result<()> {
+        if self.args.iter().any(|a| a.contains(placeholder)) {
+            Ok(())
+        } else {
+            Err(anyhow!("TTS command has no {placeholder} placeholder"))
+        }
+    }
+
+    /// Whether the command writes audio to `{out}` rather than playing it.
+    fn writes_file(&self) -> bool {
+        self.args.iter().any(|a| a.contains("{out}"))
+    }
+
+    /// Run the command once for `text`, with `out` substituted for the
+    /// `{out}` placeholder, and wait for it to exit.
+    async fn run(&mut self, text: &str, out: Option<&Path>) -> Result<()> {
+        // Terminate any existing process if it is still running.
+        self.stop().await?;
+        let text_in_args = self.args.iter().any(|a| a.contains("{text}"));
+        let voice = self.voice.clone().unwrap_or_default();
+        let out = out.map(PathBuf::from).unwrap_or_default();
+        let percent = |v: f32| ((v * 100.0).round() as u32).to_string();
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|a| {
+                a.replace("{text}", text)
+                    .replace("{voice}", &voice)
+                    .replace("{out}", &out.to_string_lossy())
+                    .replace("{rate}", &percent(self.prosody.rate))
+                    .replace("{pitch}", &percent(self.prosody.pitch))
+                    .replace("{volume}", &percent(self.prosody.volume))
+            })
+            .collect();
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&args)
+            .stdin(if text_in_args {
+                Stdio::null()
+            } else {
+                Stdio::piped()
+            })
+            .kill_on_drop(true);
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("failed to spawn TTS command '{}'", self.program))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .await
+                .context("failed to write to TTS command stdin")?;
+            // Close stdin to let the program know the input is complete.
+            stdin.shutdown().await.ok();
+        }
+        // Store the handle so that cancellation can stop the process,
+        // then await completion of the speech process.
+        self.process = Some(child);
+        if let Some(child) = self.process.as_mut() {
+            let _ = child.wait().await;
+        }
+        self.process = None;
+        Ok(())
+    }
+
+    /// The currently selected voice, if any.
+    pub fn voice(&self) -> Option<&str> {
+        self.voice.as_deref()
+    }
+}
+
+#[async_trait]
+impl Speaker for CommandSpeaker {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        if self.writes_file() {
+            let audio = self.synthesize(text).await?;
+            let stop = Arc::new(AtomicBool::new(false));
+            self.playing = Some(stop.clone());
+            let result = AudioOutput::default_device()?.play(audio, stop).await;
+            self.playing = None;
+            return result;
+        }
+        self.run(text, None).await
+    }
+
+    async fn synthesize(&mut self, text: &str) -> Result<Audio> {
+        if !self.writes_file() {
+            bail!("TTS command has no {{out}} placeholder, so it cannot write audio to a file");
+        }
+        let path = std::env::temp_dir().join(format!(
+            "jarvis-tts-{}-{}.wav",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::SeqCst)
+        ));
+        let result = self.run(text, Some(&path)).await;
+        let bytes = std::fs::read(&path);
+        let _ = std::fs::remove_file(&path);
+        result?;
+        let bytes = bytes.with_context(|| {
+            format!(
+                "TTS command '{}' did not write {}",
+                self.program,
+                path.display()
+            )
+        })?;
+        Audio::from_wav(&bytes)
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(mut child) = self.process.take() {
+            let _ = child.kill().await;
+        }
+        if let Some(playing) = self.playing.take() {
+            playing.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        self.voice = Some(name.to_string());
+        Ok(())
+    }
+
+    fn set_rate(&mut self, rate: f32) -> Result<()> {
+        self.supports("{rate}")?;
+        self.prosody.rate = rate;
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, pitch: f32) -> Result<()> {
+        self.supports("{pitch}")?;
+        self.prosody.pitch = pitch;
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<()> {
+        self.supports("{volume}")?;
+        self.prosody.volume = volume;
+        Ok(())
+    }
+
+    fn supports_ssml(&self) -> bool {
+        self.ssml
+    }
+}
@@ -0,0 +1,165 @@
+//! Lowers the volume of other applications while Jarvis speaks.
+//!
+//! With `DUCK_MEDIA=1`, every playback stream that exists when Jarvis
+//! starts talking (music, videos) is turned down through `pactl`, which
+//! works with both PulseAudio and PipeWire (via `pipewire-pulse`). The
+//! original volumes are restored once the speech queue is empty.
+//!
+//!  * `DUCK_LEVEL` (optional): fraction of its volume a stream keeps while
+//!    ducked. Defaults to `0.3`.
+//!  * `DUCK_EXCLUDE` (optional): comma separated program names that are
+//!    never ducked. Defaults to the speech engines' own players.
+
+use anyhow::{bail, Context, Result};
+use tokio::process::Command;
+
+use crate::config::{env_flag, env_list, env_parse};
+
+/// Programs that play Jarvis's own speech.
+const SPEECH_PROGRAMS: &[&str] = &[
+    "speech-dispatcher",
+    "sd_espeak-ng",
+    "sd_rhvoice",
+    "aplay",
+    "paplay",
+    "RHVoice-test",
+    "rhvoice.test",
+    "espeak-ng",
+    "piper",
+];
+
+/// A playback stream as reported by `pactl list sink-inputs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SinkInput {
+    id: u32,
+    /// Volume of the first channel, in percent.
+    volume: u32,
+    pid: Option<u32>,
+    binary: Option<String>,
+}
+
+/// Turns other streams down and back up again.
+pub struct MediaDucker {
+    level: f32,
+    exclude: Vec<String>,
+    /// Streams that were turned down, with their original volume.
+    ducked: Vec<(u32, u32)>,
+}
+
+impl MediaDucker {
+    /// The ducker, if `DUCK_MEDIA` is enabled.
+    pub fn from_env() -> Option<Self> {
+        if !env_flag("DUCK_MEDIA") {
+            return None;
+        }
+        Some(Self {
+            level: env_parse("DUCK_LEVEL", 0.3f32).clamp(0.0, 1.0),
+            exclude: env_list("DUCK_EXCLUDE", SPEECH_PROGRAMS),
+            ducked: Vec::new(),
+        })
+    }
+
+    /// Turn down every stream that is not Jarvis's own. Does nothing if the
+    /// streams are already ducked.
+    pub async fn duck(&mut self) {
+        if !self.ducked.is_empty() {
+            return;
+        }
+        let streams = match list_sink_inputs().await {
+            Ok(streams) => streams,
+            Err(e) => {
+                log::warn!("Cannot duck media: {e:#}");
+                return;
+            }
+        };
+        let own_pid = std::process::id();
+        for stream in streams {
+            let excluded = stream.pid == Some(own_pid)
+                || stream
+                    .binary
+                    .as_ref()
+                    .is_some_and(|b| self.exclude.iter().any(|e| e.eq_ignore_ascii_case(b)));
+            if excluded || stream.volume == 0 {
+                continue;
+            }
+            let lowered = (stream.volume as f32 * self.level).round() as u32;
+            if set_volume(stream.id, lowered).await {
+                self.ducked.push((stream.id, stream.volume));
+            }
+        }
+        if !self.ducked.is_empty() {
+            log::debug!("Ducked {} media stream(s)", self.ducked.len());
+        }
+    }
+
+    /// Restore the volumes changed by [`duck`](Self::duck). Streams that
+    /// have ended in the meantime are skipped.
+    pub async fn restore(&mut self) {
+        for (id, volume) in self.ducked.drain(..) {
+            set_volume(id, volume).await;
+        }
+    }
+}
+
+async fn list_sink_inputs() -> Result<Vec<SinkInput>> {
+    let output = Command::new("pactl")
+        .args(["list", "sink-inputs"])
+        .env("LC_ALL", "C")
+        .output()
+        .await
+        .context("failed to run pactl")?;
+    if !output.status.success() {
+        bail!("pactl exited with {}", output.status);
+    }
+    Ok(parse_sink_inputs(&String::from_utf8_lossy(&output.stdout)))
+}
+
+async fn set_volume(id: u32, percent: u32) -> bool {
+    let status = Command::new("pactl")
+        .args([
+            "set-sink-input-volume",
+            &id.to_string(),
+            &format!("{percent}%"),
+        ])
+        .status()
+        .await;
+    matches!(status, Ok(s) if s.success())
+}
+
+fn parse_sink_inputs(text: &str) -> Vec<SinkInput> {
+    let mut streams = Vec::new();
+    let mut current: Option<SinkInput> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(id) = line.strip_prefix("Sink Input #") {
+            streams.extend(current.take());
+            current = id.trim().parse().ok().map(|id| SinkInput {
+                id,
+                volume: 0,
+                pid: None,
+                binary: None,
+            });
+            continue;
+        }
+        let Some(stream) = current.as_mut() else {
+            continue;
+        };
+        if let Some(volume) = line.strip_prefix("Volume:") {
+            // "front-left: 39321 /  60% / -13.31 dB,   front-right: ..."
+            stream.volume = volume
+                .split('/')
+                .nth(1)
+                .and_then(|v| v.trim().trim_end_matches('%').parse().ok())
+                .unwrap_or(0);
+        } else if let Some((key, value)) = line.split_once(" = ") {
+            let value = value.trim_matches('"');
+            match key {
+                "application.process.id" => stream.pid = value.parse().ok(),
+                "application.process.binary" => stream.binary = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    streams.extend(current);
+    streams
+}
@@ -0,0 +1,174 @@
+//! [eSpeak NG](https://github.com/espeak-ng/espeak-ng), a small formant
+//! synthesiser that starts instantly even on a Raspberry Pi. It needs
+//! neither Speech Dispatcher nor a neural model:
+//!
+//!  * `ESPEAK_BIN` (optional): eSpeak NG executable. Defaults to
+//!    `espeak-ng`.
+//!  * `VOICE_NAME` (optional): voice or language, e.g. `en-gb` or
+//!    `en-us+f3`. Defaults to eSpeak's own default.
+//!
+//! Rate, pitch and volume are all supported, and SSML markup is passed
+//! through (`-m`).
+
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+
+use super::{ssml, Prosody, Speaker, VoiceInfo};
+use crate::audio_output::Audio;
+
+/// Speaking rate of eSpeak NG at `TTS_RATE=1.0`, in words per minute.
+const NORMAL_WPM: f32 = 175.0;
+
+/// Runs one eSpeak NG process per utterance.
+pub struct EspeakSpeaker {
+    bin: String,
+    voice: Option<String>,
+    prosody: Prosody,
+    process: Option<Child>,
+}
+
+impl EspeakSpeaker {
+    pub fn from_env() -> Self {
+        Self {
+            bin: std::env::var("ESPEAK_BIN").unwrap_or_else(|_| "espeak-ng".to_string()),
+            voice: None,
+            prosody: Prosody::default(),
+            process: None,
+        }
+    }
+
+    /// The eSpeak command for the current settings, reading `text` from
+    /// stdin.
+    fn command(&self, text: &str) -> Command {
+        let mut cmd = Command::new(&self.bin);
+        if let Some(voice) = &self.voice {
+            cmd.arg("-v").arg(voice);
+        }
+        let wpm = (NORMAL_WPM * self.prosody.rate).clamp(80.0, 450.0);
+        let pitch = (50.0 * self.prosody.pitch).clamp(0.0, 99.0);
+        let amplitude = (100.0 * self.prosody.volume).clamp(0.0, 200.0);
+        cmd.arg("-s")
+            .arg(format!("{wpm:.0}"))
+            .arg("-p")
+            .arg(format!("{pitch:.0}"))
+            .arg("-a")
+            .arg(format!("{amplitude:.0}"));
+        if ssml::is_ssml(text) {
+            cmd.arg("-m");
+        }
+        cmd.arg("--stdin")
+            .stdin(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        cmd
+    }
+
+    /// Spawn eSpeak for `text` and write the text to its stdin.
+    async fn spawn(&self, mut cmd: Command, text: &str) -> Result<Child> {
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("failed to spawn '{}'", self.bin))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .await
+                .context("failed to write to eSpeak stdin")?;
+            stdin.shutdown().await.ok();
+        }
+        Ok(child)
+    }
+}
+
+#[async_trait]
+impl Speaker for EspeakSpeaker {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        self.stop().await?;
+        let child = self.spawn(self.command(text), text).await?;
+        // Keep the handle so that `stop` can kill the process.
+        self.process = Some(child);
+        if let Some(child) = self.process.as_mut() {
+            let _ = child.wait().await;
+        }
+        self.process = None;
+        Ok(())
+    }
+
+    async fn synthesize(&mut self, text: &str) -> Result<Audio> {
+        self.stop().await?;
+        let mut cmd = self.command(text);
+        cmd.arg("--stdout").stdout(Stdio::piped());
+        let output = self
+            .spawn(cmd, text)
+            .await?
+            .wait_with_output()
+            .await
+            .context("failed to read eSpeak output")?;
+        if !output.status.success() {
+            bail!("eSpeak exited with {}", output.status);
+        }
+        Audio::from_wav(&output.stdout)
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(mut child) = self.process.take() {
+            let _ = child.kill().await;
+        }
+        Ok(())
+    }
+
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        self.voice = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Parses `espeak-ng --voices`, whose rows look like
+    /// ` 5  en-gb          --/M      English_(Great_Britain) gmw/en`.
+    fn list_voices(&self) -> Result<Vec<VoiceInfo>> {
+        let output = std::process::Command::new(&self.bin)
+            .arg("--voices")
+            .output()
+            .with_context(|| format!("failed to run '{} --voices'", self.bin))?;
+        let listing = String::from_utf8_lossy(&output.stdout);
+        Ok(listing
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let mut columns = line.split_whitespace().skip(1);
+                let language = columns.next()?.to_string();
+                let gender = match columns.next()?.rsplit('/').next() {
+                    Some("M") => Some("male".to_string()),
+                    Some("F") => Some("female".to_string()),
+                    _ => None,
+                };
+                Some(VoiceInfo {
+                    name: language.clone(),
+                    language: Some(language),
+                    gender,
+                })
+            })
+            .collect())
+    }
+
+    fn set_rate(&mut self, rate: f32) -> Result<()> {
+        self.prosody.rate = rate;
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, pitch: f32) -> Result<()> {
+        self.prosody.pitch = pitch;
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<()> {
+        self.prosody.volume = volume;
+        Ok(())
+    }
+
+    fn supports_ssml(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,213 @@
+//! Switching to a voice that matches the language of each reply.
+//!
+//! An English voice reading German text is barely intelligible. With
+//! `VOICE_LANGUAGES` set, the language of every utterance is recognised
+//! and the voice mapped to it is selected before speaking; text in any
+//! other language is spoken with the normal voice (`VOICE_NAME`):
+//!
+//! ```env
+//! VOICE_LANGUAGES=de=de_DE-thorsten-medium,fr=fr_FR-siwis-medium
+//! ```
+//!
+//! The language is taken from an SSML `xml:lang` attribute if present and
+//! otherwise guessed from the script and common words of the text. Text
+//! that cannot be recognised is taken to be in the reply language the
+//! user asked for (see [`crate::language`]), if any.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use super::{ssml, Speaker};
+use crate::config::env_list;
+
+/// Frequent short words that tell languages written in Latin script apart.
+const COMMON_WORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "is", "are", "you", "of", "to", "it", "that", "with", "this", "have",
+            "for", "not", "what", "your",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ich", "sie", "ein", "eine", "mit", "auf",
+            "für", "auch", "es", "zu", "den", "dem", "sind", "wir", "ihr", "ihre",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "est", "vous", "une", "un", "des", "pas", "que", "pour",
+            "dans", "avec", "je", "il", "ce", "sur", "sont", "votre",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "y", "es", "que", "una", "un", "por", "para", "con", "no",
+            "en", "está", "son", "del", "se", "muy", "su",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "la", "che", "e", "è", "di", "una", "un", "per", "non", "sono", "con", "gli",
+            "del", "della", "anche", "questo", "ho",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "a", "os", "as", "e", "é", "que", "um", "uma", "não", "para", "com", "do", "da",
+            "em", "são", "você", "está",
+        ],
+    ),
+    (
+        "hr",
+        &[
+            "je", "i", "u", "na", "se", "su", "za", "od", "ne", "što", "kako", "sam", "ali", "ili",
+            "ovo", "ovaj", "biti", "vaš", "imate", "nije",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "en", "is", "een", "van", "niet", "dat", "ik", "je", "met", "voor",
+            "zijn", "op", "ook", "wat",
+        ],
+    ),
+];
+
+/// The language the user asked replies in, as an ISO 639-1 code.
+static REPLY_LANGUAGE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Take text that cannot be recognised to be in `code`; `None` to speak it
+/// with the normal voice.
+pub fn set_reply_language(code: Option<&str>) {
+    *REPLY_LANGUAGE.lock().unwrap() = code.map(str::to_string);
+}
+
+/// Guess the language of `text` as an ISO 639-1 code. Returns `None` when
+/// the text is too short or too ambiguous to tell.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    if let Some(lang) = detect_script(text) {
+        return Some(lang);
+    }
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() < 3 {
+        return None;
+    }
+    let mut scores: Vec<(&str, usize)> = COMMON_WORDS
+        .iter()
+        .map(|(lang, common)| {
+            let hits = words
+                .iter()
+                .filter(|w| common.contains(&w.as_str()))
+                .count();
+            (*lang, hits)
+        })
+        .collect();
+    scores.sort_by_key(|(_, hits)| std::cmp::Reverse(*hits));
+    let (best, hits) = scores[0];
+    let runner_up = scores[1].1;
+    // Require a clear winner so that a stray "die" or "la" in an English
+    // sentence does not switch voices.
+    (hits >= 2 && hits * 4 >= words.len() && hits > runner_up).then_some(best)
+}
+
+/// Languages recognisable from their alphabet alone.
+fn detect_script(text: &str) -> Option<&'static str> {
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut letters = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        let lang = match c {
+            '\u{0400}'..='\u{04FF}' => "ru",
+            '\u{0370}'..='\u{03FF}' => "el",
+            '\u{0590}'..='\u{05FF}' => "he",
+            '\u{0600}'..='\u{06FF}' => "ar",
+            '\u{3040}'..='\u{30FF}' => "ja",
+            '\u{AC00}'..='\u{D7AF}' => "ko",
+            '\u{4E00}'..='\u{9FFF}' => "zh",
+            _ => continue,
+        };
+        *counts.entry(lang).or_default() += 1;
+    }
+    // Japanese mixes kana with Chinese characters.
+    if counts.contains_key("ja") {
+        return Some("ja");
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, n)| *n)
+        .filter(|(_, n)| n * 2 > letters)
+        .map(|(lang, _)| lang)
+}
+
+/// The language named by an SSML `xml:lang` attribute, e.g. `de` for
+/// `<speak xml:lang="de-DE">`.
+fn ssml_language(text: &str) -> Option<String> {
+    let start = text.find("xml:lang=")? + "xml:lang=".len();
+    let value = text[start..].trim_start_matches(['"', '\'']);
+    let end = value.find(['"', '\''])?;
+    let lang = value[..end].split(['-', '_']).next()?.to_lowercase();
+    (!lang.is_empty()).then_some(lang)
+}
+
+/// Selects voices by language on behalf of the speech queue.
+pub struct LanguageVoices {
+    voices: BTreeMap<String, String>,
+    /// The language voice currently selected on the backend, if it is not
+    /// the normal voice.
+    active: Option<String>,
+}
+
+impl LanguageVoices {
+    /// The mapping from `VOICE_LANGUAGES`, or `None` if it is not set.
+    pub fn from_env() -> Option<Self> {
+        let voices: BTreeMap<String, String> = env_list("VOICE_LANGUAGES", &[])
+            .iter()
+            .filter_map(|entry| {
+                let (lang, voice) = entry.split_once('=')?;
+                Some((lang.trim().to_lowercase(), voice.trim().to_string()))
+            })
+            .collect();
+        if voices.is_empty() {
+            return None;
+        }
+        Some(Self {
+            voices,
+            active: None,
+        })
+    }
+
+    /// Select the voice for the language of `text` on `backend`, or go back
+    /// to `normal_voice` if the text is in an unmapped language.
+    pub fn select(&mut self, backend: &mut dyn Speaker, text: &str, normal_voice: Option<&str>) {
+        let lang = ssml_language(text)
+            .or_else(|| detect_language(&ssml::strip(text)).map(str::to_string))
+            .or_else(|| REPLY_LANGUAGE.lock().unwrap().clone());
+        let wanted = lang.and_then(|lang| self.voices.get(&lang)).cloned();
+        if wanted == self.active {
+            return;
+        }
+        let Some(voice) = wanted.as_deref().or(normal_voice) else {
+            // The engine's default voice was in use and cannot be
+            // selected by name; keep the language voice.
+            return;
+        };
+        match backend.set_voice(voice) {
+            Ok(()) => {
+                log::debug!("Switched to voice '{voice}'");
+                self.active = wanted;
+            }
+            Err(e) => log::warn!("Failed to switch to voice '{voice}': {e}"),
+        }
+    }
+}
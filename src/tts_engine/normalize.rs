@@ -0,0 +1,178 @@
+//! Rewrites dates, times, percentages and units into words before they are
+//! spoken.
+//!
+//! Shell output is full of values such as `2024-06-01`, `13:45`, `42%` or
+//! `3.5GB` that speech engines read digit by digit or skip entirely. They
+//! become "June first 2024", "13 45", "42 percent" and "3.5 gigabytes".
+//! Set `TTS_NORMALIZE=0` to speak text unchanged. Like the pronunciation
+//! dictionary, this only affects the audio.
+
+use regex::{Captures, Regex};
+
+use crate::config::env_flag_or;
+
+const MONTHS: &[&str] = &[
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const ORDINALS: &[&str] = &[
+    "first",
+    "second",
+    "third",
+    "fourth",
+    "fifth",
+    "sixth",
+    "seventh",
+    "eighth",
+    "ninth",
+    "tenth",
+    "eleventh",
+    "twelfth",
+    "thirteenth",
+    "fourteenth",
+    "fifteenth",
+    "sixteenth",
+    "seventeenth",
+    "eighteenth",
+    "nineteenth",
+    "twentieth",
+    "twenty first",
+    "twenty second",
+    "twenty third",
+    "twenty fourth",
+    "twenty fifth",
+    "twenty sixth",
+    "twenty seventh",
+    "twenty eighth",
+    "twenty ninth",
+    "thirtieth",
+    "thirty first",
+];
+
+/// Unit symbols with their singular and plural names. Single-letter
+/// symbols are only recognised after a space (`30 s`, `4 G`): attached to
+/// the number they are more often part of a name, as in `1990s`, `4K`
+/// or `5G`.
+const UNITS: &[(&str, &str, &str)] = &[
+    ("B", "byte", "bytes"),
+    ("KB", "kilobyte", "kilobytes"),
+    ("MB", "megabyte", "megabytes"),
+    ("GB", "gigabyte", "gigabytes"),
+    ("TB", "terabyte", "terabytes"),
+    ("PB", "petabyte", "petabytes"),
+    ("KiB", "kibibyte", "kibibytes"),
+    ("MiB", "mebibyte", "mebibytes"),
+    ("GiB", "gibibyte", "gibibytes"),
+    ("TiB", "tebibyte", "tebibytes"),
+    ("K", "kilobyte", "kilobytes"),
+    ("M", "megabyte", "megabytes"),
+    ("G", "gigabyte", "gigabytes"),
+    ("T", "terabyte", "terabytes"),
+    ("kbps", "kilobit per second", "kilobits per second"),
+    ("Mbps", "megabit per second", "megabits per second"),
+    ("Gbps", "gigabit per second", "gigabits per second"),
+    ("ms", "millisecond", "milliseconds"),
+    ("s", "second", "seconds"),
+    ("sec", "second", "seconds"),
+    ("min", "minute", "minutes"),
+    ("h", "hour", "hours"),
+    ("Hz", "hertz", "hertz"),
+    ("kHz", "kilohertz", "kilohertz"),
+    ("MHz", "megahertz", "megahertz"),
+    ("GHz", "gigahertz", "gigahertz"),
+    ("mm", "millimetre", "millimetres"),
+    ("cm", "centimetre", "centimetres"),
+    ("km", "kilometre", "kilometres"),
+    ("kg", "kilogram", "kilograms"),
+    ("°C", "degree Celsius", "degrees Celsius"),
+    ("°F", "degree Fahrenheit", "degrees Fahrenheit"),
+];
+
+/// Compiled normalisation rules.
+pub struct Normalizer {
+    date: Regex,
+    time: Regex,
+    percent: Regex,
+    unit: Regex,
+}
+
+impl Normalizer {
+    pub fn new() -> Self {
+        Self {
+            date: Regex::new(r"\b(\d{4})-(\d{2})-(\d{2})\b").expect("valid date pattern"),
+            time: Regex::new(r"\b(\d{1,2}):(\d{2})(?::(\d{2}))?\b").expect("valid time pattern"),
+            percent: Regex::new(r"(\d+(?:\.\d+)?)\s?%").expect("valid percent pattern"),
+            unit: Regex::new(r"\b(\d+(?:\.\d+)?)(\s?)(°?[A-Za-z]+)\b").expect("valid unit pattern"),
+        }
+    }
+
+    /// The normalizer, or `None` if `TTS_NORMALIZE=0`.
+    pub fn from_env() -> Option<Self> {
+        env_flag_or("TTS_NORMALIZE", true).then(Self::new)
+    }
+
+    /// Rewrite the values in `text` into words.
+    pub fn apply(&self, text: &str) -> String {
+        let text = self.date.replace_all(text, |c: &Captures| {
+            let month: usize = c[2].parse().unwrap_or(0);
+            let day: usize = c[3].parse().unwrap_or(0);
+            match (
+                MONTHS.get(month.wrapping_sub(1)),
+                ORDINALS.get(day.wrapping_sub(1)),
+            ) {
+                (Some(month), Some(day)) => format!("{month} {day} {}", &c[1]),
+                _ => c[0].to_string(),
+            }
+        });
+        let text = self.time.replace_all(&text, |c: &Captures| {
+            let hour: u32 = c[1].parse().unwrap_or(99);
+            let minute: u32 = c[2].parse().unwrap_or(99);
+            let second: Option<u32> = c.get(3).and_then(|s| s.as_str().parse().ok());
+            if hour > 23 || minute > 59 || second.is_some_and(|s| s > 59) {
+                return c[0].to_string();
+            }
+            let spoken = match (hour, minute) {
+                (0, 0) => "midnight".to_string(),
+                (1..=12, 0) => format!("{hour} o'clock"),
+                (_, 0) => format!("{hour} hundred"),
+                (_, 1..=9) => format!("{hour} oh {minute}"),
+                _ => format!("{hour} {minute}"),
+            };
+            match second {
+                Some(0) | None => spoken,
+                Some(1) => format!("{spoken} and 1 second"),
+                Some(s) => format!("{spoken} and {s} seconds"),
+            }
+        });
+        let text = self.percent.replace_all(&text, "$1 percent");
+        let text = self.unit.replace_all(&text, |c: &Captures| {
+            let attached = c[2].is_empty();
+            let unit = UNITS
+                .iter()
+                .find(|(symbol, _, _)| *symbol == &c[3] && (!attached || symbol.len() > 1));
+            match unit {
+                Some((_, one, _)) if &c[1] == "1" => format!("{} {one}", &c[1]),
+                Some((_, _, many)) => format!("{} {many}", &c[1]),
+                None => c[0].to_string(),
+            }
+        });
+        text.into_owned()
+    }
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
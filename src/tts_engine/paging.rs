@@ -0,0 +1,161 @@
+//! Speaking long replies a page at a time.
+//!
+//! A reply longer than `SPEAK_MAX_CHARS` (default `400`, `0` disables
+//! paging) is cut after the first page, followed by a hint to say
+//! "continue". Multi-line output such as a directory listing is paged by
+//! lines, other text by sentences. The full reply is still written to
+//! `jarvis.spoken`.
+
+use std::collections::VecDeque;
+
+use super::sentences::split_sentences;
+use super::ssml;
+use crate::config::env_parse;
+
+/// Phrases that ask for the next page. A bare "next" is left to the
+/// media and music tools, where it means the next track.
+const CONTINUE_PHRASES: &[&str] = &[
+    "continue",
+    "go on",
+    "more",
+    "keep going",
+    "carry on",
+    "next page",
+    "read more",
+    "continue reading",
+];
+
+/// One page: the lines or sentences to speak, and how many units of the
+/// reply have been read once it has been spoken.
+#[derive(Debug)]
+struct Page {
+    units: Vec<String>,
+    read: usize,
+}
+
+/// Splits replies into pages and remembers what is left to read.
+#[derive(Debug, Default)]
+pub struct Pager {
+    max_chars: usize,
+    /// Pages not yet spoken.
+    remaining: VecDeque<Page>,
+    /// Whether the reply is paged by lines rather than sentences.
+    lines: bool,
+    /// Units (lines or sentences) of the whole reply.
+    total: usize,
+}
+
+impl Pager {
+    /// A pager for pages of at most `max_chars` characters; `0` speaks
+    /// every reply in full.
+    pub fn new(max_chars: usize) -> Self {
+        Self {
+            max_chars,
+            ..Self::default()
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(env_parse("SPEAK_MAX_CHARS", 400))
+    }
+
+    /// The part of `reply` to speak now. Any pages left over from an
+    /// earlier reply are discarded.
+    pub fn first_page(&mut self, reply: &str) -> String {
+        self.remaining.clear();
+        if self.max_chars == 0 || reply.chars().count() <= self.max_chars {
+            return reply.to_string();
+        }
+        let text = ssml::strip(reply);
+        let lines: Vec<String> = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_string)
+            .collect();
+        self.lines = lines.len() > 1;
+        let units = if self.lines {
+            lines
+        } else {
+            split_sentences(&text)
+        };
+        self.total = units.len();
+        self.remaining = paginate(units, self.max_chars);
+        self.next_page().unwrap_or_default()
+    }
+
+    /// The next page of the current reply, if any is left.
+    pub fn next_page(&mut self) -> Option<String> {
+        let page = self.remaining.pop_front()?;
+        let separator = if self.lines { "\n" } else { " " };
+        let mut text = page.units.join(separator);
+        if !self.remaining.is_empty() {
+            let hint = if self.lines {
+                format!(
+                    "That was {} of {} lines. Say continue to hear more.",
+                    page.read, self.total
+                )
+            } else {
+                "Say continue to hear more.".to_string()
+            };
+            text.push_str(separator);
+            text.push_str(&hint);
+        }
+        Some(text)
+    }
+
+    /// Whether `command` asks for the next page.
+    pub fn is_continue_command(command: &str) -> bool {
+        let command = command
+            .trim()
+            .trim_end_matches(|c: char| c.is_ascii_punctuation())
+            .to_lowercase();
+        let command = command.strip_prefix("please ").unwrap_or(&command);
+        CONTINUE_PHRASES.contains(&command)
+    }
+}
+
+/// Group `units` into pages of at most `max_chars` characters. A unit
+/// longer than a page is split between words over several pages.
+fn paginate(units: Vec<String>, max_chars: usize) -> VecDeque<Page> {
+    let mut pages = VecDeque::new();
+    let mut page = Vec::new();
+    let mut len = 0;
+    for (read, unit) in units.into_iter().enumerate() {
+        let unit_len = unit.chars().count();
+        if len > 0 && len + unit_len > max_chars {
+            pages.push_back(Page {
+                units: std::mem::take(&mut page),
+                read,
+            });
+            len = 0;
+        }
+        if unit_len > max_chars {
+            let mut chunk = String::new();
+            for word in unit.split_whitespace() {
+                if !chunk.is_empty() && chunk.chars().count() + word.chars().count() >= max_chars {
+                    pages.push_back(Page {
+                        units: vec![std::mem::take(&mut chunk)],
+                        read,
+                    });
+                }
+                if !chunk.is_empty() {
+                    chunk.push(' ');
+                }
+                chunk.push_str(word);
+            }
+            len = chunk.chars().count();
+            page.push(chunk);
+            continue;
+        }
+        page.push(unit);
+        len += unit_len;
+    }
+    if !page.is_empty() {
+        pages.push_back(Page {
+            read: pages.back().map_or(0, |p: &Page| p.read) + page.len(),
+            units: page,
+        });
+    }
+    pages
+}
@@ -0,0 +1,225 @@
+//! [Piper](https://github.com/rhasspy/piper) neural TTS. Piper is spawned as
+//! an external process and its raw audio is piped into a player:
+//!
+//!  * `PIPER_MODEL` (**required** for Piper): path to a `.onnx` voice model.
+//!  * `PIPER_SPEAKER` (optional): speaker id for multi-speaker models.
+//!  * `PIPER_BIN` (optional): Piper executable. Defaults to `piper`.
+//!  * `PIPER_PLAYER` (optional): command that plays raw 16-bit mono PCM
+//!    from stdin. Defaults to `aplay`; the sample rate is appended as
+//!    `-r <rate> -f S16_LE -t raw -c 1 -`.
+//!
+//! Piper can only change the speaking rate (`TTS_RATE`), through its
+//! `--length_scale` option.
+//!
+//! Other voices are the `.onnx` models stored next to `PIPER_MODEL`;
+//! `VOICE_NAME` selects one of them by file name.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+
+use super::{Speaker, VoiceInfo};
+use crate::audio_output::Audio;
+
+/// Speaks by piping Piper's raw output into an audio player.
+pub struct PiperSpeaker {
+    bin: String,
+    model: PathBuf,
+    speaker: Option<String>,
+    player: String,
+    sample_rate: u32,
+    /// Speaking rate relative to normal; Piper's length scale is its inverse.
+    rate: f32,
+    /// The synthesiser and audio player processes of the current
+    /// utterance, if any.
+    processes: Vec<Child>,
+}
+
+/// Piper ships a JSON config next to each model that records the sample
+/// rate of its output. Fall back to the common 22.05 kHz.
+fn model_sample_rate(model: &Path) -> u32 {
+    std::fs::read_to_string(format!("{}.json", model.display()))
+        .ok()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .and_then(|v| v["audio"]["sample_rate"].as_u64())
+        .map(|r| r as u32)
+        .unwrap_or(22050)
+}
+
+impl PiperSpeaker {
+    pub fn from_env() -> Result<Self> {
+        let model = std::env::var("PIPER_MODEL").context(
+            "PIPER_MODEL must point to a Piper .onnx voice model when VOICE_ENGINE=piper",
+        )?;
+        let model = PathBuf::from(model);
+        Ok(Self {
+            bin: std::env::var("PIPER_BIN").unwrap_or_else(|_| "piper".to_string()),
+            sample_rate: model_sample_rate(&model),
+            model,
+            speaker: std::env::var("PIPER_SPEAKER").ok(),
+            player: std::env::var("PIPER_PLAYER").unwrap_or_else(|_| "aplay".to_string()),
+            rate: 1.0,
+            processes: Vec::new(),
+        })
+    }
+
+    /// The Piper command for the current model and settings, reading text
+    /// from stdin.
+    fn command(&self) -> Command {
+        let mut synth = Command::new(&self.bin);
+        synth.arg("--model").arg(&self.model).arg("--output-raw");
+        if self.rate != 1.0 {
+            synth
+                .arg("--length_scale")
+                .arg(format!("{:.2}", 1.0 / self.rate));
+        }
+        if let Some(speaker) = &self.speaker {
+            synth.arg("--speaker").arg(speaker);
+        }
+        synth
+            .stdin(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        synth
+    }
+
+    /// The `.onnx` models in the same directory as the current model.
+    fn models(&self) -> Vec<PathBuf> {
+        let dir = self.model.parent().unwrap_or(Path::new("."));
+        let mut models: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|ext| ext == "onnx"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        models.sort();
+        models
+    }
+}
+
+/// Voice name of a model file: its file name without the extension.
+fn model_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl Speaker for PiperSpeaker {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        self.stop().await?;
+        let mut synth = self.command();
+        synth.stdout(Stdio::piped());
+        let mut synth = synth.spawn().context("failed to spawn Piper process")?;
+        let audio: Stdio = synth
+            .stdout
+            .take()
+            .context("Piper stdout unavailable")?
+            .try_into()
+            .context("failed to connect Piper to the audio player")?;
+        let mut player = Command::new(&self.player);
+        player
+            .args([
+                "-r",
+                &self.sample_rate.to_string(),
+                "-f",
+                "S16_LE",
+                "-t",
+                "raw",
+            ])
+            .args(["-c", "1", "-"])
+            .stdin(audio)
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        let player = player
+            .spawn()
+            .context("failed to spawn audio player for Piper")?;
+        if let Some(mut stdin) = synth.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .await
+                .context("failed to write to Piper stdin")?;
+            stdin.shutdown().await.ok();
+        }
+        // Keep the handles so that `stop` can kill them, then wait for
+        // the player to finish.
+        self.processes = vec![synth, player];
+        if let Some(player) = self.processes.last_mut() {
+            let _ = player.wait().await;
+        }
+        self.processes.clear();
+        Ok(())
+    }
+
+    async fn synthesize(&mut self, text: &str) -> Result<Audio> {
+        self.stop().await?;
+        let mut synth = self.command();
+        synth.stdout(Stdio::piped());
+        let mut synth = synth.spawn().context("failed to spawn Piper process")?;
+        if let Some(mut stdin) = synth.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .await
+                .context("failed to write to Piper stdin")?;
+            stdin.shutdown().await.ok();
+        }
+        let output = synth
+            .wait_with_output()
+            .await
+            .context("failed to read Piper output")?;
+        if !output.status.success() {
+            bail!("Piper exited with {}", output.status);
+        }
+        Ok(Audio::from_raw_mono(&output.stdout, self.sample_rate))
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        for mut child in self.processes.drain(..) {
+            let _ = child.kill().await;
+        }
+        Ok(())
+    }
+
+    fn set_rate(&mut self, rate: f32) -> Result<()> {
+        self.rate = rate;
+        Ok(())
+    }
+
+    /// Switch to another model in the same directory, matched case
+    /// insensitively against its file name.
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        let target = name.to_lowercase();
+        let model = self
+            .models()
+            .into_iter()
+            .find(|m| model_name(m).to_lowercase().contains(&target))
+            .ok_or_else(|| anyhow!(format!("no Piper model matching '{name}' found")))?;
+        self.sample_rate = model_sample_rate(&model);
+        self.model = model;
+        Ok(())
+    }
+
+    fn list_voices(&self) -> Result<Vec<VoiceInfo>> {
+        Ok(self
+            .models()
+            .iter()
+            .map(|m| {
+                let name = model_name(m);
+                // Piper models are named `<lang>_<REGION>-<name>-<quality>`.
+                let language = name.split('-').next().map(|l| l.replace('_', "-"));
+                VoiceInfo {
+                    name,
+                    language,
+                    gender: None,
+                }
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,134 @@
+//! User-editable pronunciation dictionary applied before speaking.
+//!
+//! Speech engines stumble over technical terms ("kubectl", "nginx"). The
+//! lexicon in `~/.jarvis/pronunciations.toml` (or the file named by
+//! `PRONUNCIATIONS_FILE`) rewrites them before synthesis. Whole words are
+//! matched case insensitively; regular expression rules are applied
+//! afterwards, in order, and may use `$1`-style capture groups:
+//!
+//! ```toml
+//! [words]
+//! kubectl = "cube control"
+//! nginx = "engine x"
+//! "c++" = "see plus plus"   # quote keys with punctuation
+//!
+//! [[rules]]
+//! pattern = '(\d+)ms\b'
+//! replace = "$1 milliseconds"
+//! ```
+//!
+//! A handful of common terms are built in; entries in the file override
+//! them. Only the spoken audio changes — the text written to
+//! `jarvis.spoken` and the accessibility mirror keep the original wording.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde::Deserialize;
+
+/// Pronunciations that apply unless the user file overrides them.
+const BUILT_IN: &[(&str, &str)] = &[
+    ("kubectl", "cube control"),
+    ("nginx", "engine x"),
+    ("systemctl", "system control"),
+    ("journalctl", "journal control"),
+    ("sudo", "sue doo"),
+    ("stdout", "standard out"),
+    ("stderr", "standard error"),
+    ("stdin", "standard in"),
+    ("json", "jay son"),
+    ("yaml", "yammel"),
+    ("sql", "sequel"),
+    ("gui", "gooey"),
+    ("cli", "C L I"),
+    ("ssh", "S S H"),
+    ("url", "U R L"),
+    ("npm", "N P M"),
+];
+
+#[derive(Debug, Default, Deserialize)]
+struct LexiconFile {
+    #[serde(default)]
+    words: BTreeMap<String, String>,
+    #[serde(default)]
+    rules: Vec<RuleEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleEntry {
+    pattern: String,
+    replace: String,
+}
+
+/// Compiled pronunciation rules.
+#[derive(Debug, Default)]
+pub struct Lexicon {
+    rules: Vec<(Regex, String)>,
+}
+
+impl Lexicon {
+    /// Load the built-in entries and the user's file. Problems with the
+    /// file are logged; the lexicon never prevents speech.
+    pub fn load() -> Self {
+        let path = std::env::var("PRONUNCIATIONS_FILE")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| dirs::home_dir().map(|h| h.join(".jarvis").join("pronunciations.toml")));
+        let file = match path.as_ref().map(std::fs::read_to_string) {
+            Some(Ok(data)) => toml::from_str::<LexiconFile>(&data).unwrap_or_else(|e| {
+                log::warn!("Ignoring invalid pronunciation file {:?}: {e}", path);
+                LexiconFile::default()
+            }),
+            _ => LexiconFile::default(),
+        };
+        Self::from_entries(file)
+    }
+
+    fn from_entries(file: LexiconFile) -> Self {
+        let mut words: BTreeMap<String, String> = BUILT_IN
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        for (word, spoken) in file.words {
+            words.insert(word.to_lowercase(), spoken);
+        }
+        let mut rules = Vec::new();
+        for (word, spoken) in words {
+            // `\b` only means something next to a word character, so terms
+            // such as "c++" only get a boundary on their alphanumeric side.
+            let boundary = |c: Option<char>| {
+                if c.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    r"\b"
+                } else {
+                    ""
+                }
+            };
+            let pattern = format!(
+                "(?i){}{}{}",
+                boundary(word.chars().next()),
+                regex::escape(&word),
+                boundary(word.chars().last())
+            );
+            if let Ok(re) = Regex::new(&pattern) {
+                rules.push((re, spoken.replace('$', "$$")));
+            }
+        }
+        for rule in file.rules {
+            match Regex::new(&rule.pattern) {
+                Ok(re) => rules.push((re, rule.replace)),
+                Err(e) => log::warn!("Ignoring pronunciation rule '{}': {e}", rule.pattern),
+            }
+        }
+        Self { rules }
+    }
+
+    /// Rewrite `text` for speaking.
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (re, replacement) in &self.rules {
+            text = re.replace_all(&text, replacement.as_str()).into_owned();
+        }
+        text
+    }
+}
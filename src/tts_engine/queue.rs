@@ -0,0 +1,401 @@
+//! Ordered speech queue shared by all clones of a
+//! [`TtsEngine`](super::TtsEngine).
+//!
+//! Utterances are spoken one after another by a background task that owns
+//! playback. Normal messages are appended to the queue; priority messages
+//! jump to the front and interrupt whatever is playing. Each utterance is
+//! spoken sentence by sentence and can be interrupted between or during
+//! sentences. SSML is handed to the backend as one piece if it supports
+//! it, and converted to plain text otherwise.
+//!
+//! Queuing returns a [`SpeechHandle`] straight away, so the caller can
+//! carry on (for example, start listening) while the utterance plays.
+//! Every utterance has a [`CancellationToken`]; cancelling it stops the
+//! utterance at once, or skips it if it is still waiting. If an
+//! [`EventBus`] is attached, the start and end of every utterance are
+//! published as [`JarvisEvent::SpeakingStarted`] and
+//! [`JarvisEvent::SpeakingFinished`].
+
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+
+use super::cache::AudioCache;
+use super::ducking::MediaDucker;
+use super::language::LanguageVoices;
+use super::normalize::Normalizer;
+use super::pronunciation::Lexicon;
+use super::sentences::split_sentences;
+use super::ssml;
+use super::Speaker;
+use crate::accessibility::AccessibilityOutput;
+use crate::audio_output::AudioOutput;
+use crate::events::{EventBus, JarvisEvent};
+
+/// A queued utterance. Awaiting the handle waits until it has been spoken,
+/// interrupted or dropped from the queue; dropping the handle leaves the
+/// utterance queued.
+pub struct SpeechHandle {
+    done: oneshot::Receiver<Result<()>>,
+    cancel: CancellationToken,
+}
+
+impl SpeechHandle {
+    /// Stop this utterance if it is playing, or skip it once its turn
+    /// comes. Other utterances are not affected.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+impl Future for SpeechHandle {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        // A dropped utterance counts as done.
+        Pin::new(&mut self.done)
+            .poll(cx)
+            .map(|result| result.unwrap_or(Ok(())))
+    }
+}
+
+struct Utterance {
+    text: String,
+    /// Play from (and store in) the audio cache.
+    cached: bool,
+    /// Voice settings at the time the utterance was queued, for the cache
+    /// key.
+    voice: String,
+    /// The voice selected by the user, to return to after a reply in
+    /// another language.
+    normal_voice: Option<String>,
+    done: oneshot::Sender<Result<()>>,
+    /// Interrupts this utterance, or skips it before it is spoken.
+    cancel: CancellationToken,
+}
+
+#[derive(Default)]
+struct State {
+    pending: VecDeque<Utterance>,
+    /// Cancellation token of the utterance being spoken, if any.
+    current: Option<CancellationToken>,
+    /// Engine and voice settings, used to key the audio cache.
+    voice: BTreeMap<&'static str, String>,
+    events: Option<EventBus>,
+}
+
+/// Everything the worker needs; taken when the first utterance is queued
+/// so that the task is spawned inside the caller's Tokio runtime.
+struct Worker {
+    wake: mpsc::UnboundedReceiver<()>,
+    accessibility: Option<AccessibilityOutput>,
+    /// Device to play synthesised audio on instead of the engine's own
+    /// playback.
+    output: Option<AudioOutput>,
+    cache: Option<AudioCache>,
+    rewriter: Arc<Rewriter>,
+    /// Turns other applications down while the queue is being spoken.
+    ducker: Option<MediaDucker>,
+    languages: Option<LanguageVoices>,
+}
+
+/// Turns text into the words a backend without SSML support should say:
+/// markup is stripped (see [`super::ssml`]), then the pronunciation
+/// dictionary (see [`super::pronunciation`]) and number normalisation (see
+/// [`super::normalize`]) are applied.
+struct Rewriter {
+    lexicon: Lexicon,
+    normalizer: Option<Normalizer>,
+}
+
+impl Rewriter {
+    fn apply(&self, text: &str) -> String {
+        let text = self.lexicon.apply(&ssml::strip(text));
+        match &self.normalizer {
+            Some(normalizer) => normalizer.apply(&text),
+            None => text,
+        }
+    }
+}
+
+/// Handle to the queue. Cloning shares the same queue and worker.
+#[derive(Clone)]
+pub struct SpeechQueue {
+    backend: Arc<tokio::sync::Mutex<Box<dyn Speaker>>>,
+    state: Arc<Mutex<State>>,
+    wake: mpsc::UnboundedSender<()>,
+    worker: Arc<Mutex<Option<Worker>>>,
+    rewriter: Arc<Rewriter>,
+}
+
+impl SpeechQueue {
+    /// Create a queue for `backend`; `engine` names it in cache keys.
+    pub fn new(
+        engine: &str,
+        backend: Box<dyn Speaker>,
+        accessibility: Option<AccessibilityOutput>,
+        output: Option<AudioOutput>,
+    ) -> Self {
+        let (wake, rx) = mpsc::unbounded_channel();
+        let muted = accessibility.as_ref().is_some_and(|a| a.mute_audio());
+        let mut state = State::default();
+        state.voice.insert("engine", engine.to_string());
+        let rewriter = Arc::new(Rewriter {
+            lexicon: Lexicon::load(),
+            normalizer: Normalizer::from_env(),
+        });
+        Self {
+            backend: Arc::new(tokio::sync::Mutex::new(backend)),
+            state: Arc::new(Mutex::new(state)),
+            wake,
+            worker: Arc::new(Mutex::new(Some(Worker {
+                wake: rx,
+                accessibility,
+                output,
+                cache: AudioCache::from_env(),
+                rewriter: rewriter.clone(),
+                ducker: if muted { None } else { MediaDucker::from_env() },
+                languages: LanguageVoices::from_env(),
+            }))),
+            rewriter,
+        }
+    }
+
+    /// Rewrite `text` the way it will be spoken by a backend without SSML
+    /// support.
+    pub fn prepare(&self, text: &str) -> String {
+        self.rewriter.apply(text)
+    }
+
+    /// The backend, once it is no longer speaking.
+    pub async fn backend(&self) -> tokio::sync::MutexGuard<'_, Box<dyn Speaker>> {
+        self.backend.lock().await
+    }
+
+    /// The backend, for changing voice settings. Fails instead of waiting
+    /// while the backend is speaking.
+    pub fn try_backend(&self) -> Option<tokio::sync::MutexGuard<'_, Box<dyn Speaker>>> {
+        self.backend.try_lock().ok()
+    }
+
+    /// Publish the start and end of every utterance on `events`.
+    pub fn set_event_bus(&self, events: EventBus) {
+        self.state.lock().unwrap().events = Some(events);
+    }
+
+    /// Record a voice setting so that cached audio made with different
+    /// settings is not reused.
+    pub fn set_voice_setting(&self, name: &'static str, value: String) {
+        self.state.lock().unwrap().voice.insert(name, value);
+    }
+
+    /// Add `text` to the end of the queue. With `cached`, the audio is
+    /// played from the cache (see [`super::cache`]).
+    pub fn enqueue(&self, text: &str, cached: bool) -> SpeechHandle {
+        self.push(text, cached, false, CancellationToken::new())
+    }
+
+    /// Add `text` to the end of the queue, to be stopped or skipped once
+    /// `cancel` is cancelled. Cancelling the returned handle leaves
+    /// `cancel` untouched.
+    pub fn enqueue_cancellable(&self, text: &str, cancel: &CancellationToken) -> SpeechHandle {
+        self.push(text, false, false, cancel.child_token())
+    }
+
+    /// Interrupt the current utterance and speak `text` next. The rest of
+    /// the queue is kept and resumes afterwards.
+    pub fn speak_now(&self, text: &str) -> SpeechHandle {
+        let done = self.push(text, false, true, CancellationToken::new());
+        self.interrupt_current();
+        done
+    }
+
+    /// Drop every queued utterance and interrupt the current one.
+    pub fn clear(&self) {
+        let dropped: Vec<Utterance> = self.state.lock().unwrap().pending.drain(..).collect();
+        if !dropped.is_empty() {
+            log::debug!("Dropped {} queued utterance(s)", dropped.len());
+        }
+        self.interrupt_current();
+    }
+
+    fn interrupt_current(&self) {
+        if let Some(cancel) = self.state.lock().unwrap().current.take() {
+            cancel.cancel();
+        }
+    }
+
+    fn push(
+        &self,
+        text: &str,
+        cached: bool,
+        front: bool,
+        cancel: CancellationToken,
+    ) -> SpeechHandle {
+        self.start_worker();
+        let (done, rx) = oneshot::channel();
+        {
+            let mut state = self.state.lock().unwrap();
+            let voice: Vec<String> = state
+                .voice
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect();
+            let utterance = Utterance {
+                text: text.to_string(),
+                cached,
+                voice: voice.join(";"),
+                normal_voice: state.voice.get("voice").cloned(),
+                done,
+                cancel: cancel.clone(),
+            };
+            if front {
+                state.pending.push_front(utterance);
+            } else {
+                state.pending.push_back(utterance);
+            }
+        }
+        let _ = self.wake.send(());
+        SpeechHandle { done: rx, cancel }
+    }
+
+    fn start_worker(&self) {
+        let Some(worker) = self.worker.lock().unwrap().take() else {
+            return;
+        };
+        let backend = self.backend.clone();
+        let state = self.state.clone();
+        tokio::spawn(run_worker(backend, state, worker));
+    }
+}
+
+/// Speak queued utterances until every [`SpeechQueue`] handle is dropped.
+async fn run_worker(
+    backend: Arc<tokio::sync::Mutex<Box<dyn Speaker>>>,
+    state: Arc<Mutex<State>>,
+    mut worker: Worker,
+) {
+    while worker.wake.recv().await.is_some() {
+        loop {
+            let (utterance, events) = {
+                let mut state = state.lock().unwrap();
+                let Some(utterance) = state.pending.pop_front() else {
+                    state.current = None;
+                    break;
+                };
+                state.current = Some(utterance.cancel.clone());
+                (utterance, state.events.clone())
+            };
+            if utterance.cancel.is_cancelled() {
+                let _ = utterance.done.send(Ok(()));
+                continue;
+            }
+            if let Some(ducker) = worker.ducker.as_mut() {
+                ducker.duck().await;
+            }
+            let text = ssml::strip(&utterance.text);
+            if let Some(events) = &events {
+                events.emit(JarvisEvent::SpeakingStarted { text: text.clone() });
+            }
+            let result = speak(&backend, &mut worker, &utterance).await;
+            if let Some(events) = &events {
+                events.emit(JarvisEvent::SpeakingFinished { text });
+            }
+            let _ = utterance.done.send(result);
+        }
+        if let Some(ducker) = worker.ducker.as_mut() {
+            ducker.restore().await;
+        }
+    }
+}
+
+async fn speak(
+    backend: &tokio::sync::Mutex<Box<dyn Speaker>>,
+    worker: &mut Worker,
+    utterance: &Utterance,
+) -> Result<()> {
+    // Mirror the text before synthesis so that braille and large-text
+    // readers are not delayed by audio playback.
+    if let Some(a11y) = &worker.accessibility {
+        a11y.mirror(&ssml::strip(&utterance.text));
+        if a11y.mute_audio() {
+            return Ok(());
+        }
+    }
+    let mut backend = backend.lock().await;
+    if let Some(languages) = worker.languages.as_mut() {
+        languages.select(
+            backend.as_mut(),
+            &utterance.text,
+            utterance.normal_voice.as_deref(),
+        );
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    if ssml::is_ssml(&utterance.text) && backend.supports_ssml() {
+        // Markup may span sentences, so the document is spoken whole.
+        let document = ssml::wrap(&utterance.text);
+        tokio::select! {
+            result = say(backend.as_mut(), &mut worker.output, &document, &stop) => return result,
+            _ = utterance.cancel.cancelled() => {
+                stop.store(true, Ordering::SeqCst);
+                return backend.stop().await;
+            }
+        }
+    }
+    let text = worker.rewriter.apply(&utterance.text);
+    if let (true, Some(cache)) = (utterance.cached, &worker.cache) {
+        tokio::select! {
+            result = cache.speak(backend.as_mut(), worker.output.as_ref(), &utterance.voice, text.trim(), &stop) => {
+                if result? {
+                    return Ok(());
+                }
+            }
+            _ = utterance.cancel.cancelled() => {
+                stop.store(true, Ordering::SeqCst);
+                backend.stop().await?;
+                return Ok(());
+            }
+        }
+    }
+    // Synthesising a short sentence is much quicker than a whole
+    // paragraph, so the user hears the start of the reply sooner.
+    for sentence in split_sentences(&text) {
+        tokio::select! {
+            result = say(backend.as_mut(), &mut worker.output, &sentence, &stop) => result?,
+            _ = utterance.cancel.cancelled() => {
+                stop.store(true, Ordering::SeqCst);
+                backend.stop().await?;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Speak one sentence, on the selected output device if there is one.
+async fn say(
+    backend: &mut dyn Speaker,
+    output: &mut Option<AudioOutput>,
+    sentence: &str,
+    stop: &Arc<AtomicBool>,
+) -> Result<()> {
+    if let Some(device) = output {
+        match backend.synthesize(sentence).await {
+            Ok(audio) => return device.play(audio, stop.clone()).await,
+            Err(e) => {
+                log::warn!(
+                    "Cannot route speech to the selected speaker ({e}); falling back to the engine's own playback."
+                );
+                *output = None;
+            }
+        }
+    }
+    backend.speak(sentence).await
+}
@@ -0,0 +1,110 @@
+//! [RHVoice](https://github.com/RHVoice/RHVoice) via its command line test
+//! program, matching the behaviour of the original Python prototype.
+//!
+//!  * `RHVOICE_BIN` (optional): path to the RHVoice test program. Defaults
+//!    to `/snap/bin/rhvoice.test` if it exists, otherwise `RHVoice-test`
+//!    from `PATH`.
+//!  * `VOICE_NAME` (optional): RHVoice voice profile. Defaults to `slt`.
+
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::command::CommandSpeaker;
+use super::{Speaker, VoiceInfo};
+use crate::audio_output::Audio;
+
+/// Directories where RHVoice installs its voice data.
+const VOICE_DIRS: &[&str] = &[
+    "/usr/share/RHVoice/voices",
+    "/usr/local/share/RHVoice/voices",
+    "/snap/rhvoice/current/share/RHVoice/voices",
+];
+
+/// Arguments passed to the RHVoice test program.
+const ARGS: &[&str] = &[
+    "-p", "{voice}", "-r", "{rate}", "-t", "{pitch}", "-v", "{volume}",
+];
+
+/// Speaks by piping text into the RHVoice test program.
+pub struct RhVoiceSpeaker {
+    inner: CommandSpeaker,
+    /// The same program writing a WAV file, for [`Speaker::synthesize`].
+    to_file: CommandSpeaker,
+}
+
+impl RhVoiceSpeaker {
+    pub fn from_env() -> Self {
+        let bin = std::env::var("RHVOICE_BIN").unwrap_or_else(|_| {
+            if Path::new("/snap/bin/rhvoice.test").exists() {
+                "/snap/bin/rhvoice.test".to_string()
+            } else {
+                "RHVoice-test".to_string()
+            }
+        });
+        let file_args: Vec<&str> = ARGS.iter().copied().chain(["-o", "{out}"]).collect();
+        Self {
+            inner: CommandSpeaker::new(&bin, ARGS, Some("slt".to_string())),
+            to_file: CommandSpeaker::new(&bin, &file_args, Some("slt".to_string())),
+        }
+    }
+}
+
+#[async_trait]
+impl Speaker for RhVoiceSpeaker {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        self.inner.speak(text).await
+    }
+
+    async fn synthesize(&mut self, text: &str) -> Result<Audio> {
+        self.to_file.synthesize(text).await
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.to_file.stop().await?;
+        self.inner.stop().await
+    }
+
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        self.to_file.set_voice(&name.to_lowercase())?;
+        self.inner.set_voice(&name.to_lowercase())
+    }
+
+    fn set_rate(&mut self, rate: f32) -> Result<()> {
+        self.to_file.set_rate(rate)?;
+        self.inner.set_rate(rate)
+    }
+
+    fn set_pitch(&mut self, pitch: f32) -> Result<()> {
+        self.to_file.set_pitch(pitch)?;
+        self.inner.set_pitch(pitch)
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<()> {
+        self.to_file.set_volume(volume)?;
+        self.inner.set_volume(volume)
+    }
+
+    /// RHVoice voices are directories under the voice data directory.
+    fn list_voices(&self) -> Result<Vec<VoiceInfo>> {
+        let mut voices = Vec::new();
+        for dir in VOICE_DIRS {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if entry.path().is_dir() {
+                    voices.push(VoiceInfo {
+                        name: entry.file_name().to_string_lossy().to_lowercase(),
+                        language: None,
+                        gender: None,
+                    });
+                }
+            }
+        }
+        voices.sort_by(|a, b| a.name.cmp(&b.name));
+        voices.dedup_by(|a, b| a.name == b.name);
+        Ok(voices)
+    }
+}
@@ -0,0 +1,100 @@
+//! Splitting replies into sentences so that speech can start as soon as
+//! the first sentence is available instead of after the whole reply has
+//! been synthesised.
+
+/// Words that end in a full stop without ending the sentence.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "etc", "e.g", "i.e", "approx", "fig",
+    "inc", "ltd",
+];
+
+/// Incremental sentence splitter. Text is pushed in arbitrary chunks, for
+/// example as tokens arrive from a streaming language model, and complete
+/// sentences are returned as soon as their end is known.
+#[derive(Debug, Default)]
+pub struct SentenceSplitter {
+    buffer: String,
+}
+
+impl SentenceSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `chunk` and return every sentence that is now complete.
+    pub fn push(&mut self, chunk: &str) -> Vec<String> {
+        self.buffer.push_str(chunk);
+        let mut sentences = Vec::new();
+        while let Some(end) = sentence_end(&self.buffer) {
+            let rest = self.buffer.split_off(end);
+            let sentence = std::mem::replace(&mut self.buffer, rest);
+            let sentence = sentence.trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence.to_string());
+            }
+        }
+        sentences
+    }
+
+    /// Return whatever is left once the input has ended.
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        let rest = rest.trim();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
+}
+
+/// Split a complete text into sentences.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut splitter = SentenceSplitter::new();
+    let mut sentences = splitter.push(text);
+    sentences.extend(splitter.finish());
+    sentences
+}
+
+/// Byte offset just past the first sentence in `text`, if its end can
+/// already be decided. A terminator at the very end of the buffer is not a
+/// boundary yet because more text (e.g. the digits of "3.14") may follow.
+fn sentence_end(text: &str) -> Option<usize> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    for (i, &(pos, c)) in chars.iter().enumerate() {
+        if c == '\n' {
+            // Line breaks separate list items and paragraphs.
+            if text[..pos].trim().is_empty() {
+                continue;
+            }
+            return Some(pos + 1);
+        }
+        if !matches!(c, '.' | '!' | '?' | '…') {
+            continue;
+        }
+        // Include closing quotes and brackets, and repeated terminators
+        // such as "?!" or "...".
+        let mut j = i + 1;
+        while j < chars.len()
+            && matches!(chars[j].1, '.' | '!' | '?' | '"' | '\'' | ')' | '”' | '’')
+        {
+            j += 1;
+        }
+        let next = chars.get(j)?;
+        if !next.1.is_whitespace() {
+            continue;
+        }
+        if c == '.' && j == i + 1 && is_abbreviation(&text[..pos]) {
+            continue;
+        }
+        return Some(next.0);
+    }
+    None
+}
+
+/// Whether the word before a full stop is an abbreviation or an initial.
+fn is_abbreviation(before: &str) -> bool {
+    let word = before
+        .rsplit(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or("");
+    // Initials such as "J. R. R. Tolkien", but not the pronoun "I".
+    let initial = word.chars().count() == 1 && word.chars().all(char::is_uppercase) && word != "I";
+    initial || ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+}
@@ -0,0 +1,211 @@
+//! A small subset of [SSML](https://www.w3.org/TR/speech-synthesis11/) for
+//! more expressive replies.
+//!
+//! Text containing SSML markup is passed unchanged to backends that
+//! understand it (see [`Speaker::supports_ssml`](super::Speaker::supports_ssml)).
+//! For every other backend, and for the accessibility mirror, it is turned
+//! into plain text that reads the same way:
+//!
+//!  * `<break time="500ms"/>` becomes a comma, or a full stop for pauses of
+//!    half a second or more.
+//!  * `<say-as interpret-as="characters">10.0.0.1</say-as>` spells out its
+//!    content: "1 0 dot 0 dot 0 dot 1".
+//!  * `<sub alias="structured query language">SQL</sub>` is replaced by its
+//!    alias.
+//!  * `<p>` and `<s>` end a sentence; all other tags (`<emphasis>`,
+//!    `<prosody>`, ...) are dropped and their content kept.
+
+/// Tags recognised as SSML when they appear in otherwise plain text.
+const TAGS: &[&str] = &[
+    "speak", "break", "say-as", "emphasis", "sub", "prosody", "p", "s",
+];
+
+/// Whether `text` contains SSML markup.
+pub fn is_ssml(text: &str) -> bool {
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let name = tag_name(rest.trim_start_matches('/'));
+        if TAGS.contains(&name) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Wrap `text` in a `<speak>` root element unless it already has one, as
+/// most engines require.
+pub fn wrap(text: &str) -> String {
+    let text = text.trim();
+    if text.starts_with("<speak") {
+        text.to_string()
+    } else {
+        format!("<speak>{text}</speak>")
+    }
+}
+
+/// `text` with any SSML markup converted to plain text; other text is
+/// returned unchanged.
+pub fn strip(text: &str) -> String {
+    if is_ssml(text) {
+        to_plain(text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Convert SSML into plain text for engines that would otherwise read the
+/// markup aloud.
+pub fn to_plain(ssml: &str) -> String {
+    let mut out = String::new();
+    // The kind of text being read: spelled out, or replaced by an alias.
+    let mut spell = false;
+    let mut alias: Option<String> = None;
+    let mut rest = ssml;
+    while let Some(start) = rest.find('<') {
+        let text = decode_entities(&rest[..start]);
+        if alias.is_none() {
+            if spell {
+                out.push_str(&spell_out(&text));
+            } else {
+                out.push_str(&text);
+            }
+        }
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+        let closing = tag.starts_with('/');
+        let name = tag_name(tag.trim_start_matches('/'));
+        match (name, closing) {
+            ("break", false) => out.push_str(if long_break(tag) { " . " } else { " , " }),
+            ("say-as", false) => {
+                spell = matches!(
+                    attribute(tag, "interpret-as").as_deref(),
+                    Some("characters" | "spell-out" | "verbatim" | "digits")
+                );
+            }
+            ("say-as", true) => spell = false,
+            ("sub", false) => alias = attribute(tag, "alias"),
+            ("sub", true) => {
+                if let Some(alias) = alias.take() {
+                    out.push_str(&alias);
+                }
+            }
+            ("p" | "s", true) => out.push_str(" . "),
+            ("p" | "s", false) => out.push(' '),
+            _ => {}
+        }
+    }
+    out.push_str(&decode_entities(rest));
+    tidy(&out)
+}
+
+/// The element name at the start of a tag body such as `break time="1s"/`.
+fn tag_name(tag: &str) -> &str {
+    let end = tag
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(tag.len());
+    &tag[..end]
+}
+
+/// The value of attribute `name` in a tag body.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(pos) = rest.find(name) {
+        let before = rest[..pos].chars().last();
+        let after = rest[pos + name.len()..].trim_start();
+        rest = &rest[pos + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            continue;
+        }
+        let value = &value[1..];
+        return value.find(quote).map(|end| decode_entities(&value[..end]));
+    }
+    None
+}
+
+/// Whether a `<break>` is long enough to be read as the end of a sentence.
+fn long_break(tag: &str) -> bool {
+    if let Some(strength) = attribute(tag, "strength") {
+        return matches!(strength.as_str(), "strong" | "x-strong");
+    }
+    let Some(time) = attribute(tag, "time") else {
+        return false;
+    };
+    let millis = if let Some(ms) = time.strip_suffix("ms") {
+        ms.trim().parse::<f32>().ok()
+    } else if let Some(s) = time.strip_suffix('s') {
+        s.trim().parse::<f32>().ok().map(|s| s * 1000.0)
+    } else {
+        None
+    };
+    millis.is_some_and(|ms| ms >= 500.0)
+}
+
+/// Spell out `text` one character at a time, naming punctuation that
+/// engines would otherwise skip or read inconsistently.
+fn spell_out(text: &str) -> String {
+    let mut words = Vec::new();
+    for c in text.chars() {
+        let word = match c {
+            '.' => "dot".to_string(),
+            '-' => "dash".to_string(),
+            '_' => "underscore".to_string(),
+            '/' => "slash".to_string(),
+            ':' => "colon".to_string(),
+            '@' => "at".to_string(),
+            '#' => "hash".to_string(),
+            c if c.is_whitespace() => continue,
+            c => c.to_string(),
+        };
+        words.push(word);
+    }
+    words.join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Collapse whitespace and the doubled punctuation left where breaks and
+/// sentence ends meet.
+fn tidy(text: &str) -> String {
+    let mut out = String::new();
+    for word in text.split_whitespace() {
+        if matches!(word, "." | "," | ".." | ".," | ",." | ",,") {
+            // Attach stray punctuation to the previous word; a full stop
+            // wins over a comma.
+            let stop = word.contains('.');
+            match out.pop() {
+                Some(p @ ('.' | '!' | '?')) => out.push(p),
+                Some(',') => out.push(if stop { '.' } else { ',' }),
+                Some(c) => {
+                    out.push(c);
+                    out.push(if stop { '.' } else { ',' });
+                }
+                None => {}
+            }
+            continue;
+        }
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
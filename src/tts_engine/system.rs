@@ -0,0 +1,157 @@
+//! Operating system voices via the [`tts`] crate (Speech Dispatcher on
+//! Linux, SAPI on Windows, AVFoundation on macOS).
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use tts::{Gender, Tts};
+
+use super::{Speaker, VoiceInfo};
+
+/// Speaks through the platform's native speech synthesiser.
+pub struct SystemSpeaker {
+    tts: Tts,
+}
+
+impl SystemSpeaker {
+    /// Initialise the system speech synthesis backend. If no backend is
+    /// available on the host platform this will return an error.
+    pub fn new() -> Result<Self> {
+        let tts = Tts::default().context("failed to initialise text‑to‑speech engine")?;
+        Ok(Self { tts })
+    }
+}
+
+#[async_trait]
+impl Speaker for SystemSpeaker {
+    /// This method is asynchronous because the call to
+    /// [`tts::Tts::speak`] blocks until the underlying OS has queued the
+    /// utterance. We clone the engine and speak on a blocking thread so
+    /// as not to stall the Tokio executor while synthesis takes place.
+    /// Where the backend can report it, we then wait for playback to end
+    /// so that consecutive sentences do not cut each other off.
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        let text_owned = text.to_owned();
+        let tts = self.tts.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut tts = tts;
+            // Stop any existing utterances. Ignore errors here since we
+            // immediately follow with a new speak call.
+            let _ = tts.stop();
+            tts.speak(&text_owned, true)
+                .map_err(|e| anyhow!(format!("TTS speak failed: {e:?}")))
+        })
+        .await
+        .context("failed to join blocking TTS task")??;
+        if self.tts.supported_features().is_speaking {
+            while self.tts.is_speaking().unwrap_or(false) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.tts
+            .stop()
+            .map_err(|e| anyhow!(format!("Failed to stop TTS: {:?}", e)))?;
+        Ok(())
+    }
+
+    /// The supplied name is matched case insensitively against the
+    /// available voices.
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        let available = self.tts.voices().context("failed to enumerate voices")?;
+        let target = name.to_lowercase();
+        for voice in available {
+            if voice.name().to_lowercase().contains(&target) {
+                self.tts
+                    .set_voice(&voice)
+                    .context("failed to set TTS voice")?;
+                return Ok(());
+            }
+        }
+        Err(anyhow!(format!("no voice matching '{name}' found")))
+    }
+
+    fn set_rate(&mut self, rate: f32) -> Result<()> {
+        if !self.tts.supported_features().rate {
+            return Err(anyhow!("the system TTS backend cannot change its rate"));
+        }
+        let value = scale(
+            rate,
+            self.tts.min_rate(),
+            self.tts.normal_rate(),
+            self.tts.max_rate(),
+        );
+        self.tts
+            .set_rate(value)
+            .map_err(|e| anyhow!(format!("failed to set TTS rate: {e:?}")))?;
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, pitch: f32) -> Result<()> {
+        if !self.tts.supported_features().pitch {
+            return Err(anyhow!("the system TTS backend cannot change its pitch"));
+        }
+        let value = scale(
+            pitch,
+            self.tts.min_pitch(),
+            self.tts.normal_pitch(),
+            self.tts.max_pitch(),
+        );
+        self.tts
+            .set_pitch(value)
+            .map_err(|e| anyhow!(format!("failed to set TTS pitch: {e:?}")))?;
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<()> {
+        if !self.tts.supported_features().volume {
+            return Err(anyhow!("the system TTS backend cannot change its volume"));
+        }
+        // Volume goes all the way down to the engine's minimum.
+        let (min, normal, max) = (
+            self.tts.min_volume(),
+            self.tts.normal_volume(),
+            self.tts.max_volume(),
+        );
+        let value = if volume <= 1.0 {
+            min + (normal - min) * volume.max(0.0)
+        } else {
+            scale(volume, min, normal, max)
+        };
+        self.tts
+            .set_volume(value)
+            .map_err(|e| anyhow!(format!("failed to set TTS volume: {e:?}")))?;
+        Ok(())
+    }
+
+    fn list_voices(&self) -> Result<Vec<VoiceInfo>> {
+        let voices = self.tts.voices().context("failed to enumerate voices")?;
+        Ok(voices
+            .into_iter()
+            .map(|v| VoiceInfo {
+                name: v.name(),
+                language: Some(v.language().to_string()),
+                gender: v.gender().map(|g| match g {
+                    Gender::Male => "male".to_string(),
+                    Gender::Female => "female".to_string(),
+                }),
+            })
+            .collect())
+    }
+}
+
+/// Map a multiplier in `0.5..=2.0` onto an engine range whose normal value
+/// is not necessarily in the middle: `0.5` is the minimum, `1.0` normal and
+/// `2.0` the maximum.
+fn scale(factor: f32, min: f32, normal: f32, max: f32) -> f32 {
+    let factor = factor.clamp(0.5, 2.0);
+    if factor < 1.0 {
+        normal - (normal - min) * (1.0 - factor) * 2.0
+    } else {
+        normal + (max - normal) * (factor - 1.0)
+    }
+}
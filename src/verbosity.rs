@@ -0,0 +1,73 @@
+//! How much Jarvis says when a command fails.
+//!
+//! Agent and tool errors are always logged and published as
+//! [`JarvisEvent::Error`](crate::events::JarvisEvent::Error); hands-free,
+//! Jarvis also says that something went wrong. `ERROR_VERBOSITY` selects
+//! how much:
+//!
+//!  * `terse`: just "That failed."
+//!  * `normal` (default): what failed, e.g. "Sorry, the shell task tool
+//!    failed."
+//!  * `debug`: the full error, e.g. "That failed: the shell_task tool
+//!    failed: command exited with status 127".
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+use crate::config::env_parse;
+
+/// Longest error message spoken in `debug` mode, in characters.
+const MAX_DEBUG_CHARS: usize = 300;
+
+/// Detail level of spoken error reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorVerbosity {
+    Terse,
+    #[default]
+    Normal,
+    Debug,
+}
+
+impl ErrorVerbosity {
+    /// The level from `ERROR_VERBOSITY`.
+    pub fn from_env() -> Self {
+        env_parse("ERROR_VERBOSITY", Self::default())
+    }
+
+    /// The sentence to speak for `error`.
+    pub fn describe(self, error: &anyhow::Error) -> String {
+        match self {
+            ErrorVerbosity::Terse => "That failed.".to_string(),
+            ErrorVerbosity::Normal => {
+                // The outermost context says what failed without the
+                // technical detail underneath it.
+                let what = error.to_string().replace('_', " ");
+                format!("Sorry, {}.", what.trim_end_matches('.'))
+            }
+            ErrorVerbosity::Debug => {
+                let mut detail = format!("{error:#}");
+                if detail.chars().count() > MAX_DEBUG_CHARS {
+                    detail = detail.chars().take(MAX_DEBUG_CHARS).collect();
+                    detail.push_str(", and so on");
+                }
+                format!("That failed: {}.", detail.trim_end_matches('.'))
+            }
+        }
+    }
+}
+
+impl FromStr for ErrorVerbosity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "terse" => Ok(ErrorVerbosity::Terse),
+            "normal" => Ok(ErrorVerbosity::Normal),
+            "debug" => Ok(ErrorVerbosity::Debug),
+            other => Err(anyhow!(
+                "unknown error verbosity '{other}' (expected terse, normal or debug)"
+            )),
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Wake word detection diagnostics.
+//!
+//! Every idle-mode transcript that contains, or almost contains, the
+//! trigger word is appended to `~/.jarvis/wake_log.jsonl` together with
+//! whether it activated Jarvis. When an activation is followed by a
+//! conversation that times out without any command, an outcome record is
+//! written as well; such activations are likely false positives.
+//!
+//! `jarvis tune-wake` reads the log and prints a summary of false-positive
+//! and false-negative patterns so users can pick a trigger word that their
+//! recogniser model hears reliably.
+//!
+//! Besides `TRIGGER_WORD`, more wake words can be given in `WAKE_PERSONAS`,
+//! each with its own persona and model, e.g.
+//! `WAKE_PERSONAS=codex=coder@qwen2.5-coder:7b,friday=pirate`. Every wake
+//! word keeps its own conversation and long-term memory; see
+//! [`Agent::enter_scope`](crate::agent::Agent::enter_scope).
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::env_list;
+
+/// One line of the wake log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum WakeRecord {
+    /// An idle transcript that contained or nearly contained the trigger.
+    Transcript {
+        timestamp: String,
+        transcript: String,
+        confidence: Option<f32>,
+        trigger: String,
+        activated: bool,
+        /// The transcript token(s) closest to the trigger word.
+        closest: String,
+        /// Edit distance between `closest` and the trigger word.
+        distance: usize,
+    },
+    /// Whether the conversation following an activation produced a command.
+    Outcome { timestamp: String, command: bool },
+}
+
+/// Result of matching a transcript against the trigger word.
+pub struct WakeMatch {
+    pub activated: bool,
+    closest: String,
+    distance: usize,
+}
+
+impl WakeMatch {
+    /// Whether the transcript is worth logging: either it activated, or the
+    /// closest token is within a small edit distance of the trigger.
+    fn is_relevant(&self, trigger: &str) -> bool {
+        self.activated || self.distance <= near_miss_threshold(trigger)
+    }
+}
+
+/// Near misses may differ from the trigger by about a third of its length.
+fn near_miss_threshold(trigger: &str) -> usize {
+    (trigger.chars().count() / 3).max(1)
+}
+
+/// Compare a cleaned transcript with the trigger word. Activation uses the
+/// same substring rule as the main loop; the closest window of tokens is
+/// recorded for diagnostics.
+pub fn match_trigger(transcript: &str, trigger: &str) -> WakeMatch {
+    let lower = transcript.to_lowercase();
+    let trigger = trigger.to_lowercase();
+    let activated = lower.contains(&trigger);
+    let tokens: Vec<&str> = lower.split_whitespace().collect();
+    let width = trigger.split_whitespace().count().max(1);
+    let mut best = (String::new(), usize::MAX);
+    for window in tokens.windows(width.min(tokens.len().max(1))) {
+        let candidate = window.join(" ");
+        let distance = levenshtein(&candidate, &trigger);
+        if distance < best.1 {
+            best = (candidate, distance);
+        }
+    }
+    WakeMatch {
+        activated,
+        closest: best.0,
+        distance: best.1,
+    }
+}
+
+/// A wake word with its own persona, model, conversation and memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WakeScope {
+    /// The wake word, lower case.
+    pub word: String,
+    /// The persona in `~/.jarvis/personas`; the default persona if `None`.
+    pub persona: Option<String>,
+    /// The model to use; the configured model if `None`.
+    pub model: Option<String>,
+}
+
+impl FromStr for WakeScope {
+    type Err = anyhow::Error;
+
+    /// Parse `word`, `word=persona`, `word=persona@model` or `word=@model`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (word, rest) = s.split_once('=').unwrap_or((s, ""));
+        let word = word.trim().to_lowercase();
+        if word.is_empty() {
+            anyhow::bail!("'{s}' has no wake word");
+        }
+        let (persona, model) = rest.split_once('@').unwrap_or((rest, ""));
+        let non_empty = |v: &str| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+        Ok(Self {
+            word,
+            persona: non_empty(persona),
+            model: non_empty(model),
+        })
+    }
+}
+
+/// The extra wake words configured in `WAKE_PERSONAS`. Invalid entries are
+/// logged and skipped.
+pub fn wake_scopes() -> Vec<WakeScope> {
+    env_list("WAKE_PERSONAS", &[])
+        .iter()
+        .filter_map(|entry| match entry.parse() {
+            Ok(scope) => Some(scope),
+            Err(e) => {
+                log::warn!("Ignoring WAKE_PERSONAS entry: {e}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// The rest of `transcript` if it starts with `word`, as in "codex, list
+/// the branches".
+pub fn strip_wake_word<'a>(transcript: &'a str, word: &str) -> Option<&'a str> {
+    let head = transcript.get(..word.len())?;
+    if !head.eq_ignore_ascii_case(word) {
+        return None;
+    }
+    let rest = &transcript[word.len()..];
+    if rest.starts_with(|c: char| c.is_alphanumeric()) {
+        return None;
+    }
+    Some(rest.trim_start_matches(|c: char| c.is_whitespace() || c.is_ascii_punctuation()))
+}
+
+/// Classic dynamic-programming edit distance over characters.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Append-only log of wake word decisions.
+pub struct WakeLog {
+    path: PathBuf,
+}
+
+impl Default for WakeLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WakeLog {
+    pub fn new() -> Self {
+        let base = dirs::home_dir().unwrap().join(".jarvis");
+        let _ = fs::create_dir_all(&base);
+        Self {
+            path: base.join("wake_log.jsonl"),
+        }
+    }
+
+    fn append(&self, record: &WakeRecord) {
+        let line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(e) => {
+                log::warn!("Failed to serialise wake log record: {e}");
+                return;
+            }
+        };
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| writeln!(f, "{line}"));
+        if let Err(e) = result {
+            log::warn!("Failed to write wake log: {e}");
+        }
+    }
+
+    /// Record an idle transcript if it contained or nearly contained the
+    /// trigger word.
+    pub fn record(&self, transcript: &str, confidence: Option<f32>, trigger: &str, m: &WakeMatch) {
+        if !m.is_relevant(trigger) {
+            return;
+        }
+        self.append(&WakeRecord::Transcript {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            transcript: transcript.to_string(),
+            confidence,
+            trigger: trigger.to_lowercase(),
+            activated: m.activated,
+            closest: m.closest.clone(),
+            distance: m.distance,
+        });
+    }
+
+    /// Record whether the conversation after an activation produced a
+    /// command before Jarvis went back to idle.
+    pub fn record_outcome(&self, command: bool) {
+        self.append(&WakeRecord::Outcome {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            command,
+        });
+    }
+
+    /// Build a human readable tuning report from the log.
+    pub fn tuning_report(&self) -> Result<String> {
+        let data = match fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(format!(
+                    "No wake word data recorded yet ({}). Run Jarvis for a while and try again.",
+                    self.path.display()
+                ));
+            }
+            Err(e) => return Err(e).context("failed to read wake log"),
+        };
+
+        let mut activations = 0usize;
+        let mut unused_activations = 0usize;
+        let mut awaiting_outcome = false;
+        let mut near_misses: HashMap<String, usize> = HashMap::new();
+        let mut false_positive_phrases: HashMap<String, usize> = HashMap::new();
+        let mut last_activation = String::new();
+        let mut triggers: HashMap<String, usize> = HashMap::new();
+        let mut low_confidence = 0usize;
+
+        for line in data.lines().filter(|l| !l.trim().is_empty()) {
+            let record: WakeRecord = match serde_json::from_str(line) {
+                Ok(r) => r,
+                Err(e) => {
+                    log::debug!("Skipping malformed wake log line: {e}");
+                    continue;
+                }
+            };
+            match record {
+                WakeRecord::Transcript {
+                    transcript,
+                    confidence,
+                    trigger,
+                    activated,
+                    closest,
+                    ..
+                } => {
+                    *triggers.entry(trigger).or_default() += 1;
+                    if activated {
+                        activations += 1;
+                        awaiting_outcome = true;
+                        last_activation = transcript;
+                        if confidence.is_some_and(|c| c < 0.6) {
+                            low_confidence += 1;
+                        }
+                    } else {
+                        *near_misses.entry(closest).or_default() += 1;
+                    }
+                }
+                WakeRecord::Outcome { command, .. } => {
+                    if awaiting_outcome && !command {
+                        unused_activations += 1;
+                        *false_positive_phrases
+                            .entry(last_activation.clone())
+                            .or_default() += 1;
+                    }
+                    awaiting_outcome = false;
+                }
+            }
+        }
+
+        let mut out = String::new();
+        let trigger_list: Vec<_> = triggers.keys().cloned().collect();
+        out.push_str(&format!(
+            "Wake word tuning report (trigger(s): {})\n\n",
+            trigger_list.join(", ")
+        ));
+        out.push_str(&format!("Activations:               {activations}\n"));
+        out.push_str(&format!(
+            "  followed by no command:  {unused_activations} (likely false positives)\n"
+        ));
+        out.push_str(&format!("  low confidence (<0.6):   {low_confidence}\n"));
+        let total_near: usize = near_misses.values().sum();
+        out.push_str(&format!(
+            "Near misses:               {total_near} (possible false negatives)\n"
+        ));
+
+        if !near_misses.is_empty() {
+            out.push_str("\nMost common near misses:\n");
+            for (word, count) in top(&near_misses, 10) {
+                out.push_str(&format!("  {count:>4} × \"{word}\"\n"));
+            }
+        }
+        if !false_positive_phrases.is_empty() {
+            out.push_str("\nTranscripts that activated without a follow-up command:\n");
+            for (phrase, count) in top(&false_positive_phrases, 10) {
+                out.push_str(&format!("  {count:>4} × \"{phrase}\"\n"));
+            }
+        }
+
+        out.push_str("\nSuggestions:\n");
+        let mut suggested = false;
+        if activations > 0 && unused_activations * 3 > activations {
+            out.push_str(
+                "  * Over a third of activations were not followed by a command. A longer,\n    \
+                 less common trigger word (two or three syllables) reduces false positives.\n",
+            );
+            suggested = true;
+        }
+        if total_near > activations {
+            out.push_str(
+                "  * The recogniser misses the trigger more often than it hears it. Pick a\n    \
+                 word that is in the Vosk model's vocabulary, or use a larger model.\n",
+            );
+            suggested = true;
+        }
+        if let Some((word, count)) = top(&near_misses, 1).into_iter().next() {
+            if count >= 3 {
+                out.push_str(&format!(
+                    "  * \"{word}\" is heard often. Setting TRIGGER_WORD=\"{word}\" would match\n    \
+                     how your voice is transcribed.\n"
+                ));
+                suggested = true;
+            }
+        }
+        if !suggested {
+            out.push_str("  * No obvious problems detected.\n");
+        }
+        Ok(out)
+    }
+}
+
+/// Return the `n` entries with the highest counts, most frequent first.
+fn top(map: &HashMap<String, usize>, n: usize) -> Vec<(String, usize)> {
+    let mut entries: Vec<(String, usize)> = map.iter().map(|(k, v)| (k.clone(), *v)).collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    entries.truncate(n);
+    entries
+}
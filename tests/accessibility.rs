@@ -0,0 +1,23 @@
+//! Mirroring spoken text to the accessibility output.
+
+use jarvis_rust::accessibility::AccessibilityOutput;
+
+#[test]
+fn spoken_text_is_mirrored_wrapped_and_in_capitals() {
+    let path = std::env::temp_dir().join(format!("jarvis-a11y-{}.txt", std::process::id()));
+    std::env::set_var("A11Y_OUTPUT", &path);
+    std::env::set_var("A11Y_WRAP", "12");
+    std::env::set_var("A11Y_UPPERCASE", "1");
+    let output = AccessibilityOutput::from_env().unwrap();
+    output.mirror("the quick brown fox jumps");
+    output.mirror("   ");
+    output.mirror("unbelievably");
+    output.mirror("extraordinary");
+    let mirrored = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        mirrored,
+        "THE QUICK\nBROWN FOX\nJUMPS\n\nUNBELIEVABLY\n\nEXTRAORDINAR\nY\n\n"
+    );
+    assert!(!output.mute_audio());
+}
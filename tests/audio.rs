@@ -0,0 +1,19 @@
+//! Speech audio held in memory, saved to files and routed to a device.
+
+use jarvis_rust::audio_output::{Audio, AudioOutput};
+
+#[test]
+fn audio_survives_a_round_trip_through_wav() {
+    let audio = Audio::from_raw_mono(&[1, 0, 0xff, 0xff, 0, 0x80], 22050);
+    assert_eq!(audio.samples, [1, -1, i16::MIN]);
+    let wav = audio.to_wav();
+    assert_eq!(wav.len(), 44 + 6);
+    let read = Audio::from_wav(&wav).unwrap();
+    assert_eq!(read.samples, audio.samples);
+    assert_eq!((read.sample_rate, read.channels), (22050, 1));
+    assert!(Audio::from_wav(b"RIFF....AVI LIST").is_err());
+    assert!(
+        AudioOutput::from_env().unwrap().is_none(),
+        "engines keep their own playback unless a speaker is chosen"
+    );
+}
@@ -0,0 +1,84 @@
+//! The subcommands of the `jarvis_rust` binary.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A fresh folder to use as the home directory of one test.
+fn home(name: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("jarvis-cli-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    home
+}
+
+/// Run the binary with `args`, `home` as the home directory and the
+/// variables `env`.
+fn jarvis(home: &Path, args: &[&str], env: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jarvis_rust"))
+        .args(args)
+        .env("HOME", home)
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn voices_lists_the_voices_of_the_engine() {
+    let home = home("voices");
+    std::fs::write(home.join("en_GB-alan-low.onnx"), "").unwrap();
+    let model = home.join("en_GB-alan-low.onnx");
+    let output = jarvis(
+        &home,
+        &["voices"],
+        &[
+            ("VOICE_ENGINE", "piper"),
+            ("PIPER_MODEL", model.to_str().unwrap()),
+        ],
+    );
+    std::fs::remove_dir_all(&home).unwrap();
+    assert!(output.status.success());
+    let listed = String::from_utf8(output.stdout).unwrap();
+    let row = listed.lines().nth(1).unwrap();
+    assert_eq!(
+        row.split_whitespace().collect::<Vec<_>>(),
+        ["en_GB-alan-low", "en-GB", "-"]
+    );
+}
+
+#[test]
+fn speak_test_reports_which_engines_work() {
+    let home = home("speak-test");
+    let spoken = home.join("spoken.txt");
+    let command = format!("cp /dev/stdin {}", spoken.display());
+    let output = jarvis(
+        &home,
+        &["speak-test"],
+        &[
+            ("VOICE_ENGINE", "command"),
+            ("TTS_COMMAND", command.as_str()),
+        ],
+    );
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success());
+    assert!(report.contains("\ncommand:\n"), "{report}");
+    assert!(report.contains("\nWorking engines: command\n"), "{report}");
+    assert_eq!(
+        std::fs::read_to_string(&spoken).unwrap(),
+        "If you can hear me, speech works."
+    );
+
+    let output = jarvis(
+        &home,
+        &["speak-test"],
+        &[("VOICE_ENGINE", "command"), ("TTS_COMMAND", "")],
+    );
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        report.contains("  not available: TTS_COMMAND is empty\n"),
+        "{report}"
+    );
+    assert!(report.contains("No engine could speak."), "{report}");
+
+    let output = jarvis(&home, &["speak-test", "--loud"], &[]);
+    std::fs::remove_dir_all(&home).unwrap();
+    assert!(!output.status.success());
+}
@@ -0,0 +1,52 @@
+//! Fitting the conversation history into the context window.
+
+use jarvis_rust::conversation::Conversation;
+
+fn conversation(turns: usize) -> Conversation {
+    let mut history = Conversation::new(10_000);
+    for i in 0..turns {
+        history.record(
+            &format!("question number {i} about the weather"),
+            &format!("answer number {i}: {}", "it is sunny and warm. ".repeat(15)),
+        );
+    }
+    history
+}
+
+#[test]
+fn history_within_budget_is_sent_whole() {
+    let history = conversation(3);
+    let (earlier, messages) = history.within(history.tokens());
+    assert!(earlier.is_empty());
+    assert_eq!(messages.len(), 6);
+}
+
+#[test]
+fn older_turns_become_notes() {
+    let history = conversation(10);
+    let (earlier, messages) = history.within(history.tokens() - 1);
+    assert_eq!(messages.len(), 18);
+    assert!(messages[0].content.contains("question number 1 "));
+    assert!(earlier.contains("User: question number 0 about the weather / Jarvis: answer number 0"));
+    assert!(earlier.ends_with("…\n"));
+}
+
+#[test]
+fn nothing_is_sent_without_room() {
+    let (earlier, messages) = conversation(5).within(0);
+    assert!(earlier.is_empty());
+    assert!(messages.is_empty());
+}
+
+#[test]
+fn dropped_turns_leave_notes() {
+    let mut history = Conversation::new(200);
+    for i in 0..40 {
+        history.record(&format!("question {i}"), &format!("answer {i}"));
+    }
+    let (earlier, messages) = history.within(usize::MAX);
+    let first_kept = &messages[0].content;
+    let last_dropped: usize = first_kept["question ".len()..].parse::<usize>().unwrap() - 1;
+    assert!(earlier.contains(&format!("User: question {last_dropped} /")));
+    assert!(!earlier.contains("User: question 0 /"));
+}
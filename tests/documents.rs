@@ -0,0 +1,75 @@
+//! Indexing local documents and finding the chunks a question is about.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use jarvis_rust::documents::{chunk_text, DocumentIndex, IndexReport};
+use jarvis_rust::llm::LlmBackend;
+
+/// Embeds a text by whether it mentions deadlines and holidays.
+struct Topics;
+
+#[async_trait]
+impl LlmBackend for Topics {
+    async fn generate(&self, _prompt: &str) -> Result<String> {
+        Ok(String::new())
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mentions = |text: &str, word: &str| f32::from(u8::from(text.contains(word)));
+        Ok(texts
+            .iter()
+            .map(|text| vec![mentions(text, "deadline"), mentions(text, "holiday"), 0.1])
+            .collect())
+    }
+}
+
+#[tokio::test]
+async fn changed_files_are_indexed_and_searched_by_meaning() {
+    let root = std::env::temp_dir().join(format!("jarvis-documents-{}", std::process::id()));
+    let folder = root.join("notes");
+    std::fs::create_dir_all(folder.join(".git")).unwrap();
+    std::fs::write(
+        folder.join("meeting.md"),
+        "Meeting notes.\n\nThe deadline is Friday.",
+    )
+    .unwrap();
+    std::fs::write(folder.join("plans.txt"), "The holiday starts in July.").unwrap();
+    std::fs::write(folder.join("photo.png"), "not text").unwrap();
+    std::fs::write(folder.join(".git").join("HEAD.txt"), "deadline").unwrap();
+
+    let mut index = DocumentIndex::open(root.join("documents.jsonl"));
+    let report = index.index(&folder, &Topics).await.unwrap();
+    assert_eq!(
+        report,
+        IndexReport {
+            indexed: 2,
+            unchanged: 0,
+            removed: 0
+        }
+    );
+    assert_eq!(index.chunks().len(), 2);
+
+    std::fs::remove_file(folder.join("plans.txt")).unwrap();
+    let mut index = DocumentIndex::open(root.join("documents.jsonl"));
+    let report = index.index(&folder, &Topics).await.unwrap();
+    assert_eq!(
+        report,
+        IndexReport {
+            indexed: 0,
+            unchanged: 1,
+            removed: 1
+        }
+    );
+
+    let found = index.search(&[1.0, 0.0, 0.0]);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].text, "Meeting notes.\n\nThe deadline is Friday.");
+    assert!(found[0].source.ends_with("meeting.md"));
+    assert!(index.search(&[0.0, 1.0, 0.0]).is_empty());
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(
+        chunk_text("One two.\n\nThree four five six seven.\n\n\n", 12),
+        ["One two.", "Three four", "five six", "seven."]
+    );
+}
@@ -0,0 +1,57 @@
+//! Turning other applications down while Jarvis speaks, against a fake
+//! `pactl` on the `PATH`.
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+
+use jarvis_rust::tts_engine::ducking::MediaDucker;
+
+const SINK_INPUTS: &str = "\
+Sink Input #7
+\tVolume: front-left: 39321 /  60% / -13.31 dB,   front-right: 39321 /  60% / -13.31 dB
+\tProperties:
+\t\tapplication.process.id = \"4242\"
+\t\tapplication.process.binary = \"firefox\"
+Sink Input #8
+\tVolume: front-left: 65536 / 100% / 0.00 dB
+\tProperties:
+\t\tapplication.process.binary = \"piper\"
+Sink Input #9
+\tVolume: front-left: 0 /   0% / -inf dB
+\tProperties:
+\t\tapplication.process.binary = \"vlc\"
+";
+
+#[tokio::test]
+async fn other_streams_are_turned_down_and_restored() {
+    let dir = std::env::temp_dir().join(format!("jarvis-ducking-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("volumes");
+    let pactl = dir.join("pactl");
+    std::fs::write(
+        &pactl,
+        format!(
+            "#!/bin/sh\nif [ \"$1\" = list ]; then\ncat <<'EOF'\n{SINK_INPUTS}EOF\nelse\necho \"$2 $3\" >> {}\nfi\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&pactl, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::var("PATH").unwrap_or_default();
+    std::env::set_var("PATH", format!("{}:{path}", dir.display()));
+    std::env::set_var("DUCK_MEDIA", "1");
+    std::env::set_var("DUCK_LEVEL", "0.5");
+
+    let mut ducker = MediaDucker::from_env().unwrap();
+    ducker.duck().await;
+    ducker.duck().await;
+    let ducked = std::fs::read_to_string(&log).unwrap();
+    ducker.restore().await;
+    let restored = std::fs::read_to_string(&log).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        ducked, "7 30%\n",
+        "speech players and silent streams are left alone"
+    );
+    assert_eq!(restored, "7 30%\n7 60%\n");
+}
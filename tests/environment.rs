@@ -0,0 +1,36 @@
+//! The facts about the machine added to every prompt.
+
+use jarvis_rust::environment::context_block;
+use jarvis_rust::jarvis_io::JarvisIO;
+
+#[test]
+fn the_prompt_says_when_and_where_the_tools_run() {
+    let home = std::env::temp_dir().join(format!("jarvis-environment-{}", std::process::id()));
+    std::env::set_var("HOME", &home);
+    std::env::set_var("HOSTNAME", " workstation\n");
+
+    let block = context_block();
+    assert!(
+        block.starts_with("\nCurrent context (use it to answer questions about it directly):\n")
+    );
+    let year = chrono::Local::now().format(" %Y, ").to_string();
+    assert!(block
+        .lines()
+        .any(|line| line.starts_with("- Date and time: ") && line.contains(&year)));
+    let started_in = std::env::current_dir().unwrap();
+    assert!(block.contains(&format!(
+        "- Working directory of shell commands: {}\n",
+        started_in.display()
+    )));
+    assert!(block.contains("- Host name: workstation\n"));
+    assert!(block
+        .lines()
+        .last()
+        .unwrap()
+        .starts_with("- Operating system: "));
+
+    JarvisIO::new().write_working_directory("/srv/www");
+    let block = context_block();
+    std::fs::remove_dir_all(&home).unwrap();
+    assert!(block.contains("- Working directory of shell commands: /srv/www\n"));
+}
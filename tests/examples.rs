@@ -0,0 +1,37 @@
+//! Example dialogues added to the system prompt.
+
+use jarvis_rust::examples::Examples;
+
+#[test]
+fn examples_are_added_in_name_order_up_to_the_limit() {
+    let dir = std::env::temp_dir().join(format!("jarvis-examples-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, text) in [
+        ("2-uptime.md", "User: uptime?\nJarvis: Two days.\n"),
+        ("1-disk.md", "\nUser: disk?\nJarvis: Half free.\n\n"),
+        ("3-empty.md", "  \n"),
+        ("4-long.md", "User: tell me everything about the machine\n"),
+        ("notes.txt", "Not an example."),
+    ] {
+        std::fs::write(dir.join(name), text).unwrap();
+    }
+    std::env::set_var("EXAMPLES_MAX_CHARS", "70");
+    let examples = Examples::new(dir.clone());
+    std::env::remove_var("EXAMPLES_MAX_CHARS");
+
+    assert_eq!(
+        examples.load(),
+        [
+            "User: disk?\nJarvis: Half free.",
+            "User: uptime?\nJarvis: Two days."
+        ]
+    );
+    assert_eq!(
+        examples.prompt_section(),
+        "\nExamples of requests and how to handle them. Follow their format, not their facts:\n\
+\n<example>\nUser: disk?\nJarvis: Half free.\n</example>\n\
+\n<example>\nUser: uptime?\nJarvis: Two days.\n</example>\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(examples.prompt_section(), "");
+}
@@ -0,0 +1,77 @@
+//! Trivial commands answered without the model.
+
+use jarvis_rust::intents::IntentRouter;
+use jarvis_rust::llm::ToolCall;
+use jarvis_rust::tools::ToolRun;
+use serde_json::json;
+
+#[test]
+fn quick_commands_are_answered_and_the_rest_go_to_the_model() {
+    let path = std::env::temp_dir().join(format!("jarvis-intents-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[[intent]]
+name = "thanks"
+patterns = ['^thank(s| you)']
+reply = "You're welcome."
+
+[[intent]]
+name = "stop"
+patterns = ['^hush$']
+reply = "Hushed."
+
+[[intent]]
+name = "greeting"
+patterns = ['^hello$']
+shell = "true"
+reply = "Hello there."
+
+[[intent]]
+name = "broken"
+patterns = ['^(unclosed$']
+reply = "Never said."
+
+[[intent]]
+name = "nothing to do"
+patterns = ['^idle$']
+"#,
+    )
+    .unwrap();
+    std::env::set_var("INTENTS_FILE", &path);
+    let router = IntentRouter::load();
+    std::env::remove_var("INTENTS_FILE");
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(router
+        .handle("What time is it?", &[])
+        .unwrap()
+        .starts_with("It's "));
+    assert_eq!(
+        router.handle("Please thank you!", &[]).as_deref(),
+        Some("You're welcome.")
+    );
+    assert_eq!(router.handle("Hello", &[]).as_deref(), Some("Hello there."));
+    // The user's "stop" replaced the built-in one.
+    assert_eq!(router.handle("hush", &[]).as_deref(), Some("Hushed."));
+    assert_eq!(router.handle("stop", &[]), None);
+    assert_eq!(router.handle("(unclosed", &[]), None);
+    assert_eq!(router.handle("idle", &[]), None);
+    assert_eq!(router.handle("what is the time in Tokyo", &[]), None);
+
+    assert_eq!(
+        router.handle("what did you just run", &[]).as_deref(),
+        Some("I haven't run anything yet.")
+    );
+    let run = ToolRun {
+        call: ToolCall {
+            name: "shell_task".to_string(),
+            arguments: json!({ "command": "df -h" }),
+        },
+        result: Ok("Filesystem Size\n/dev/sda1 100G".to_string()),
+    };
+    assert_eq!(
+        router.handle("what did you just run", &[run]).as_deref(),
+        Some("I ran shell_task with df -h, which printed: Filesystem Size /dev/sda1 100G.")
+    );
+}
@@ -0,0 +1,110 @@
+//! The library's builder API, used without a microphone or speakers.
+
+use std::sync::Once;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use jarvis_rust::llm::mock::{MockLlm, MockReply};
+use jarvis_rust::tools::{Tool, ToolRegistry};
+use jarvis_rust::Jarvis;
+use serde_json::{json, Value};
+
+/// Keep the tests away from the user's `~/.jarvis`.
+fn setup() {
+    static ENV: Once = Once::new();
+    ENV.call_once(|| {
+        let home = std::env::temp_dir().join(format!("jarvis-library-{}", std::process::id()));
+        std::fs::create_dir_all(&home).unwrap();
+        std::env::set_var("HOME", &home);
+        for (name, value) in [
+            ("SESSIONS", "0"),
+            ("STATS", "0"),
+            ("MEMORY", "0"),
+            ("EXAMPLES", "0"),
+            ("PROMPT_CONTEXT", "0"),
+            ("LLM_RETRIES", "0"),
+        ] {
+            std::env::set_var(name, value);
+        }
+    });
+}
+
+/// A tool that answers with its `text` argument.
+struct Echo;
+
+impl Tool for Echo {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    fn description(&self) -> &str {
+        "Repeat the text."
+    }
+
+    fn run(&self, arguments: &Value) -> Result<String> {
+        Ok(arguments["text"].as_str().unwrap_or_default().to_string())
+    }
+}
+
+fn jarvis(replies: Vec<MockReply>) -> Jarvis {
+    setup();
+    Jarvis::builder()
+        .without_asr()
+        .without_tts()
+        .with_llm(MockLlm::new(replies))
+        .with_tools(ToolRegistry::new())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_pipeline_answers_typed_commands() {
+    let jarvis = jarvis(vec![MockReply::text("Paris, sir.")]);
+    assert_eq!(
+        jarvis.ask("what is the capital of France").await.unwrap(),
+        "Paris, sir."
+    );
+}
+
+#[tokio::test]
+async fn a_text_only_pipeline_says_nothing_and_cannot_listen() {
+    let jarvis = jarvis(vec![]);
+    jarvis.say("Hello.").await.unwrap();
+    assert!(jarvis.start().is_err(), "there is nothing to listen with");
+    assert!(jarvis.run().await.is_err());
+}
+
+#[tokio::test]
+async fn tool_calls_are_published_as_events() {
+    setup();
+    let call = json!({ "tool": "echo", "arguments": { "text": "hi" } });
+    let mut tools = ToolRegistry::new();
+    tools.register(Echo);
+    let jarvis = Jarvis::builder()
+        .without_asr()
+        .without_tts()
+        .with_llm(MockLlm::new(vec![
+            MockReply::text(&call.to_string()),
+            MockReply::text("It said hi."),
+        ]))
+        .with_tools(tools)
+        .build()
+        .unwrap();
+    let events = jarvis.events();
+    assert_eq!(jarvis.ask("repeat hi").await.unwrap(), "It said hi.");
+    let events: Vec<Value> =
+        tokio::time::timeout(Duration::from_secs(1), events.take(2).collect::<Vec<_>>())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect();
+    assert_eq!(
+        events,
+        [
+            json!({ "type": "tool_call", "tool": "echo", "arguments": { "text": "hi" } }),
+            json!({ "type": "tool_result", "tool": "echo", "output": "hi" }),
+        ]
+    );
+}
@@ -0,0 +1,198 @@
+//! The language model backends, without a model server.
+
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use jarvis_rust::llm::{self, find_model, model_switch_target, LlmBackend, Message, ToolCall};
+use serde_json::json;
+
+/// A completion-only backend that keeps the last prompt it was given.
+#[derive(Default)]
+struct Completion {
+    prompt: Mutex<String>,
+}
+
+#[async_trait]
+impl LlmBackend for Completion {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        *self.prompt.lock().unwrap() = prompt.to_string();
+        Ok("You're welcome.".to_string())
+    }
+}
+
+#[tokio::test]
+async fn chats_are_flattened_for_completion_only_backends() {
+    let call = ToolCall {
+        name: "shell_task".to_string(),
+        arguments: json!({ "command": "uptime" }),
+    };
+    let mut asked = Message::assistant("");
+    asked.tool_calls = vec![call.clone()];
+    let backend = Completion::default();
+    assert!(!backend.native_tools());
+    let reply = backend
+        .chat(
+            &[
+                Message::system("Be brief."),
+                Message::user("uptime?"),
+                asked,
+                Message::tool("up 2 days"),
+                Message::user("thanks"),
+            ],
+            &[],
+        )
+        .await
+        .unwrap();
+    assert_eq!(reply, Message::assistant("You're welcome."));
+    let call = json!({ "tool": call.name, "arguments": call.arguments });
+    assert_eq!(
+        *backend.prompt.lock().unwrap(),
+        format!("Be brief.\n\nUser: uptime?\nAssistant: {call}\nTool: up 2 days\nUser: thanks\nAssistant:")
+    );
+}
+
+#[test]
+fn the_backend_is_chosen_by_llm_backend() {
+    std::env::set_var("LLM_BACKEND", "openai");
+    assert_eq!(llm::from_env().is_ok(), cfg!(feature = "openai"));
+    std::env::set_var("LLM_BACKEND", "bard");
+    let Err(error) = llm::from_env() else {
+        panic!("there is no such backend");
+    };
+    std::env::remove_var("LLM_BACKEND");
+    assert_eq!(
+        error.to_string(),
+        "unknown LLM_BACKEND 'bard' (expected ollama or openai)"
+    );
+}
+
+/// Answer one HTTP request on a local port with `body` as JSON; the
+/// thread returns the request it got.
+#[cfg(feature = "openai")]
+fn serve_once(body: &str) -> (String, std::thread::JoinHandle<String>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            request.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut content = vec![0; length];
+        reader.read_exact(&mut content).unwrap();
+        request.push_str(&String::from_utf8(content).unwrap());
+        reader.into_inner().write_all(response.as_bytes()).unwrap();
+        request
+    });
+    (url, server)
+}
+
+#[cfg(feature = "openai")]
+#[tokio::test]
+async fn openai_tool_calls_are_numbered_and_read_back() {
+    use jarvis_rust::llm::openai::OpenAiBackend;
+    use jarvis_rust::llm::ToolSpec;
+    use serde_json::Value;
+
+    let answer = json!({
+        "choices": [{ "message": { "content": "", "tool_calls": [{
+            "id": "abc",
+            "type": "function",
+            "function": { "name": "shell_task", "arguments": "{\"command\":\"whoami\"}" },
+        }] } }],
+    });
+    let (url, server) = serve_once(&answer.to_string());
+    let backend = OpenAiBackend::new(&url, Some("secret".to_string()), "qwen3").unwrap();
+    let mut asked = Message::assistant("");
+    asked.tool_calls = vec![ToolCall {
+        name: "shell_task".to_string(),
+        arguments: json!({ "command": "uptime" }),
+    }];
+    let tools = [ToolSpec {
+        name: "shell_task".to_string(),
+        description: "Run a shell command.".to_string(),
+        parameters: json!({ "type": "object" }),
+    }];
+    let reply = backend
+        .chat(
+            &[Message::user("uptime?"), asked, Message::tool("up 2 days")],
+            &tools,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        reply.tool_calls,
+        [ToolCall {
+            name: "shell_task".to_string(),
+            arguments: json!({ "command": "whoami" }),
+        }]
+    );
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /v1/chat/completions "));
+    assert!(request
+        .to_lowercase()
+        .contains("authorization: bearer secret\r\n"));
+    let body: Value = serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    assert_eq!(body["model"], "qwen3");
+    assert_eq!(body["tools"][0]["function"]["name"], "shell_task");
+    assert_eq!(body["messages"][1]["tool_calls"][0]["id"], "call_0");
+    assert_eq!(
+        body["messages"][1]["tool_calls"][0]["function"]["arguments"],
+        "{\"command\":\"uptime\"}"
+    );
+    assert_eq!(
+        body["messages"][2],
+        json!({ "role": "tool", "tool_call_id": "call_0", "content": "up 2 days" })
+    );
+}
+
+#[test]
+fn spoken_model_names_find_the_installed_model() {
+    assert_eq!(
+        model_switch_target("Please use the big model.").as_deref(),
+        Some("big")
+    );
+    assert_eq!(
+        model_switch_target("use model qwen three one point seven b").as_deref(),
+        Some("qwen three one point seven b")
+    );
+    assert_eq!(model_switch_target("use the force"), None);
+
+    let available: Vec<String> = ["qwen3:1.7b", "qwen3:14b", "llama3:latest", "mistral:7b"]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    std::env::set_var("MODEL_ALIASES", "big=qwen3:14b");
+    assert_eq!(find_model("big", &available).unwrap(), "qwen3:14b");
+    assert_eq!(
+        find_model("qwen three one point seven b", &available).unwrap(),
+        "qwen3:1.7b"
+    );
+    assert_eq!(find_model("llama3", &available).unwrap(), "llama3:latest");
+    assert_eq!(find_model("Mistral", &available).unwrap(), "mistral:7b");
+    assert_eq!(
+        find_model("qwen", &available).unwrap_err(),
+        ["qwen3:1.7b", "qwen3:14b"]
+    );
+    assert!(find_model("gemma", &available).unwrap_err().is_empty());
+}
@@ -0,0 +1,61 @@
+//! Long-term memory and its retrieval.
+
+use jarvis_rust::memory::{self, Memory};
+
+#[test]
+fn the_closest_facts_are_recalled_and_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("jarvis-memory-{}.jsonl", std::process::id()));
+    let mut facts = Memory::open(path.clone());
+    facts
+        .add("The WiFi password is on the fridge.", vec![1.0, 0.0, 0.0])
+        .unwrap();
+    facts
+        .add("My sister's birthday is in May.", vec![0.0, 1.0, 0.0])
+        .unwrap();
+    facts.add("The car is blue.", Vec::new()).unwrap();
+
+    let mut facts = Memory::open(path.clone());
+    assert_eq!(facts.entries().len(), 3);
+    assert!(facts.has_embeddings());
+    let texts = |query: &str, embedding: Option<&[f32]>| -> Vec<String> {
+        facts
+            .relevant(query, embedding)
+            .into_iter()
+            .map(|entry| entry.text.clone())
+            .collect()
+    };
+    // Facts without an embedding are found by their words.
+    assert_eq!(
+        texts("what colour is the car", Some(&[0.9, 0.1, 0.0][..])),
+        ["The WiFi password is on the fridge.", "The car is blue."]
+    );
+    assert_eq!(
+        texts("where is the password", None),
+        ["The WiFi password is on the fridge."]
+    );
+    assert!(texts("tell me a joke", Some(&[0.0, 0.0, 1.0][..])).is_empty());
+
+    facts.clear().unwrap();
+    assert!(!path.exists());
+    assert!(Memory::open(path).entries().is_empty());
+
+    assert_eq!(
+        memory::cosine_similarity(&[3.0, 4.0], &[6.0, 8.0]),
+        Some(1.0)
+    );
+    assert_eq!(
+        memory::cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]),
+        Some(0.0)
+    );
+    assert_eq!(memory::cosine_similarity(&[1.0], &[1.0, 0.0]), None);
+    assert_eq!(
+        memory::remember_command("Please remember that the spare key is in the shed."),
+        Some("the spare key is in the shed".to_string())
+    );
+    assert_eq!(
+        memory::remember_command("remember what I said about Bob"),
+        None
+    );
+    assert!(memory::is_list_command("What do you remember?"));
+    assert!(memory::is_clear_command("Clear your memory."));
+}
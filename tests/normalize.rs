@@ -0,0 +1,32 @@
+//! Dates, times, percentages and units put into words for speech.
+
+use jarvis_rust::tts_engine::normalize::Normalizer;
+
+fn spoken(text: &str) -> String {
+    Normalizer::new().apply(text)
+}
+
+#[test]
+fn attached_units_with_several_letters_are_spoken() {
+    assert_eq!(spoken("3.5GB free"), "3.5 gigabytes free");
+    assert_eq!(spoken("took 250ms"), "took 250 milliseconds");
+    assert_eq!(spoken("1MiB"), "1 mebibyte");
+    assert_eq!(spoken("it is 21°C"), "it is 21 degrees Celsius");
+}
+
+#[test]
+fn single_letter_units_need_a_space() {
+    assert_eq!(spoken("music of the 1990s"), "music of the 1990s");
+    assert_eq!(spoken("a 4K screen"), "a 4K screen");
+    assert_eq!(spoken("no 5G here"), "no 5G here");
+    assert_eq!(spoken("after 30 s"), "after 30 seconds");
+    assert_eq!(spoken("1 h left"), "1 hour left");
+}
+
+#[test]
+fn dates_times_and_percentages_are_spoken() {
+    assert_eq!(
+        spoken("2024-06-01 13:45, 42% done"),
+        "June first 2024 13 45, 42 percent done"
+    );
+}
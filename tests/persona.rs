@@ -0,0 +1,67 @@
+//! Personas: the opening of the system prompt, switchable by voice.
+
+use jarvis_rust::llm::GenerationOptions;
+use jarvis_rust::persona::{Persona, Personas};
+
+#[test]
+fn personas_are_switched_by_voice() {
+    let dir = std::env::temp_dir().join(format!("jarvis-personas-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("personas")).unwrap();
+    std::fs::write(dir.join("persona.md"), "You are Jarvis. Be terse.\n").unwrap();
+    std::fs::write(
+        dir.join("personas").join("pirate-captain.md"),
+        "Talk like a pirate.",
+    )
+    .unwrap();
+    std::fs::write(dir.join("personas").join("notes.txt"), "Not a persona.").unwrap();
+    std::env::remove_var("SYSTEM_PROMPT");
+    let mut personas = Personas::new(Some(dir.clone()));
+    assert_eq!(personas.current().prompt, "You are Jarvis. Be terse.");
+    assert_eq!(personas.names(), ["pirate-captain"]);
+
+    assert_eq!(
+        personas
+            .handle_command("Switch to pirate captain mode.")
+            .as_deref(),
+        Some("Switching to pirate captain mode.")
+    );
+    assert_eq!(personas.active(), "pirate-captain");
+    assert_eq!(personas.current().prompt, "Talk like a pirate.");
+    assert_eq!(
+        personas
+            .handle_command("switch to sea shanty mode")
+            .as_deref(),
+        Some("I don't have a persona called sea shanty. I know pirate-captain.")
+    );
+    assert_eq!(personas.active(), "pirate-captain");
+    assert_eq!(
+        personas.handle_command("go back to normal mode").as_deref(),
+        Some("Back to normal.")
+    );
+    assert_eq!(personas.active(), "default");
+    assert_eq!(personas.handle_command("what mode is the fan in"), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn front_matter_overrides_the_generation_settings() {
+    let pirate =
+        Persona::parse("+++\ntemperature = 1.1\nmax_tokens = 120\n+++\nTalk like a pirate.\n");
+    assert_eq!(pirate.prompt, "Talk like a pirate.");
+    std::env::set_var("LLM_TEMPERATURE", "0.2");
+    std::env::set_var("LLM_TOP_P", " 0.9 ");
+    std::env::set_var("LLM_MAX_TOKENS", "many");
+    let configured = GenerationOptions::from_env();
+    assert_eq!(
+        configured.with_overrides(&pirate.options),
+        GenerationOptions {
+            temperature: Some(1.1),
+            top_p: Some(0.9),
+            num_ctx: None,
+            max_tokens: Some(120),
+        }
+    );
+    let broken = Persona::parse("+++\ntemperature = \"hot\"\n+++\nBe calm.");
+    assert_eq!(broken.prompt, "Be calm.");
+    assert_eq!(broken.options, GenerationOptions::default());
+}
@@ -0,0 +1,178 @@
+//! The listen/respond loop, driven by a scripted recogniser.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use jarvis_rust::llm::mock::{MockLlm, MockReply};
+use jarvis_rust::speech::{Listener, Transcript};
+use jarvis_rust::tools::ToolRegistry;
+use jarvis_rust::tts_engine::Speaker;
+use jarvis_rust::{Jarvis, JarvisEvent};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+/// Hears the phrases it was given, one per listen window, then silence.
+#[derive(Clone)]
+struct Script {
+    phrases: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl Script {
+    fn new(phrases: &[&str]) -> Self {
+        Self {
+            phrases: Arc::new(Mutex::new(
+                phrases.iter().map(|phrase| phrase.to_string()).collect(),
+            )),
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.phrases.lock().unwrap().is_empty()
+    }
+}
+
+impl Listener for Script {
+    fn listen(&self, _duration: Duration) -> Result<Transcript> {
+        let text = self.phrases.lock().unwrap().pop_front();
+        if text.is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Ok(Transcript {
+            text: text.unwrap_or_default(),
+            confidence: Some(1.0),
+        })
+    }
+}
+
+/// The home directory is set for the whole process, so the tests take
+/// turns.
+static HOME: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Make a fresh `name` folder the home directory and keep the loop from
+/// warming up the model or listening for "stop".
+fn use_home(name: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("jarvis-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("HOME", &home);
+    for (name, value) in [
+        ("SESSIONS", "0"),
+        ("STATS", "0"),
+        ("MEMORY", "0"),
+        ("EXAMPLES", "0"),
+        ("PROMPT_CONTEXT", "0"),
+        ("STARTUP_CHECK", "0"),
+        ("CANCEL_BY_VOICE", "0"),
+        ("TTS_CACHE", "0"),
+    ] {
+        std::env::set_var(name, value);
+    }
+    home
+}
+
+/// Records what it is asked to say.
+#[derive(Clone, Default)]
+struct Spoken {
+    said: Arc<Mutex<Vec<String>>>,
+}
+
+impl Spoken {
+    fn said(&self) -> Vec<String> {
+        self.said.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Speaker for Spoken {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        self.said.lock().unwrap().push(text.to_string());
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Run the loop over `phrases` with `home` as the home directory and
+/// return the outcomes in the wake log.
+async fn wake_outcomes(home: &str, phrases: &[&str], replies: Vec<MockReply>) -> Vec<bool> {
+    let home = use_home(home);
+    let script = Script::new(phrases);
+    let jarvis = Jarvis::builder()
+        .with_asr(script.clone())
+        .with_llm(MockLlm::new(replies))
+        .with_tools(ToolRegistry::new())
+        .with_trigger_word("jarvis")
+        .without_tts()
+        .build()
+        .unwrap();
+    jarvis.start().unwrap();
+    for _ in 0..200 {
+        if script.done() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    jarvis.stop().await;
+    let log = std::fs::read_to_string(home.join(".jarvis").join("wake_log.jsonl")).unwrap();
+    std::fs::remove_dir_all(&home).ok();
+    log.lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|record| record["kind"] == "outcome")
+        .map(|record| record["command"].as_bool().unwrap())
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shadow_records_whether_a_command_followed_the_wake_word() {
+    let _turn = HOME.lock().await;
+    assert_eq!(
+        wake_outcomes("shadow-alone", &["jarvis", "shadow"], vec![]).await,
+        [false]
+    );
+    assert_eq!(
+        wake_outcomes(
+            "shadow-after-command",
+            &["jarvis", "what is the weather", "shadow"],
+            vec![MockReply::text("It is sunny.")],
+        )
+        .await,
+        [true]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn silence_is_asked_about_before_going_idle() {
+    let _turn = HOME.lock().await;
+    let home = use_home("timeout");
+    std::env::set_var("TIMEOUT_GRACE_SECS", "0");
+    let spoken = Spoken::default();
+    let jarvis = Jarvis::builder()
+        .with_asr(Script::new(&["jarvis"]))
+        .with_llm(MockLlm::new(vec![]))
+        .with_tools(ToolRegistry::new())
+        .with_tts(spoken.clone())
+        .with_trigger_word("jarvis")
+        .with_conversation_timeout(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let mut events = Box::pin(jarvis.events());
+    jarvis.start().unwrap();
+    let idle = tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(event) = events.next().await {
+            if matches!(event, JarvisEvent::Idle) {
+                break;
+            }
+        }
+    })
+    .await;
+    jarvis.stop().await;
+    std::fs::remove_dir_all(&home).ok();
+    assert!(idle.is_ok(), "the conversation timed out");
+    assert_eq!(spoken.said(), ["Yes sir?", "Still there?"]);
+}
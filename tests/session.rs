@@ -0,0 +1,114 @@
+//! Recording, exporting and finding earlier turns in the session
+//! transcripts.
+
+use chrono::{DateTime, Local, TimeZone};
+use jarvis_rust::llm::ToolCall;
+use jarvis_rust::session::{self, HistoryQuery, Period, SessionLog, StepRecord, TurnRecord};
+use serde_json::json;
+
+fn at(day: u32, hour: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2025, 6, day, hour, 0, 0).unwrap()
+}
+
+fn turn(time: DateTime<Local>, user: &str, reply: &str) -> TurnRecord {
+    TurnRecord {
+        time: time.to_rfc3339(),
+        user: user.to_string(),
+        reply: reply.to_string(),
+        ..TurnRecord::default()
+    }
+}
+
+#[test]
+fn history_questions_are_recognised() {
+    let query = HistoryQuery::parse("What did I ask you this morning?").unwrap();
+    assert_eq!(query.period, Some(Period::Morning));
+    assert!(query.keywords.is_empty());
+
+    let query = HistoryQuery::parse("what did we talk about docker yesterday").unwrap();
+    assert_eq!(query.period, Some(Period::Yesterday));
+    assert_eq!(query.keywords, ["docker"]);
+    assert_eq!(query.describe(), "about docker yesterday");
+
+    assert_eq!(HistoryQuery::parse("what did I ask you?"), None);
+    assert_eq!(HistoryQuery::parse("what is the time"), None);
+}
+
+#[test]
+fn turns_match_by_time_and_keyword() {
+    let now = at(11, 15);
+    let morning = HistoryQuery::parse("what did I ask you this morning").unwrap();
+    assert!(morning.matches(&turn(at(11, 9), "disk space?", "Half free."), now));
+    assert!(!morning.matches(&turn(at(11, 13), "disk space?", "Half free."), now));
+    assert!(!morning.matches(&turn(at(10, 9), "disk space?", "Half free."), now));
+    assert!(!morning.matches(&turn(at(11, 9), "what did I say today", "Nothing."), now));
+
+    let docker = HistoryQuery::parse("what did I ask about docker").unwrap();
+    assert!(docker.matches(&turn(at(1, 9), "list containers", "Docker runs two."), now));
+    assert!(!docker.matches(&turn(at(11, 9), "disk space?", "Half free."), now));
+}
+
+#[test]
+fn sessions_are_recorded_and_exported_as_markdown() {
+    let dir = std::env::temp_dir().join(format!("jarvis-sessions-{}", std::process::id()));
+    let mut log = SessionLog::new(dir.clone());
+    assert_eq!(log.current(), None);
+    let call = ToolCall {
+        name: "shell_task".to_string(),
+        arguments: json!({ "command": "df -h" }),
+    };
+    log.record(&TurnRecord {
+        steps: vec![
+            StepRecord {
+                raw: "df -h, please.".to_string(),
+                tool_calls: vec![call],
+                tool_results: vec!["Filesystem\n/dev/sda1\n".to_string()],
+                model_ms: 800,
+                tools_ms: 300,
+                ..StepRecord::default()
+            },
+            StepRecord {
+                raw: "Half is free.".to_string(),
+                model_ms: 100,
+                ..StepRecord::default()
+            },
+        ],
+        total_ms: 1200,
+        ..turn(at(11, 9), "free space?", "Half is free.")
+    });
+    let id = log.current().unwrap().to_string();
+    log.record(&TurnRecord {
+        error: Some("timed out".to_string()),
+        total_ms: 5,
+        ..turn(at(11, 10), "hello", "Sorry.")
+    });
+    assert_eq!(log.current(), Some(id.as_str()));
+
+    std::env::set_var("SESSIONS_DIR", &dir);
+    let listed = session::list();
+    let loaded = session::load("last");
+    std::env::remove_var("SESSIONS_DIR");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(listed, [id.clone()]);
+    let (loaded_id, turns) = loaded.unwrap();
+    assert_eq!(loaded_id, id);
+    assert_eq!(
+        session::to_markdown(&id, &turns),
+        format!(
+            "# Session {id}\n\
+\n## 1. free space?\n\n*{} · 1200 ms*\n\
+\n**Step 1** (model 800 ms, tools 300 ms)\n\
+\n```text\ndf -h, please.\n```\n\
+\n- Tool `shell_task` `{{\"command\":\"df -h\"}}`\n\
+\n  ```text\n  Filesystem\n  /dev/sda1\n  ```\n\
+\n**Step 2** (model 100 ms, tools 0 ms)\n\
+\n```text\nHalf is free.\n```\n\
+\n**Jarvis:** Half is free.\n\
+\n## 2. hello\n\n*{} · 5 ms*\n\
+\n**Error:** timed out\n\
+\n**Jarvis:** Sorry.\n",
+            at(11, 9).to_rfc3339(),
+            at(11, 10).to_rfc3339()
+        )
+    );
+}
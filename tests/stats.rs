@@ -0,0 +1,29 @@
+//! Tool and model usage counts.
+
+use jarvis_rust::stats::Stats;
+
+#[test]
+fn processes_sharing_the_stats_file_add_up_their_calls() {
+    let path = std::env::temp_dir().join(format!("jarvis-stats-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut voice = Stats::load(path.clone());
+    let mut text = Stats::load(path.clone());
+
+    voice.record_tool("shell_task", 30, true);
+    voice.save();
+    text.record_tool("shell_task", 50, false);
+    text.record_model("llama3.2", 600, true);
+    text.save();
+    voice.record_tool("shell_task", 40, true);
+    voice.save();
+
+    let stats = Stats::load(path);
+    assert_eq!(stats.tools["shell_task"].calls, 3);
+    assert_eq!(stats.tools["shell_task"].failures, 1);
+    assert_eq!(stats.models["llama3.2"].calls, 1);
+    assert_eq!(voice.tools["shell_task"].latencies_ms, vec![30, 50, 40]);
+    let summary = stats.summary();
+    assert_eq!(summary["tools"]["shell_task"]["calls"], 3);
+    assert_eq!(summary["tools"]["shell_task"]["p50_ms"], 40);
+    assert_eq!(summary["models"]["llama3.2"]["failures"], 0);
+}
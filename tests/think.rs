@@ -0,0 +1,48 @@
+//! The `<think>` blocks of reasoning models.
+
+use jarvis_rust::think::{self, ThinkMode};
+
+#[test]
+fn reasoning_is_split_off_and_kept_as_the_mode_asks() {
+    assert_eq!(
+        think::split("<think>a</think>Hi <think>b</think>there"),
+        (
+            "Hi there".to_string(),
+            vec!["a".to_string(), "b".to_string()]
+        )
+    );
+    assert_eq!(
+        think::split("plan first</think>Answer"),
+        ("Answer".to_string(), vec!["plan first".to_string()])
+    );
+    assert_eq!(
+        think::split("<think>cut off"),
+        (String::new(), vec!["cut off".to_string()])
+    );
+    assert!(think::is_thinking("<think>hmm"));
+    assert!(!think::is_thinking("<think>hmm</think>Yes"));
+
+    assert_eq!(" Log ".parse::<ThinkMode>().unwrap(), ThinkMode::Log);
+    assert!("mumble".parse::<ThinkMode>().is_err());
+    assert_eq!(ThinkMode::Strip.status(), None);
+    std::env::set_var("THINK_STATUS", "Hmm.");
+    assert_eq!(ThinkMode::Speak.status().as_deref(), Some("Hmm."));
+    std::env::remove_var("THINK_STATUS");
+
+    let home = std::env::temp_dir().join(format!("jarvis-think-{}", std::process::id()));
+    std::env::set_var("HOME", &home);
+    let file = home.join(".jarvis").join("jarvis.think");
+    ThinkMode::Strip.record(&["first".to_string()]);
+    ThinkMode::Strip.record(&["second".to_string(), "third".to_string()]);
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "second\n\nthird");
+    std::fs::remove_file(&file).unwrap();
+    ThinkMode::Log.record(&["first".to_string()]);
+    ThinkMode::Log.record(&[]);
+    ThinkMode::Log.record(&["second".to_string()]);
+    let log = std::fs::read_to_string(&file).unwrap();
+    std::fs::remove_dir_all(&home).unwrap();
+    let entries: Vec<&str> = log.split_terminator("\n\n").collect();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].starts_with('[') && entries[0].ends_with("]\nfirst"));
+    assert!(entries[1].starts_with('[') && entries[1].ends_with("]\nsecond"));
+}
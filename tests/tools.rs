@@ -0,0 +1,1465 @@
+//! The tool registry, tools declared in a manifest file, plugins, limits on tool output,
+//! progress of running tools, the confirmation guard, the shell policy,
+//! the sandbox, the shell, the persistent shell session, the file, notes,
+//! list, HTTP, weather, Wikipedia, news, email, MQTT, calculator, git,
+//! container, SSH and Kubernetes tools, Codex sessions, the system
+//! controls and status, audio devices, notifications, music, the screen
+//! reader, the app launcher, the stopwatch and the Pomodoro timer.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use jarvis_rust::llm::ToolCall;
+use jarvis_rust::tools::apps::{self, DesktopEntry};
+use jarvis_rust::tools::audio::{self, AudioDevice, BluetoothDevice};
+use jarvis_rust::tools::calculator::{self, Rates};
+use jarvis_rust::tools::codex_sessions::{self, CodexSession, CodexSessions};
+use jarvis_rust::tools::containers::{self, ContainersTool};
+use jarvis_rust::tools::files::{self, FileTool};
+use jarvis_rust::tools::git::{self, GitTool};
+use jarvis_rust::tools::guard::Guard;
+use jarvis_rust::tools::kubernetes::{self, KubectlTool};
+use jarvis_rust::tools::lists::{self, ListsTool};
+use jarvis_rust::tools::manifest;
+use jarvis_rust::tools::notes::{self, NotesTool};
+use jarvis_rust::tools::notify::{self, AppFilter, Notification};
+use jarvis_rust::tools::policy::{self, Policy, Verdict};
+use jarvis_rust::tools::progress::Narrator;
+use jarvis_rust::tools::remote::{self, RemoteTool};
+use jarvis_rust::tools::sandbox::{Kind, Sandbox};
+use jarvis_rust::tools::screen;
+use jarvis_rust::tools::shell::Shell;
+use jarvis_rust::tools::shell_session::{self, SessionShellTool};
+use jarvis_rust::tools::status::{self, Battery, DiskSpace, Snapshot};
+use jarvis_rust::tools::system;
+use jarvis_rust::tools::timers::{self, PhaseKind, PomodoroSettings, PomodoroTool, Stopwatch};
+use jarvis_rust::tools::{codex_argv, is_transient_error, truncate_bytes, Tool, ToolRegistry};
+use serde_json::json;
+
+/// Files written by [`temp_toml`] so far, so tests running at the same
+/// time get files of their own.
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh path for a TOML file in the temporary folder.
+fn temp_toml(name: &str) -> PathBuf {
+    let n = TEMP_FILES.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("jarvis-{name}-{}-{n}.toml", std::process::id()))
+}
+
+fn manifest(text: &str) -> Vec<manifest::CommandTool> {
+    let path = temp_toml("tools");
+    std::fs::write(&path, text).unwrap();
+    let tools = manifest::load_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    tools
+}
+
+/// A tool that only has a name and a description.
+struct Named(&'static str, &'static str);
+
+impl Tool for Named {
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn description(&self) -> &str {
+        self.1
+    }
+
+    fn run(&self, _: &serde_json::Value) -> anyhow::Result<String> {
+        Ok(self.1.to_string())
+    }
+}
+
+#[test]
+fn the_registry_offers_describes_and_finds_its_tools() {
+    let mut tools = ToolRegistry::new();
+    assert!(tools.is_empty());
+    assert_eq!(tools.prompt_section(), "");
+    tools
+        .register(Named("shell_task", "Run a shell command."))
+        .register(Named("clock", "Tell the time."))
+        .register(Named("shell_task", "Run a command in the shell."));
+    assert_eq!(tools.names(), ["clock", "shell_task"]);
+    let specs = tools.specs();
+    assert_eq!(specs[1].name, "shell_task");
+    assert_eq!(specs[1].description, "Run a command in the shell.");
+    assert_eq!(specs[1].parameters["required"], json!(["command"]));
+    let section = tools.prompt_section();
+    assert!(section.contains("- clock: Tell the time. Arguments: {"));
+    assert!(section.contains("- shell_task: Run a command in the shell. Arguments: {"));
+    assert_eq!(
+        tools.get("clock").unwrap().run(&json!({})).unwrap(),
+        "Tell the time."
+    );
+    assert!(tools.get("weather").is_none());
+    assert_eq!(tools.only(|name| name != "clock").names(), ["shell_task"]);
+}
+
+#[test]
+fn manifest_tools_get_a_schema_and_quoted_arguments() {
+    let tools = manifest(
+        r#"
+[[tool]]
+name = "greet"
+description = "Say hello."
+command = "echo hello {who} {times}"
+confirm = true
+
+[tool.args.who]
+description = "Who to greet."
+
+[tool.args.times]
+type = "integer"
+default = 1
+
+[[tool]]
+name = "broken"
+description = "Uses an argument it does not declare."
+command = "echo {nobody}"
+"#,
+    );
+    assert_eq!(tools.len(), 1);
+    let greet = &tools[0];
+    assert_eq!(greet.name(), "greet");
+    assert!(greet.needs_confirmation());
+    assert_eq!(greet.parameters()["required"], json!(["who"]));
+    assert_eq!(
+        greet.command_line(&json!({ "who": "it's {times}" })),
+        r#"echo hello 'it'\''s {times}' '1'"#
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn manifest_tools_run_in_the_shell() {
+    let tools = manifest(
+        r#"
+[[tool]]
+name = "shout"
+description = "Print a word in capitals."
+command = "echo {word} | tr a-z A-Z"
+
+[tool.args.word]
+description = "The word."
+"#,
+    );
+    let output = tools[0].run(&json!({ "word": "hi; rm -rf x" })).unwrap();
+    assert_eq!(output, "HI; RM -RF X");
+}
+
+#[cfg(unix)]
+#[test]
+fn commands_that_run_too_long_are_stopped() {
+    let tools = manifest(
+        r#"
+[[tool]]
+name = "nap"
+description = "Sleep for a while."
+command = "sleep 5"
+timeout_secs = 1
+"#,
+    );
+    let started = std::time::Instant::now();
+    let output = tools[0].run(&json!({})).unwrap();
+    assert_eq!(output, "nap timed out after 1 seconds.");
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+}
+
+#[test]
+fn codex_gets_the_instruction_as_one_argument() {
+    assert_eq!(
+        codex_argv(
+            "codex",
+            "exec --full-auto",
+            "write a \"hello\" script; rm -rf ~"
+        ),
+        [
+            "codex",
+            "exec",
+            "--full-auto",
+            "write a \"hello\" script; rm -rf ~"
+        ]
+    );
+    assert_eq!(
+        codex_argv("codex", "", "--help me"),
+        ["codex", "--", "--help me"]
+    );
+    assert!(is_transient_error("ERROR: 429 Too Many Requests"));
+    assert!(is_transient_error("stream disconnected before completion"));
+    assert!(!is_transient_error(
+        "error: unexpected argument '--full-auto'"
+    ));
+}
+
+#[test]
+fn codex_sessions_are_resumed_per_project() {
+    assert_eq!(
+        codex_sessions::session_id("session id: 0199A213-81C0-7800-8AA1-BBAB2A035A53"),
+        Some("0199a213-81c0-7800-8aa1-bbab2a035a53".to_string())
+    );
+    assert_eq!(
+        codex_sessions::session_id(
+            r#"{"type":"thread.started","thread_id":"0199a213-81c0-7800-8aa1-bbab2a035a53"}"#
+        ),
+        Some("0199a213-81c0-7800-8aa1-bbab2a035a53".to_string())
+    );
+    assert_eq!(codex_sessions::session_id("model: gpt-5-codex"), None);
+    assert_eq!(
+        codex_sessions::resume_argv("codex", "exec --full-auto", "abc", "apply that change"),
+        [
+            "codex",
+            "exec",
+            "--full-auto",
+            "resume",
+            "abc",
+            "apply that change"
+        ]
+    );
+
+    let path =
+        std::env::temp_dir().join(format!("jarvis-codex-sessions-{}.json", std::process::id()));
+    let sessions = CodexSessions::open(path.clone());
+    let project = std::path::Path::new("/home/you/blog");
+    assert_eq!(sessions.get(project), None);
+    let session = CodexSession {
+        id: "abc".to_string(),
+        instruction: "add an RSS feed".to_string(),
+        time: "2026-10-16T10:00:00+02:00".to_string(),
+        branch: Some("main".to_string()),
+    };
+    sessions.save(project, session.clone()).unwrap();
+    assert_eq!(sessions.get(project), Some(session.clone()));
+    assert_eq!(sessions.get(std::path::Path::new("/home/you/shop")), None);
+    assert_eq!(codex_sessions::resume_note(&session, Some("main")), None);
+    assert_eq!(
+        codex_sessions::resume_note(&session, Some("feed")).as_deref(),
+        Some("The last Codex session here was on main; you're on feed now.")
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn long_output_is_truncated_on_a_character_boundary() {
+    assert_eq!(truncate_bytes("short", 10), "short");
+    assert_eq!(
+        truncate_bytes("naïve text", 3),
+        "na\n... (output truncated)"
+    );
+    assert_eq!(
+        truncate_bytes("na\n... (output truncated)", 3),
+        "na\n... (output truncated)",
+        "output is only cut once"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn output_lines_are_passed_on_while_the_command_runs() {
+    let tools = manifest(
+        r#"
+[[tool]]
+name = "count"
+description = "Count to three."
+command = "for n in 1 2 3; do echo step $n; done"
+"#,
+    );
+    let mut lines = Vec::new();
+    let output = tools[0]
+        .run_streaming(&json!({}), &mut |line| lines.push(line.to_string()))
+        .unwrap();
+    assert_eq!(lines, ["step 1", "step 2", "step 3"]);
+    assert_eq!(output, "step 1\nstep 2\nstep 3");
+}
+
+#[test]
+fn progress_is_only_spoken_when_asked_for() {
+    assert_eq!(Narrator::new(None).line("copying photos"), None);
+    let mut narrator = Narrator::new(Some(std::time::Duration::ZERO));
+    assert_eq!(
+        narrator.line("  copying \x1b[1mphotos\x1b[0m"),
+        Some("Still working: copying photos".to_string())
+    );
+    assert_eq!(narrator.line("   "), None);
+}
+
+fn policy(text: &str) -> Policy {
+    let path = temp_toml("policy");
+    std::fs::write(&path, text).unwrap();
+    let policy = Policy::load_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    policy
+}
+
+#[test]
+fn every_program_of_a_command_line_is_found() {
+    assert_eq!(
+        policy::programs("FOO=1 sudo /usr/bin/rm -rf x 2>&1 | tee log && echo $(date)"),
+        ["sudo", "rm", "tee", "echo", "date"]
+    );
+}
+
+#[test]
+fn programs_are_found_after_keywords_braces_and_shells() {
+    assert_eq!(policy::programs("{ curl x; }"), ["curl"]);
+    assert_eq!(
+        policy::programs("if curl x; then nc y; else :; fi"),
+        ["curl", "nc", ":"]
+    );
+    assert_eq!(policy::programs("! wget -q z"), ["wget"]);
+    assert_eq!(
+        policy::programs("while true; do time nc y; done"),
+        ["true", "time", "nc"]
+    );
+    assert_eq!(policy::programs("bash -c 'curl x'"), ["bash", "curl"]);
+    assert_eq!(policy::programs("sh -lc \"wget z\""), ["sh", "wget"]);
+}
+
+#[test]
+fn writes_are_confirmed_wherever_dots_lead() {
+    let guard = Guard::from_env();
+    let home = std::env::var("HOME").unwrap();
+    let check = |command: &str| {
+        guard.check(&ToolCall {
+            name: "shell_task".to_string(),
+            arguments: json!({ "command": command }),
+        })
+    };
+    assert_eq!(check(&format!("touch {home}/notes.txt")), None);
+    assert_eq!(check(&format!("mkdir -p {home}/jarvis-new/deeper")), None);
+    assert_eq!(check("echo hi > /tmp/hello"), None);
+    assert!(check(&format!("touch {home}/../../etc/evil")).is_some());
+    assert!(check("cp x /tmp/../etc/passwd").is_some());
+}
+
+#[test]
+fn the_policy_allows_confirms_or_refuses_commands() {
+    let rules = r#"
+allow = ["ls", "cat"]
+deny = ["curl"]
+deny_patterns = ['>\s*/etc/']
+paths = ["/tmp"]
+"#;
+    let confirm = policy(&format!("mode = \"confirm\"\n{rules}"));
+    assert_eq!(confirm.check("ls -la /tmp/x | cat"), Verdict::Allow);
+    assert!(matches!(confirm.check("df -h"), Verdict::Confirm(_)));
+    assert!(matches!(
+        confirm.check("cat /var/log/syslog"),
+        Verdict::Confirm(_)
+    ));
+    assert!(matches!(
+        confirm.check("cat /tmp/../etc/passwd"),
+        Verdict::Confirm(_)
+    ));
+    assert!(matches!(confirm.check("ls; curl x"), Verdict::Deny(_)));
+    assert!(matches!(
+        confirm.check("cat x > /etc/hosts"),
+        Verdict::Deny(_)
+    ));
+
+    let strict = policy(&format!("mode = \"strict\"\n{rules}"));
+    assert_eq!(
+        strict.check("df -h"),
+        Verdict::Deny("df is not on the allow list".to_string())
+    );
+
+    let open = policy(&format!("mode = \"open\"\n{rules}"));
+    assert_eq!(open.check("df -h"), Verdict::Allow);
+    assert!(matches!(open.check("curl x"), Verdict::Deny(_)));
+
+    // A broken policy asks rather than letting everything through.
+    assert!(matches!(
+        policy("mode = \"paranoid\"").check("ls"),
+        Verdict::Confirm(_)
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn sandboxed_commands_may_only_write_to_the_workspace() {
+    let sandbox = Sandbox::new(
+        Kind::Bubblewrap,
+        vec!["/tmp".into(), "/no/such/dir".into()],
+        false,
+    );
+    let shell = Shell::new("zsh", true, false, Vec::new());
+    let argv = sandbox.argv(
+        &shell.argv("touch x"),
+        shell.env(),
+        Some(std::path::Path::new("/tmp")),
+    );
+    assert_eq!(
+        argv,
+        [
+            "bwrap",
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--die-with-parent",
+            "--unshare-net",
+            "--bind",
+            "/tmp",
+            "/tmp",
+            "--chdir",
+            "/tmp",
+            "zsh",
+            "-l",
+            "-c",
+            "touch x",
+        ]
+    );
+    assert!("nsjail".parse::<Kind>().is_err());
+}
+
+#[test]
+fn the_shell_can_read_its_profiles() {
+    let plain = Shell::new("sh", false, false, Vec::new());
+    assert_eq!(plain.argv("echo hi"), ["sh", "-c", "echo hi"]);
+    let fish = Shell::new("/usr/bin/fish", true, true, Vec::new());
+    assert_eq!(fish.argv("ll"), ["/usr/bin/fish", "-l", "-i", "-c", "ll"]);
+    let cmd = Shell::new("cmd.exe", true, true, Vec::new());
+    assert_eq!(cmd.argv("dir"), ["cmd.exe", "/C", "dir"]);
+}
+
+#[test]
+fn reset_shell_is_recognised() {
+    assert!(shell_session::is_reset_command("Reset the shell."));
+    assert!(shell_session::is_reset_command("restart your shell"));
+    assert!(!shell_session::is_reset_command("reset the router"));
+}
+
+#[cfg(unix)]
+#[test]
+fn the_session_keeps_variables_and_directory_until_reset() {
+    let tool = SessionShellTool::new();
+    let run = |command: &str| tool.run(&json!({ "command": command })).unwrap();
+    assert!(!tool.reset());
+    run("export JARVIS_TEST_VALUE=kept; cd /tmp");
+    assert_eq!(run("echo $JARVIS_TEST_VALUE; pwd"), "kept\n/tmp");
+    assert_eq!(
+        run("false"),
+        "Command exited with 1 and produced no output."
+    );
+    assert!(tool.reset());
+    assert_eq!(
+        run("echo \"[$JARVIS_TEST_VALUE]\""),
+        "[]",
+        "a fresh shell forgets the variable"
+    );
+}
+
+#[test]
+fn the_file_tool_stays_inside_its_folders() {
+    let root = std::env::temp_dir().join(format!("jarvis-files-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let tool = FileTool::new(vec![root.clone()]);
+    let todo = root.join("todo.md").display().to_string();
+    let run = |arguments: serde_json::Value| tool.run(&arguments).unwrap();
+    assert_eq!(
+        run(json!({ "action": "create", "path": todo, "text": "# Today\n- [ ] milk" })),
+        "Created todo.md."
+    );
+    assert_eq!(
+        run(json!({ "action": "append", "path": todo, "text": "- [ ] bread" })),
+        "Added to todo.md."
+    );
+    assert_eq!(
+        run(json!({ "action": "read", "path": todo })),
+        "# Today\n- [ ] milk\n- [ ] bread\n"
+    );
+    assert_eq!(
+        run(json!({ "action": "search", "path": root.display().to_string(), "query": "BREAD" })),
+        "todo.md:3: - [ ] bread"
+    );
+    assert!(run(json!({ "action": "create", "path": todo, "text": "" })).contains("already exists"));
+    let outside = format!("{}/../escape.txt", root.display());
+    assert!(run(json!({ "action": "create", "path": outside, "text": "x" })).contains("outside"));
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(
+        files::summary("todo.md", "# Today\n- [ ] milk\n- [ ] bread\nCall mum."),
+        "todo.md has 4 lines and 12 words. Headings: Today. 2 open to-dos: milk, bread. \
+It begins: - [ ] milk - [ ] bread Call mum."
+    );
+}
+
+#[test]
+fn file_searches_do_not_follow_links_out_of_the_folders() {
+    let base = std::env::temp_dir().join(format!("jarvis-links-{}", std::process::id()));
+    let (root, outside) = (base.join("root"), base.join("outside"));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    std::fs::write(root.join("todo.md"), "bread\n").unwrap();
+    std::fs::write(outside.join("secret.md"), "bread and passwords\n").unwrap();
+    std::os::unix::fs::symlink(&outside, root.join("elsewhere")).unwrap();
+    std::os::unix::fs::symlink(outside.join("secret.md"), root.join("secret.md")).unwrap();
+    std::os::unix::fs::symlink(&root, root.join("up")).unwrap();
+    let tool = FileTool::new(vec![root.clone()]);
+    let found = tool
+        .run(&json!({ "action": "search", "path": root.display().to_string(), "query": "bread" }))
+        .unwrap();
+    std::fs::remove_dir_all(&base).unwrap();
+    assert_eq!(found, "todo.md:1: bread");
+}
+
+#[test]
+fn notes_are_kept_by_topic_and_found_by_their_words() {
+    let dir = std::env::temp_dir().join(format!("jarvis-notes-{}", std::process::id()));
+    let tool = NotesTool::new(dir.clone());
+    tool.add("Garden", "Planted the tomatoes.", "2026-05-01 09:00")
+        .unwrap();
+    tool.add("garden", "Tomatoes need staking.", "2026-05-20 18:30")
+        .unwrap();
+    tool.add(
+        "Boiler",
+        "Serviced, next check in March.",
+        "2026-09-02 11:15",
+    )
+    .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("garden.md")).unwrap(),
+        "# Garden\n\n## 2026-05-01 09:00\n\nPlanted the tomatoes.\n\n\
+## 2026-05-20 18:30\n\nTomatoes need staking.\n"
+    );
+    assert_eq!(
+        tool.read("the garden"),
+        "garden, 2026-05-20 18:30: Tomatoes need staking.\n\
+garden, 2026-05-01 09:00: Planted the tomatoes."
+    );
+    assert_eq!(
+        tool.read("Garden"),
+        "2026-05-01 09:00: Planted the tomatoes.\n2026-05-20 18:30: Tomatoes need staking."
+    );
+    assert_eq!(
+        tool.search("when was the boiler serviced"),
+        "boiler, 2026-09-02 11:15: Serviced, next check in March."
+    );
+    assert!(tool.read("holidays").starts_with("There's nothing"));
+    assert_eq!(tool.topics(), ["boiler", "garden"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(notes::file_name("Garden Plans!"), "garden-plans");
+    assert!(notes::entries("# Empty\n\n## 2026-01-01 00:00\n\n").is_empty());
+}
+
+#[test]
+fn list_items_are_added_removed_and_read_out() {
+    let dir = std::env::temp_dir().join(format!("jarvis-lists-{}", std::process::id()));
+    let tool = ListsTool::new(dir.clone());
+    let items =
+        |items: &[&str]| -> Vec<String> { items.iter().map(|item| item.to_string()).collect() };
+    assert_eq!(
+        tool.add("the shopping list", &items(&["milk", "a dozen eggs"]))
+            .unwrap(),
+        "Added milk and a dozen eggs to the shopping list."
+    );
+    assert_eq!(
+        tool.add("Shopping", &items(&["bread", "Milk"])).unwrap(),
+        "Added bread to the shopping list; Milk is already on it."
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("shopping.md")).unwrap(),
+        "# shopping\n\n- milk\n- a dozen eggs\n- bread\n"
+    );
+    assert_eq!(
+        tool.remove("shopping", &items(&["the eggs", "butter"]))
+            .unwrap(),
+        "Removed a dozen eggs from the shopping list; butter isn't on it."
+    );
+    assert_eq!(
+        tool.read("my shopping list"),
+        "The shopping list has 2 items: milk and bread."
+    );
+    tool.add("to-do", &items(&["call the plumber"])).unwrap();
+    assert_eq!(
+        tool.read("todo"),
+        "The todo list has one item: call the plumber."
+    );
+    assert_eq!(tool.names(), ["shopping", "to do"]);
+    assert_eq!(
+        tool.confirmation(&json!({"action": "clear", "list": "shopping"})),
+        Some("clear the shopping list".to_string())
+    );
+    assert_eq!(
+        tool.clear("shopping").unwrap(),
+        "Cleared the shopping list."
+    );
+    assert_eq!(
+        tool.read("shopping"),
+        "There's nothing on the shopping list."
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(lists::file_name("My Grocery List"), "grocery");
+    assert_eq!(
+        lists::items("# Todo\n\n* one\n- two\nnotes\n"),
+        ["one", "two"]
+    );
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn http_requests_only_go_to_allowed_hosts() {
+    use jarvis_rust::tools::http::HttpTool;
+    use reqwest::Url;
+
+    let path = std::env::temp_dir().join(format!("jarvis-http-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[hosts."ha.local:8123"]
+headers = { Authorization = "Bearer {env:JARVIS_TEST_HA_TOKEN}" }
+
+[hosts."wiki.example.com"]
+"#,
+    )
+    .unwrap();
+    let tool = HttpTool::load_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let url = |url: &str| Url::parse(url).unwrap();
+
+    std::env::set_var("JARVIS_TEST_HA_TOKEN", "secret");
+    assert_eq!(
+        tool.headers(&url("http://ha.local:8123/api/states"))
+            .unwrap(),
+        [("Authorization".to_string(), "Bearer secret".to_string())]
+    );
+    assert!(tool.headers(&url("http://ha.local:9000/")).is_err());
+    assert!(tool
+        .headers(&url("https://wiki.example.com:8443/page"))
+        .unwrap()
+        .is_empty());
+    assert!(tool.headers(&url("file:///etc/passwd")).is_err());
+    assert_eq!(
+        tool.run(&json!({ "method": "GET", "url": "https://evil.example.org/" }))
+            .unwrap(),
+        "Not sent: evil.example.org is not one of the allowed hosts."
+    );
+}
+
+#[test]
+fn sums_and_conversions_are_worked_out_locally() {
+    let no_rates = || -> anyhow::Result<Rates> { anyhow::bail!("no rates") };
+    let answer = |text: &str| calculator::answer(text, &no_rates).unwrap();
+    assert_eq!(answer("What is 18% of 245?"), "18% of 245 is 44.1.");
+    assert_eq!(
+        answer("2 to the power of 10"),
+        "2 to the power of 10 is 1024."
+    );
+    assert_eq!(answer("(3 + 4) * -2 / 4"), "(3 + 4) * -2 / 4 is -3.5.");
+    assert_eq!(answer("1,200 minus 5!"), "1,200 minus 5! is 1080.");
+    assert_eq!(
+        answer("2 liters in ounces"),
+        "2 liters is 67.628 US fluid ounces."
+    );
+    assert_eq!(answer("3 oz in grams"), "3 ounces is 85.0486 grams.");
+    assert_eq!(
+        answer("100 degrees F to C"),
+        "100 degrees Fahrenheit is 37.7778 degrees Celsius."
+    );
+    assert!(calculator::answer("3 kg in miles", &no_rates).is_err());
+    assert!(calculator::evaluate("1 / 0").is_err());
+    assert!(calculator::evaluate("2 +").is_err());
+
+    let rates = || -> anyhow::Result<Rates> {
+        Ok(Rates {
+            base: "EUR".to_string(),
+            date: "2024-05-31".to_string(),
+            rates: [("USD".to_string(), 1.25)].into_iter().collect(),
+        })
+    };
+    assert_eq!(
+        calculator::answer("100 USD in euros", &rates).unwrap(),
+        "100 USD is 80.00 EUR at the rate of 2024-05-31."
+    );
+}
+
+#[test]
+fn system_controls_run_fixed_commands() {
+    assert_eq!(
+        system::volume_commands("up", 10).unwrap(),
+        [
+            vec![
+                "wpctl",
+                "set-volume",
+                "-l",
+                "1.0",
+                "@DEFAULT_AUDIO_SINK@",
+                "10%+"
+            ],
+            vec!["pactl", "set-sink-volume", "@DEFAULT_SINK@", "+10%"],
+        ]
+    );
+    assert_eq!(
+        system::brightness_commands("set", 0).unwrap(),
+        [
+            vec!["brightnessctl", "--quiet", "set", "5%"],
+            vec!["light", "-S", "5"]
+        ]
+    );
+    assert_eq!(
+        system::media_commands("toggle").unwrap(),
+        [vec!["playerctl", "play-pause"]]
+    );
+    assert!(system::volume_commands("; rm -rf ~", 10).is_none());
+}
+
+#[test]
+fn bluetooth_devices_and_outputs_are_found_by_name() {
+    let devices = audio::parse_bluetooth_devices(
+        "Device AA:BB:CC:DD:EE:FF WH-1000XM4\nDevice 11:22:33:44:55:66 JBL Flip 5\n",
+    );
+    assert_eq!(
+        devices[1],
+        BluetoothDevice {
+            address: "11:22:33:44:55:66".to_string(),
+            name: "JBL Flip 5".to_string(),
+        }
+    );
+    let aliases = vec!["headphones=WH-1000XM4".to_string()];
+    let found = |wanted| audio::find_bluetooth_device(&devices, wanted, &aliases);
+    assert_eq!(found("my headphones").unwrap().address, "AA:BB:CC:DD:EE:FF");
+    assert_eq!(found("jbl flip").unwrap().address, "11:22:33:44:55:66");
+    assert_eq!(found("sony"), None);
+
+    let sinks = audio::parse_pactl_list(
+        "Sink #50\n\tState: RUNNING\n\tName: alsa_output.pci-0000_00_1f.3.analog-stereo\n\
+         \tDescription: Built-in Audio Analog Stereo\n\
+         Sink #71\n\tName: bluez_output.AA_BB_CC_DD_EE_FF.1\n\tDescription: WH-1000XM4\n\
+         Source #72\n\tName: bluez_output.AA_BB_CC_DD_EE_FF.1.monitor\n\
+         \tDescription: Monitor of WH-1000XM4\n",
+    );
+    assert_eq!(sinks.len(), 2);
+    assert_eq!(
+        audio::bluetooth_audio(&sinks, "aa:bb:cc:dd:ee:ff"),
+        Some(&AudioDevice {
+            name: "bluez_output.AA_BB_CC_DD_EE_FF.1".to_string(),
+            description: "WH-1000XM4".to_string(),
+        })
+    );
+    assert_eq!(
+        audio::find_audio_device(&sinks, "built-in audio")
+            .unwrap()
+            .name,
+        "alsa_output.pci-0000_00_1f.3.analog-stereo"
+    );
+}
+
+#[test]
+fn notifications_are_read_from_dbus_monitor_and_filtered_by_app() {
+    let header = "method call time=1718000000.1 sender=:1.87 -> destination=:1.21 serial=9 \
+                  path=/org/freedesktop/Notifications; \
+                  interface=org.freedesktop.Notifications; member=Notify";
+    let text = format!(
+        "signal time=1718000000.0 sender=org.freedesktop.DBus -> destination=:1.90 \
+         path=/org/freedesktop/DBus; interface=org.freedesktop.DBus; member=NameAcquired\n   \
+         string \":1.90\"\n\
+         {header}\n   string \"Slack\"\n   uint32 0\n   string \"slack\"\n   \
+         string \"Anna\"\n   string \"Lunch at <b>noon</b>?\"\n   array [\n   ]\n\
+         {header}\n   string \"Thunderbird\"\n   uint32 0\n   string \"\"\n   \
+         string \"Build failed\"\n   string \"main is red\n2 tests &amp; lint\"\n"
+    );
+    let notifications = notify::parse_dbus_monitor(&text);
+    assert_eq!(
+        notifications,
+        [
+            Notification {
+                app: "Slack".to_string(),
+                summary: "Anna".to_string(),
+                body: "Lunch at noon?".to_string(),
+            },
+            Notification {
+                app: "Thunderbird".to_string(),
+                summary: "Build failed".to_string(),
+                body: "main is red\n2 tests & lint".to_string(),
+            },
+        ]
+    );
+    assert_eq!(notifications[0].sentence(), "Slack: Anna. Lunch at noon?");
+    assert_eq!(
+        notifications[1].sentence(),
+        "Thunderbird: Build failed. main is red 2 tests & lint."
+    );
+
+    let filter = AppFilter {
+        only: vec![],
+        ignore: vec!["spotify".to_string(), notify::APP_NAME.to_string()],
+    };
+    assert!(filter.allows("Slack"));
+    assert!(!filter.allows("Spotify"));
+    assert!(!filter.allows("jarvis"));
+    let filter = AppFilter {
+        only: vec!["Slack".to_string()],
+        ignore: vec![],
+    };
+    assert!(filter.allows("slack"));
+    assert!(!filter.allows("Thunderbird"));
+}
+
+#[test]
+fn stopwatch_laps_and_pomodoro_phases_are_spoken() {
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let at = |seconds| start + Duration::from_secs(seconds);
+    let mut stopwatch = Stopwatch::new();
+    assert_eq!(stopwatch.lap(at(0)), "The stopwatch isn't running.");
+    assert_eq!(stopwatch.start(at(0)), "Stopwatch started.");
+    assert_eq!(
+        stopwatch.lap(at(65)),
+        "Lap 1: 1 minute and 5 seconds, 1 minute and 5 seconds in all."
+    );
+    assert_eq!(
+        stopwatch.lap(at(3725)),
+        "Lap 2: 1 hour and 1 minute, 1 hour, 2 minutes and 5 seconds in all."
+    );
+    assert_eq!(
+        stopwatch.stop(at(3730)),
+        "Stopped at 1 hour, 2 minutes and 10 seconds."
+    );
+    assert_eq!(
+        stopwatch.status(at(4000)),
+        "The stopwatch stopped at 1 hour, 2 minutes and 10 seconds."
+    );
+    assert_eq!(timers::stopwatch_time(Duration::ZERO), "0 seconds");
+
+    let settings = PomodoroSettings::default();
+    let minutes = |count| Duration::from_secs(count * 60);
+    let first = settings.phase_at(Duration::ZERO);
+    assert_eq!((first.kind, first.index), (PhaseKind::Focus(1), 0));
+    assert_eq!(
+        first.announcement(&settings),
+        "Focus session 1 of 4: 25 minutes."
+    );
+    let rest = settings.phase_at(minutes(26));
+    assert_eq!((rest.kind, rest.index), (PhaseKind::Break, 1));
+    assert_eq!(rest.announcement(&settings), "Time for a break: 5 minutes.");
+    assert_eq!(rest.status(&settings), "On a break, 4 minutes left.");
+    assert_eq!(
+        settings.phase_at(minutes(30)).announcement(&settings),
+        "Break's over. Focus session 2 of 4: 25 minutes."
+    );
+    let long = settings.phase_at(minutes(115));
+    assert_eq!((long.kind, long.sessions_done()), (PhaseKind::LongBreak, 4));
+    assert_eq!(
+        long.announcement(&settings),
+        "That's 4 focus sessions. Time for a long break: 15 minutes."
+    );
+    let again = settings.phase_at(minutes(130) + Duration::from_secs(30));
+    assert_eq!((again.kind, again.index), (PhaseKind::Focus(1), 8));
+    assert_eq!(
+        again.status(&settings),
+        "Focus session 1 of 4, 25 minutes left."
+    );
+}
+
+#[tokio::test]
+async fn pomodoro_phases_are_published_as_they_begin() {
+    use futures_util::StreamExt;
+    use jarvis_rust::events::{EventBus, JarvisEvent};
+    use std::time::Duration;
+
+    let bus = EventBus::new();
+    let events = bus.subscribe();
+    tokio::pin!(events);
+    let tool = PomodoroTool::default();
+    tool.set_event_bus(&bus);
+    let second = Duration::from_secs(1);
+    let settings = PomodoroSettings {
+        work: second,
+        short_break: second,
+        long_break: second,
+        rounds: 2,
+    };
+    assert!(tool
+        .start(settings)
+        .starts_with("Pomodoro started. Focus session 1 of 2"));
+    let event = tokio::time::timeout(Duration::from_secs(3), events.next())
+        .await
+        .unwrap();
+    let Some(JarvisEvent::Timer { text }) = event else {
+        panic!("expected a timer event, got {event:?}");
+    };
+    assert!(text.starts_with("Time for a break"), "{text}");
+    assert_eq!(tool.stop(), "Pomodoro stopped after 1 focus session.");
+    assert_eq!(tool.status(), "The Pomodoro timer isn't running.");
+}
+
+#[cfg(unix)]
+#[test]
+fn music_is_found_by_the_words_asked_for() {
+    use jarvis_rust::tools::music;
+    use std::path::Path;
+    let words = music::query_words("play some Miles Davis songs");
+    assert_eq!(words, ["miles", "davis"]);
+    let track = Path::new("Miles Davis/Kind of Blue/01 So What.flac");
+    assert!(music::matches(track, &words));
+    assert!(music::matches(track, &music::query_words("So What")));
+    assert!(!music::matches(track, &music::query_words("John Coltrane")));
+    assert_eq!(music::track_name(track), "So What");
+    assert_eq!(
+        music::mpc_play_commands(&words),
+        [
+            vec!["mpc", "clear"],
+            vec!["mpc", "searchadd", "any", "miles", "any", "davis"],
+            vec!["mpc", "play"],
+        ]
+    );
+    assert_eq!(
+        music::mpv_control("next").unwrap(),
+        json!({ "command": ["playlist-next"] })
+    );
+    assert_eq!(
+        music::mpv_control("volume 40").unwrap(),
+        json!({ "command": ["set_property", "volume", 40] })
+    );
+    assert!(music::mpv_control("rm").is_none());
+    // Nothing was started, so the media tool is left to playerctl.
+    assert!(music::control("pause").is_none());
+}
+
+#[test]
+fn system_status_is_read_out_in_words() {
+    const GIGABYTE: u64 = 1024 * 1024 * 1024;
+    let mut snapshot = Snapshot {
+        cpu_percent: 12.4,
+        memory_available: 9 * GIGABYTE,
+        memory_total: 16 * GIGABYTE,
+        disks: vec![
+            DiskSpace {
+                mount: "/".to_string(),
+                available: 120 * GIGABYTE,
+                total: 500 * GIGABYTE,
+            },
+            DiskSpace {
+                mount: "/mnt/backup".to_string(),
+                available: 50 * GIGABYTE,
+                total: 1000 * GIGABYTE,
+            },
+        ],
+        battery: Some(Battery {
+            percent: 80,
+            status: "Charging".to_string(),
+        }),
+        temperature: Some(54.2),
+        uptime: std::time::Duration::from_secs(3 * 86400 + 4 * 3600 + 59),
+    };
+    assert_eq!(snapshot.spoken("cpu"), "CPU is at 12 percent.");
+    assert_eq!(
+        snapshot.spoken("memory"),
+        "9 gigabytes of memory free out of 16 gigabytes."
+    );
+    assert_eq!(
+        snapshot.spoken("disk"),
+        "The main disk is 76 percent full, with 120 gigabytes free. /mnt/backup is 95 percent full."
+    );
+    assert_eq!(snapshot.spoken("uptime"), "Up for 3 days and 4 hours.");
+    assert!(!snapshot.healthy());
+    snapshot.disks.pop();
+    assert_eq!(
+        snapshot.spoken("all"),
+        "Everything looks healthy. CPU is at 12 percent. 9 gigabytes of memory free out of 16 gigabytes. \
+         The main disk is 76 percent full, with 120 gigabytes free. \
+         The battery is at 80 percent and charging. The temperature is 54 degrees. Up for 3 days and 4 hours."
+    );
+    snapshot.battery = Some(Battery {
+        percent: 9,
+        status: "Discharging".to_string(),
+    });
+    assert_eq!(
+        snapshot.spoken("battery"),
+        "The battery is low, at 9 percent. Plug in soon."
+    );
+    assert!(!snapshot.healthy());
+
+    assert_eq!(status::bytes(1536 * 1024 * 1024), "1.5 gigabytes");
+    assert_eq!(status::bytes(512 * 1024 * 1024), "512 megabytes");
+    assert_eq!(
+        status::spoken_duration(std::time::Duration::from_secs(2 * 3600 + 5 * 60)),
+        "2 hours and 5 minutes"
+    );
+    assert_eq!(
+        status::spoken_duration(std::time::Duration::from_secs(60)),
+        "1 minute"
+    );
+}
+
+#[test]
+fn the_screen_is_captured_and_its_text_cleaned_up() {
+    let path = std::path::Path::new("/tmp/screen.png");
+    let programs = |commands: Vec<Vec<String>>| -> Vec<String> {
+        commands.into_iter().map(|argv| argv[0].clone()).collect()
+    };
+    assert_eq!(
+        programs(screen::capture_commands(path, true, None)),
+        ["grim", "spectacle", "scrot"]
+    );
+    assert_eq!(
+        programs(screen::capture_commands(path, false, None)),
+        ["scrot", "spectacle", "grim"]
+    );
+    assert_eq!(
+        screen::capture_commands(path, true, Some("scrot")),
+        [["scrot", "--overwrite", "/tmp/screen.png"]]
+    );
+    assert_eq!(
+        screen::ocr_command(path, "eng+hrv"),
+        ["tesseract", "/tmp/screen.png", "stdout", "-l", "eng+hrv"]
+    );
+    assert_eq!(
+        screen::clean_ocr("  Error  \n|\n—\n\n\nCould not save file.\n~\n OK \n\n"),
+        "Error\n\nCould not save file.\n\nOK"
+    );
+    assert_eq!(screen::clean_ocr("| . —\n\n"), "");
+}
+
+#[test]
+fn applications_are_found_by_what_the_user_calls_them() {
+    let firefox = DesktopEntry::parse(
+        "org.mozilla.firefox",
+        "[Desktop Entry]\nType=Application\nName=Firefox\nName[de]=Feuerfuchs\n\
+GenericName=Web Browser\nExec=firefox --name \"my browser\" %u\n\
+[Desktop Action new-window]\nName=New Window\nExec=firefox --new-window %u\n",
+    )
+    .unwrap();
+    let editor = DesktopEntry::parse(
+        "org.gnome.TextEditor",
+        "[Desktop Entry]\nType=Application\nName=Text Editor\nKeywords=text;notes;\nExec=gnome-text-editor %U\n",
+    )
+    .unwrap();
+    assert!(DesktopEntry::parse(
+        "hidden",
+        "[Desktop Entry]\nType=Application\nName=X\nExec=x\nNoDisplay=true\n"
+    )
+    .is_none());
+    assert_eq!(firefox.name, "Firefox");
+    assert_eq!(
+        apps::exec_argv(&firefox.exec),
+        ["firefox", "--name", "my browser"]
+    );
+    let entries = [firefox, editor];
+    let found = |wanted: &str| apps::find(&entries, wanted).map(|entry| entry.name.as_str());
+    assert_eq!(found("firefox"), Some("Firefox"));
+    assert_eq!(found("the web browser"), Some("Firefox"));
+    assert_eq!(found("web browser"), Some("Firefox"));
+    assert_eq!(found("notes"), Some("Text Editor"));
+    assert_eq!(found("text editor app"), Some("Text Editor"));
+    assert_eq!(found("spotify"), None);
+}
+
+#[test]
+fn containers_are_found_by_spoken_name_and_stopping_is_confirmed() {
+    let names: Vec<String> = ["nextcloud-app-1", "nextcloud-db-1", "media_server"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    assert_eq!(
+        containers::find(&names, "the media server"),
+        Some("media_server")
+    );
+    assert_eq!(
+        containers::find(&names, "Nextcloud app 1"),
+        Some("nextcloud-app-1")
+    );
+    assert_eq!(
+        containers::find(&names, "next cloud db"),
+        Some("nextcloud-db-1")
+    );
+    assert_eq!(containers::find(&names, "nextcloud"), None, "two match");
+
+    assert_eq!(
+        containers::spoken_list("nextcloud-app-1\tUp 2 days\njellyfin\tUp 3 hours (healthy)\n"),
+        "2 containers are running: nextcloud-app-1, up 2 days; jellyfin, up 3 hours (healthy)."
+    );
+    assert_eq!(containers::spoken_list(""), "No containers are running.");
+
+    let tool = ContainersTool::new("docker");
+    assert_eq!(
+        tool.confirmation(&json!({ "action": "restart", "name": "media server" }))
+            .as_deref(),
+        Some("restart the container media server")
+    );
+    assert_eq!(
+        tool.confirmation(&json!({ "action": "logs", "name": "media server" })),
+        None
+    );
+}
+
+#[test]
+fn git_answers_are_sentences_and_commits_are_confirmed() {
+    assert_eq!(
+        git::spoken_status("## main...origin/main [ahead 2]\nM  src/lib.rs\n M README.md\nAM new.rs\n?? notes.txt\n"),
+        "On main, 2 commits ahead of origin/main. 2 staged, 2 modified and 1 untracked files."
+    );
+    assert_eq!(
+        git::spoken_status("## main...origin/main [ahead 1, behind 3]\n"),
+        "On main, 1 commit ahead and 3 commits behind origin/main. Nothing to commit."
+    );
+    assert_eq!(
+        git::spoken_status("## feature\nUU src/lib.rs\n"),
+        "On feature. 1 file has conflicts."
+    );
+    assert_eq!(
+        git::spoken_commit("abc1234\tAna\t2 hours ago\tFix the parser\n"),
+        "The last commit, abc1234 by Ana 2 hours ago: Fix the parser."
+    );
+    assert_eq!(
+        git::spoken_shortstat(" 3 files changed, 10 insertions(+), 2 deletions(-)\n").as_deref(),
+        Some("3 files changed, 10 insertions, 2 deletions")
+    );
+    assert_eq!(git::spoken_shortstat(""), None);
+
+    let tool = GitTool::new(Some("/home/ana/blog".into()));
+    assert_eq!(
+        tool.confirmation(&json!({ "action": "commit", "message": "Add a post", "all": true }))
+            .as_deref(),
+        Some("commit all changes in blog with the message: Add a post")
+    );
+    assert_eq!(
+        tool.confirmation(&json!({ "action": "push" })).as_deref(),
+        Some("push blog")
+    );
+    assert_eq!(tool.confirmation(&json!({ "action": "status" })), None);
+}
+
+#[test]
+fn remote_commands_must_be_allowed() {
+    assert!(remote::allows("df -h", "df  -h"));
+    assert!(!remote::allows("df -h", "df -h /"));
+    assert!(remote::allows(
+        "systemctl status *",
+        "systemctl status nginx"
+    ));
+    assert!(remote::allows("systemctl status *", "systemctl status"));
+    assert!(!remote::allows("systemctl status *", "systemctl statusx"));
+    assert!(!remote::allows(
+        "systemctl status *",
+        "systemctl status x; reboot"
+    ));
+    assert!(!remote::allows(
+        "systemctl status *",
+        "systemctl status $(reboot)"
+    ));
+
+    let path = std::env::temp_dir().join(format!("jarvis-remote-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+allow = ["uptime"]
+
+[hosts.NAS]
+address = "192.168.1.10"
+user = "admin"
+port = 2222
+allow = ["df -h"]
+"#,
+    )
+    .unwrap();
+    let tool = RemoteTool::load_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        tool.ssh_argv("the NAS", "df -h").unwrap(),
+        [
+            "ssh",
+            "-o",
+            "BatchMode=yes",
+            "-o",
+            "ConnectTimeout=10",
+            "-p",
+            "2222",
+            "admin@192.168.1.10",
+            "--",
+            "df -h"
+        ]
+    );
+    assert!(tool.ssh_argv("nas", "uptime").is_ok());
+    assert!(tool
+        .ssh_argv("nas", "rm -rf /")
+        .unwrap_err()
+        .contains("not allowed"));
+    assert!(tool
+        .ssh_argv("desktop", "uptime")
+        .unwrap_err()
+        .contains("no host"));
+}
+
+#[test]
+fn kubectl_stays_in_its_namespaces_and_reports_sick_pods() {
+    let path = std::env::temp_dir().join(format!("jarvis-kube-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[contexts.prod]
+context = "gke_acme_prod"
+namespaces = ["web", "payments"]
+allow_changes = true
+
+[contexts.staging]
+"#,
+    )
+    .unwrap();
+    let tool = KubectlTool::load_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let pods = tool
+        .commands(&json!({ "action": "pods", "context": "prod" }))
+        .unwrap();
+    assert_eq!(pods.len(), 2);
+    assert_eq!(
+        pods[1],
+        [
+            "kubectl",
+            "--context",
+            "gke_acme_prod",
+            "--namespace",
+            "payments",
+            "get",
+            "pods",
+            "--output",
+            "json"
+        ]
+    );
+    assert!(tool
+        .commands(&json!({ "action": "logs", "context": "prod", "name": "api" }))
+        .unwrap_err()
+        .starts_with("Which namespace"));
+    assert!(tool
+        .commands(&json!({ "action": "pods", "context": "prod", "namespace": "kube-system" }))
+        .is_err());
+    assert!(tool
+        .commands(&json!({ "action": "delete_pod", "context": "staging", "name": "api" }))
+        .unwrap_err()
+        .contains("not allowed"));
+    assert_eq!(
+        tool.confirmation(&json!({
+            "action": "restart", "context": "prod", "namespace": "web", "name": "api"
+        }))
+        .as_deref(),
+        Some("restart the deployment api in web on gke_acme_prod")
+    );
+    assert_eq!(
+        tool.confirmation(&json!({ "action": "pods", "context": "prod" })),
+        None
+    );
+
+    let pod = |name: &str, phase: &str, status: serde_json::Value| {
+        json!({
+            "metadata": { "name": name },
+            "status": { "phase": phase, "containerStatuses": [status] },
+        })
+    };
+    let pods = json!({ "items": [
+        pod("api-1", "Running", json!({ "ready": true, "restartCount": 0, "state": { "running": {} } })),
+        pod("api-2", "Running", json!({
+            "ready": false,
+            "restartCount": 14,
+            "state": { "waiting": { "reason": "CrashLoopBackOff" } },
+        })),
+        pod("worker", "Pending", json!({ "ready": false, "restartCount": 0, "state": {} })),
+    ]});
+    assert_eq!(
+        kubernetes::spoken_pods(&pods),
+        "2 of 3 pods have problems: api-2 is in CrashLoopBackOff after 14 restarts; worker is pending."
+    );
+    assert_eq!(
+        kubernetes::spoken_pods(&json!({ "items": [pods["items"][0]] })),
+        "1 pod, all healthy."
+    );
+}
+
+#[cfg(feature = "weather")]
+#[test]
+fn forecasts_are_phrased_for_speech() {
+    use jarvis_rust::tools::weather::{self, Day, Report, Units};
+
+    let report: Report = serde_json::from_value(json!({
+        "current": { "temperature_2m": 13.6, "weather_code": 2, "wind_speed_10m": 41.0 },
+        "daily": {
+            "weather_code": [61, 0],
+            "temperature_2m_max": [16.2, 18.0],
+            "temperature_2m_min": [8.7, -2.4],
+            "precipitation_sum": [2.6, 0.0],
+            "precipitation_probability_max": [70, null],
+        },
+    }))
+    .unwrap();
+    assert_eq!(
+        weather::spoken_forecast(&report, "in Zagreb", Day::Today, Units::Metric).unwrap(),
+        "In Zagreb it's 14 degrees and partly cloudy. It's windy, 41 kilometres per hour. \
+Today: light rain, from 9 to 16 degrees, with a 70 percent chance of rain and about 3 millimetres."
+    );
+    assert_eq!(
+        weather::spoken_forecast(&report, "at home", Day::Tomorrow, Units::Metric).unwrap(),
+        "Tomorrow at home: clear, from minus 2 to 18 degrees, and no rain expected."
+    );
+}
+
+#[cfg(feature = "wiki")]
+#[test]
+fn wikipedia_introductions_are_cut_to_a_few_sentences() {
+    use jarvis_rust::tools::wiki;
+    let extract = "Nikola Tesla (/ˈtɛslə/; Serbian Cyrillic: Никола Тесла; 10 July 1856 – \
+        7 January 1943) was a Serbian-American engineer and inventor. He is known for his \
+        contributions to the design of the modern AC electricity supply system. Born in the \
+        Austrian Empire, he studied in Graz. He emigrated to the U.S. in 1884.";
+    assert_eq!(
+        wiki::spoken_intro("Nikola Tesla", extract, 2),
+        "Nikola Tesla was a Serbian-American engineer and inventor. He is known for his \
+         contributions to the design of the modern AC electricity supply system."
+    );
+    assert_eq!(
+        wiki::first_sentences("J. R. R. Tolkien was a writer. Dr. Who is not. Third.", 2),
+        "J. R. R. Tolkien was a writer. Dr. Who is not."
+    );
+    assert_eq!(
+        wiki::first_sentences("It moved to the U.S. Army in 1950. Then it closed.", 1),
+        "It moved to the U.S. Army in 1950."
+    );
+    assert_eq!(
+        wiki::spoken_intro("Empty", "", 3),
+        "The Wikipedia article Empty has no introduction."
+    );
+}
+
+#[cfg(feature = "news")]
+#[test]
+fn news_feeds_become_one_briefing() {
+    use jarvis_rust::tools::news;
+    let rss = br#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>BBC</title>
+<item><title>Storm hits the coast</title><link>https://example.com/storm</link>
+<pubDate>Fri, 16 Oct 2026 07:00:00 GMT</pubDate></item>
+<item><title>Elections called</title><link>https://example.com/elections</link>
+<pubDate>Fri, 16 Oct 2026 08:00:00 GMT</pubDate></item>
+<item><title>Old news</title><pubDate>Thu, 15 Oct 2026 06:00:00 GMT</pubDate></item>
+</channel></rss>"#;
+    let atom = br#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom"><title>Reuters</title><id>r</id>
+<updated>2026-10-16T09:00:00Z</updated>
+<entry><title>Storm hits the coast!</title><id>1</id><link href="https://example.com/storm/"/>
+<updated>2026-10-16T09:00:00Z</updated></entry>
+<entry><title>Markets rally?</title><id>2</id><link href="https://example.com/markets"/>
+<updated>2026-10-16T08:30:00Z</updated></entry>
+</feed>"#;
+    let mut headlines = news::parse_feed("BBC", rss).unwrap();
+    assert_eq!(headlines[0].title, "Elections called");
+    assert_eq!(headlines.len(), 3);
+    headlines.extend(news::parse_feed("Reuters", atom).unwrap());
+    let chosen = news::select(news::dedupe(headlines), 2, 3);
+    assert_eq!(
+        news::briefing(&chosen),
+        "Here's the news. From BBC: Elections called. Storm hits the coast. \
+         From Reuters: Markets rally?"
+    );
+    assert_eq!(news::briefing(&[]), "There's no news right now.");
+    assert!(news::parse_feed("Broken", b"<html>").is_err());
+}
+
+#[cfg(feature = "email")]
+#[test]
+fn emails_are_read_out_and_sending_is_confirmed() {
+    use jarvis_rust::tools::email::{self, EmailTool, Summary};
+
+    let path = std::env::temp_dir().join(format!("jarvis-email-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+address = "me@example.com"
+
+[smtp]
+host = "smtp.example.com"
+
+[contacts]
+Bob = "bob@example.com"
+"#,
+    )
+    .unwrap();
+    let tool = EmailTool::load_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(tool.recipient("bob").as_deref(), Some("bob@example.com"));
+    assert_eq!(tool.recipient("carol"), None);
+    assert_eq!(
+        tool.confirmation(&json!({ "action": "send", "to": "Bob", "body": "I'll be late." }))
+            .as_deref(),
+        Some("send an email to Bob, bob@example.com, saying: I'll be late.")
+    );
+    assert_eq!(tool.confirmation(&json!({ "action": "unread" })), None);
+    assert_eq!(
+        tool.run(&json!({ "action": "send", "to": "Carol", "body": "Hi" }))
+            .unwrap(),
+        "I don't have an email address for Carol."
+    );
+
+    let summary = |from: &str, subject: &str| Summary {
+        from: from.to_string(),
+        subject: subject.to_string(),
+    };
+    assert_eq!(email::spoken_unread(&[]), "No new email.");
+    assert_eq!(
+        email::spoken_unread(&[summary("Alice", "Lunch?"), summary("GitHub", "")]),
+        "2 unread emails. From Alice: Lunch? From GitHub, with no subject."
+    );
+    assert_eq!(
+        email::decode_header("=?UTF-8?Q?Caf=C3=A9_at_?= =?UTF-8?B?bm9vbg==?= today"),
+        "Café at noon today"
+    );
+}
+
+#[cfg(feature = "mqtt")]
+#[test]
+fn mqtt_messages_become_announcements() {
+    use jarvis_rust::tools::mqtt::{self, Announce};
+
+    assert!(mqtt::topic_matches("home/+/state", "home/washer/state"));
+    assert!(mqtt::topic_matches("home/#", "home/washer/state"));
+    assert!(!mqtt::topic_matches("home/+", "home/washer/state"));
+    assert!(!mqtt::topic_matches("home/washer/state", "home/washer"));
+
+    let washer = Announce {
+        topic: "home/+/state".to_string(),
+        say: "The {topic} says {payload}.".to_string(),
+        values: [(
+            "done".to_string(),
+            "The washing machine is done.".to_string(),
+        )]
+        .into(),
+        only: vec!["done".to_string(), "error".to_string()],
+    };
+    assert_eq!(
+        washer.sentence("home/washer/state", "done\n").as_deref(),
+        Some("The washing machine is done.")
+    );
+    assert_eq!(
+        washer.sentence("home/washer/state", "error").as_deref(),
+        Some("The home/washer/state says error.")
+    );
+    assert_eq!(washer.sentence("home/washer/state", "rinsing"), None);
+    assert_eq!(washer.sentence("garden/pump/state", "done"), None);
+}
+
+#[cfg(feature = "plugins")]
+#[test]
+fn plugins_describe_themselves_and_run() {
+    use jarvis_rust::tools::plugin::PluginTool;
+
+    let path = std::env::temp_dir().join(format!("jarvis-plugin-{}.wat", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"(module
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{\22name\22:\22ping\22,\22description\22:\22Answer pong.\22}")
+  (data (i32.const 100) "{\22output\22:\22pong\22}")
+  (func (export "jarvis_alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "jarvis_manifest") (result i64) (i64.const 44))
+  (func (export "jarvis_invoke") (param i32 i32) (result i64) (i64.const 429496729617)))"#,
+    )
+    .unwrap();
+    let plugin = PluginTool::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(plugin.name(), "ping");
+    assert_eq!(plugin.description(), "Answer pong.");
+    assert_eq!(plugin.run(&json!({})).unwrap(), "pong");
+}
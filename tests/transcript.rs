@@ -0,0 +1,26 @@
+//! Cleaning and checking what the recogniser heard.
+
+use jarvis_rust::transcript::TranscriptFilter;
+
+#[test]
+fn noise_is_trimmed_and_noise_phrases_are_silence() {
+    let filter = TranscriptFilter::from_env();
+    assert_eq!(filter.clean("uh the open the door um"), "open the door");
+    assert_eq!(filter.clean("Thank   You"), "");
+    assert_eq!(
+        filter.clean("thank you for the music"),
+        "thank you for the music"
+    );
+    assert_eq!(filter.clean("the"), "");
+}
+
+#[test]
+fn gibberish_and_unsure_transcripts_are_not_plausible() {
+    let filter = TranscriptFilter::from_env();
+    assert!(filter.is_plausible("open the door", Some(0.9)));
+    assert!(filter.is_plausible("open the door", None));
+    assert!(!filter.is_plausible("open the door", Some(0.2)));
+    assert!(!filter.is_plausible("x q um", None));
+    assert_eq!(filter.retry_limit, 2);
+    assert_eq!(filter.retry_prompt, "Sorry, say that again?");
+}
@@ -0,0 +1,429 @@
+//! The speech backends and what is done to text before it is spoken.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use jarvis_rust::audio_output::Audio;
+use jarvis_rust::events::EventBus;
+use jarvis_rust::tts_engine::cache::AudioCache;
+use jarvis_rust::tts_engine::command::CommandSpeaker;
+use jarvis_rust::tts_engine::espeak::EspeakSpeaker;
+use jarvis_rust::tts_engine::language::{detect_language, LanguageVoices};
+use jarvis_rust::tts_engine::paging::Pager;
+use jarvis_rust::tts_engine::piper::PiperSpeaker;
+use jarvis_rust::tts_engine::pronunciation::Lexicon;
+use jarvis_rust::tts_engine::sentences::{split_sentences, SentenceSplitter};
+use jarvis_rust::tts_engine::ssml;
+use jarvis_rust::tts_engine::{Prosody, Speaker, TtsEngine};
+use serde_json::{json, Value};
+use tokio_util::sync::CancellationToken;
+
+/// Records what it is asked to say and the voices it is asked to use.
+#[derive(Clone, Default)]
+struct Spoken {
+    said: Arc<Mutex<Vec<String>>>,
+    voices: Arc<Mutex<Vec<String>>>,
+}
+
+impl Spoken {
+    fn said(&self) -> Vec<String> {
+        self.said.lock().unwrap().clone()
+    }
+
+    fn voices(&self) -> Vec<String> {
+        self.voices.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Speaker for Spoken {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        self.said.lock().unwrap().push(text.to_string());
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn synthesize(&mut self, text: &str) -> Result<Audio> {
+        self.said.lock().unwrap().push(text.to_string());
+        Ok(Audio {
+            samples: vec![0; 160],
+            sample_rate: 16000,
+            channels: 1,
+        })
+    }
+
+    fn set_voice(&mut self, name: &str) -> Result<()> {
+        self.voices.lock().unwrap().push(name.to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn piper_voices_are_the_models_next_to_the_configured_one() {
+    let dir = std::env::temp_dir().join(format!("jarvis-piper-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for model in ["en_US-amy-medium.onnx", "de_DE-thorsten-low.onnx"] {
+        std::fs::write(dir.join(model), "").unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), "").unwrap();
+    std::env::set_var("PIPER_MODEL", dir.join("en_US-amy-medium.onnx"));
+    let mut piper = PiperSpeaker::from_env().unwrap();
+    let voices: Vec<(String, Option<String>)> = piper
+        .list_voices()
+        .unwrap()
+        .into_iter()
+        .map(|voice| (voice.name, voice.language))
+        .collect();
+    assert_eq!(
+        voices,
+        [
+            ("de_DE-thorsten-low".to_string(), Some("de-DE".to_string())),
+            ("en_US-amy-medium".to_string(), Some("en-US".to_string())),
+        ]
+    );
+    assert!(piper.set_voice("Thorsten").is_ok());
+    assert!(piper.set_voice("nobody").is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn commands_get_the_text_as_an_argument_or_on_stdin() {
+    let path = std::env::temp_dir().join(format!("jarvis-tts-command-{}", std::process::id()));
+    let write = format!("echo \"$1: $2\" > {}", path.display());
+    let mut speaker = CommandSpeaker::new(
+        "sh",
+        &["-c", &write, "sh", "{voice}", "{text}"],
+        Some("amy".to_string()),
+    );
+    speaker.speak("Hello there.").await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "amy: Hello there.\n"
+    );
+
+    let copy = format!("cat > {}", path.display());
+    let mut speaker = CommandSpeaker::new("sh", &["-c", &copy], None);
+    speaker.speak("From stdin.").await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "From stdin.");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn short_requests_change_the_rate_pitch_and_volume() {
+    let normal = Prosody::default();
+    let (faster, reply) = normal.adjust_for_command("Speak faster").unwrap();
+    assert_eq!(faster.rate, 1.25);
+    assert_eq!(reply, "Okay, I'll speak faster.");
+    let (louder, _) = faster.adjust_for_command("a bit louder please").unwrap();
+    assert_eq!((louder.rate, louder.volume), (1.25, 1.25));
+    let mut fast = normal;
+    for _ in 0..10 {
+        fast = fast.adjust_for_command("speed up").unwrap().0;
+    }
+    assert_eq!(fast.rate, 2.0, "the rate stops at twice normal");
+    assert_eq!(louder.adjust_for_command("normal voice").unwrap().0, normal);
+    assert!(normal
+        .adjust_for_command("write a script that makes the fan spin faster")
+        .is_none());
+    assert!(normal.adjust_for_command("what's the weather").is_none());
+}
+
+#[test]
+fn sentences_end_where_no_more_text_can_continue_them() {
+    let mut splitter = SentenceSplitter::new();
+    assert!(splitter.push("Dr. Smith said pi is 3").is_empty());
+    assert_eq!(
+        splitter.push(".14. Really?! Yes"),
+        ["Dr. Smith said pi is 3.14.", "Really?!"]
+    );
+    assert_eq!(splitter.finish().as_deref(), Some("Yes"));
+    assert_eq!(splitter.finish(), None);
+    assert_eq!(
+        split_sentences("Shopping:\n- eggs\n- milk"),
+        ["Shopping:", "- eggs", "- milk"]
+    );
+}
+
+#[tokio::test]
+async fn utterances_are_spoken_in_order_sentence_by_sentence() {
+    let spoken = Spoken::default();
+    let tts = TtsEngine::with_backend(Box::new(spoken.clone()));
+    let first = tts.speak("First of all. Then the rest.");
+    let skipped = tts.speak("Never said.");
+    let last = tts.speak("Last of all.");
+    let urgent = tts.speak_now("Urgent!");
+    skipped.cancel();
+    for handle in [urgent, first, skipped, last] {
+        handle.await.unwrap();
+    }
+    assert_eq!(
+        spoken.said(),
+        ["Urgent!", "First of all.", "Then the rest.", "Last of all."]
+    );
+}
+
+#[tokio::test]
+async fn fixed_phrases_are_synthesised_once_per_voice() {
+    let home = std::env::temp_dir().join(format!("jarvis-tts-cache-{}", std::process::id()));
+    std::env::set_var("HOME", &home);
+    let cache = AudioCache::from_env().unwrap();
+    let mut spoken = Spoken::default();
+    let stop = Arc::new(AtomicBool::new(false));
+    // Playing needs a sound card; the audio is stored before that.
+    for voice in ["engine=piper", "engine=piper", "engine=espeak"] {
+        let _ = cache
+            .speak(&mut spoken, None, voice, "Yes sir?", &stop)
+            .await;
+    }
+    let files = std::fs::read_dir(home.join(".jarvis").join("tts_cache"))
+        .unwrap()
+        .count();
+    std::fs::remove_dir_all(&home).unwrap();
+    assert_eq!(spoken.said(), ["Yes sir?", "Yes sir?"]);
+    assert_eq!(files, 2);
+}
+
+#[tokio::test]
+async fn speech_can_be_saved_to_a_file_instead_of_played() {
+    let spoken = Spoken::default();
+    let mut tts = TtsEngine::with_backend(Box::new(spoken.clone()));
+    let path = std::env::temp_dir().join(format!("jarvis-say-{}.wav", std::process::id()));
+    tts.speak_to_file("Saved for later.", &path).await.unwrap();
+    let saved = Audio::from_wav(&std::fs::read(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(spoken.said(), ["Saved for later."]);
+    assert_eq!((saved.samples.len(), saved.sample_rate), (160, 16000));
+}
+
+#[test]
+fn the_pronunciation_file_adds_words_and_rules_to_the_built_in_ones() {
+    let path = std::env::temp_dir().join(format!("jarvis-lexicon-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[words]
+"c++" = "see plus plus"
+SQL = "S Q L"
+
+[[rules]]
+pattern = '(\d+)ms\b'
+replace = "$1 milliseconds"
+
+[[rules]]
+pattern = '('
+replace = "never used"
+"#,
+    )
+    .unwrap();
+    std::env::set_var("PRONUNCIATIONS_FILE", &path);
+    let lexicon = Lexicon::load();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        lexicon.apply("Run kubectl and sql in C++ within 250ms, not kubectls."),
+        "Run cube control and S Q L in see plus plus within 250 milliseconds, not kubectls."
+    );
+}
+
+#[test]
+fn ssml_reads_the_same_as_plain_text() {
+    let reply = r#"Ping <say-as interpret-as="characters">10.0.1</say-as><break time="1s"/>it answered <sub alias="in three milliseconds">3ms</sub>."#;
+    assert!(ssml::is_ssml(reply));
+    assert_eq!(
+        ssml::strip(reply),
+        "Ping 1 0 dot 0 dot 1. it answered in three milliseconds."
+    );
+    assert_eq!(
+        ssml::to_plain(r#"<s>Fish &amp; chips</s>Wait<break time="200ms"/> then go"#),
+        "Fish & chips. Wait, then go"
+    );
+    assert!(!ssml::is_ssml("if a < b and c > d"));
+    assert_eq!(ssml::strip("Fish &amp; chips"), "Fish &amp; chips");
+    assert_eq!(ssml::wrap(" Hello "), "<speak>Hello</speak>");
+    assert_eq!(ssml::wrap("<speak>Hello</speak>"), "<speak>Hello</speak>");
+}
+
+#[test]
+fn long_listings_are_read_a_page_of_lines_at_a_time() {
+    let listing = "alpha.txt\nbeta.txt\ngamma.txt\ndelta.txt\nepsilon.txt";
+    let mut pager = Pager::new(30);
+    assert_eq!(
+        pager.first_page(listing),
+        "alpha.txt\nbeta.txt\ngamma.txt\nThat was 3 of 5 lines. Say continue to hear more."
+    );
+    assert_eq!(pager.next_page().as_deref(), Some("delta.txt\nepsilon.txt"));
+    assert_eq!(pager.next_page(), None);
+    assert_eq!(Pager::new(0).first_page(listing), listing);
+    assert!(Pager::is_continue_command("Please go on."));
+    assert!(Pager::is_continue_command("next page"));
+    assert!(
+        !Pager::is_continue_command("next"),
+        "that is the next track"
+    );
+    assert!(!Pager::is_continue_command("continue the story"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn espeak_gets_the_voice_prosody_and_markup_flags() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("jarvis-espeak-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("call");
+    let bin = dir.join("espeak-ng");
+    std::fs::write(
+        &bin,
+        format!(
+            "#!/bin/sh\n\
+             if [ \"$1\" = --voices ]; then\n\
+             printf 'Pty Language Age/Gender VoiceName File\\n 5  en-gb  --/M  English gmw/en\\n 5  de  --/F  German gmw/de\\n'\n\
+             else\n\
+             printf '%s\\n' \"$*\" > {0}; cat >> {0}\n\
+             fi\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::env::set_var("ESPEAK_BIN", &bin);
+    let mut espeak = EspeakSpeaker::from_env();
+    let voices: Vec<(String, Option<String>)> = espeak
+        .list_voices()
+        .unwrap()
+        .into_iter()
+        .map(|voice| (voice.name, voice.gender))
+        .collect();
+    assert_eq!(
+        voices,
+        [
+            ("en-gb".to_string(), Some("male".to_string())),
+            ("de".to_string(), Some("female".to_string())),
+        ]
+    );
+    espeak.set_voice("en-gb").unwrap();
+    espeak.set_rate(2.0).unwrap();
+    espeak.set_volume(0.5).unwrap();
+    espeak.speak("<speak>Hi</speak>").await.unwrap();
+    let call = std::fs::read_to_string(&log).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        call,
+        "-v en-gb -s 350 -p 50 -a 50 -m --stdin\n<speak>Hi</speak>"
+    );
+}
+
+#[test]
+fn replies_are_spoken_with_the_voice_for_their_language() {
+    assert_eq!(
+        detect_language("Das ist nicht mein Auto und es ist alt"),
+        Some("de")
+    );
+    assert_eq!(detect_language("Привет, как дела?"), Some("ru"));
+    assert_eq!(detect_language("I like die hard movies"), None);
+    assert_eq!(detect_language("Hi there"), None);
+
+    std::env::set_var("VOICE_LANGUAGES", "de=thorsten, fr=siwis");
+    let mut languages = LanguageVoices::from_env().unwrap();
+    std::env::remove_var("VOICE_LANGUAGES");
+    let mut spoken = Spoken::default();
+    for text in [
+        "Das ist nicht mein Auto",
+        "Das Auto ist nicht alt",
+        r#"<speak xml:lang="fr-FR">Oui</speak>"#,
+        "This is the end of it",
+        "And that is all for you",
+    ] {
+        languages.select(&mut spoken, text, Some("amy"));
+    }
+    assert_eq!(spoken.voices(), ["thorsten", "siwis", "amy"]);
+}
+
+#[tokio::test]
+async fn the_start_and_end_of_every_utterance_are_published() {
+    let bus = EventBus::new();
+    let events = bus.subscribe();
+    let tts = TtsEngine::with_backend(Box::new(Spoken::default()));
+    tts.set_event_bus(bus);
+    tts.speak("Hello <emphasis>world</emphasis>").await.unwrap();
+    tts.speak("Goodbye.").await.unwrap();
+    let events: Vec<Value> =
+        tokio::time::timeout(Duration::from_secs(1), events.take(4).collect::<Vec<_>>())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect();
+    assert_eq!(
+        events,
+        [
+            json!({ "type": "speaking_started", "text": "Hello world" }),
+            json!({ "type": "speaking_finished", "text": "Hello world" }),
+            json!({ "type": "speaking_started", "text": "Goodbye." }),
+            json!({ "type": "speaking_finished", "text": "Goodbye." }),
+        ]
+    );
+}
+
+#[test]
+fn cloud_engines_say_what_they_are_missing() {
+    std::env::remove_var("AZURE_SPEECH_KEY");
+    let Err(error) = TtsEngine::for_engine("azure") else {
+        panic!("Azure cannot work without a key");
+    };
+    let expected = if cfg!(feature = "cloud-tts") {
+        "AZURE_SPEECH_KEY must be set when VOICE_ENGINE=azure"
+    } else {
+        "VOICE_ENGINE=azure requires Jarvis to be built with `--features cloud-tts`"
+    };
+    assert_eq!(error.to_string(), expected);
+}
+
+/// Takes a minute to say anything unless it is stopped.
+struct Lengthy(Spoken);
+
+#[async_trait]
+impl Speaker for Lengthy {
+    async fn speak(&mut self, text: &str) -> Result<()> {
+        self.0.speak(text).await?;
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn cancelling_stops_the_utterance_and_skips_the_rest() {
+    let spoken = Spoken::default();
+    let tts = TtsEngine::with_backend(Box::new(Lengthy(spoken.clone())));
+    let cancel = CancellationToken::new();
+    let story = tts.speak_cancellable("Once upon a time.", &cancel);
+    let sequel = tts.speak_cancellable("The sequel.", &cancel);
+    let aside = tts.speak_cancellable("An aside.", &CancellationToken::new());
+    while spoken.said().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    cancel.cancel();
+    aside.cancel();
+    tokio::time::timeout(Duration::from_secs(1), async {
+        for handle in [story, sequel, aside] {
+            handle.await.unwrap();
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(spoken.said(), ["Once upon a time."]);
+
+    let parent = CancellationToken::new();
+    tts.speak_cancellable("Skipped.", &parent).cancel();
+    assert!(!parent.is_cancelled(), "other utterances keep the token");
+}
@@ -0,0 +1,23 @@
+//! How much is said about a failed command.
+
+use anyhow::anyhow;
+use jarvis_rust::ErrorVerbosity;
+
+#[test]
+fn errors_are_described_in_as_much_detail_as_asked_for() {
+    let error = anyhow!("command exited with status 127").context("the shell_task tool failed");
+    let said = |verbosity: &str| {
+        verbosity
+            .parse::<ErrorVerbosity>()
+            .unwrap()
+            .describe(&error)
+    };
+    assert_eq!(said("terse"), "That failed.");
+    assert_eq!(said(" Normal "), "Sorry, the shell task tool failed.");
+    assert_eq!(
+        said("debug"),
+        "That failed: the shell_task tool failed: command exited with status 127."
+    );
+    assert!("chatty".parse::<ErrorVerbosity>().is_err());
+    assert_eq!(ErrorVerbosity::default(), ErrorVerbosity::Normal);
+}